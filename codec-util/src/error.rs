@@ -0,0 +1,43 @@
+use std::io;
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    IoError(io::Error),
+    /// a line or length given data longer than the reader's maximum length
+    LimitExceeded,
+    /// a line (or length given data) not ended by the line ending
+    MissingLineEnding,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::IoError(io_error) => Some(io_error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(io_error: io::Error) -> Self {
+        let message = io_error.to_string();
+        Self {
+            kind: ErrorKind::IoError(io_error),
+            message,
+        }
+    }
+}
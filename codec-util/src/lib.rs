@@ -0,0 +1,5 @@
+mod error;
+mod reader;
+
+pub use error::{Error, ErrorKind, Result};
+pub use reader::{LineEnding, Reader};
@@ -0,0 +1,145 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    fmt,
+    io::{self, BufRead, Read},
+};
+
+use super::error::{Error, ErrorKind, Result};
+
+/// Ending of the lines of a format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    /// `\n` alone (as of kvs-proto)
+    Lf,
+    /// `\r\n` (as of RESP), a `\n` alone being no ending
+    CrLf,
+}
+
+impl LineEnding {
+    /// the bytes ending a line
+    pub fn as_bytes(self) -> &'static [u8] {
+        match self {
+            Self::Lf => b"\n",
+            Self::CrLf => b"\r\n",
+        }
+    }
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Lf => "LF",
+            Self::CrLf => "CR LF",
+        })
+    }
+}
+
+/// Reader of the input of a line based format, such as kvs-proto or RESP, whose values are lines
+/// or data of a length given on the line before them (and ended as a line is)
+///
+/// Lines and length given data longer than the maximum length are refused with LimitExceeded,
+/// rather than read (or allocated) whole, as the input is not to be trusted.
+pub struct Reader<'a, R: io::Read> {
+    inner: &'a mut io::BufReader<R>,
+    ending: LineEnding,
+    max_len: u64,
+}
+
+impl<'a, R: io::Read> Reader<'a, R> {
+    /// reader of lines of the ending, of any length
+    pub fn new(inner: &'a mut io::BufReader<R>, ending: LineEnding) -> Self {
+        Self {
+            inner,
+            ending,
+            max_len: u64::MAX,
+        }
+    }
+    /// refuse lines and length given data longer than the maximum length (in bytes)
+    pub fn with_max_len(mut self, max_len: u64) -> Self {
+        self.max_len = max_len;
+        self
+    }
+    /// longest line or length given data read (in bytes)
+    pub fn max_len(&self) -> u64 {
+        self.max_len
+    }
+
+    /// the next byte, without consuming it, None at the end of input
+    pub fn peek(&mut self) -> Result<Option<u8>> {
+        Ok(self.peekn(1)?.first().copied())
+    }
+    /// up to the next `num` bytes (fewer if fewer are buffered), without consuming them
+    pub fn peekn(&mut self, num: usize) -> Result<&[u8]> {
+        let buf = self.inner.fill_buf()?;
+        Ok(&buf[..num.min(buf.len())])
+    }
+    /// consumes `num` bytes already peeked
+    pub fn consume(&mut self, num: usize) {
+        self.inner.consume(num);
+    }
+
+    /// reads the next line, returning it without its ending
+    pub fn read_line(&mut self) -> Result<Vec<u8>> {
+        let ending = self.ending.as_bytes();
+        let mut line = Vec::new();
+        let _ = (&mut self.inner)
+            .take(self.max_len.saturating_add(ending.len() as u64))
+            .read_until(b'\n', &mut line)?;
+        if line.ends_with(ending) {
+            line.truncate(line.len() - ending.len());
+            return Ok(line);
+        }
+        if line.len() as u64 > self.max_len {
+            return Err(limit_exceeded(format!(
+                "Line longer than the limit of {} bytes",
+                self.max_len
+            )));
+        }
+        Err(Error {
+            kind: ErrorKind::MissingLineEnding,
+            message: format!(
+                "End of input reached with missing or incorrect ending {}. Input is: {}",
+                self.ending,
+                String::from_utf8_lossy(&line)
+            ),
+        })
+    }
+
+    /// reads data of the given length followed by a line ending, returning it without the ending
+    ///
+    /// The data is read as it arrives rather than allocated up front, as the length may be a lie.
+    pub fn read_given(&mut self, len: u64) -> Result<Vec<u8>> {
+        if len > self.max_len {
+            return Err(limit_exceeded(format!(
+                "Length {} beyond the limit of {} bytes",
+                len, self.max_len
+            )));
+        }
+        let mut data = Vec::new();
+        if (&mut self.inner).take(len).read_to_end(&mut data)? as u64 != len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let ending = self.ending.as_bytes();
+        let found = &mut [0; 2][..ending.len()];
+        self.inner.read_exact(found)?;
+        if found != ending {
+            return Err(Error {
+                kind: ErrorKind::MissingLineEnding,
+                message: format!(
+                    "Expected ending delimiter {} for input of length given data, found: {:?}",
+                    self.ending, found
+                ),
+            });
+        }
+        Ok(data)
+    }
+}
+
+fn limit_exceeded(message: String) -> Error {
+    Error {
+        kind: ErrorKind::LimitExceeded,
+        message,
+    }
+}
@@ -0,0 +1,102 @@
+use super::*;
+
+fn reader(input: &[u8]) -> io::BufReader<&[u8]> {
+    io::BufReader::new(input)
+}
+
+fn is_missing_line_ending<T>(result: Result<T>) -> bool {
+    matches!(
+        result,
+        Err(Error {
+            kind: ErrorKind::MissingLineEnding,
+            ..
+        })
+    )
+}
+
+fn is_limit_exceeded<T>(result: Result<T>) -> bool {
+    matches!(
+        result,
+        Err(Error {
+            kind: ErrorKind::LimitExceeded,
+            ..
+        })
+    )
+}
+
+#[test]
+fn test_lf_lines() -> Result<()> {
+    let input = &mut reader(b"first\nsecond\r\n\nlast");
+    let reader = &mut Reader::new(input, LineEnding::Lf);
+
+    assert_eq!(b"first", reader.read_line()?.as_slice());
+    // a CR before the LF is part of the line
+    assert_eq!(b"second\r", reader.read_line()?.as_slice());
+    assert_eq!(b"", reader.read_line()?.as_slice());
+    assert!(is_missing_line_ending(reader.read_line()));
+
+    Ok(())
+}
+
+#[test]
+fn test_cr_lf_lines() -> Result<()> {
+    let input = &mut reader(b"first\r\nsec\rond\r\n\r\nbare\n");
+    let reader = &mut Reader::new(input, LineEnding::CrLf);
+
+    assert_eq!(b"first", reader.read_line()?.as_slice());
+    // a CR alone is part of the line
+    assert_eq!(b"sec\rond", reader.read_line()?.as_slice());
+    assert_eq!(b"", reader.read_line()?.as_slice());
+    // as is an LF alone, though it ends the line read
+    assert!(is_missing_line_ending(reader.read_line()));
+
+    Ok(())
+}
+
+#[test]
+fn test_peek_and_consume() -> Result<()> {
+    let input = &mut reader(b"$abc\n");
+    let reader = &mut Reader::new(input, LineEnding::Lf);
+
+    assert_eq!(Some(b'$'), reader.peek()?);
+    assert_eq!(b"$ab", reader.peekn(3)?);
+    reader.consume(1);
+    assert_eq!(b"abc\n", reader.peekn(10)?);
+    assert_eq!(b"abc", reader.read_line()?.as_slice());
+    assert_eq!(None, reader.peek()?);
+
+    Ok(())
+}
+
+#[test]
+fn test_read_given() -> Result<()> {
+    let input = &mut reader(b"a\nb\n\r\nab\nc\r\nabc");
+    let reader = &mut Reader::new(input, LineEnding::CrLf);
+
+    // the data may hold line endings of its own
+    assert_eq!(b"a\nb\n", reader.read_given(4)?.as_slice());
+    assert!(is_missing_line_ending(reader.read_given(3)));
+    // and must be followed by the whole of it
+    assert!(matches!(
+        reader.read_given(4),
+        Err(Error {
+            kind: ErrorKind::IoError(_),
+            ..
+        })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_max_len() -> Result<()> {
+    let input = &mut reader(b"1234\n12345\n");
+    let reader = &mut Reader::new(input, LineEnding::Lf).with_max_len(4);
+
+    assert_eq!(4, reader.max_len());
+    assert_eq!(b"1234", reader.read_line()?.as_slice());
+    assert!(is_limit_exceeded(reader.read_line()));
+    assert!(is_limit_exceeded(reader.read_given(u64::MAX)));
+
+    Ok(())
+}
@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 
 fn main() -> Result<(), Box<dyn Error>> {
     let moves = &mut Vec::<Move>::new();
-    let db = &mut Database::open_new_or_truncate(&Path::new("./temp.database.kvs"))?;
+    let db = &mut Database::open_new_or_truncate(Path::new("./temp.database.kvs"))?;
 
     let mut current_direction = Direction::North;
     for i in 0..1000 {
@@ -67,7 +67,7 @@ struct Database<'a> {
 }
 
 impl<'a> Database<'a> {
-    pub fn open_new_or_truncate(path: &'a Path) -> Result<Database, Box<dyn Error>> {
+    pub fn open_new_or_truncate(path: &'a Path) -> Result<Database<'a>, Box<dyn Error>> {
         let writer = Box::new(io::BufWriter::new(
             fs::OpenOptions::new()
                 .create(true)
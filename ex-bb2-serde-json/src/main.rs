@@ -8,7 +8,7 @@ fn main() -> Result<(), io::Error> {
         count: 3,
     };
 
-    let file = serialize(&move_a, &Path::new("./tmp.txt"))?;
+    let file = serialize(&move_a, Path::new("./tmp.txt"))?;
     let move_b = deserialize(file)?;
 
     println!("Move A = {:?}", move_a);
@@ -1,5 +1,6 @@
 use std::{
     error::Error,
+    fmt,
     io::{self, BufRead, Write},
     net, str, vec,
 };
@@ -64,6 +65,25 @@ enum FieldType {
     Array(Vec<FieldType>),
 }
 
+/// An error replied by the other end, of the kind of the first word of its message (ERR,
+/// WRONGTYPE and so on) if it is in capitals
+#[derive(Debug)]
+struct ServerError {
+    kind: Option<String>,
+    message: String,
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            Some(kind) => write!(f, "Server Error {}: {}", kind, self.message),
+            None => write!(f, "Server Error: {}", self.message),
+        }
+    }
+}
+
+impl Error for ServerError {}
+
 fn socket_addresses_from(args: &clap::ArgMatches) -> io::Result<vec::IntoIter<net::SocketAddr>> {
     net::ToSocketAddrs::to_socket_addrs(&(
         args.value_of("host")
@@ -89,9 +109,16 @@ fn start_server(listen_on: vec::IntoIter<net::SocketAddr>) -> Result<(), Box<dyn
 fn handle_connection(stream: net::TcpStream) -> Result<(), Box<dyn Error>> {
     let read_stream = io::BufReader::new(stream.try_clone()?);
     let write_stream = io::BufWriter::new(stream);
-    let _ = expect_simple_command(read_stream, "PING")?;
-    println!("Received PING. Sending PONG!");
-    send_simple_message(write_stream, "PONG")
+    match expect_simple_command(read_stream, "PING") {
+        Ok(_) => {
+            println!("Received PING. Sending PONG!");
+            send_simple_message(write_stream, "PONG")
+        }
+        Err(err) => {
+            println!("Received other than PING. Sending ERR!");
+            send_error_message(write_stream, "ERR", &format!("expected PING. {}", err))
+        }
+    }
 }
 
 fn start_client(connect_to: vec::IntoIter<net::SocketAddr>) -> Result<(), Box<dyn Error>> {
@@ -102,7 +129,7 @@ fn start_client(connect_to: vec::IntoIter<net::SocketAddr>) -> Result<(), Box<dy
 fn ping_server(stream: net::TcpStream) -> Result<(), Box<dyn Error>> {
     let read_stream = io::BufReader::new(stream.try_clone()?);
     let write_stream = io::BufWriter::new(stream);
-    let _ = send_simple_message(write_stream, "PING")?;
+    send_simple_message(write_stream, "PING")?;
     println!("Sent PING.");
     let _ = expect_simple_command(read_stream, "PONG")?;
     println!("Received PONG.");
@@ -126,8 +153,8 @@ fn expect_simple_command(
             command: Some(command),
             message,
         }),
+        FieldType::Error { kind, message } => Err(Box::new(ServerError { kind, message })),
         incorrect_message @ FieldType::SimpleString { .. }
-        | incorrect_message @ FieldType::Error { .. }
         | incorrect_message @ FieldType::Integer(_)
         | incorrect_message @ FieldType::BulkString(_)
         | incorrect_message @ FieldType::Array(_) => Err(Box::new(io::Error::new(
@@ -165,6 +192,21 @@ fn read_protocol_message(
 fn read_simple_string_from(
     stream: &mut io::BufReader<net::TcpStream>,
 ) -> Result<FieldType, Box<dyn Error>> {
+    let (command, message) = split_kind(read_line_from(stream, "Simple String")?);
+    Ok(FieldType::SimpleString { command, message })
+}
+
+fn read_error_from(
+    stream: &mut io::BufReader<net::TcpStream>,
+) -> Result<FieldType, Box<dyn Error>> {
+    let (kind, message) = split_kind(read_line_from(stream, "Error")?);
+    Ok(FieldType::Error { kind, message })
+}
+
+fn read_line_from(
+    stream: &mut io::BufReader<net::TcpStream>,
+    field_type: &str,
+) -> Result<String, Box<dyn Error>> {
     let mut buf = String::default();
     let _ = stream.read_line(&mut buf)?;
     buf.pop();
@@ -172,35 +214,27 @@ fn read_simple_string_from(
         Err(Box::new(io::Error::new(
             io::ErrorKind::InvalidData,
             format!(
-                "I/O Error: Missing CR at end of Simple String. Found {}",
+                "I/O Error: Missing CR at end of {}. Found {}",
+                field_type,
                 buf.chars().last().unwrap_or('\0')
             ),
         )))
     } else {
         buf.pop();
-        match buf.split_once(|c: char| c.is_whitespace()) {
-            Some((command, message)) if command.chars().all(|c| c.is_uppercase()) => {
-                Ok(FieldType::SimpleString {
-                    command: Some(command.into()),
-                    message: message.into(),
-                })
-            }
-            None if buf.chars().all(|c| c.is_uppercase()) => Ok(FieldType::SimpleString {
-                command: Some(buf),
-                message: "".into(),
-            }),
-            Some(_) | None => Ok(FieldType::SimpleString {
-                command: None,
-                message: buf,
-            }),
-        }
+        Ok(buf)
     }
 }
 
-fn read_error_from(
-    _stream: &mut io::BufReader<net::TcpStream>,
-) -> Result<FieldType, Box<dyn Error>> {
-    todo!()
+/// the first word of the line, if in capitals (the command of a simple string, or kind of an
+/// error), and the rest of it
+fn split_kind(line: String) -> (Option<String>, String) {
+    match line.split_once(|c: char| c.is_whitespace()) {
+        Some((kind, message)) if kind.chars().all(|c| c.is_uppercase()) => {
+            (Some(kind.into()), message.into())
+        }
+        None if line.chars().all(|c| c.is_uppercase()) => (Some(line), "".into()),
+        Some(_) | None => (None, line),
+    }
 }
 
 fn read_integer_from(
@@ -221,6 +255,18 @@ fn read_array_from(
     todo!()
 }
 
+fn send_error_message(
+    mut stream: io::BufWriter<net::TcpStream>,
+    kind: &str,
+    message: &str,
+) -> Result<(), Box<dyn Error>> {
+    // the message of an error is of a single line
+    let message = message.replace(['\r', '\n'], " ");
+    let message = format!("-{} {}\r\n", kind, message);
+    stream.write_all(message.as_bytes())?;
+    Ok(())
+}
+
 fn send_simple_message(
     mut stream: io::BufWriter<net::TcpStream>,
     message: &str,
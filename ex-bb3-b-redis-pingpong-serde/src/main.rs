@@ -4,7 +4,7 @@ use std::{
     net, vec,
 };
 
-mod redis_serde;
+mod pingpong;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = arguments();
@@ -71,7 +71,7 @@ fn start_server(listen_on: vec::IntoIter<net::SocketAddr>) -> Result<(), Box<dyn
 }
 
 fn handle_connection(stream: net::TcpStream) -> Result<(), Box<dyn Error>> {
-    Ok(redis_serde::handle_command(
+    Ok(pingpong::handle_command(
         &mut io::BufReader::new(stream.try_clone()?),
         &mut io::BufWriter::new(stream),
     )?)
@@ -79,7 +79,7 @@ fn handle_connection(stream: net::TcpStream) -> Result<(), Box<dyn Error>> {
 
 fn start_client(connect_to: vec::IntoIter<net::SocketAddr>) -> Result<(), Box<dyn Error>> {
     let stream = net::TcpStream::connect(connect_to.collect::<Vec<_>>().as_slice())?;
-    Ok(redis_serde::send_ping_and_handle_response(
+    Ok(pingpong::send_ping_and_handle_response(
         &mut io::BufReader::new(stream.try_clone()?),
         &mut io::BufWriter::new(stream),
     )?)
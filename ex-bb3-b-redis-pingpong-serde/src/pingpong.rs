@@ -1,17 +1,8 @@
-#[cfg(test)]
-mod tests;
-
-mod error;
-
-mod de;
-mod ser;
-
 use std::io;
 
-pub use de::from_reader;
-pub use ser::to_writer;
-
-pub use error::{Error, ErrorKind, Result};
+use redis_serde::{
+    from_reader, from_reader_with_limits, to_writer, Error, ErrorKind, Limits, Result,
+};
 
 #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
 enum Command {
@@ -23,7 +14,12 @@ pub fn handle_command<R: io::Read, W: io::Write>(
     reader: &mut io::BufReader<R>,
     writer: &mut io::BufWriter<W>,
 ) -> Result<()> {
-    match from_reader::<_, Command>(reader)? {
+    // a command is a single word, so anything longer is not one
+    let limits = Limits {
+        max_len: 64,
+        max_elements: 1,
+    };
+    match from_reader_with_limits::<_, Command>(reader, limits)? {
         Command::Ping => {
             println!("Ping Received.");
             to_writer(writer, Command::Pong)?;
@@ -3,35 +3,116 @@ mod tests;
 
 use super::error;
 
-use std::{
-    io::{self, BufRead, Read},
-    str,
-};
+use std::{io, str};
 
 use serde::{
     de::{self, IntoDeserializer},
     Deserialize,
 };
 
+use codec_util::{LineEnding, Reader};
+
 use error::{Error, ErrorKind, Result};
 
+/// deepest nesting of the elements of a value deserialized without knowing its type (with
+/// `deserialize_any`) or skipped (with `deserialize_ignored_any`), below which it is refused
+const MAX_DEPTH: usize = 64;
+
+/// Bounds on what the input of a deserialization may hold, beyond which it fails with
+/// LimitExceeded rather than reading on (as the lengths and counts the input gives are not to be
+/// trusted)
+///
+/// The defaults are those of Redis: strings of up to 512MiB and up to 1048576 elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// longest string, byte array or line (of a number, say), in bytes
+    pub max_len: u64,
+    /// most elements of a sequence, tuple, map (its entries), struct or variant
+    pub max_elements: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_len: 512 * 1024 * 1024,
+            max_elements: 1024 * 1024,
+        }
+    }
+}
+
+/// How strings of the input which are not valid UTF-8 are deserialized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Utf8 {
+    /// refused with a FromUtf8Error
+    #[default]
+    Strict,
+    /// with each invalid sequence replaced by U+FFFD (the replacement character)
+    Lossy,
+    /// as the bytes they are, for types taking bytes as well as strings (as serde_bytes::ByteBuf
+    /// does) to hold them unchanged; byte arrays may then be read from strings too
+    Raw,
+}
+
+/// Options of a deserialization
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Options {
+    /// bounds on what the input may hold
+    pub limits: Limits,
+    /// how strings which are not valid UTF-8 are deserialized
+    pub utf8: Utf8,
+}
+
 struct Deserializer<'reader, R: io::Read> {
-    reader: &'reader mut io::BufReader<R>,
+    reader: Reader<'reader, R>,
+    options: Options,
+    depth: usize,
 }
 
+/// deserializes the value at the position of the reader, with the default Options
 pub fn from_reader<'reader, R: io::Read, T>(reader: &'reader mut io::BufReader<R>) -> Result<T>
 where
     T: Deserialize<'reader>,
 {
-    let mut deserializer = Deserializer { reader };
+    from_reader_with_options(reader, Options::default())
+}
+
+/// deserializes the value at the position of the reader, LimitExceeded if its input goes beyond
+/// the limits
+pub fn from_reader_with_limits<'reader, R: io::Read, T>(
+    reader: &'reader mut io::BufReader<R>,
+    limits: Limits,
+) -> Result<T>
+where
+    T: Deserialize<'reader>,
+{
+    let options = Options {
+        limits,
+        ..Options::default()
+    };
+    from_reader_with_options(reader, options)
+}
+
+/// deserializes the value at the position of the reader with the options
+pub fn from_reader_with_options<'reader, R: io::Read, T>(
+    reader: &'reader mut io::BufReader<R>,
+    options: Options,
+) -> Result<T>
+where
+    T: Deserialize<'reader>,
+{
+    let mut deserializer = Deserializer {
+        reader: Reader::new(reader, LineEnding::Lf).with_max_len(options.limits.max_len),
+        options,
+        depth: 0,
+    };
     T::deserialize(&mut deserializer)
 }
 
 macro_rules! parse_number {
     (from $self:ident type $type:ident indicated by $indicator:expr) => {{
-        match $self.peek()? {
+        match $self.reader.peek()? {
             Some($indicator) => {
-                $self.consume(1);
+                $self.reader.consume(1);
                 Ok($self.read_line()?.parse::<$type>()?)
             }
             input => Err(Error {
@@ -48,38 +129,8 @@ macro_rules! parse_number {
 }
 
 impl<'a, R: io::Read> Deserializer<'a, R> {
-    fn peek(&mut self) -> Result<Option<u8>> {
-        let buf = self.peekn(1)?;
-        match buf {
-            [b] => Ok(Some(*b)),
-            _ => Ok(None),
-        }
-    }
-
-    fn peekn(&mut self, num: u8) -> Result<&[u8]> {
-        let buf = self.reader.fill_buf()?;
-        Ok(&buf[..(num as usize).min(buf.len())])
-    }
-
-    fn consume(&mut self, num: u8) {
-        self.reader.consume(num as usize);
-    }
-
     fn read_line(&mut self) -> Result<String> {
-        let mut line = String::new();
-        let _ = self.reader.read_line(&mut line)?;
-        if line.ends_with('\n') {
-            line.pop();
-            Ok(line)
-        } else {
-            Err(Error {
-                kind: ErrorKind::DataError,
-                message: format!(
-                    "End of input reached with missing or incorrect ending LF. Input is: {}",
-                    line
-                ),
-            })
-        }
+        Ok(String::from_utf8(self.reader.read_line()?)?)
     }
 
     fn read_and_verify_name(&mut self, name: &str) -> Result<()> {
@@ -102,7 +153,14 @@ impl<'a, R: io::Read> Deserializer<'a, R> {
     }
 
     fn read_length(&mut self) -> Result<u32> {
-        Ok(self.read_line()?.parse::<u32>()?)
+        let element_count = self.read_line()?.parse::<u32>()?;
+        if element_count > self.options.limits.max_elements {
+            return Err(Error::limit_exceeded(format!(
+                "Element count {} beyond the limit of {}",
+                element_count, self.options.limits.max_elements
+            )));
+        }
+        Ok(element_count)
     }
 
     fn verify_length(
@@ -111,7 +169,7 @@ impl<'a, R: io::Read> Deserializer<'a, R> {
         element_count: usize,
         looking_for: &'static str,
     ) -> Result<()> {
-        if len != element_count as usize {
+        if len != element_count {
             return Err(Error {
                 kind: ErrorKind::DataError,
                 message: format!(
@@ -124,33 +182,18 @@ impl<'a, R: io::Read> Deserializer<'a, R> {
     }
 
     fn read_exact_given_discarding_ending_newline(&mut self) -> Result<Vec<u8>> {
-        let len = self.read_line()?.parse::<usize>()?;
-        let mut buf = Vec::<u8>::with_capacity(len);
-        buf.resize(len, Default::default());
-        self.reader.read_exact(buf.as_mut())?;
-        match self.peek()? {
-            Some(b'\n') => {
-                self.consume(1);
-                Ok(buf)
-            }
-            input => Err(Error {
-                kind: ErrorKind::DataError,
-                message: format!(
-                    "Expected ending delimiter 'LF' for input of Length given data, found: {:?}",
-                    input
-                ),
-            }),
-        }
+        let len = self.read_line()?.parse::<u64>()?;
+        Ok(self.reader.read_given(len)?)
     }
 
     fn parse_bool(&mut self) -> Result<bool> {
-        match self.peekn(2)? {
+        match self.reader.peekn(2)? {
             b"1\n" => {
-                self.consume(2);
+                self.reader.consume(2);
                 Ok(true)
             }
             b"0\n" => {
-                self.consume(2);
+                self.reader.consume(2);
                 Ok(false)
             }
             input => Err(Error {
@@ -216,16 +259,19 @@ impl<'a, R: io::Read> Deserializer<'a, R> {
     }
 
     fn parse_string(&mut self) -> Result<String> {
-        match self.peek()? {
+        Ok(String::from_utf8(self.parse_string_bytes()?)?)
+    }
+
+    /// the bytes of a string, which may not be valid UTF-8
+    fn parse_string_bytes(&mut self) -> Result<Vec<u8>> {
+        match self.reader.peek()? {
             Some(b'$') => {
-                self.consume(1);
-                Ok(self.read_line()?)
+                self.reader.consume(1);
+                Ok(self.reader.read_line()?)
             }
             Some(b'&') => {
-                self.consume(1);
-                Ok(String::from_utf8(
-                    self.read_exact_given_discarding_ending_newline()?,
-                )?)
+                self.reader.consume(1);
+                self.read_exact_given_discarding_ending_newline()
             }
             input => Err(Error {
                 kind: ErrorKind::DataError,
@@ -238,9 +284,9 @@ impl<'a, R: io::Read> Deserializer<'a, R> {
     }
 
     fn parse_bytes(&mut self) -> Result<Vec<u8>> {
-        match self.peek()? {
+        match self.reader.peek()? {
             Some(b'%') => {
-                self.consume(1);
+                self.reader.consume(1);
                 Ok(self.read_exact_given_discarding_ending_newline()?)
             }
             input => Err(Error {
@@ -249,16 +295,128 @@ impl<'a, R: io::Read> Deserializer<'a, R> {
             }),
         }
     }
+
+    fn verify_depth(&self, depth: usize) -> Result<()> {
+        if depth > MAX_DEPTH {
+            return Err(Error {
+                kind: ErrorKind::DataError,
+                message: format!("Elements nested deeper than {}", MAX_DEPTH),
+            });
+        }
+        Ok(())
+    }
+
+    /// deserializes the elements of a compound, a level deeper than the compound itself
+    fn nested<T>(&mut self, deserialize: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        self.verify_depth(self.depth + 1)?;
+        self.depth += 1;
+        let value = deserialize(self);
+        self.depth -= 1;
+        value
+    }
+
+    /// reads past the element (of any type), checking only that it is well formed
+    fn skip_element(&mut self, depth: usize) -> Result<()> {
+        self.verify_depth(depth)?;
+        let indicator = match self.reader.peek()? {
+            Some(indicator) => indicator,
+            None => {
+                return Err(Error {
+                    kind: ErrorKind::DataError,
+                    message: "End of input reached, expected an element".into(),
+                })
+            }
+        };
+        let element_count = match indicator {
+            b'&' | b'%' => {
+                self.reader.consume(1);
+                self.read_exact_given_discarding_ending_newline()?;
+                0
+            }
+            b'`' | b'~' => {
+                self.reader.consume(1);
+                self.read_length()?
+            }
+            b'{' => {
+                self.reader.consume(1);
+                self.read_length()?.saturating_mul(2)
+            }
+            b':' | b'}' => {
+                self.reader.consume(1);
+                let element_count = self.read_length()?;
+                self.read_line()?;
+                match indicator {
+                    b':' => element_count,
+                    _ => element_count.saturating_mul(2),
+                }
+            }
+            b'@' | b'^' | b'#' => {
+                self.reader.consume(1);
+                let element_count = match indicator {
+                    b'@' => 0,
+                    _ => self.read_length()?,
+                };
+                self.read_line()?;
+                self.parse_string()?;
+                match indicator {
+                    b'#' => element_count.saturating_mul(2),
+                    _ => element_count,
+                }
+            }
+            // scalars (numbers, booleans, short strings and None), of the line alone
+            _ => {
+                self.read_line()?;
+                0
+            }
+        };
+        (0..element_count).try_for_each(|_| self.skip_element(depth + 1))
+    }
 }
 
-impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R> {
+impl<'de, R: io::Read> de::Deserializer<'de> for &mut Deserializer<'de, R> {
     type Error = Error;
 
-    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
-    where
-        V: de::Visitor<'de>,
-    {
-        todo!()
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.reader.peek()? {
+            Some(b'$') | Some(b'&') => self.deserialize_string(visitor),
+            Some(b'%') => self.deserialize_byte_buf(visitor),
+            Some(b'0') | Some(b'1') => self.deserialize_bool(visitor),
+            Some(b'c') => self.deserialize_char(visitor),
+            Some(b'B') => self.deserialize_u8(visitor),
+            Some(b'W') => self.deserialize_u16(visitor),
+            Some(b'I') => self.deserialize_u32(visitor),
+            Some(b'D') => self.deserialize_u64(visitor),
+            Some(b'Q') => self.deserialize_u128(visitor),
+            Some(b'b') => self.deserialize_i8(visitor),
+            Some(b'w') => self.deserialize_i16(visitor),
+            Some(b'i') => self.deserialize_i32(visitor),
+            Some(b'd') => self.deserialize_i64(visitor),
+            Some(b'q') => self.deserialize_i128(visitor),
+            Some(b'f') => self.deserialize_f32(visitor),
+            Some(b'F') => self.deserialize_f64(visitor),
+            Some(b'!') => self.deserialize_option(visitor),
+            Some(indicator @ (b'`' | b'~' | b'{' | b':' | b'}')) => {
+                self.reader.consume(1);
+                let element_count = self.read_length()?;
+                if let b':' | b'}' = indicator {
+                    self.read_line()?;
+                }
+                self.nested(|de| match indicator {
+                    b'{' | b'}' => visitor.visit_map(DeserializerSeqElements { de, element_count }),
+                    _ => visitor.visit_seq(DeserializerSeqElements { de, element_count }),
+                })
+            }
+            input => Err(Error {
+                kind: ErrorKind::DataError,
+                message: format!(
+                    "Expected input of a self-describing type (not an Enum), found: {:?}",
+                    input
+                ),
+            }),
+        }
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
@@ -359,43 +517,55 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
         visitor.visit_char(self.parse_char()?)
     }
 
-    fn deserialize_str<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!("Deserialization of unowned strings is not supported with this deserializer")
+        // there is no borrowing from the reader, so a visitor of borrowed strings alone refuses it
+        self.deserialize_string(visitor)
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_string(self.parse_string()?)
+        let bytes = self.parse_string_bytes()?;
+        match (String::from_utf8(bytes), self.options.utf8) {
+            (Ok(string), _) => visitor.visit_string(string),
+            (Err(err), Utf8::Strict) => Err(err.into()),
+            (Err(err), Utf8::Lossy) => {
+                visitor.visit_string(String::from_utf8_lossy(err.as_bytes()).into_owned())
+            }
+            (Err(err), Utf8::Raw) => visitor.visit_byte_buf(err.into_bytes()),
+        }
     }
 
-    fn deserialize_bytes<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!(
-            "Deserialization of unowned byte arrays is not supported with this deserializer"
-        )
+        self.deserialize_byte_buf(visitor)
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_byte_buf(self.parse_bytes()?)
+        match self.reader.peek()? {
+            Some(b'$') | Some(b'&') if self.options.utf8 == Utf8::Raw => {
+                visitor.visit_byte_buf(self.parse_string_bytes()?)
+            }
+            _ => visitor.visit_byte_buf(self.parse_bytes()?),
+        }
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        match self.peekn(2)? {
+        match self.reader.peekn(2)? {
             b"!\n" => {
-                self.consume(2);
+                self.reader.consume(2);
                 visitor.visit_none()
             }
             _ => visitor.visit_some(self),
@@ -406,9 +576,9 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
     where
         V: de::Visitor<'de>,
     {
-        match self.peekn(3)? {
+        match self.reader.peekn(3)? {
             b"~0\n" => {
-                self.consume(3);
+                self.reader.consume(3);
                 visitor.visit_unit()
             }
             input => Err(Error {
@@ -436,14 +606,11 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
     where
         V: de::Visitor<'de>,
     {
-        match self.peek()? {
+        match self.reader.peek()? {
             Some(b'`') => {
-                self.consume(1);
-                let element_count = self.read_line()?.parse::<u32>()?;
-                visitor.visit_seq(DeserializerSeqElements {
-                    de: self,
-                    element_count,
-                })
+                self.reader.consume(1);
+                let element_count = self.read_length()?;
+                self.nested(|de| visitor.visit_seq(DeserializerSeqElements { de, element_count }))
             }
             input => Err(Error {
                 kind: ErrorKind::DataError,
@@ -459,10 +626,10 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
     where
         V: de::Visitor<'de>,
     {
-        match self.peek()? {
+        match self.reader.peek()? {
             Some(b'~') => {
-                self.consume(1);
-                let element_count = self.read_line()?.parse::<u32>()?;
+                self.reader.consume(1);
+                let element_count = self.read_length()?;
                 if len != element_count as usize {
                     return Err(Error {
                         kind: ErrorKind::DataError,
@@ -472,10 +639,7 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
                         ),
                     });
                 }
-                visitor.visit_seq(DeserializerSeqElements {
-                    de: self,
-                    element_count,
-                })
+                self.nested(|de| visitor.visit_seq(DeserializerSeqElements { de, element_count }))
             }
             input => Err(Error {
                 kind: ErrorKind::DataError,
@@ -496,10 +660,10 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
     where
         V: de::Visitor<'de>,
     {
-        match self.peek()? {
+        match self.reader.peek()? {
             Some(b':') => {
-                self.consume(1);
-                let element_count = self.read_line()?.parse::<u32>()?;
+                self.reader.consume(1);
+                let element_count = self.read_length()?;
                 if len != element_count as usize {
                     return Err(Error {
                         kind: ErrorKind::DataError,
@@ -510,10 +674,7 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
                     });
                 }
                 self.read_and_verify_name(name)?;
-                visitor.visit_seq(DeserializerSeqElements {
-                    de: self,
-                    element_count,
-                })
+                self.nested(|de| visitor.visit_seq(DeserializerSeqElements { de, element_count }))
             }
             input => Err(Error {
                 kind: ErrorKind::DataError,
@@ -529,14 +690,11 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
     where
         V: de::Visitor<'de>,
     {
-        match self.peek()? {
+        match self.reader.peek()? {
             Some(b'{') => {
-                self.consume(1);
-                let element_count = self.read_line()?.parse::<u32>()?;
-                visitor.visit_map(DeserializerSeqElements {
-                    de: self,
-                    element_count,
-                })
+                self.reader.consume(1);
+                let element_count = self.read_length()?;
+                self.nested(|de| visitor.visit_map(DeserializerSeqElements { de, element_count }))
             }
             input => Err(Error {
                 kind: ErrorKind::DataError,
@@ -557,17 +715,19 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
     where
         V: de::Visitor<'de>,
     {
-        match self.peek()? {
+        match self.reader.peek()? {
             Some(b'}') => {
-                self.consume(1);
+                self.reader.consume(1);
                 self.read_and_verify_length(fields.len(), "tuple")?;
                 self.read_and_verify_name(name)?;
                 if fields.is_empty() {
                     visitor.visit_unit()
                 } else {
-                    visitor.visit_map(DeserializerSeqElements {
-                        de: self,
-                        element_count: fields.len() as u32,
+                    self.nested(|de| {
+                        visitor.visit_map(DeserializerSeqElements {
+                            de,
+                            element_count: fields.len() as u32,
+                        })
                     })
                 }
             }
@@ -590,33 +750,27 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
     where
         V: de::Visitor<'de>,
     {
-        match self.peekn(1)? {
+        match self.reader.peekn(1)? {
             b"@" => {
                 // Unit Variant
-                self.consume(1);
+                self.reader.consume(1);
                 self.read_and_verify_name(name)?;
                 let variant = self.parse_string()?;
                 visitor.visit_enum(variant.into_deserializer())
             }
             b"^" => {
                 // Tuple/New-Type Variant
-                self.consume(1);
+                self.reader.consume(1);
                 let element_count = self.read_length()?;
                 self.read_and_verify_name(name)?;
-                Ok(visitor.visit_enum(DeserializeEnum {
-                    de: self,
-                    element_count,
-                })?)
+                self.nested(|de| visitor.visit_enum(DeserializeEnum { de, element_count }))
             }
             b"#" => {
                 // Struct Variant
-                self.consume(1);
+                self.reader.consume(1);
                 let element_count = self.read_length()?;
                 self.read_and_verify_name(name)?;
-                Ok(visitor.visit_enum(DeserializeEnum {
-                    de: self,
-                    element_count,
-                })?)
+                self.nested(|de| visitor.visit_enum(DeserializeEnum { de, element_count }))
             }
             input => Err(Error {
                 kind: ErrorKind::DataError,
@@ -632,7 +786,7 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
     where
         V: de::Visitor<'de>,
     {
-        match self.peek()? {
+        match self.reader.peek()? {
             Some(b'$') => self.deserialize_string(visitor),
             input => Err(Error {
                 kind: ErrorKind::DataError,
@@ -641,11 +795,12 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
         }
     }
 
-    fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        self.skip_element(self.depth)?;
+        visitor.visit_unit()
     }
 }
 
@@ -713,7 +868,17 @@ impl<'de, 'a, R: io::Read> de::VariantAccess<'de> for DeserializeEnum<'a, 'de, R
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
-        unimplemented!("should never be called - unit variants handled immediately")
+        // unit variants are written with '@', but one with '^' or '#' and no elements will do
+        if self.element_count != 0 {
+            return Err(Error {
+                kind: ErrorKind::DataError,
+                message: format!(
+                    "Expected length 0 for Enum Unit Variant, found: {}",
+                    self.element_count
+                ),
+            });
+        }
+        Ok(())
     }
 
     fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
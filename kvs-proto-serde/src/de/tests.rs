@@ -578,3 +578,227 @@ mod test_enum {
         Ok(())
     }
 }
+
+mod test_malformed_input {
+    use super::super::*;
+
+    #[derive(PartialEq, Deserialize, Debug)]
+    struct Defaulted {
+        #[serde(default)]
+        a: u32,
+    }
+
+    #[derive(PartialEq, Deserialize, Debug)]
+    enum Variants {
+        Unit,
+        Pair(u32, u32),
+    }
+
+    #[test]
+    fn test_unknown_field_is_skipped() -> Result<()> {
+        let input = "}1\nDefaulted\n$b\n#2\nVariants\n$Pair\n$x\n`1\n&2\nab\n$y\nI5\n";
+        let reader = &mut io::BufReader::new(input.as_bytes());
+
+        assert_eq!(Defaulted { a: 0 }, from_reader(reader)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deeply_nested_unknown_field_is_refused() {
+        let input = format!("}}1\nDefaulted\n$b\n{}", "`1\n".repeat(100_000));
+        let reader = &mut io::BufReader::new(input.as_bytes());
+
+        assert!(matches!(
+            from_reader::<_, Defaulted>(reader),
+            Err(Error {
+                kind: ErrorKind::DataError,
+                ..
+            })
+        ));
+    }
+
+    #[derive(PartialEq, Deserialize, Debug)]
+    enum Nested {
+        Leaf,
+        Node(Box<Nested>),
+    }
+
+    #[test]
+    fn test_deeply_nested_variant_is_refused() -> Result<()> {
+        let nested = |depth| "^1\nNested\n$Node\n".repeat(depth) + "@Nested\n$Leaf\n";
+
+        let input = nested(2);
+        let reader = &mut io::BufReader::new(input.as_bytes());
+        assert_eq!(
+            Nested::Node(Box::new(Nested::Node(Box::new(Nested::Leaf)))),
+            from_reader(reader)?
+        );
+
+        let input = nested(100_000);
+        let reader = &mut io::BufReader::new(input.as_bytes());
+        assert!(matches!(
+            from_reader::<_, Nested>(reader),
+            Err(Error {
+                kind: ErrorKind::DataError,
+                ..
+            })
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unit_variant_with_elements_is_refused() -> Result<()> {
+        let reader =
+            &mut io::BufReader::new("^0\nVariants\n$Unit\n^1\nVariants\n$Unit\n".as_bytes());
+
+        assert_eq!(Variants::Unit, from_reader(reader)?);
+        assert!(from_reader::<_, Variants>(reader).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_huge_length_is_not_allocated() {
+        let input = format!("&{}\nshort\n", u64::MAX);
+        let reader = &mut io::BufReader::new(input.as_bytes());
+
+        assert!(from_reader::<_, String>(reader).is_err());
+    }
+
+    #[test]
+    fn test_any() -> Result<()> {
+        use std::collections::BTreeMap;
+
+        #[derive(PartialEq, Deserialize, Debug)]
+        #[serde(untagged)]
+        enum Any {
+            Number(u64),
+            Text(String),
+            List(Vec<Any>),
+            Map(BTreeMap<String, Any>),
+        }
+
+        let input = "`3\nD7\n$text\n{1\n$key\n`0\n";
+        let reader = &mut io::BufReader::new(input.as_bytes());
+
+        let expected = Any::List(vec![
+            Any::Number(7),
+            Any::Text("text".into()),
+            Any::Map(
+                vec![("key".to_owned(), Any::List(Vec::new()))]
+                    .into_iter()
+                    .collect(),
+            ),
+        ]);
+        assert_eq!(expected, from_reader(reader)?);
+
+        Ok(())
+    }
+}
+
+mod test_limits {
+    use super::super::*;
+
+    const LIMITS: Limits = Limits {
+        max_len: 8,
+        max_elements: 2,
+    };
+
+    fn limit_exceeded<T: serde::de::DeserializeOwned>(input: &str) -> bool {
+        let reader = &mut io::BufReader::new(input.as_bytes());
+        matches!(
+            from_reader_with_limits::<_, T>(reader, LIMITS),
+            Err(Error {
+                kind: ErrorKind::LimitExceeded,
+                ..
+            })
+        )
+    }
+
+    #[test]
+    fn test_within_limits() -> Result<()> {
+        let reader = &mut io::BufReader::new("&8\n12345678\n`2\nI1\nI2\n".as_bytes());
+
+        assert_eq!(
+            "12345678",
+            from_reader_with_limits::<_, String>(reader, LIMITS)?
+        );
+        assert_eq!(
+            vec![1, 2],
+            from_reader_with_limits::<_, Vec<u32>>(reader, LIMITS)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_beyond_limits() {
+        assert!(limit_exceeded::<String>("&9\n123456789\n"));
+        assert!(limit_exceeded::<serde_bytes::ByteBuf>(&format!(
+            "%{}\n",
+            u64::MAX
+        )));
+        assert!(limit_exceeded::<String>("$123456789"));
+        assert!(limit_exceeded::<Vec<u32>>("`3\nI1\nI2\nI3\n"));
+        assert!(limit_exceeded::<std::collections::HashMap<u32, u32>>(
+            &format!("{{{}\n", u32::MAX)
+        ));
+    }
+}
+
+mod test_utf8 {
+    use super::super::*;
+    use serde_bytes::ByteBuf;
+
+    const INVALID: &[u8] = b"$ab\xffc\n&4\nab\xffc\n";
+
+    fn options(utf8: Utf8) -> Options {
+        Options {
+            utf8,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn test_strict() {
+        let reader = &mut io::BufReader::new(INVALID);
+
+        assert!(matches!(
+            from_reader_with_options::<_, String>(reader, options(Utf8::Strict)),
+            Err(Error {
+                kind: ErrorKind::FromUtf8Error(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_lossy() -> Result<()> {
+        let reader = &mut io::BufReader::new(INVALID);
+
+        for _ in 0..2 {
+            let string = from_reader_with_options::<_, String>(reader, options(Utf8::Lossy))?;
+            assert_eq!("ab\u{FFFD}c", string);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_raw() -> Result<()> {
+        let reader = &mut io::BufReader::new(INVALID);
+
+        for _ in 0..2 {
+            let bytes = from_reader_with_options::<_, ByteBuf>(reader, options(Utf8::Raw))?;
+            assert_eq!(b"ab\xffc", bytes.as_slice());
+        }
+        let reader = &mut io::BufReader::new(INVALID);
+        assert!(from_reader_with_options::<_, String>(reader, options(Utf8::Raw)).is_err());
+        let reader = &mut io::BufReader::new(INVALID);
+        assert!(from_reader_with_options::<_, ByteBuf>(reader, options(Utf8::Strict)).is_err());
+
+        Ok(())
+    }
+}
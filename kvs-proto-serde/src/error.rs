@@ -13,6 +13,8 @@ pub enum ErrorKind {
     ParseCharError(std::char::ParseCharError),
     FromUtf8Error(string::FromUtf8Error),
     DataError,
+    /// the input went beyond the Limits of the deserialization
+    LimitExceeded,
 }
 
 #[derive(Debug)]
@@ -23,9 +25,18 @@ pub struct Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    pub(crate) fn limit_exceeded(message: String) -> Self {
+        Self {
+            kind: ErrorKind::LimitExceeded,
+            message,
+        }
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        todo!()
+        f.write_str(&self.message)
     }
 }
 
@@ -40,7 +51,10 @@ impl ser::Error for Error {
     where
         T: std::fmt::Display,
     {
-        todo!()
+        Self {
+            kind: ErrorKind::DataError,
+            message: msg.to_string(),
+        }
     }
 }
 
@@ -49,7 +63,10 @@ impl de::Error for Error {
     where
         T: std::fmt::Display,
     {
-        todo!()
+        Self {
+            kind: ErrorKind::DataError,
+            message: msg.to_string(),
+        }
     }
 }
 
@@ -63,6 +80,20 @@ impl From<io::Error> for Error {
     }
 }
 
+impl From<codec_util::Error> for Error {
+    fn from(codec_error: codec_util::Error) -> Self {
+        let kind = match codec_error.kind {
+            codec_util::ErrorKind::IoError(io_error) => ErrorKind::IoError(io_error),
+            codec_util::ErrorKind::LimitExceeded => ErrorKind::LimitExceeded,
+            codec_util::ErrorKind::MissingLineEnding => ErrorKind::DataError,
+        };
+        Self {
+            kind,
+            message: codec_error.message,
+        }
+    }
+}
+
 impl From<num::ParseIntError> for Error {
     fn from(parse_error: num::ParseIntError) -> Self {
         let message = parse_error.to_string();
@@ -0,0 +1,24 @@
+use super::*;
+
+#[test]
+fn test_display_is_message() {
+    let error = Error::from("not a number".parse::<u32>().unwrap_err());
+    assert_eq!(
+        "not a number".parse::<u32>().unwrap_err().to_string(),
+        error.to_string()
+    );
+}
+
+#[test]
+fn test_ser_custom() {
+    let error = <Error as ser::Error>::custom("custom serialization error");
+    assert!(matches!(error.kind, ErrorKind::DataError));
+    assert_eq!("custom serialization error", error.to_string());
+}
+
+#[test]
+fn test_de_custom() {
+    let error = <Error as de::Error>::custom("custom deserialization error");
+    assert!(matches!(error.kind, ErrorKind::DataError));
+    assert_eq!("custom deserialization error", error.to_string());
+}
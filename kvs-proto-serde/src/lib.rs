@@ -6,7 +6,9 @@ mod error;
 mod de;
 mod ser;
 
-pub use de::from_reader;
+pub use de::{
+    from_reader, from_reader_with_limits, from_reader_with_options, Limits, Options, Utf8,
+};
 pub use ser::to_writer;
 
 pub use error::{Error, ErrorKind, Result};
@@ -51,83 +51,81 @@ impl<'a, 'writer, W: io::Write> ser::Serializer for &'a mut Serializer<'writer,
     }
 
     fn serialize_i8(self, v: i8) -> Result<Self::Ok> {
-        self.writer.write_all(format!("b{}\n", v).as_bytes())?;
+        writeln!(self.writer, "b{}", v)?;
         Ok(())
     }
 
     fn serialize_i16(self, v: i16) -> Result<Self::Ok> {
-        self.writer.write_all(format!("w{}\n", v).as_bytes())?;
+        writeln!(self.writer, "w{}", v)?;
         Ok(())
     }
 
     fn serialize_i32(self, v: i32) -> Result<Self::Ok> {
-        self.writer.write_all(format!("i{}\n", v).as_bytes())?;
+        writeln!(self.writer, "i{}", v)?;
         Ok(())
     }
 
     fn serialize_i64(self, v: i64) -> Result<Self::Ok> {
-        self.writer.write_all(format!("d{}\n", v).as_bytes())?;
+        writeln!(self.writer, "d{}", v)?;
         Ok(())
     }
 
     fn serialize_i128(self, v: i128) -> Result<Self::Ok> {
-        self.writer.write_all(format!("q{}\n", v).as_bytes())?;
+        writeln!(self.writer, "q{}", v)?;
         Ok(())
     }
 
     fn serialize_u8(self, v: u8) -> Result<Self::Ok> {
-        self.writer.write_all(format!("B{}\n", v).as_bytes())?;
+        writeln!(self.writer, "B{}", v)?;
         Ok(())
     }
 
     fn serialize_u16(self, v: u16) -> Result<Self::Ok> {
-        self.writer.write_all(format!("W{}\n", v).as_bytes())?;
+        writeln!(self.writer, "W{}", v)?;
         Ok(())
     }
 
     fn serialize_u32(self, v: u32) -> Result<Self::Ok> {
-        self.writer.write_all(format!("I{}\n", v).as_bytes())?;
+        writeln!(self.writer, "I{}", v)?;
         Ok(())
     }
 
     fn serialize_u64(self, v: u64) -> Result<Self::Ok> {
-        self.writer.write_all(format!("D{}\n", v).as_bytes())?;
+        writeln!(self.writer, "D{}", v)?;
         Ok(())
     }
 
     fn serialize_u128(self, v: u128) -> Result<Self::Ok> {
-        self.writer.write_all(format!("Q{}\n", v).as_bytes())?;
+        writeln!(self.writer, "Q{}", v)?;
         Ok(())
     }
 
     fn serialize_f32(self, v: f32) -> Result<Self::Ok> {
-        self.writer.write_all(format!("f{}\n", v).as_bytes())?;
+        writeln!(self.writer, "f{}", v)?;
         Ok(())
     }
 
     fn serialize_f64(self, v: f64) -> Result<Self::Ok> {
-        self.writer.write_all(format!("F{}\n", v).as_bytes())?;
+        writeln!(self.writer, "F{}", v)?;
         Ok(())
     }
 
     fn serialize_char(self, v: char) -> Result<Self::Ok> {
-        self.writer.write_all(format!("c{}\n", v).as_bytes())?;
+        writeln!(self.writer, "c{}", v)?;
         Ok(())
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok> {
-        let to_write = if v.contains(|c| c == '\n') {
-            format!("&{}\n{}\n", v.len(), v)
+        if v.contains('\n') {
+            writeln!(self.writer, "&{}\n{}", v.len(), v)?;
         } else {
-            format!("${}\n", v)
-        };
-        self.writer.write_all(to_write.as_bytes())?;
+            writeln!(self.writer, "${}", v)?;
+        }
         Ok(())
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<Self::Ok> {
-        self.writer
-            .write_all(format!("%{}\n", v.len()).as_bytes())?;
+        writeln!(self.writer, "%{}", v.len())?;
         self.writer.write_all(v)?;
         self.writer.write_all("\n".as_bytes())?;
         Ok(())
@@ -138,9 +136,9 @@ impl<'a, 'writer, W: io::Write> ser::Serializer for &'a mut Serializer<'writer,
         Ok(())
     }
 
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok>
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(self)
     }
@@ -151,8 +149,7 @@ impl<'a, 'writer, W: io::Write> ser::Serializer for &'a mut Serializer<'writer,
     }
 
     fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok> {
-        self.writer
-            .write_all(format!("}}0\n{}\n", name).as_bytes())?;
+        writeln!(self.writer, "}}0\n{}", name)?;
         Ok(())
     }
 
@@ -162,21 +159,19 @@ impl<'a, 'writer, W: io::Write> ser::Serializer for &'a mut Serializer<'writer,
         _variant_index: u32,
         variant: &'static str,
     ) -> Result<Self::Ok> {
-        self.writer
-            .write_all(format!("@{}\n${}\n", name, variant).as_bytes())?;
+        writeln!(self.writer, "@{}\n${}", name, variant)?;
         Ok(())
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(self, name: &'static str, value: &T) -> Result<Self::Ok>
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Self::Ok>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.writer
-            .write_all(format!(":1\n{}\n", name).as_bytes())?;
+        writeln!(self.writer, ":1\n{}", name)?;
         value.serialize(&mut *self)
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         name: &'static str,
         _variant_index: u32,
@@ -184,28 +179,27 @@ impl<'a, 'writer, W: io::Write> ser::Serializer for &'a mut Serializer<'writer,
         value: &T,
     ) -> Result<Self::Ok>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
-        self.writer
-            .write_all(format!("^1\n{}\n${}\n", name, variant).as_bytes())?;
+        writeln!(self.writer, "^1\n{}\n${}", name, variant)?;
         value.serialize(&mut *self)?;
         Ok(())
     }
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
         match len {
-            Some(len) => {
-                self.writer.write_all(format!("`{}\n", len).as_bytes())?
-            },
-            None => unimplemented!(
-                "Sequences without a known length before iterating are not supported by this serialization format"
-            ),
+            Some(len) => writeln!(self.writer, "`{}", len)?,
+            None => {
+                return Err(ser::Error::custom(
+                    "sequences without a known length are not supported by kvs-proto",
+                ))
+            }
         };
         Ok(self)
     }
 
     fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
-        self.writer.write_all(format!("~{}\n", len).as_bytes())?;
+        writeln!(self.writer, "~{}", len)?;
         Ok(self)
     }
 
@@ -214,8 +208,7 @@ impl<'a, 'writer, W: io::Write> ser::Serializer for &'a mut Serializer<'writer,
         name: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleStruct> {
-        self.writer
-            .write_all(format!(":{}\n{}\n", len, name).as_bytes())?;
+        writeln!(self.writer, ":{}\n{}", len, name)?;
         Ok(self)
     }
 
@@ -226,24 +219,24 @@ impl<'a, 'writer, W: io::Write> ser::Serializer for &'a mut Serializer<'writer,
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
-        self.writer
-            .write_all(format!("^{}\n{}\n${}\n", len, name, variant).as_bytes())?;
+        writeln!(self.writer, "^{}\n{}\n${}", len, name, variant)?;
         Ok(self)
     }
 
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
         match len {
-            Some(len) => self.writer.write_all(format!("{{{}\n", len).as_bytes())?,
-            None => unimplemented!(
-                "Maps without a known length before iterating are not supported by this serialization format"
-            ),
+            Some(len) => writeln!(self.writer, "{{{}", len)?,
+            None => {
+                return Err(ser::Error::custom(
+                    "maps without a known length are not supported by kvs-proto",
+                ))
+            }
         };
         Ok(self)
     }
 
     fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
-        self.writer
-            .write_all(format!("}}{}\n{}\n", len, name).as_bytes())?;
+        writeln!(self.writer, "}}{}\n{}", len, name)?;
         Ok(self)
     }
 
@@ -254,8 +247,7 @@ impl<'a, 'writer, W: io::Write> ser::Serializer for &'a mut Serializer<'writer,
         variant: &'static str,
         len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        self.writer
-            .write_all(format!("#{}\n{}\n${}\n", len, name, variant).as_bytes())?;
+        writeln!(self.writer, "#{}\n{}\n${}", len, name, variant)?;
         Ok(self)
     }
 }
@@ -265,9 +257,9 @@ impl<'a, 'writer, W: io::Write> ser::SerializeSeq for &'a mut Serializer<'writer
 
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut **self)
     }
@@ -282,9 +274,9 @@ impl<'a, 'writer, W: io::Write> ser::SerializeTuple for &'a mut Serializer<'writ
 
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut **self)
     }
@@ -299,9 +291,9 @@ impl<'a, 'writer, W: io::Write> ser::SerializeTupleStruct for &'a mut Serializer
 
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut **self)
     }
@@ -316,9 +308,9 @@ impl<'a, 'writer, W: io::Write> ser::SerializeTupleVariant for &'a mut Serialize
 
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut **self)
     }
@@ -334,16 +326,16 @@ impl<'a, 'writer, W: io::Write> ser::SerializeMap for &'a mut Serializer<'writer
 
     type Error = Error;
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<()>
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         key.serialize(&mut **self)
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut **self)?;
         Ok(())
@@ -359,9 +351,9 @@ impl<'a, 'writer, W: io::Write> ser::SerializeStruct for &'a mut Serializer<'wri
 
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         key.serialize(&mut **self)?;
         value.serialize(&mut **self)?;
@@ -378,9 +370,9 @@ impl<'a, 'writer, W: io::Write> ser::SerializeStructVariant for &'a mut Serializ
 
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         key.serialize(&mut **self)?;
         value.serialize(&mut **self)?;
@@ -21,11 +21,11 @@ fn test_float<T: Display + Serialize>(indicator: char, value: T) -> Result<()> {
 #[test]
 fn test_bool() -> Result<()> {
     let mut buf = Vec::<u8>::new();
-    to_writer(&mut io::BufWriter::new(&mut buf), &true)?;
+    to_writer(&mut io::BufWriter::new(&mut buf), true)?;
     assert_eq!("1\n".as_bytes(), buf.as_slice());
 
     let mut buf = Vec::<u8>::new();
-    to_writer(&mut io::BufWriter::new(&mut buf), &false)?;
+    to_writer(&mut io::BufWriter::new(&mut buf), false)?;
     assert_eq!("0\n".as_bytes(), buf.as_slice());
 
     Ok(())
@@ -157,7 +157,7 @@ fn test_str() -> Result<()> {
     ];
     let mut buf = Vec::<u8>::new();
     for str in strs_to_test {
-        let expected = if str.contains(|c| c == '\n') {
+        let expected = if str.contains('\n') {
             format!("&{}\n{}\n", str.len(), str)
         } else {
             format!("${}\n", str)
@@ -775,6 +775,24 @@ mod test_map {
         assert_eq!(expected.as_bytes(), buf.as_slice());
         Ok(())
     }
+
+    #[derive(Serialize)]
+    struct Flattened {
+        field1: u8,
+        #[serde(flatten)]
+        extra: HashMap<String, u8>,
+    }
+
+    #[test]
+    fn test_map_of_unknown_length_is_an_error() {
+        let flattened = Flattened {
+            field1: 1,
+            extra: HashMap::new(),
+        };
+        let mut buf = Vec::new();
+        let err = to_writer(&mut io::BufWriter::new(&mut buf), &flattened).unwrap_err();
+        assert!(matches!(err.kind, error::ErrorKind::DataError));
+    }
 }
 
 mod test_struct {
@@ -0,0 +1,42 @@
+//! Compares the time of a lookup of the index of a KvStore with `IndexHasher::sip` and with
+//! `IndexHasher::custom` (`cargo bench --bench index_hasher`), `custom` being given the
+//! BuildHasher of the same SipHash so that what it costs beyond `sip` is the boxing of its hasher.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::BuildHasherDefault,
+    hint::black_box,
+    time::Instant,
+};
+
+use kvs::IndexHasher;
+
+const KEYS: usize = 100_000;
+const ROUNDS: usize = 20;
+
+fn lookups(name: &str, hasher: IndexHasher, keys: &[String]) {
+    let mut index = HashMap::with_hasher(hasher);
+    for (offset, key) in keys.iter().enumerate() {
+        index.insert(key.clone(), offset as u64);
+    }
+    let started = Instant::now();
+    for _ in 0..ROUNDS {
+        for key in keys {
+            black_box(index.get(black_box(key)));
+        }
+    }
+    let per_lookup = started.elapsed() / (ROUNDS * keys.len()) as u32;
+    println!("{:<8} {:>6?} per lookup", name, per_lookup);
+}
+
+fn main() {
+    let keys = (0..KEYS)
+        .map(|key| format!("key:{:010}", key))
+        .collect::<Vec<_>>();
+    lookups("sip", IndexHasher::sip(), &keys);
+    lookups(
+        "custom",
+        IndexHasher::custom(BuildHasherDefault::<DefaultHasher>::default()),
+        &keys,
+    );
+}
@@ -0,0 +1,15 @@
+fn main() {
+    // the gRPC service is generated from its definition, parsed without needing protoc installed
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/kvs.proto");
+        let descriptors = protox::compile(["proto/kvs.proto"], ["proto"])
+            .expect("proto/kvs.proto could not be parsed");
+        // the client's `connect` is not generated, as it relies on the 2021 prelude, so clients are
+        // created from a tonic Channel with `KvsClient::new`
+        tonic_build::configure()
+            .build_transport(false)
+            .compile_fds(descriptors)
+            .expect("the gRPC service could not be generated");
+    }
+}
@@ -0,0 +1,52 @@
+//! Compares the I/O backends of KvStore: the time taken to set keys (syncing the log every so
+//! often) and to reopen the store, which scans its log.
+//!
+//! `cargo run --release --features io-uring --example io_backends [<keys>]`
+
+use kvs::{IoBackend, KvStore, Result, StoreOptions};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+/// number of sets between syncs of the log
+const SETS_PER_SYNC: usize = 100;
+
+fn main() -> Result<()> {
+    let keys = std::env::args()
+        .nth(1)
+        .map_or(100_000, |keys| keys.parse().expect("the number of keys"));
+    println!("{:<10} {:>12} {:>12}", "backend", "set+sync", "reopen");
+    for backend in &[IoBackend::Standard, IoBackend::IoUring] {
+        let (writing, reopening) = run(*backend, keys)?;
+        println!(
+            "{:<10} {:>10.1}ms {:>10.1}ms",
+            format!("{:?}", backend),
+            writing.as_secs_f64() * 1000.0,
+            reopening.as_secs_f64() * 1000.0
+        );
+    }
+    Ok(())
+}
+
+fn run(io_backend: IoBackend, keys: usize) -> Result<(Duration, Duration)> {
+    let temp_dir = TempDir::new()?;
+    let options = StoreOptions {
+        io_backend,
+        ..StoreOptions::default()
+    };
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    let started = Instant::now();
+    for i in 0..keys {
+        store.set(format!("key{}", i), format!("value{}", i))?;
+        if i % SETS_PER_SYNC == SETS_PER_SYNC - 1 {
+            store.sync()?;
+        }
+    }
+    store.sync()?;
+    let writing = started.elapsed();
+    drop(store);
+
+    let started = Instant::now();
+    let store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    assert_eq!(store.len(), keys);
+    Ok((writing, started.elapsed()))
+}
@@ -0,0 +1,45 @@
+use std::{fmt, hash, thread, time::Duration};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{Error, ErrorKind, KvStore, Result, StoreEvent};
+
+/// What a write to a KvStore does when its log holds more stale records per key than the
+/// `max_stale_ratio` set in its StoreOptions, and compacting the log (which the write tries
+/// first) fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backpressure {
+    /// fail the write with Busy
+    #[default]
+    Reject,
+    /// sleep for the duration and try compacting again, failing the write with Busy if that
+    /// fails too
+    Sleep(Duration),
+}
+
+impl<K, V> KvStore<K, V>
+where
+    K: Serialize + DeserializeOwned + Eq + PartialEq + hash::Hash + Clone + fmt::Debug,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    /// compacts the log before a write of a value if it holds more stale records per key than
+    /// the store's ceiling (however few keys it holds), Busy if it cannot, so the log does not
+    /// grow without bound while compaction is failing
+    pub(crate) fn hold_back_if_compaction_lags(&mut self) -> Result<()> {
+        let ceiling = match self.max_stale_ratio {
+            Some(ceiling) => ceiling,
+            None => return Ok(()),
+        };
+        let stale_ratio = self.stale_count as f64 / self.index.len().max(1) as f64;
+        if stale_ratio <= ceiling {
+            return Ok(());
+        }
+        self.notify_event(|| StoreEvent::WriteStalled { stale_ratio });
+        let mut compacted = self.compact();
+        if let (Err(_), Backpressure::Sleep(pause)) = (&compacted, self.backpressure) {
+            thread::sleep(pause);
+            compacted = self.compact();
+        }
+        compacted.map_err(|err| Error::with_source(ErrorKind::Busy, err))
+    }
+}
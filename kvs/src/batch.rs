@@ -0,0 +1,55 @@
+/// A group of writes applied to a KvStore together, in order, with a single flush of the log
+///
+/// # Example
+/// ```
+/// use kvs::{KvStore, WriteBatch};
+///
+/// let mut store = KvStore::<String,String>::temporary().unwrap();
+/// let mut batch = WriteBatch::new();
+/// batch.set("key1".into(), "value1".into()).set("key2".into(), "value2".into()).remove("key1".into());
+/// let applied = store.write_batch(batch).unwrap();
+/// assert_eq!(applied, vec![true, true, true]);
+/// assert_eq!(store.get("key1".into()).unwrap(), None);
+/// assert_eq!(store.get("key2".into()).unwrap(), Some("value2".into()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct WriteBatch<K, V> {
+    operations: Vec<(K, Option<V>)>,
+}
+
+impl<K, V> WriteBatch<K, V> {
+    /// create a new empty batch
+    pub fn new() -> Self {
+        Self {
+            operations: Vec::new(),
+        }
+    }
+    /// add setting a key to a value to the batch
+    pub fn set(&mut self, key: K, value: V) -> &mut Self {
+        self.operations.push((key, Some(value)));
+        self
+    }
+    /// add removing a key to the batch (which is skipped if the key is not present when applied)
+    pub fn remove(&mut self, key: K) -> &mut Self {
+        self.operations.push((key, None));
+        self
+    }
+    /// number of operations in the batch
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+    /// true if there are no operations in the batch
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    pub(crate) fn into_operations(self) -> Vec<(K, Option<V>)> {
+        self.operations
+    }
+}
+
+impl<K, V> Default for WriteBatch<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
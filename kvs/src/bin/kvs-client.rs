@@ -0,0 +1,345 @@
+use clap::{App, Arg};
+use kvs::{Error, ErrorKind, KvsClient, Result, Topology};
+use serde_json::json;
+use std::time::Duration;
+
+fn main() -> Result<()> {
+    let result = match arguments().subcommand() {
+        ("set", Some(args)) => handle_subcommand_set(args),
+        ("get", Some(args)) => handle_subcommand_get(args),
+        ("rm", Some(args)) => handle_subcommand_rm(args),
+        ("compact", Some(args)) => connect(args)?.compact(),
+        ("flushall", Some(args)) => connect(args)?.clear(),
+        ("reload-config", Some(args)) => connect(args)?.reload_config(),
+        ("dbsize", Some(args)) => handle_subcommand_dbsize(args),
+        ("stats", Some(args)) => handle_subcommand_stats(args),
+        ("scan", Some(args)) => handle_subcommand_scan(args),
+        ("set-topology", Some(args)) => connect(args)?.set_topology(topology(args)?),
+        ("migrate", Some(args)) => handle_subcommand_migrate(args),
+        ("migration", Some(args)) => handle_subcommand_migration(args),
+        ("cutover", Some(args)) => connect(args)?.finish_migration(topology(args)?),
+        _ => handle_invalid_command(),
+    };
+    match result {
+        Err(err) if *err.kind() == ErrorKind::KeyNotPresent => {
+            eprintln!("Key not found");
+            Err(err)
+        }
+        Err(err) if *err.kind() == ErrorKind::AuthenticationFailed => {
+            eprintln!("Authentication failed");
+            Err(err)
+        }
+        result => result,
+    }
+}
+
+fn arguments() -> clap::ArgMatches<'static> {
+    let connection = connection_arguments();
+    App::new("kvs-client")
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .about("Key-Value Store Client")
+        .version(env!("CARGO_PKG_VERSION"))
+        .subcommand(
+            App::new("set")
+                .about("sets a <key> to the given <value>")
+                .arg(Arg::with_name("key").index(1).required(true))
+                .arg(Arg::with_name("value").index(2).required(true))
+                .args(&connection),
+        )
+        .subcommand(
+            App::new("get")
+                .about("given a <key> gets the given <value> (if present)")
+                .arg(Arg::with_name("key").index(1).required(true))
+                .arg(
+                    Arg::with_name("max-lag-ms")
+                        .long("max-lag-ms")
+                        .value_name("MILLISECONDS")
+                        .takes_value(true)
+                        .help(
+                            "allow a follower of a Raft cluster to answer with a value this stale",
+                        ),
+                )
+                .args(&connection),
+        )
+        .subcommand(
+            App::new("rm")
+                .about("remove the given <key> (and associated value) if present")
+                .arg(Arg::with_name("key").index(1).required(true))
+                .args(&connection),
+        )
+        .subcommand(
+            App::new("compact")
+                .about("compact the server's storage now")
+                .args(&connection),
+        )
+        .subcommand(
+            App::new("flushall")
+                .about("remove every key (and associated value)")
+                .args(&connection),
+        )
+        .subcommand(
+            App::new("reload-config")
+                .about("make the server read its config file again")
+                .args(&connection),
+        )
+        .subcommand(
+            App::new("dbsize")
+                .about("print the number of keys present")
+                .args(&connection),
+        )
+        .subcommand(
+            App::new("stats")
+                .about(
+                    "print the server's INFO: its number of keys and (with the stats feature) the \
+                        sizes of the keys and values written",
+                )
+                .args(&connection),
+        )
+        .subcommand(
+            App::new("scan")
+                .about(
+                    "print each key starting with the <prefix> (every key if none) and its value",
+                )
+                .arg(Arg::with_name("prefix").index(1).default_value(""))
+                .args(&connection),
+        )
+        .subcommand(
+            App::new("set-topology")
+                .about("replace the server's cluster topology with that of the JSON <file>")
+                .arg(Arg::with_name("file").index(1).required(true))
+                .args(&connection),
+        )
+        .subcommand(
+            App::new("migrate")
+                .about("start migrating the keys from <start> to the server at <target>")
+                .arg(Arg::with_name("start").index(1).required(true))
+                .arg(Arg::with_name("target").index(2).required(true))
+                .arg(
+                    Arg::with_name("end")
+                        .long("end")
+                        .value_name("KEY")
+                        .takes_value(true)
+                        .help("first key not migrated (all keys after <start> if not given)"),
+                )
+                .args(&connection),
+        )
+        .subcommand(
+            App::new("migration")
+                .about("print the progress of the server's last migration")
+                .args(&connection),
+        )
+        .subcommand(
+            App::new("cutover")
+                .about("cut the server's migration over to the topology of the JSON <file>")
+                .arg(Arg::with_name("file").index(1).required(true))
+                .args(&connection),
+        )
+        .after_help(
+            "kvs-client is a command-line client for kvs-server. \
+                It is implemented as part of the PingCAP Talent Plan tutorial series for Rust.",
+        )
+        .get_matches()
+}
+
+fn connection_arguments() -> Vec<Arg<'static, 'static>> {
+    let addr = Arg::with_name("addr")
+        .long("addr")
+        .value_name("IP:PORT")
+        .takes_value(true)
+        .default_value("127.0.0.1:4000")
+        .help("address of the server to connect to");
+    let password = Arg::with_name("password")
+        .long("password")
+        .value_name("SECRET")
+        .takes_value(true)
+        .env("KVS_PASSWORD")
+        .hide_env_values(true)
+        .help("password to authenticate with before sending the command");
+    let request_id = Arg::with_name("request-id")
+        .long("request-id")
+        .value_name("ID")
+        .takes_value(true)
+        .help("ID to tag the request with, by which the server logs it and reports its errors");
+    let db = Arg::with_name("db")
+        .long("db")
+        .value_name("INDEX")
+        .takes_value(true)
+        .default_value("0")
+        .help("number of the server's database to send the command to");
+    let output = Arg::with_name("output")
+        .long("output")
+        .value_name("FORMAT")
+        .takes_value(true)
+        .possible_values(&["text", "json"])
+        .default_value("text")
+        .help("format in which to print results");
+    #[allow(unused_mut)]
+    let mut args = vec![addr, password, db, request_id, output];
+    #[cfg(feature = "tls")]
+    args.extend(vec![
+        Arg::with_name("tls-ca")
+            .long("tls-ca")
+            .value_name("PATH")
+            .takes_value(true)
+            .help("PEM file of the CA certificates to trust, connecting using TLS"),
+        Arg::with_name("tls-server-name")
+            .long("tls-server-name")
+            .value_name("NAME")
+            .takes_value(true)
+            .default_value("localhost")
+            .help("name the server's TLS certificate must be valid for"),
+    ]);
+    args
+}
+
+fn connect(args: &clap::ArgMatches) -> Result<KvsClient> {
+    let mut client = connect_transport(args)?;
+    client.set_request_id(args.value_of("request-id").map(String::from));
+    if let Some(password) = args.value_of("password") {
+        client.authenticate(password.into())?;
+    }
+    match args.value_of("db").unwrap() {
+        "0" => {}
+        db => client.select(
+            db.parse()
+                .map_err(|_| Error::new(ErrorKind::InvalidConfiguration))?,
+        )?,
+    }
+    Ok(client)
+}
+
+#[cfg(feature = "tls")]
+fn connect_transport(args: &clap::ArgMatches) -> Result<KvsClient> {
+    let addr = args.value_of("addr").unwrap();
+    match args.value_of("tls-ca") {
+        Some(ca) => KvsClient::connect_tls(
+            addr,
+            &kvs::ClientTlsConfig::from_pem_file(
+                std::path::Path::new(ca),
+                args.value_of("tls-server-name").unwrap(),
+            )?,
+        ),
+        None => KvsClient::connect(addr),
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+fn connect_transport(args: &clap::ArgMatches) -> Result<KvsClient> {
+    KvsClient::connect(args.value_of("addr").unwrap())
+}
+
+fn handle_subcommand_set(args: &clap::ArgMatches) -> Result<()> {
+    connect(args)?.set(
+        args.value_of("key").unwrap().into(),
+        args.value_of("value").unwrap().into(),
+    )
+}
+
+fn handle_subcommand_get(args: &clap::ArgMatches) -> Result<()> {
+    let key = args.value_of("key").unwrap();
+    let mut client = connect(args)?;
+    let value = match args.value_of("max-lag-ms") {
+        Some(max_lag_ms) => {
+            let max_lag_ms = max_lag_ms
+                .parse()
+                .map_err(|_| Error::new(ErrorKind::InvalidConfiguration))?;
+            client.get_with_max_lag(key.into(), Duration::from_millis(max_lag_ms))?
+        }
+        None => client.get(key.into())?,
+    };
+    match (value, is_json(args)) {
+        (value, true) => println!("{}", json!({ "key": key, "value": value })),
+        (Some(value), false) => println!("{}", value),
+        (None, false) => println!("Key not found"),
+    }
+    Ok(())
+}
+
+fn handle_subcommand_rm(args: &clap::ArgMatches) -> Result<()> {
+    connect(args)?.remove(args.value_of("key").unwrap().into())
+}
+
+fn handle_subcommand_dbsize(args: &clap::ArgMatches) -> Result<()> {
+    let count = connect(args)?.key_count()?;
+    match is_json(args) {
+        true => println!("{}", json!({ "count": count })),
+        false => println!("{}", count),
+    }
+    Ok(())
+}
+
+fn handle_subcommand_stats(args: &clap::ArgMatches) -> Result<()> {
+    let info = connect(args)?.info()?;
+    // the `<field>:<value>` lines of every section, the `# <Section>` lines heading them left out
+    let fields = info
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(field, _)| !field.starts_with('#'));
+    match is_json(args) {
+        true => {
+            let fields = fields.map(|(field, value)| match value.parse::<u64>() {
+                Ok(number) => (field.to_owned(), json!(number)),
+                Err(_) => (field.to_owned(), json!(value)),
+            });
+            println!("{}", serde_json::Value::Object(fields.collect()))
+        }
+        false => fields.for_each(|(field, value)| println!("{}: {}", field, value)),
+    }
+    Ok(())
+}
+
+fn handle_subcommand_scan(args: &clap::ArgMatches) -> Result<()> {
+    let mut client = connect(args)?;
+    for pair in client.scan(args.value_of("prefix").unwrap().into()) {
+        let (key, value) = pair?;
+        match is_json(args) {
+            true => println!("{}", json!({ "key": key, "value": value })),
+            false => println!("{} {}", key, value),
+        }
+    }
+    Ok(())
+}
+
+fn handle_subcommand_migrate(args: &clap::ArgMatches) -> Result<()> {
+    connect(args)?.migrate_range(
+        args.value_of("start").unwrap().into(),
+        args.value_of("end").map(Into::into),
+        args.value_of("target").unwrap().into(),
+    )
+}
+
+fn handle_subcommand_migration(args: &clap::ArgMatches) -> Result<()> {
+    let status = connect(args)?.migration_status()?;
+    match (status, is_json(args)) {
+        (status, true) => println!("{}", json!(status)),
+        (Some(status), false) => {
+            let state = match (&status.error, status.finished, status.streaming) {
+                (Some(error), _, _) => format!("failed: {}", error),
+                (None, true, _) => "finished".to_owned(),
+                (None, false, true) => "streaming".to_owned(),
+                (None, false, false) => "copying".to_owned(),
+            };
+            println!(
+                "{} ({} keys copied to {})",
+                state, status.copied, status.target
+            );
+        }
+        (None, false) => println!("No migration"),
+    }
+    Ok(())
+}
+
+/// reads the topology of a cluster from the JSON file given
+fn topology(args: &clap::ArgMatches) -> Result<Topology> {
+    let json = std::fs::read_to_string(args.value_of("file").unwrap())?;
+    serde_json::from_str(&json).map_err(|_| Error::new(ErrorKind::InvalidConfiguration))
+}
+
+fn is_json(args: &clap::ArgMatches) -> bool {
+    args.value_of("output") == Some("json")
+}
+
+fn handle_invalid_command() -> Result<()> {
+    eprintln!("Invalid Options or Command");
+    std::process::exit(1)
+}
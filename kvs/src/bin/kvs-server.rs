@@ -0,0 +1,386 @@
+use std::path;
+
+use clap::{App, Arg};
+use kvs::{
+    Acl, Error, ErrorKind, KvStore, KvsEngine, KvsServer, RaftEngine, RaftOptions, RateLimit,
+    Result, SharedKvStore, Topology,
+};
+
+fn main() -> Result<()> {
+    let args = arguments();
+    if args.is_present("redis-compat-report") {
+        print!("{}", kvs::redis_compatibility_report());
+        return Ok(());
+    }
+    let path = path::Path::new("./");
+    match args.value_of("raft-id") {
+        Some(id) => run(raft_engine(path, parse(id)?, &args)?, Vec::new(), &args),
+        None => {
+            let store = KvStore::open(path)?;
+            let databases = (1..parse(args.value_of("databases").unwrap())?)
+                .map(|db: u64| store.keyspace(&db.to_string()).map(SharedKvStore::new))
+                .collect::<Result<_>>()?;
+            run(SharedKvStore::new(store), databases, &args)
+        }
+    }
+}
+
+/// serves the engine as database 0 and the others as the databases numbered after it
+fn run<E: KvsEngine + Sync>(engine: E, databases: Vec<E>, args: &clap::ArgMatches) -> Result<()> {
+    let addr = args.value_of("addr").unwrap();
+    eprintln!(
+        "{} {} listening on {}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        addr
+    );
+    #[cfg(feature = "http")]
+    if let Some(http_addr) = args.value_of("http-addr") {
+        serve_http(engine.clone(), http_addr)?;
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(grpc_addr) = args.value_of("grpc-addr") {
+        serve_grpc(engine.clone(), grpc_addr)?;
+    }
+    let server = databases
+        .into_iter()
+        .fold(server(engine, args)?, KvsServer::with_database);
+    let shutdown = server.shutdown_handle();
+    // SIGINT and SIGTERM stop accepting connections, drain open ones and shut the engine down
+    ctrlc::set_handler(move || shutdown.shutdown())
+        .map_err(|_| Error::new(ErrorKind::UnknownError))?;
+    #[cfg(unix)]
+    if args.is_present("config") {
+        reload_on_sighup(server.reload_handle())?;
+    }
+    server.run(addr)?;
+    eprintln!("{} shut down cleanly", env!("CARGO_PKG_NAME"));
+    Ok(())
+}
+
+/// reloads the server's config file on each SIGHUP (which otherwise shuts it down as SIGTERM does)
+#[cfg(unix)]
+fn reload_on_sighup(reload: kvs::ReloadHandle) -> Result<()> {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+    extern "C" fn request_reload(_: libc::c_int) {
+        RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+    }
+    // the handler only stores to an atomic, which is safe to do in a signal handler
+    let handler = request_reload as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    if unsafe { libc::signal(libc::SIGHUP, handler) } == libc::SIG_ERR {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    std::thread::spawn(move || loop {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        if RELOAD_REQUESTED.swap(false, Ordering::SeqCst) {
+            match reload.reload() {
+                Ok(()) => eprintln!("Configuration reloaded"),
+                Err(err) => eprintln!("Failed to reload the configuration: {}", err),
+            }
+        }
+    });
+    Ok(())
+}
+
+/// starts the store as a node of a Raft cluster, listening for its peers on `--raft-addr`
+fn raft_engine(path: &path::Path, id: u64, args: &clap::ArgMatches) -> Result<RaftEngine> {
+    let raft_addr = args.value_of("raft-addr").unwrap();
+    let mut peers = Vec::new();
+    for peer in args.values_of("raft-peer").into_iter().flatten() {
+        let (peer_id, peer_addr) = peer
+            .split_once('=')
+            .ok_or_else(|| Error::new(ErrorKind::InvalidConfiguration))?;
+        peers.push((parse(peer_id)?, parse(peer_addr)?));
+    }
+    let listener = std::net::TcpListener::bind(raft_addr)?;
+    eprintln!("Raft node {} listening for peers on {}", id, raft_addr);
+    RaftEngine::start(path, id, peers, listener, RaftOptions::default())
+}
+
+/// serves the HTTP gateway upon its own thread
+#[cfg(feature = "http")]
+fn serve_http<E: KvsEngine>(engine: E, addr: &str) -> Result<()> {
+    let listener = std::net::TcpListener::bind(addr)?;
+    eprintln!("HTTP gateway listening on {}", addr);
+    std::thread::spawn(move || kvs::HttpGateway::new(engine).serve(listener));
+    Ok(())
+}
+
+/// serves the gRPC service upon its own thread
+#[cfg(feature = "grpc")]
+fn serve_grpc<E: KvsEngine + Sync>(engine: E, addr: &str) -> Result<()> {
+    let listener = std::net::TcpListener::bind(addr)?;
+    eprintln!("gRPC service listening on {}", addr);
+    std::thread::spawn(move || kvs::GrpcServer::new(engine).serve(listener));
+    Ok(())
+}
+
+#[cfg(feature = "tls")]
+fn server<E: KvsEngine>(engine: E, args: &clap::ArgMatches) -> Result<KvsServer<E>> {
+    let server = configure(KvsServer::new(engine), args)?;
+    match (args.value_of("tls-cert"), args.value_of("tls-key")) {
+        (Some(cert), Some(key)) => Ok(server.with_tls(kvs::ServerTlsConfig::from_pem_files(
+            path::Path::new(cert),
+            path::Path::new(key),
+        )?)),
+        _ => Ok(server),
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+fn server<E: KvsEngine>(engine: E, args: &clap::ArgMatches) -> Result<KvsServer<E>> {
+    configure(KvsServer::new(engine), args)
+}
+
+fn configure<E: KvsEngine>(
+    mut server: KvsServer<E>,
+    args: &clap::ArgMatches,
+) -> Result<KvsServer<E>> {
+    if let Some(password) = args.value_of("password") {
+        server = server.with_password(password.into());
+    }
+    if let Some(acl_path) = args.value_of("acl") {
+        server = server.with_acl(read_acl(path::Path::new(acl_path))?);
+    }
+    if let Some(config_path) = args.value_of("config") {
+        server = server.with_config_file(config_path.into());
+    }
+    if let Some(slow_log_ms) = args.value_of("slow-log-ms") {
+        server = server.with_slow_log(std::time::Duration::from_millis(parse(slow_log_ms)?));
+    }
+    if let Some(max_connections) = args.value_of("max-connections") {
+        server = server.with_max_connections(parse(max_connections)?);
+    }
+    if let Some(max_bulk_len) = args.value_of("proto-max-bulk-len") {
+        server = server.with_max_bulk_len(parse(max_bulk_len)?);
+    }
+    if let Some(requests_per_second) = args.value_of("rate-limit") {
+        let requests_per_second = parse(requests_per_second)?;
+        let burst = match args.value_of("rate-limit-burst") {
+            Some(burst) => parse(burst)?,
+            None => requests_per_second,
+        };
+        server = server.with_rate_limit(RateLimit::new(requests_per_second, burst)?);
+    }
+    if let Some(topology_path) = args.value_of("cluster-topology") {
+        let id = match args
+            .value_of("cluster-id")
+            .or_else(|| args.value_of("raft-id"))
+        {
+            Some(id) => parse(id)?,
+            None => return Err(Error::new(ErrorKind::InvalidConfiguration)),
+        };
+        server = server.with_cluster(id, read_topology(path::Path::new(topology_path))?);
+    }
+    Ok(server)
+}
+
+/// reads the topology of the cluster from its JSON file
+fn read_topology(path: &path::Path) -> Result<Topology> {
+    let json = std::fs::read_to_string(path)?;
+    serde_json::from_str(&json).map_err(|_| Error::new(ErrorKind::InvalidConfiguration))
+}
+
+/// reads the access control list from its JSON file
+fn read_acl(path: &path::Path) -> Result<Acl> {
+    let json = std::fs::read_to_string(path)?;
+    serde_json::from_str(&json).map_err(|_| Error::new(ErrorKind::InvalidConfiguration))
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| Error::new(ErrorKind::InvalidConfiguration))
+}
+
+fn arguments() -> clap::ArgMatches<'static> {
+    let app = App::new("kvs-server")
+        .author(env!("CARGO_PKG_AUTHORS"))
+        .about("Key-Value Store Server")
+        .version(env!("CARGO_PKG_VERSION"))
+        .arg(
+            Arg::with_name("addr")
+                .long("addr")
+                .value_name("IP:PORT")
+                .takes_value(true)
+                .default_value("127.0.0.1:4000")
+                .help("address to listen on for connections"),
+        )
+        .arg(
+            Arg::with_name("password")
+                .long("password")
+                .value_name("SECRET")
+                .takes_value(true)
+                .env("KVS_PASSWORD")
+                .hide_env_values(true)
+                .help("password clients must authenticate with before any other command"),
+        )
+        .arg(
+            Arg::with_name("redis-compat-report")
+                .long("redis-compat-report")
+                .help(
+                    "print the RESP commands supported and the redis-benchmark tests which \
+                        may be run against the server, then exit",
+                ),
+        )
+        .arg(
+            Arg::with_name("acl")
+                .long("acl")
+                .value_name("FILE")
+                .takes_value(true)
+                .help(
+                    "JSON file of the users clients may authenticate as with their tokens, \
+                        each allowed some commands and key prefixes, read-only or read-write",
+                ),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("FILE")
+                .takes_value(true)
+                .help(
+                    "JSON file of the log level, slow log, rate limit and store compaction \
+                        thresholds and quotas, read again on SIGHUP or `kvs-client reload-config`",
+                ),
+        )
+        .arg(
+            Arg::with_name("slow-log-ms")
+                .long("slow-log-ms")
+                .value_name("MILLISECONDS")
+                .takes_value(true)
+                .help("log each kvs-proto request taking at least this long (with its request ID)"),
+        )
+        .arg(
+            Arg::with_name("max-connections")
+                .long("max-connections")
+                .value_name("COUNT")
+                .takes_value(true)
+                .help("maximum number of connections open at once (unlimited if not given)"),
+        )
+        .arg(
+            Arg::with_name("proto-max-bulk-len")
+                .long("proto-max-bulk-len")
+                .value_name("BYTES")
+                .takes_value(true)
+                .help("longest RESP bulk string accepted (512 MiB if not given, as with Redis)"),
+        )
+        .arg(
+            Arg::with_name("rate-limit")
+                .long("rate-limit")
+                .value_name("REQUESTS_PER_SECOND")
+                .takes_value(true)
+                .help(
+                    "maximum rate of requests from each client IP address (unlimited if not given)",
+                ),
+        )
+        .arg(
+            Arg::with_name("rate-limit-burst")
+                .long("rate-limit-burst")
+                .value_name("REQUESTS")
+                .takes_value(true)
+                .requires("rate-limit")
+                .help("largest burst of requests allowed from a client (defaults to the rate)"),
+        )
+        .arg(
+            Arg::with_name("raft-id")
+                .long("raft-id")
+                .value_name("ID")
+                .takes_value(true)
+                .requires("raft-addr")
+                .help("run as the node of the given id of a Raft cluster, replicating every write"),
+        )
+        .arg(
+            Arg::with_name("raft-addr")
+                .long("raft-addr")
+                .value_name("IP:PORT")
+                .takes_value(true)
+                .requires("raft-id")
+                .help("address to listen on for the other nodes of the Raft cluster"),
+        )
+        .arg(
+            Arg::with_name("raft-peer")
+                .long("raft-peer")
+                .value_name("ID=IP:PORT")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("raft-id")
+                .help("id and Raft address of another node of the cluster (given for each)"),
+        )
+        .arg(
+            Arg::with_name("cluster-topology")
+                .long("cluster-topology")
+                .value_name("FILE")
+                .takes_value(true)
+                .help(
+                    "JSON file of the topology of the sharded cluster the server is a node of, \
+                        redirecting requests for keys of the shards of other nodes to them",
+                ),
+        )
+        .arg(
+            Arg::with_name("cluster-id")
+                .long("cluster-id")
+                .value_name("ID")
+                .takes_value(true)
+                .requires("cluster-topology")
+                .help("id of the server in the cluster topology (defaults to the Raft id)"),
+        )
+        .arg(
+            Arg::with_name("databases")
+                .long("databases")
+                .value_name("COUNT")
+                .takes_value(true)
+                .default_value("1")
+                .conflicts_with("raft-id")
+                .help(
+                    "number of databases to serve, switched between with SELECT (database 0 \
+                        being the store in the current directory, each other a keyspace of it)",
+                ),
+        )
+        .after_help(
+            "kvs-server serves the key-value store in the current directory over TCP using the \
+                Redis protocol (RESP) or kvs-proto, supporting the GET, SET, MGET, MSET, DEL, \
+                EXISTS, APPEND, STRLEN, KEYS, SELECT, AUTH, COMPACT, FLUSHALL, DBSIZE and INFO \
+                commands, and MULTI/EXEC transactions. \
+                It is implemented as part of the PingCAP Talent Plan tutorial series for Rust.",
+        );
+    #[cfg(feature = "http")]
+    let app = app.arg(
+        Arg::with_name("http-addr")
+            .long("http-addr")
+            .value_name("IP:PORT")
+            .takes_value(true)
+            .help(
+                "address to also serve the HTTP gateway on (/keys/{key}, /stats, /watch/{prefix})",
+            ),
+    );
+    #[cfg(feature = "grpc")]
+    let app = app.arg(
+        Arg::with_name("grpc-addr")
+            .long("grpc-addr")
+            .value_name("IP:PORT")
+            .takes_value(true)
+            .help("address to also serve the gRPC service (kvs.Kvs of proto/kvs.proto) on"),
+    );
+    #[cfg(feature = "tls")]
+    let app = app
+        .arg(
+            Arg::with_name("tls-cert")
+                .long("tls-cert")
+                .value_name("PATH")
+                .takes_value(true)
+                .requires("tls-key")
+                .help("PEM file of the certificate chain to present, requiring TLS connections"),
+        )
+        .arg(
+            Arg::with_name("tls-key")
+                .long("tls-key")
+                .value_name("PATH")
+                .takes_value(true)
+                .requires("tls-cert")
+                .help("PEM file of the private key for the TLS certificate"),
+        );
+    app.get_matches()
+}
@@ -1,37 +1,225 @@
-use std::path;
+use std::{
+    io::{self, BufRead},
+    mem, path, thread,
+    time::{Duration, Instant},
+};
 
-use clap::{App, Arg};
-use kvs::Result;
+use clap::{App, AppSettings, Arg};
+use kvs::{
+    DataLayout, Error, ErrorKind, KvStore, KvsClient, KvsEngine, RecordState, Result,
+    SharedKvStore, WriteBatch,
+};
+use rustyline::error::ReadlineError;
+use serde::Deserialize;
+use serde_json::json;
+
+/// number of times `backup --remote` reconnects to resume the transfer when its connection fails
+const BACKUP_RETRIES: u32 = 3;
 
 fn main() -> Result<()> {
     match arguments().subcommand() {
-        ("set", Some(args)) => handle_subcommand_set(args),
-        ("get", Some(args)) => handle_subcommand_get(args),
-        ("rm", Some(args)) => handle_subcommand_rm(args),
+        ("repl", Some(args)) => repl(&mut open(args)?, output(args)),
+        ("load", Some(args)) => handle_subcommand_load(&mut open(args)?, args),
+        ("verify", Some(args)) => handle_subcommand_verify(args),
+        ("compact", Some(args)) => handle_subcommand_compact(&mut open(args)?, args),
+        ("backup", Some(args)) => handle_subcommand_backup(args),
+        ("migrate", Some(args)) => handle_subcommand_migrate(args),
+        ("bench", Some(args)) => handle_subcommand_bench(args),
+        (command, Some(args)) => execute(&mut open(args)?, output(args), command, args),
         _ => handle_invalid_command(),
     }
 }
 
 fn arguments() -> clap::ArgMatches<'static> {
+    let store = store_arguments();
     App::new(env!("CARGO_PKG_NAME"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .about("Key-Value Store")
         .version(env!("CARGO_PKG_VERSION"))
+        .subcommands(
+            commands()
+                .into_iter()
+                .map(|command| command.args(&store).arg(output_argument())),
+        )
+        .subcommand(
+            App::new("repl")
+                .about("open the store once and read commands interactively")
+                .args(&store)
+                .arg(output_argument()),
+        )
+        .subcommand(
+            App::new("load")
+                .about("set the keys and values read from stdin, one record per line")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .value_name("FORMAT")
+                        .takes_value(true)
+                        .possible_values(&["tsv", "ndjson"])
+                        .default_value("tsv")
+                        .help(
+                            "format of the records: <key>TAB<value> (tsv) or \
+                                {\"key\": <key>, \"value\": <value>} (ndjson)",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("batch-size")
+                        .long("batch-size")
+                        .value_name("RECORDS")
+                        .takes_value(true)
+                        .default_value("1000")
+                        .help(
+                            "number of records written per batch (progress is reported after each)",
+                        ),
+                )
+                .args(&store),
+        )
+        .subcommand(
+            App::new("compact")
+                .about("compact the log now, discarding stale records")
+                .arg(
+                    Arg::with_name("target-dir")
+                        .long("target-dir")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .help("write the compacted log to a new database in PATH instead"),
+                )
+                .args(&store),
+        )
+        .subcommand(
+            App::new("backup")
+                .about("copy every key and value to a new database in <target-dir>")
+                .arg(Arg::with_name("target-dir").index(1).required(true))
+                .arg(
+                    Arg::with_name("remote")
+                        .long("remote")
+                        .value_name("IP:PORT")
+                        .takes_value(true)
+                        .help("copy a snapshot of the kvs-server at this address instead"),
+                )
+                .arg(
+                    Arg::with_name("password")
+                        .long("password")
+                        .value_name("SECRET")
+                        .takes_value(true)
+                        .env("KVS_PASSWORD")
+                        .hide_env_values(true)
+                        .requires("remote")
+                        .help("password to authenticate to the kvs-server with"),
+                )
+                .args(&store),
+        )
         .subcommand(
-            App::new("set")
-                .about("sets a <key> to the given <value>")
-                .arg(Arg::with_name("key").index(1).required(true))
-                .arg(Arg::with_name("value").index(2).required(true)),
+            App::new("migrate")
+                .about("upgrade the database in place from an earlier on-disk layout")
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .value_name("LAYOUT")
+                        .takes_value(true)
+                        .required(true)
+                        .possible_values(&["uuid-log"])
+                        .help("layout of the database: a single log named with a UUID (uuid-log)"),
+                )
+                .arg(
+                    Arg::with_name("backup-dir")
+                        .long("backup-dir")
+                        .value_name("PATH")
+                        .takes_value(true)
+                        .help(
+                            "directory to copy the database's files to first \
+                                (<db-dir>.<LAYOUT>-backup beside the database if not given)",
+                        ),
+                )
+                .args(&store),
         )
         .subcommand(
-            App::new("get")
-                .about("given a <key> gets the given <value> (if present)")
-                .arg(Arg::with_name("key").index(1).required(true)),
+            App::new("bench")
+                .about("run a load of gets and sets against the database (or a kvs-server)")
+                .arg(
+                    Arg::with_name("ops")
+                        .long("ops")
+                        .value_name("N")
+                        .takes_value(true)
+                        .default_value("100000")
+                        .validator(is_positive)
+                        .help("number of operations to run, shared between the threads"),
+                )
+                .arg(
+                    Arg::with_name("value-size")
+                        .long("value-size")
+                        .value_name("BYTES")
+                        .takes_value(true)
+                        .default_value("256")
+                        .validator(is_number)
+                        .help("length of the values set"),
+                )
+                .arg(
+                    Arg::with_name("threads")
+                        .long("threads")
+                        .value_name("N")
+                        .takes_value(true)
+                        .default_value("8")
+                        .validator(is_positive)
+                        .help("number of threads running operations at once"),
+                )
+                .arg(
+                    Arg::with_name("workload")
+                        .long("workload")
+                        .value_name("WORKLOAD")
+                        .takes_value(true)
+                        .default_value("read90")
+                        .validator(is_workload)
+                        .help(
+                            "mix of operations: read<PERCENT> for PERCENT of them to be gets \
+                                and the rest sets (read100 for gets only, read0 for sets only)",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("keys")
+                        .long("keys")
+                        .value_name("N")
+                        .takes_value(true)
+                        .default_value("10000")
+                        .validator(is_positive)
+                        .help(
+                            "number of keys, each set before the load runs, operated on at random",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("remote")
+                        .long("remote")
+                        .value_name("IP:PORT")
+                        .takes_value(true)
+                        .help("run the load against the kvs-server at this address instead"),
+                )
+                .arg(
+                    Arg::with_name("password")
+                        .long("password")
+                        .value_name("SECRET")
+                        .takes_value(true)
+                        .env("KVS_PASSWORD")
+                        .hide_env_values(true)
+                        .requires("remote")
+                        .help("password to authenticate to the kvs-server with"),
+                )
+                .args(&store)
+                .arg(output_argument()),
         )
         .subcommand(
-            App::new("rm")
-                .about("remove the given <key> (and associated value) if present")
-                .arg(Arg::with_name("key").index(1).required(true)),
+            App::new("verify")
+                .about("check every record of the log, reporting stale records and any corruption")
+                .arg(
+                    Arg::with_name("repair")
+                        .long("repair")
+                        .help("truncate the log at the first corrupt record"),
+                )
+                .arg(
+                    Arg::with_name("verbose")
+                        .long("verbose")
+                        .help("list every record of the log, live or not, before the report"),
+                )
+                .args(&store),
         )
         .after_help(
             "kvs is a command-line program to act as a key-value store. \
@@ -40,29 +228,291 @@ fn arguments() -> clap::ArgMatches<'static> {
         .get_matches()
 }
 
-fn handle_subcommand_set(args: &clap::ArgMatches) -> Result<()> {
-    let store = &mut kvs::KvStore::<String, String>::open(path::Path::new("./"))?;
+/// the commands accepted both on the command line and by the REPL
+fn commands() -> Vec<App<'static, 'static>> {
+    vec![
+        App::new("set")
+            .about("sets a <key> to the given <value>")
+            .arg(Arg::with_name("key").index(1).required(true))
+            .arg(Arg::with_name("value").index(2).required(true)),
+        App::new("get")
+            .about("given a <key> gets the given <value> (if present)")
+            .arg(Arg::with_name("key").index(1).required(true)),
+        App::new("rm")
+            .about("remove the given <key> (and associated value) if present")
+            .arg(Arg::with_name("key").index(1).required(true)),
+        App::new("scan")
+            .about("print the keys starting with <prefix> (every key if not given) in order")
+            .arg(Arg::with_name("prefix").index(1))
+            .arg(
+                Arg::with_name("limit")
+                    .long("limit")
+                    .value_name("N")
+                    .takes_value(true)
+                    .help("print at most N keys"),
+            )
+            .arg(
+                Arg::with_name("values")
+                    .long("values")
+                    .help("print the value of each key after it, separated by a tab"),
+            ),
+        App::new("stats").about(
+            "print the number of keys and (with the stats feature) the latencies of the store's \
+                operations and the sizes of the keys and values written since it was opened",
+        ),
+    ]
+}
+
+fn store_arguments() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("db-dir")
+            .long("db-dir")
+            .value_name("PATH")
+            .takes_value(true)
+            .default_value("./")
+            .help("directory of the database to use"),
+        Arg::with_name("keyspace")
+            .long("keyspace")
+            .value_name("NAME")
+            .takes_value(true)
+            .help("keyspace of the database to use (the default keyspace if not given)"),
+    ]
+}
+
+fn output_argument() -> Arg<'static, 'static> {
+    Arg::with_name("output")
+        .long("output")
+        .value_name("FORMAT")
+        .takes_value(true)
+        .possible_values(&["text", "json"])
+        .default_value("text")
+        .help("format in which to print results")
+}
+
+/// format in which results are printed
+#[derive(Clone, Copy, PartialEq)]
+enum Output {
+    Text,
+    Json,
+}
+
+fn output(args: &clap::ArgMatches) -> Output {
+    match args.value_of("output") {
+        Some("json") => Output::Json,
+        _ => Output::Text,
+    }
+}
+
+fn store_dir(args: &clap::ArgMatches) -> Result<path::PathBuf> {
+    let dir = path::Path::new(args.value_of("db-dir").unwrap());
+    match args.value_of("keyspace") {
+        Some(keyspace) => KvStore::<String, String>::keyspace_path(dir, keyspace),
+        None => Ok(dir.to_owned()),
+    }
+}
+
+fn open(args: &clap::ArgMatches) -> Result<KvStore<String, String>> {
+    let store = KvStore::open(path::Path::new(args.value_of("db-dir").unwrap()))?;
+    match args.value_of("keyspace") {
+        Some(keyspace) => store.keyspace(keyspace),
+        None => Ok(store),
+    }
+}
+
+fn execute(
+    store: &mut KvStore<String, String>,
+    output: Output,
+    command: &str,
+    args: &clap::ArgMatches,
+) -> Result<()> {
+    match command {
+        "set" => handle_subcommand_set(store, args),
+        "get" => handle_subcommand_get(store, output, args),
+        "rm" => handle_subcommand_rm(store, args),
+        "scan" => handle_subcommand_scan(store, output, args),
+        "stats" => handle_subcommand_stats(store, output),
+        _ => handle_invalid_command(),
+    }
+}
+
+/// reads commands (quoting words containing spaces with `"`) until `exit`, `quit` or end of input
+fn repl(store: &mut KvStore<String, String>, output: Output) -> Result<()> {
+    let mut editor = rustyline::DefaultEditor::new().map_err(readline_error)?;
+    let commands = App::new("")
+        .setting(AppSettings::NoBinaryName)
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::DisableVersion)
+        .subcommands(commands());
+    loop {
+        let line = match editor.readline("kvs> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => return Ok(()),
+            Err(err) => return Err(readline_error(err)),
+        };
+        let words = split_words(&line);
+        match words.first().map(String::as_str) {
+            None => continue,
+            Some("exit") | Some("quit") => return Ok(()),
+            Some(_) => {
+                let _ = editor.add_history_entry(line.as_str());
+            }
+        }
+        let matches = match commands.clone().get_matches_from_safe(words) {
+            Ok(matches) => matches,
+            Err(err) => {
+                match err.kind {
+                    clap::ErrorKind::HelpDisplayed => println!("{}", err.message),
+                    _ => eprintln!("{}", err.message),
+                }
+                continue;
+            }
+        };
+        if let (command, Some(args)) = matches.subcommand() {
+            match execute(store, output, command, args) {
+                // already reported as "Key not found"
+                Err(err) if *err.kind() == ErrorKind::KeyNotPresent => {}
+                Err(err) => eprintln!("{}", err),
+                Ok(()) => {}
+            }
+        }
+    }
+}
+
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+fn readline_error(err: ReadlineError) -> Error {
+    match err {
+        ReadlineError::Io(err) => err.into(),
+        _ => Error::new(ErrorKind::UnknownError),
+    }
+}
+
+fn handle_subcommand_set(
+    store: &mut KvStore<String, String>,
+    args: &clap::ArgMatches,
+) -> Result<()> {
     store.set(
         args.value_of("key").unwrap().into(),
         args.value_of("value").unwrap().into(),
     )
 }
 
-fn handle_subcommand_get(args: &clap::ArgMatches) -> Result<()> {
-    let store = &mut kvs::KvStore::<String, String>::open(path::Path::new("./"))?;
-    match store.get(args.value_of("key").unwrap().into()) {
-        Ok(Some(value)) => println!("{}", value),
-        Ok(None) => println!("Key not found"),
-        Err(err) => return Err(err),
+fn handle_subcommand_get(
+    store: &mut KvStore<String, String>,
+    output: Output,
+    args: &clap::ArgMatches,
+) -> Result<()> {
+    let key = args.value_of("key").unwrap();
+    match (store.get(key.into())?, output) {
+        (value, Output::Json) => println!("{}", json!({ "key": key, "value": value })),
+        (Some(value), Output::Text) => println!("{}", value),
+        (None, Output::Text) => println!("Key not found"),
     }
     Ok(())
 }
 
-fn handle_subcommand_rm(args: &clap::ArgMatches) -> Result<()> {
-    let store = &mut kvs::KvStore::<String, String>::open(path::Path::new("./"))?;
+fn handle_subcommand_stats(store: &mut KvStore<String, String>, output: Output) -> Result<()> {
+    let keys = store.len();
+    #[cfg(feature = "stats")]
+    let stats = store.stats();
+    match output {
+        #[cfg(feature = "stats")]
+        Output::Json => println!(
+            "{}",
+            json!({
+                "keys": keys,
+                "latencies": {
+                    "set": stats_latency_json(&stats.set),
+                    "get": stats_latency_json(&stats.get),
+                    "remove": stats_latency_json(&stats.remove),
+                    "compaction": stats_latency_json(&stats.compaction),
+                },
+                "sizes": {
+                    "keys": size_json(&stats.key_sizes),
+                    "values": size_json(&stats.value_sizes),
+                },
+            })
+        ),
+        #[cfg(not(feature = "stats"))]
+        Output::Json => println!("{}", json!({ "keys": keys })),
+        Output::Text => {
+            println!("keys: {}", keys);
+            #[cfg(feature = "stats")]
+            {
+                let latencies = [
+                    ("set", &stats.set),
+                    ("get", &stats.get),
+                    ("remove", &stats.remove),
+                    ("compaction", &stats.compaction),
+                ];
+                for (name, latency) in latencies {
+                    println!(
+                        "{}: {} ops, p50 {:?}, p99 {:?}, p99.9 {:?}, max {:?}",
+                        name, latency.count, latency.p50, latency.p99, latency.p999, latency.max
+                    );
+                }
+                for (name, sizes) in [("key", &stats.key_sizes), ("value", &stats.value_sizes)] {
+                    println!(
+                        "{} sizes: {} written, p50 {}B, p90 {}B, p99 {}B, max {}B",
+                        name, sizes.count, sizes.p50, sizes.p90, sizes.p99, sizes.max
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// latencies of an operation of the store as JSON, in nanoseconds (as `GET /stats` reports them)
+#[cfg(feature = "stats")]
+fn stats_latency_json(latency: &kvs::LatencyStats) -> serde_json::Value {
+    json!({
+        "count": latency.count,
+        "min_ns": latency.min.as_nanos() as u64,
+        "mean_ns": latency.mean.as_nanos() as u64,
+        "p50_ns": latency.p50.as_nanos() as u64,
+        "p99_ns": latency.p99.as_nanos() as u64,
+        "p999_ns": latency.p999.as_nanos() as u64,
+        "max_ns": latency.max.as_nanos() as u64,
+    })
+}
+
+/// sizes of the keys or values written as JSON, in bytes
+#[cfg(feature = "stats")]
+fn size_json(sizes: &kvs::SizeStats) -> serde_json::Value {
+    json!({
+        "count": sizes.count,
+        "p50": sizes.p50,
+        "p90": sizes.p90,
+        "p99": sizes.p99,
+        "max": sizes.max,
+    })
+}
+
+fn handle_subcommand_rm(
+    store: &mut KvStore<String, String>,
+    args: &clap::ArgMatches,
+) -> Result<()> {
     match store.remove(args.value_of("key").unwrap().into()) {
         Ok(_) => Ok(()),
-        Err(err) if *err.kind() == kvs::ErrorKind::KeyNotPresent => {
+        Err(err) if *err.kind() == ErrorKind::KeyNotPresent => {
             println!("Key not found");
             Err(err)
         }
@@ -70,6 +520,422 @@ fn handle_subcommand_rm(args: &clap::ArgMatches) -> Result<()> {
     }
 }
 
+fn handle_subcommand_scan(
+    store: &mut KvStore<String, String>,
+    output: Output,
+    args: &clap::ArgMatches,
+) -> Result<()> {
+    let limit = match args.value_of("limit") {
+        Some(limit) => limit
+            .parse()
+            .map_err(|_| Error::new(ErrorKind::InvalidConfiguration))?,
+        None => usize::MAX,
+    };
+    let prefix = args.value_of("prefix").unwrap_or("");
+    let mut keys = store
+        .keys()
+        .filter(|key| key.starts_with(prefix))
+        .cloned()
+        .collect::<Vec<_>>();
+    keys.sort();
+    let mut found = Vec::new();
+    for key in keys.into_iter().take(limit) {
+        match (args.is_present("values"), output) {
+            (true, Output::Json) => {
+                let value = store.get(key.clone())?;
+                found.push(json!({ "key": key, "value": value }));
+            }
+            (false, Output::Json) => found.push(json!(key)),
+            (true, Output::Text) => {
+                let value = store.get(key.clone())?.unwrap_or_default();
+                println!("{}\t{}", key, value);
+            }
+            (false, Output::Text) => println!("{}", key),
+        }
+    }
+    if output == Output::Json {
+        println!("{}", serde_json::Value::Array(found));
+    }
+    Ok(())
+}
+
+/// record of `kvs load --format ndjson`
+#[derive(Deserialize)]
+struct LoadRecord {
+    key: String,
+    value: String,
+}
+
+fn handle_subcommand_load(
+    store: &mut KvStore<String, String>,
+    args: &clap::ArgMatches,
+) -> Result<()> {
+    let batch_size = match args.value_of("batch-size").unwrap().parse() {
+        Ok(batch_size) if batch_size > 0 => batch_size,
+        _ => return Err(Error::new(ErrorKind::InvalidConfiguration)),
+    };
+    let ndjson = args.value_of("format") == Some("ndjson");
+    let mut batch = WriteBatch::new();
+    let mut loaded = 0;
+    for (number, line) in io::stdin().lock().lines().enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let record = match ndjson {
+            true => serde_json::from_str(&line)
+                .ok()
+                .map(|record: LoadRecord| (record.key, record.value)),
+            false => line
+                .split_once('\t')
+                .map(|(key, value)| (key.to_owned(), value.to_owned())),
+        };
+        match record {
+            Some((key, value)) => batch.set(key, value),
+            None => {
+                loaded += store.write_batch(batch)?.len();
+                eprintln!("Invalid record on line {} ({} loaded)", number + 1, loaded);
+                std::process::exit(1)
+            }
+        };
+        if batch.len() >= batch_size {
+            loaded += store.write_batch(mem::take(&mut batch))?.len();
+            eprintln!("{} loaded", loaded);
+        }
+    }
+    if !batch.is_empty() {
+        loaded += store.write_batch(batch)?.len();
+        eprintln!("{} loaded", loaded);
+    }
+    Ok(())
+}
+
+fn handle_subcommand_compact(
+    store: &mut KvStore<String, String>,
+    args: &clap::ArgMatches,
+) -> Result<()> {
+    match args.value_of("target-dir") {
+        Some(target_dir) => store.compact_into(path::Path::new(target_dir)),
+        None => store.compact_now(),
+    }
+}
+
+fn handle_subcommand_backup(args: &clap::ArgMatches) -> Result<()> {
+    let target_dir = path::Path::new(args.value_of("target-dir").unwrap());
+    match args.value_of("remote") {
+        Some(addr) => {
+            let mut client = KvsClient::connect(addr)?.with_retries(BACKUP_RETRIES);
+            if let Some(password) = args.value_of("password") {
+                client.authenticate(password.into())?;
+            }
+            client.backup(target_dir).map(|_| ())
+        }
+        None => open(args)?.compact_into(target_dir),
+    }
+}
+
+fn handle_subcommand_migrate(args: &clap::ArgMatches) -> Result<()> {
+    let from: DataLayout = args.value_of("from").unwrap().parse()?;
+    let dir = store_dir(args)?;
+    let backup_dir = match args.value_of("backup-dir") {
+        Some(backup_dir) => path::PathBuf::from(backup_dir),
+        None => {
+            let dir = dir.canonicalize()?;
+            let name = dir.file_name().unwrap_or_default().to_string_lossy();
+            dir.with_file_name(format!("{}.{}-backup", name, from))
+        }
+    };
+    KvStore::<String, String>::upgrade_layout(&dir, from, &backup_dir)?;
+    println!(
+        "upgraded {} from the {} layout (backup in {})",
+        dir.display(),
+        from,
+        backup_dir.display()
+    );
+    Ok(())
+}
+
+/// operation run by `kvs bench`
+#[derive(Clone, Copy, PartialEq)]
+enum BenchOp {
+    Get,
+    Set,
+}
+
+/// what `kvs bench` runs its load against
+enum BenchTarget {
+    Local(SharedKvStore),
+    Remote {
+        addr: String,
+        password: Option<String>,
+    },
+}
+
+/// connection of a thread of `kvs bench` to its target
+enum BenchConnection {
+    Local(SharedKvStore),
+    Remote(KvsClient),
+}
+
+impl BenchTarget {
+    fn connect(&self) -> Result<BenchConnection> {
+        match self {
+            Self::Local(engine) => Ok(BenchConnection::Local(engine.clone())),
+            Self::Remote { addr, password } => {
+                let mut client = KvsClient::connect(addr.as_str())?;
+                if let Some(password) = password {
+                    client.authenticate(password.clone())?;
+                }
+                Ok(BenchConnection::Remote(client))
+            }
+        }
+    }
+}
+
+impl BenchConnection {
+    fn run(&mut self, op: BenchOp, key: String, value: &str) -> Result<()> {
+        match (self, op) {
+            (Self::Local(engine), BenchOp::Get) => engine.get(key).map(|_| ()),
+            (Self::Local(engine), BenchOp::Set) => engine.set(key, value.to_owned()),
+            (Self::Remote(client), BenchOp::Get) => client.get(key).map(|_| ()),
+            (Self::Remote(client), BenchOp::Set) => client.set(key, value.to_owned()),
+        }
+    }
+}
+
+/// latencies, in nanoseconds, of the gets and sets run by a thread of `kvs bench`
+#[derive(Default)]
+struct BenchLatencies {
+    get: Vec<u64>,
+    set: Vec<u64>,
+}
+
+fn handle_subcommand_bench(args: &clap::ArgMatches) -> Result<()> {
+    // each argument has been checked by its validator
+    let number = |name| args.value_of(name).unwrap().parse::<u64>().unwrap();
+    let (ops, threads, keys) = (number("ops"), number("threads"), number("keys"));
+    let value_size = number("value-size") as usize;
+    let workload = args.value_of("workload").unwrap();
+    let read_percent = read_percent(workload).unwrap();
+    let target = match args.value_of("remote") {
+        Some(addr) => BenchTarget::Remote {
+            addr: addr.to_owned(),
+            password: args.value_of("password").map(str::to_owned),
+        },
+        None => BenchTarget::Local(SharedKvStore::new(open(args)?)),
+    };
+    let value = "v".repeat(value_size);
+    let mut connection = target.connect()?;
+    for key in 0..keys {
+        connection.run(BenchOp::Set, bench_key(key), &value)?;
+    }
+    drop(connection);
+
+    let started = Instant::now();
+    let runs = (0..threads)
+        .map(|thread| {
+            // the operations left over by the division are run by the first threads
+            let thread_ops = ops / threads + u64::from(thread < ops % threads);
+            let mut connection = target.connect()?;
+            let value = value.clone();
+            Ok(thread::spawn(move || -> Result<BenchLatencies> {
+                let mut random = thread + 1;
+                let mut latencies = BenchLatencies::default();
+                for _ in 0..thread_ops {
+                    let key = bench_key(next_random(&mut random) % keys);
+                    let op = match next_random(&mut random) % 100 < read_percent {
+                        true => BenchOp::Get,
+                        false => BenchOp::Set,
+                    };
+                    let start = Instant::now();
+                    connection.run(op, key, &value)?;
+                    let elapsed = start.elapsed().as_nanos() as u64;
+                    match op {
+                        BenchOp::Get => latencies.get.push(elapsed),
+                        BenchOp::Set => latencies.set.push(elapsed),
+                    }
+                }
+                Ok(latencies)
+            }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut latencies = BenchLatencies::default();
+    for run in runs {
+        let run = run
+            .join()
+            .map_err(|_| Error::new(ErrorKind::UnknownError))??;
+        latencies.get.extend(run.get);
+        latencies.set.extend(run.set);
+    }
+    let elapsed = started.elapsed();
+    latencies.get.sort_unstable();
+    latencies.set.sort_unstable();
+
+    let ops_per_sec = ops as f64 / elapsed.as_secs_f64();
+    match output(args) {
+        Output::Json => println!(
+            "{}",
+            json!({
+                "workload": workload,
+                "threads": threads,
+                "ops": ops,
+                "seconds": elapsed.as_secs_f64(),
+                "ops_per_sec": ops_per_sec,
+                "get": latency_json(&latencies.get),
+                "set": latency_json(&latencies.set),
+            })
+        ),
+        Output::Text => {
+            println!(
+                "{} operations ({}) on {} threads in {:.2}s: {:.0} ops/s",
+                ops,
+                workload,
+                threads,
+                elapsed.as_secs_f64(),
+                ops_per_sec
+            );
+            for (name, latencies) in [("get", &latencies.get), ("set", &latencies.set)] {
+                println!(
+                    "{}: {} ops, p50 {:?}, p99 {:?}, p99.9 {:?}, max {:?}",
+                    name,
+                    latencies.len(),
+                    percentile(latencies, 0.5),
+                    percentile(latencies, 0.99),
+                    percentile(latencies, 0.999),
+                    percentile(latencies, 1.0)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// the percentage of the operations of the workload (`read<PERCENT>`) which are gets, if valid
+fn read_percent(workload: &str) -> Option<u64> {
+    match workload.strip_prefix("read")?.parse() {
+        Ok(percent) if percent <= 100 => Some(percent),
+        _ => None,
+    }
+}
+
+/// validates an argument which must be a number
+fn is_number(value: String) -> std::result::Result<(), String> {
+    match value.parse::<u64>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(format!("expected a number, not '{}'", value)),
+    }
+}
+
+/// validates an argument which must be a number greater than 0
+fn is_positive(value: String) -> std::result::Result<(), String> {
+    match value.parse::<u64>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("expected a number greater than 0, not '{}'", value)),
+    }
+}
+
+/// validates the workload of `kvs bench`
+fn is_workload(value: String) -> std::result::Result<(), String> {
+    match read_percent(&value) {
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "expected read<PERCENT> with PERCENT from 0 to 100 (such as read90), not '{}'",
+            value
+        )),
+    }
+}
+
+fn bench_key(key: u64) -> String {
+    format!("bench:{:010}", key)
+}
+
+/// the next number of the xorshift sequence of the state (which must not be 0)
+fn next_random(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+/// the latency below which the fraction of the sorted latencies fall, zero if there are none
+fn percentile(sorted: &[u64], quantile: f64) -> Duration {
+    let rank = (sorted.len() as f64 * quantile).ceil() as usize;
+    let nanos = match sorted.len() {
+        0 => 0,
+        len => sorted[rank.clamp(1, len) - 1],
+    };
+    Duration::from_nanos(nanos)
+}
+
+fn latency_json(sorted: &[u64]) -> serde_json::Value {
+    let micros = |quantile| percentile(sorted, quantile).as_secs_f64() * 1e6;
+    json!({
+        "count": sorted.len(),
+        "p50_us": micros(0.5),
+        "p99_us": micros(0.99),
+        "p999_us": micros(0.999),
+        "max_us": micros(1.0),
+    })
+}
+
+fn handle_subcommand_verify(args: &clap::ArgMatches) -> Result<()> {
+    if args.is_present("verbose") {
+        list_all_records(&mut open(args)?)?;
+    }
+    let report = KvStore::<String, String>::verify(&store_dir(args)?, args.is_present("repair"))?;
+    match &report.log_path {
+        Some(log_path) => println!("log: {}", log_path.display()),
+        None => println!("log: none"),
+    }
+    println!("records: {}", report.records);
+    println!("live records: {}", report.live_records);
+    println!(
+        "stale records: {} ({:.1}%)",
+        report.stale_records(),
+        report.stale_fraction() * 100.0
+    );
+    println!("closed logs checked: {}", report.digests_checked);
+    for log_path in &report.digest_mismatches {
+        println!("digest mismatch: {}", log_path.display());
+    }
+    match (report.corruption_offset, report.repaired) {
+        (None, _) => println!("corruption: none"),
+        (Some(offset), true) => println!("corruption: at offset {} (truncated)", offset),
+        (Some(offset), false) => {
+            println!("corruption: at offset {}", offset);
+            std::process::exit(1)
+        }
+    }
+    if !report.digest_mismatches.is_empty() {
+        std::process::exit(1)
+    }
+    Ok(())
+}
+
+/// prints each record of the log with its offset and state, up to the first corrupt one (which
+/// the report then gives the offset of)
+fn list_all_records(store: &mut KvStore<String, String>) -> Result<()> {
+    for rec in store.debug_iter_all_records()? {
+        let rec = match rec {
+            Ok(rec) => rec,
+            Err(_) => break,
+        };
+        let state = match rec.state {
+            RecordState::Live => "live",
+            RecordState::Superseded => "superseded",
+            RecordState::Tombstone => "tombstone",
+        };
+        match (rec.value, rec.merge) {
+            (Some(value), false) => println!("{} {} {} = {}", rec.offset, state, rec.key, value),
+            (Some(operand), true) => {
+                println!("{} {} {} += {}", rec.offset, state, rec.key, operand)
+            }
+            (None, _) => println!("{} {} {}", rec.offset, state, rec.key),
+        }
+    }
+    Ok(())
+}
+
 fn handle_invalid_command() -> Result<()> {
     eprintln!("Invalid Options or Command");
     std::process::exit(1)
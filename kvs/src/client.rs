@@ -0,0 +1,778 @@
+#[cfg(feature = "async")]
+mod async_client;
+#[cfg(feature = "async")]
+pub use async_client::AsyncKvsClient;
+mod cluster;
+pub use cluster::{ClusterClient, ClusterOptions};
+mod pool;
+pub use pool::{KvsClientPool, PoolOptions};
+
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    io, net,
+    sync::atomic::{AtomicU64, Ordering},
+    thread, time,
+};
+
+#[cfg(feature = "tls")]
+use super::ClientTlsConfig;
+use super::{
+    protocol, resp,
+    transfer::{Digest, DEFAULT_CHUNK_SIZE},
+    Error, ErrorKind, KeyEvent, KvStore, Lock, MigrationStatus, Request, Response, Result,
+    SnapshotChunk, Topology, WriteBatch,
+};
+
+/// how long a KvsClient waits before its first retry of a request (doubled after each retry)
+const INITIAL_RETRY_BACKOFF: time::Duration = time::Duration::from_millis(10);
+
+/// number of pairs KvsClient::scan requests in each page
+const DEFAULT_SCAN_COUNT: u64 = 1000;
+
+/// number of idempotency tokens created by this process, mixed into each so that none repeat
+static TOKENS_CREATED: AtomicU64 = AtomicU64::new(0);
+
+/// Client for a Key-Value Storage server, speaking the kvs-proto protocol
+///
+/// A client created `with_retries` retries the requests which are safe to repeat (`get`,
+/// `get_with_max_lag`, `set`, `key_count` and `compact`) when its connection fails, reconnecting
+/// (and authenticating again) before each retry. Each `set` then carries an idempotency token, so
+/// that a retry of a write which the server applied before the connection failed is not applied
+/// again.
+///
+/// A request which waits on the server for longer than the client's timeout (given `with_timeout`
+/// or `connect_timeout`), or past the deadline set with `set_deadline`, fails with Timeout rather
+/// than blocking on a dead server, and the client reconnects before its next request (so a late
+/// response is not taken for the response to it). Retries are not made once the deadline has
+/// passed, so a deadline bounds a request however many times it is retried.
+pub struct KvsClient {
+    stream: io::BufReader<Box<dyn Stream>>,
+    reconnect: Connect,
+    retries: u32,
+    password: Option<String>,
+    database: u64,
+    request_id: Option<String>,
+    timeout: Option<time::Duration>,
+    deadline: Option<time::Instant>,
+    /// timeout of the connection's socket, None if not known (as for a new connection)
+    socket_timeout: Option<Option<time::Duration>>,
+    /// true if a request timed out, so the connection may yet receive its response
+    broken: bool,
+}
+
+/// Events of the keys a KvsClient subscribed to, from `KvsClient::subscribe`, which waits for
+/// each event and ends if the server closes the connection
+pub struct Subscription {
+    stream: io::BufReader<Box<dyn Stream>>,
+}
+
+impl Iterator for Subscription {
+    type Item = Result<KeyEvent<String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match protocol::read_message(&mut self.stream) {
+            Ok(Some(Response::Event(event))) => Some(Ok(event)),
+            Ok(Some(response)) => Some(Err(unexpected(response))),
+            Ok(None) => None,
+            Err(err) => Some(Err(err)),
+        }
+    }
+}
+
+/// Keys starting with a prefix and their values, from `KvsClient::scan`, in the order of the keys
+///
+/// The pairs are requested from the server a page at a time as they are iterated, so a scan of
+/// many keys is not held in a single response. Each key present throughout the scan is returned
+/// once, however the keys are written (or the storage compacted) in between, and a key set or
+/// removed during the scan at most once.
+pub struct Scan<'c> {
+    client: &'c mut KvsClient,
+    prefix: String,
+    count: u64,
+    /// cursor of the next page, None once the last page has been received
+    cursor: Option<u64>,
+    page: std::vec::IntoIter<(String, String)>,
+}
+
+impl Iterator for Scan<'_> {
+    type Item = Result<(String, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(pair) = self.page.next() {
+                return Some(Ok(pair));
+            }
+            let cursor = self.cursor?;
+            match self
+                .client
+                .scan_page(cursor, self.prefix.clone(), self.count)
+            {
+                Ok((next, pairs)) => {
+                    self.cursor = Some(next).filter(|&next| next != 0);
+                    self.page = pairs.into_iter();
+                }
+                Err(err) => {
+                    self.cursor = None;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+/// transport a KvsClient speaks over (a plain TCP stream or, with the `tls` feature, a TLS stream)
+trait Stream: io::Read + io::Write + Send {
+    /// fail each read and write which waits for longer than the timeout (None for no limit)
+    fn set_timeout(&self, timeout: Option<time::Duration>) -> io::Result<()>;
+}
+
+impl Stream for net::TcpStream {
+    fn set_timeout(&self, timeout: Option<time::Duration>) -> io::Result<()> {
+        self.set_read_timeout(timeout)?;
+        self.set_write_timeout(timeout)
+    }
+}
+
+#[cfg(feature = "tls")]
+impl Stream for rustls::StreamOwned<rustls::ClientConnection, net::TcpStream> {
+    fn set_timeout(&self, timeout: Option<time::Duration>) -> io::Result<()> {
+        self.sock.set_timeout(timeout)
+    }
+}
+
+/// opens a new connection to the server a KvsClient connected to
+type Connect = Box<dyn Fn() -> Result<Box<dyn Stream>> + Send>;
+
+impl KvsClient {
+    /// connect to the server at the given address
+    pub fn connect<A: net::ToSocketAddrs>(addr: A) -> Result<Self> {
+        let addrs = resolve(addr)?;
+        Self::connect_with(Box::new(move || {
+            Ok(Box::new(net::TcpStream::connect(addrs.as_slice())?))
+        }))
+    }
+    /// connect to the server at the given address, failing any connection attempt, read or write
+    /// which takes longer than the timeout
+    pub fn connect_timeout<A: net::ToSocketAddrs>(
+        addr: A,
+        timeout: time::Duration,
+    ) -> Result<Self> {
+        let addrs = resolve(addr)?;
+        let client = Self::connect_with(Box::new(move || {
+            Ok(Box::new(connect_stream_timeout(addrs.as_slice(), timeout)?))
+        }))?;
+        Ok(client.with_timeout(timeout))
+    }
+    /// connect to the server at the given address using TLS
+    #[cfg(feature = "tls")]
+    pub fn connect_tls<A: net::ToSocketAddrs>(addr: A, tls: &ClientTlsConfig) -> Result<Self> {
+        let (addrs, tls) = (resolve(addr)?, tls.clone());
+        Self::connect_with(Box::new(move || {
+            Ok(Box::new(
+                tls.connect(net::TcpStream::connect(addrs.as_slice())?)?,
+            ))
+        }))
+    }
+    /// connect to the server at the given address using TLS, failing any connection attempt,
+    /// handshake, read or write which takes longer than the timeout
+    #[cfg(feature = "tls")]
+    pub fn connect_tls_timeout<A: net::ToSocketAddrs>(
+        addr: A,
+        tls: &ClientTlsConfig,
+        timeout: time::Duration,
+    ) -> Result<Self> {
+        let (addrs, tls) = (resolve(addr)?, tls.clone());
+        let client = Self::connect_with(Box::new(move || {
+            let stream = connect_stream_timeout(addrs.as_slice(), timeout)?;
+            Ok(Box::new(tls.connect(stream)?))
+        }))?;
+        Ok(client.with_timeout(timeout))
+    }
+    /// retry the requests which are safe to repeat up to the given number of times when the
+    /// connection fails, reconnecting before each retry
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+    /// fail each request which waits on the server for longer than the timeout with Timeout
+    pub fn with_timeout(mut self, timeout: time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    /// fail the later requests of the client (retries included) with Timeout once the deadline
+    /// has passed, until it is cleared with None
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kvs::KvsClient;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let mut client = KvsClient::connect("127.0.0.1:4000").unwrap().with_retries(3);
+    /// client.set_deadline(Some(Instant::now() + Duration::from_millis(250)));
+    /// let value = client.get("key1".into());
+    /// client.set_deadline(None);
+    /// ```
+    pub fn set_deadline(&mut self, deadline: Option<time::Instant>) {
+        self.deadline = deadline;
+    }
+    /// tag the later requests of the client with the ID (such as the correlation ID of the work
+    /// they are part of), which the server reports them by in its logs and tracing spans and
+    /// which ends the message of each ServerError in response to them, until it is cleared with
+    /// None
+    pub fn set_request_id(&mut self, id: Option<String>) {
+        self.request_id = id;
+    }
+    /// a new idempotency token for `set_once`, which no other token created (by any client) will
+    /// equal but by chance
+    pub fn new_idempotency_token() -> u64 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(TOKENS_CREATED.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u32(std::process::id());
+        hasher.finish()
+    }
+    /// authenticate the connection to a server which requires a password (authenticating again
+    /// with it whenever the client reconnects)
+    pub fn authenticate(&mut self, password: String) -> Result<()> {
+        let request = Request::Auth {
+            password: password.clone(),
+        };
+        match self.request(&request)? {
+            Response::Ok => {
+                self.password = Some(password);
+                Ok(())
+            }
+            response => Err(unexpected(response)),
+        }
+    }
+    /// make the later requests of the connection (and of any the client reconnects with) to the
+    /// server's database of the given number, ServerError if it has no such database
+    pub fn select(&mut self, db: u64) -> Result<()> {
+        match self.request(&Request::Select { db })? {
+            Response::Ok => {
+                self.database = db;
+                Ok(())
+            }
+            response => Err(unexpected(response)),
+        }
+    }
+    /// get the value stored under the given key or None if no such key
+    pub fn get(&mut self, key: String) -> Result<Option<String>> {
+        match self.retried_request(&Request::Get { key })? {
+            Response::Value(value) => Ok(value),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// get the value stored under the given key or None if no such key, allowing the value to be
+    /// as it was up to `max_lag` ago (so that a follower of a replicated server may answer)
+    pub fn get_with_max_lag(
+        &mut self,
+        key: String,
+        max_lag: time::Duration,
+    ) -> Result<Option<String>> {
+        let max_lag_ms = max_lag.as_millis() as u64;
+        match self.retried_request(&Request::GetWithMaxLag { key, max_lag_ms })? {
+            Response::Value(value) => Ok(value),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// set a key to a value, overwriting any value already stored under the key
+    pub fn set(&mut self, key: String, value: String) -> Result<()> {
+        if self.retries > 0 {
+            return self
+                .set_once(key, value, Self::new_idempotency_token())
+                .map(|_| ());
+        }
+        match self.request(&Request::Set { key, value })? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// set a key to a value unless a write with the same idempotency token (from
+    /// `new_idempotency_token`) has already been applied, returning whether it was applied now
+    ///
+    /// The server remembers the tokens of many recent writes, so a write which may or may not
+    /// have been applied (the connection having failed before the response was received) can be
+    /// repeated with the same token, even over another connection.
+    pub fn set_once(&mut self, key: String, value: String, token: u64) -> Result<bool> {
+        match self.retried_request(&Request::SetOnce { key, value, token })? {
+            Response::Applied(applied) => Ok(applied),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// set a key to a value only if the key is not present, returning whether it was set
+    pub fn set_if_absent(&mut self, key: String, value: String) -> Result<bool> {
+        match self.request(&Request::SetIfAbsent { key, value })? {
+            Response::Applied(applied) => Ok(applied),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// get the values stored under the given keys in a single request, in the order of the keys
+    /// (None for each key not present)
+    pub fn get_many(&mut self, keys: Vec<String>) -> Result<Vec<Option<String>>> {
+        match self.retried_request(&Request::GetMany { keys })? {
+            Response::Values(values) => Ok(values),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// set each key to its value in a single request, the server writing them all together as a
+    /// batch
+    pub fn set_many(&mut self, pairs: Vec<(String, String)>) -> Result<()> {
+        match self.retried_request(&Request::SetMany { pairs })? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// append the suffix to the value stored under the given key (setting the key to the suffix if
+    /// no such key), returning the length of the value in bytes once appended to
+    pub fn append(&mut self, key: String, suffix: String) -> Result<u64> {
+        match self.request(&Request::Append { key, suffix })? {
+            Response::Count(len) => Ok(len),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// length in bytes of the value stored under the given key, 0 if no such key
+    pub fn value_len(&mut self, key: String) -> Result<u64> {
+        match self.retried_request(&Request::ValueLen { key })? {
+            Response::Count(len) => Ok(len),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// remove the value stored under the given key or KeyNotPresent if the key does not exist
+    pub fn remove(&mut self, key: String) -> Result<()> {
+        match self.request(&Request::Remove { key })? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// compact the server's storage now
+    pub fn compact(&mut self) -> Result<()> {
+        match self.retried_request(&Request::Compact)? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// remove every key from the server's storage
+    pub fn clear(&mut self) -> Result<()> {
+        match self.request(&Request::Clear)? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// make the server read its config file again, applying its settings (a ServerError if the
+    /// server has no config file or it is not valid)
+    pub fn reload_config(&mut self) -> Result<()> {
+        match self.retried_request(&Request::ReloadConfig)? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// number of keys present in the server's storage
+    pub fn key_count(&mut self) -> Result<u64> {
+        match self.retried_request(&Request::KeyCount)? {
+            Response::Count(count) => Ok(count),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// report of Redis's INFO on the server: a `# <Section>` line heading the `<field>:<value>`
+    /// lines of each section, the number of keys (Keyspace) and, with the `stats` feature, the
+    /// percentiles of the sizes of the keys and values written (Sizes)
+    pub fn info(&mut self) -> Result<String> {
+        match self.retried_request(&Request::Info)? {
+            Response::Info(report) => Ok(report),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// acquire the expiring lock of the given name for the ttl, None if it is held
+    ///
+    /// The lock's expiry is reckoned from when the request was sent, so it expires on the
+    /// server no earlier.
+    pub fn acquire_lock(&mut self, name: String, ttl: time::Duration) -> Result<Option<Lock>> {
+        let sent = time::SystemTime::now();
+        let request = Request::AcquireLock {
+            name: name.clone(),
+            ttl_ms: ttl.as_millis() as u64,
+        };
+        self.lock_request(&request, name, sent + ttl)
+    }
+    /// extend the lock to expire after the ttl, None if it is no longer held
+    pub fn refresh_lock(&mut self, lock: &Lock, ttl: time::Duration) -> Result<Option<Lock>> {
+        let sent = time::SystemTime::now();
+        let request = Request::RefreshLock {
+            name: lock.name.clone(),
+            token: lock.token,
+            ttl_ms: ttl.as_millis() as u64,
+        };
+        self.lock_request(&request, lock.name.clone(), sent + ttl)
+    }
+    /// release the lock, returning whether it was released (false if another holder has taken
+    /// it since it expired)
+    pub fn release_lock(&mut self, lock: &Lock) -> Result<bool> {
+        let request = Request::ReleaseLock {
+            name: lock.name.clone(),
+            token: lock.token,
+        };
+        match self.request(&request)? {
+            Response::Applied(released) => Ok(released),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// topology of the cluster the server is a node of
+    pub fn topology(&mut self) -> Result<Topology> {
+        match self.retried_request(&Request::Topology)? {
+            Response::Topology(topology) => Ok(topology),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// replace the topology of the server's cluster, unless the topology given is older
+    pub fn set_topology(&mut self, topology: Topology) -> Result<()> {
+        match self.retried_request(&Request::SetTopology { topology })? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// start migrating the keys of the range (from `start` up to but excluding `end`, or to the
+    /// end of the keys if None) from the server to the node at the target address, the keys
+    /// being copied upon a thread of the server while it goes on serving requests
+    pub fn migrate_range(
+        &mut self,
+        start: String,
+        end: Option<String>,
+        target: String,
+    ) -> Result<()> {
+        match self.request(&Request::MigrateRange { start, end, target })? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// progress of the server's last migration, None if it has made none
+    pub fn migration_status(&mut self) -> Result<Option<MigrationStatus>> {
+        match self.retried_request(&Request::MigrationStatus)? {
+            Response::Migration(status) => Ok(status),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// cut the server's migration over to its target once it is streaming, the topology (which
+    /// should give the range to the target) replacing those of the server and the target
+    pub fn finish_migration(&mut self, topology: Topology) -> Result<()> {
+        match self.request(&Request::FinishMigration { topology })? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// set (or remove) a key copied from another node by a migration
+    pub(crate) fn import(&mut self, key: String, value: Option<String>) -> Result<()> {
+        match self.retried_request(&Request::Import { key, value })? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// get the chunk at the offset of the server's snapshot of the id, holding the pairs which fit
+    /// in `max_bytes` bytes of keys and values, or the first chunk of a new snapshot if no id is
+    /// given or the server no longer holds the snapshot
+    pub fn snapshot_chunk(
+        &mut self,
+        snapshot_id: Option<u64>,
+        offset: u64,
+        max_bytes: u64,
+    ) -> Result<SnapshotChunk> {
+        let request = Request::Snapshot {
+            snapshot_id,
+            offset,
+            max_bytes,
+        };
+        match self.retried_request(&request)? {
+            Response::SnapshotChunk(chunk) => Ok(chunk),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// copy every key and value of the server, as of a snapshot it takes, to a new store in the
+    /// directory at `path`, returning the number of keys copied (InvalidConfiguration if that
+    /// directory already holds keys)
+    ///
+    /// The snapshot is received in chunks, each written to the store as it arrives. A chunk which
+    /// fails its checksum is requested again (as many times as the client retries requests), and
+    /// a client created `with_retries` resumes from the chunk it was receiving when its connection
+    /// fails. If the server no longer holds the snapshot, the store is cleared and a new snapshot
+    /// received from the start, as the snapshot is if the pairs received, once they all are, do
+    /// not match its digest.
+    pub fn backup(&mut self, path: &std::path::Path) -> Result<u64> {
+        let mut store = KvStore::<String, String>::open(path)?;
+        if !store.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidConfiguration));
+        }
+        let (mut snapshot_id, mut offset, mut corrupt) = (None, 0, 0);
+        let mut received = Digest::new();
+        loop {
+            let chunk = self.snapshot_chunk(snapshot_id, offset, DEFAULT_CHUNK_SIZE as u64)?;
+            if !chunk.is_intact() {
+                corrupt += 1;
+                if corrupt > self.retries {
+                    return Err(Error::with_message(
+                        ErrorKind::ProtocolError,
+                        "a chunk of the snapshot failed its checksum".into(),
+                    ));
+                }
+                continue;
+            }
+            if snapshot_id.is_some_and(|id| id != chunk.snapshot_id) {
+                store.clear()?;
+                received = Digest::new();
+            }
+            snapshot_id = Some(chunk.snapshot_id);
+            offset = chunk.offset + chunk.pairs.len() as u64;
+            let (last, total, digest) = (chunk.is_last(), chunk.total, chunk.digest);
+            received.update(&chunk.pairs);
+            let mut batch = WriteBatch::new();
+            for (key, value) in chunk.pairs {
+                batch.set(key, value);
+            }
+            store.write_batch(batch)?;
+            if last && received.value() != digest {
+                // every chunk was intact, yet together they are not the snapshot: start over
+                corrupt += 1;
+                if corrupt > self.retries {
+                    return Err(Error::with_message(
+                        ErrorKind::ProtocolError,
+                        "the snapshot failed its digest".into(),
+                    ));
+                }
+                store.clear()?;
+                received = Digest::new();
+                offset = 0;
+                continue;
+            }
+            if last {
+                store.shutdown()?;
+                return Ok(total);
+            }
+        }
+    }
+    /// get the page of up to `count` of the keys starting with the prefix (and their values)
+    /// following the page of the cursor (0 for the first page), and the cursor of the next page
+    /// (0 if the scan is complete)
+    pub fn scan_page(
+        &mut self,
+        cursor: u64,
+        prefix: String,
+        count: u64,
+    ) -> Result<(u64, Vec<(String, String)>)> {
+        let request = Request::Scan {
+            cursor,
+            prefix,
+            count,
+        };
+        match self.retried_request(&request)? {
+            Response::ScanPage { cursor, pairs } => Ok((cursor, pairs)),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// iterate over the keys starting with the prefix (every key, if it is empty) and their
+    /// values, requesting them from the server in pages of DEFAULT_SCAN_COUNT pairs
+    pub fn scan(&mut self, prefix: String) -> Scan<'_> {
+        Scan {
+            client: self,
+            prefix,
+            count: DEFAULT_SCAN_COUNT,
+            cursor: Some(0),
+            page: Vec::new().into_iter(),
+        }
+    }
+    /// subscribe to the events of the keys starting with the prefix (and to Cleared), the
+    /// connection being given over to streaming them (each event being waited for however long it
+    /// takes, whatever the client's timeout)
+    pub fn subscribe(mut self, prefix: String) -> Result<Subscription> {
+        match self.request(&Request::Subscribe { prefix })? {
+            Response::Ok => {
+                self.stream.get_ref().set_timeout(None)?;
+                Ok(Subscription {
+                    stream: self.stream,
+                })
+            }
+            response => Err(unexpected(response)),
+        }
+    }
+
+    fn lock_request(
+        &mut self,
+        request: &Request,
+        name: String,
+        expires: time::SystemTime,
+    ) -> Result<Option<Lock>> {
+        match self.request(request)? {
+            Response::Token(token) => Ok(token.map(|token| Lock {
+                name,
+                token,
+                expires,
+            })),
+            response => Err(unexpected(response)),
+        }
+    }
+    fn connect_with(connect: Connect) -> Result<Self> {
+        Ok(Self {
+            stream: io::BufReader::new(connect()?),
+            reconnect: connect,
+            retries: 0,
+            password: None,
+            database: 0,
+            request_id: None,
+            timeout: None,
+            deadline: None,
+            socket_timeout: None,
+            broken: false,
+        })
+    }
+    /// replaces the connection with a new one, authenticated (and using the database) as the
+    /// last was
+    fn reconnect(&mut self) -> Result<()> {
+        self.stream = io::BufReader::new((self.reconnect)()?);
+        self.socket_timeout = None;
+        self.broken = false;
+        if let Some(password) = self.password.clone() {
+            self.authenticate(password)?;
+        }
+        match self.database {
+            0 => Ok(()),
+            database => self.select(database),
+        }
+    }
+    /// makes a request which is safe to repeat, retrying it (as many times as the client retries)
+    /// over a new connection if the connection fails
+    fn retried_request(&mut self, request: &Request) -> Result<Response> {
+        let mut backoff = INITIAL_RETRY_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            match self.request(request) {
+                Err(err)
+                    if attempt < self.retries
+                        && is_connection_broken(&err)
+                        && !self.is_past_deadline() =>
+                {
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                    // if reconnecting fails, so does the retry, which counts as an attempt
+                    let _ = self.reconnect();
+                }
+                response => return response,
+            }
+        }
+    }
+    fn request(&mut self, request: &Request) -> Result<Response> {
+        if self.broken {
+            self.reconnect()?;
+        }
+        let timeout = self.request_timeout()?;
+        if self.socket_timeout != Some(timeout) {
+            self.stream.get_ref().set_timeout(timeout)?;
+            self.socket_timeout = Some(timeout);
+        }
+        self.exchange(request)
+            .map_err(|err| match is_timed_out(&err) {
+                true => {
+                    self.broken = true;
+                    Error::with_source(ErrorKind::Timeout, err)
+                }
+                false => err,
+            })
+    }
+    /// how long the next request may wait on the server: the client's timeout, cut short by its
+    /// deadline (Timeout if the deadline has passed)
+    fn request_timeout(&self) -> Result<Option<time::Duration>> {
+        let remaining = match self.deadline {
+            Some(deadline) => match deadline.checked_duration_since(time::Instant::now()) {
+                Some(remaining) if !remaining.is_zero() => Some(remaining),
+                _ => return Err(Error::new(ErrorKind::Timeout)),
+            },
+            None => None,
+        };
+        Ok(match (self.timeout, remaining) {
+            (Some(timeout), Some(remaining)) => Some(timeout.min(remaining)),
+            (timeout, remaining) => timeout.or(remaining),
+        })
+    }
+    fn is_past_deadline(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| deadline <= time::Instant::now())
+    }
+    /// sends the request and reads the response to it
+    fn exchange(&mut self, request: &Request) -> Result<Response> {
+        match &self.request_id {
+            Some(id) => {
+                let traced = Request::Traced {
+                    id: id.clone(),
+                    request: Box::new(request.clone()),
+                };
+                protocol::write_message(self.stream.get_mut(), &traced)?
+            }
+            None => protocol::write_message(self.stream.get_mut(), request)?,
+        }
+        match protocol::read_message(&mut self.stream)? {
+            Some(Response::KeyNotFound) => Err(Error::new(ErrorKind::KeyNotPresent)),
+            Some(Response::Unauthenticated) => Err(Error::new(ErrorKind::AuthenticationFailed)),
+            Some(Response::ServerError { msg }) => {
+                Err(Error::with_message(resp::error_kind(&msg), msg))
+            }
+            Some(response) => Ok(response),
+            None => Err(Error::new(ErrorKind::IoError)),
+        }
+    }
+}
+
+fn resolve<A: net::ToSocketAddrs>(addr: A) -> Result<Vec<net::SocketAddr>> {
+    Ok(addr.to_socket_addrs()?.collect())
+}
+
+fn connect_stream_timeout(
+    addrs: &[net::SocketAddr],
+    timeout: time::Duration,
+) -> Result<net::TcpStream> {
+    let mut last_err = None;
+    for addr in addrs {
+        match net::TcpStream::connect_timeout(addr, timeout) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                return Ok(stream);
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.map_or_else(|| Error::new(ErrorKind::IoError), Error::from))
+}
+
+/// true if the error means the connection can no longer be used
+fn is_connection_broken(err: &Error) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::IoError | ErrorKind::ProtocolError | ErrorKind::Timeout
+    )
+}
+
+/// true if the error is of a read or write which waited for longer than the socket's timeout
+fn is_timed_out(err: &Error) -> bool {
+    let source = std::error::Error::source(err);
+    let io_error = source
+        .and_then(|source| source.downcast_ref::<io::Error>())
+        .or_else(
+            || match &source?.downcast_ref::<kvs_proto_serde::Error>()?.kind {
+                kvs_proto_serde::ErrorKind::IoError(err) => Some(err),
+                _ => None,
+            },
+        );
+    io_error.is_some_and(|err| {
+        matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        )
+    })
+}
+
+fn unexpected(response: Response) -> Error {
+    match response {
+        Response::Moved { addr } => Error::with_message(ErrorKind::Moved, addr),
+        _ => Error::new(ErrorKind::ProtocolError),
+    }
+}
@@ -0,0 +1,149 @@
+use std::{net, sync::mpsc, thread};
+
+use tokio::sync::{mpsc as async_mpsc, oneshot};
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+
+use super::super::{Error, ErrorKind, Result};
+use super::KvsClient;
+
+/// number of pairs of a scan buffered ahead of the stream they are read from
+const SCAN_BUFFER: usize = 256;
+
+/// request of an AsyncKvsClient, made by the KvsClient on its thread
+type Job = Box<dyn FnOnce(&mut KvsClient) + Send>;
+
+/// Client for a Key-Value Storage server with an async API, for async (such as tokio)
+/// applications, from `KvsClient::into_async`
+///
+/// The KvsClient it is made from is moved to a thread of its own, which makes the requests of
+/// the async methods one at a time in the order they are called, so awaiting a response does not
+/// block the threads of the application's runtime (nor take one of its blocking threads). Clones
+/// share the connection, their requests being made in turn.
+///
+/// # Example
+/// ```no_run
+/// use kvs::AsyncKvsClient;
+///
+/// # async fn example() -> kvs::Result<()> {
+/// let client = AsyncKvsClient::connect("127.0.0.1:4000").await?;
+/// client.set("key1".into(), "value1".into()).await?;
+/// assert_eq!(client.get("key1".into()).await?, Some("value1".into()));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct AsyncKvsClient {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl AsyncKvsClient {
+    /// connect to the server at the given address (connecting on the client's thread)
+    pub async fn connect<A>(addr: A) -> Result<Self>
+    where
+        A: net::ToSocketAddrs + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        thread::spawn(move || match KvsClient::connect(addr) {
+            Ok(client) => {
+                let _ = sender.send(Ok(client.into_async()));
+            }
+            Err(err) => {
+                let _ = sender.send(Err(err));
+            }
+        });
+        receiver.await.unwrap_or_else(|_| Err(disconnected()))
+    }
+    /// authenticate the connection to a server which requires a password
+    pub async fn authenticate(&self, password: String) -> Result<()> {
+        self.call(move |client| client.authenticate(password)).await
+    }
+    /// make the later requests of the connection to the server's database of the given number
+    pub async fn select(&self, db: u64) -> Result<()> {
+        self.call(move |client| client.select(db)).await
+    }
+    /// get the value stored under the given key or None if no such key
+    pub async fn get(&self, key: String) -> Result<Option<String>> {
+        self.call(move |client| client.get(key)).await
+    }
+    /// set the value for the given key (overwriting any prior value)
+    pub async fn set(&self, key: String, value: String) -> Result<()> {
+        self.call(move |client| client.set(key, value)).await
+    }
+    /// remove the given key (and its value), KeyNotPresent if no such key
+    pub async fn remove(&self, key: String) -> Result<()> {
+        self.call(move |client| client.remove(key)).await
+    }
+    /// count the keys of the server
+    pub async fn key_count(&self) -> Result<u64> {
+        self.call(|client| client.key_count()).await
+    }
+    /// stream the keys starting with the prefix (every key, if it is empty) and their values, in
+    /// the order of the keys (as `KvsClient::scan` iterates over them)
+    ///
+    /// The pairs are received in pages of the scan as the stream is read, the requests
+    /// made through the client (or its clones) in the meantime waiting until the stream ends or
+    /// is dropped.
+    pub fn scan(&self, prefix: String) -> impl Stream<Item = Result<(String, String)>> {
+        let (sender, receiver) = async_mpsc::channel(SCAN_BUFFER);
+        let job: Job = Box::new(move |client| {
+            if let Err(err) = scan_into(client, prefix, &sender) {
+                let _ = sender.blocking_send(Err(err));
+            }
+        });
+        if let Err(mpsc::SendError(_)) = self.jobs.send(job) {
+            // the client's thread has ended, so the stream ends with the error
+            let (sender, receiver) = async_mpsc::channel(1);
+            let _ = sender.try_send(Err(disconnected()));
+            return ReceiverStream::new(receiver);
+        }
+        ReceiverStream::new(receiver)
+    }
+
+    /// makes the request with the client on its thread, awaiting its result
+    async fn call<T, F>(&self, request: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut KvsClient) -> Result<T> + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        self.jobs
+            .send(Box::new(move |client| {
+                let _ = sender.send(request(client));
+            }))
+            .map_err(|_| disconnected())?;
+        receiver.await.unwrap_or_else(|_| Err(disconnected()))
+    }
+}
+
+impl KvsClient {
+    /// move the client to a thread of its own, returning an AsyncKvsClient making its requests
+    pub fn into_async(mut self) -> AsyncKvsClient {
+        let (jobs, requests) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            for job in requests {
+                job(&mut self);
+            }
+        });
+        AsyncKvsClient { jobs }
+    }
+}
+
+/// sends the pairs of the keys starting with the prefix, page by page of a scan, until they have
+/// all been sent or the stream they are sent to is dropped
+fn scan_into(
+    client: &mut KvsClient,
+    prefix: String,
+    pairs: &async_mpsc::Sender<Result<(String, String)>>,
+) -> Result<()> {
+    for pair in client.scan(prefix) {
+        if pairs.blocking_send(Ok(pair?)).is_err() {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+/// error of a request to a client whose thread has ended
+fn disconnected() -> Error {
+    Error::new(ErrorKind::IoError)
+}
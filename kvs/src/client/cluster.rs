@@ -0,0 +1,268 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    net, time,
+};
+
+#[cfg(feature = "tls")]
+use super::super::ClientTlsConfig;
+use super::super::{Error, ErrorKind, Request, Response, Result, Topology};
+use super::{is_connection_broken, unexpected, KvsClient};
+
+/// Options controlling a ClusterClient
+#[derive(Debug, Clone)]
+pub struct ClusterOptions {
+    /// maximum time to wait for connecting to a node, and for each read and write (no limit if
+    /// None)
+    pub request_timeout: Option<time::Duration>,
+    /// number of times a request which is safe to repeat is retried over a new connection to the
+    /// same node when its connection fails (as `KvsClient::with_retries`)
+    pub retries: u32,
+    /// number of times a request redirected to another node is sent again before failing
+    pub max_redirections: u32,
+    /// password each connection authenticates with when opened (no authentication if None)
+    pub password: Option<String>,
+    /// connect to the nodes using TLS (plain TCP if None)
+    #[cfg(feature = "tls")]
+    pub tls: Option<ClientTlsConfig>,
+}
+
+impl Default for ClusterOptions {
+    fn default() -> Self {
+        Self {
+            request_timeout: Some(time::Duration::from_secs(30)),
+            retries: 2,
+            max_redirections: 5,
+            password: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+}
+
+/// Client for a cluster of Key-Value Storage servers, routing each request to the node holding
+/// its key
+///
+/// The client fetches the topology of the cluster from the first of the seed addresses which
+/// answers, then sends each request to the leader of the shard holding its key (or to the first
+/// node of the shard if its leader is not known), keeping a connection open to each node it has
+/// sent requests to. When a node redirects a request (as the shard has moved, or the node no longer
+/// leads it), the topology is fetched again, from the node redirected to first, and the request is
+/// sent again as routed by the new topology. If the connection to a node fails, the request fails
+/// and the topology is fetched again before the next request.
+///
+/// Only requests for a single key are routed; a KvsClient connected to each node (from
+/// `topology`) serves the others.
+///
+/// # Example
+/// ```no_run
+/// use kvs::{ClusterClient, ClusterOptions};
+///
+/// let seeds = ["10.0.0.1:4000", "10.0.0.2:4000"];
+/// let mut client = ClusterClient::connect(&seeds, ClusterOptions::default()).unwrap();
+/// client.set("key1".into(), "value1".into()).unwrap();
+/// assert_eq!(client.get("key1".into()).unwrap(), Some("value1".into()));
+/// ```
+pub struct ClusterClient {
+    seeds: Vec<String>,
+    options: ClusterOptions,
+    topology: Topology,
+    /// true if the topology must be fetched again before the next request
+    stale: bool,
+    clients: HashMap<String, KvsClient>,
+}
+
+impl ClusterClient {
+    /// connect to the cluster, fetching its topology from one of the nodes at the seed addresses
+    pub fn connect<A: net::ToSocketAddrs>(seeds: &[A], options: ClusterOptions) -> Result<Self> {
+        let mut resolved = Vec::new();
+        for seed in seeds {
+            resolved.extend(seed.to_socket_addrs()?.map(|addr| addr.to_string()));
+        }
+        if resolved.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidConfiguration));
+        }
+        let mut client = Self {
+            seeds: resolved,
+            options,
+            topology: Topology {
+                epoch: 0,
+                nodes: Vec::new(),
+                shards: Vec::new(),
+            },
+            stale: true,
+            clients: HashMap::new(),
+        };
+        client.refresh_topology(None)?;
+        Ok(client)
+    }
+    /// the topology of the cluster as last fetched
+    pub fn topology(&self) -> &Topology {
+        &self.topology
+    }
+    /// get the value stored under the given key or None if no such key
+    pub fn get(&mut self, key: String) -> Result<Option<String>> {
+        match self.route(&Request::Get { key })? {
+            Response::Value(value) => Ok(value),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// get the value stored under the given key or None if no such key, allowing the value to be
+    /// as it was up to `max_lag` ago (as `KvsClient::get_with_max_lag`)
+    pub fn get_with_max_lag(
+        &mut self,
+        key: String,
+        max_lag: time::Duration,
+    ) -> Result<Option<String>> {
+        let max_lag_ms = max_lag.as_millis() as u64;
+        match self.route(&Request::GetWithMaxLag { key, max_lag_ms })? {
+            Response::Value(value) => Ok(value),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// set a key to a value, overwriting any value already stored under the key
+    ///
+    /// If the client retries requests, the write carries an idempotency token as
+    /// `KvsClient::set` does.
+    pub fn set(&mut self, key: String, value: String) -> Result<()> {
+        if self.options.retries > 0 {
+            return self
+                .set_once(key, value, KvsClient::new_idempotency_token())
+                .map(|_| ());
+        }
+        match self.route(&Request::Set { key, value })? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// set a key to a value unless a write with the same idempotency token has already been
+    /// applied, returning whether it was applied now (as `KvsClient::set_once`)
+    pub fn set_once(&mut self, key: String, value: String, token: u64) -> Result<bool> {
+        match self.route(&Request::SetOnce { key, value, token })? {
+            Response::Applied(applied) => Ok(applied),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// set a key to a value only if the key is not present, returning whether it was set
+    pub fn set_if_absent(&mut self, key: String, value: String) -> Result<bool> {
+        match self.route(&Request::SetIfAbsent { key, value })? {
+            Response::Applied(applied) => Ok(applied),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// append the suffix to the value stored under the given key, returning the length of the
+    /// value in bytes once appended to (as `KvsClient::append`)
+    pub fn append(&mut self, key: String, suffix: String) -> Result<u64> {
+        match self.route(&Request::Append { key, suffix })? {
+            Response::Count(len) => Ok(len),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// length in bytes of the value stored under the given key, 0 if no such key
+    pub fn value_len(&mut self, key: String) -> Result<u64> {
+        match self.route(&Request::ValueLen { key })? {
+            Response::Count(len) => Ok(len),
+            response => Err(unexpected(response)),
+        }
+    }
+    /// remove the value stored under the given key or KeyNotPresent if the key does not exist
+    pub fn remove(&mut self, key: String) -> Result<()> {
+        match self.route(&Request::Remove { key })? {
+            Response::Ok => Ok(()),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// sends the request to the node holding its key, following redirections to other nodes
+    fn route(&mut self, request: &Request) -> Result<Response> {
+        let key = request
+            .key()
+            .ok_or_else(|| Error::new(ErrorKind::UnknownError))?;
+        let mut redirected_to = None;
+        for _ in 0..=self.options.max_redirections {
+            if self.stale {
+                self.refresh_topology(redirected_to.as_deref())?;
+            }
+            let addr = match self.topology.route(key) {
+                Some(addr) => addr.to_owned(),
+                None => {
+                    return Err(Error::with_message(
+                        ErrorKind::InvalidConfiguration,
+                        "no node of the cluster holds the key".into(),
+                    ))
+                }
+            };
+            let response = self
+                .client(&addr)
+                .and_then(|client| client.retried_request(request));
+            match response {
+                Ok(Response::Moved { addr }) => {
+                    self.stale = true;
+                    redirected_to = Some(addr);
+                }
+                Err(err) if is_connection_broken(&err) => {
+                    self.clients.remove(&addr);
+                    self.stale = true;
+                    return Err(err);
+                }
+                response => return response,
+            }
+        }
+        let addr = redirected_to.unwrap_or_default();
+        Err(Error::with_message(ErrorKind::Moved, addr))
+    }
+    /// fetches the topology from the first node which answers, trying the given address first,
+    /// then the nodes of the last topology and then the seeds
+    fn refresh_topology(&mut self, first: Option<&str>) -> Result<()> {
+        let candidates = first
+            .map(str::to_owned)
+            .into_iter()
+            .chain(self.topology.nodes.iter().map(|node| node.addr.clone()))
+            .chain(self.seeds.iter().cloned())
+            .collect::<Vec<_>>();
+        let mut last_err = None;
+        for addr in candidates {
+            match self.client(&addr).and_then(KvsClient::topology) {
+                Ok(topology) => {
+                    self.topology = topology;
+                    self.stale = false;
+                    return Ok(());
+                }
+                Err(err) => {
+                    if is_connection_broken(&err) {
+                        self.clients.remove(&addr);
+                    }
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(last_err.unwrap_or_else(|| Error::new(ErrorKind::IoError)))
+    }
+    /// the client connected to the node at the address, connecting to it if not already
+    fn client(&mut self, addr: &str) -> Result<&mut KvsClient> {
+        match self.clients.entry(addr.to_owned()) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(connect(addr, &self.options)?)),
+        }
+    }
+}
+
+/// connects (and authenticates) to the node at the address
+fn connect(addr: &str, options: &ClusterOptions) -> Result<KvsClient> {
+    #[cfg(feature = "tls")]
+    let client = match (&options.tls, options.request_timeout) {
+        (Some(tls), Some(timeout)) => KvsClient::connect_tls_timeout(addr, tls, timeout),
+        (Some(tls), None) => KvsClient::connect_tls(addr, tls),
+        (None, Some(timeout)) => KvsClient::connect_timeout(addr, timeout),
+        (None, None) => KvsClient::connect(addr),
+    };
+    #[cfg(not(feature = "tls"))]
+    let client = match options.request_timeout {
+        Some(timeout) => KvsClient::connect_timeout(addr, timeout),
+        None => KvsClient::connect(addr),
+    };
+    let mut client = client?.with_retries(options.retries);
+    if let Some(password) = &options.password {
+        client.authenticate(password.clone())?;
+    }
+    Ok(client)
+}
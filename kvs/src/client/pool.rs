@@ -0,0 +1,271 @@
+use std::{
+    net,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread, time,
+};
+
+#[cfg(feature = "tls")]
+use super::super::ClientTlsConfig;
+use super::super::{Error, ErrorKind, Lock, Result};
+use super::{is_connection_broken, KvsClient};
+
+/// Options controlling a KvsClientPool
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    /// maximum number of connections open to the server at once
+    pub pool_size: usize,
+    /// maximum time to wait for connecting, for each read and write, and for a pooled connection
+    /// to become available (no limit if None)
+    pub request_timeout: Option<time::Duration>,
+    /// how long to wait before the first reconnection attempt (doubled after each failed attempt)
+    pub initial_backoff: time::Duration,
+    /// upper bound on the time waited between reconnection attempts
+    pub max_backoff: time::Duration,
+    /// number of times connecting is retried before giving up
+    pub max_reconnect_attempts: u32,
+    /// password each connection authenticates with when opened (no authentication if None)
+    pub password: Option<String>,
+    /// connect to the server using TLS (plain TCP if None)
+    #[cfg(feature = "tls")]
+    pub tls: Option<ClientTlsConfig>,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            pool_size: 8,
+            request_timeout: Some(time::Duration::from_secs(30)),
+            initial_backoff: time::Duration::from_millis(10),
+            max_backoff: time::Duration::from_secs(1),
+            max_reconnect_attempts: 5,
+            password: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+}
+
+/// Pool of KvsClient connections to a single server which may be shared between threads
+///
+/// Connections are opened lazily, up to the pool size. Connecting is retried with exponential
+/// backoff and a connection which fails with an I/O or protocol error is discarded, so it is
+/// replaced by a new connection on a later request.
+///
+/// # Example
+/// ```no_run
+/// use kvs::{KvsClientPool, PoolOptions};
+///
+/// let pool = KvsClientPool::new("127.0.0.1:4000", PoolOptions::default()).unwrap();
+/// pool.set("key1".into(), "value1".into()).unwrap();
+/// assert_eq!(pool.get("key1".into()).unwrap(), Some("value1".into()));
+/// ```
+#[derive(Clone)]
+pub struct KvsClientPool {
+    shared: Arc<PoolShared>,
+}
+
+struct PoolShared {
+    addrs: Vec<net::SocketAddr>,
+    options: PoolOptions,
+    state: Mutex<PoolState>,
+    available: Condvar,
+}
+
+struct PoolState {
+    idle: Vec<KvsClient>,
+    open_connections: usize,
+}
+
+impl KvsClientPool {
+    /// create a pool of connections to the server at the given address
+    pub fn new<A: net::ToSocketAddrs>(addr: A, options: PoolOptions) -> Result<Self> {
+        let addrs = addr.to_socket_addrs()?.collect::<Vec<_>>();
+        if addrs.is_empty() || options.pool_size == 0 {
+            return Err(Error::new(ErrorKind::InvalidConfiguration));
+        }
+        Ok(Self {
+            shared: Arc::new(PoolShared {
+                addrs,
+                options,
+                state: Mutex::new(PoolState {
+                    idle: Vec::new(),
+                    open_connections: 0,
+                }),
+                available: Condvar::new(),
+            }),
+        })
+    }
+    /// get the value stored under the given key or None if no such key
+    pub fn get(&self, key: String) -> Result<Option<String>> {
+        self.with_client(|client| client.get(key))
+    }
+    /// get the value stored under the given key or None if no such key, allowing the value to be
+    /// as it was up to `max_lag` ago
+    pub fn get_with_max_lag(&self, key: String, max_lag: time::Duration) -> Result<Option<String>> {
+        self.with_client(|client| client.get_with_max_lag(key, max_lag))
+    }
+    /// set a key to a value, overwriting any value already stored under the key
+    pub fn set(&self, key: String, value: String) -> Result<()> {
+        self.with_client(|client| client.set(key, value))
+    }
+    /// set a key to a value unless a write with the same idempotency token has already been
+    /// applied, returning whether it was applied now (see `KvsClient::set_once`)
+    pub fn set_once(&self, key: String, value: String, token: u64) -> Result<bool> {
+        self.with_client(|client| client.set_once(key, value, token))
+    }
+    /// set a key to a value only if the key is not present, returning whether it was set
+    pub fn set_if_absent(&self, key: String, value: String) -> Result<bool> {
+        self.with_client(|client| client.set_if_absent(key, value))
+    }
+    /// get the values stored under the given keys in a single request, in the order of the keys
+    pub fn get_many(&self, keys: Vec<String>) -> Result<Vec<Option<String>>> {
+        self.with_client(|client| client.get_many(keys))
+    }
+    /// set each key to its value in a single request, written all together as a batch
+    pub fn set_many(&self, pairs: Vec<(String, String)>) -> Result<()> {
+        self.with_client(|client| client.set_many(pairs))
+    }
+    /// append the suffix to the value stored under the given key, returning the length of the
+    /// value in bytes once appended to (see `KvsClient::append`)
+    pub fn append(&self, key: String, suffix: String) -> Result<u64> {
+        self.with_client(|client| client.append(key, suffix))
+    }
+    /// length in bytes of the value stored under the given key, 0 if no such key
+    pub fn value_len(&self, key: String) -> Result<u64> {
+        self.with_client(|client| client.value_len(key))
+    }
+    /// remove the value stored under the given key or KeyNotPresent if the key does not exist
+    pub fn remove(&self, key: String) -> Result<()> {
+        self.with_client(|client| client.remove(key))
+    }
+    /// compact the server's storage now
+    pub fn compact(&self) -> Result<()> {
+        self.with_client(|client| client.compact())
+    }
+    /// remove every key from the server's storage
+    pub fn clear(&self) -> Result<()> {
+        self.with_client(|client| client.clear())
+    }
+    /// number of keys present in the server's storage
+    pub fn key_count(&self) -> Result<u64> {
+        self.with_client(|client| client.key_count())
+    }
+    /// acquire the expiring lock of the given name for the ttl, None if it is held
+    pub fn acquire_lock(&self, name: String, ttl: time::Duration) -> Result<Option<Lock>> {
+        self.with_client(|client| client.acquire_lock(name, ttl))
+    }
+    /// extend the lock to expire after the ttl, None if it is no longer held
+    pub fn refresh_lock(&self, lock: &Lock, ttl: time::Duration) -> Result<Option<Lock>> {
+        self.with_client(|client| client.refresh_lock(lock, ttl))
+    }
+    /// release the lock, returning whether it was released
+    pub fn release_lock(&self, lock: &Lock) -> Result<bool> {
+        self.with_client(|client| client.release_lock(lock))
+    }
+
+    fn with_client<T, F>(&self, request: F) -> Result<T>
+    where
+        F: FnOnce(&mut KvsClient) -> Result<T>,
+    {
+        let mut client = self.checkout()?;
+        let result = request(&mut client);
+        match &result {
+            Err(err) if is_connection_broken(err) => self.discard(),
+            _ => self.checkin(client)?,
+        }
+        result
+    }
+    fn checkout(&self) -> Result<KvsClient> {
+        let started = time::Instant::now();
+        let mut state = self.lock()?;
+        loop {
+            if let Some(client) = state.idle.pop() {
+                return Ok(client);
+            }
+            if state.open_connections < self.shared.options.pool_size {
+                state.open_connections += 1;
+                drop(state);
+                return self.connect_with_backoff().inspect_err(|_| self.discard());
+            }
+            state = match self.shared.options.request_timeout {
+                Some(timeout) => {
+                    let remaining = timeout
+                        .checked_sub(started.elapsed())
+                        .ok_or_else(|| Error::new(ErrorKind::Timeout))?;
+                    self.shared
+                        .available
+                        .wait_timeout(state, remaining)
+                        .map_err(|_| Error::new(ErrorKind::UnknownError))?
+                        .0
+                }
+                None => self
+                    .shared
+                    .available
+                    .wait(state)
+                    .map_err(|_| Error::new(ErrorKind::UnknownError))?,
+            };
+        }
+    }
+    fn checkin(&self, client: KvsClient) -> Result<()> {
+        self.lock()?.idle.push(client);
+        self.shared.available.notify_one();
+        Ok(())
+    }
+    fn discard(&self) {
+        if let Ok(mut state) = self.lock() {
+            state.open_connections -= 1;
+        }
+        self.shared.available.notify_one();
+    }
+    fn connect_with_backoff(&self) -> Result<KvsClient> {
+        let options = &self.shared.options;
+        let mut backoff = options.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            let connected = self.connect().and_then(|mut client| {
+                if let Some(password) = &options.password {
+                    client.authenticate(password.clone())?;
+                }
+                Ok(client)
+            });
+            match connected {
+                Err(err) if attempt < options.max_reconnect_attempts => {
+                    if !is_connection_broken(&err) {
+                        return Err(err);
+                    }
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(options.max_backoff);
+                    attempt += 1;
+                }
+                connected => return connected,
+            }
+        }
+    }
+    #[cfg(feature = "tls")]
+    fn connect(&self) -> Result<KvsClient> {
+        let addrs = self.shared.addrs.as_slice();
+        match (
+            &self.shared.options.tls,
+            self.shared.options.request_timeout,
+        ) {
+            (Some(tls), Some(timeout)) => KvsClient::connect_tls_timeout(addrs, tls, timeout),
+            (Some(tls), None) => KvsClient::connect_tls(addrs, tls),
+            (None, Some(timeout)) => KvsClient::connect_timeout(addrs, timeout),
+            (None, None) => KvsClient::connect(addrs),
+        }
+    }
+    #[cfg(not(feature = "tls"))]
+    fn connect(&self) -> Result<KvsClient> {
+        let addrs = self.shared.addrs.as_slice();
+        match self.shared.options.request_timeout {
+            Some(timeout) => KvsClient::connect_timeout(addrs, timeout),
+            None => KvsClient::connect(addrs),
+        }
+    }
+    fn lock(&self) -> Result<MutexGuard<'_, PoolState>> {
+        self.shared
+            .state
+            .lock()
+            .map_err(|_| Error::new(ErrorKind::UnknownError))
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Source of the current time of a KvStore, which timestamps its writes and decides when its
+/// locks expire, set through `StoreOptions::clock`
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// the current time
+    fn now(&self) -> SystemTime;
+}
+
+/// Clock reading the time of the system, the default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Clock whose time only moves when it is advanced or set, so that tests of expiry need not
+/// sleep; its clones share its time
+///
+/// # Example
+/// ```
+/// use kvs::{Clock, ManualClock};
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// let clock = ManualClock::new(UNIX_EPOCH + Duration::from_secs(60));
+/// clock.clone().advance(Duration::from_secs(1));
+/// assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(61));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl ManualClock {
+    /// a clock reading the given time until it is advanced
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+    /// move the time of the clock (and its clones) forward
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+    /// set the time of the clock (and its clones), which may move it backward
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+impl Default for ManualClock {
+    /// a clock reading the current time of the system until it is advanced
+    fn default() -> Self {
+        Self::new(SystemTime::now())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// the time in milliseconds since the Unix epoch, as records and locks hold it (0 before it)
+pub(crate) fn millis_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
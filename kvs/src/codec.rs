@@ -0,0 +1,376 @@
+use std::{
+    fmt,
+    io::{self, BufRead, Read, Seek, Write},
+    ops::Range,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{Error, ErrorKind, Record, Result};
+
+#[cfg(feature = "legacy-asn1")]
+mod legacy;
+
+/// first byte of a record, which is written as a kvs-proto tuple of its fields (in the order of
+/// their declaration)
+const RECORD_TAG: u8 = b'~';
+/// first byte of a record written by earlier versions, an ASN.1 DER sequence
+const LEGACY_RECORD_TAG: u8 = 0x30;
+/// first byte of the line of the checksum of a record of the compact format, which precedes the
+/// record: the CRC-32 of the record in 8 hexadecimal digits
+const CHECKSUM_TAG: u8 = b'*';
+/// length of the line of the checksum of a record, with its tag and newline
+const CHECKSUM_LINE_LEN: usize = 10;
+/// serialization of None (the value of a removal record)
+const NONE: &[u8] = b"!\n";
+/// deepest nesting of the elements of a record (below which the log is taken to be malformed)
+const MAX_DEPTH: usize = 64;
+/// number of fields of a record of the compact format, which leaves out the record's offset
+pub(crate) const COMPACT_RECORD_FIELDS: usize = 6;
+
+/// Format of the records a KvStore writes to its log, records of either format being read
+/// whichever it writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecordFormat {
+    /// each record leaves out its offset in the log, which is taken to be the position it is read
+    /// at, and is preceded by a CRC-32 of its bytes, checked whenever it is read so that a record
+    /// which has changed since it was written is reported as CorruptLog (earlier versions cannot
+    /// read these records, and those of the compact format they wrote have no checksum)
+    #[default]
+    Compact,
+    /// each record holds its own offset in the log, as earlier versions wrote (and read)
+    WithOffsets,
+}
+
+/// reads the record at the position of the reader, which is at the offset in the log, advancing
+/// the offset past the record, None if the log ends there with a torn or malformed record (or none
+/// at all)
+///
+/// A record of the compact format is taken to be at the offset given, and one whose checksum does
+/// not match is CorruptLog. Records written in ASN.1 DER by earlier versions are read with the
+/// `legacy-asn1` feature (until compaction rewrites them), UnsupportedFormat without it.
+pub(crate) fn read_record<R, K, V>(
+    reader: &mut io::BufReader<R>,
+    offset: &mut u64,
+) -> Result<Option<Record<K, V>>>
+where
+    R: io::Read + io::Seek,
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    match reader.fill_buf()?.first() {
+        Some(&RECORD_TAG) | Some(&CHECKSUM_TAG) => (),
+        Some(&LEGACY_RECORD_TAG) => {
+            // the length of such a record is not known, so the offset is asked of the reader
+            let rec = read_legacy_record(reader)?;
+            *offset = reader.stream_position()?;
+            return Ok(rec);
+        }
+        _ => return Ok(None),
+    }
+    let buf = &mut Vec::new();
+    let db_key = *offset;
+    let checksum = match read_checksummed_element(reader, buf) {
+        Ok(checksum) => checksum,
+        Err(err) if is_checksum_mismatch(&err) => {
+            return Err(Error::new(ErrorKind::CorruptLog { offset: db_key }).at_offset(db_key))
+        }
+        Err(err) if is_malformed(&err) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    // the record is read whole into the buffer, so it is as long as the buffer (and its checksum)
+    *offset += (buf.len() + checksum.map_or(0, |_| CHECKSUM_LINE_LEN)) as u64;
+    if field_count(buf) != Some(COMPACT_RECORD_FIELDS as u64) {
+        return decode_record(buf).map(Some);
+    }
+    let (key, value, merge, previous, version, timestamp) = decode(buf)?;
+    Ok(Some(Record {
+        db_key,
+        key,
+        value,
+        merge,
+        previous,
+        version,
+        timestamp,
+    }))
+}
+
+/// reads the record at the position of the reader into the buffer as it is serialized (without
+/// its checksum), returning the ranges of the buffer holding its fields (COMPACT_RECORD_FIELDS of
+/// them for a record of the compact format, which has no offset), or None if the record is of the
+/// ASN.1 DER format of earlier versions (which is not read)
+///
+/// A torn or malformed record is an UnexpectedEof or InvalidData error, as is a record whose
+/// checksum does not match (for which `is_checksum_mismatch` is true).
+pub(crate) fn read_record_fields(
+    reader: &mut impl BufRead,
+    buf: &mut Vec<u8>,
+) -> io::Result<Option<Vec<Range<usize>>>> {
+    let checksum = match reader.fill_buf()?.first() {
+        Some(&RECORD_TAG) => None,
+        Some(&CHECKSUM_TAG) => Some(read_checksum(reader)?),
+        Some(&LEGACY_RECORD_TAG) => return Ok(None),
+        _ => return Err(malformed()),
+    };
+    let start = buf.len();
+    let header = read_line(reader, buf)?;
+    let count = parse_len(&buf[header.start + 1..header.end])?;
+    let mut fields = Vec::new();
+    for _ in 0..count {
+        let start = buf.len();
+        read_element(reader, buf, 1)?;
+        fields.push(start..buf.len());
+    }
+    verify_checksum(checksum, &buf[start..])?;
+    Ok(Some(fields))
+}
+
+/// serializes the record in the format into the buffer (appending to what it holds), returning
+/// the ranges of the buffer its key and its value are serialized in
+///
+/// The record is the tuple of its fields, serialized a field at a time after the line of the
+/// tuple's length so that where each field is serialized is known without reading it again. The
+/// line of the checksum of a record of the compact format is written before the tuple with room
+/// for the checksum, which is filled in once the tuple is serialized.
+pub(crate) fn encode_record<K, V>(
+    rec: &Record<K, V>,
+    format: RecordFormat,
+    buf: &mut Vec<u8>,
+) -> Result<(Range<usize>, Range<usize>)>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    let field_count = match format {
+        RecordFormat::Compact => COMPACT_RECORD_FIELDS,
+        RecordFormat::WithOffsets => COMPACT_RECORD_FIELDS + 1,
+    };
+    let checksum = buf.len();
+    if format == RecordFormat::Compact {
+        buf.extend_from_slice(&[CHECKSUM_TAG; CHECKSUM_LINE_LEN]);
+    }
+    let start = buf.len();
+    buf.push(RECORD_TAG);
+    writeln!(buf, "{}", field_count)?;
+    if format == RecordFormat::WithOffsets {
+        encode(&rec.db_key, buf)?;
+    }
+    let key = encode_field(&rec.key, buf)?;
+    let value = encode_field(&rec.value, buf)?;
+    encode(&rec.merge, buf)?;
+    encode(&rec.previous, buf)?;
+    encode(&rec.version, buf)?;
+    encode(&rec.timestamp, buf)?;
+    if format == RecordFormat::Compact {
+        let crc = crc32fast::hash(&buf[start..]);
+        writeln!(&mut buf[checksum + 1..start], "{:08x}", crc)?;
+    }
+    Ok((key, value))
+}
+
+/// serializes the field into the buffer (appending to what it holds), returning the range of the
+/// buffer it is serialized in
+fn encode_field<T: Serialize>(field: &T, buf: &mut Vec<u8>) -> Result<Range<usize>> {
+    let start = buf.len();
+    encode(field, buf)?;
+    Ok(start..buf.len())
+}
+
+/// serializes the value into the buffer (appending to what it holds)
+pub(crate) fn encode<T: Serialize>(value: &T, buf: &mut Vec<u8>) -> Result<()> {
+    // without capacity of its own the writer writes straight through to the buffer
+    kvs_proto_serde::to_writer(&mut io::BufWriter::with_capacity(0, buf), value)
+        .map_err(|err| Error::with_source(ErrorKind::SerializationError, err.to_string()))
+}
+
+/// deserializes the value from its serialization
+pub(crate) fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    let reader = &mut io::BufReader::with_capacity(bytes.len().max(1), bytes);
+    kvs_proto_serde::from_reader(reader)
+        .map_err(|err| Error::with_source(ErrorKind::DeserializationError, err.to_string()))
+}
+
+fn decode_record<K, V>(bytes: &[u8]) -> Result<Record<K, V>>
+where
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    let (db_key, key, value, merge, previous, version, timestamp) = decode(bytes)?;
+    Ok(Record {
+        db_key,
+        key,
+        value,
+        merge,
+        previous,
+        version,
+        timestamp,
+    })
+}
+
+/// number of elements of the sequence (or tuple) serialized in the bytes, from its first line
+fn field_count(bytes: &[u8]) -> Option<u64> {
+    let end = bytes.iter().position(|&byte| byte == b'\n')?;
+    parse_len(bytes.get(1..end)?).ok()
+}
+
+/// true if the bytes are the serialization of None
+pub(crate) fn is_none(bytes: &[u8]) -> bool {
+    bytes == NONE
+}
+
+/// replaces the serialized String in the buffer (a value read by `get_raw`) with its UTF-8 bytes
+pub(crate) fn unwrap_string(buf: &mut Vec<u8>) -> Result<()> {
+    let contents = match buf.first() {
+        _ if !buf.ends_with(b"\n") => None,
+        Some(b'$') => Some(1..buf.len() - 1),
+        Some(b'&') => buf
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .filter(|&end| end < buf.len() - 1)
+            .map(|end| end + 1..buf.len() - 1),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        Error::with_message(
+            ErrorKind::DeserializationError,
+            "malformed serialized value".to_owned(),
+        )
+    })?;
+    buf.truncate(contents.end);
+    buf.drain(..contents.start);
+    Ok(())
+}
+
+#[cfg(not(feature = "legacy-asn1"))]
+fn read_legacy_record<R, K, V>(_reader: &mut io::BufReader<R>) -> Result<Option<Record<K, V>>> {
+    Err(Error::new(ErrorKind::UnsupportedFormat))
+}
+#[cfg(feature = "legacy-asn1")]
+use legacy::read_record as read_legacy_record;
+
+/// reads the element at the position of the reader into the buffer as `read_element` does, after
+/// the line of its checksum (if it has one), which the element must match, returning the checksum
+fn read_checksummed_element(
+    reader: &mut impl BufRead,
+    buf: &mut Vec<u8>,
+) -> io::Result<Option<u32>> {
+    let checksum = match reader.fill_buf()?.first() {
+        Some(&CHECKSUM_TAG) => Some(read_checksum(reader)?),
+        _ => None,
+    };
+    read_element(reader, buf, 0)?;
+    verify_checksum(checksum, buf)?;
+    Ok(checksum)
+}
+
+/// reads the line of the checksum of a record at the position of the reader
+fn read_checksum(reader: &mut impl BufRead) -> io::Result<u32> {
+    let mut line = [0; CHECKSUM_LINE_LEN];
+    reader.read_exact(&mut line)?;
+    match line {
+        [CHECKSUM_TAG, ref digits @ .., b'\n'] => std::str::from_utf8(digits)
+            .ok()
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(malformed),
+        _ => Err(malformed()),
+    }
+}
+
+/// fails with a ChecksumMismatch if the bytes do not match the checksum (if any)
+fn verify_checksum(checksum: Option<u32>, bytes: &[u8]) -> io::Result<()> {
+    match checksum {
+        Some(checksum) if crc32fast::hash(bytes) != checksum => {
+            Err(io::Error::new(io::ErrorKind::InvalidData, ChecksumMismatch))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// a record does not match its checksum, having changed since it was written
+#[derive(Debug)]
+struct ChecksumMismatch;
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the record does not match its checksum")
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+/// true if the error is of a record which does not match its checksum
+pub(crate) fn is_checksum_mismatch(err: &io::Error) -> bool {
+    err.get_ref()
+        .is_some_and(|err| err.is::<ChecksumMismatch>())
+}
+
+/// reads the element (of any type) at the position of the reader into the buffer, checking only
+/// that it is well formed
+fn read_element(reader: &mut impl BufRead, buf: &mut Vec<u8>, depth: usize) -> io::Result<()> {
+    if depth > MAX_DEPTH {
+        return Err(malformed());
+    }
+    let line = read_line(reader, buf)?;
+    let tag = *buf[line.clone()].first().ok_or_else(malformed)?;
+    let len = parse_len(&buf[line.start + 1..line.end]);
+    let elements = match tag {
+        // strings and byte arrays of the given length, ended by a newline
+        b'&' | b'%' => {
+            let len = len?.saturating_add(1);
+            if reader.take(len).read_to_end(buf)? as u64 != len {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            match buf.last() {
+                Some(b'\n') => 0,
+                _ => return Err(malformed()),
+            }
+        }
+        b'`' | b'~' => len?,
+        b'{' => len?.saturating_mul(2),
+        // named, after the line of the name (and of the variant, for enum variants)
+        b':' | b'}' | b'@' | b'^' | b'#' => {
+            read_line(reader, buf)?;
+            if let b'@' | b'^' | b'#' = tag {
+                read_line(reader, buf)?;
+            }
+            match tag {
+                b'@' => 0,
+                b'}' | b'#' => len?.saturating_mul(2),
+                _ => len?,
+            }
+        }
+        // scalars, of the line alone
+        _ => 0,
+    };
+    (0..elements).try_for_each(|_| read_element(reader, buf, depth + 1))
+}
+
+/// reads the line at the position of the reader into the buffer, returning the range of the
+/// buffer it is in (without its newline)
+fn read_line(reader: &mut impl BufRead, buf: &mut Vec<u8>) -> io::Result<Range<usize>> {
+    let start = buf.len();
+    reader.read_until(b'\n', buf)?;
+    match buf.last() {
+        Some(b'\n') if buf.len() > start => Ok(start..buf.len() - 1),
+        _ => Err(io::ErrorKind::UnexpectedEof.into()),
+    }
+}
+
+fn parse_len(digits: &[u8]) -> io::Result<u64> {
+    std::str::from_utf8(digits)
+        .ok()
+        .and_then(|digits| digits.parse().ok())
+        .ok_or_else(malformed)
+}
+
+fn malformed() -> io::Error {
+    io::ErrorKind::InvalidData.into()
+}
+
+/// true if the error is of a record which is torn or malformed, rather than of reading the log
+fn is_malformed(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData
+    )
+}
@@ -0,0 +1,24 @@
+use std::io;
+
+use serde::de::DeserializeOwned;
+
+use super::super::{Error, ErrorKind, Record, Result};
+
+/// reads the record (written in ASN.1 DER by earlier versions) at the position of the reader, None
+/// if it is torn or malformed
+pub(crate) fn read_record<R, K, V>(reader: &mut io::BufReader<R>) -> Result<Option<Record<K, V>>>
+where
+    R: io::Read,
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    let vec = &mut Vec::new();
+    match serde_asn1_der::from_reader(reader, serde_asn1_der::VecBacking(vec)) {
+        Ok(rec) => Ok(Some(rec)),
+        Err(serde_asn1_der::SerdeAsn1DerError::Asn1DerError(_)) => Ok(None),
+        Err(err) => Err(Error::with_source(
+            ErrorKind::DeserializationError,
+            err.to_string(),
+        )),
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    fmt, hash,
+    io::{self, Seek},
+    marker,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    read_record_from, writer_position, Error, ErrorKind, Index, KvStore, LogReader, Result, Version,
+};
+
+/// Whether a record read back by `debug_iter_all_records` is the one the index points at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordState {
+    /// the latest write of a key the store holds, which the index points at
+    Live,
+    /// a value (or merge operand) written over by a later write of its key
+    Superseded,
+    /// the removal of a key
+    Tombstone,
+}
+
+/// Record of the log of a KvStore, whatever its state, from `debug_iter_all_records`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugRecord<K, V> {
+    /// offset of the record in the log
+    pub offset: u64,
+    /// length of the record in the log
+    pub len: u64,
+    /// key written
+    pub key: K,
+    /// value set, or operand merged, None if the key was removed
+    pub value: Option<V>,
+    /// the value is an operand merged into the key's value rather than its new value
+    pub merge: bool,
+    /// version of the write
+    pub version: Version,
+    /// whether the record is live, superseded or a tombstone
+    pub state: RecordState,
+}
+
+/// Iterator over every record of the log of a KvStore, from `debug_iter_all_records`
+pub struct AllRecords<'a, K, V> {
+    reader: &'a mut LogReader,
+    index: &'a Index<K>,
+    offset: u64,
+    end: u64,
+    phantom: marker::PhantomData<V>,
+}
+
+impl<K, V> Iterator for AllRecords<'_, K, V>
+where
+    K: DeserializeOwned + Eq + hash::Hash,
+    V: DeserializeOwned,
+{
+    type Item = Result<DebugRecord<K, V>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.end {
+            return None;
+        }
+        let (offset, mut next) = (self.offset, self.offset);
+        let rec = self
+            .reader
+            .seek(io::SeekFrom::Start(offset))
+            .map_err(Error::from)
+            .and_then(|_| read_record_from::<_, K, V>(self.reader, &mut next));
+        // nothing after a corrupt record can be read, so it is the last item
+        let rec = match rec {
+            Ok(Some(rec)) if rec.db_key == offset => rec,
+            Err(err) => {
+                self.offset = self.end;
+                return Some(Err(err.at_offset(offset)));
+            }
+            _ => {
+                self.offset = self.end;
+                return Some(Err(
+                    Error::new(ErrorKind::CorruptLog { offset }).at_offset(offset)
+                ));
+            }
+        };
+        self.offset = next;
+        let state = match (&rec.value, self.index.get(&rec.key)) {
+            (None, _) => RecordState::Tombstone,
+            (Some(_), Some(&db_key)) if db_key == offset => RecordState::Live,
+            (Some(_), _) => RecordState::Superseded,
+        };
+        Some(Ok(DebugRecord {
+            offset,
+            len: self.offset - offset,
+            version: Version::of(&rec),
+            key: rec.key,
+            value: rec.value,
+            merge: rec.merge,
+            state,
+        }))
+    }
+}
+
+impl<K, V> KvStore<K, V>
+where
+    K: Serialize + DeserializeOwned + Eq + PartialEq + hash::Hash + Clone + fmt::Debug,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    /// iterate over every record of the active log, oldest first, including the values written
+    /// over and the removals which compaction would drop, each with its offset and whether the
+    /// index points at it, for inspecting what the log holds
+    ///
+    /// Iteration ends with a CorruptLog error at the first record which cannot be read. The merge
+    /// operands of a live value, other than the latest, are reported as superseded.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::{KvStore, RecordState};
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// store.set("debug1".into(),"value1".into()).unwrap();
+    /// store.set("debug1".into(),"value2".into()).unwrap();
+    /// for rec in store.debug_iter_all_records().unwrap() {
+    ///     let rec = rec.unwrap();
+    ///     if rec.key == "debug1" && rec.value == Some("value1".into()) {
+    ///         assert_eq!(rec.state, RecordState::Superseded);
+    ///     }
+    /// }
+    /// ```
+    pub fn debug_iter_all_records(&mut self) -> Result<AllRecords<'_, K, V>> {
+        let end = writer_position(&mut self.writer)?;
+        self.flush_log()?;
+        Ok(AllRecords {
+            reader: &mut self.reader,
+            index: &self.index,
+            offset: 0,
+            end,
+            phantom: marker::PhantomData,
+        })
+    }
+}
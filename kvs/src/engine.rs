@@ -0,0 +1,332 @@
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    path, str,
+    sync::{mpsc, Arc, Mutex, MutexGuard, PoisonError, Weak},
+    time::Duration,
+};
+
+#[cfg(feature = "stats")]
+use super::Stats;
+use super::{
+    codec, Error, ErrorKind, KeyEvent, KvStore, Lock, Result, RuntimeOptions, StoreEvent,
+    WriteBatch,
+};
+
+/// Key-Value Storage Engine which may be shared between threads (e.g. by `kvs-server`)
+pub trait KvsEngine: Clone + Send + 'static {
+    /// set a key to a value, overwriting any value already stored under the key
+    fn set(&self, key: String, value: String) -> Result<()>;
+    /// set a key to a value only if the key is not present, returning whether it was set
+    fn set_if_absent(&self, key: String, value: String) -> Result<bool>;
+    /// set a key to a value unless a write with the same idempotency token has already been
+    /// applied (among the recent writes the engine remembers), returning whether it was set
+    fn set_once(&self, key: String, value: String, token: u64) -> Result<bool>;
+    /// get the value stored under the given key or None if no such key
+    fn get(&self, key: String) -> Result<Option<String>>;
+    /// get the values stored under the given keys, in the order of the keys (None for each key not
+    /// present)
+    fn get_many(&self, keys: Vec<String>) -> Result<Vec<Option<String>>> {
+        keys.into_iter().map(|key| self.get(key)).collect()
+    }
+    /// get the value stored under the given key as `get` does, but allowing the value to be as
+    /// it was up to `max_lag` ago, so that an engine replicating another may serve the read itself
+    fn get_with_max_lag(&self, key: String, max_lag: Duration) -> Result<Option<String>>;
+    /// read the value stored under the given key into the buffer as its UTF-8 bytes (replacing
+    /// what the buffer held), returning false if no such key, so that a server forwarding values
+    /// to its clients reads them without decoding each into a String
+    fn get_bytes(&self, key: &[u8], buf: &mut Vec<u8>) -> Result<bool> {
+        buf.clear();
+        // a key which is not UTF-8 cannot have been stored
+        let key = match str::from_utf8(key) {
+            Ok(key) => key.to_owned(),
+            Err(_) => return Ok(false),
+        };
+        match self.get(key)? {
+            Some(value) => {
+                buf.extend_from_slice(value.as_bytes());
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+    /// set a key to a value, both given as UTF-8 bytes, DeserializationError if either is not
+    /// valid UTF-8
+    fn set_bytes(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        self.set(utf8(key)?, utf8(value)?)
+    }
+    /// append the suffix to the value stored under the given key (setting the key to the suffix if
+    /// no such key), returning the length of the value in bytes once appended to
+    fn append(&self, key: String, suffix: String) -> Result<usize>;
+    /// length in bytes of the value stored under the given key, 0 if no such key
+    fn value_len(&self, key: String) -> Result<usize> {
+        Ok(self.get(key)?.map_or(0, |value| value.len()))
+    }
+    /// remove the value stored under the given key or KeyNotPresent if the key does not exist
+    fn remove(&self, key: String) -> Result<()>;
+    /// all keys currently present (in no particular order)
+    fn keys(&self) -> Result<Vec<String>>;
+    /// every key present with its value, all read as of the same moment (in no particular order)
+    fn pairs(&self) -> Result<Vec<(String, String)>>;
+    /// apply all the writes of the batch together, returning whether each operation was applied
+    fn write_batch(&self, batch: WriteBatch<String, String>) -> Result<Vec<bool>>;
+    /// number of keys currently present
+    fn key_count(&self) -> Result<usize>;
+    /// acquire the expiring lock of the given name for the ttl, None if it is held
+    fn acquire_lock(&self, name: String, ttl: Duration) -> Result<Option<Lock>>;
+    /// extend the lock of the given name held with the token, None if it is no longer held
+    fn refresh_lock(&self, name: String, token: u64, ttl: Duration) -> Result<Option<Lock>>;
+    /// release the lock of the given name held with the token, returning whether it was released
+    fn release_lock(&self, name: String, token: u64) -> Result<bool>;
+    /// subscribe to the events of the keys starting with the prefix (and to Cleared), until the
+    /// receiver is dropped or falls too far behind (see `KvStore::subscribe`)
+    fn subscribe(&self, prefix: String) -> Result<mpsc::Receiver<KeyEvent<String>>>;
+    /// subscribe to the operational events of the storage (compactions, stalled writes, log
+    /// rotations and recoveries)
+    fn subscribe_events(&self) -> Result<mpsc::Receiver<StoreEvent>>;
+    /// compact the storage now
+    fn compact(&self) -> Result<()>;
+    /// remove every key
+    fn clear(&self) -> Result<()>;
+    /// change the compaction thresholds and quotas of the storage while it is open
+    fn reconfigure(&self, options: RuntimeOptions) -> Result<()>;
+    /// latencies of the operations of the storage since it was opened
+    #[cfg(feature = "stats")]
+    fn stats(&self) -> Result<Stats>;
+    /// id of the node accepting writes among those replicating the storage, None if it is not
+    /// replicated (or no node is known to accept writes)
+    fn leader(&self) -> Result<Option<u64>>;
+    /// flush and fsync all writes and mark the storage as cleanly shut down (no further writes
+    /// should be made)
+    fn shutdown(&self) -> Result<()>;
+}
+
+/// number of idempotency tokens of applied writes a SharedKvStore remembers
+const REMEMBERED_TOKENS: usize = 100_000;
+
+/// the SharedKvStores opened in the process (with `open`), by the canonical path of their
+/// directory
+static OPENED: Mutex<BTreeMap<path::PathBuf, WeakShared>> = Mutex::new(BTreeMap::new());
+
+/// handle of a SharedKvStore which does not keep it open
+type WeakShared = (
+    Weak<Mutex<KvStore<String, String>>>,
+    Weak<Mutex<AppliedTokens>>,
+);
+
+/// KvStore shared between threads behind a Mutex
+///
+/// The merge operator of the store is replaced by one appending each operand to the value, which
+/// `append` merges suffixes with rather than reading and rewriting the value.
+///
+/// The idempotency tokens of the most recent writes made with `set_once` are remembered in memory
+/// only, so a write retried after the store is reopened is applied again.
+#[derive(Clone)]
+pub struct SharedKvStore {
+    store: Arc<Mutex<KvStore<String, String>>>,
+    applied_tokens: Arc<Mutex<AppliedTokens>>,
+}
+
+/// idempotency tokens of the most recent writes applied, oldest first
+#[derive(Default)]
+struct AppliedTokens {
+    order: VecDeque<u64>,
+    tokens: HashSet<u64>,
+}
+
+impl AppliedTokens {
+    /// records the token, returning false if it was already recorded
+    fn insert(&mut self, token: u64) -> bool {
+        if !self.tokens.insert(token) {
+            return false;
+        }
+        self.order.push_back(token);
+        if self.order.len() > REMEMBERED_TOKENS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.tokens.remove(&oldest);
+            }
+        }
+        true
+    }
+    fn remove(&mut self, token: u64) {
+        if self.tokens.remove(&token) {
+            self.order.retain(|&recorded| recorded != token);
+        }
+    }
+}
+
+impl SharedKvStore {
+    /// open a disk-based, log-based storage at a path for sharing between threads
+    ///
+    /// A store already opened in the process this way is shared rather than opened again (a
+    /// KvStore open in the process any other way failing this with AlreadyOpen).
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::{KvsEngine, SharedKvStore};
+    ///
+    /// let dir = tempfile::TempDir::new().unwrap();
+    /// let engine = SharedKvStore::open(dir.path()).unwrap();
+    /// let _ = engine.set("key1".into(), "value1".into());
+    /// assert_eq!(engine.get("key1".into()).unwrap(), Some("value1".into()));
+    /// ```
+    pub fn open(path: &path::Path) -> Result<Self> {
+        let mut opened = OPENED.lock().unwrap_or_else(PoisonError::into_inner);
+        opened.retain(|_, (store, _)| store.strong_count() > 0);
+        let shared = path
+            .canonicalize()
+            .ok()
+            .and_then(|dir| opened.get(&dir))
+            .and_then(|(store, applied_tokens)| {
+                Some((store.upgrade()?, applied_tokens.upgrade()?))
+            });
+        if let Some((store, applied_tokens)) = shared {
+            return Ok(Self {
+                store,
+                applied_tokens,
+            });
+        }
+        let shared = Self::new(KvStore::open(path)?);
+        opened.insert(
+            path.canonicalize()?,
+            (
+                Arc::downgrade(&shared.store),
+                Arc::downgrade(&shared.applied_tokens),
+            ),
+        );
+        Ok(shared)
+    }
+    /// share an already opened KvStore
+    pub fn new(mut store: KvStore<String, String>) -> Self {
+        store.set_merge_operator(|_key, value: Option<String>, suffix| {
+            value.unwrap_or_default() + &suffix
+        });
+        Self {
+            store: Arc::new(Mutex::new(store)),
+            applied_tokens: Default::default(),
+        }
+    }
+
+    pub(crate) fn lock(&self) -> Result<MutexGuard<'_, KvStore<String, String>>> {
+        self.store
+            .lock()
+            .map_err(|_| Error::new(ErrorKind::UnknownError))
+    }
+}
+
+impl KvsEngine for SharedKvStore {
+    fn set(&self, key: String, value: String) -> Result<()> {
+        self.lock()?.set(key, value)
+    }
+    fn set_if_absent(&self, key: String, value: String) -> Result<bool> {
+        self.lock()?.set_if_absent(key, value)
+    }
+    fn set_once(&self, key: String, value: String, token: u64) -> Result<bool> {
+        // the tokens stay locked until the write is made, so a concurrent retry waits to see it
+        let mut applied_tokens = self
+            .applied_tokens
+            .lock()
+            .map_err(|_| Error::new(ErrorKind::UnknownError))?;
+        if !applied_tokens.insert(token) {
+            return Ok(false);
+        }
+        let result = self.lock().and_then(|mut store| store.set(key, value));
+        if result.is_err() {
+            // a failed write may be retried with the same token
+            applied_tokens.remove(token);
+        }
+        result.map(|_| true)
+    }
+    fn get(&self, key: String) -> Result<Option<String>> {
+        self.lock()?.get(key)
+    }
+    fn get_many(&self, keys: Vec<String>) -> Result<Vec<Option<String>>> {
+        // the values are all read as of the same moment
+        let mut store = self.lock()?;
+        keys.into_iter().map(|key| store.get(key)).collect()
+    }
+    fn get_with_max_lag(&self, key: String, _max_lag: Duration) -> Result<Option<String>> {
+        self.get(key)
+    }
+    fn get_bytes(&self, key: &[u8], buf: &mut Vec<u8>) -> Result<bool> {
+        buf.clear();
+        let key = match str::from_utf8(key) {
+            Ok(key) => key.to_owned(),
+            Err(_) => return Ok(false),
+        };
+        // the value is copied from its record as it is serialized, then unwrapped in place
+        match self.lock()?.get_raw(key, buf)? {
+            true => codec::unwrap_string(buf).map(|_| true),
+            false => Ok(false),
+        }
+    }
+    fn append(&self, key: String, suffix: String) -> Result<usize> {
+        let mut store = self.lock()?;
+        store.merge(key.clone(), suffix)?;
+        Ok(store.get(key)?.map_or(0, |value| value.len()))
+    }
+    fn remove(&self, key: String) -> Result<()> {
+        self.lock()?.remove(key)
+    }
+    fn keys(&self) -> Result<Vec<String>> {
+        Ok(self.lock()?.keys().cloned().collect())
+    }
+    fn pairs(&self) -> Result<Vec<(String, String)>> {
+        let mut store = self.lock()?;
+        let keys = store.keys().cloned().collect::<Vec<_>>();
+        let mut pairs = Vec::with_capacity(keys.len());
+        for key in keys {
+            if let Some(value) = store.get(key.clone())? {
+                pairs.push((key, value));
+            }
+        }
+        Ok(pairs)
+    }
+    fn write_batch(&self, batch: WriteBatch<String, String>) -> Result<Vec<bool>> {
+        self.lock()?.write_batch(batch)
+    }
+    fn key_count(&self) -> Result<usize> {
+        Ok(self.lock()?.len())
+    }
+    fn acquire_lock(&self, name: String, ttl: Duration) -> Result<Option<Lock>> {
+        self.lock()?.acquire_lock(&name, ttl)
+    }
+    fn refresh_lock(&self, name: String, token: u64, ttl: Duration) -> Result<Option<Lock>> {
+        self.lock()?.refresh_lock(&name, token, ttl)
+    }
+    fn release_lock(&self, name: String, token: u64) -> Result<bool> {
+        self.lock()?.release_lock(&name, token)
+    }
+    fn subscribe(&self, prefix: String) -> Result<mpsc::Receiver<KeyEvent<String>>> {
+        Ok(self
+            .lock()?
+            .subscribe(move |key: &String| key.starts_with(&prefix)))
+    }
+    fn subscribe_events(&self) -> Result<mpsc::Receiver<StoreEvent>> {
+        Ok(self.lock()?.subscribe_events())
+    }
+    fn compact(&self) -> Result<()> {
+        self.lock()?.compact_now()
+    }
+    fn clear(&self) -> Result<()> {
+        self.lock()?.clear()
+    }
+    fn reconfigure(&self, options: RuntimeOptions) -> Result<()> {
+        self.lock()?.reconfigure(options)
+    }
+    #[cfg(feature = "stats")]
+    fn stats(&self) -> Result<Stats> {
+        Ok(self.lock()?.stats())
+    }
+    fn leader(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+    fn shutdown(&self) -> Result<()> {
+        self.lock()?.shutdown()
+    }
+}
+
+fn utf8(bytes: &[u8]) -> Result<String> {
+    str::from_utf8(bytes)
+        .map(str::to_owned)
+        .map_err(|err| Error::with_source(ErrorKind::DeserializationError, err))
+}
@@ -0,0 +1,485 @@
+//! Conformance tests of the behaviour every KvsEngine must have, for implementations outside
+//! this crate to check themselves against
+//!
+//! Each test opens the engine under test in a directory of its own (removed afterwards) with the
+//! function given, and panics at the first thing the engine gets wrong:
+//!
+//! ```no_run
+//! # fn main() {
+//! kvs::engine_tests::run_all(kvs::SharedKvStore::open);
+//! # }
+//! ```
+//!
+//! The tests are also public one by one, so that an engine may be checked against some of them
+//! only (an engine which cannot be reopened, say, skipping `persistence`).
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{ErrorKind, KeyEvent, KvsEngine, Result, WriteBatch};
+
+/// number of threads writing at once in `concurrent_access`
+const THREADS: usize = 8;
+
+/// number of keys each thread of `concurrent_access` sets
+const KEYS_PER_THREAD: usize = 50;
+
+/// how long a test waits for an event before failing
+const EVENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// number of directories created by this process, so that each is new
+static DIRS_CREATED: AtomicU64 = AtomicU64::new(0);
+
+/// runs every test of the suite against the engine opened (or reopened) at a path by `open`
+pub fn run_all<E, F>(open: F)
+where
+    E: KvsEngine,
+    F: Fn(&Path) -> Result<E>,
+{
+    basic_operations(&open);
+    byte_operations(&open);
+    error_semantics(&open);
+    conditional_writes(&open);
+    appends(&open);
+    batches(&open);
+    locks(&open);
+    events(&open);
+    persistence(&open);
+    compaction(&open);
+    clear(&open);
+    concurrent_access(&open);
+}
+
+/// setting, overwriting, getting and removing keys, and listing them
+pub fn basic_operations<E, F>(open: &F)
+where
+    E: KvsEngine,
+    F: Fn(&Path) -> Result<E>,
+{
+    with_engine(open, |engine| {
+        assert_eq!(engine.get("key1".to_owned()).unwrap(), None);
+        engine.set("key1".to_owned(), "value1".to_owned()).unwrap();
+        engine.set("key2".to_owned(), "value2".to_owned()).unwrap();
+        engine.set("key1".to_owned(), "value3".to_owned()).unwrap();
+        assert_eq!(
+            engine.get("key1".to_owned()).unwrap(),
+            Some("value3".to_owned())
+        );
+        assert_eq!(
+            engine
+                .get_with_max_lag("key2".to_owned(), Duration::ZERO)
+                .unwrap(),
+            Some("value2".to_owned())
+        );
+        assert_eq!(engine.key_count().unwrap(), 2);
+        assert_eq!(sorted(engine.keys().unwrap()), vec!["key1", "key2"]);
+        assert_eq!(
+            sorted(engine.pairs().unwrap()),
+            vec![
+                ("key1".to_owned(), "value3".to_owned()),
+                ("key2".to_owned(), "value2".to_owned())
+            ]
+        );
+        engine.remove("key1".to_owned()).unwrap();
+        assert_eq!(engine.get("key1".to_owned()).unwrap(), None);
+        assert_eq!(engine.key_count().unwrap(), 1);
+        // an empty key and value are keys and values like any other
+        engine.set(String::new(), String::new()).unwrap();
+        assert_eq!(engine.get(String::new()).unwrap(), Some(String::new()));
+    });
+}
+
+/// getting and setting keys and values given as UTF-8 bytes, as the String ones do
+pub fn byte_operations<E, F>(open: &F)
+where
+    E: KvsEngine,
+    F: Fn(&Path) -> Result<E>,
+{
+    with_engine(open, |engine| {
+        let mut buf = b"left over".to_vec();
+        assert!(!engine.get_bytes(b"key1", &mut buf).unwrap());
+        assert!(buf.is_empty());
+        engine.set_bytes(b"key1", "valu\u{e9}1".as_bytes()).unwrap();
+        assert_eq!(
+            engine.get("key1".to_owned()).unwrap(),
+            Some("valu\u{e9}1".to_owned())
+        );
+        engine.set("key2".to_owned(), "v".repeat(300)).unwrap();
+        assert!(engine.get_bytes(b"key2", &mut buf).unwrap());
+        assert_eq!(buf, "v".repeat(300).into_bytes());
+        assert!(engine.get_bytes(b"key1", &mut buf).unwrap());
+        assert_eq!(buf, "valu\u{e9}1".as_bytes());
+        assert!(!engine.get_bytes(b"\xff", &mut buf).unwrap());
+        assert_eq!(
+            *engine.set_bytes(b"key3", b"\xff").unwrap_err().kind(),
+            ErrorKind::DeserializationError
+        );
+        assert_eq!(engine.get("key3".to_owned()).unwrap(), None);
+    });
+}
+
+/// the errors an engine must raise
+pub fn error_semantics<E, F>(open: &F)
+where
+    E: KvsEngine,
+    F: Fn(&Path) -> Result<E>,
+{
+    with_engine(open, |engine| {
+        let err = engine.remove("key1".to_owned()).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::KeyNotPresent);
+        engine.set("key1".to_owned(), "value1".to_owned()).unwrap();
+        engine.remove("key1".to_owned()).unwrap();
+        let err = engine.remove("key1".to_owned()).unwrap_err();
+        assert_eq!(err.kind(), &ErrorKind::KeyNotPresent);
+    });
+}
+
+/// `set_if_absent` and `set_once`
+pub fn conditional_writes<E, F>(open: &F)
+where
+    E: KvsEngine,
+    F: Fn(&Path) -> Result<E>,
+{
+    with_engine(open, |engine| {
+        assert!(engine
+            .set_if_absent("key1".to_owned(), "value1".to_owned())
+            .unwrap());
+        assert!(!engine
+            .set_if_absent("key1".to_owned(), "value2".to_owned())
+            .unwrap());
+        assert_eq!(
+            engine.get("key1".to_owned()).unwrap(),
+            Some("value1".to_owned())
+        );
+
+        assert!(engine
+            .set_once("key2".to_owned(), "value1".to_owned(), 7)
+            .unwrap());
+        engine.set("key2".to_owned(), "value2".to_owned()).unwrap();
+        // a write retried with the same token is not applied again
+        assert!(!engine
+            .set_once("key2".to_owned(), "value1".to_owned(), 7)
+            .unwrap());
+        assert_eq!(
+            engine.get("key2".to_owned()).unwrap(),
+            Some("value2".to_owned())
+        );
+        assert!(engine
+            .set_once("key2".to_owned(), "value3".to_owned(), 8)
+            .unwrap());
+    });
+}
+
+/// `append` and `value_len`, the appended values being as set ones when read, compacted and
+/// reopened
+pub fn appends<E, F>(open: &F)
+where
+    E: KvsEngine,
+    F: Fn(&Path) -> Result<E>,
+{
+    let dir = TestDir::new();
+    let engine = open(dir.path()).unwrap();
+    assert_eq!(engine.value_len("key1".to_owned()).unwrap(), 0);
+    assert_eq!(
+        engine.append("key1".to_owned(), "valu".to_owned()).unwrap(),
+        4
+    );
+    assert_eq!(
+        engine
+            .append("key1".to_owned(), "\u{e9}1".to_owned())
+            .unwrap(),
+        7
+    );
+    assert_eq!(engine.value_len("key1".to_owned()).unwrap(), 7);
+    engine.set("key2".to_owned(), "value".to_owned()).unwrap();
+    engine.append("key2".to_owned(), "2".to_owned()).unwrap();
+    engine.compact().unwrap();
+    engine.append("key2".to_owned(), "2".to_owned()).unwrap();
+    engine.shutdown().unwrap();
+    drop(engine);
+
+    let engine = open(dir.path()).unwrap();
+    assert_eq!(
+        engine.get("key1".to_owned()).unwrap(),
+        Some("valu\u{e9}1".to_owned())
+    );
+    assert_eq!(
+        engine.get("key2".to_owned()).unwrap(),
+        Some("value22".to_owned())
+    );
+    engine.remove("key2".to_owned()).unwrap();
+    assert_eq!(engine.append("key2".to_owned(), "2".to_owned()).unwrap(), 1);
+    engine.shutdown().unwrap();
+}
+
+/// batches applied together, reporting which operations were applied, and read with `get_many`
+pub fn batches<E, F>(open: &F)
+where
+    E: KvsEngine,
+    F: Fn(&Path) -> Result<E>,
+{
+    with_engine(open, |engine| {
+        engine.set("key1".to_owned(), "value1".to_owned()).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.set("key2".to_owned(), "value2".to_owned());
+        batch.remove("key1".to_owned());
+        batch.remove("key3".to_owned());
+        let applied = engine.write_batch(batch).unwrap();
+        assert_eq!(applied, vec![true, true, false]);
+        assert_eq!(
+            engine
+                .get_many(vec!["key1".to_owned(), "key2".to_owned()])
+                .unwrap(),
+            vec![None, Some("value2".to_owned())]
+        );
+    });
+}
+
+/// acquiring, refreshing and releasing locks, with increasing fencing tokens
+pub fn locks<E, F>(open: &F)
+where
+    E: KvsEngine,
+    F: Fn(&Path) -> Result<E>,
+{
+    with_engine(open, |engine| {
+        let ttl = Duration::from_secs(60);
+        let lock = engine.acquire_lock("lock1".to_owned(), ttl).unwrap();
+        let lock = lock.expect("a free lock should be acquired");
+        assert!(engine
+            .acquire_lock("lock1".to_owned(), ttl)
+            .unwrap()
+            .is_none());
+        assert!(engine
+            .refresh_lock("lock1".to_owned(), lock.token, ttl)
+            .unwrap()
+            .is_some());
+        assert!(engine
+            .refresh_lock("lock1".to_owned(), lock.token + 1, ttl)
+            .unwrap()
+            .is_none());
+        assert!(!engine
+            .release_lock("lock1".to_owned(), lock.token + 1)
+            .unwrap());
+        assert!(engine.release_lock("lock1".to_owned(), lock.token).unwrap());
+        assert!(!engine.release_lock("lock1".to_owned(), lock.token).unwrap());
+        let again = engine.acquire_lock("lock1".to_owned(), ttl).unwrap();
+        let again = again.expect("a released lock should be acquired");
+        assert!(again.token > lock.token);
+    });
+}
+
+/// the events sent to subscribers of a prefix
+pub fn events<E, F>(open: &F)
+where
+    E: KvsEngine,
+    F: Fn(&Path) -> Result<E>,
+{
+    with_engine(open, |engine| {
+        let events = engine.subscribe("user:".to_owned()).unwrap();
+        engine.set("other".to_owned(), "value1".to_owned()).unwrap();
+        engine
+            .set("user:1".to_owned(), "value1".to_owned())
+            .unwrap();
+        engine.remove("user:1".to_owned()).unwrap();
+        engine.clear().unwrap();
+        let expected = [
+            KeyEvent::Set("user:1".to_owned()),
+            KeyEvent::Removed("user:1".to_owned()),
+            KeyEvent::Cleared,
+        ];
+        for event in expected {
+            assert_eq!(events.recv_timeout(EVENT_TIMEOUT).ok(), Some(event));
+        }
+    });
+}
+
+/// keys written before a clean shutdown are there when the engine is reopened
+pub fn persistence<E, F>(open: &F)
+where
+    E: KvsEngine,
+    F: Fn(&Path) -> Result<E>,
+{
+    let dir = TestDir::new();
+    {
+        let engine = open(dir.path()).unwrap();
+        for i in 0..100 {
+            engine
+                .set(format!("key{}", i), format!("value{}", i))
+                .unwrap();
+        }
+        engine.remove("key0".to_owned()).unwrap();
+        engine.shutdown().unwrap();
+    }
+    let engine = open(dir.path()).unwrap();
+    assert_eq!(engine.key_count().unwrap(), 99);
+    assert_eq!(engine.get("key0".to_owned()).unwrap(), None);
+    assert_eq!(
+        engine.get("key99".to_owned()).unwrap(),
+        Some("value99".to_owned())
+    );
+    engine.shutdown().unwrap();
+}
+
+/// compaction keeps the current value of every key, before and after reopening
+pub fn compaction<E, F>(open: &F)
+where
+    E: KvsEngine,
+    F: Fn(&Path) -> Result<E>,
+{
+    let dir = TestDir::new();
+    {
+        let engine = open(dir.path()).unwrap();
+        for round in 0..20 {
+            for i in 0..10 {
+                engine
+                    .set(format!("key{}", i), format!("value{}", round))
+                    .unwrap();
+            }
+        }
+        engine.remove("key9".to_owned()).unwrap();
+        engine.compact().unwrap();
+        assert_eq!(engine.key_count().unwrap(), 9);
+        assert_eq!(
+            engine.get("key0".to_owned()).unwrap(),
+            Some("value19".to_owned())
+        );
+        engine
+            .set("key10".to_owned(), "value10".to_owned())
+            .unwrap();
+        engine.shutdown().unwrap();
+    }
+    let engine = open(dir.path()).unwrap();
+    assert_eq!(engine.key_count().unwrap(), 10);
+    assert_eq!(engine.get("key9".to_owned()).unwrap(), None);
+    assert_eq!(
+        engine.get("key8".to_owned()).unwrap(),
+        Some("value19".to_owned())
+    );
+    assert_eq!(
+        engine.get("key10".to_owned()).unwrap(),
+        Some("value10".to_owned())
+    );
+    engine.shutdown().unwrap();
+}
+
+/// clearing removes every key
+pub fn clear<E, F>(open: &F)
+where
+    E: KvsEngine,
+    F: Fn(&Path) -> Result<E>,
+{
+    with_engine(open, |engine| {
+        for i in 0..10 {
+            engine
+                .set(format!("key{}", i), format!("value{}", i))
+                .unwrap();
+        }
+        engine.clear().unwrap();
+        assert_eq!(engine.key_count().unwrap(), 0);
+        assert!(engine.keys().unwrap().is_empty());
+        assert_eq!(engine.get("key1".to_owned()).unwrap(), None);
+        engine.set("key1".to_owned(), "value1".to_owned()).unwrap();
+        assert_eq!(engine.key_count().unwrap(), 1);
+    });
+}
+
+/// clones of the engine used from many threads at once
+pub fn concurrent_access<E, F>(open: &F)
+where
+    E: KvsEngine,
+    F: Fn(&Path) -> Result<E>,
+{
+    with_engine(open, |engine| {
+        let threads = (0..THREADS)
+            .map(|thread| {
+                let engine = engine.clone();
+                thread::spawn(move || {
+                    let mut set_contended = false;
+                    for i in 0..KEYS_PER_THREAD {
+                        let key = format!("thread{}:key{}", thread, i);
+                        engine.set(key.clone(), i.to_string()).unwrap();
+                        assert_eq!(engine.get(key).unwrap(), Some(i.to_string()));
+                        set_contended |= engine
+                            .set_if_absent("contended".to_owned(), thread.to_string())
+                            .unwrap();
+                    }
+                    set_contended
+                })
+            })
+            .collect::<Vec<_>>();
+        let winners = threads
+            .into_iter()
+            .map(|thread| {
+                thread
+                    .join()
+                    .expect("a thread writing to the engine panicked")
+            })
+            .filter(|&won| won)
+            .count();
+        assert_eq!(
+            winners, 1,
+            "exactly one set_if_absent of a key should apply"
+        );
+        assert_eq!(engine.key_count().unwrap(), THREADS * KEYS_PER_THREAD + 1);
+        for thread in 0..THREADS {
+            let key = format!("thread{}:key{}", thread, KEYS_PER_THREAD - 1);
+            assert_eq!(
+                engine.get(key).unwrap(),
+                Some((KEYS_PER_THREAD - 1).to_string())
+            );
+        }
+    });
+}
+
+/// opens the engine in a new directory, runs the test and shuts the engine down
+fn with_engine<E, F>(open: &F, test: impl FnOnce(&E))
+where
+    E: KvsEngine,
+    F: Fn(&Path) -> Result<E>,
+{
+    let dir = TestDir::new();
+    let engine = open(dir.path()).unwrap();
+    test(&engine);
+    engine.shutdown().unwrap();
+}
+
+fn sorted<T: Ord>(mut items: Vec<T>) -> Vec<T> {
+    items.sort();
+    items
+}
+
+/// new directory under the system's temporary directory, removed when dropped
+struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    fn new() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let path = env::temp_dir().join(format!(
+            "kvs-engine-tests-{}-{}-{}",
+            std::process::id(),
+            DIRS_CREATED.fetch_add(1, Ordering::Relaxed),
+            nanos
+        ));
+        fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -1,62 +1,233 @@
-use failure::{Backtrace, Fail};
-use std::{
-    fmt::{self, Display},
-    io,
-};
+use std::{error, fmt, io, path};
+
+/// underlying error which caused a kvs Error
+type Source = Box<dyn error::Error + Send + Sync + 'static>;
 
 /// kvs error type
-#[derive(Debug)]
+///
+/// Besides its ErrorKind, an Error records where it occurred (the path of the file, the offset
+/// of the record and the key concerned) when known, and keeps the error which caused it (such as
+/// an `io::Error`) as its `source()`.
+#[derive(Debug, thiserror::Error)]
+#[error("{kind}{}", Context(.path, .offset, .key))]
 pub struct Error {
-    inner: failure::Context<ErrorKind>,
+    kind: ErrorKind,
+    path: Option<path::PathBuf>,
+    offset: Option<u64>,
+    key: Option<String>,
+    #[source]
+    source: Option<Source>,
 }
 
 impl Error {
     /// create a new kvs Error of the given ErrorKind
     pub fn new(kind: ErrorKind) -> Self {
         Self {
-            inner: failure::Context::new(kind),
+            kind,
+            path: None,
+            offset: None,
+            key: None,
+            source: None,
+        }
+    }
+
+    /// create a new kvs Error of the given ErrorKind caused by the given error
+    pub(crate) fn with_source(kind: ErrorKind, source: impl Into<Source>) -> Self {
+        Self {
+            source: Some(source.into()),
+            ..Self::new(kind)
         }
     }
 
+    /// create a new kvs Error of the given ErrorKind caused by a failure described by the message
+    pub(crate) fn with_message(kind: ErrorKind, message: String) -> Self {
+        Self::with_source(kind, message)
+    }
+
+    /// record the path of the file the error occurred on (unless already known)
+    pub(crate) fn at_path(mut self, path: &path::Path) -> Self {
+        self.path.get_or_insert_with(|| path.to_owned());
+        self
+    }
+
+    /// record the offset of the record the error occurred on (unless already known)
+    pub(crate) fn at_offset(mut self, offset: u64) -> Self {
+        self.offset.get_or_insert(offset);
+        self
+    }
+
+    /// record the key the error occurred on (unless already known)
+    pub(crate) fn for_key(mut self, key: &impl fmt::Debug) -> Self {
+        self.key.get_or_insert_with(|| format!("{:?}", key));
+        self
+    }
+
     /// gets a referenced to the ErrorKind of this Error
     pub fn kind(&self) -> &ErrorKind {
-        self.inner.get_context()
+        &self.kind
+    }
+
+    /// path of the file the error occurred on, if known
+    pub fn path(&self) -> Option<&path::Path> {
+        self.path.as_deref()
+    }
+
+    /// offset in the log of the record the error occurred on, if known
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    /// key the error occurred on (formatted with `Debug`), if known
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
+    }
+}
+
+/// formats the known context of an Error as a parenthesised suffix
+struct Context<'a>(
+    &'a Option<path::PathBuf>,
+    &'a Option<u64>,
+    &'a Option<String>,
+);
+
+impl fmt::Display for Context<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Context(path, offset, key) = self;
+        let mut separator = " (";
+        if let Some(path) = path {
+            write!(f, "{}path {}", separator, path.display())?;
+            separator = ", ";
+        }
+        if let Some(offset) = offset {
+            write!(f, "{}offset {}", separator, offset)?;
+            separator = ", ";
+        }
+        if let Some(key) = key {
+            write!(f, "{}key {}", separator, key)?;
+            separator = ", ";
+        }
+        match separator {
+            ", " => f.write_str(")"),
+            _ => Ok(()),
+        }
     }
 }
 
 /// kvs error kind
-#[derive(Copy, Clone, Debug, PartialEq, Eq, failure_derive::Fail)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, thiserror::Error)]
 pub enum ErrorKind {
-    #[fail(display = "An I/O error occurred")]
+    #[error("An I/O error occurred")]
     /// raised if there is an I/O error
     IoError,
-    #[fail(display = "Key not present in database")]
+    #[error("A record could not be serialized")]
+    /// raised if a key or value cannot be encoded as a record of the log
+    SerializationError,
+    #[error("A record could not be deserialized")]
+    /// raised if a record read from the log cannot be decoded as the store's key and value types
+    DeserializationError,
+    #[error("The log is corrupt at offset {offset}")]
+    /// raised if a record of the log is malformed or does not match its checksum
+    CorruptLog {
+        /// offset of the record in the log
+        offset: u64,
+    },
+    #[error("The log holds records of a format which this build cannot read")]
+    /// raised if a record of the log is in the ASN.1 DER format of earlier versions, which is read
+    /// only with the `legacy-asn1` feature
+    UnsupportedFormat,
+    #[error("The index refers to an unreadable record at offset {offset}")]
+    /// raised if the record of the log the index refers to for a key is missing, malformed or
+    /// belongs to another key
+    IndexInconsistent {
+        /// offset of the record in the log
+        offset: u64,
+    },
+    #[error("The manifest is corrupt")]
+    /// raised if the manifest of a store cannot be parsed or names a log which does not exist
+    /// (removing the manifest makes the store open its log of the highest generation instead)
+    CorruptManifest,
+    #[error("The store is already open")]
+    /// raised if opening a store which is already open in the process (the same directory and
+    /// file prefix), as the two would write over each other's records
+    AlreadyOpen,
+    #[error("Compaction failed")]
+    /// raised if compacting the log fails (the error which caused it is the source)
+    CompactionFailed,
+    #[error("Key not present in database")]
     /// raised if key is not present on a remove
     KeyNotPresent,
-    #[fail(display = "An unknown error occurred")]
+    #[error("A quota of the store would be exceeded")]
+    /// raised if a write would take a store past the maximum number of keys or log bytes set in
+    /// its StoreOptions (nothing of the write is applied)
+    QuotaExceeded,
+    #[error("The store is busy compacting its log")]
+    /// raised if a write is held back because the log holds more stale records than the ceiling
+    /// set in the store's StoreOptions and compacting it fails (the error of which is the source)
+    Busy,
+    #[error("The write was rejected by a hook")]
+    /// raised if the before-set hook of a store rejects a write (the message it gave being the
+    /// source), nothing of the write being applied
+    Rejected,
+    #[error("The log position is not in the store's log")]
+    /// raised if reading as of a log position which is past the end of the log, or was in a log
+    /// which compaction (or clear) has since replaced
+    PositionUnavailable,
+    #[error("No merge operator is registered to apply merge operands")]
+    /// raised if merging into a key, or reading a merged key, without a merge operator registered
+    MergeOperatorMissing,
+    #[error("No secondary index exists with the given name")]
+    /// raised if looking up values by a secondary index which has not been declared
+    IndexNotFound,
+    #[error("Keyspace names may only contain ASCII letters, digits, '-' and '_'")]
+    /// raised if opening a keyspace whose name is empty or contains other characters
+    InvalidKeyspaceName,
+    #[error("This node is not the leader of its cluster")]
+    /// raised by a node of a Raft cluster which is asked to write (or to read) while it is not the
+    /// leader (the leader's id being in the source, if known)
+    NotLeader,
+    #[error("A malformed protocol message was received")]
+    /// raised if a message received over the network cannot be understood
+    ProtocolError,
+    #[error("The server failed to process the request")]
+    /// raised by a client if the server reports an error processing a request
+    ServerError,
+    #[error("The request must be sent to another node of the cluster")]
+    /// raised by a client if the server redirects a request to another node of its cluster (the
+    /// address of which is the source)
+    Moved,
+    #[error("The request timed out")]
+    /// raised by a client if a request waits on the server for longer than its timeout, or is made
+    /// once its deadline has passed
+    Timeout,
+    #[error("The migration of keys to another node failed")]
+    /// raised if a migration of a range of keys to another node of a cluster fails, or is cut over
+    /// before it is streaming (the failure being described by the source)
+    MigrationFailed,
+    #[error("Authentication failed")]
+    /// raised by a client if the password is wrong or the server requires authentication first
+    AuthenticationFailed,
+    #[error("TLS could not be configured or negotiated")]
+    /// raised if a TLS configuration cannot be built or a TLS handshake fails
+    TlsError,
+    #[error("Invalid configuration")]
+    /// raised if the options given when creating something are invalid
+    InvalidConfiguration,
+    #[error("An unknown error occurred")]
     /// raised for any other error
     UnknownError,
 }
 
-impl Fail for Error {
-    fn cause(&self) -> Option<&dyn Fail> {
-        self.inner.cause()
-    }
-    fn backtrace(&self) -> Option<&Backtrace> {
-        self.inner.backtrace()
-    }
-}
-
-impl Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        Display::fmt(&self.inner, f)
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self::with_source(ErrorKind::IoError, error)
     }
 }
 
-impl From<io::Error> for Error {
-    fn from(_: io::Error) -> Self {
-        Self {
-            inner: failure::Context::new(ErrorKind::IoError),
+impl From<kvs_proto_serde::Error> for Error {
+    fn from(error: kvs_proto_serde::Error) -> Self {
+        match error.kind {
+            kvs_proto_serde::ErrorKind::IoError(_) => Self::with_source(ErrorKind::IoError, error),
+            _ => Self::with_source(ErrorKind::ProtocolError, error),
         }
     }
 }
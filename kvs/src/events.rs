@@ -0,0 +1,104 @@
+use std::{fmt, path, sync::mpsc, time::Duration};
+
+/// Operational event of a KvStore, sent to each subscriber of its events (`subscribe_events`)
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoreEvent {
+    /// compaction of the log started, the log holding the number of stale records
+    CompactionStarted {
+        /// stale records (written over or removed) the compaction drops
+        stale_records: u64,
+    },
+    /// compaction of the log finished, or failed (leaving the log as it was)
+    CompactionFinished {
+        /// how long the compaction took
+        duration: Duration,
+        /// false if the compaction failed
+        succeeded: bool,
+    },
+    /// a write was held back to compact the log first, as the log held more stale records per key
+    /// than the store's `max_stale_ratio`
+    WriteStalled {
+        /// stale records per key of the log
+        stale_ratio: f64,
+    },
+    /// the store moved on to a new log (of the next generation), by compacting or clearing its log
+    SegmentRotated {
+        /// path of the new log
+        log: path::PathBuf,
+    },
+    /// the index was rebuilt by replaying the log, as when the store is opened without having
+    /// been shut down cleanly (or by `rebuild_index`)
+    RecoveryPerformed {
+        /// records of the log replayed
+        records: u64,
+        /// how long replaying them took
+        duration: Duration,
+    },
+}
+
+impl fmt::Display for StoreEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CompactionStarted { stale_records } => {
+                write!(f, "compaction started ({} stale records)", stale_records)
+            }
+            Self::CompactionFinished {
+                duration,
+                succeeded: true,
+            } => write!(f, "compaction finished in {}ms", duration.as_millis()),
+            Self::CompactionFinished { duration, .. } => {
+                write!(f, "compaction failed after {}ms", duration.as_millis())
+            }
+            Self::WriteStalled { stale_ratio } => write!(
+                f,
+                "write stalled to compact the log ({:.1} stale records per key)",
+                stale_ratio
+            ),
+            Self::SegmentRotated { log } => write!(f, "log rotated to {}", log.display()),
+            Self::RecoveryPerformed { records, duration } => write!(
+                f,
+                "recovered by replaying {} records in {}ms",
+                records,
+                duration.as_millis()
+            ),
+        }
+    }
+}
+
+/// Subscribers to the StoreEvents of a KvStore
+pub(crate) struct EventSubscribers {
+    subscribers: Vec<mpsc::Sender<StoreEvent>>,
+    /// recovery performed when the store was opened (before any subscriber could subscribe),
+    /// sent to each subscriber first
+    recovery: Option<StoreEvent>,
+}
+
+impl EventSubscribers {
+    pub(crate) fn new() -> Self {
+        Self {
+            subscribers: Vec::new(),
+            recovery: None,
+        }
+    }
+    pub(crate) fn subscribe(&mut self) -> mpsc::Receiver<StoreEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribe_with(sender);
+        receiver
+    }
+    /// subscribes the sender (which may be subscribed to other stores too)
+    pub(crate) fn subscribe_with(&mut self, sender: mpsc::Sender<StoreEvent>) {
+        if let Some(recovery) = &self.recovery {
+            let _ = sender.send(recovery.clone());
+        }
+        self.subscribers.push(sender);
+    }
+    /// records the recovery performed when the store was opened
+    pub(crate) fn recovered_on_open(&mut self, recovery: StoreEvent) {
+        self.recovery = Some(recovery);
+    }
+    /// sends the event to every subscriber, forgetting those which have dropped their receiver
+    pub(crate) fn notify(&mut self, event: StoreEvent) {
+        self.subscribers
+            .retain(|sender| sender.send(event.clone()).is_ok());
+    }
+}
@@ -0,0 +1,100 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash,
+};
+
+/// What a KvStore does once it holds the maximum number of keys set in its StoreOptions and a
+/// key it does not hold is set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvictionPolicy {
+    /// evict nothing: the write fails with QuotaExceeded
+    #[default]
+    Reject,
+    /// evict the key read or written least recently, as a cache would
+    LeastRecentlyUsed,
+    /// evict the key read or written least often (least recently of those used as often)
+    LeastFrequentlyUsed,
+}
+
+/// Uses of the keys of a store which evicts them, ranking the keys in the order in which they
+/// are to be evicted
+///
+/// Uses are held in memory only: on open, keys are ranked as if used in the order their records
+/// were written.
+pub(crate) struct Usage<K> {
+    policy: EvictionPolicy,
+    clock: u64,
+    uses: HashMap<K, Use>,
+    ranked: BTreeMap<(u64, u64), K>,
+}
+
+struct Use {
+    count: u64,
+    last: u64,
+}
+
+impl<K> Usage<K>
+where
+    K: Eq + hash::Hash + Clone,
+{
+    pub(crate) fn new(policy: EvictionPolicy) -> Self {
+        Self {
+            policy,
+            clock: 0,
+            uses: HashMap::new(),
+            ranked: BTreeMap::new(),
+        }
+    }
+    pub(crate) fn policy(&self) -> EvictionPolicy {
+        self.policy
+    }
+    /// record a read or write of the key
+    pub(crate) fn record(&mut self, key: &K) {
+        self.clock += 1;
+        let clock = self.clock;
+        let (old_rank, new_rank) = match self.uses.get_mut(key) {
+            Some(key_use) => {
+                let old_rank = rank(self.policy, key_use);
+                key_use.count += 1;
+                key_use.last = clock;
+                (Some(old_rank), rank(self.policy, key_use))
+            }
+            None => {
+                let key_use = Use {
+                    count: 1,
+                    last: clock,
+                };
+                let new_rank = rank(self.policy, &key_use);
+                self.uses.insert(key.clone(), key_use);
+                (None, new_rank)
+            }
+        };
+        if let Some(old_rank) = old_rank {
+            self.ranked.remove(&old_rank);
+        }
+        self.ranked.insert(new_rank, key.clone());
+    }
+    /// forget the uses of a key no longer held
+    pub(crate) fn forget(&mut self, key: &K) {
+        if let Some(key_use) = self.uses.remove(key) {
+            self.ranked.remove(&rank(self.policy, &key_use));
+        }
+    }
+    /// forget the uses of every key
+    pub(crate) fn clear(&mut self) {
+        self.uses.clear();
+        self.ranked.clear();
+    }
+    /// the key to be evicted first of those not spared, None if all are spared
+    pub(crate) fn victim(&self, spared: impl Fn(&K) -> bool) -> Option<K> {
+        self.ranked.values().find(|key| !spared(key)).cloned()
+    }
+}
+
+/// rank of a key (lowest evicted first), unique as no two uses happen at the same clock
+fn rank(policy: EvictionPolicy, key_use: &Use) -> (u64, u64) {
+    match policy {
+        EvictionPolicy::LeastFrequentlyUsed => (key_use.count, key_use.last),
+        EvictionPolicy::Reject | EvictionPolicy::LeastRecentlyUsed => (key_use.last, 0),
+    }
+}
@@ -0,0 +1,82 @@
+//! Entry points of the fuzz targets of `fuzz/` (built with the `fuzzing` feature), each feeding
+//! arbitrary bytes to a part of the server which reads them from the network
+//!
+//! Malformed input must be refused with an error: none of them may panic, nor allocate more than
+//! the input warrants (as by trusting a length it gives). With cargo-fuzz installed, they are run
+//! from the `fuzz` directory of the crate:
+//!
+//! ```text
+//! cargo +nightly fuzz run connection -- -rss_limit_mb=256
+//! ```
+
+use std::{
+    env, fs, io,
+    net::{IpAddr, Ipv4Addr},
+    sync::OnceLock,
+};
+
+use super::{protocol, resp, KvsServer, LogLevel, Request, Response, SharedKvStore};
+
+/// reads every RESP command of the input (until the first malformed one)
+pub fn resp_commands(data: &[u8]) {
+    let reader = &mut io::BufReader::new(data);
+    while let Ok(Some(_)) = resp::read_command(reader, resp::DEFAULT_MAX_BULK_LEN) {}
+}
+
+/// reads every kvs-proto request of the input, and then every response (as a client does)
+pub fn kvs_proto_messages(data: &[u8]) {
+    let reader = &mut io::BufReader::new(data);
+    while let Ok(Some(_)) = protocol::read_message::<_, Request>(reader) {}
+    let reader = &mut io::BufReader::new(data);
+    while let Ok(Some(_)) = protocol::read_message::<_, Response>(reader) {}
+}
+
+/// serves a connection of a client sending the input, to a server (of two databases, each a
+/// store in a temporary directory) shared by every call, discarding what it responds
+pub fn connection(data: &[u8]) {
+    let stream = FuzzStream {
+        input: data,
+        output: io::sink(),
+    };
+    let _ = server().serve_stream(stream, IpAddr::V4(Ipv4Addr::LOCALHOST));
+}
+
+fn server() -> &'static KvsServer<SharedKvStore> {
+    static SERVER: OnceLock<KvsServer<SharedKvStore>> = OnceLock::new();
+    SERVER.get_or_init(|| {
+        let path = env::temp_dir().join(format!("kvs-fuzz-{}", std::process::id()));
+        let open = |db: &str| {
+            let path = path.join(db);
+            fs::create_dir_all(&path).expect("unable to create the directory of a store");
+            SharedKvStore::open(&path).expect("unable to open a store of the server")
+        };
+        let server = KvsServer::new(open("0"))
+            .with_database(open("1"))
+            .with_log_level(LogLevel::Off);
+        // a subscribing connection streams events until the server shuts down, which it is
+        // asked to straight away so that the connection ends
+        server.shutdown_handle().shutdown();
+        server
+    })
+}
+
+/// stream of a connection, reading the input and writing to the output
+struct FuzzStream<'a, W> {
+    input: &'a [u8],
+    output: W,
+}
+
+impl<W> io::Read for FuzzStream<'_, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl<W: io::Write> io::Write for FuzzStream<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
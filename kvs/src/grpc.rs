@@ -0,0 +1,197 @@
+use std::{net, pin::Pin, sync::mpsc, thread, time::Duration};
+
+use tokio::sync::mpsc as async_mpsc;
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tonic::{transport::server::TcpIncoming, Request, Response, Status};
+
+use super::{Error, ErrorKind, KeyEvent, KvsEngine, Result};
+
+/// messages and service of the gRPC API, generated from `proto/kvs.proto`, including the client
+/// (`kvs_client::KvsClient`, created from a tonic Channel)
+#[allow(missing_docs, clippy::all)]
+pub mod proto {
+    tonic::include_proto!("kvs");
+}
+
+use proto::kvs_server::{Kvs, KvsServer};
+
+/// how often a Watch whose prefix has had no events checks whether it has been cancelled
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// number of events buffered for a Watch which is not keeping up with them
+const WATCH_BUFFER: usize = 64;
+
+/// gRPC server of a KvsEngine, serving the `kvs.Kvs` service of `proto/kvs.proto` (Get, Set,
+/// Remove, and streaming Scan and Watch) as an alternative to the TCP protocols of KvsServer
+///
+/// Errors are reported with gRPC status codes: NOT_FOUND for the removal of a key which is not
+/// present, INVALID_ARGUMENT for a write rejected by a hook of the store, RESOURCE_EXHAUSTED for
+/// one which would exceed a quota of the store and INTERNAL for anything else.
+///
+/// # Example
+/// ```no_run
+/// use kvs::{GrpcServer, SharedKvStore};
+///
+/// let engine = SharedKvStore::open(std::path::Path::new("testdb")).unwrap();
+/// GrpcServer::new(engine).run("127.0.0.1:50051").unwrap();
+/// ```
+pub struct GrpcServer<E: KvsEngine> {
+    engine: E,
+}
+
+impl<E: KvsEngine + Sync> GrpcServer<E> {
+    /// create a new server which serves requests from the given engine
+    pub fn new(engine: E) -> Self {
+        Self { engine }
+    }
+    /// bind to the given address and serve requests until an error occurs accepting connections
+    pub fn run<A: net::ToSocketAddrs>(self, addr: A) -> Result<()> {
+        self.serve(net::TcpListener::bind(addr)?)
+    }
+    /// serve requests from an already bound listener upon a runtime of its own
+    pub fn serve(self, listener: net::TcpListener) -> Result<()> {
+        listener.set_nonblocking(true)?;
+        let runtime = tokio::runtime::Runtime::new()?;
+        runtime.block_on(async move {
+            let listener = tokio::net::TcpListener::from_std(listener)?;
+            let incoming = TcpIncoming::from_listener(listener, true, None)
+                .map_err(|err| Error::with_source(ErrorKind::IoError, err))?;
+            tonic::transport::Server::builder()
+                .add_service(KvsServer::new(Service {
+                    engine: self.engine,
+                }))
+                .serve_with_incoming(incoming)
+                .await
+                .map_err(|err| Error::with_source(ErrorKind::IoError, err))
+        })
+    }
+}
+
+/// the `kvs.Kvs` service, executing requests upon the blocking threads of the runtime
+struct Service<E> {
+    engine: E,
+}
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = std::result::Result<T, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl<E: KvsEngine + Sync> Kvs for Service<E> {
+    async fn get(
+        &self,
+        request: Request<proto::GetRequest>,
+    ) -> std::result::Result<Response<proto::GetResponse>, Status> {
+        let proto::GetRequest { key } = request.into_inner();
+        let value = self.blocking(move |engine| engine.get(key)).await?;
+        Ok(Response::new(proto::GetResponse { value }))
+    }
+    async fn set(
+        &self,
+        request: Request<proto::SetRequest>,
+    ) -> std::result::Result<Response<proto::SetResponse>, Status> {
+        let proto::SetRequest { key, value } = request.into_inner();
+        self.blocking(move |engine| engine.set(key, value)).await?;
+        Ok(Response::new(proto::SetResponse {}))
+    }
+    async fn remove(
+        &self,
+        request: Request<proto::RemoveRequest>,
+    ) -> std::result::Result<Response<proto::RemoveResponse>, Status> {
+        let proto::RemoveRequest { key } = request.into_inner();
+        self.blocking(move |engine| engine.remove(key)).await?;
+        Ok(Response::new(proto::RemoveResponse {}))
+    }
+
+    type ScanStream = ResponseStream<proto::KeyValue>;
+
+    async fn scan(
+        &self,
+        request: Request<proto::ScanRequest>,
+    ) -> std::result::Result<Response<Self::ScanStream>, Status> {
+        let proto::ScanRequest { prefix } = request.into_inner();
+        let found = self
+            .blocking(move |engine| {
+                let mut keys = engine.keys()?;
+                keys.retain(|key| key.starts_with(&prefix));
+                keys.sort_unstable();
+                let mut found = Vec::with_capacity(keys.len());
+                for key in keys {
+                    // keys removed since they were listed are skipped
+                    if let Some(value) = engine.get(key.clone())? {
+                        found.push(Ok(proto::KeyValue { key, value }));
+                    }
+                }
+                Ok(found)
+            })
+            .await?;
+        Ok(Response::new(Box::pin(tokio_stream::iter(found))))
+    }
+
+    type WatchStream = ResponseStream<proto::KeyEvent>;
+
+    async fn watch(
+        &self,
+        request: Request<proto::WatchRequest>,
+    ) -> std::result::Result<Response<Self::WatchStream>, Status> {
+        let proto::WatchRequest { prefix } = request.into_inner();
+        let events = self
+            .blocking(move |engine| engine.subscribe(prefix))
+            .await?;
+        let (sender, receiver) = async_mpsc::channel(WATCH_BUFFER);
+        thread::spawn(move || forward_events(events, sender));
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+}
+
+impl<E: KvsEngine> Service<E> {
+    /// runs the operation on the engine upon a blocking thread of the runtime
+    async fn blocking<T, F>(&self, operation: F) -> std::result::Result<T, Status>
+    where
+        T: Send + 'static,
+        F: FnOnce(&E) -> Result<T> + Send + 'static,
+    {
+        let engine = self.engine.clone();
+        match tokio::task::spawn_blocking(move || operation(&engine)).await {
+            Ok(result) => result.map_err(status),
+            Err(err) => Err(Status::internal(err.to_string())),
+        }
+    }
+}
+
+/// sends each event of the subscription to the stream of a Watch until it is cancelled
+fn forward_events(
+    events: mpsc::Receiver<KeyEvent<String>>,
+    sender: async_mpsc::Sender<std::result::Result<proto::KeyEvent, Status>>,
+) {
+    use proto::key_event::Kind;
+    loop {
+        let event = match events.recv_timeout(CANCEL_POLL_INTERVAL) {
+            Ok(event) => event,
+            Err(mpsc::RecvTimeoutError::Timeout) if !sender.is_closed() => continue,
+            Err(_) => return,
+        };
+        let (kind, key) = match event {
+            KeyEvent::Set(key) => (Kind::Set, key),
+            KeyEvent::Removed(key) => (Kind::Removed, key),
+            KeyEvent::Evicted(key) => (Kind::Evicted, key),
+            KeyEvent::Cleared => (Kind::Cleared, String::new()),
+        };
+        let event = proto::KeyEvent {
+            kind: kind.into(),
+            key,
+        };
+        if sender.blocking_send(Ok(event)).is_err() {
+            return;
+        }
+    }
+}
+
+fn status(err: Error) -> Status {
+    let message = err.to_string();
+    match err.kind() {
+        ErrorKind::KeyNotPresent => Status::not_found(message),
+        ErrorKind::Rejected => Status::invalid_argument(message),
+        ErrorKind::QuotaExceeded => Status::resource_exhausted(message),
+        ErrorKind::Busy => Status::unavailable(message),
+        _ => Status::internal(message),
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    collections::hash_map::{DefaultHasher, RandomState},
+    convert::TryInto,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    sync::Arc,
+};
+
+/// multiplier of FxHash (that of rustc), a truncation of the golden ratio
+const FX_SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+/// Hasher of the keys of the index of a KvStore, set through `StoreOptions::index_hasher`
+///
+/// The default is SipHash keyed at random (as HashMap's own), which resists clients flooding the
+/// index with keys crafted to collide, so it is what a server exposed to untrusted clients should
+/// keep. Where the keys are trusted, the much cheaper FxHash (`fx`) or any other BuildHasher (such
+/// as aHash's, through `custom`) may be used instead.
+///
+/// # Example
+/// ```
+/// use kvs::{IndexHasher, KvStore, StoreOptions};
+///
+/// let options = StoreOptions {
+///     index_hasher: IndexHasher::fx(),
+///     ..StoreOptions::default()
+/// };
+/// let dir = tempfile::TempDir::new().unwrap();
+/// let mut store = KvStore::<String,String>::open_with_options(dir.path(), &options).unwrap();
+/// store.set("key1".into(),"value1".into()).unwrap();
+/// assert_eq!(store.get("key1".into()).unwrap(), Some("value1".into()));
+/// ```
+#[derive(Clone)]
+pub struct IndexHasher {
+    kind: HasherKind,
+}
+
+#[derive(Clone)]
+enum HasherKind {
+    Sip(RandomState),
+    Fx,
+    Custom(Arc<dyn Fn() -> Box<dyn Hasher> + Send + Sync>),
+}
+
+impl IndexHasher {
+    /// SipHash keyed at random, the default
+    pub fn sip() -> Self {
+        Self {
+            kind: HasherKind::Sip(RandomState::new()),
+        }
+    }
+    /// FxHash, which is fast for short keys but open to keys crafted to collide
+    pub fn fx() -> Self {
+        Self {
+            kind: HasherKind::Fx,
+        }
+    }
+    /// the hasher built by the BuildHasher (each hash of which is boxed, so that the store need
+    /// not be generic over it)
+    ///
+    /// Boxing allocates for each key hashed, which makes a lookup of the index with the SipHash of
+    /// `sip` take some 60ns rather than 35ns (as `cargo bench --bench index_hasher` measures), so
+    /// a custom hasher is only worth it if it saves more than that over SipHash.
+    pub fn custom<B>(build: B) -> Self
+    where
+        B: BuildHasher + Send + Sync + 'static,
+        B::Hasher: 'static,
+    {
+        Self {
+            kind: HasherKind::Custom(Arc::new(move || Box::new(build.build_hasher()))),
+        }
+    }
+}
+
+impl Default for IndexHasher {
+    fn default() -> Self {
+        Self::sip()
+    }
+}
+
+impl fmt::Debug for IndexHasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self.kind {
+            HasherKind::Sip(_) => "IndexHasher::Sip",
+            HasherKind::Fx => "IndexHasher::Fx",
+            HasherKind::Custom(_) => "IndexHasher::Custom",
+        })
+    }
+}
+
+impl BuildHasher for IndexHasher {
+    type Hasher = IndexHash;
+
+    fn build_hasher(&self) -> IndexHash {
+        IndexHash {
+            state: match &self.kind {
+                HasherKind::Sip(random) => HashState::Sip(random.build_hasher()),
+                HasherKind::Fx => HashState::Fx(0),
+                HasherKind::Custom(build) => HashState::Custom(build()),
+            },
+        }
+    }
+}
+
+/// Hash of a key of the index of a KvStore, as its IndexHasher computes it
+pub struct IndexHash {
+    state: HashState,
+}
+
+enum HashState {
+    Sip(DefaultHasher),
+    Fx(u64),
+    Custom(Box<dyn Hasher>),
+}
+
+impl IndexHash {
+    fn add_fx_word(hash: &mut u64, word: u64) {
+        *hash = (hash.rotate_left(5) ^ word).wrapping_mul(FX_SEED);
+    }
+}
+
+impl Hasher for IndexHash {
+    fn write(&mut self, bytes: &[u8]) {
+        let hash = match &mut self.state {
+            HashState::Sip(hasher) => return hasher.write(bytes),
+            HashState::Custom(hasher) => return hasher.write(bytes),
+            HashState::Fx(hash) => hash,
+        };
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            Self::add_fx_word(hash, u64::from_le_bytes(word.try_into().unwrap()));
+        }
+        let rest = words.remainder();
+        if !rest.is_empty() {
+            let mut word = [0; 8];
+            word[..rest.len()].copy_from_slice(rest);
+            Self::add_fx_word(hash, u64::from_le_bytes(word));
+        }
+    }
+    fn write_u8(&mut self, n: u8) {
+        self.write_u64(u64::from(n));
+    }
+    fn write_u32(&mut self, n: u32) {
+        self.write_u64(u64::from(n));
+    }
+    fn write_u64(&mut self, n: u64) {
+        match &mut self.state {
+            HashState::Sip(hasher) => hasher.write_u64(n),
+            HashState::Fx(hash) => Self::add_fx_word(hash, n),
+            HashState::Custom(hasher) => hasher.write_u64(n),
+        }
+    }
+    fn write_usize(&mut self, n: usize) {
+        self.write_u64(n as u64);
+    }
+    fn finish(&self) -> u64 {
+        match &self.state {
+            HashState::Sip(hasher) => hasher.finish(),
+            HashState::Fx(hash) => *hash,
+            HashState::Custom(hasher) => hasher.finish(),
+        }
+    }
+}
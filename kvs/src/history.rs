@@ -0,0 +1,182 @@
+use std::{
+    fmt, hash,
+    io::{self, Seek},
+    marker,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    db_file_generation, file_prefix_of, read_record_from, resolve_value, writer_position, Error,
+    ErrorKind, KvStore, LogReader, Record, Result, Version,
+};
+
+/// Position in the log of a KvStore, from `current_position`, as of which values may be read
+/// back with `get_as_of` until the log is compacted (or cleared)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LogPosition {
+    generation: u128,
+    offset: u64,
+}
+
+impl LogPosition {
+    /// offset in the log (the length it had when the position was captured)
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+/// Write read back from the log of a KvStore by `tail`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change<K, V> {
+    /// position just after the write, from which to tail the writes which followed it
+    pub position: LogPosition,
+    /// key written
+    pub key: K,
+    /// value set, or operand merged, None if the key was removed
+    pub value: Option<V>,
+    /// the value is an operand merged into the key's value rather than its new value
+    pub merge: bool,
+    /// version of the write
+    pub version: Version,
+}
+
+/// Iterator over the writes to a KvStore since a log position, from `tail`
+pub struct Tail<'a, K, V> {
+    reader: &'a mut LogReader,
+    generation: u128,
+    offset: u64,
+    end: u64,
+    phantom: marker::PhantomData<(K, V)>,
+}
+
+impl<K, V> Iterator for Tail<'_, K, V>
+where
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    type Item = Result<Change<K, V>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.end {
+            return None;
+        }
+        let mut offset = self.offset;
+        let rec = self
+            .reader
+            .seek(io::SeekFrom::Start(self.offset))
+            .map_err(Error::from)
+            .and_then(|_| read_record_from::<_, K, V>(self.reader, &mut offset));
+        let rec = match rec {
+            Ok(Some(rec)) if rec.db_key == self.offset => rec,
+            Err(err) => return Some(Err(err.at_offset(self.offset))),
+            _ => {
+                return Some(Err(Error::new(ErrorKind::CorruptLog {
+                    offset: self.offset,
+                })
+                .at_offset(self.offset)))
+            }
+        };
+        self.offset = offset;
+        Some(Ok(Change {
+            position: LogPosition {
+                generation: self.generation,
+                offset,
+            },
+            version: Version::of(&rec),
+            key: rec.key,
+            value: rec.value,
+            merge: rec.merge,
+        }))
+    }
+}
+
+impl<K, V> KvStore<K, V>
+where
+    K: Serialize + DeserializeOwned + Eq + PartialEq + hash::Hash + Clone + fmt::Debug,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    /// position of the end of the log, after every write so far
+    pub fn current_position(&mut self) -> Result<LogPosition> {
+        self.flush_log()?;
+        Ok(LogPosition {
+            generation: db_file_generation(
+                &self.file_path,
+                &file_prefix_of(&self.file_path),
+                "log",
+            )
+            .unwrap_or_default(),
+            offset: writer_position(&mut self.writer)?,
+        })
+    }
+    /// get the value the key had as of the position (ignoring every write since), reading the log
+    /// up to it, PositionUnavailable if the position is no longer in the log
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// store.set("key1".into(),"value1".into()).unwrap();
+    /// let position = store.current_position().unwrap();
+    /// store.set("key1".into(),"value2".into()).unwrap();
+    /// assert_eq!(store.get_as_of("key1".into(), position).unwrap(), Some("value1".into()));
+    /// ```
+    pub fn get_as_of(&mut self, key: K, position: LogPosition) -> Result<Option<V>> {
+        self.check_position(position)?;
+        self.reader.seek(io::SeekFrom::Start(0))?;
+        let (mut latest, mut offset) = (None, 0);
+        while offset < position.offset {
+            match read_record_from::<_, K, V>(&mut self.reader, &mut offset)? {
+                Some(rec) if rec.key == key => latest = Some(rec),
+                Some(_) => (),
+                None => break,
+            }
+        }
+        match latest {
+            Some(Record {
+                db_key,
+                value: Some(_),
+                ..
+            }) => resolve_value(&mut self.reader, self.merge_operator.as_ref(), &key, db_key),
+            _ => Ok(None),
+        }
+    }
+    /// iterate over the writes made since the position, oldest first, up to those made before
+    /// `tail` was called, PositionUnavailable if the position is no longer in the log
+    ///
+    /// Consumers of the store's changes resume from the position of the last change they read.
+    /// Once the log is compacted (or cleared), positions in the log it replaced are unavailable
+    /// and consumers must start over from the store's current contents.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// let position = store.current_position().unwrap();
+    /// store.set("key1".into(),"value1".into()).unwrap();
+    /// store.remove("key1".into()).unwrap();
+    /// let changes = store.tail(position).unwrap().collect::<kvs::Result<Vec<_>>>().unwrap();
+    /// assert_eq!(changes[0].value, Some("value1".into()));
+    /// assert_eq!(changes[1].value, None);
+    /// ```
+    pub fn tail(&mut self, from: LogPosition) -> Result<Tail<'_, K, V>> {
+        let current = self.check_position(from)?;
+        Ok(Tail {
+            reader: &mut self.reader,
+            generation: from.generation,
+            offset: from.offset,
+            end: current.offset,
+            phantom: marker::PhantomData,
+        })
+    }
+    /// the current position, PositionUnavailable unless the position is in the current log
+    fn check_position(&mut self, position: LogPosition) -> Result<LogPosition> {
+        let current = self.current_position()?;
+        if position.generation != current.generation || position.offset > current.offset {
+            return Err(Error::new(ErrorKind::PositionUnavailable).at_path(&self.file_path));
+        }
+        Ok(current)
+    }
+}
@@ -0,0 +1,119 @@
+use std::{fmt, hash};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{Error, ErrorKind, KvStore, Result};
+
+/// Hook run before a KvStore sets a key: given the key and the value, returns the value to store
+/// (the value given or a transformation of it), or a message describing why the write is rejected
+pub type BeforeSetHook<K, V> = Box<dyn Fn(&K, V) -> std::result::Result<V, String> + Send>;
+
+/// Hook run after a KvStore removes (or evicts) a key, given the key
+pub type KeyHook<K> = Box<dyn Fn(&K) + Send>;
+
+/// Hooks registered with a KvStore
+pub(crate) struct Hooks<K, V> {
+    pub(crate) before_set: Option<BeforeSetHook<K, V>>,
+    pub(crate) after_remove: Option<KeyHook<K>>,
+    pub(crate) on_evict: Option<KeyHook<K>>,
+}
+
+impl<K, V> Hooks<K, V> {
+    pub(crate) fn new() -> Self {
+        Self {
+            before_set: None,
+            after_remove: None,
+            on_evict: None,
+        }
+    }
+}
+
+impl<K, V> KvStore<K, V>
+where
+    K: Serialize + DeserializeOwned + Eq + PartialEq + hash::Hash + Clone + fmt::Debug,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    /// register the hook run before each key is set (by `set`, `set_if_absent`, `set_if_version`
+    /// and `write_batch`) or merged into, which may transform the value stored or reject the write
+    ///
+    /// A rejected write fails with Rejected, nothing of it (or of the batch it is part of) being
+    /// applied. The hook is given the operand of a `merge`, not the value it merges into, which is
+    /// not read. Like the merge operator, hooks must be registered again each time the store is
+    /// opened.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::{ErrorKind, KvStore};
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// store.set_before_set_hook(|_key, value: String| match value.is_empty() {
+    ///     true => Err("values may not be empty".into()),
+    ///     false => Ok(value.to_uppercase()),
+    /// });
+    /// store.set("key1".into(),"value1".into()).unwrap();
+    /// assert_eq!(store.get("key1".into()).unwrap(), Some("VALUE1".into()));
+    /// let err = store.set("key2".into(),"".into()).unwrap_err();
+    /// assert_eq!(*err.kind(), ErrorKind::Rejected);
+    /// ```
+    pub fn set_before_set_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&K, V) -> std::result::Result<V, String> + Send + 'static,
+    {
+        self.hooks.before_set = Some(Box::new(hook));
+    }
+    /// register the hook run after each key is removed (by `remove`, `release_lock` and
+    /// `write_batch`, but not `clear`)
+    pub fn set_after_remove_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&K) + Send + 'static,
+    {
+        self.hooks.after_remove = Some(Box::new(hook));
+    }
+    /// register the hook run after each key is evicted to keep the store within its maximum
+    /// number of keys (the keys of a store with an EvictionPolicy expiring as a cache's do)
+    pub fn set_on_evict_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&K) + Send + 'static,
+    {
+        self.hooks.on_evict = Some(Box::new(hook));
+    }
+    /// the value the before-set hook (if any) transforms the value of the key into, Rejected if
+    /// it rejects the write
+    pub(crate) fn before_set(&self, key: &K, value: V) -> Result<V> {
+        match &self.hooks.before_set {
+            Some(hook) => hook(key, value)
+                .map_err(|message| Error::with_message(ErrorKind::Rejected, message).for_key(key)),
+            None => Ok(value),
+        }
+    }
+    /// the operations of a batch with the values set transformed by the before-set hook (if
+    /// any), Rejected if it rejects any of them
+    pub(crate) fn before_set_all(
+        &self,
+        operations: Vec<(K, Option<V>)>,
+    ) -> Result<Vec<(K, Option<V>)>> {
+        if self.hooks.before_set.is_none() {
+            return Ok(operations);
+        }
+        operations
+            .into_iter()
+            .map(|(key, value)| match value {
+                Some(value) => {
+                    let value = self.before_set(&key, value)?;
+                    Ok((key, Some(value)))
+                }
+                None => Ok((key, None)),
+            })
+            .collect()
+    }
+    /// runs the after-remove hook (or the on-evict hook if the key was evicted)
+    pub(crate) fn after_remove(&self, key: &K, evicted: bool) {
+        let hook = match evicted {
+            true => &self.hooks.on_evict,
+            false => &self.hooks.after_remove,
+        };
+        if let Some(hook) = hook {
+            hook(key);
+        }
+    }
+}
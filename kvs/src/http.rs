@@ -0,0 +1,267 @@
+use std::{io, net, sync::mpsc, thread, time::Duration};
+
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, StatusCode};
+use tungstenite::{protocol::Role, Message, WebSocket};
+
+use super::{Error, ErrorKind, KeyEvent, KvsEngine, Result};
+
+/// prefix of the paths of keys
+const KEYS_PATH: &str = "/keys/";
+
+/// prefix of the paths of the WebSocket streams of key events
+const WATCH_PATH: &str = "/watch/";
+
+/// how often a WebSocket whose prefix has had no events is pinged, to find out if it was closed
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// HTTP front end of a KvsEngine, so that the store may be used with curl or from a web app
+///
+/// Keys are reached at `/keys/{key}` (the key being percent-encoded): `GET` returns the value as
+/// text (404 if the key is not present), `PUT` sets the key to the body of the request (which must
+/// be UTF-8) and `DELETE` removes it (404 if it is not present). `GET /stats` returns the number of
+/// keys and, with the `stats` feature, the latencies of the store's operations and the sizes of
+/// the keys and values it has written as JSON.
+///
+/// A WebSocket opened at `/watch/{prefix}` (the prefix being percent-encoded, and empty for every
+/// key) is sent a text message for each event of the keys starting with the prefix until it is
+/// closed, as JSON: `{"event": "set", "key": ...}` (or `"removed"` or `"evicted"`), or
+/// `{"event": "cleared"}` when every key is removed.
+///
+/// A write rejected by a hook of the store fails with 422 and one which would exceed a quota of the
+/// store with 507; the body of an error response describes the error.
+///
+/// # Example
+/// ```no_run
+/// use kvs::{HttpGateway, SharedKvStore};
+///
+/// let engine = SharedKvStore::open(std::path::Path::new("testdb")).unwrap();
+/// HttpGateway::new(engine).run("127.0.0.1:8080").unwrap();
+/// ```
+pub struct HttpGateway<E: KvsEngine> {
+    engine: E,
+}
+
+impl<E: KvsEngine> HttpGateway<E> {
+    /// create a new gateway which serves requests from the given engine
+    pub fn new(engine: E) -> Self {
+        Self { engine }
+    }
+    /// bind to the given address and serve requests until an error occurs accepting connections
+    pub fn run<A: net::ToSocketAddrs>(self, addr: A) -> Result<()> {
+        self.serve(net::TcpListener::bind(addr)?)
+    }
+    /// serve requests from an already bound listener, each upon its own thread
+    pub fn serve(self, listener: net::TcpListener) -> Result<()> {
+        let server = tiny_http::Server::from_listener(listener, None)
+            .map_err(|err| Error::with_source(ErrorKind::IoError, err))?;
+        loop {
+            let request = server.recv()?;
+            let engine = self.engine.clone();
+            thread::spawn(move || {
+                if let Err(err) = handle_request(&engine, request) {
+                    eprintln!("HTTP request failed with error: {}", err);
+                }
+            });
+        }
+    }
+}
+
+type HttpResponse = Response<io::Cursor<Vec<u8>>>;
+
+fn handle_request<E: KvsEngine>(engine: &E, mut request: Request) -> Result<()> {
+    let method = request.method().clone();
+    let url = request.url();
+    let path = url.split_once('?').map_or(url, |(path, _)| path).to_owned();
+    let response = match (&method, path.as_str()) {
+        (Method::Get, path) if path.starts_with(WATCH_PATH) => {
+            return match percent_decode(&path[WATCH_PATH.len()..]) {
+                Some(prefix) => watch(engine, prefix, request),
+                None => Ok(request.respond(text(400, "the prefix must be percent-encoded UTF-8"))?),
+            };
+        }
+        (_, path) if path.starts_with(WATCH_PATH) => method_not_allowed("GET"),
+        (Method::Get, "/stats") => stats(engine),
+        (_, "/stats") => method_not_allowed("GET"),
+        (method, path) if path.starts_with(KEYS_PATH) => {
+            match percent_decode(&path[KEYS_PATH.len()..]) {
+                Some(key) if !key.is_empty() => key_request(engine, method, key, &mut request),
+                _ => text(
+                    400,
+                    "the key must be a non-empty percent-encoded UTF-8 string",
+                ),
+            }
+        }
+        _ => text(404, "not found"),
+    };
+    Ok(request.respond(response)?)
+}
+
+fn key_request<E: KvsEngine>(
+    engine: &E,
+    method: &Method,
+    key: String,
+    request: &mut Request,
+) -> HttpResponse {
+    let result = match method {
+        Method::Get => engine.get(key).map(|value| match value {
+            Some(value) => text(200, value),
+            None => text(404, "key not found"),
+        }),
+        Method::Put => {
+            let mut value = String::new();
+            if request.as_reader().read_to_string(&mut value).is_err() {
+                return text(400, "the value must be UTF-8");
+            }
+            engine.set(key, value).map(|_| empty(204))
+        }
+        Method::Delete => engine.remove(key).map(|_| empty(204)),
+        _ => return method_not_allowed("GET, PUT, DELETE"),
+    };
+    result.unwrap_or_else(error_response)
+}
+
+/// upgrades the request to a WebSocket streaming the events of the keys starting with the prefix,
+/// until it is closed
+fn watch<E: KvsEngine>(engine: &E, prefix: String, request: Request) -> Result<()> {
+    let key = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Sec-WebSocket-Key"))
+        .map(|header| tungstenite::handshake::derive_accept_key(header.value.as_bytes()));
+    let accept = match key {
+        Some(accept) => accept,
+        None => return Ok(request.respond(text(400, "expected a WebSocket upgrade"))?),
+    };
+    let events = match engine.subscribe(prefix) {
+        Ok(events) => events,
+        Err(err) => return Ok(request.respond(error_response(err))?),
+    };
+    let response = empty(101).with_header(header("Sec-WebSocket-Accept", &accept));
+    let stream = request.upgrade("websocket", response);
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    loop {
+        let message = match events.recv_timeout(PING_INTERVAL) {
+            Ok(event) => Message::Text(event_json(event).to_string()),
+            Err(mpsc::RecvTimeoutError::Timeout) => Message::Ping(Vec::new()),
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+        };
+        if socket.send(message).is_err() {
+            // the client has closed the WebSocket (or the connection failed)
+            return Ok(());
+        }
+    }
+}
+
+fn event_json(event: KeyEvent<String>) -> serde_json::Value {
+    match event {
+        KeyEvent::Set(key) => json!({ "event": "set", "key": key }),
+        KeyEvent::Removed(key) => json!({ "event": "removed", "key": key }),
+        KeyEvent::Evicted(key) => json!({ "event": "evicted", "key": key }),
+        KeyEvent::Cleared => json!({ "event": "cleared" }),
+    }
+}
+
+fn stats<E: KvsEngine>(engine: &E) -> HttpResponse {
+    let keys = match engine.key_count() {
+        Ok(keys) => keys,
+        Err(err) => return error_response(err),
+    };
+    #[cfg(feature = "stats")]
+    let body = match engine.stats() {
+        Ok(stats) => json!({
+            "keys": keys,
+            "latencies": {
+                "set": latency_json(&stats.set),
+                "get": latency_json(&stats.get),
+                "remove": latency_json(&stats.remove),
+                "compaction": latency_json(&stats.compaction),
+            },
+            "sizes": {
+                "keys": size_json(&stats.key_sizes),
+                "values": size_json(&stats.value_sizes),
+            },
+        }),
+        Err(err) => return error_response(err),
+    };
+    #[cfg(not(feature = "stats"))]
+    let body = json!({ "keys": keys });
+    text(200, body.to_string()).with_header(header("Content-Type", "application/json"))
+}
+
+/// sizes of the keys or values written as JSON, in bytes
+#[cfg(feature = "stats")]
+fn size_json(sizes: &super::SizeStats) -> serde_json::Value {
+    json!({
+        "count": sizes.count,
+        "p50": sizes.p50,
+        "p90": sizes.p90,
+        "p99": sizes.p99,
+        "max": sizes.max,
+    })
+}
+
+/// latencies of an operation as JSON, in nanoseconds
+#[cfg(feature = "stats")]
+fn latency_json(latency: &super::LatencyStats) -> serde_json::Value {
+    json!({
+        "count": latency.count,
+        "min_ns": latency.min.as_nanos() as u64,
+        "mean_ns": latency.mean.as_nanos() as u64,
+        "p50_ns": latency.p50.as_nanos() as u64,
+        "p99_ns": latency.p99.as_nanos() as u64,
+        "p999_ns": latency.p999.as_nanos() as u64,
+        "max_ns": latency.max.as_nanos() as u64,
+    })
+}
+
+fn error_response(err: Error) -> HttpResponse {
+    let status = match err.kind() {
+        ErrorKind::KeyNotPresent => 404,
+        ErrorKind::Rejected => 422,
+        ErrorKind::QuotaExceeded => 507,
+        ErrorKind::Busy => 503,
+        _ => 500,
+    };
+    text(status, err.to_string())
+}
+
+fn method_not_allowed(allowed: &str) -> HttpResponse {
+    text(405, "method not allowed").with_header(header("Allow", allowed))
+}
+
+fn text(status: u16, body: impl Into<String>) -> HttpResponse {
+    Response::from_string(body)
+        .with_status_code(StatusCode(status))
+        .with_header(header("Content-Type", "text/plain; charset=utf-8"))
+}
+
+fn empty(status: u16) -> HttpResponse {
+    Response::from_data(Vec::new()).with_status_code(StatusCode(status))
+}
+
+fn header(field: &str, value: &str) -> Header {
+    // the fields and values used are all ASCII, so are valid headers
+    Header::from_bytes(field, value).unwrap()
+}
+
+/// decodes the `%XX` escapes of a path segment, None if an escape is malformed or the result is
+/// not UTF-8
+fn percent_decode(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        match byte {
+            b'%' => {
+                let hex = std::str::from_utf8(after.get(..2)?).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                rest = &after[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = after;
+            }
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
@@ -0,0 +1,57 @@
+use std::{
+    collections::{HashMap, HashSet},
+    hash,
+};
+
+/// function extracting the index key (if any) from a value
+type IndexKeyExtractor<V> = Box<dyn Fn(&V) -> Option<String> + Send>;
+
+/// Secondary index of a KvStore, mapping the index key extracted from each value to the keys
+/// of the values it was extracted from
+pub(crate) struct SecondaryIndex<K, V> {
+    extract: IndexKeyExtractor<V>,
+    keys_by_index_key: HashMap<String, HashSet<K>>,
+    index_key_by_key: HashMap<K, String>,
+}
+
+impl<K, V> SecondaryIndex<K, V>
+where
+    K: Eq + hash::Hash + Clone,
+{
+    pub(crate) fn new<F>(extract: F) -> Self
+    where
+        F: Fn(&V) -> Option<String> + Send + 'static,
+    {
+        Self {
+            extract: Box::new(extract),
+            keys_by_index_key: HashMap::new(),
+            index_key_by_key: HashMap::new(),
+        }
+    }
+    /// re-indexes the key for its new value (None if the key was removed)
+    pub(crate) fn update(&mut self, key: &K, value: Option<&V>) {
+        if let Some(index_key) = self.index_key_by_key.remove(key) {
+            if let Some(keys) = self.keys_by_index_key.get_mut(&index_key) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.keys_by_index_key.remove(&index_key);
+                }
+            }
+        }
+        if let Some(index_key) = value.and_then(|value| (self.extract)(value)) {
+            self.keys_by_index_key
+                .entry(index_key.clone())
+                .or_default()
+                .insert(key.clone());
+            self.index_key_by_key.insert(key.clone(), index_key);
+        }
+    }
+    pub(crate) fn clear(&mut self) {
+        self.keys_by_index_key.clear();
+        self.index_key_by_key.clear();
+    }
+    /// keys whose values have the given index key (in no particular order)
+    pub(crate) fn keys(&self, index_key: &str) -> impl Iterator<Item = &K> {
+        self.keys_by_index_key.get(index_key).into_iter().flatten()
+    }
+}
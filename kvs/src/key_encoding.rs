@@ -0,0 +1,322 @@
+use std::fmt;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{Error, ErrorKind, Result};
+
+/// number of bytes of each group of an encoded byte string, followed by its marker byte
+const GROUP: usize = 8;
+/// marker of a group of a byte string holding no padding, so followed by another
+const FULL_GROUP_MARKER: u8 = 0xff;
+
+/// Key encoded to bytes which compare (as byte strings, as when sorted) in the order of the keys
+/// themselves, and which may be decoded again
+///
+/// Integers are encoded big-endian (signed integers with their sign bit flipped), and strings
+/// and byte strings in groups of 8 bytes each followed by a marker of how much of the group is
+/// padding (the memcomparable format), so that no encoding is a prefix of another. A tuple is
+/// encoded as its fields one after the other, so tuples compare field by field and the encoding
+/// of a tuple's leading fields is a prefix of the encoding of the tuple, which is how keys
+/// sharing them are found in a scan.
+pub trait KeyEncoding: Sized {
+    /// append the encoding of the key to the buffer
+    fn encode_to(&self, buf: &mut Vec<u8>);
+    /// decode a key from the start of the input, advancing it past the key's encoding
+    fn decode_from(input: &mut &[u8]) -> Result<Self>;
+}
+
+/// the order-preserving encoding of the key
+///
+/// # Example
+/// ```
+/// use kvs::encode_key;
+///
+/// let user = |name: &str, id: u64| encode_key(&(name.to_owned(), id));
+/// assert!(user("ann", 9) < user("ann", 10));
+/// assert!(user("ann", 10) < user("anna", 0));
+/// assert!(encode_key(&-1i64) < encode_key(&0i64));
+/// ```
+pub fn encode_key<T: KeyEncoding>(key: &T) -> Vec<u8> {
+    let mut buf = Vec::new();
+    key.encode_to(&mut buf);
+    buf
+}
+
+/// decode a key encoded by `encode_key`, DeserializationError unless the bytes are exactly its
+/// encoding
+///
+/// # Example
+/// ```
+/// use kvs::{decode_key, encode_key};
+///
+/// let key = ("user".to_owned(), 42u64);
+/// assert_eq!(decode_key::<(String, u64)>(&encode_key(&key)).unwrap(), key);
+/// ```
+pub fn decode_key<T: KeyEncoding>(mut bytes: &[u8]) -> Result<T> {
+    let key = T::decode_from(&mut bytes)?;
+    match bytes.is_empty() {
+        true => Ok(key),
+        false => Err(malformed("bytes follow the encoded key")),
+    }
+}
+
+fn malformed(message: &str) -> Error {
+    Error::with_message(
+        ErrorKind::DeserializationError,
+        format!("malformed key encoding: {}", message),
+    )
+}
+
+/// takes the given number of bytes from the start of the input
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if input.len() < len {
+        return Err(malformed("the encoding ends early"));
+    }
+    let (taken, rest) = input.split_at(len);
+    *input = rest;
+    Ok(taken)
+}
+
+macro_rules! unsigned_key_encoding {
+    ($($int:ty),*) => {$(
+        impl KeyEncoding for $int {
+            fn encode_to(&self, buf: &mut Vec<u8>) {
+                buf.extend_from_slice(&self.to_be_bytes());
+            }
+            fn decode_from(input: &mut &[u8]) -> Result<Self> {
+                const LEN: usize = std::mem::size_of::<$int>();
+                let mut bytes = [0; LEN];
+                bytes.copy_from_slice(take(input, LEN)?);
+                Ok(<$int>::from_be_bytes(bytes))
+            }
+        }
+    )*};
+}
+
+unsigned_key_encoding!(u8, u16, u32, u64, u128);
+
+macro_rules! signed_key_encoding {
+    ($($int:ty => $unsigned:ty),*) => {$(
+        impl KeyEncoding for $int {
+            // flipping the sign bit puts the negative integers before the others
+            fn encode_to(&self, buf: &mut Vec<u8>) {
+                ((*self as $unsigned) ^ (1 << (<$unsigned>::BITS - 1))).encode_to(buf)
+            }
+            fn decode_from(input: &mut &[u8]) -> Result<Self> {
+                let flipped = <$unsigned>::decode_from(input)?;
+                Ok((flipped ^ (1 << (<$unsigned>::BITS - 1))) as $int)
+            }
+        }
+    )*};
+}
+
+signed_key_encoding!(i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128);
+
+impl KeyEncoding for bool {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        match take(input, 1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(malformed("a boolean is neither 0 nor 1")),
+        }
+    }
+}
+
+impl KeyEncoding for Vec<u8> {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        encode_bytes(self, buf)
+    }
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        decode_bytes(input)
+    }
+}
+
+impl KeyEncoding for String {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        encode_bytes(self.as_bytes(), buf)
+    }
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        String::from_utf8(decode_bytes(input)?)
+            .map_err(|err| Error::with_source(ErrorKind::DeserializationError, err))
+    }
+}
+
+/// appends the bytes in groups of 8, each followed by a marker of 0xff less the padding of the
+/// group, the last group (which may be all padding) holding at least one byte of padding
+fn encode_bytes(bytes: &[u8], buf: &mut Vec<u8>) {
+    buf.reserve((bytes.len() / GROUP + 1) * (GROUP + 1));
+    let mut groups = bytes.chunks_exact(GROUP);
+    for group in &mut groups {
+        buf.extend_from_slice(group);
+        buf.push(FULL_GROUP_MARKER);
+    }
+    let last = groups.remainder();
+    let padding = GROUP - last.len();
+    buf.extend_from_slice(last);
+    buf.resize(buf.len() + padding, 0);
+    buf.push(FULL_GROUP_MARKER - padding as u8);
+}
+
+fn decode_bytes(input: &mut &[u8]) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    loop {
+        let group = take(input, GROUP + 1)?;
+        let padding = (FULL_GROUP_MARKER - group[GROUP]) as usize;
+        if padding > GROUP {
+            return Err(malformed("a byte string group has an invalid marker"));
+        }
+        let (data, pad) = group[..GROUP].split_at(GROUP - padding);
+        if pad.iter().any(|&byte| byte != 0) {
+            return Err(malformed("the padding of a byte string is not zero"));
+        }
+        bytes.extend_from_slice(data);
+        if padding > 0 {
+            return Ok(bytes);
+        }
+    }
+}
+
+macro_rules! tuple_key_encoding {
+    ($($field:ident),*) => {
+        impl<$($field: KeyEncoding),*> KeyEncoding for ($($field,)*) {
+            #[allow(non_snake_case)]
+            fn encode_to(&self, buf: &mut Vec<u8>) {
+                let ($($field,)*) = self;
+                $($field.encode_to(buf);)*
+            }
+            fn decode_from(input: &mut &[u8]) -> Result<Self> {
+                Ok(($($field::decode_from(input)?,)*))
+            }
+        }
+    };
+}
+
+tuple_key_encoding!(A);
+tuple_key_encoding!(A, B);
+tuple_key_encoding!(A, B, C);
+tuple_key_encoding!(A, B, C, D);
+
+/// Key of a KvStore which is a u64, held in the log in its order-preserving encoding
+///
+/// # Example
+/// ```
+/// use kvs::{KvStore, U64Key};
+///
+/// let dir = tempfile::TempDir::new().unwrap();
+/// let mut store = KvStore::<U64Key,String>::open(dir.path()).unwrap();
+/// store.set(U64Key(10), "ten".into()).unwrap();
+/// store.set(U64Key(9), "nine".into()).unwrap();
+/// let mut keys: Vec<_> = store.keys().copied().collect();
+/// keys.sort();
+/// assert_eq!(keys.first(), Some(&U64Key(9)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct U64Key(pub u64);
+
+impl U64Key {
+    /// the order-preserving encoding of the key
+    pub fn encoded(&self) -> Vec<u8> {
+        encode_key(self)
+    }
+}
+
+impl From<u64> for U64Key {
+    fn from(key: u64) -> Self {
+        Self(key)
+    }
+}
+
+impl KeyEncoding for U64Key {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        self.0.encode_to(buf)
+    }
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        u64::decode_from(input).map(Self)
+    }
+}
+
+/// Key of a KvStore made up of the fields of a tuple (of integers, strings, byte strings and
+/// booleans), held in the log in its order-preserving encoding, so keys compare (and sort) field
+/// by field and those sharing leading fields are found by the prefix of their encodings
+///
+/// # Example
+/// ```
+/// use kvs::{CompositeKey, KvStore};
+///
+/// let dir = tempfile::TempDir::new().unwrap();
+/// let mut store = KvStore::<CompositeKey<(String, u64)>,String>::open(dir.path()).unwrap();
+/// store.set(CompositeKey(("ann".into(), 10)), "order 10".into()).unwrap();
+/// store.set(CompositeKey(("ann".into(), 9)), "order 9".into()).unwrap();
+/// store.set(CompositeKey(("bob".into(), 1)), "order 1".into()).unwrap();
+/// let prefix = CompositeKey(("ann".to_owned(),)).encoded();
+/// let mut keys: Vec<_> = store.keys().filter(|key| key.encoded().starts_with(&prefix)).collect();
+/// keys.sort();
+/// assert_eq!(keys[0].0, ("ann".into(), 9));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct CompositeKey<T>(pub T);
+
+impl<T: KeyEncoding> CompositeKey<T> {
+    /// the order-preserving encoding of the key
+    pub fn encoded(&self) -> Vec<u8> {
+        encode_key(&self.0)
+    }
+}
+
+impl<T> From<T> for CompositeKey<T> {
+    fn from(fields: T) -> Self {
+        Self(fields)
+    }
+}
+
+impl<T: KeyEncoding> KeyEncoding for CompositeKey<T> {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        self.0.encode_to(buf)
+    }
+    fn decode_from(input: &mut &[u8]) -> Result<Self> {
+        T::decode_from(input).map(Self)
+    }
+}
+
+macro_rules! serde_as_encoded_bytes {
+    ($key:ty $(, $param:ident)?) => {
+        impl$(<$param: KeyEncoding>)? Serialize for $key {
+            fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+                serializer.serialize_bytes(&encode_key(self))
+            }
+        }
+
+        impl<'de, $($param: KeyEncoding)?> Deserialize<'de> for $key {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+                deserializer.deserialize_byte_buf(EncodedKeyVisitor(std::marker::PhantomData))
+            }
+        }
+    };
+}
+
+serde_as_encoded_bytes!(U64Key);
+serde_as_encoded_bytes!(CompositeKey<T>, T);
+
+/// deserializes a key from the bytes of its encoding
+struct EncodedKeyVisitor<T>(std::marker::PhantomData<T>);
+
+impl<'de, T: KeyEncoding> de::Visitor<'de> for EncodedKeyVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("the bytes of an encoded key")
+    }
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> std::result::Result<T, E> {
+        decode_key(bytes).map_err(E::custom)
+    }
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<T, A::Error> {
+        let mut bytes = Vec::new();
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        self.visit_bytes(&bytes)
+    }
+}
@@ -6,7 +6,7 @@
 //! ```
 //! use kvs::KvStore;
 //!
-//! let mut store = KvStore::<String, String>::new(std::path::Path::new("testdb")).unwrap();
+//! let mut store = KvStore::<String, String>::temporary().unwrap();
 //!
 //! let _ = store.set(String::from("key1"), String::from("value1"));
 //! let value1 = store.get(String::from("key1")).unwrap();
@@ -22,11 +22,14 @@
 //!
 
 use std::{
-    collections::HashMap,
-    fs, hash,
+    collections::{HashMap, HashSet},
+    fmt, fs, hash,
     io::{self, Seek, Write},
     marker, mem,
+    ops::Range,
     path::{self, Path},
+    sync,
+    time::{Duration, Instant},
 };
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
@@ -34,15 +37,293 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 mod error;
 pub use error::{Error, ErrorKind, Result};
 
+mod batch;
+pub use batch::WriteBatch;
+
+mod index;
+use index::SecondaryIndex;
+
+mod snapshot;
+
+mod history;
+pub use history::{Change, LogPosition, Tail};
+
+mod version;
+pub use version::Version;
+
+mod clock;
+pub use clock::{Clock, ManualClock, SystemClock};
+
+mod hasher;
+pub use hasher::{IndexHash, IndexHasher};
+
+mod key_encoding;
+pub use key_encoding::{decode_key, encode_key, CompositeKey, KeyEncoding, U64Key};
+
+mod lock;
+pub use lock::Lock;
+
+mod trash;
+use trash::Trashed;
+
+mod typed;
+pub use typed::TypedStore;
+
+mod migrate;
+pub use migrate::DataLayout;
+
+mod raw;
+
+mod codec;
+pub use codec::RecordFormat;
+
+mod notify;
+pub use notify::KeyEvent;
+use notify::{EventSender, Subscribers};
+
+mod events;
+use events::EventSubscribers;
+pub use events::StoreEvent;
+
+mod hooks;
+use hooks::Hooks;
+pub use hooks::{BeforeSetHook, KeyHook};
+
+mod manifest;
+use manifest::{Digest, Manifest, Segment};
+
+mod eviction;
+pub use eviction::EvictionPolicy;
+
+mod backpressure;
+pub use backpressure::Backpressure;
+mod runtime;
+use eviction::Usage;
+pub use runtime::RuntimeOptions;
+
+mod storage;
+use storage::{FileStorage, OpenStorage, Storage};
+pub use storage::{IoBackend, WriteMode};
+
+mod verify;
+pub use verify::VerifyReport;
+
+mod debug;
+pub use debug::{AllRecords, DebugRecord, RecordState};
+
+mod registry;
+use registry::Registration;
+
+mod temporary;
+use temporary::TemporaryDir;
+
+mod stats;
+use stats::Operation;
+#[cfg(feature = "stats")]
+pub use stats::{LatencyStats, SizeStats, Stats};
+
+mod engine;
+pub use engine::{KvsEngine, SharedKvStore};
+
+mod sharded;
+pub use sharded::ShardedKvStore;
+
+pub mod engine_tests;
+
+mod protocol;
+pub use protocol::{Request, Response};
+
+mod resp;
+
+mod client;
+#[cfg(feature = "async")]
+pub use client::AsyncKvsClient;
+pub use client::{
+    ClusterClient, ClusterOptions, KvsClient, KvsClientPool, PoolOptions, Scan, Subscription,
+};
+
+mod server;
+pub use server::{
+    redis_compatibility_report, Acl, AclUser, KvsServer, LogLevel, RateLimit, ReloadHandle,
+    ServerConfig, ShutdownHandle,
+};
+
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
+
+mod raft;
+pub use raft::{RaftEngine, RaftOptions, RaftRole, RaftStatus};
+
+mod topology;
+pub use topology::{ClusterNode, MigrationStatus, Shard, Topology};
+
+mod transfer;
+pub use transfer::SnapshotChunk;
+
+#[cfg(feature = "http")]
+mod http;
+#[cfg(feature = "http")]
+pub use http::HttpGateway;
+
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "grpc")]
+pub use grpc::{proto as grpc_proto, GrpcServer};
+
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "tls")]
+pub use tls::{ClientTlsConfig, ServerTlsConfig};
+
+/// subdirectory of a store's directory holding its named keyspaces
+const KEYSPACES_DIR: &str = "keyspaces";
+
+/// prefix of the names of a store's files unless another is given in its StoreOptions
+const DEFAULT_FILE_PREFIX: &str = "kvsdb";
+
+/// number of records written to the target store per batch by `compact_into`
+const COMPACT_INTO_BATCH_SIZE: usize = 1000;
+
+/// buffered reader of a store's log
+type LogReader = io::BufReader<Box<dyn Storage>>;
+
+/// buffered writer of a store's log
+type LogWriter = io::BufWriter<Box<dyn Storage>>;
+
+/// index of a store, the offset in its log of the current record of each key
+type Index<K> = HashMap<K, u64, IndexHasher>;
+
+/// Merge operator of a KvStore: given the key, its existing value (if any) and the next operand
+/// merged into it, returns the new value
+pub type MergeOperator<K, V> = Box<dyn Fn(&K, Option<V>, V) -> V + Send>;
+
+/// Options controlling how a KvStore is opened
+#[derive(Debug, Clone)]
+pub struct StoreOptions {
+    /// prefix of the names of the store's files (`<prefix>-<id>.log` and so on), made up of ASCII
+    /// letters, digits, `-` and `_`, so that stores with different prefixes may share a directory
+    pub file_prefix: String,
+    /// maximum number of keys the store may hold, None (the default) for no limit; once it is
+    /// reached, setting a key the store does not hold fails with QuotaExceeded or evicts another
+    /// key, as `eviction` says
+    pub max_keys: Option<u64>,
+    /// maximum length in bytes of the store's log, None (the default) for no limit; a write of a
+    /// value taking the log past it fails with QuotaExceeded (removals are always written, and
+    /// compacting the log may make room again)
+    pub max_log_bytes: Option<u64>,
+    /// what the store does once it holds `max_keys` keys and another is set: reject the write
+    /// (the default), or evict keys (writing their removal) as a bounded cache would
+    pub eviction: EvictionPolicy,
+    /// how the store reads and writes its files
+    pub io_backend: IoBackend,
+    /// how the store's writes reach the disk; opening the store fails with InvalidConfiguration
+    /// if the platform (or the io_backend) does not support the mode
+    pub write_mode: WriteMode,
+    /// allocate the disk blocks of each log up to `max_log_bytes` (which must then be set) when
+    /// it is opened or created, so that appending to it neither fragments it nor updates the
+    /// metadata of its blocks; only on Linux (fallocate), the log keeping its length
+    pub preallocate: bool,
+    /// source of the time with which writes are timestamped and locks expire, the system's clock
+    /// by default
+    pub clock: sync::Arc<dyn Clock>,
+    /// how long a removed key is kept in the trash (say, some days), from which `restore_key`
+    /// brings it back, before compaction drops it for good; None (the default) for removals to
+    /// be permanent
+    pub trash_retention: Option<Duration>,
+    /// hard ceiling of the number of stale records (written over or removed) the log may hold
+    /// per key, None (the default) for none; a write of a value to a store over it first compacts
+    /// the log, and if that fails is held back as `backpressure` says, rather than letting the
+    /// log grow without bound (it must be positive)
+    pub max_stale_ratio: Option<f64>,
+    /// what a write does when the log is over `max_stale_ratio` and cannot be compacted: fail
+    /// with Busy (the default), or sleep and try compacting once more
+    pub backpressure: Backpressure,
+    /// hasher of the keys of the index, SipHash keyed at random (which resists keys crafted to
+    /// collide) by default
+    pub index_hasher: IndexHasher,
+    /// send the store's operational events and record its latencies and sizes (with the
+    /// `instrumentation` and `stats` features), true by default; a store which is not
+    /// instrumented does neither, as if the features were off
+    pub instrumented: bool,
+    /// format of the records the store writes, Compact (the default) or, for a log to be read by
+    /// earlier versions, WithOffsets; records of either format are read
+    pub record_format: RecordFormat,
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        Self {
+            file_prefix: DEFAULT_FILE_PREFIX.into(),
+            max_keys: None,
+            max_log_bytes: None,
+            eviction: EvictionPolicy::default(),
+            io_backend: IoBackend::default(),
+            write_mode: WriteMode::default(),
+            preallocate: false,
+            clock: sync::Arc::new(SystemClock),
+            trash_retention: None,
+            max_stale_ratio: None,
+            backpressure: Backpressure::default(),
+            index_hasher: IndexHasher::default(),
+            instrumented: true,
+            record_format: RecordFormat::default(),
+        }
+    }
+}
+
+/// Progress of rebuilding the index of a KvStore, from `rebuild_index_with_progress`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RebuildProgress {
+    /// number of records of the log read so far
+    pub records: u64,
+    /// offset of the last record read
+    pub offset: u64,
+    /// length of the log being read
+    pub log_len: u64,
+}
+
 /// Simple Key-Value Storage Type
 pub struct KvStore<K, V> {
-    index: HashMap<K, u64>,
+    index: Index<K>,
     stale_count: u64,
+    next_version: u64,
     file_path: path::PathBuf,
-    reader: io::BufReader<fs::File>,
-    writer: io::BufWriter<fs::File>,
+    reader: LogReader,
+    writer: LogWriter,
+    storage: sync::Arc<dyn OpenStorage>,
+    scratch: Vec<u8>,
     stale_fraction_for_compaction: f64,
     min_records_before_compaction: u64,
+    was_shut_down_cleanly: bool,
+    merge_operator: Option<MergeOperator<K, V>>,
+    rebuild_index_on_inconsistency: bool,
+    secondary_indexes: HashMap<String, SecondaryIndex<K, V>>,
+    max_keys: Option<u64>,
+    max_log_bytes: Option<u64>,
+    io_backend: IoBackend,
+    write_mode: WriteMode,
+    preallocate: bool,
+    clock: sync::Arc<dyn Clock>,
+    trash: HashMap<K, Trashed>,
+    trash_retention: Option<Duration>,
+    max_stale_ratio: Option<f64>,
+    backpressure: Backpressure,
+    usage: Option<Usage<K>>,
+    subscribers: Subscribers<K>,
+    events: EventSubscribers,
+    hooks: Hooks<K, V>,
+    instrumented: bool,
+    record_format: RecordFormat,
+    #[cfg(feature = "stats")]
+    latencies: stats::Latencies,
+    #[cfg(feature = "stats")]
+    sizes: stats::Sizes,
+    #[cfg(feature = "stats")]
+    backlog: stats::Backlog,
+    // dropped after the store's files are closed
+    _registration: Registration,
+    _temporary_dir: Option<TemporaryDir>,
     phantom_value: marker::PhantomData<V>,
 }
 
@@ -52,11 +333,23 @@ pub struct Record<K, V> {
     db_key: u64,
     key: K,
     value: Option<V>,
+    // merge operand records (whose value is the operand) point back to the previous record of the
+    // key; defaulted so that logs written before merging was supported can still be read
+    #[serde(default)]
+    merge: bool,
+    #[serde(default)]
+    previous: Option<u64>,
+    // sequence number of the write across the store, and when it was made (in milliseconds since
+    // the Unix epoch), zero in records written before writes were versioned
+    #[serde(default)]
+    version: u64,
+    #[serde(default)]
+    timestamp: u64,
 }
 
 impl<K, V> KvStore<K, V>
 where
-    K: Serialize + DeserializeOwned + Eq + PartialEq + hash::Hash + Clone,
+    K: Serialize + DeserializeOwned + Eq + PartialEq + hash::Hash + Clone + fmt::Debug,
     V: Serialize + DeserializeOwned + Clone,
 {
     /// create a new empty Key-Value storage instance
@@ -65,12 +358,35 @@ where
     /// ```
     /// use kvs::KvStore;
     ///
-    /// let store = KvStore::<String,String>::new(std::path::Path::new("testdb")).unwrap();
+    /// let dir = tempfile::TempDir::new().unwrap();
+    /// let store = KvStore::<String,String>::new(dir.path()).unwrap();
     /// ```
     pub fn new(path: &Path) -> Result<Self> {
-        ensure_dir_exists(path);
-        let db_path = use_existing_or_create_new_db_log_path(path)?;
-        Self::init_self(&db_path, true)
+        let path = &data_dir(path)?;
+        let registration = Registration::register(path, DEFAULT_FILE_PREFIX)?;
+        let (db_path, manifest) =
+            use_existing_or_create_new_db_log_path(path, DEFAULT_FILE_PREFIX)?;
+        // discard any persisted index of the log being truncated
+        take_hint::<K>(&db_path, &manifest, &IndexHasher::default())?;
+        Self::init_self(&db_path, true, sync::Arc::new(FileStorage), registration)
+    }
+    /// create a new empty store in a directory of its own under the system's temporary directory,
+    /// which is removed with the store's files when the store is dropped (as a test would want)
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// store.set("key1".into(),"value1".into()).unwrap();
+    /// let dir = store.dir().to_owned();
+    /// drop(store);
+    /// assert!(!dir.exists());
+    /// ```
+    pub fn temporary() -> Result<Self> {
+        let dir = TemporaryDir::new()?;
+        let mut store = Self::open(dir.path())?;
+        store._temporary_dir = Some(dir);
+        Ok(store)
     }
     /// open a disk-based, log-based storage at a path
     /// If the file exists it opens for reading and appending. If the file does not exist it creates it.
@@ -78,13 +394,91 @@ where
     /// ```
     /// use kvs::KvStore;
     ///
-    /// let store = KvStore::<String,String>::open(std::path::Path::new("testdb")).unwrap();
+    /// let dir = tempfile::TempDir::new().unwrap();
+    /// let store = KvStore::<String,String>::open(dir.path()).unwrap();
     /// ```
     pub fn open(path: &path::Path) -> Result<Self> {
-        ensure_dir_exists(path);
-        let db_path = use_existing_or_create_new_db_log_path(path)?;
-        let mut kv_store = Self::init_self(&db_path, false)?;
-        kv_store.load_index()?;
+        Self::open_with_options(path, &StoreOptions::default())
+    }
+    /// open a store at a path as `open` does, with the given options, InvalidConfiguration if
+    /// they are not valid
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::{KvStore, StoreOptions};
+    ///
+    /// let options = StoreOptions {
+    ///     file_prefix: "sessions".into(),
+    ///     ..StoreOptions::default()
+    /// };
+    /// let dir = tempfile::TempDir::new().unwrap();
+    /// let mut store = KvStore::<String,String>::open_with_options(dir.path(), &options).unwrap();
+    /// let _ = store.set("key1".into(),"value1".into());
+    /// assert_eq!(store.get("key1".into()).unwrap(), Some("value1".into()));
+    /// ```
+    pub fn open_with_options(path: &path::Path, options: &StoreOptions) -> Result<Self> {
+        let storage = options
+            .io_backend
+            .storage(options.write_mode)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidConfiguration))?;
+        Self::open_with_storage(path, options, storage)
+    }
+    /// open the store at a path as `open_with_options` does, reaching its files through the
+    /// given storage
+    pub(crate) fn open_with_storage(
+        path: &path::Path,
+        options: &StoreOptions,
+        storage: sync::Arc<dyn OpenStorage>,
+    ) -> Result<Self> {
+        if !is_valid_file_prefix(&options.file_prefix)
+            || (options.preallocate && options.max_log_bytes.is_none())
+            || options
+                .max_stale_ratio
+                .is_some_and(|ratio| !(ratio.is_finite() && ratio > 0.0))
+        {
+            return Err(Error::new(ErrorKind::InvalidConfiguration));
+        }
+        let path = &data_dir(path)?;
+        let registration = Registration::register(path, &options.file_prefix)?;
+        let (db_path, manifest) =
+            use_existing_or_create_new_db_log_path(path, &options.file_prefix)?;
+        let mut kv_store = Self::init_self(&db_path, false, storage, registration)?;
+        kv_store.max_keys = options.max_keys;
+        kv_store.max_log_bytes = options.max_log_bytes;
+        kv_store.io_backend = options.io_backend;
+        kv_store.write_mode = options.write_mode;
+        kv_store.preallocate = options.preallocate;
+        kv_store.clock = options.clock.clone();
+        kv_store.trash_retention = options.trash_retention;
+        kv_store.max_stale_ratio = options.max_stale_ratio;
+        kv_store.backpressure = options.backpressure;
+        kv_store.instrumented = options.instrumented;
+        kv_store.record_format = options.record_format;
+        kv_store.index = Index::with_hasher(options.index_hasher.clone());
+        kv_store.preallocate_log(&kv_store.writer)?;
+        // a snapshot is only trusted if the store was shut down cleanly after writing it
+        match take_hint(&db_path, &manifest, &options.index_hasher)? {
+            Some((index, stale_count, next_version, trash)) if kv_store.was_shut_down_cleanly => {
+                kv_store.index = index;
+                kv_store.trash = trash;
+                kv_store.stale_count = stale_count;
+                kv_store.next_version = next_version.max(1);
+            }
+            _ => {
+                let started = Instant::now();
+                let mut replayed = 0;
+                kv_store.load_index(|records, _| replayed = records)?;
+                if kv_store.is_instrumented() && !kv_store.was_shut_down_cleanly && replayed > 0 {
+                    kv_store
+                        .events
+                        .recovered_on_open(StoreEvent::RecoveryPerformed {
+                            records: replayed,
+                            duration: started.elapsed(),
+                        });
+                }
+            }
+        }
+        kv_store.track_usage(options.eviction);
         Ok(kv_store)
     }
     /// set a key to a value in the Key-Value Storage instance
@@ -96,29 +490,58 @@ where
     /// ```
     /// use kvs::KvStore;
     ///
-    /// let mut store = KvStore::<String,String>::new(std::path::Path::new("testdb")).unwrap();
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
     /// let _ = store.set("key1".into(),"value1".into());
     /// let _ = store.set("key1".into(),"value2".into());
     /// let value = store.get("key1".into()).unwrap();
     /// assert_eq!(value,Some("value2".into()));
     /// ```
     pub fn set(&mut self, key: K, value: V) -> Result<()> {
-        let rec = self.build_output_record(&key, Some(value))?;
+        self.timed(Operation::Set, |store| store.set_untimed(key, value))
+    }
+    fn set_untimed(&mut self, key: K, value: V) -> Result<()> {
+        let value = self.before_set(&key, value)?;
+        self.hold_back_if_compaction_lags()?;
+        let key_count = self.index.len() + usize::from(!self.index.contains_key(&key));
+        let key_count = self.evict(key_count, |evicted| *evicted == key)?;
+        self.check_key_quota(key_count)?;
+        let rec = self.build_output_record(&key, Some(&value))?;
         let db_key = rec.db_key;
         self.write_record_to_db(rec)?;
+        self.update_secondary_indexes(&key, Some(&value));
+        self.record_use(&key);
+        self.notify(&key, KeyEvent::Set);
         if self.index.insert(key, db_key).is_some() {
             self.stale_count += 1;
         };
         self.compact_if_stale_threshold_reached()?;
         Ok(())
     }
+    /// set a key to a value only if the key is not present, returning whether it was set
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// assert!(store.set_if_absent("key1".into(),"value1".into()).unwrap());
+    /// assert!(!store.set_if_absent("key1".into(),"value2".into()).unwrap());
+    /// assert_eq!(store.get("key1".into()).unwrap(), Some("value1".into()));
+    /// ```
+    pub fn set_if_absent(&mut self, key: K, value: V) -> Result<bool> {
+        if self.index.contains_key(&key) {
+            return Ok(false);
+        }
+        self.set(key, value)?;
+        Ok(true)
+    }
     /// get the value stored under the given key or None if no such key
     ///
     /// # Example
     /// ```
     /// use kvs::KvStore;
     ///
-    /// let mut store = KvStore::<String,String>::new(std::path::Path::new("testdb")).unwrap();
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
     /// let _ = store.set("key1".into(),"value1".into());
     /// let value = store.get("key1".into()).unwrap();
     /// assert_eq!(value,Some("value1".into()));
@@ -126,12 +549,26 @@ where
     /// assert_eq!(value,None);
     /// ```
     pub fn get(&mut self, key: K) -> Result<Option<V>> {
-        let db_key = match self.index.get(&key) {
-            Some(&db_key) => db_key,
-            None => return Ok(None),
-        };
-        let _ = self.reader.seek(io::SeekFrom::Start(db_key))?;
-        self.read_next_record_value()
+        self.timed(Operation::Get, |store| store.get_untimed(key))
+    }
+    fn get_untimed(&mut self, key: K) -> Result<Option<V>> {
+        let value = self.get_consistent(&key)?;
+        if value.is_some() {
+            self.record_use(&key);
+        }
+        Ok(value)
+    }
+    fn get_consistent(&mut self, key: &K) -> Result<Option<V>> {
+        match self.get_indexed(key) {
+            Err(err)
+                if self.rebuild_index_on_inconsistency
+                    && matches!(err.kind(), ErrorKind::IndexInconsistent { .. }) =>
+            {
+                self.rebuild_index()?;
+                self.get_indexed(key)
+            }
+            result => result,
+        }
     }
     /// remove the value stored under the given key or no-op if the key does not exist
     ///
@@ -139,7 +576,7 @@ where
     /// ```
     /// use kvs::KvStore;
     ///
-    /// let mut store = KvStore::<String,String>::new(std::path::Path::new("testdb")).unwrap();
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
     /// let _ = store.set("key1".into(),"value1".into());
     /// let value = store.get("key1".into()).unwrap();
     /// assert_eq!(value,Some("value1".into()));
@@ -149,40 +586,771 @@ where
     /// let _ = store.remove("key2".into());
     /// ```
     pub fn remove(&mut self, key: K) -> Result<()> {
+        self.timed(Operation::Remove, |store| store.remove_untimed(key))
+    }
+    fn remove_untimed(&mut self, key: K) -> Result<()> {
+        self.remove_key(key, false)
+    }
+    /// removes the key as `remove` does, notifying subscribers and running the hooks of its
+    /// removal (or eviction)
+    fn remove_key(&mut self, key: K, evicted: bool) -> Result<()> {
         match self.index.contains_key(&key) {
             true => {
-                let rec = self.build_output_record(&key, None)?;
+                let mut rec = self.build_output_record(&key, None)?;
+                // a removal (unlike an eviction) moves the value to the trash, if the store has one
+                if !evicted && self.trash_retention.is_some() {
+                    rec.previous = self.index.get(&key).copied();
+                }
+                let trashed = Trashed::of(&rec);
                 self.write_record_to_db(rec)?;
+                self.update_secondary_indexes(&key, None);
                 self.index.remove(&key);
+                match trashed {
+                    Some(trashed) => self.trash.insert(key.clone(), trashed),
+                    None => self.trash.remove(&key),
+                };
+                if let Some(usage) = &mut self.usage {
+                    usage.forget(&key);
+                }
+                let event = match evicted {
+                    true => KeyEvent::Evicted,
+                    false => KeyEvent::Removed,
+                };
+                self.notify(&key, event);
+                self.after_remove(&key, evicted);
                 self.stale_count += 1;
                 self.compact_if_stale_threshold_reached()?;
                 Ok(())
             }
-            false => Err(Error::new(ErrorKind::KeyNotPresent)),
+            false => Err(Error::new(ErrorKind::KeyNotPresent).for_key(&key)),
+        }
+    }
+    /// apply all the writes of the batch, in order, flushing the log only once
+    ///
+    /// Returns, for each operation in the batch, whether it was applied. Removes of keys which are
+    /// not present (at that point in the batch) are skipped rather than failing the whole batch.
+    /// If writing any record fails, none of the batch is applied.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::{KvStore, WriteBatch};
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// let mut batch = WriteBatch::new();
+    /// batch.set("key1".into(),"value1".into()).remove("key2".into());
+    /// assert_eq!(store.write_batch(batch).unwrap(), vec![true, false]);
+    /// assert_eq!(store.get("key1".into()).unwrap(), Some("value1".into()));
+    /// ```
+    pub fn write_batch(&mut self, batch: WriteBatch<K, V>) -> Result<Vec<bool>> {
+        let operations = self.before_set_all(batch.into_operations())?;
+        if operations.iter().any(|(_, value)| value.is_some()) {
+            self.hold_back_if_compaction_lags()?;
+        }
+        let batch_start = writer_position(&mut self.writer)?;
+        let mut pending_index = HashMap::new();
+        let mut stale_count = 0;
+        let mut applied = Vec::with_capacity(operations.len());
+        let mut indexed_values = Vec::new();
+        let mut key_count = self.index.len();
+        let mut writes_values = false;
+        let mut events = Vec::new();
+        let mut removed_keys = Vec::new();
+        let mut trashed_keys = Vec::new();
+        for (key, value) in operations {
+            let present = match pending_index.get(&key) {
+                Some(db_key) => Option::is_some(db_key),
+                None => self.index.contains_key(&key),
+            };
+            if value.is_none() && !present {
+                applied.push(false);
+                continue;
+            }
+            if present {
+                stale_count += 1;
+            }
+            match (present, &value) {
+                (false, Some(_)) => key_count += 1,
+                (true, None) => key_count -= 1,
+                _ => (),
+            }
+            writes_values |= value.is_some();
+            let mut rec = self.build_output_record(&key, value.as_ref())?;
+            if value.is_none() {
+                if self.trash_retention.is_some() {
+                    rec.previous = match pending_index.get(&key) {
+                        Some(db_key) => *db_key,
+                        None => self.index.get(&key).copied(),
+                    };
+                }
+                trashed_keys.push((key.clone(), Trashed::of(&rec)));
+            }
+            let db_key = rec.db_key;
+            if let Err(err) = self.write_record(rec) {
+                self.discard_unwritten(batch_start)?;
+                return Err(err);
+            }
+            let db_key = value.as_ref().map(|_| db_key);
+            if !self.secondary_indexes.is_empty() {
+                indexed_values.push((key.clone(), value));
+            }
+            if !self.subscribers.is_empty() {
+                let event = match db_key {
+                    Some(_) => KeyEvent::Set,
+                    None => KeyEvent::Removed,
+                };
+                events.push(event(key.clone()));
+            }
+            if db_key.is_none() && self.hooks.after_remove.is_some() {
+                removed_keys.push(key.clone());
+            }
+            pending_index.insert(key, db_key);
+            applied.push(true);
+        }
+        // keys the batch does not write make room for those it adds if the store evicts keys
+        let evictable = match self.usage {
+            Some(_) => {
+                let written = pending_index.keys();
+                self.index.len() - written.filter(|key| self.index.contains_key(key)).count()
+            }
+            None => 0,
+        };
+        let written = self
+            .check_key_quota(key_count.saturating_sub(evictable))
+            .and_then(|_| match writes_values {
+                true => self.check_log_quota(),
+                false => Ok(()),
+            })
+            .and_then(|_| self.flush_log());
+        if let Err(err) = written {
+            self.discard_unwritten(batch_start)?;
+            return Err(err);
+        }
+        let written_keys = match self.usage {
+            Some(_) => pending_index.keys().cloned().collect(),
+            None => HashSet::new(),
+        };
+        for (key, db_key) in pending_index {
+            match db_key {
+                Some(_) => self.record_use(&key),
+                None => {
+                    if let Some(usage) = &mut self.usage {
+                        usage.forget(&key);
+                    }
+                }
+            }
+            match db_key {
+                Some(db_key) => self.index.insert(key, db_key),
+                None => self.index.remove(&key),
+            };
+        }
+        for (key, value) in indexed_values {
+            self.update_secondary_indexes(&key, value.as_ref());
+        }
+        for (key, trashed) in trashed_keys {
+            match trashed {
+                Some(trashed) => self.trash.insert(key, trashed),
+                None => self.trash.remove(&key),
+            };
+        }
+        self.stale_count += stale_count;
+        for event in events {
+            self.subscribers.notify(event);
+        }
+        for key in removed_keys {
+            self.after_remove(&key, false);
+        }
+        self.evict(self.index.len(), |key| written_keys.contains(key))?;
+        self.compact_if_stale_threshold_reached()?;
+        Ok(applied)
+    }
+    /// subscribe to the events of the keys the filter accepts (and to Cleared), which are sent
+    /// once each write has been made until the receiver is dropped
+    ///
+    /// A subscriber which falls 4096 events behind is dropped rather than have the store hold its
+    /// events without limit: it receives those queued, and then finds its receiver disconnected.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::{KeyEvent, KvStore};
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// let events = store.subscribe(|key: &String| key.starts_with("user:"));
+    /// store.set("user:1".into(),"alice".into()).unwrap();
+    /// store.set("session:1".into(),"xyz".into()).unwrap();
+    /// assert_eq!(events.try_recv().unwrap(), KeyEvent::Set("user:1".into()));
+    /// assert!(events.try_recv().is_err());
+    /// ```
+    pub fn subscribe<F>(&mut self, filter: F) -> sync::mpsc::Receiver<KeyEvent<K>>
+    where
+        F: Fn(&K) -> bool + Send + 'static,
+    {
+        self.subscribers.subscribe(Box::new(filter))
+    }
+    /// subscribe to the operational events of the store (its compactions, stalled writes, log
+    /// rotations and recoveries), the recovery performed when it was opened (if it was not shut
+    /// down cleanly) being received first
+    ///
+    /// Events are only sent with the `instrumentation` feature (on by default), while the store
+    /// is instrumented (see StoreOptions).
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::{KvStore, StoreEvent};
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// let events = store.subscribe_events();
+    /// store.compact_now().unwrap();
+    /// assert!(events.try_iter().any(|event| matches!(event, StoreEvent::CompactionFinished { .. })));
+    /// ```
+    pub fn subscribe_events(&mut self) -> sync::mpsc::Receiver<StoreEvent> {
+        self.events.subscribe()
+    }
+    /// subscribes the sender (which may be subscribed to other stores too) to the events of the
+    /// keys the filter accepts, and to Cleared if `receives_cleared`
+    pub(crate) fn subscribe_with<F>(
+        &mut self,
+        filter: F,
+        sender: EventSender<K>,
+        receives_cleared: bool,
+    ) where
+        F: Fn(&K) -> bool + Send + 'static,
+    {
+        self.subscribers
+            .subscribe_with(Box::new(filter), sender, receives_cleared)
+    }
+    /// subscribes the sender (which may be subscribed to other stores too) to the operational
+    /// events of the store
+    pub(crate) fn subscribe_events_with(&mut self, sender: sync::mpsc::Sender<StoreEvent>) {
+        self.events.subscribe_with(sender)
+    }
+    /// register the merge operator applied (on read and compaction) to operands written by `merge`
+    ///
+    /// The operator must be registered again each time the store is opened, before any key with
+    /// merged operands is read or written.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// store.set_merge_operator(|_key, list, item| list.unwrap_or_default() + &item);
+    /// store.merge("list".into(), "a".into()).unwrap();
+    /// store.merge("list".into(), "b".into()).unwrap();
+    /// assert_eq!(store.get("list".into()).unwrap(), Some("ab".into()));
+    /// ```
+    pub fn set_merge_operator<F>(&mut self, operator: F)
+    where
+        F: Fn(&K, Option<V>, V) -> V + Send + 'static,
+    {
+        self.merge_operator = Some(Box::new(operator));
+    }
+    /// merge an operand into the value of a key without reading it first, the merge operator
+    /// combining it with the existing value when the key is next read (or the log compacted)
+    ///
+    /// Fails with MergeOperatorMissing if no merge operator has been registered. The operand is
+    /// given to the before-set hook (if any) as the value set, and its latency is recorded as that
+    /// of a `set`.
+    pub fn merge(&mut self, key: K, operand: V) -> Result<()> {
+        self.timed(Operation::Set, |store| store.merge_untimed(key, operand))
+    }
+    fn merge_untimed(&mut self, key: K, operand: V) -> Result<()> {
+        if self.merge_operator.is_none() {
+            return Err(Error::new(ErrorKind::MergeOperatorMissing));
+        }
+        let operand = self.before_set(&key, operand)?;
+        self.hold_back_if_compaction_lags()?;
+        let key_count = self.index.len() + usize::from(!self.index.contains_key(&key));
+        let key_count = self.evict(key_count, |evicted| *evicted == key)?;
+        self.check_key_quota(key_count)?;
+        // read after evicting, which may compact the log
+        let previous = self.index.get(&key).copied();
+        let mut rec = self.build_output_record(&key, Some(&operand))?;
+        rec.merge = true;
+        rec.previous = previous;
+        let db_key = rec.db_key;
+        self.write_record_to_db(rec)?;
+        if self.index.insert(key.clone(), db_key).is_some() {
+            self.stale_count += 1;
+        }
+        self.record_use(&key);
+        self.notify(&key, KeyEvent::Set);
+        if !self.secondary_indexes.is_empty() {
+            let value =
+                resolve_value(&mut self.reader, self.merge_operator.as_ref(), &key, db_key)?;
+            self.update_secondary_indexes(&key, value.as_ref());
+        }
+        self.compact_if_stale_threshold_reached()?;
+        Ok(())
+    }
+    /// declare a secondary index named `name` over the index keys extracted from values (a value
+    /// from which None is extracted is not indexed), replacing any index of the same name
+    ///
+    /// The index is built from the values already stored and maintained as they are written. It
+    /// is held in memory only, so it must be declared again each time the store is opened.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// let _ = store.set("alice".into(),"admin:alice@example.com".into());
+    /// store
+    ///     .create_index("role", |value: &String| value.split(':').next().map(str::to_owned))
+    ///     .unwrap();
+    /// let _ = store.set("bob".into(),"admin:bob@example.com".into());
+    /// let mut admins = store.get_by_index("role", "admin").unwrap();
+    /// admins.sort();
+    /// assert_eq!(admins[0].0, "alice");
+    /// assert_eq!(admins[1].0, "bob");
+    /// ```
+    pub fn create_index<F>(&mut self, name: &str, extract: F) -> Result<()>
+    where
+        F: Fn(&V) -> Option<String> + Send + 'static,
+    {
+        let mut index = SecondaryIndex::new(extract);
+        self.build_secondary_index(&mut index)?;
+        self.secondary_indexes.insert(name.to_owned(), index);
+        Ok(())
+    }
+    /// the keys and values of every value with the given index key in the named secondary index
+    /// (in no particular order), IndexNotFound if no such index has been declared
+    pub fn get_by_index(&mut self, name: &str, index_key: &str) -> Result<Vec<(K, V)>> {
+        let keys = match self.secondary_indexes.get(name) {
+            Some(index) => index.keys(index_key).cloned().collect::<Vec<_>>(),
+            None => return Err(Error::new(ErrorKind::IndexNotFound)),
+        };
+        let mut found = Vec::with_capacity(keys.len());
+        for key in keys {
+            if let Some(value) = self.get(key.clone())? {
+                found.push((key, value));
+            }
+        }
+        Ok(found)
+    }
+    /// all keys currently present in the Key-Value Storage instance (in no particular order)
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// let _ = store.set("key1".into(),"value1".into());
+    /// let keys = store.keys().collect::<Vec<_>>();
+    /// assert_eq!(keys,vec!["key1"]);
+    /// ```
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.index.keys()
+    }
+    /// number of keys currently present in the Key-Value Storage instance
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// let _ = store.set("key1".into(),"value1".into());
+    /// assert_eq!(store.len(), 1);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+    /// true if no keys are present in the Key-Value Storage instance
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+    /// compact the log now, rather than waiting for enough stale records to accumulate
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// let _ = store.set("key1".into(),"value1".into());
+    /// let _ = store.set("key1".into(),"value2".into());
+    /// store.compact_now().unwrap();
+    /// assert_eq!(store.get("key1".into()).unwrap(), Some("value2".into()));
+    /// ```
+    pub fn compact_now(&mut self) -> Result<()> {
+        self.compact()
+    }
+    /// write a compacted copy of the log, holding only the current value of each key, to a new
+    /// store in the directory at `path` (leaving this store unchanged), InvalidConfiguration if
+    /// that directory already holds a log
+    ///
+    /// Keyspaces are not copied; each may be compacted into its own directory.
+    pub fn compact_into(&mut self, path: &Path) -> Result<()> {
+        let options = self.options();
+        if path.is_dir() && latest_log_for_dir(path, &options.file_prefix)?.is_some() {
+            return Err(Error::new(ErrorKind::InvalidConfiguration));
+        }
+        let mut target = KvStore::<K, V>::open_with_options(path, &options)?;
+        let keys = self.index.keys().cloned().collect::<Vec<_>>();
+        for keys in keys.chunks(COMPACT_INTO_BATCH_SIZE) {
+            let mut batch = WriteBatch::new();
+            for key in keys {
+                if let Some(value) = self.get(key.clone())? {
+                    batch.set(key.clone(), value);
+                }
+            }
+            target.write_batch(batch)?;
+        }
+        target.shutdown()
+    }
+    /// remove every key, replacing the log with a new empty one
+    ///
+    /// The empty log is written and synced under a temporary name, then renamed into place before
+    /// the old log is deleted, so a crash part way through leaves either the old or the new log.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// let _ = store.set("key1".into(),"value1".into());
+    /// store.clear().unwrap();
+    /// assert!(store.is_empty());
+    /// assert_eq!(store.get("key1".into()).unwrap(), None);
+    /// ```
+    pub fn clear(&mut self) -> Result<()> {
+        let cleared_path = make_next_db_log_path(self.file_path.clone());
+        let (reader, writer) = open_db_reader_and_writer(&*self.storage, &cleared_path, true)?;
+        self.preallocate_log(&writer)?;
+        writer.get_ref().sync()?;
+        let index = Index::with_hasher(self.index.hasher().clone());
+        let (_, _, _, orig_path) =
+            self.replace_reader_writer_index_file(reader, writer, index, cleared_path);
+        self.finalize_compacted_filename()?;
+        sync_dir_of(&self.file_path);
+        self.remove_file(&orig_path)?;
+        self.stale_count = 0;
+        self.trash.clear();
+        for index in self.secondary_indexes.values_mut() {
+            index.clear();
+        }
+        if let Some(usage) = &mut self.usage {
+            usage.clear();
+        }
+        self.subscribers.notify(KeyEvent::Cleared);
+        Ok(())
+    }
+    /// flush buffered writes and fsync the log so that all writes so far survive a crash
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// let _ = store.set("key1".into(),"value1".into());
+    /// store.sync().unwrap();
+    /// ```
+    pub fn sync(&mut self) -> Result<()> {
+        self.flush_log()?;
+        self.writer.get_ref().sync()?;
+        #[cfg(feature = "stats")]
+        self.backlog.synced();
+        Ok(())
+    }
+    /// sync the log, persist the index and write the clean-shutdown marker, after which the store
+    /// should not be written to again
+    ///
+    /// The marker is removed when the store is next opened, which reports whether it was found
+    /// through `was_shut_down_cleanly`. If it was, the persisted index is loaded (after checking
+    /// its checksum and that the log has not changed since) instead of replaying the log.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let dir = tempfile::TempDir::new().unwrap();
+    /// let mut store = KvStore::<String,String>::new(dir.path()).unwrap();
+    /// let _ = store.set("key1".into(),"value1".into());
+    /// store.shutdown().unwrap();
+    /// drop(store);
+    /// let store = KvStore::<String,String>::open(dir.path()).unwrap();
+    /// assert!(store.was_shut_down_cleanly());
+    /// ```
+    pub fn shutdown(&mut self) -> Result<()> {
+        self.sync()?;
+        let hint_path = self.file_path.with_extension("hint");
+        snapshot::write_index_snapshot(
+            &hint_path,
+            &self.file_path,
+            writer_position(&mut self.writer)?,
+            self.stale_count,
+            self.next_version,
+            &self.index,
+            &self.trash,
+        )?;
+        // the log is closed until the store is next opened, so it is recorded with its digest
+        let mut segment = segment_of(&self.file_path, Some(&hint_path));
+        segment.digest = Some(
+            Digest::of_file(&self.file_path)
+                .map_err(|err| Error::from(err).at_path(&self.file_path))?,
+        );
+        write_active_segment(&self.file_path, segment)?;
+        let marker_path = clean_shutdown_marker_path(&self.file_path);
+        fs::write(
+            &marker_path,
+            self.file_path
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .as_bytes(),
+        )?;
+        Ok(fs::File::open(marker_path)?.sync_all()?)
+    }
+    /// the directory holding the store's files
+    pub fn dir(&self) -> &Path {
+        db_dir_of(&self.file_path)
+    }
+    /// true if the store was shut down cleanly (with `shutdown`) before it was last opened
+    pub fn was_shut_down_cleanly(&self) -> bool {
+        self.was_shut_down_cleanly
+    }
+    /// latencies of the sets, gets, removes and compactions of this store since it was opened, the
+    /// distribution of the sizes of the keys and values it has written, and how much of what was
+    /// written to the log has not yet been flushed or synced
+    ///
+    /// Each write is flushed once made, but not synced, so `unsynced_bytes` is what an embedder
+    /// deciding when to call `sync` would look at. The sizes are those of the keys and values as
+    /// encoded in the log, which is what planning its capacity (or whether to compress values)
+    /// would look at.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// let _ = store.set("key1".into(),"value1".into());
+    /// let _ = store.get("key1".into());
+    /// let stats = store.stats();
+    /// assert_eq!((stats.set.count, stats.get.count, stats.remove.count), (1, 1, 0));
+    /// assert!(stats.set.p99 <= stats.set.max);
+    /// assert_eq!(stats.value_sizes.count, 1);
+    /// assert!(stats.key_sizes.max > 0);
+    /// assert!(stats.unsynced_bytes > 0);
+    /// store.sync().unwrap();
+    /// assert_eq!(store.stats().unsynced_bytes, 0);
+    /// ```
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> Stats {
+        self.latencies
+            .stats(&self.sizes, &self.backlog, self.writer.buffer().len())
+    }
+    /// rebuild the index, and any secondary indexes, by reading the log from the start as when the
+    /// store is opened (records after the first unreadable one are dropped from the index)
+    pub fn rebuild_index(&mut self) -> Result<()> {
+        self.rebuild_index_with_progress(|_| ())
+    }
+    /// rebuild the index as `rebuild_index` does, calling back with the progress made after each
+    /// record of the log is read
+    ///
+    /// This is how the store recovers at runtime from an IndexInconsistent error (which
+    /// `set_rebuild_index_on_inconsistency` has it do on its own), reporting how far through a
+    /// large log it is.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// let _ = store.set("key1".into(),"value1".into());
+    /// let mut records = 0;
+    /// store.rebuild_index_with_progress(|progress| records = progress.records).unwrap();
+    /// assert!(records >= 1);
+    /// ```
+    pub fn rebuild_index_with_progress<F>(&mut self, mut progress: F) -> Result<()>
+    where
+        F: FnMut(RebuildProgress),
+    {
+        self.flush_log()?;
+        let log_len = writer_position(&mut self.writer)?;
+        self.index.clear();
+        self.trash.clear();
+        self.stale_count = 0;
+        let started = Instant::now();
+        let mut replayed = 0;
+        self.load_index(|records, offset| {
+            replayed = records;
+            progress(RebuildProgress {
+                records,
+                offset,
+                log_len,
+            })
+        })?;
+        self.notify_event(|| StoreEvent::RecoveryPerformed {
+            records: replayed,
+            duration: started.elapsed(),
+        });
+        if let Some(policy) = self.usage.as_ref().map(Usage::policy) {
+            self.track_usage(policy);
+        }
+        let mut secondary_indexes = mem::take(&mut self.secondary_indexes);
+        let rebuilt = secondary_indexes.values_mut().try_for_each(|index| {
+            index.clear();
+            self.build_secondary_index(index)
+        });
+        self.secondary_indexes = secondary_indexes;
+        rebuilt
+    }
+    /// rebuild the index (see `rebuild_index`) and retry, rather than failing, when `get` finds the
+    /// index pointing at a record which is missing or belongs to another key
+    pub fn set_rebuild_index_on_inconsistency(&mut self, rebuild: bool) {
+        self.rebuild_index_on_inconsistency = rebuild;
+    }
+    /// open (creating it if it does not exist) the keyspace of the given name within this store's
+    /// directory, InvalidKeyspaceName unless the name is made up of ASCII letters, digits, `-`
+    /// and `_`
+    ///
+    /// Each keyspace is a separate store with its own index and log (and so its own compaction)
+    /// which may hold keys and values of different types. It starts with this store's compaction
+    /// settings, but not its merge operator or secondary indexes.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// let mut users = store.keyspace::<String,String>("users").unwrap();
+    /// let _ = users.set("alice".into(),"admin".into());
+    /// assert_eq!(users.get("alice".into()).unwrap(),Some("admin".into()));
+    /// assert_eq!(store.get("alice".into()).unwrap(),None);
+    /// ```
+    pub fn keyspace<K2, V2>(&self, name: &str) -> Result<KvStore<K2, V2>>
+    where
+        K2: Serialize + DeserializeOwned + Eq + PartialEq + hash::Hash + Clone + fmt::Debug,
+        V2: Serialize + DeserializeOwned + Clone,
+    {
+        let dir = Self::keyspace_path(
+            self.file_path.parent().unwrap_or_else(|| Path::new(".")),
+            name,
+        )?;
+        fs::create_dir_all(&dir)?;
+        let mut keyspace = KvStore::<K2, V2>::open_with_options(&dir, &self.options())?;
+        keyspace.stale_fraction_for_compaction = self.stale_fraction_for_compaction;
+        keyspace.min_records_before_compaction = self.min_records_before_compaction;
+        Ok(keyspace)
+    }
+    /// directory holding the keyspace of the given name of the store in `path`, InvalidKeyspaceName
+    /// if the name is not valid
+    pub fn keyspace_path(path: &Path, name: &str) -> Result<path::PathBuf> {
+        if !is_valid_keyspace_name(name) {
+            return Err(Error::new(ErrorKind::InvalidKeyspaceName));
+        }
+        Ok(path.join(KEYSPACES_DIR).join(name))
+    }
+    /// names of the keyspaces created within this store's directory (in no particular order)
+    pub fn keyspace_names(&self) -> Result<Vec<String>> {
+        let dir = self
+            .file_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join(KEYSPACES_DIR);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut names = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if let (true, Some(name)) = (entry.path().is_dir(), entry.file_name().to_str()) {
+                if is_valid_keyspace_name(name) {
+                    names.push(name.to_owned());
+                }
+            }
         }
+        Ok(names)
     }
 
-    fn init_self(db_path: &path::Path, do_truncate_on_open: bool) -> Result<Self> {
-        let (reader, writer) = open_db_reader_and_writer(db_path, do_truncate_on_open)?;
+    /// options this store was opened with
+    fn options(&self) -> StoreOptions {
+        StoreOptions {
+            file_prefix: file_prefix_of(&self.file_path),
+            max_keys: self.max_keys,
+            max_log_bytes: self.max_log_bytes,
+            eviction: self
+                .usage
+                .as_ref()
+                .map_or(EvictionPolicy::Reject, Usage::policy),
+            io_backend: self.io_backend,
+            write_mode: self.write_mode,
+            preallocate: self.preallocate,
+            clock: self.clock.clone(),
+            trash_retention: self.trash_retention,
+            max_stale_ratio: self.max_stale_ratio,
+            backpressure: self.backpressure,
+            index_hasher: self.index.hasher().clone(),
+            instrumented: self.instrumented,
+            record_format: self.record_format,
+        }
+    }
+    fn init_self(
+        db_path: &path::Path,
+        do_truncate_on_open: bool,
+        storage: sync::Arc<dyn OpenStorage>,
+        registration: Registration,
+    ) -> Result<Self> {
+        let (reader, writer) = open_db_reader_and_writer(&*storage, db_path, do_truncate_on_open)?;
+        let was_shut_down_cleanly = take_clean_shutdown_marker(db_path)?;
         Ok(Self {
-            index: HashMap::new(),
+            index: Index::default(),
             stale_count: 0,
+            next_version: 1,
             file_path: db_path.to_owned(),
             reader,
             writer,
+            storage,
+            scratch: Vec::new(),
             stale_fraction_for_compaction: 0.25,
             min_records_before_compaction: 100,
-            phantom_value: marker::PhantomData::default(),
+            was_shut_down_cleanly,
+            merge_operator: None,
+            rebuild_index_on_inconsistency: false,
+            secondary_indexes: HashMap::new(),
+            max_keys: None,
+            max_log_bytes: None,
+            io_backend: IoBackend::default(),
+            write_mode: WriteMode::default(),
+            preallocate: false,
+            clock: sync::Arc::new(SystemClock),
+            trash: HashMap::new(),
+            trash_retention: None,
+            max_stale_ratio: None,
+            backpressure: Backpressure::default(),
+            usage: None,
+            subscribers: Subscribers::new(),
+            events: EventSubscribers::new(),
+            hooks: Hooks::new(),
+            instrumented: true,
+            record_format: RecordFormat::default(),
+            #[cfg(feature = "stats")]
+            latencies: stats::Latencies::new(),
+            #[cfg(feature = "stats")]
+            sizes: stats::Sizes::new(),
+            #[cfg(feature = "stats")]
+            backlog: stats::Backlog::default(),
+            _registration: registration,
+            _temporary_dir: None,
+            phantom_value: marker::PhantomData,
         })
     }
-    fn load_index(&mut self) -> Result<()> {
-        while let Some(rec) = self.read_next_record()? {
+    /// indexes the records from the start of the log, calling back with the number read so far
+    /// and the offset of the last one after each
+    fn load_index(&mut self, mut progress: impl FnMut(u64, u64)) -> Result<()> {
+        self.reader.seek(io::SeekFrom::Start(0))?;
+        let (mut records, mut offset) = (0, 0);
+        while let Some(rec) = self.read_next_record(&mut offset)? {
+            records += 1;
+            progress(records, rec.db_key);
+            self.next_version = self.next_version.max(rec.version + 1);
+            let trashed = Trashed::of(&rec);
             match rec {
                 Record {
                     db_key,
                     key,
                     value: Some(_),
+                    ..
                 } => {
+                    self.trash.remove(&key);
                     if self.index.insert(key, db_key).is_some() {
                         self.stale_count += 1;
                     }
@@ -190,6 +1358,10 @@ where
                 Record {
                     key, value: None, ..
                 } => {
+                    match trashed {
+                        Some(trashed) => self.trash.insert(key.clone(), trashed),
+                        None => self.trash.remove(&key),
+                    };
                     self.index.remove(&key);
                     self.stale_count += 1;
                 }
@@ -197,32 +1369,197 @@ where
         }
         Ok(())
     }
-    fn read_next_record(&mut self) -> Result<Option<Record<K, V>>> {
-        let vec = &mut Vec::new();
-        let read_value =
-            serde_asn1_der::from_reader(&mut self.reader, serde_asn1_der::VecBacking(vec));
-        match read_value {
-            Ok(rec) => Ok(Some(rec)),
-            Err(serde_asn1_der::SerdeAsn1DerError::Asn1DerError(_)) => Ok(None),
-            Err(_) => Err(Error::new(ErrorKind::IoError)),
+    fn get_indexed(&mut self, key: &K) -> Result<Option<V>> {
+        let db_key = match self.index.get(key) {
+            Some(&db_key) => db_key,
+            None => return Ok(None),
+        };
+        resolve_value(&mut self.reader, self.merge_operator.as_ref(), key, db_key)
+    }
+    fn build_secondary_index(&mut self, index: &mut SecondaryIndex<K, V>) -> Result<()> {
+        for (key, &db_key) in &self.index {
+            let value = resolve_value(&mut self.reader, self.merge_operator.as_ref(), key, db_key)?;
+            index.update(key, value.as_ref());
         }
+        Ok(())
     }
-    fn read_next_record_value(&mut self) -> Result<Option<V>> {
-        match self.read_next_record() {
-            Ok(Some(rec)) => Ok(rec.value),
-            _ => Err(Error::new(ErrorKind::IoError)),
+    fn update_secondary_indexes(&mut self, key: &K, value: Option<&V>) {
+        for index in self.secondary_indexes.values_mut() {
+            index.update(key, value);
         }
     }
-    fn build_output_record(&mut self, key: &K, value: Option<V>) -> Result<Record<K, V>> {
+    /// reads the record at the position of the log's reader, which is at the offset, advancing the
+    /// offset past the record
+    fn read_next_record(&mut self, offset: &mut u64) -> Result<Option<Record<K, V>>> {
+        read_record_from(&mut self.reader, offset)
+    }
+    /// builds a record to be written, borrowing the key and value (records are only serialized)
+    fn build_output_record<'a>(
+        &mut self,
+        key: &'a K,
+        value: Option<&'a V>,
+    ) -> Result<Record<&'a K, &'a V>> {
+        let version = self.next_version;
+        self.next_version += 1;
         Ok(Record {
-            db_key: self.writer.get_ref().stream_position()?,
-            key: key.clone(),
+            db_key: writer_position(&mut self.writer)?,
+            key,
             value,
+            merge: false,
+            previous: None,
+            version,
+            timestamp: clock::millis_since_epoch(self.clock.now()),
         })
     }
-    fn write_record_to_db(&mut self, rec: Record<K, V>) -> Result<()> {
-        let writer = &mut self.writer;
-        write_record_to_writer(rec, writer)
+    fn write_record_to_db(&mut self, rec: Record<&K, &V>) -> Result<()> {
+        let record_start = rec.db_key;
+        let is_tombstone = rec.value.is_none();
+        let written = self
+            .write_record(rec)
+            .and_then(|_| match is_tombstone {
+                true => Ok(()),
+                false => self.check_log_quota(),
+            })
+            .and_then(|_| self.flush_log());
+        if written.is_err() {
+            self.discard_unwritten(record_start)?;
+        }
+        written
+    }
+    /// tracks the uses of keys for the eviction policy (not at all if it evicts nothing), ranking
+    /// the keys held as if used in the order their records were written
+    fn track_usage(&mut self, policy: EvictionPolicy) {
+        if policy == EvictionPolicy::Reject {
+            self.usage = None;
+            return;
+        }
+        let mut keys = self.index.iter().collect::<Vec<_>>();
+        keys.sort_unstable_by_key(|&(_, db_key)| db_key);
+        let mut usage = Usage::new(policy);
+        for (key, _) in keys {
+            usage.record(key);
+        }
+        self.usage = Some(usage);
+    }
+    /// notifies subscribers of the event of the key (cloning it only if there are any)
+    fn notify(&mut self, key: &K, event: fn(K) -> KeyEvent<K>) {
+        if !self.subscribers.is_empty() {
+            self.subscribers.notify(event(key.clone()));
+        }
+    }
+    fn record_use(&mut self, key: &K) {
+        if let Some(usage) = &mut self.usage {
+            usage.record(key);
+        }
+    }
+    /// removes keys other than those spared, as ranked by the eviction policy (if the store has
+    /// one), until a write leaving the store with `key_count` keys would keep it within its
+    /// maximum number of keys, returning the number of keys it will hold after the write instead
+    fn evict(&mut self, mut key_count: usize, spared: impl Fn(&K) -> bool) -> Result<usize> {
+        let max_keys = match self.max_keys {
+            Some(max_keys) => max_keys,
+            None => return Ok(key_count),
+        };
+        while key_count as u64 > max_keys {
+            match self.usage.as_ref().and_then(|usage| usage.victim(&spared)) {
+                Some(victim) => self.remove_key(victim, true)?,
+                None => break,
+            }
+            key_count -= 1;
+        }
+        Ok(key_count)
+    }
+    /// QuotaExceeded if the store would hold more than its maximum number of keys after a write
+    /// leaving it with `key_count` keys
+    fn check_key_quota(&self, key_count: usize) -> Result<()> {
+        match self.max_keys {
+            Some(max_keys) if key_count as u64 > max_keys => {
+                Err(Error::new(ErrorKind::QuotaExceeded))
+            }
+            _ => Ok(()),
+        }
+    }
+    /// QuotaExceeded if what has been written to the log (flushed or not) takes it past its
+    /// maximum length
+    fn check_log_quota(&mut self) -> Result<()> {
+        match self.max_log_bytes {
+            Some(max_log_bytes) if writer_position(&mut self.writer)? > max_log_bytes => {
+                Err(Error::new(ErrorKind::QuotaExceeded).at_path(&self.file_path))
+            }
+            _ => Ok(()),
+        }
+    }
+    /// allocates the blocks of the log written by the writer up to the maximum length of the log,
+    /// if the store preallocates its logs
+    fn preallocate_log(&self, writer: &LogWriter) -> Result<()> {
+        match self.max_log_bytes {
+            Some(max_log_bytes) if self.preallocate => {
+                Ok(writer.get_ref().preallocate(max_log_bytes)?)
+            }
+            _ => Ok(()),
+        }
+    }
+    /// drops whatever of a failed write is still buffered (rather than letting it reach the log
+    /// with a later write) and truncates the log back to the length it had before the write
+    fn discard_unwritten(&mut self, log_len: u64) -> Result<()> {
+        let writer = self.storage.open_writer(&self.file_path, false)?;
+        let _ = mem::replace(&mut self.writer, io::BufWriter::new(writer)).into_parts();
+        self.writer.get_ref().set_len(log_len)?;
+        self.writer.seek(io::SeekFrom::Start(log_len))?;
+        #[cfg(feature = "stats")]
+        self.backlog.discarded();
+        Ok(())
+    }
+    /// appends the record to the log (see `write_record_to_writer`), in the write buffer until the
+    /// log is flushed
+    fn write_record(&mut self, rec: Record<&K, &V>) -> Result<()> {
+        let has_value = rec.value.is_some();
+        let (key, value) =
+            write_record_to_writer(rec, self.record_format, &mut self.scratch, &mut self.writer)?;
+        #[cfg(feature = "stats")]
+        {
+            // the sizes are those of the fields of the record just serialized
+            if self.is_instrumented() {
+                self.sizes
+                    .record(key.len(), has_value.then_some(value.len()));
+            }
+            self.backlog.written(self.scratch.len());
+        }
+        #[cfg(not(feature = "stats"))]
+        let _ = (has_value, key, value);
+        Ok(())
+    }
+    /// hands what is in the write buffer to the log file
+    fn flush_log(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        #[cfg(feature = "stats")]
+        self.backlog.flushed();
+        Ok(())
+    }
+    /// runs the operation, recording its latency if the `stats` feature is enabled and the store
+    /// is instrumented
+    fn timed<T>(&mut self, operation: Operation, run: impl FnOnce(&mut Self) -> T) -> T {
+        #[cfg(feature = "stats")]
+        if self.is_instrumented() {
+            let started = std::time::Instant::now();
+            let result = run(self);
+            self.latencies.record(operation, started.elapsed());
+            return result;
+        }
+        let _ = operation;
+        run(self)
+    }
+    /// true if the `instrumentation` feature is enabled and the store is instrumented (a
+    /// constant false without the feature, so what it guards is compiled out)
+    pub(crate) fn is_instrumented(&self) -> bool {
+        cfg!(feature = "instrumentation") && self.instrumented
+    }
+    /// sends the event, made only then, to the subscribers of the store's events if the store is
+    /// instrumented
+    pub(crate) fn notify_event(&mut self, event: impl FnOnce() -> StoreEvent) {
+        if self.is_instrumented() {
+            self.events.notify(event());
+        }
     }
     fn compact_if_stale_threshold_reached(&mut self) -> Result<()> {
         if self.index.len() as u64 >= self.min_records_before_compaction
@@ -231,22 +1568,36 @@ where
         {
             self.compact()?;
         }
-        assert!(
-            self.index.len() < usize::MAX && (self.index.len() as u64) < u64::MAX,
-            "Maximum Database size reached - unable to continue"
-        );
         Ok(())
     }
     fn compact(&mut self) -> Result<()> {
+        if !self.is_instrumented() {
+            return self.compact_untimed();
+        }
+        self.events.notify(StoreEvent::CompactionStarted {
+            stale_records: self.stale_count,
+        });
+        let started = Instant::now();
+        let compacted = self.timed(Operation::Compaction, Self::compact_untimed);
+        self.events.notify(StoreEvent::CompactionFinished {
+            duration: started.elapsed(),
+            succeeded: compacted.is_ok(),
+        });
+        compacted
+    }
+    fn compact_untimed(&mut self) -> Result<()> {
         let compact_path = make_next_db_log_path(self.file_path.clone());
         match self.copy_active_records_to_compaction_file_and_update_indexes(compact_path.clone()) {
             Err(err) => {
                 self.remove_file(&compact_path)?;
-                return Err(err);
+                return Err(
+                    Error::with_source(ErrorKind::CompactionFailed, err).at_path(&compact_path)
+                );
             }
             Ok((_, _, _, orig_path)) => {
-                self.finalize_compacted_filename()?;
-                self.remove_file(&orig_path)?;
+                self.finalize_compacted_filename()
+                    .and_then(|_| self.remove_file(&orig_path))
+                    .map_err(|err| Error::with_source(ErrorKind::CompactionFailed, err))?;
                 self.stale_count = 0;
             }
         }
@@ -257,140 +1608,511 @@ where
     fn copy_active_records_to_compaction_file_and_update_indexes(
         &mut self,
         compact_file_path: path::PathBuf,
-    ) -> Result<(
-        io::BufReader<fs::File>,
-        io::BufWriter<fs::File>,
-        HashMap<K, u64>,
-        path::PathBuf,
-    )> {
+    ) -> Result<(LogReader, LogWriter, Index<K>, path::PathBuf)> {
         let (compacted_reader, mut compacted_writer) =
-            open_db_reader_and_writer(&compact_file_path, true)?;
-        let mut compacted_index = HashMap::new();
+            open_db_reader_and_writer(&*self.storage, &compact_file_path, true)?;
+        self.preallocate_log(&compacted_writer)?;
+        let mut compacted_index = Index::with_hasher(self.index.hasher().clone());
+        // tombstones are dropped unless a retained segment holds a record of their key, which they
+        // must go on hiding, in which case the latest tombstone of the key is kept
+        let retained_keys = self.keys_of_retained_segments()?;
+        let mut retained_tombstones = HashMap::new();
+        // merged values are resolved with their own reader so the scan of the log is undisturbed
+        let mut merge_reader = None;
         self.reader.seek(io::SeekFrom::Start(0))?;
-        while let Some(mut rec) = self.read_next_record()? {
+        let mut offset = 0;
+        while let Some(mut rec) = self.read_next_record(&mut offset)? {
             match self.index.get(&rec.key) {
                 Some(current_db_key) if *current_db_key == rec.db_key => {
-                    let (key, db_key) = (rec.key.clone(), compacted_writer.stream_position()?);
+                    if rec.merge {
+                        let merge_reader = match &mut merge_reader {
+                            Some(merge_reader) => merge_reader,
+                            None => merge_reader.insert(io::BufReader::new(
+                                self.storage.open_reader(&self.file_path)?,
+                            )),
+                        };
+                        rec.value = resolve_value(
+                            merge_reader,
+                            self.merge_operator.as_ref(),
+                            &rec.key,
+                            rec.db_key,
+                        )?;
+                        rec.merge = false;
+                        rec.previous = None;
+                    }
+                    let (key, db_key) = (rec.key.clone(), writer_position(&mut compacted_writer)?);
                     rec.db_key = db_key;
-                    write_record_to_writer(rec, &mut compacted_writer)?;
+                    write_record_to_writer(
+                        rec,
+                        self.record_format,
+                        &mut self.scratch,
+                        &mut compacted_writer,
+                    )?;
                     compacted_index.insert(key, db_key);
                 }
+                None if rec.value.is_none() && retained_keys.contains(&rec.key) => {
+                    retained_tombstones.insert(rec.key.clone(), rec);
+                }
                 _ => (),
             }
         }
-        Ok(self.replace_reader_writer_index_file(
+        // keys kept in the trash are written with their value and a tombstone pointing back at it,
+        // those which have been in it for longer than its retention being dropped for good
+        let compacted_trash = self.copy_trash_to(&mut compacted_writer)?;
+        // the keys are not in the index, so no later record of them is being written
+        for (key, mut rec) in retained_tombstones {
+            if compacted_trash.contains_key(&key) {
+                continue;
+            }
+            rec.db_key = writer_position(&mut compacted_writer)?;
+            rec.previous = None;
+            write_record_to_writer(
+                rec,
+                self.record_format,
+                &mut self.scratch,
+                &mut compacted_writer,
+            )?;
+        }
+        compacted_writer.flush()?;
+        compacted_writer.get_ref().sync()?;
+        let replaced = self.replace_reader_writer_index_file(
             compacted_reader,
             compacted_writer,
             compacted_index,
             compact_file_path,
-        ))
+        );
+        self.trash = compacted_trash;
+        Ok(replaced)
+    }
+    /// keys with records in the segments compaction retains: every segment but the active one,
+    /// which is the only one compacted
+    fn keys_of_retained_segments(&self) -> Result<HashSet<K>> {
+        let dir = db_dir_of(&self.file_path);
+        let mut keys = HashSet::new();
+        if let Some(manifest) = manifest::read_manifest(dir, &file_prefix_of(&self.file_path))? {
+            for segment in &manifest.segments[..manifest.segments.len() - 1] {
+                let log_path = dir.join(&segment.log);
+                let log = self
+                    .storage
+                    .open_reader(&log_path)
+                    .map_err(|err| Error::from(err).at_path(&log_path))?;
+                let (mut reader, mut offset) = (io::BufReader::new(log), 0);
+                while let Some(rec) = read_record_from::<_, K, V>(&mut reader, &mut offset)? {
+                    keys.insert(rec.key);
+                }
+            }
+        }
+        Ok(keys)
     }
     #[allow(clippy::type_complexity)]
     fn replace_reader_writer_index_file(
         &mut self,
-        mut reader: io::BufReader<fs::File>,
-        mut writer: io::BufWriter<fs::File>,
-        mut index: HashMap<K, u64>,
+        mut reader: LogReader,
+        mut writer: LogWriter,
+        mut index: Index<K>,
         mut file_path: path::PathBuf,
-    ) -> (
-        io::BufReader<fs::File>,
-        io::BufWriter<fs::File>,
-        HashMap<K, u64>,
-        path::PathBuf,
-    ) {
+    ) -> (LogReader, LogWriter, Index<K>, path::PathBuf) {
         mem::swap(&mut reader, &mut self.reader);
         mem::swap(&mut writer, &mut self.writer);
         mem::swap(&mut index, &mut self.index);
+        // the log replacing the store's was synced once written
+        #[cfg(feature = "stats")]
+        self.backlog.synced();
         mem::swap(&mut file_path, &mut self.file_path);
         (reader, writer, index, file_path)
     }
     fn remove_file(&self, compacted_path: &path::Path) -> Result<()> {
-        fs::remove_file(compacted_path)?;
-        Ok(())
+        fs::remove_file(compacted_path).map_err(|err| Error::from(err).at_path(compacted_path))
     }
+    /// renames the compacted (or cleared) log into place and makes it the active log by naming it
+    /// in the manifest, after which the previous log may be removed
     fn finalize_compacted_filename(&mut self) -> Result<()> {
         let final_path = self.file_path.with_extension("log");
         fs::rename(&self.file_path, &final_path)?;
         self.file_path = final_path;
+        write_manifest_of(&self.file_path, None)?;
+        if self.is_instrumented() {
+            self.events.notify(StoreEvent::SegmentRotated {
+                log: self.file_path.clone(),
+            });
+        }
         Ok(())
     }
 }
 
-fn ensure_dir_exists(path: &Path) {
-    if !path.exists() {
-        let _ = fs::create_dir(path);
+fn is_valid_keyspace_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+fn is_valid_file_prefix(prefix: &str) -> bool {
+    // the same characters as keyspace names, which are safe in file names on every platform
+    is_valid_keyspace_name(prefix)
+}
+/// creates the directory of a store (and its parents) if it does not exist, returning the path
+/// its files are reached through (on Windows, the verbatim `\\?\` form, which is not limited to
+/// 260 characters), InvalidConfiguration if the path is of something other than a directory
+fn data_dir(path: &Path) -> Result<path::PathBuf> {
+    if path.exists() && !path.is_dir() {
+        return Err(Error::new(ErrorKind::InvalidConfiguration).at_path(path));
+    }
+    fs::create_dir_all(path).map_err(|err| Error::from(err).at_path(path))?;
+    #[cfg(windows)]
+    return fs::canonicalize(path).map_err(|err| Error::from(err).at_path(path));
+    #[cfg(not(windows))]
+    Ok(path.to_owned())
+}
+/// finds (or for a new store, names) the active log of the store in the directory, removing the
+/// files of the store its manifest does not list and writing the manifest if there is none
+fn use_existing_or_create_new_db_log_path(
+    path: &Path,
+    prefix: &str,
+) -> Result<(path::PathBuf, Manifest)> {
+    let db_path = match latest_log_for_dir(path, prefix)? {
+        Some(db_path) => db_path,
+        None => make_db_log_path(path, prefix, 0),
+    };
+    let manifest = match manifest::read_manifest(path, prefix)? {
+        Some(manifest) => manifest,
+        None => {
+            // the log must exist before the manifest lists it
+            let create = || {
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&db_path)
+            };
+            create()
+                .and_then(|log| log.sync_all())
+                .map_err(|err| Error::from(err).at_path(&db_path))?;
+            write_manifest_of(&db_path, None)?;
+            Manifest::single(segment_of(&db_path, None))
+        }
+    };
+    remove_unlisted_files(path, prefix, &manifest)?;
+    Ok((db_path, manifest))
+}
+/// the segment made up of the log at db_path and the hint file (if any)
+fn segment_of(db_path: &Path, hint_path: Option<&Path>) -> Segment {
+    let file_name = |path: &Path| path.file_name().unwrap().to_string_lossy().into_owned();
+    Segment {
+        generation: db_file_generation(db_path, &file_prefix_of(db_path), "log")
+            .unwrap_or_default(),
+        log: file_name(db_path),
+        hint: hint_path.map(file_name),
+        digest: None,
     }
-    assert!(path.is_dir());
 }
-fn use_existing_or_create_new_db_log_path(path: &Path) -> Result<path::PathBuf> {
-    let db_path = match latest_log_for_dir(path) {
-        Ok(Some(path)) => path,
-        Ok(None) => make_db_log_path(path),
-        Err(err) => return Err(err),
+/// writes the manifest of the store, its active segment being the log at db_path and the hint
+/// file (keeping any older segments the manifest already lists)
+fn write_manifest_of(db_path: &Path, hint_path: Option<&Path>) -> Result<()> {
+    write_active_segment(db_path, segment_of(db_path, hint_path))
+}
+/// writes the manifest of the store whose active log is at db_path, its active segment being the
+/// given one (keeping any older segments the manifest already lists)
+fn write_active_segment(db_path: &Path, segment: Segment) -> Result<()> {
+    let (dir, prefix) = (db_dir_of(db_path), file_prefix_of(db_path));
+    let manifest = match manifest::read_manifest(dir, &prefix)? {
+        Some(mut manifest) => {
+            *manifest.segments.last_mut().unwrap() = segment;
+            manifest
+        }
+        None => Manifest::single(segment),
     };
-    Ok(db_path)
+    manifest::write_manifest(dir, &prefix, &manifest)
+}
+fn db_dir_of(db_path: &Path) -> &Path {
+    db_path.parent().unwrap_or_else(|| Path::new("."))
 }
-fn latest_log_for_dir(path: &path::Path) -> Result<Option<path::PathBuf>> {
-    let mut max_modified = None;
-    let mut existing_path = None;
-    for entry in fs::read_dir(path)? {
-        let entry = entry?;
-        let path = entry.path();
-        if let (true, Some(filestem), Some(extension)) =
-            (path.is_file(), path.file_stem(), path.extension())
+/// takes the index snapshot from the hint file of the active segment (if there is one), which is
+/// consumed: it is first removed from the manifest, then deleted
+fn take_hint<K>(
+    db_path: &Path,
+    manifest: &Manifest,
+    hasher: &IndexHasher,
+) -> Result<Option<snapshot::LoadedIndex<K>>>
+where
+    K: DeserializeOwned + Eq + hash::Hash,
+{
+    let hint_path = match &manifest.active().hint {
+        Some(hint) => db_path.with_file_name(hint),
+        None => return Ok(None),
+    };
+    write_manifest_of(db_path, None)?;
+    snapshot::take_index_snapshot(&hint_path, db_path, hasher)
+}
+/// logs (or other files of the store, such as compacted logs with `extension` "compact") of the
+/// store in the directory, with their generations
+fn db_files_in_dir(
+    path: &path::Path,
+    prefix: &str,
+    extension: &str,
+) -> Result<Vec<(u128, path::PathBuf)>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path).map_err(|err| Error::from(err).at_path(path))? {
+        let path = entry?.path();
+        if let (true, Some(generation)) =
+            (path.is_file(), db_file_generation(&path, prefix, extension))
         {
-            if let (Some(filestem), Some(extension)) = (filestem.to_str(), extension.to_str()) {
-                if filestem.starts_with("kvsdb-") && filestem.len() == 38 && extension == "log" {
-                    let last_modified = entry.metadata()?.modified()?;
-                    if max_modified.is_none() || last_modified > max_modified.unwrap() {
-                        max_modified = Some(last_modified);
-                        existing_path = Some(path);
-                    }
+            files.push((generation, path));
+        }
+    }
+    Ok(files)
+}
+/// the active log of the store in the directory: the log of the active segment listed by its
+/// manifest or, if it has no manifest (being written before manifests were), the log of the
+/// highest generation
+fn latest_log_for_dir(path: &path::Path, prefix: &str) -> Result<Option<path::PathBuf>> {
+    let logs = db_files_in_dir(path, prefix, "log")?;
+    match manifest::read_manifest(path, prefix)? {
+        Some(manifest) => {
+            let active = manifest.active();
+            match logs.into_iter().find(|(gen, _)| *gen == active.generation) {
+                Some((_, log)) => Ok(Some(log)),
+                None => {
+                    Err(Error::new(ErrorKind::CorruptManifest).at_path(&path.join(&active.log)))
                 }
             }
         }
+        None => Ok(logs.into_iter().max().map(|(_, log)| log)),
+    }
+}
+/// removes the files of the store which the manifest does not list: any compacted log, and any
+/// other log or hint file, left behind by a crash part way through compacting (or clearing) the
+/// log, opening or shutting down, as well as the index snapshot written by earlier versions
+///
+/// A compacted log is only renamed into place once it is complete and synced, and only becomes
+/// the active log once the manifest lists it, which is done before the previous log is removed.
+fn remove_unlisted_files(path: &path::Path, prefix: &str, manifest: &Manifest) -> Result<()> {
+    let listed_logs = manifest
+        .segments
+        .iter()
+        .map(|segment| segment.generation)
+        .collect::<HashSet<_>>();
+    let listed_hints = manifest
+        .segments
+        .iter()
+        .filter_map(|segment| segment.hint.as_ref())
+        .filter_map(|hint| db_file_generation(Path::new(hint), prefix, "hint"))
+        .collect::<HashSet<_>>();
+    let mut unlisted = db_files_in_dir(path, prefix, "compact")?;
+    unlisted.extend(
+        db_files_in_dir(path, prefix, "log")?
+            .into_iter()
+            .filter(|(generation, _)| !listed_logs.contains(generation)),
+    );
+    unlisted.extend(
+        db_files_in_dir(path, prefix, "hint")?
+            .into_iter()
+            .filter(|(generation, _)| !listed_hints.contains(generation)),
+    );
+    let legacy_snapshot = path.join(format!("{}.index", prefix));
+    if legacy_snapshot.is_file() {
+        unlisted.push((0, legacy_snapshot));
+    }
+    for (_, unlisted_path) in &unlisted {
+        fs::remove_file(unlisted_path).map_err(|err| Error::from(err).at_path(unlisted_path))?;
+    }
+    if let Some((_, unlisted_path)) = unlisted.first() {
+        sync_dir_of(unlisted_path);
     }
-    Ok(existing_path)
+    Ok(())
 }
-fn make_db_log_path(path: &Path) -> path::PathBuf {
-    let uuid = uuid::Uuid::new_v4().to_simple();
-    path.join(path::Path::new(&format!("kvsdb-{}.log", uuid)))
+/// generation of the file if it is named `<prefix>-<generation>.<extension>`, the generation
+/// being 32 hex digits, ignoring case (as file systems on Windows and macOS do)
+///
+/// Logs written before generations were used are named with a random UUID instead, which is read
+/// as the generation, so the log written by their next compaction follows them.
+fn db_file_generation(path: &Path, prefix: &str, extension: &str) -> Option<u128> {
+    let (stem, file_extension) = (path.file_stem()?.to_string_lossy(), path.extension()?);
+    let stem_prefix = stem.get(..prefix.len())?;
+    let generation = stem[prefix.len()..].strip_prefix('-')?;
+    match stem_prefix.eq_ignore_ascii_case(prefix)
+        && file_extension.eq_ignore_ascii_case(extension)
+        && generation.len() == 32
+        && generation.bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        true => u128::from_str_radix(generation, 16).ok(),
+        false => None,
+    }
+}
+/// prefix of the names of the files of the store whose log is at db_path
+fn file_prefix_of(db_path: &Path) -> String {
+    let stem = db_path.file_stem().unwrap_or_default().to_string_lossy();
+    match stem.rsplit_once('-') {
+        Some((prefix, _)) => prefix.to_owned(),
+        None => DEFAULT_FILE_PREFIX.to_owned(),
+    }
 }
+fn make_db_log_path(path: &Path, prefix: &str, generation: u128) -> path::PathBuf {
+    path.join(path::Path::new(&format!(
+        "{}-{:032x}.log",
+        prefix, generation
+    )))
+}
+/// path of the log to be written by the next compaction (or clear) of the log at existing_path,
+/// with the next generation and the "compact" extension until it is complete
 fn make_next_db_log_path(mut existing_path: path::PathBuf) -> path::PathBuf {
+    let prefix = file_prefix_of(&existing_path);
+    let generation = db_file_generation(&existing_path, &prefix, "log").unwrap_or_default();
     existing_path.pop();
-    make_db_log_path(&existing_path).with_extension("compact")
+    make_db_log_path(&existing_path, &prefix, generation.wrapping_add(1)).with_extension("compact")
+}
+/// fsync the directory containing the path so that a rename within it is durable
+fn sync_dir_of(path: &path::Path) {
+    // directories cannot be opened (or synced) as files on all platforms, so this is best effort
+    if let Some(Ok(dir)) = path.parent().map(fs::File::open) {
+        let _ = dir.sync_all();
+    }
+}
+fn clean_shutdown_marker_path(db_path: &path::Path) -> path::PathBuf {
+    db_path.with_file_name(format!("{}.clean", file_prefix_of(db_path)))
+}
+/// removes the clean-shutdown marker (if any) from the directory of the log, returning whether the
+/// marker was present and named the log being opened
+fn take_clean_shutdown_marker(db_path: &path::Path) -> Result<bool> {
+    let marker_path = clean_shutdown_marker_path(db_path);
+    let marked_log = match fs::read_to_string(&marker_path) {
+        Ok(marked_log) => marked_log,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err.into()),
+    };
+    fs::remove_file(&marker_path)?;
+    Ok(db_path
+        .file_name()
+        .is_some_and(|log| log.to_string_lossy() == marked_log))
 }
 fn open_db_reader_and_writer(
+    storage: &dyn OpenStorage,
     db_path: &path::Path,
     truncate: bool,
-) -> Result<(io::BufReader<fs::File>, io::BufWriter<fs::File>)> {
-    let mut file = fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(truncate)
-        .open(db_path)?;
-    if !truncate {
-        file.seek(io::SeekFrom::End(0))?;
-    }
-    Ok((
-        io::BufReader::new(fs::OpenOptions::new().read(true).open(db_path)?),
-        io::BufWriter::new(file),
-    ))
+) -> Result<(LogReader, LogWriter)> {
+    let open = || -> io::Result<_> {
+        let writer = storage.open_writer(db_path, truncate)?;
+        Ok((
+            io::BufReader::new(storage.open_reader(db_path)?),
+            io::BufWriter::new(writer),
+        ))
+    };
+    open().map_err(|err| Error::from(err).at_path(db_path))
 }
+/// reads the record at the position of the reader, which is at the offset in the log, advancing
+/// the offset past the record
+fn read_record_from<R, K, V>(
+    reader: &mut io::BufReader<R>,
+    offset: &mut u64,
+) -> Result<Option<Record<K, V>>>
+where
+    R: io::Read + io::Seek,
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    codec::read_record(reader, offset)
+}
+/// reads the value of the record at db_key, applying the merge operator to any chain of merge
+/// operand records ending there (oldest operand first)
+fn resolve_value<R, K, V>(
+    reader: &mut io::BufReader<R>,
+    merge_operator: Option<&MergeOperator<K, V>>,
+    key: &K,
+    db_key: u64,
+) -> Result<Option<V>>
+where
+    R: io::Read + io::Seek,
+    K: DeserializeOwned + PartialEq + fmt::Debug,
+    V: DeserializeOwned,
+{
+    Ok(resolve_versioned_value(reader, merge_operator, key, db_key)?.0)
+}
+/// reads the value of the record at db_key as `resolve_value` does, with the version of that
+/// record (the latest write of the key)
+fn resolve_versioned_value<R, K, V>(
+    reader: &mut io::BufReader<R>,
+    merge_operator: Option<&MergeOperator<K, V>>,
+    key: &K,
+    db_key: u64,
+) -> Result<(Option<V>, Version)>
+where
+    R: io::Read + io::Seek,
+    K: DeserializeOwned + PartialEq + fmt::Debug,
+    V: DeserializeOwned,
+{
+    let mut version = None;
+    let mut operands = Vec::new();
+    let mut next = Some(db_key);
+    let mut value = None;
+    while let Some(db_key) = next {
+        let mut offset = db_key;
+        let rec = reader
+            .seek(io::SeekFrom::Start(db_key))
+            .map_err(Error::from)
+            .and_then(|_| read_record_from::<_, K, V>(reader, &mut offset));
+        let rec = match rec {
+            Ok(Some(rec)) if rec.db_key == db_key && rec.key == *key => rec,
+            // failing to read the file at all, or a record changed since it was written, says
+            // nothing about the index
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    ErrorKind::IoError | ErrorKind::CorruptLog { .. }
+                ) =>
+            {
+                return Err(err.at_offset(db_key).for_key(key))
+            }
+            _ => {
+                return Err(Error::new(ErrorKind::IndexInconsistent { offset: db_key })
+                    .at_offset(db_key)
+                    .for_key(key))
+            }
+        };
+        version.get_or_insert(Version::of(&rec));
+        if !rec.merge {
+            value = rec.value;
+            break;
+        }
+        operands.push(rec.value.ok_or_else(|| {
+            Error::new(ErrorKind::CorruptLog { offset: db_key })
+                .at_offset(db_key)
+                .for_key(key)
+        })?);
+        next = rec.previous;
+    }
+    // the loop reads at least the record at db_key
+    let version = version.unwrap_or_default();
+    if operands.is_empty() {
+        return Ok((value, version));
+    }
+    let merge_operator =
+        merge_operator.ok_or_else(|| Error::new(ErrorKind::MergeOperatorMissing))?;
+    let value = operands.into_iter().rev().fold(value, |value, operand| {
+        Some(merge_operator(key, value, operand))
+    });
+    Ok((value, version))
+}
+/// serializes the record in the format into the scratch buffer (reused from one record to the
+/// next, rather than allocating for each) and appends it to the log, which is left untouched if
+/// serializing fails, returning the ranges of the scratch buffer its key and value are serialized
+/// in
 fn write_record_to_writer<K, V>(
     rec: Record<K, V>,
-    mut writer: &mut io::BufWriter<fs::File>,
-) -> Result<()>
+    format: RecordFormat,
+    scratch: &mut Vec<u8>,
+    writer: &mut impl Write,
+) -> Result<(Range<usize>, Range<usize>)>
 where
-    K: Serialize + DeserializeOwned + Eq + PartialEq + hash::Hash + Clone,
-    V: Serialize + DeserializeOwned + Clone,
+    K: Serialize,
+    V: Serialize,
 {
-    if serde_asn1_der::to_writer(&rec, &mut writer).is_err() {
-        writer.seek(io::SeekFrom::Start(rec.db_key))?;
-        writer.get_mut().set_len(rec.db_key)?;
-        return Err(Error::new(ErrorKind::IoError));
-    }
-    Ok(writer.flush()?)
+    scratch.clear();
+    let fields =
+        codec::encode_record(&rec, format, scratch).map_err(|err| err.at_offset(rec.db_key))?;
+    writer.write_all(scratch)?;
+    Ok(fields)
+}
+/// position at which the next record will be written (accounting for data not yet flushed)
+fn writer_position(writer: &mut LogWriter) -> Result<u64> {
+    Ok(writer.get_mut().stream_position()? + writer.buffer().len() as u64)
 }
 
 #[cfg(test)]
@@ -0,0 +1,139 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::{clock::millis_since_epoch, KvStore, Result};
+
+/// Expiring lock held in a KvStore, from `acquire_lock` or `refresh_lock`
+///
+/// The key named after the lock holds its fencing token and when it expires. A holder which does
+/// not refresh the lock before it expires loses it to the next to acquire it, so the resources a
+/// lock guards should reject requests made with a token lower than the highest they have seen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lock {
+    /// name of the lock (the key holding it)
+    pub name: String,
+    /// fencing token of this holding of the lock, greater than the token of every earlier holding
+    pub token: u64,
+    /// time at which the lock expires unless it is refreshed
+    pub expires: SystemTime,
+}
+
+/// token and expiry (in milliseconds since the Unix epoch) held under a lock's key
+struct LockState {
+    token: u64,
+    expires: u64,
+}
+
+impl LockState {
+    fn new(token: u64, ttl: Duration, now: u64) -> Self {
+        Self {
+            token,
+            expires: now.saturating_add(ttl.as_millis() as u64),
+        }
+    }
+    fn parse(value: &str) -> Option<Self> {
+        let (token, expires) = value.split_once(' ')?;
+        Some(Self {
+            token: token.parse().ok()?,
+            expires: expires.parse().ok()?,
+        })
+    }
+    fn value(&self) -> String {
+        format!("{} {}", self.token, self.expires)
+    }
+    fn held(&self, now: u64) -> bool {
+        self.expires > now
+    }
+    fn lock(&self, name: &str) -> Lock {
+        Lock {
+            name: name.to_owned(),
+            token: self.token,
+            expires: UNIX_EPOCH + Duration::from_millis(self.expires),
+        }
+    }
+}
+
+impl KvStore<String, String> {
+    /// acquire the lock of the given name for the ttl, None if another holder has it (and it has
+    /// not expired)
+    ///
+    /// A key of the name which does not hold a lock is taken to hold one which never expires.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    /// use std::time::Duration;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// let lock = store.acquire_lock("job", Duration::from_secs(10)).unwrap().unwrap();
+    /// assert_eq!(store.acquire_lock("job", Duration::from_secs(10)).unwrap(), None);
+    /// assert!(store.release_lock("job", lock.token).unwrap());
+    /// ```
+    pub fn acquire_lock(&mut self, name: &str, ttl: Duration) -> Result<Option<Lock>> {
+        // the token is the sequence number of the write taking the lock, so it exceeds that of
+        // every earlier write, including those which took the lock before
+        let token = self.next_version;
+        let now = millis_since_epoch(self.clock.now());
+        self.acquire_lock_at(name, ttl, token, now)
+    }
+    /// acquire the lock as `acquire_lock` does at the given time (in milliseconds since the Unix
+    /// epoch) with the given token, which must exceed that of every earlier holding of the lock
+    pub(crate) fn acquire_lock_at(
+        &mut self,
+        name: &str,
+        ttl: Duration,
+        token: u64,
+        now: u64,
+    ) -> Result<Option<Lock>> {
+        let expired = match self.get_versioned(name.to_owned())? {
+            Some((value, version)) => match LockState::parse(&value) {
+                Some(state) if !state.held(now) => Some(version),
+                _ => return Ok(None),
+            },
+            None => None,
+        };
+        let state = LockState::new(token, ttl, now);
+        let acquired = match expired {
+            Some(version) => self.set_if_version(name.to_owned(), state.value(), version)?,
+            None => self.set_if_absent(name.to_owned(), state.value())?,
+        };
+        Ok(acquired.then(|| state.lock(name)))
+    }
+    /// extend the lock of the given name, held with the token, to expire after the ttl, None if
+    /// it is no longer held with the token (having expired)
+    pub fn refresh_lock(&mut self, name: &str, token: u64, ttl: Duration) -> Result<Option<Lock>> {
+        let now = millis_since_epoch(self.clock.now());
+        self.refresh_lock_at(name, token, ttl, now)
+    }
+    /// extend the lock as `refresh_lock` does at the given time (in milliseconds since the Unix
+    /// epoch)
+    pub(crate) fn refresh_lock_at(
+        &mut self,
+        name: &str,
+        token: u64,
+        ttl: Duration,
+        now: u64,
+    ) -> Result<Option<Lock>> {
+        let version = match self.get_versioned(name.to_owned())? {
+            Some((value, version)) => match LockState::parse(&value) {
+                Some(state) if state.token == token && state.held(now) => version,
+                _ => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+        let state = LockState::new(token, ttl, now);
+        let refreshed = self.set_if_version(name.to_owned(), state.value(), version)?;
+        Ok(refreshed.then(|| state.lock(name)))
+    }
+    /// release the lock of the given name held with the token, returning whether it was released
+    /// (false if another holder has taken it since it expired)
+    pub fn release_lock(&mut self, name: &str, token: u64) -> Result<bool> {
+        let value = self.get(name.to_owned())?;
+        match value.as_deref().and_then(LockState::parse) {
+            Some(state) if state.token == token => {
+                self.remove(name.to_owned())?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path,
+};
+
+use super::{sync_dir_of, Error, ErrorKind, Result};
+
+/// first line of manifests written before they listed segments, followed by a generation line
+const MANIFEST_V1_HEADER: &str = "kvs manifest 1";
+
+/// first line of every manifest, identifying the format
+const MANIFEST_HEADER: &str = "kvs manifest 2";
+
+/// Manifest of a KvStore, listing the segments making up the store, oldest first
+///
+/// It is rewritten (atomically) each time the segments change: when a store is created, when
+/// compaction or clear makes a new log active, and when a hint file is written or consumed. `open`
+/// trusts it over whichever files are found in the directory. Stores currently write a single
+/// segment, and only the active segment is read, but older segments listed are kept (and taken
+/// into account when compacting the active one).
+///
+/// Each segment is listed on a line of its own:
+/// `segment <generation> <log file name> [<hint file name>] [digest=<length>:<CRC-32>]`, the
+/// digest of the log being recorded once it is closed (when the store is shut down), so that the
+/// log can be checked for bit rot without reading its records.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Manifest {
+    pub(crate) segments: Vec<Segment>,
+}
+
+/// Segment of a KvStore: a log, with the hint file holding a snapshot of its index (written on a
+/// clean shutdown) if there is one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Segment {
+    pub(crate) generation: u128,
+    /// file name of the log, within the store's directory
+    pub(crate) log: String,
+    /// file name of the hint file, within the store's directory
+    pub(crate) hint: Option<String>,
+    /// digest of the log, if it is closed
+    pub(crate) digest: Option<Digest>,
+}
+
+/// Length and CRC-32 of the whole of a closed log
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Digest {
+    pub(crate) len: u64,
+    pub(crate) crc: u32,
+}
+
+impl Digest {
+    /// the digest of the file, read from start to end
+    pub(crate) fn of_file(path: &path::Path) -> io::Result<Self> {
+        let mut file = fs::File::open(path)?;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buf = vec![0; 64 * 1024];
+        let mut len = 0;
+        loop {
+            match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => {
+                    hasher.update(&buf[..read]);
+                    len += read as u64;
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(Self {
+            len,
+            crc: hasher.finalize(),
+        })
+    }
+    fn parse(field: &str) -> Option<Self> {
+        let (len, crc) = field.strip_prefix("digest=")?.split_once(':')?;
+        Some(Self {
+            len: len.parse().ok()?,
+            crc: u32::from_str_radix(crc, 16).ok()?,
+        })
+    }
+}
+
+impl Manifest {
+    /// manifest of a store made up of the single segment
+    pub(crate) fn single(segment: Segment) -> Self {
+        Self {
+            segments: vec![segment],
+        }
+    }
+    /// the segment records are written to (the newest)
+    pub(crate) fn active(&self) -> &Segment {
+        self.segments.last().expect("manifest lists no segments")
+    }
+}
+
+fn manifest_path(dir: &path::Path, prefix: &str) -> path::PathBuf {
+    dir.join(format!("{}.manifest", prefix))
+}
+
+/// reads the manifest of the store with the prefix in the directory, None if it has none (a new
+/// store, or one written before manifests were), CorruptManifest if it cannot be parsed
+pub(crate) fn read_manifest(dir: &path::Path, prefix: &str) -> Result<Option<Manifest>> {
+    let path = manifest_path(dir, prefix);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(Error::from(err).at_path(&path)),
+    };
+    let mut lines = text.lines();
+    let segments = match lines.next() {
+        Some(MANIFEST_HEADER) => lines.map(parse_segment).collect::<Option<Vec<_>>>(),
+        Some(MANIFEST_V1_HEADER) => lines
+            .next()
+            .and_then(|line| line.strip_prefix("generation "))
+            .and_then(parse_generation)
+            .map(|generation| {
+                vec![Segment {
+                    generation,
+                    log: format!("{}-{:032x}.log", prefix, generation),
+                    hint: None,
+                    digest: None,
+                }]
+            }),
+        _ => None,
+    };
+    match segments {
+        Some(segments) if !segments.is_empty() => Ok(Some(Manifest { segments })),
+        _ => Err(Error::new(ErrorKind::CorruptManifest).at_path(&path)),
+    }
+}
+
+fn parse_segment(line: &str) -> Option<Segment> {
+    let mut fields = line.split(' ');
+    if fields.next()? != "segment" {
+        return None;
+    }
+    let mut segment = Segment {
+        generation: parse_generation(fields.next()?)?,
+        log: fields.next()?.to_owned(),
+        hint: None,
+        digest: None,
+    };
+    let mut field = fields.next();
+    if let Some(hint) = field.filter(|field| !field.starts_with("digest=")) {
+        segment.hint = Some(hint.to_owned());
+        field = fields.next();
+    }
+    if let Some(digest) = field {
+        segment.digest = Some(Digest::parse(digest)?);
+    }
+    match fields.next() {
+        Some(_) => None,
+        None => Some(segment),
+    }
+}
+
+fn parse_generation(generation: &str) -> Option<u128> {
+    u128::from_str_radix(generation, 16).ok()
+}
+
+/// writes (and syncs) the manifest of the store with the prefix in the directory, under a
+/// temporary name renamed into place so that a crash leaves either the old or the new manifest
+pub(crate) fn write_manifest(dir: &path::Path, prefix: &str, manifest: &Manifest) -> Result<()> {
+    let path = manifest_path(dir, prefix);
+    let temp_path = path.with_extension("manifest.tmp");
+    let mut text = format!("{}\n", MANIFEST_HEADER);
+    for segment in &manifest.segments {
+        text += &format!("segment {:032x} {}", segment.generation, segment.log);
+        if let Some(hint) = &segment.hint {
+            text += &format!(" {}", hint);
+        }
+        if let Some(digest) = &segment.digest {
+            text += &format!(" digest={}:{:08x}", digest.len, digest.crc);
+        }
+        text += "\n";
+    }
+    let write = || -> io::Result<()> {
+        fs::write(&temp_path, text)?;
+        fs::File::open(&temp_path)?.sync_all()?;
+        fs::rename(&temp_path, &path)
+    };
+    write().map_err(|err| Error::from(err).at_path(&path))?;
+    sync_dir_of(&path);
+    Ok(())
+}
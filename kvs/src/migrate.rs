@@ -0,0 +1,249 @@
+use std::{
+    fmt, fs, hash,
+    io::{self, Seek, Write},
+    path, str,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    db_dir_of, db_files_in_dir, file_prefix_of, make_db_log_path, make_next_db_log_path, manifest,
+    open_db_reader_and_writer, resolve_value, segment_of, sync_dir_of, write_record_to_writer,
+    writer_position, Error, ErrorKind, KvStore, Manifest, Record, Result, DEFAULT_FILE_PREFIX,
+};
+
+/// On-disk layout of the directory of a KvStore, which `upgrade_layout` upgrades from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataLayout {
+    /// a single log named with a random UUID (`kvsdb-<uuid>.log`) and no manifest, perhaps with an
+    /// index snapshot (`kvsdb.index`), as the first versions wrote (in ASN.1 DER, so read only
+    /// with the `legacy-asn1` feature)
+    UuidLog,
+    /// logs named by their generation and listed by a manifest, with hint files, as now written
+    Manifest,
+}
+
+impl DataLayout {
+    /// the layout of the store in the directory, None if it holds no store
+    pub fn detect(path: &path::Path) -> Result<Option<Self>> {
+        if manifest::read_manifest(path, DEFAULT_FILE_PREFIX)?.is_some() {
+            return Ok(Some(DataLayout::Manifest));
+        }
+        match db_files_in_dir(path, DEFAULT_FILE_PREFIX, "log")?.is_empty() {
+            true => Ok(None),
+            false => Ok(Some(DataLayout::UuidLog)),
+        }
+    }
+}
+
+impl fmt::Display for DataLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataLayout::UuidLog => write!(f, "uuid-log"),
+            DataLayout::Manifest => write!(f, "manifest"),
+        }
+    }
+}
+
+impl str::FromStr for DataLayout {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "uuid-log" => Ok(DataLayout::UuidLog),
+            "manifest" => Ok(DataLayout::Manifest),
+            _ => Err(Error::new(ErrorKind::InvalidConfiguration)),
+        }
+    }
+}
+
+impl<K, V> KvStore<K, V>
+where
+    K: Serialize + DeserializeOwned + Eq + PartialEq + hash::Hash + Clone + fmt::Debug,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    /// rewrite the log with the value of every key converted to a new type by the function,
+    /// returning the store reopened with values of the new type
+    ///
+    /// The store is opened with the old value type, migrated and from then on opened with the new
+    /// one, so that records written before the value type changed are not left unreadable. The
+    /// migrated log is written as a compaction is (keeping only the current value of each key,
+    /// with its version and timestamp) under a temporary name, then renamed into place before the
+    /// old logs are deleted, so a crash part way through leaves either the old or the migrated
+    /// log. Hooks, secondary indexes and merge operators are not carried over to the new store.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,u32>::temporary().unwrap();
+    /// let _ = store.set("key1".into(), 7);
+    /// let mut store = store.migrate_values(|_, value| value.to_string()).unwrap();
+    /// assert_eq!(store.get("key1".into()).unwrap(), Some("7".into()));
+    /// ```
+    pub fn migrate_values<V2, F>(mut self, f: F) -> Result<KvStore<K, V2>>
+    where
+        V2: Serialize + DeserializeOwned + Clone,
+        F: FnMut(&K, V) -> V2,
+    {
+        let options = self.options();
+        let migrated_path = make_next_db_log_path(self.file_path.clone());
+        if let Err(err) = self.write_migrated_log(&migrated_path, f) {
+            self.remove_file(&migrated_path)?;
+            return Err(
+                Error::with_source(ErrorKind::CompactionFailed, err).at_path(&migrated_path)
+            );
+        }
+        let final_path = migrated_path.with_extension("log");
+        fs::rename(&migrated_path, &final_path)?;
+        // the migrated log becomes the only segment, the logs of the old value type being
+        // removed as unlisted files when the store is reopened
+        let dir = db_dir_of(&final_path).to_owned();
+        let prefix = file_prefix_of(&final_path);
+        manifest::write_manifest(
+            &dir,
+            &prefix,
+            &Manifest::single(segment_of(&final_path, None)),
+        )?;
+        sync_dir_of(&final_path);
+        let storage = self.storage.clone();
+        // a temporary store's directory is kept until the migrated store is dropped
+        let temporary_dir = self._temporary_dir.take();
+        drop(self);
+        let mut migrated = KvStore::<K, V2>::open_with_storage(&dir, &options, storage)?;
+        migrated._temporary_dir = temporary_dir;
+        Ok(migrated)
+    }
+
+    /// upgrade the store in the directory at `path` from the layout to the current one in place,
+    /// first copying its files to `backup_dir` (which must not hold files already), so that a store
+    /// written by an earlier version may use the features of the current layout
+    ///
+    /// The log of a store in the UuidLog layout is renamed as the first generation of the
+    /// current layout, then the store is opened (writing its manifest and dropping its index
+    /// snapshot) and compacted (rewriting each record as the current version writes it) and shut
+    /// down cleanly. InvalidConfiguration, changing nothing, if the store is not in the layout
+    /// (or holds more than the single log of the UuidLog layout).
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kvs::{DataLayout, KvStore};
+    /// use std::path::Path;
+    ///
+    /// KvStore::<String,String>::upgrade_layout(
+    ///     Path::new("testdb"),
+    ///     DataLayout::UuidLog,
+    ///     Path::new("testdb-backup"),
+    /// ).unwrap();
+    /// ```
+    pub fn upgrade_layout(
+        path: &path::Path,
+        from: DataLayout,
+        backup_dir: &path::Path,
+    ) -> Result<()> {
+        let layout = DataLayout::detect(path)?;
+        let logs = db_files_in_dir(path, DEFAULT_FILE_PREFIX, "log")?;
+        let log = match (from, layout, logs.as_slice()) {
+            (DataLayout::UuidLog, Some(DataLayout::UuidLog), [(_, log)]) => log,
+            _ => {
+                return Err(Error::with_message(
+                    ErrorKind::InvalidConfiguration,
+                    format!("the store is not in the {} layout", from),
+                )
+                .at_path(path))
+            }
+        };
+        back_up_store_files(path, backup_dir)?;
+        let upgraded = make_db_log_path(path, DEFAULT_FILE_PREFIX, 0);
+        fs::rename(log, &upgraded).map_err(|err| Error::from(err).at_path(log))?;
+        sync_dir_of(&upgraded);
+        let mut store = Self::open(path)?;
+        store.compact_now()?;
+        store.shutdown()
+    }
+
+    /// writes the current value of every key, converted by the function, to a new log at the
+    /// path, synced once complete
+    fn write_migrated_log<V2, F>(&mut self, migrated_path: &path::Path, mut f: F) -> Result<()>
+    where
+        V2: Serialize,
+        F: FnMut(&K, V) -> V2,
+    {
+        let (_, mut migrated_writer) =
+            open_db_reader_and_writer(&*self.storage, migrated_path, true)?;
+        // merged values are resolved with their own reader so the scan of the log is undisturbed
+        let mut merge_reader = None;
+        self.reader.seek(io::SeekFrom::Start(0))?;
+        let mut offset = 0;
+        while let Some(rec) = self.read_next_record(&mut offset)? {
+            if self.index.get(&rec.key) != Some(&rec.db_key) {
+                continue;
+            }
+            let value = match rec.merge {
+                true => {
+                    let merge_reader = match &mut merge_reader {
+                        Some(merge_reader) => merge_reader,
+                        None => merge_reader.insert(io::BufReader::new(
+                            self.storage.open_reader(&self.file_path)?,
+                        )),
+                    };
+                    resolve_value(
+                        merge_reader,
+                        self.merge_operator.as_ref(),
+                        &rec.key,
+                        rec.db_key,
+                    )?
+                }
+                false => rec.value,
+            };
+            if let Some(value) = value {
+                let value = f(&rec.key, value);
+                let migrated = Record {
+                    db_key: writer_position(&mut migrated_writer)?,
+                    key: &rec.key,
+                    value: Some(&value),
+                    merge: false,
+                    previous: None,
+                    version: rec.version,
+                    timestamp: rec.timestamp,
+                };
+                write_record_to_writer(
+                    migrated,
+                    self.record_format,
+                    &mut self.scratch,
+                    &mut migrated_writer,
+                )?;
+            }
+        }
+        migrated_writer.flush()?;
+        Ok(migrated_writer.get_ref().sync()?)
+    }
+}
+
+/// copies the files of the store in the directory (leaving out its keyspaces) to the backup
+/// directory, synced, InvalidConfiguration if the backup directory already holds files
+fn back_up_store_files(path: &path::Path, backup_dir: &path::Path) -> Result<()> {
+    let in_use = backup_dir.is_dir()
+        && fs::read_dir(backup_dir)
+            .map_err(|err| Error::from(err).at_path(backup_dir))?
+            .next()
+            .is_some();
+    if in_use {
+        return Err(Error::new(ErrorKind::InvalidConfiguration).at_path(backup_dir));
+    }
+    fs::create_dir_all(backup_dir).map_err(|err| Error::from(err).at_path(backup_dir))?;
+    for entry in fs::read_dir(path).map_err(|err| Error::from(err).at_path(path))? {
+        let file = entry?.path();
+        let is_store_file = file.is_file()
+            && file
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with(DEFAULT_FILE_PREFIX));
+        if is_store_file {
+            let copy = backup_dir.join(file.file_name().unwrap_or_default());
+            fs::copy(&file, &copy).map_err(|err| Error::from(err).at_path(&file))?;
+            fs::File::open(&copy)?.sync_all()?;
+        }
+    }
+    sync_dir_of(&backup_dir.join(DEFAULT_FILE_PREFIX));
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc, Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Change to the keys of a KvStore, sent to each subscriber whose filter accepts the key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyEvent<K> {
+    /// the key was set (or an operand merged into its value)
+    Set(K),
+    /// the key was removed
+    Removed(K),
+    /// the key was evicted to keep the store within its maximum number of keys
+    Evicted(K),
+    /// every key was removed
+    Cleared,
+}
+
+impl<K> KeyEvent<K> {
+    /// the key changed, None for Cleared
+    pub fn key(&self) -> Option<&K> {
+        match self {
+            Self::Set(key) | Self::Removed(key) | Self::Evicted(key) => Some(key),
+            Self::Cleared => None,
+        }
+    }
+}
+
+/// number of events a subscriber may fall behind by (not having received them) before it is dropped
+pub(crate) const SUBSCRIBER_BACKLOG: usize = 4096;
+
+/// new channel of the events sent to a subscriber
+pub(crate) fn channel<K>() -> (EventSender<K>, mpsc::Receiver<KeyEvent<K>>) {
+    let (sender, receiver) = mpsc::sync_channel(SUBSCRIBER_BACKLOG);
+    let dropped = Arc::new(AtomicBool::new(false));
+    (EventSender { sender, dropped }, receiver)
+}
+
+/// sender of the events of a subscriber (which may be subscribed to several stores), dropped by
+/// every store once its backlog is full, so that the subscriber receives no events after those
+/// queued and its receiver is disconnected
+#[derive(Clone)]
+pub(crate) struct EventSender<K> {
+    sender: mpsc::SyncSender<KeyEvent<K>>,
+    dropped: Arc<AtomicBool>,
+}
+
+impl<K> EventSender<K> {
+    /// sends the event, false if the subscriber has been dropped (or dropped its receiver)
+    fn send(&self, event: KeyEvent<K>) -> bool {
+        if self.dropped.load(Ordering::Relaxed) {
+            return false;
+        }
+        match self.sender.try_send(event) {
+            Ok(()) => true,
+            Err(mpsc::TrySendError::Full(_)) => {
+                self.dropped.store(true, Ordering::Relaxed);
+                false
+            }
+            Err(mpsc::TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+/// filter of the keys whose events a subscriber receives
+type KeyFilter<K> = Box<dyn Fn(&K) -> bool + Send>;
+
+/// Subscribers to the KeyEvents of a KvStore
+pub(crate) struct Subscribers<K> {
+    subscribers: Vec<Subscriber<K>>,
+}
+
+struct Subscriber<K> {
+    filter: KeyFilter<K>,
+    sender: EventSender<K>,
+    /// false if the subscriber is sent Cleared by another store (as the partitions of a
+    /// ShardedKvStore but the first are)
+    receives_cleared: bool,
+}
+
+impl<K: Clone> Subscribers<K> {
+    pub(crate) fn new() -> Self {
+        Self {
+            subscribers: Vec::new(),
+        }
+    }
+    pub(crate) fn subscribe(&mut self, filter: KeyFilter<K>) -> mpsc::Receiver<KeyEvent<K>> {
+        let (sender, receiver) = channel();
+        self.subscribe_with(filter, sender, true);
+        receiver
+    }
+    /// subscribes the sender (which may be subscribed to other stores too)
+    pub(crate) fn subscribe_with(
+        &mut self,
+        filter: KeyFilter<K>,
+        sender: EventSender<K>,
+        receives_cleared: bool,
+    ) {
+        self.subscribers.push(Subscriber {
+            filter,
+            sender,
+            receives_cleared,
+        });
+    }
+    pub(crate) fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+    /// sends the event to every subscriber whose filter accepts its key (every subscriber which
+    /// receives it for Cleared), forgetting those which have dropped their receiver or fallen
+    /// behind
+    pub(crate) fn notify(&mut self, event: KeyEvent<K>) {
+        self.subscribers.retain(|subscriber| match event.key() {
+            Some(key) if !(subscriber.filter)(key) => true,
+            None if !subscriber.receives_cleared => true,
+            _ => subscriber.sender.send(event.clone()),
+        });
+    }
+}
@@ -0,0 +1,371 @@
+use std::{cell::Cell, io};
+
+use serde::{
+    de::{self, DeserializeOwned},
+    Deserialize, Serialize,
+};
+
+use super::{KeyEvent, MigrationStatus, Result, SnapshotChunk, Topology};
+
+/// Request sent from a KvsClient to the server using the kvs-proto framing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Request {
+    /// get the value stored under the key
+    Get {
+        /// the key to look up
+        key: String,
+    },
+    /// set the key to the value
+    Set {
+        /// the key to set
+        key: String,
+        /// the value to store under the key
+        value: String,
+    },
+    /// remove the key (and its value)
+    Remove {
+        /// the key to remove
+        key: String,
+    },
+    /// compact the storage now
+    Compact,
+    /// remove every key
+    Clear,
+    /// count the keys present
+    KeyCount,
+    /// authenticate the connection to a server which requires a password
+    Auth {
+        /// the password the server was configured with
+        password: String,
+    },
+    /// set the key to the value only if the key is not present
+    SetIfAbsent {
+        /// the key to set
+        key: String,
+        /// the value to store under the key
+        value: String,
+    },
+    /// acquire the expiring lock of the given name
+    AcquireLock {
+        /// name of the lock
+        name: String,
+        /// milliseconds for which the lock is to be held
+        ttl_ms: u64,
+    },
+    /// extend the expiring lock of the given name, held with the token
+    RefreshLock {
+        /// name of the lock
+        name: String,
+        /// fencing token the lock was acquired with
+        token: u64,
+        /// milliseconds for which the lock is to be held from now
+        ttl_ms: u64,
+    },
+    /// release the expiring lock of the given name, held with the token
+    ReleaseLock {
+        /// name of the lock
+        name: String,
+        /// fencing token the lock was acquired with
+        token: u64,
+    },
+    /// set the key to the value unless a write with the same idempotency token has already been
+    /// applied
+    SetOnce {
+        /// the key to set
+        key: String,
+        /// the value to store under the key
+        value: String,
+        /// idempotency token of the write
+        token: u64,
+    },
+    /// get the value stored under the key, allowing it to be as it was up to the lag ago (served by
+    /// a follower of a replicated engine which is caught up within the lag)
+    GetWithMaxLag {
+        /// the key to look up
+        key: String,
+        /// milliseconds by which the value may lag the latest
+        max_lag_ms: u64,
+    },
+    /// stream the events of the keys starting with the prefix: the server replies Ok, then sends
+    /// an Event for each until the connection is closed (reading no further requests)
+    Subscribe {
+        /// prefix of the keys whose events are sent (empty for every key)
+        prefix: String,
+    },
+    /// get the topology of the cluster the server is a node of
+    Topology,
+    /// replace the topology of the cluster the server is a node of, unless the topology is older
+    /// (of a lower epoch) than the server's
+    SetTopology {
+        /// the new topology
+        topology: Topology,
+    },
+    /// start migrating the keys of the range to another node of the cluster, copying every key
+    /// and then each write to the range as it is made, until the migration is cut over
+    MigrateRange {
+        /// first key of the range
+        start: String,
+        /// key following the last of the range, None if the range runs to the end of the keys
+        end: Option<String>,
+        /// address of the node to migrate the keys to
+        target: String,
+    },
+    /// get the progress of the server's last migration
+    MigrationStatus,
+    /// cut the server's migration over to its target: the server replaces its topology (which
+    /// should give the range to the target), copies the writes still to be copied, sends the
+    /// topology to the target and removes its own copy of the keys of the range
+    FinishMigration {
+        /// the new topology
+        topology: Topology,
+    },
+    /// set (or remove, if the value is None) a key copied from another node by a migration,
+    /// whether or not the server holds the shard of the key
+    Import {
+        /// the key copied
+        key: String,
+        /// the value of the key on the node it was copied from
+        value: Option<String>,
+    },
+    /// get a chunk of a snapshot of every key and value of the server, taking a new snapshot if
+    /// no id is given or the server no longer holds the snapshot of the id
+    Snapshot {
+        /// id of the snapshot to continue receiving, None to start a new one
+        snapshot_id: Option<u64>,
+        /// position of the first pair to send among the pairs of the snapshot
+        offset: u64,
+        /// most bytes of keys and values to send in the chunk (at least one pair being sent)
+        max_bytes: u64,
+    },
+    /// switch the connection to the database of the given number, to which its later requests
+    /// are made (database 0 until it is switched)
+    Select {
+        /// number of the database
+        db: u64,
+    },
+    /// make the request, tagged with an ID given by the client which the server reports it by
+    /// (in its tracing span, its logs and the messages of errors in response to it)
+    Traced {
+        /// ID of the request (such as the correlation ID of the work it is part of)
+        id: String,
+        /// the request to make, which may not be traced itself
+        #[serde(deserialize_with = "deserialize_untraced")]
+        request: Box<Request>,
+    },
+    /// get a page of the keys starting with the prefix and their values, in the order of the
+    /// keys, continuing the scan from the cursor of the page before (0 to start a scan)
+    ///
+    /// The server continues from the keys after the last key of the page before, so the cursor
+    /// stays valid across writes and compactions (until the server drops it, as it does the
+    /// oldest of many cursors, or restarts, a ServerError then being returned).
+    Scan {
+        /// cursor of the page before, 0 for the first page
+        cursor: u64,
+        /// prefix of the keys scanned (empty for every key)
+        prefix: String,
+        /// most pairs to send in the page (at least one being sent, unless no keys are left)
+        count: u64,
+    },
+    /// read the server's config file again, applying its settings
+    ReloadConfig,
+    /// append the suffix to the value stored under the key (setting the key to the suffix if it
+    /// is not present)
+    Append {
+        /// the key to append to
+        key: String,
+        /// the string appended to the value
+        suffix: String,
+    },
+    /// get the length in bytes of the value stored under the key (0 if it is not present)
+    ValueLen {
+        /// the key to look up
+        key: String,
+    },
+    /// get the values stored under the keys, all read as of the same moment
+    GetMany {
+        /// the keys to look up
+        keys: Vec<String>,
+    },
+    /// set each key to its value, all together as a single batch
+    SetMany {
+        /// the keys to set, each with the value to store under it
+        pairs: Vec<(String, String)>,
+    },
+    /// get the report of Redis's INFO on the server: the number of keys and (with the `stats`
+    /// feature) the percentiles of the sizes of the keys and values written
+    Info,
+}
+
+/// Response sent from the server to a KvsClient using the kvs-proto framing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Response {
+    /// the request succeeded with no further information
+    Ok,
+    /// the value found for a Get request (if any)
+    Value(Option<String>),
+    /// the number of keys present, for a KeyCount request, or the length in bytes of a value, for
+    /// an Append or ValueLen request
+    Count(u64),
+    /// the key to be removed is not present
+    KeyNotFound,
+    /// the password given was wrong or the connection must authenticate before other requests
+    Unauthenticated,
+    /// the server failed to process the request
+    ServerError {
+        /// description of the failure, starting with the code of a RESP error (such as ERR or OOM)
+        /// of which the client takes the ErrorKind
+        msg: String,
+    },
+    /// whether a conditional write (such as SetIfAbsent or SetOnce) was applied
+    Applied(bool),
+    /// the fencing token of the lock acquired or refreshed, None if it is not held
+    Token(Option<u64>),
+    /// an event of a key subscribed to
+    Event(KeyEvent<String>),
+    /// the topology of the server's cluster, for a Topology request
+    Topology(Topology),
+    /// the progress of the server's last migration (None if it has made none), for a
+    /// MigrationStatus request
+    Migration(Option<MigrationStatus>),
+    /// a chunk of a snapshot of the server, for a Snapshot request
+    SnapshotChunk(SnapshotChunk),
+    /// the key of the request is served by another node of the cluster (the shard holding it not
+    /// being on this node, or this node not being the leader of the shard)
+    Moved {
+        /// address of the node to send the request to
+        addr: String,
+    },
+    /// the values found for a GetMany request, in the order of its keys
+    Values(Vec<Option<String>>),
+    /// a page of the pairs of a scan, for a Scan request
+    ScanPage {
+        /// cursor to request the next page with, 0 if the scan is complete
+        cursor: u64,
+        /// the keys of the page and their values
+        pairs: Vec<(String, String)>,
+    },
+    /// the report of INFO on the server, for an Info request
+    Info(String),
+}
+
+impl Request {
+    /// the key (or the name of the lock) the request is for, which the server must hold the shard
+    /// of, None if it is not for a single key (or is served whatever the shard, as Import is)
+    pub(crate) fn key(&self) -> Option<&str> {
+        match self {
+            Request::Get { key }
+            | Request::Set { key, .. }
+            | Request::Remove { key }
+            | Request::SetIfAbsent { key, .. }
+            | Request::SetOnce { key, .. }
+            | Request::GetWithMaxLag { key, .. }
+            | Request::Append { key, .. }
+            | Request::ValueLen { key } => Some(key),
+            Request::AcquireLock { name, .. }
+            | Request::RefreshLock { name, .. }
+            | Request::ReleaseLock { name, .. } => Some(name),
+            Request::Compact
+            | Request::Clear
+            | Request::KeyCount
+            | Request::Auth { .. }
+            | Request::Subscribe { .. }
+            | Request::Topology
+            | Request::SetTopology { .. }
+            | Request::MigrateRange { .. }
+            | Request::MigrationStatus
+            | Request::FinishMigration { .. }
+            | Request::Import { .. }
+            | Request::Snapshot { .. }
+            | Request::Select { .. }
+            | Request::Scan { .. }
+            | Request::ReloadConfig
+            | Request::GetMany { .. }
+            | Request::SetMany { .. }
+            | Request::Info => None,
+            Request::Traced { request, .. } => request.key(),
+        }
+    }
+    /// the requests of a single key each which a request for many keys amounts to, so that each
+    /// key may be checked as a request of its own, None if the request is not for many keys
+    pub(crate) fn per_key(&self) -> Option<Vec<Request>> {
+        match self {
+            Request::GetMany { keys } => Some(
+                keys.iter()
+                    .map(|key| Request::Get { key: key.clone() })
+                    .collect(),
+            ),
+            Request::SetMany { pairs } => Some(
+                pairs
+                    .iter()
+                    .map(|(key, value)| Request::Set {
+                        key: key.clone(),
+                        value: value.clone(),
+                    })
+                    .collect(),
+            ),
+            Request::Traced { request, .. } => request.per_key(),
+            _ => None,
+        }
+    }
+    /// the ID the request is tagged with (the outermost, if tagged more than once) and the request
+    /// it is made of
+    pub(crate) fn untraced(self) -> (Option<String>, Request) {
+        match self {
+            Request::Traced { id, request } => (Some(id), request.untraced().1),
+            request => (None, request),
+        }
+    }
+}
+
+/// true if the first byte received on a connection starts a kvs-proto message
+///
+/// All kvs-proto requests are enums, so they begin with one of the enum variant indicators (`@`,
+/// `^` or `#`) which never start a RESP message.
+pub(crate) fn is_kvs_proto_message_start(first_byte: u8) -> bool {
+    matches!(first_byte, b'@' | b'^' | b'#')
+}
+
+thread_local! {
+    /// true while the request of a Traced is deserialized on the thread
+    static DESERIALIZING_TRACED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// deserializes the request of a Traced, refusing a Traced within it as soon as it is found (the
+/// derived deserialization recursing once per Traced, a request traced again and again would
+/// otherwise overflow the stack of the thread reading it)
+fn deserialize_untraced<'de, D>(deserializer: D) -> std::result::Result<Box<Request>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    struct Deserializing;
+    impl Drop for Deserializing {
+        fn drop(&mut self) {
+            DESERIALIZING_TRACED.with(|traced| traced.set(false));
+        }
+    }
+    if DESERIALIZING_TRACED.with(|traced| traced.replace(true)) {
+        return Err(de::Error::custom(
+            "a traced request may not be traced again",
+        ));
+    }
+    let _deserializing = Deserializing;
+    Request::deserialize(deserializer).map(Box::new)
+}
+
+/// reads the next message or None if the reader is at the end of input
+pub(crate) fn read_message<R: io::Read, T: DeserializeOwned>(
+    reader: &mut io::BufReader<R>,
+) -> Result<Option<T>> {
+    if io::BufRead::fill_buf(reader)?.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(kvs_proto_serde::from_reader(reader)?))
+}
+
+/// writes (and flushes) the message
+pub(crate) fn write_message<W: io::Write, T: Serialize>(writer: W, message: &T) -> Result<()> {
+    Ok(kvs_proto_serde::to_writer(
+        &mut io::BufWriter::new(writer),
+        message,
+    )?)
+}
@@ -0,0 +1,273 @@
+mod log;
+mod message;
+mod node;
+
+use std::{
+    collections::HashSet,
+    net, path,
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+
+#[cfg(feature = "stats")]
+use super::Stats;
+use super::{
+    transfer::DEFAULT_CHUNK_SIZE, version::now_millis, Error, ErrorKind, KeyEvent, KvsEngine, Lock,
+    Result, RuntimeOptions, StoreEvent, WriteBatch,
+};
+use message::Command;
+use node::{Node, Output};
+
+/// subdirectory of a store's directory holding the Raft state and log of its node
+const RAFT_DIR: &str = "raft";
+
+/// Options controlling a node of a Raft cluster
+#[derive(Debug, Clone)]
+pub struct RaftOptions {
+    /// least time a follower waits to hear from the leader before standing for election (each
+    /// wait being chosen at random, up to twice as long), also the timeout of requests to peers
+    pub election_timeout: Duration,
+    /// interval between the heartbeats a leader sends to each follower
+    pub heartbeat_interval: Duration,
+    /// how long a request waits for its write to be committed, or for a read to be confirmed by
+    /// a majority, before failing
+    pub request_timeout: Duration,
+    /// number of entries applied since the log was last compacted after which it is compacted
+    /// again, the store becoming the snapshot of the entries removed
+    pub snapshot_threshold: u64,
+    /// most bytes of keys and values sent to a follower in each chunk of a snapshot
+    pub snapshot_chunk_size: usize,
+}
+
+impl Default for RaftOptions {
+    fn default() -> Self {
+        Self {
+            election_timeout: Duration::from_millis(300),
+            heartbeat_interval: Duration::from_millis(50),
+            request_timeout: Duration::from_secs(5),
+            snapshot_threshold: 10_000,
+            snapshot_chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+}
+
+/// role of a node of a Raft cluster in its current term
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RaftRole {
+    /// replicating the log of the leader
+    Follower,
+    /// standing for election as leader
+    Candidate,
+    /// accepting writes and replicating them to the followers
+    Leader,
+}
+
+/// state of a node of a Raft cluster, from `RaftEngine::status`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RaftStatus {
+    /// id of the node
+    pub id: u64,
+    /// role of the node
+    pub role: RaftRole,
+    /// current term of the node
+    pub term: u64,
+    /// id of the leader of the current term, if known
+    pub leader: Option<u64>,
+    /// index of the last entry of the log known to be committed
+    pub commit_index: u64,
+    /// index of the last entry of the log applied to the store
+    pub applied_index: u64,
+}
+
+/// KvsEngine replicating its store to the other nodes of a cluster through a Raft log
+///
+/// Every write is appended to the log of the leader, and is applied to the store (and answered)
+/// once a majority of the nodes hold it, so the cluster keeps every write made through it while a
+/// majority of its nodes are running. Reads are served by the leader once a majority has
+/// confirmed that it still leads, so they see every write made before them. A node which is not
+/// the leader fails each read and write with NotLeader, except for reads made with
+/// `get_with_max_lag`: a follower serves those from its own store if it has caught up with the
+/// leader within the lag, forwarding them to the leader otherwise.
+///
+/// The log is compacted once `snapshot_threshold` entries have been applied, the store being the
+/// snapshot of the entries removed, and a node which needs entries the leader has compacted away
+/// is sent the leader's keys and values instead, as a snapshot taken once and sent in checksummed
+/// chunks of `snapshot_chunk_size` bytes. A follower holds the chunks it has received until the
+/// last arrives, so a transfer interrupted by a failed connection resumes where it stopped.
+///
+/// Compaction and subscriptions are local to each node, events being those of the writes applied
+/// to its store. The idempotency tokens of `set_once` are remembered in memory only, so a write
+/// retried after the leader restarts may be applied again.
+///
+/// # Example
+/// ```no_run
+/// use kvs::{KvsServer, RaftEngine, RaftOptions};
+/// use std::net::TcpListener;
+///
+/// let peers = vec![
+///     (2, "10.0.0.2:4100".parse().unwrap()),
+///     (3, "10.0.0.3:4100".parse().unwrap()),
+/// ];
+/// let listener = TcpListener::bind("10.0.0.1:4100").unwrap();
+/// let engine = RaftEngine::start(
+///     std::path::Path::new("testdb"),
+///     1,
+///     peers,
+///     listener,
+///     RaftOptions::default(),
+/// )
+/// .unwrap();
+/// KvsServer::new(engine).run("10.0.0.1:4000").unwrap();
+/// ```
+#[derive(Clone)]
+pub struct RaftEngine {
+    node: Arc<Node>,
+}
+
+impl RaftEngine {
+    /// open the store at the path as the node of the given id of a cluster whose other nodes are
+    /// the peers (their ids and the addresses they listen on for each other), listening for them
+    /// on the listener
+    ///
+    /// The Raft state and log of the node are kept in the `raft` subdirectory of the store's.
+    pub fn start(
+        path: &path::Path,
+        id: u64,
+        peers: Vec<(u64, net::SocketAddr)>,
+        listener: net::TcpListener,
+        options: RaftOptions,
+    ) -> Result<Self> {
+        let mut ids = peers.iter().map(|&(peer, _)| peer).collect::<HashSet<_>>();
+        let valid = ids.len() == peers.len()
+            && ids.insert(id)
+            && options.heartbeat_interval < options.election_timeout
+            && options.snapshot_threshold > 0;
+        if !valid {
+            return Err(Error::new(ErrorKind::InvalidConfiguration));
+        }
+        Ok(Self {
+            node: Node::start(path, id, peers, listener, options)?,
+        })
+    }
+    /// current state of the node
+    pub fn status(&self) -> Result<RaftStatus> {
+        self.node.status()
+    }
+
+    fn propose(&self, command: Command) -> Result<Output> {
+        self.node.propose(command)
+    }
+}
+
+impl KvsEngine for RaftEngine {
+    fn set(&self, key: String, value: String) -> Result<()> {
+        self.propose(Command::Set { key, value }).map(|_| ())
+    }
+    fn set_if_absent(&self, key: String, value: String) -> Result<bool> {
+        applied(self.propose(Command::SetIfAbsent { key, value })?)
+    }
+    fn set_once(&self, key: String, value: String, token: u64) -> Result<bool> {
+        applied(self.propose(Command::SetOnce { key, value, token })?)
+    }
+    fn get(&self, key: String) -> Result<Option<String>> {
+        self.node.read_barrier()?;
+        self.node.store().get(key)
+    }
+    fn get_many(&self, keys: Vec<String>) -> Result<Vec<Option<String>>> {
+        self.node.read_barrier()?;
+        self.node.store().get_many(keys)
+    }
+    fn get_with_max_lag(&self, key: String, max_lag: Duration) -> Result<Option<String>> {
+        self.node.read_with_max_lag(key, max_lag)
+    }
+    fn append(&self, key: String, suffix: String) -> Result<usize> {
+        match self.propose(Command::Append { key, suffix })? {
+            Output::Length(len) => Ok(len),
+            _ => Err(Error::new(ErrorKind::UnknownError)),
+        }
+    }
+    fn value_len(&self, key: String) -> Result<usize> {
+        self.node.read_barrier()?;
+        self.node.store().value_len(key)
+    }
+    fn remove(&self, key: String) -> Result<()> {
+        self.propose(Command::Remove { key }).map(|_| ())
+    }
+    fn keys(&self) -> Result<Vec<String>> {
+        self.node.read_barrier()?;
+        self.node.store().keys()
+    }
+    fn pairs(&self) -> Result<Vec<(String, String)>> {
+        self.node.read_barrier()?;
+        self.node.store().pairs()
+    }
+    fn write_batch(&self, batch: WriteBatch<String, String>) -> Result<Vec<bool>> {
+        let operations = batch.into_operations();
+        match self.propose(Command::Batch { operations })? {
+            Output::Batch(applied) => Ok(applied),
+            _ => Err(Error::new(ErrorKind::UnknownError)),
+        }
+    }
+    fn key_count(&self) -> Result<usize> {
+        self.node.read_barrier()?;
+        self.node.store().key_count()
+    }
+    fn acquire_lock(&self, name: String, ttl: Duration) -> Result<Option<Lock>> {
+        lock(self.propose(Command::AcquireLock {
+            name,
+            ttl_ms: ttl.as_millis() as u64,
+            now_ms: now_millis(),
+        })?)
+    }
+    fn refresh_lock(&self, name: String, token: u64, ttl: Duration) -> Result<Option<Lock>> {
+        lock(self.propose(Command::RefreshLock {
+            name,
+            token,
+            ttl_ms: ttl.as_millis() as u64,
+            now_ms: now_millis(),
+        })?)
+    }
+    fn release_lock(&self, name: String, token: u64) -> Result<bool> {
+        applied(self.propose(Command::ReleaseLock { name, token })?)
+    }
+    fn subscribe(&self, prefix: String) -> Result<mpsc::Receiver<KeyEvent<String>>> {
+        self.node.store().subscribe(prefix)
+    }
+    fn subscribe_events(&self) -> Result<mpsc::Receiver<StoreEvent>> {
+        self.node.store().subscribe_events()
+    }
+    fn compact(&self) -> Result<()> {
+        self.node.store().compact()
+    }
+    fn clear(&self) -> Result<()> {
+        self.propose(Command::Clear).map(|_| ())
+    }
+    fn reconfigure(&self, options: RuntimeOptions) -> Result<()> {
+        self.node.store().reconfigure(options)
+    }
+    #[cfg(feature = "stats")]
+    fn stats(&self) -> Result<Stats> {
+        self.node.store().stats()
+    }
+    fn leader(&self) -> Result<Option<u64>> {
+        Ok(self.status()?.leader)
+    }
+    fn shutdown(&self) -> Result<()> {
+        self.node.stop()?;
+        self.node.store().shutdown()
+    }
+}
+
+fn applied(output: Output) -> Result<bool> {
+    match output {
+        Output::Applied(applied) => Ok(applied),
+        _ => Err(Error::new(ErrorKind::UnknownError)),
+    }
+}
+
+fn lock(output: Output) -> Result<Option<Lock>> {
+    match output {
+        Output::Lock(lock) => Ok(lock),
+        _ => Err(Error::new(ErrorKind::UnknownError)),
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    path,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::super::{protocol, sync_dir_of, Error, ErrorKind, Result};
+use super::message::Entry;
+
+const STATE_FILE: &str = "state";
+const LOG_FILE: &str = "log";
+
+/// state of a node which must survive a restart, besides its log
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct HardState {
+    term: u64,
+    voted_for: Option<u64>,
+    /// index and term of the last entry the store held when the log was last compacted
+    snapshot_index: u64,
+    snapshot_term: u64,
+}
+
+/// persistent state of a Raft node: its term, its vote and its log
+///
+/// Only the entries after the last snapshot are kept, the store itself being the snapshot. Each
+/// entry is written to the log file as its length and CRC-32 followed by the entry, so an entry
+/// torn by a crash is detected (and dropped) when the log is opened.
+pub(super) struct RaftLog {
+    dir: path::PathBuf,
+    state: HardState,
+    entries: Vec<Entry>,
+    file: fs::File,
+}
+
+impl RaftLog {
+    /// opens (or creates) the state and log in the directory
+    pub(super) fn open(dir: &path::Path) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let state_path = dir.join(STATE_FILE);
+        let state = match fs::read(&state_path) {
+            Ok(bytes) => kvs_proto_serde::from_reader(&mut io::BufReader::new(&bytes[..]))
+                .map_err(|err| Error::with_source(ErrorKind::CorruptManifest, err))
+                .map_err(|err| err.at_path(&state_path))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => HardState::default(),
+            Err(err) => return Err(Error::from(err).at_path(&state_path)),
+        };
+        let log_path = dir.join(LOG_FILE);
+        let (mut entries, valid_len) = read_entries(&log_path)?;
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)?;
+        file.set_len(valid_len)?;
+        // entries the log was compacted past before a crash interrupted rewriting it
+        entries.retain(|entry| entry.index > state.snapshot_index);
+        Ok(Self {
+            dir: dir.to_owned(),
+            state,
+            entries,
+            file,
+        })
+    }
+    pub(super) fn term(&self) -> u64 {
+        self.state.term
+    }
+    pub(super) fn voted_for(&self) -> Option<u64> {
+        self.state.voted_for
+    }
+    /// records the term and vote (which must be kept together, the vote being for the term)
+    pub(super) fn set_term_and_vote(&mut self, term: u64, voted_for: Option<u64>) -> Result<()> {
+        self.state.term = term;
+        self.state.voted_for = voted_for;
+        self.write_state()
+    }
+    pub(super) fn snapshot_index(&self) -> u64 {
+        self.state.snapshot_index
+    }
+    pub(super) fn last_index(&self) -> u64 {
+        self.entries
+            .last()
+            .map_or(self.state.snapshot_index, |entry| entry.index)
+    }
+    pub(super) fn last_term(&self) -> u64 {
+        self.entries
+            .last()
+            .map_or(self.state.snapshot_term, |entry| entry.term)
+    }
+    /// term of the entry at the index, None if it is not in the log (nor the last of the snapshot)
+    pub(super) fn term_at(&self, index: u64) -> Option<u64> {
+        match index {
+            index if index == self.state.snapshot_index => Some(self.state.snapshot_term),
+            index => self.entry(index).map(|entry| entry.term),
+        }
+    }
+    /// entry at the index, None if it is not in the log
+    pub(super) fn entry(&self, index: u64) -> Option<&Entry> {
+        let offset = index.checked_sub(self.state.snapshot_index + 1)?;
+        self.entries.get(offset as usize)
+    }
+    /// up to `max` entries starting at the index
+    pub(super) fn entries_from(&self, index: u64, max: usize) -> Vec<Entry> {
+        let offset = index.saturating_sub(self.state.snapshot_index + 1) as usize;
+        self.entries
+            .iter()
+            .skip(offset)
+            .take(max)
+            .cloned()
+            .collect()
+    }
+    /// index of the first entry of the log with the same term as the entry at the index
+    pub(super) fn first_index_of_term_at(&self, index: u64) -> u64 {
+        let term = self.term_at(index);
+        let mut first = index;
+        while first > self.state.snapshot_index + 1 && self.term_at(first - 1) == term {
+            first -= 1;
+        }
+        first
+    }
+    /// appends (and syncs) the entries, which must follow on from the last
+    pub(super) fn append(&mut self, entries: Vec<Entry>) -> Result<()> {
+        let mut buffer = Vec::new();
+        for entry in &entries {
+            write_entry(&mut buffer, entry)?;
+        }
+        self.file.write_all(&buffer)?;
+        self.file.sync_data()?;
+        self.entries.extend(entries);
+        Ok(())
+    }
+    /// removes the entry at the index and every later entry
+    pub(super) fn truncate_from(&mut self, index: u64) -> Result<()> {
+        let offset = index.saturating_sub(self.state.snapshot_index + 1) as usize;
+        self.entries.truncate(offset);
+        self.rewrite()
+    }
+    /// removes the entries up to the index, which the store (having been synced) holds
+    pub(super) fn compact_to(&mut self, index: u64, term: u64) -> Result<()> {
+        let offset = index.saturating_sub(self.state.snapshot_index) as usize;
+        self.entries.drain(..offset.min(self.entries.len()));
+        self.state.snapshot_index = index;
+        self.state.snapshot_term = term;
+        self.write_state()?;
+        self.rewrite()
+    }
+    /// removes every entry, the store (having been synced) holding those up to the index
+    pub(super) fn reset_to(&mut self, index: u64, term: u64) -> Result<()> {
+        self.entries.clear();
+        self.state.snapshot_index = index;
+        self.state.snapshot_term = term;
+        self.write_state()?;
+        self.rewrite()
+    }
+
+    /// writes the state to a new file which replaces the last, so it is never left torn
+    fn write_state(&mut self) -> Result<()> {
+        let path = self.dir.join(STATE_FILE);
+        let temp_path = path.with_extension("tmp");
+        let mut file = fs::File::create(&temp_path)?;
+        protocol::write_message(&mut file, &self.state)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &path)?;
+        sync_dir_of(&path);
+        Ok(())
+    }
+    /// writes the entries to a new log file which replaces the last
+    fn rewrite(&mut self) -> Result<()> {
+        let path = self.dir.join(LOG_FILE);
+        let temp_path = path.with_extension("tmp");
+        let mut buffer = Vec::new();
+        for entry in &self.entries {
+            write_entry(&mut buffer, entry)?;
+        }
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(&buffer)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &path)?;
+        sync_dir_of(&path);
+        self.file = fs::OpenOptions::new().append(true).open(&path)?;
+        Ok(())
+    }
+}
+
+fn write_entry(buffer: &mut Vec<u8>, entry: &Entry) -> Result<()> {
+    let mut payload = Vec::new();
+    protocol::write_message(&mut payload, entry)?;
+    buffer.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    buffer.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    buffer.extend_from_slice(&payload);
+    Ok(())
+}
+
+/// reads the entries of the log file, stopping at the first which is torn or corrupt, along with
+/// the length of the file up to it
+fn read_entries(path: &path::Path) -> Result<(Vec<Entry>, u64)> {
+    let mut bytes = Vec::new();
+    match fs::File::open(path) {
+        Ok(mut file) => file.read_to_end(&mut bytes).map(|_| ())?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+        Err(err) => return Err(Error::from(err).at_path(path)),
+    }
+    let mut entries = Vec::new();
+    let mut offset = 0;
+    while let Some(header) = bytes.get(offset..offset + 8) {
+        let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let payload = match bytes.get(offset + 8..offset + 8 + len) {
+            Some(payload) if crc32fast::hash(payload) == crc => payload,
+            _ => break,
+        };
+        match kvs_proto_serde::from_reader(&mut io::BufReader::new(payload)) {
+            Ok(entry) => entries.push(entry),
+            Err(_) => break,
+        }
+        offset += 8 + len;
+    }
+    Ok((entries, offset as u64))
+}
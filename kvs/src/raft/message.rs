@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+
+use super::super::SnapshotChunk;
+
+/// write replicated through the Raft log, applied to the store of every node in log order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) enum Command {
+    /// appended by each new leader so that it commits the entries of earlier terms
+    Noop,
+    Set {
+        key: String,
+        value: String,
+    },
+    SetIfAbsent {
+        key: String,
+        value: String,
+    },
+    SetOnce {
+        key: String,
+        value: String,
+        token: u64,
+    },
+    Remove {
+        key: String,
+    },
+    Append {
+        key: String,
+        suffix: String,
+    },
+    Batch {
+        operations: Vec<(String, Option<String>)>,
+    },
+    Clear,
+    /// the time of the leader (in milliseconds since the Unix epoch) is replicated, and the index
+    /// of the entry is the fencing token, so every node decides the same
+    AcquireLock {
+        name: String,
+        ttl_ms: u64,
+        now_ms: u64,
+    },
+    RefreshLock {
+        name: String,
+        token: u64,
+        ttl_ms: u64,
+        now_ms: u64,
+    },
+    ReleaseLock {
+        name: String,
+        token: u64,
+    },
+}
+
+/// entry of the Raft log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct Entry {
+    pub(super) index: u64,
+    pub(super) term: u64,
+    pub(super) command: Command,
+}
+
+/// request sent from one node of a cluster to another, using the kvs-proto framing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) enum RaftRequest {
+    /// a candidate asks for the vote of the node
+    RequestVote {
+        term: u64,
+        candidate: u64,
+        last_log_index: u64,
+        last_log_term: u64,
+    },
+    /// the leader replicates entries of its log (none for a heartbeat)
+    AppendEntries {
+        term: u64,
+        leader: u64,
+        prev_log_index: u64,
+        prev_log_term: u64,
+        entries: Vec<Entry>,
+        leader_commit: u64,
+    },
+    /// the leader sends a chunk of the snapshot of its store as of the last entry applied to a
+    /// node which needs entries it has compacted away, the node replacing its store with the
+    /// snapshot once it has every chunk
+    InstallSnapshot {
+        term: u64,
+        leader: u64,
+        last_index: u64,
+        last_term: u64,
+        chunk: SnapshotChunk,
+    },
+    /// a follower which has fallen too far behind for a read forwards it to the leader
+    Read { key: String },
+}
+
+/// response to a RaftRequest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) enum RaftResponse {
+    Vote {
+        term: u64,
+        granted: bool,
+    },
+    /// on success, the index of the last entry known to match the leader's log; on failure, the
+    /// index of the next entry the leader should try to send
+    Appended {
+        term: u64,
+        success: bool,
+        index: u64,
+    },
+    /// the offset of the next pair of the snapshot the node needs (the number of pairs in the
+    /// snapshot once it has installed it, or holds the entries already)
+    Installed {
+        term: u64,
+        next_offset: u64,
+    },
+    /// the value read for a Read request
+    Value {
+        value: Option<String>,
+    },
+    /// the leader failed to read (as it may no longer lead)
+    ReadFailed {
+        msg: String,
+    },
+}
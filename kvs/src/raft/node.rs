@@ -0,0 +1,987 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap, HashSet},
+    hash::{BuildHasher, Hasher},
+    io, net, path,
+    sync::{mpsc, Arc, Condvar, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::super::{
+    protocol,
+    transfer::{Snapshot, SnapshotReceiver},
+    Error, ErrorKind, KvsEngine, Lock, Result, SharedKvStore, SnapshotChunk, WriteBatch,
+};
+use super::log::RaftLog;
+use super::message::{Command, Entry, RaftRequest, RaftResponse};
+use super::{RaftOptions, RaftRole, RaftStatus};
+
+/// how often a node checks whether its election timeout has elapsed
+const ELECTION_TICK: Duration = Duration::from_millis(10);
+
+/// most entries sent to a follower in one AppendEntries request
+const MAX_ENTRIES_PER_REQUEST: usize = 256;
+
+/// result of applying a Command to the store
+pub(super) enum Output {
+    Done,
+    Applied(bool),
+    Batch(Vec<bool>),
+    Lock(Option<Lock>),
+    Length(usize),
+}
+
+/// a node of a Raft cluster, whose threads elect a leader and replicate its log to the others
+pub(super) struct Node {
+    id: u64,
+    peers: Vec<(u64, net::SocketAddr)>,
+    options: RaftOptions,
+    store: SharedKvStore,
+    core: Mutex<Core>,
+    /// notified whenever the core changes, waking replication and waiting requests
+    changed: Condvar,
+    listen_addr: net::SocketAddr,
+}
+
+/// state of a node guarded by its lock
+struct Core {
+    log: RaftLog,
+    role: RaftRole,
+    leader: Option<u64>,
+    commit: u64,
+    applied: u64,
+    election_deadline: Instant,
+    /// when a follower last heard from the leader having applied every entry it had committed
+    caught_up_at: Option<Instant>,
+    votes: HashSet<u64>,
+    /// the leader's view of each follower
+    progress: HashMap<u64, Progress>,
+    /// incremented for each linearizable read, which waits for a majority to acknowledge a
+    /// request sent since
+    read_round: u64,
+    /// requests awaiting the application of the entry they appended at each index (in a term)
+    pending: HashMap<u64, (u64, mpsc::Sender<Result<Output>>)>,
+    /// the snapshot a leader sends to followers needing entries it has compacted away (its id
+    /// being the index of its last entry), with the term of that entry
+    sending: Option<(Snapshot, u64)>,
+    /// the chunks of the leader's snapshot a follower has received so far
+    receiving: Option<SnapshotReceiver>,
+    stopped: bool,
+}
+
+#[derive(Default)]
+struct Progress {
+    /// index of the next entry to send
+    next: u64,
+    /// index of the last entry known to be replicated
+    matched: u64,
+    /// last read round the follower has acknowledged a request of
+    acked_round: u64,
+    /// id of the snapshot being sent to the follower, and the offset of the next pair to send
+    snapshot_offset: (u64, u64),
+}
+
+impl Node {
+    /// opens the node's log and starts its threads
+    pub(super) fn start(
+        path: &path::Path,
+        id: u64,
+        peers: Vec<(u64, net::SocketAddr)>,
+        listener: net::TcpListener,
+        options: RaftOptions,
+    ) -> Result<Arc<Self>> {
+        let store = SharedKvStore::open(path)?;
+        let log = RaftLog::open(&path.join(super::RAFT_DIR))?;
+        // the store holds every entry up to the snapshot, and those after it are applied again
+        // as they are committed
+        let applied = log.snapshot_index();
+        let node = Arc::new(Self {
+            id,
+            peers,
+            store,
+            core: Mutex::new(Core {
+                log,
+                role: RaftRole::Follower,
+                leader: None,
+                commit: applied,
+                applied,
+                election_deadline: Instant::now() + random_timeout(options.election_timeout),
+                caught_up_at: None,
+                votes: HashSet::new(),
+                progress: HashMap::new(),
+                read_round: 0,
+                pending: HashMap::new(),
+                sending: None,
+                receiving: None,
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+            listen_addr: listener.local_addr()?,
+            options,
+        });
+        let serving = Arc::clone(&node);
+        thread::spawn(move || serving.serve_peers(listener));
+        let electing = Arc::clone(&node);
+        thread::spawn(move || electing.run_elections());
+        for &(peer, addr) in &node.peers {
+            let replicating = Arc::clone(&node);
+            thread::spawn(move || replicating.replicate(peer, addr));
+        }
+        Ok(node)
+    }
+
+    pub(super) fn store(&self) -> &SharedKvStore {
+        &self.store
+    }
+
+    pub(super) fn status(&self) -> Result<RaftStatus> {
+        let core = self.lock()?;
+        Ok(RaftStatus {
+            id: self.id,
+            role: core.role,
+            term: core.log.term(),
+            leader: core.leader,
+            commit_index: core.commit,
+            applied_index: core.applied,
+        })
+    }
+
+    /// appends the command to the log of the leader and waits for it to be applied
+    pub(super) fn propose(&self, command: Command) -> Result<Output> {
+        let receiver = {
+            let mut core = self.lock()?;
+            if core.role != RaftRole::Leader {
+                return Err(not_leader(core.leader));
+            }
+            let (index, term) = (core.log.last_index() + 1, core.log.term());
+            core.log.append(vec![Entry {
+                index,
+                term,
+                command,
+            }])?;
+            let (sender, receiver) = mpsc::channel();
+            core.pending.insert(index, (term, sender));
+            self.advance_commit(&mut core)?;
+            self.changed.notify_all();
+            receiver
+        };
+        match receiver.recv_timeout(self.options.request_timeout) {
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(timed_out()),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(not_leader(None)),
+        }
+    }
+
+    /// waits until the store of the leader reflects every write committed before the call, having
+    /// confirmed with a majority of the cluster that it is still the leader
+    pub(super) fn read_barrier(&self) -> Result<()> {
+        let deadline = Instant::now() + self.options.request_timeout;
+        let mut core = self.lock()?;
+        let term = core.log.term();
+        let mut read = None;
+        loop {
+            if core.role != RaftRole::Leader || core.log.term() != term {
+                return Err(not_leader(core.leader));
+            }
+            // a new leader knows which entries are committed only once it commits one of its own
+            if read.is_none() && core.log.term_at(core.commit) == Some(term) {
+                core.read_round += 1;
+                read = Some((core.commit, core.read_round));
+                self.changed.notify_all();
+            }
+            if let Some((index, round)) = read {
+                let acks = 1 + core
+                    .progress
+                    .values()
+                    .filter(|progress| progress.acked_round >= round)
+                    .count();
+                if acks >= self.majority() && core.applied >= index {
+                    return Ok(());
+                }
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(timed_out());
+            }
+            core = self.wait(core, deadline - now)?;
+        }
+    }
+
+    /// reads the key from the store of a follower which has caught up with the leader within the
+    /// lag, or else from the leader (as `read_barrier` does)
+    pub(super) fn read_with_max_lag(
+        &self,
+        key: String,
+        max_lag: Duration,
+    ) -> Result<Option<String>> {
+        let (role, caught_up_at, leader) = {
+            let core = self.lock()?;
+            (core.role, core.caught_up_at, core.leader)
+        };
+        if role == RaftRole::Leader {
+            self.read_barrier()?;
+            return self.store.get(key);
+        }
+        if caught_up_at.is_some_and(|caught_up_at| caught_up_at.elapsed() <= max_lag) {
+            return self.store.get(key);
+        }
+        let addr = self
+            .peers
+            .iter()
+            .find(|&&(peer, _)| Some(peer) == leader)
+            .map(|&(_, addr)| addr)
+            .ok_or_else(|| not_leader(None))?;
+        match self.call(&mut None, addr, &RaftRequest::Read { key })? {
+            RaftResponse::Value { value } => Ok(value),
+            RaftResponse::ReadFailed { msg } => {
+                Err(Error::with_message(ErrorKind::ServerError, msg))
+            }
+            _ => Err(Error::new(ErrorKind::ProtocolError)),
+        }
+    }
+
+    /// stops the node's threads, failing the requests waiting on it
+    pub(super) fn stop(&self) -> Result<()> {
+        let mut core = self.lock()?;
+        core.stopped = true;
+        core.role = RaftRole::Follower;
+        core.leader = None;
+        core.pending.clear();
+        self.changed.notify_all();
+        drop(core);
+        // wakes the thread accepting connections from peers, so it sees the node has stopped
+        let _ = net::TcpStream::connect(self.listen_addr);
+        Ok(())
+    }
+
+    fn serve_peers(self: Arc<Self>, listener: net::TcpListener) {
+        for stream in listener.incoming() {
+            if self.is_stopped() {
+                return;
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let node = Arc::clone(&self);
+            thread::spawn(move || {
+                // the connection fails once the peer disconnects
+                let _ = node.handle_connection(stream);
+            });
+        }
+    }
+
+    fn handle_connection(&self, stream: net::TcpStream) -> Result<()> {
+        let mut reader = io::BufReader::new(stream);
+        while let Some(request) = protocol::read_message(&mut reader)? {
+            // a read forwarded by a follower waits for a majority, so must not hold the lock
+            if let RaftRequest::Read { key } = request {
+                let response = match self.read_barrier().and_then(|_| self.store.get(key)) {
+                    Ok(value) => RaftResponse::Value { value },
+                    Err(err) => RaftResponse::ReadFailed {
+                        msg: err.to_string(),
+                    },
+                };
+                protocol::write_message(reader.get_mut(), &response)?;
+                continue;
+            }
+            let response = match self.handle_request(request)? {
+                Some(response) => response,
+                None => return Ok(()),
+            };
+            protocol::write_message(reader.get_mut(), &response)?;
+        }
+        Ok(())
+    }
+
+    /// handles the request from a peer, None once the node has stopped (so that nothing is
+    /// applied to the store after it is shut down)
+    fn handle_request(&self, request: RaftRequest) -> Result<Option<RaftResponse>> {
+        let mut core = self.lock()?;
+        if core.stopped {
+            return Ok(None);
+        }
+        let response = match request {
+            RaftRequest::RequestVote {
+                term,
+                candidate,
+                last_log_index,
+                last_log_term,
+            } => {
+                if term > core.log.term() {
+                    self.step_down(&mut core, term, None)?;
+                }
+                let up_to_date = (last_log_term, last_log_index)
+                    >= (core.log.last_term(), core.log.last_index());
+                let granted = term == core.log.term()
+                    && core.log.voted_for().unwrap_or(candidate) == candidate
+                    && up_to_date;
+                if granted {
+                    core.log.set_term_and_vote(term, Some(candidate))?;
+                    core.election_deadline = self.next_election_deadline();
+                }
+                RaftResponse::Vote {
+                    term: core.log.term(),
+                    granted,
+                }
+            }
+            RaftRequest::AppendEntries {
+                term,
+                leader,
+                prev_log_index,
+                prev_log_term,
+                entries,
+                leader_commit,
+            } => {
+                if term < core.log.term() {
+                    RaftResponse::Appended {
+                        term: core.log.term(),
+                        success: false,
+                        index: 0,
+                    }
+                } else {
+                    self.follow(&mut core, term, leader)?;
+                    self.append_entries(
+                        &mut core,
+                        prev_log_index,
+                        prev_log_term,
+                        entries,
+                        leader_commit,
+                    )?
+                }
+            }
+            RaftRequest::InstallSnapshot {
+                term,
+                leader,
+                last_index,
+                last_term,
+                chunk,
+            } => {
+                let mut next_offset = chunk.offset;
+                if term >= core.log.term() {
+                    self.follow(&mut core, term, leader)?;
+                    next_offset = self.receive_snapshot(&mut core, last_index, last_term, chunk)?;
+                }
+                RaftResponse::Installed {
+                    term: core.log.term(),
+                    next_offset,
+                }
+            }
+            RaftRequest::Read { .. } => unreachable!("Read is handled by handle_connection"),
+        };
+        self.changed.notify_all();
+        Ok(Some(response))
+    }
+
+    /// appends the entries from the leader to the log (unless it does not hold the entry they
+    /// follow) and applies those the leader has committed
+    fn append_entries(
+        &self,
+        core: &mut Core,
+        prev_log_index: u64,
+        prev_log_term: u64,
+        entries: Vec<Entry>,
+        leader_commit: u64,
+    ) -> Result<RaftResponse> {
+        let term = core.log.term();
+        if prev_log_index > core.log.last_index() {
+            return Ok(RaftResponse::Appended {
+                term,
+                success: false,
+                index: core.log.last_index() + 1,
+            });
+        }
+        if let Some(held_term) = core.log.term_at(prev_log_index) {
+            if held_term != prev_log_term {
+                // the leader skips back past every entry of the conflicting term at once
+                return Ok(RaftResponse::Appended {
+                    term,
+                    success: false,
+                    index: core.log.first_index_of_term_at(prev_log_index),
+                });
+            }
+        }
+        let last_new = prev_log_index + entries.len() as u64;
+        let mut new_entries = Vec::new();
+        for entry in entries {
+            // entries up to the snapshot are committed, so already match
+            if entry.index <= core.log.snapshot_index() {
+                continue;
+            }
+            if new_entries.is_empty() {
+                match core.log.term_at(entry.index) {
+                    Some(held_term) if held_term == entry.term => continue,
+                    Some(_) => core.log.truncate_from(entry.index)?,
+                    None => (),
+                }
+            }
+            new_entries.push(entry);
+        }
+        if !new_entries.is_empty() {
+            core.log.append(new_entries)?;
+        }
+        if leader_commit > core.commit {
+            core.commit = leader_commit.min(last_new).max(core.commit);
+            self.apply_committed(core)?;
+        }
+        if core.applied >= leader_commit {
+            core.caught_up_at = Some(Instant::now());
+        }
+        Ok(RaftResponse::Appended {
+            term,
+            success: true,
+            index: last_new,
+        })
+    }
+
+    /// adds the chunk to the leader's snapshot, installing the snapshot once every chunk has been
+    /// received, returning the offset of the next pair needed
+    ///
+    /// A chunk which fails its checksum or does not follow those received is dropped, so the
+    /// leader sends it again, and a snapshot which fails its digest is dropped as a whole.
+    fn receive_snapshot(
+        &self,
+        core: &mut Core,
+        last_index: u64,
+        last_term: u64,
+        chunk: SnapshotChunk,
+    ) -> Result<u64> {
+        let total = chunk.total;
+        if last_index <= core.applied {
+            core.receiving = None;
+            return Ok(total);
+        }
+        let mut receiver = match core.receiving.take() {
+            Some(receiver) if receiver.snapshot_id() == chunk.snapshot_id => receiver,
+            _ => SnapshotReceiver::new(chunk.snapshot_id),
+        };
+        receiver.receive(chunk);
+        if !receiver.is_complete() {
+            let next_offset = receiver.next_offset();
+            core.receiving = Some(receiver);
+            return Ok(next_offset);
+        }
+        if !receiver.is_intact() {
+            return Ok(0);
+        }
+        self.install_snapshot(core, last_index, last_term, receiver.into_pairs())?;
+        Ok(total)
+    }
+
+    /// replaces the store with the leader's snapshot, unless it already holds the entries
+    fn install_snapshot(
+        &self,
+        core: &mut Core,
+        last_index: u64,
+        last_term: u64,
+        pairs: Vec<(String, String)>,
+    ) -> Result<()> {
+        if last_index <= core.applied {
+            return Ok(());
+        }
+        {
+            let mut store = self.store.lock()?;
+            store.clear()?;
+            let mut batch = WriteBatch::new();
+            for (key, value) in pairs {
+                batch.set(key, value);
+            }
+            store.write_batch(batch)?;
+            store.sync()?;
+        }
+        // later entries of the log are kept if the log agrees with the snapshot
+        match core.log.term_at(last_index) {
+            Some(term) if term == last_term => core.log.compact_to(last_index, last_term)?,
+            _ => core.log.reset_to(last_index, last_term)?,
+        }
+        core.commit = core.commit.max(last_index);
+        core.applied = last_index;
+        Ok(())
+    }
+
+    fn run_elections(self: Arc<Self>) {
+        loop {
+            thread::sleep(ELECTION_TICK);
+            let request = match self.lock() {
+                Ok(core) if core.stopped => return,
+                Ok(mut core) => match self.start_election_if_due(&mut core) {
+                    Ok(request) => request,
+                    Err(err) => {
+                        eprintln!("Raft election failed with error: {}", err);
+                        None
+                    }
+                },
+                Err(_) => return,
+            };
+            let request = match request {
+                Some(request) => request,
+                None => continue,
+            };
+            for &(peer, addr) in &self.peers {
+                let node = Arc::clone(&self);
+                let request = request.clone();
+                thread::spawn(move || {
+                    if let Ok(response) = node.call(&mut None, addr, &request) {
+                        let _ = node.handle_vote(peer, &request, response);
+                    }
+                });
+            }
+        }
+    }
+
+    /// becomes a candidate if the election timeout has elapsed without hearing from a leader,
+    /// returning the vote request to send to the peers
+    fn start_election_if_due(&self, core: &mut Core) -> Result<Option<RaftRequest>> {
+        if core.role == RaftRole::Leader || Instant::now() < core.election_deadline {
+            return Ok(None);
+        }
+        let term = core.log.term() + 1;
+        core.log.set_term_and_vote(term, Some(self.id))?;
+        core.role = RaftRole::Candidate;
+        core.leader = None;
+        core.votes = [self.id].iter().copied().collect();
+        core.election_deadline = self.next_election_deadline();
+        if core.votes.len() >= self.majority() {
+            self.become_leader(core)?;
+            return Ok(None);
+        }
+        Ok(Some(RaftRequest::RequestVote {
+            term,
+            candidate: self.id,
+            last_log_index: core.log.last_index(),
+            last_log_term: core.log.last_term(),
+        }))
+    }
+
+    fn handle_vote(&self, peer: u64, request: &RaftRequest, response: RaftResponse) -> Result<()> {
+        let mut core = self.lock()?;
+        match (request, response) {
+            (_, RaftResponse::Vote { term, .. }) if term > core.log.term() => {
+                self.step_down(&mut core, term, None)?;
+            }
+            (RaftRequest::RequestVote { term, .. }, RaftResponse::Vote { granted: true, .. })
+                if *term == core.log.term() && core.role == RaftRole::Candidate =>
+            {
+                core.votes.insert(peer);
+                if core.votes.len() >= self.majority() {
+                    self.become_leader(&mut core)?;
+                }
+            }
+            _ => (),
+        }
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    fn become_leader(&self, core: &mut Core) -> Result<()> {
+        core.role = RaftRole::Leader;
+        core.leader = Some(self.id);
+        let next = core.log.last_index() + 1;
+        core.progress = self
+            .peers
+            .iter()
+            .map(|&(peer, _)| {
+                let progress = Progress {
+                    next,
+                    ..Progress::default()
+                };
+                (peer, progress)
+            })
+            .collect();
+        let (index, term) = (next, core.log.term());
+        core.log.append(vec![Entry {
+            index,
+            term,
+            command: Command::Noop,
+        }])?;
+        self.advance_commit(core)
+    }
+
+    /// becomes a follower of the given term (forgetting its vote if the term is new)
+    fn step_down(&self, core: &mut Core, term: u64, leader: Option<u64>) -> Result<()> {
+        if term > core.log.term() {
+            core.log.set_term_and_vote(term, None)?;
+        }
+        core.role = RaftRole::Follower;
+        core.leader = leader;
+        core.votes.clear();
+        core.progress.clear();
+        core.sending = None;
+        Ok(())
+    }
+
+    /// records having heard from the leader of the term (at least as high as the node's own)
+    fn follow(&self, core: &mut Core, term: u64, leader: u64) -> Result<()> {
+        if term > core.log.term() || core.role != RaftRole::Follower {
+            self.step_down(core, term, Some(leader))?;
+        }
+        core.leader = Some(leader);
+        core.election_deadline = self.next_election_deadline();
+        Ok(())
+    }
+
+    /// sends the leader's log to the peer, and heartbeats while there is nothing to send
+    fn replicate(self: Arc<Self>, peer: u64, addr: net::SocketAddr) {
+        let mut connection = None;
+        let mut last_sent = Instant::now() - self.options.heartbeat_interval;
+        let mut sent_round = 0;
+        let mut failed = false;
+        loop {
+            let (request, round) = match self.next_request(peer, last_sent, sent_round, failed) {
+                Ok(Some(next)) => next,
+                _ => return,
+            };
+            last_sent = Instant::now();
+            sent_round = round;
+            match self.call(&mut connection, addr, &request) {
+                Ok(response) => {
+                    failed = false;
+                    if let Err(err) = self.handle_replicated(peer, &request, round, response) {
+                        eprintln!("Raft replication failed with error: {}", err);
+                    }
+                }
+                Err(_) => failed = true,
+            }
+        }
+    }
+
+    /// waits until a request is due to the peer (entries to send, a read to confirm, or a
+    /// heartbeat), returning it and the read round it confirms, or None once the node has stopped
+    fn next_request(
+        &self,
+        peer: u64,
+        last_sent: Instant,
+        sent_round: u64,
+        failed: bool,
+    ) -> Result<Option<(RaftRequest, u64)>> {
+        let mut core = self.lock()?;
+        loop {
+            if core.stopped {
+                return Ok(None);
+            }
+            if core.role == RaftRole::Leader {
+                if let Some(next) = core.progress.get(&peer).map(|progress| progress.next) {
+                    let heartbeat_due = last_sent.elapsed() >= self.options.heartbeat_interval;
+                    let entries_due = next <= core.log.last_index();
+                    let read_due = core.read_round > sent_round;
+                    // a peer which cannot be reached is only retried at heartbeats
+                    if heartbeat_due || (!failed && (entries_due || read_due)) {
+                        let round = core.read_round;
+                        let request = self.request_for(&mut core, peer, next)?;
+                        return Ok(Some((request, round)));
+                    }
+                }
+            }
+            core = self.wait(core, self.options.heartbeat_interval / 2)?;
+        }
+    }
+
+    /// the AppendEntries request sending the peer the entries from the index, or the
+    /// InstallSnapshot request sending it the next chunk of the snapshot if they have been
+    /// compacted away
+    ///
+    /// The snapshot is taken once and kept until the log is compacted past it, so that each chunk
+    /// sent to each follower comes from the same snapshot.
+    fn request_for(&self, core: &mut Core, peer: u64, next: u64) -> Result<RaftRequest> {
+        let term = core.log.term();
+        if next <= core.log.snapshot_index() {
+            let (snapshot, last_term) = match core.sending.take() {
+                Some((snapshot, last_term)) if snapshot.id() >= core.log.snapshot_index() => {
+                    (snapshot, last_term)
+                }
+                _ => {
+                    let last_index = core.applied;
+                    let snapshot = Snapshot::new(last_index, self.store.pairs()?);
+                    (snapshot, core.log.term_at(last_index).unwrap_or_default())
+                }
+            };
+            let offset = match core.progress.get(&peer) {
+                Some(progress) if progress.snapshot_offset.0 == snapshot.id() => {
+                    progress.snapshot_offset.1
+                }
+                _ => 0,
+            };
+            let request = RaftRequest::InstallSnapshot {
+                term,
+                leader: self.id,
+                last_index: snapshot.id(),
+                last_term,
+                chunk: snapshot.chunk(offset, self.options.snapshot_chunk_size),
+            };
+            core.sending = Some((snapshot, last_term));
+            return Ok(request);
+        }
+        let prev_log_index = next - 1;
+        Ok(RaftRequest::AppendEntries {
+            term,
+            leader: self.id,
+            prev_log_index,
+            prev_log_term: core.log.term_at(prev_log_index).unwrap_or_default(),
+            entries: core.log.entries_from(next, MAX_ENTRIES_PER_REQUEST),
+            leader_commit: core.commit,
+        })
+    }
+
+    fn handle_replicated(
+        &self,
+        peer: u64,
+        request: &RaftRequest,
+        round: u64,
+        response: RaftResponse,
+    ) -> Result<()> {
+        let mut core = self.lock()?;
+        let response_term = match response {
+            RaftResponse::Appended { term, .. } | RaftResponse::Installed { term, .. } => term,
+            _ => return Ok(()),
+        };
+        let request_term = match request {
+            RaftRequest::AppendEntries { term, .. } | RaftRequest::InstallSnapshot { term, .. } => {
+                *term
+            }
+            _ => return Ok(()),
+        };
+        if response_term > core.log.term() {
+            self.step_down(&mut core, response_term, None)?;
+            self.changed.notify_all();
+            return Ok(());
+        }
+        if core.role != RaftRole::Leader || request_term != core.log.term() {
+            return Ok(());
+        }
+        let progress = match core.progress.get_mut(&peer) {
+            Some(progress) => progress,
+            None => return Ok(()),
+        };
+        // the peer accepted the leader's term, confirming the leadership as of the read round
+        progress.acked_round = progress.acked_round.max(round);
+        match (request, response) {
+            (
+                _,
+                RaftResponse::Appended {
+                    success: true,
+                    index,
+                    ..
+                },
+            ) => {
+                progress.matched = progress.matched.max(index);
+                progress.next = progress.matched + 1;
+            }
+            (_, RaftResponse::Appended { index, .. }) => {
+                progress.next = index.max(progress.matched + 1);
+            }
+            (
+                RaftRequest::InstallSnapshot {
+                    last_index, chunk, ..
+                },
+                RaftResponse::Installed { next_offset, .. },
+            ) => {
+                if next_offset >= chunk.total {
+                    progress.matched = progress.matched.max(*last_index);
+                    progress.next = progress.matched + 1;
+                    progress.snapshot_offset = (0, 0);
+                } else {
+                    progress.snapshot_offset = (chunk.snapshot_id, next_offset);
+                }
+            }
+            _ => (),
+        }
+        self.advance_commit(&mut core)?;
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// commits the entries of the leader's term which a majority holds, applying them
+    fn advance_commit(&self, core: &mut Core) -> Result<()> {
+        let term = core.log.term();
+        let mut index = core.log.last_index();
+        while index > core.commit && core.log.term_at(index) == Some(term) {
+            let replicas = 1 + core
+                .progress
+                .values()
+                .filter(|progress| progress.matched >= index)
+                .count();
+            if replicas >= self.majority() {
+                core.commit = index;
+                break;
+            }
+            index -= 1;
+        }
+        self.apply_committed(core)
+    }
+
+    /// applies each committed entry to the store, answering the request which appended it (if
+    /// any), and compacts the log once enough entries have been applied
+    ///
+    /// The entries after the snapshot are applied again when the node restarts, which only an
+    /// Append would not come to the same value for, so the log is compacted past each Append.
+    fn apply_committed(&self, core: &mut Core) -> Result<()> {
+        let mut appended = false;
+        while core.applied < core.commit {
+            let index = core.applied + 1;
+            let entry = match core.log.entry(index) {
+                Some(entry) => entry.clone(),
+                None => break,
+            };
+            let term = entry.term;
+            appended |= matches!(entry.command, Command::Append { .. });
+            let result = self.execute(entry);
+            core.applied = index;
+            if let Some((proposed_term, sender)) = core.pending.remove(&index) {
+                let _ = match proposed_term == term {
+                    true => sender.send(result),
+                    // another leader's entry replaced the one proposed
+                    false => sender.send(Err(not_leader(core.leader))),
+                };
+            }
+        }
+        if appended || core.applied - core.log.snapshot_index() >= self.options.snapshot_threshold {
+            self.store.lock()?.sync()?;
+            let term = core.log.term_at(core.applied).unwrap_or_default();
+            core.log.compact_to(core.applied, term)?;
+        }
+        Ok(())
+    }
+
+    fn execute(&self, entry: Entry) -> Result<Output> {
+        let store = &self.store;
+        match entry.command {
+            Command::Noop => Ok(Output::Done),
+            Command::Set { key, value } => store.set(key, value).map(|_| Output::Done),
+            Command::SetIfAbsent { key, value } => {
+                store.set_if_absent(key, value).map(Output::Applied)
+            }
+            Command::SetOnce { key, value, token } => {
+                store.set_once(key, value, token).map(Output::Applied)
+            }
+            Command::Remove { key } => store.remove(key).map(|_| Output::Done),
+            Command::Append { key, suffix } => store.append(key, suffix).map(Output::Length),
+            Command::Batch { operations } => {
+                let mut batch = WriteBatch::new();
+                for (key, value) in operations {
+                    match value {
+                        Some(value) => batch.set(key, value),
+                        None => batch.remove(key),
+                    };
+                }
+                store.write_batch(batch).map(Output::Batch)
+            }
+            Command::Clear => store.clear().map(|_| Output::Done),
+            Command::AcquireLock {
+                name,
+                ttl_ms,
+                now_ms,
+            } => store
+                .lock()?
+                .acquire_lock_at(&name, Duration::from_millis(ttl_ms), entry.index, now_ms)
+                .map(Output::Lock),
+            Command::RefreshLock {
+                name,
+                token,
+                ttl_ms,
+                now_ms,
+            } => store
+                .lock()?
+                .refresh_lock_at(&name, token, Duration::from_millis(ttl_ms), now_ms)
+                .map(Output::Lock),
+            Command::ReleaseLock { name, token } => {
+                store.release_lock(name, token).map(Output::Applied)
+            }
+        }
+    }
+
+    /// sends the request to the peer over the connection (opening it if need be), dropping the
+    /// connection if it fails
+    fn call(
+        &self,
+        connection: &mut Option<io::BufReader<net::TcpStream>>,
+        addr: net::SocketAddr,
+        request: &RaftRequest,
+    ) -> Result<RaftResponse> {
+        let result = self.call_over(connection, addr, request);
+        if result.is_err() {
+            *connection = None;
+        }
+        result
+    }
+
+    fn call_over(
+        &self,
+        connection: &mut Option<io::BufReader<net::TcpStream>>,
+        addr: net::SocketAddr,
+        request: &RaftRequest,
+    ) -> Result<RaftResponse> {
+        let reader = match connection {
+            Some(reader) => reader,
+            None => {
+                let timeout = self.options.election_timeout;
+                let stream = net::TcpStream::connect_timeout(&addr, timeout)?;
+                stream.set_read_timeout(Some(timeout))?;
+                stream.set_write_timeout(Some(timeout))?;
+                stream.set_nodelay(true)?;
+                connection.insert(io::BufReader::new(stream))
+            }
+        };
+        protocol::write_message(reader.get_mut(), request)?;
+        protocol::read_message(reader)?
+            .ok_or_else(|| Error::from(io::Error::from(io::ErrorKind::UnexpectedEof)))
+    }
+
+    fn majority(&self) -> usize {
+        let cluster_size = self.peers.len() + 1;
+        cluster_size / 2 + 1
+    }
+
+    fn next_election_deadline(&self) -> Instant {
+        Instant::now() + random_timeout(self.options.election_timeout)
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.lock().map_or(true, |core| core.stopped)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Core>> {
+        self.core
+            .lock()
+            .map_err(|_| Error::new(ErrorKind::UnknownError))
+    }
+
+    fn wait<'a>(
+        &self,
+        core: MutexGuard<'a, Core>,
+        timeout: Duration,
+    ) -> Result<MutexGuard<'a, Core>> {
+        self.changed
+            .wait_timeout(core, timeout)
+            .map(|(core, _)| core)
+            .map_err(|_| Error::new(ErrorKind::UnknownError))
+    }
+}
+
+/// a timeout randomly between the given one and twice it, so that nodes rarely stand for
+/// election at once
+fn random_timeout(timeout: Duration) -> Duration {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    let millis = timeout.as_millis().max(1) as u64;
+    timeout + Duration::from_millis(hasher.finish() % millis)
+}
+
+fn not_leader(leader: Option<u64>) -> Error {
+    match leader {
+        Some(leader) => Error::with_message(
+            ErrorKind::NotLeader,
+            format!("the leader is node {}", leader),
+        ),
+        None => Error::new(ErrorKind::NotLeader),
+    }
+}
+
+fn timed_out() -> Error {
+    Error::with_source(
+        ErrorKind::IoError,
+        io::Error::new(
+            io::ErrorKind::TimedOut,
+            "the cluster did not respond in time",
+        ),
+    )
+}
@@ -0,0 +1,127 @@
+use std::{
+    fmt, hash,
+    io::{self, Seek},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{codec, Error, ErrorKind, KvStore, Operation, Result};
+
+/// positions of the fields of a record in its sequence (each after the first being one sooner in
+/// a record of the compact format, which has no offset)
+const DB_KEY_FIELD: usize = 0;
+const KEY_FIELD: usize = 1;
+const VALUE_FIELD: usize = 2;
+const MERGE_FIELD: usize = 3;
+
+impl<K, V> KvStore<K, V>
+where
+    K: Serialize + DeserializeOwned + Eq + PartialEq + hash::Hash + Clone + fmt::Debug,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    /// read the value stored under the given key into the buffer (replacing what it held) as it
+    /// is serialized in the log, without deserializing it, returning false (with the buffer left
+    /// empty) if no such key
+    ///
+    /// The bytes are the kvs-proto encoding of the value (which `kvs_proto_serde::from_reader`
+    /// deserializes), copied from the record into the buffer, which is reused from one call to
+    /// the next rather than allocating for each value. The value of a key written with merges (or
+    /// in a record of the ASN.1 DER format of earlier versions) is resolved and serialized again.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// let _ = store.set("key1".into(),"value1".into());
+    /// let mut buf = Vec::new();
+    /// assert!(store.get_raw("key1".into(), &mut buf).unwrap());
+    /// let value: String = kvs_proto_serde::from_reader(&mut std::io::BufReader::new(&buf[..])).unwrap();
+    /// assert_eq!(value, "value1");
+    /// assert!(!store.get_raw("key2".into(), &mut buf).unwrap());
+    /// ```
+    pub fn get_raw(&mut self, key: K, buf: &mut Vec<u8>) -> Result<bool> {
+        self.timed(Operation::Get, |store| store.get_raw_untimed(key, buf))
+    }
+    fn get_raw_untimed(&mut self, key: K, buf: &mut Vec<u8>) -> Result<bool> {
+        let found = match self.read_raw_value(&key, buf) {
+            Err(err)
+                if self.rebuild_index_on_inconsistency
+                    && matches!(err.kind(), ErrorKind::IndexInconsistent { .. }) =>
+            {
+                self.rebuild_index()?;
+                self.read_raw_value(&key, buf)
+            }
+            result => result,
+        }?;
+        if found {
+            self.record_use(&key);
+        }
+        Ok(found)
+    }
+    fn read_raw_value(&mut self, key: &K, buf: &mut Vec<u8>) -> Result<bool> {
+        buf.clear();
+        let db_key = match self.index.get(key) {
+            Some(&db_key) => db_key,
+            None => return Ok(false),
+        };
+        let inconsistent = || {
+            Error::new(ErrorKind::IndexInconsistent { offset: db_key })
+                .at_offset(db_key)
+                .for_key(key)
+        };
+        let read = self
+            .reader
+            .seek(io::SeekFrom::Start(db_key))
+            .and_then(|_| codec::read_record_fields(&mut self.reader, buf));
+        let fields = match read {
+            Ok(Some(fields)) if fields.len() > VALUE_FIELD => fields,
+            // a record of the format of earlier versions is read (and its value serialized again)
+            Ok(None) => return self.serialize_indexed_value(key, buf),
+            Ok(Some(_)) => return Err(inconsistent()),
+            Err(err) if codec::is_checksum_mismatch(&err) => {
+                return Err(Error::new(ErrorKind::CorruptLog { offset: db_key })
+                    .at_offset(db_key)
+                    .for_key(key))
+            }
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData
+                ) =>
+            {
+                return Err(inconsistent())
+            }
+            // failing to read the file at all says nothing about the index
+            Err(err) => return Err(Error::from(err).at_offset(db_key).for_key(key)),
+        };
+        let compact = fields.len() == codec::COMPACT_RECORD_FIELDS;
+        let field = |field: usize| fields[field - usize::from(compact)].clone();
+        let is_record_of_key = (compact
+            || codec::decode::<u64>(&buf[field(DB_KEY_FIELD)]).is_ok_and(|found| found == db_key))
+            && codec::decode::<K>(&buf[field(KEY_FIELD)]).is_ok_and(|found| found == *key);
+        if !is_record_of_key || codec::is_none(&buf[field(VALUE_FIELD)]) {
+            return Err(inconsistent());
+        }
+        let is_merge = fields
+            .get(MERGE_FIELD - usize::from(compact))
+            .is_some_and(|merge| codec::decode::<bool>(&buf[merge.clone()]).unwrap_or_default());
+        if is_merge {
+            return self.serialize_indexed_value(key, buf);
+        }
+        let value = field(VALUE_FIELD);
+        buf.copy_within(value.clone(), 0);
+        buf.truncate(value.len());
+        Ok(true)
+    }
+    /// reads the value of the key (resolving any merges) into the buffer, serialized again
+    fn serialize_indexed_value(&mut self, key: &K, buf: &mut Vec<u8>) -> Result<bool> {
+        buf.clear();
+        match self.get_indexed(key)? {
+            Some(value) => codec::encode(&value, buf)
+                .map(|_| true)
+                .map_err(|err| err.for_key(key)),
+            None => Ok(false),
+        }
+    }
+}
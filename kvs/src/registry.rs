@@ -0,0 +1,45 @@
+use std::{
+    collections::BTreeSet,
+    path,
+    sync::{Mutex, PoisonError},
+};
+
+use super::{Error, ErrorKind, Result};
+
+/// stores open in the process, by the directory and file prefix of their logs
+static OPEN: Mutex<BTreeSet<path::PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Registration of a KvStore as open in the process, until it is dropped (with the store), so
+/// that the store is not opened again while it is, as two stores writing to the same log would
+/// each write records at offsets the other does not know of
+pub(crate) struct Registration {
+    store: path::PathBuf,
+}
+
+impl Registration {
+    /// registers the store of the directory and file prefix as open, AlreadyOpen if it already is
+    pub(crate) fn register(dir: &path::Path, file_prefix: &str) -> Result<Self> {
+        // by the canonical path of the directory, so that the store is not opened again through
+        // another path to it
+        let store = dir
+            .canonicalize()
+            .map_err(|err| Error::from(err).at_path(dir))?
+            .join(file_prefix);
+        if !lock().insert(store.clone()) {
+            return Err(Error::new(ErrorKind::AlreadyOpen).at_path(dir));
+        }
+        Ok(Self { store })
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        lock().remove(&self.store);
+    }
+}
+
+/// the stores open in the process (the set being left consistent by each use, a panic while it
+/// is locked does not matter)
+fn lock() -> std::sync::MutexGuard<'static, BTreeSet<path::PathBuf>> {
+    OPEN.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,274 @@
+use std::{
+    io::{self, BufRead, Read, Write},
+    iter,
+};
+
+use super::{Error, ErrorKind};
+
+/// A single RESP (REdis Serialization Protocol) value
+pub use redis_serde::Value;
+
+/// longest line, of an inline command or of a length (that of Redis)
+const MAX_LINE_LEN: u64 = 64 * 1024;
+/// longest bulk string unless the server is configured otherwise (Redis's `proto-max-bulk-len`)
+pub(crate) const DEFAULT_MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+/// deepest nesting of arrays, below which a value is refused (a command being a flat array)
+const MAX_DEPTH: usize = 32;
+
+/// reads the next command from the reader, an array of bulk strings, or None if the reader is at
+/// the end of input
+///
+/// As with Redis, a command not starting with `*` is an inline command: a line of arguments
+/// separated by whitespace (as typed into telnet, and sent by `redis-benchmark` for PING_INLINE),
+/// which is read as the array of them. Empty lines are skipped. An argument may be quoted as
+/// redis-cli quotes it: in double quotes, with the escapes `\n`, `\r`, `\t`, `\b`, `\a`, `\xHH` (a
+/// byte of the hex digits) and `\` before any other character (that character), or in single
+/// quotes, with `\'` alone.
+///
+/// A line longer than 64 KiB or a bulk string longer than the given maximum is refused as
+/// InvalidData, before it is read.
+pub fn read_command<R: BufRead>(reader: &mut R, max_bulk_len: usize) -> io::Result<Option<Value>> {
+    loop {
+        match reader.fill_buf()?.first() {
+            None => return Ok(None),
+            Some(b'*') => return read_next_value(reader, 0, max_bulk_len).map(Some),
+            Some(_) => (),
+        }
+        let arguments = read_inline_arguments(reader)?;
+        if !arguments.is_empty() {
+            return Ok(Some(Value::Array(Some(arguments))));
+        }
+    }
+}
+
+/// writes the value to the writer (without flushing)
+pub fn write_value<W: Write>(writer: &mut W, value: &Value) -> io::Result<()> {
+    match value {
+        Value::SimpleString(string) => write!(writer, "+{}\r\n", string),
+        Value::Error(message) => write!(writer, "-{}\r\n", message),
+        Value::Integer(integer) => write!(writer, ":{}\r\n", integer),
+        Value::BulkString(None) => writer.write_all(b"$-1\r\n"),
+        Value::BulkString(Some(bytes)) => {
+            write!(writer, "${}\r\n", bytes.len())?;
+            writer.write_all(bytes)?;
+            writer.write_all(b"\r\n")
+        }
+        Value::Array(None) => writer.write_all(b"*-1\r\n"),
+        Value::Array(Some(values)) => {
+            write!(writer, "*{}\r\n", values.len())?;
+            values
+                .iter()
+                .try_for_each(|value| write_value(writer, value))
+        }
+    }
+}
+
+/// the RESP error replied for the error, of the code of its kind and its description
+pub(crate) fn error_reply(err: &Error) -> String {
+    format!("{} {}", error_code(*err.kind()), err)
+}
+
+/// the code (the first word of an error reply) of the errors of the kind, that of the Redis error
+/// most like it, and ERR for kinds which Redis has none like
+pub(crate) fn error_code(kind: ErrorKind) -> &'static str {
+    match kind {
+        // a value of another type than the store's, as with a Redis key of another type
+        ErrorKind::DeserializationError => "WRONGTYPE",
+        ErrorKind::QuotaExceeded => "OOM",
+        ErrorKind::Busy => "BUSY",
+        ErrorKind::NotLeader => "READONLY",
+        ErrorKind::Moved => "MOVED",
+        ErrorKind::AuthenticationFailed => "NOAUTH",
+        _ => "ERR",
+    }
+}
+
+/// the kind of the error of a RESP error reply, by its code, ServerError for ERR and codes of no
+/// kind of their own
+pub(crate) fn error_kind(reply: &str) -> ErrorKind {
+    match reply.split(' ').next().unwrap_or_default() {
+        "WRONGTYPE" => ErrorKind::DeserializationError,
+        "OOM" => ErrorKind::QuotaExceeded,
+        "BUSY" => ErrorKind::Busy,
+        "READONLY" => ErrorKind::NotLeader,
+        "MOVED" => ErrorKind::Moved,
+        "NOAUTH" | "WRONGPASS" => ErrorKind::AuthenticationFailed,
+        _ => ErrorKind::ServerError,
+    }
+}
+
+fn read_next_value<R: BufRead>(
+    reader: &mut R,
+    depth: usize,
+    max_bulk_len: usize,
+) -> io::Result<Value> {
+    let prefix = &mut [u8::default()];
+    reader.read_exact(prefix)?;
+    match prefix[0] {
+        b'+' => Ok(Value::SimpleString(read_line_as_string(reader)?)),
+        b'-' => Ok(Value::Error(read_line_as_string(reader)?)),
+        b':' => Ok(Value::Integer(read_line_as_integer(reader)?)),
+        b'$' => read_bulk_string(reader, max_bulk_len),
+        b'*' if depth < MAX_DEPTH => read_array(reader, depth + 1, max_bulk_len),
+        b'*' => Err(invalid_data(
+            "Protocol error: arrays nested too deeply".into(),
+        )),
+        unrecognized_prefix => Err(invalid_data(format!(
+            "Incorrect Field Prefix. Prefix received {:?}",
+            unrecognized_prefix as char
+        ))),
+    }
+}
+
+fn read_inline_arguments<R: BufRead>(reader: &mut R) -> io::Result<Vec<Value>> {
+    let mut line = Vec::new();
+    reader.take(MAX_LINE_LEN).read_until(b'\n', &mut line)?;
+    if !line.ends_with(b"\n") {
+        return Err(match line.len() as u64 {
+            MAX_LINE_LEN => invalid_data("Protocol error: too big inline request".into()),
+            _ => io::ErrorKind::UnexpectedEof.into(),
+        });
+    }
+    line.pop();
+    if line.ends_with(b"\r") {
+        line.pop();
+    }
+    Ok(split_inline_arguments(&line)?
+        .into_iter()
+        .map(|argument| Value::BulkString(Some(argument)))
+        .collect())
+}
+
+/// the arguments of an inline command, split as Redis splits them (sdssplitargs)
+fn split_inline_arguments(line: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    let unbalanced = || invalid_data("Protocol error: unbalanced quotes in request".into());
+    let mut arguments = Vec::new();
+    let mut bytes = line.iter().copied().peekable();
+    loop {
+        while bytes.next_if(is_separator).is_some() {}
+        if bytes.peek().is_none() {
+            return Ok(arguments);
+        }
+        let mut argument = Vec::new();
+        while let Some(byte) = bytes.next() {
+            let quote = match byte {
+                b'"' | b'\'' => byte,
+                byte if is_separator(&byte) => break,
+                byte => {
+                    argument.push(byte);
+                    continue;
+                }
+            };
+            loop {
+                match (bytes.next().ok_or_else(unbalanced)?, quote) {
+                    (b'\\', b'"') => argument.push(match bytes.next().ok_or_else(unbalanced)? {
+                        b'x' => hex_byte(&mut bytes).unwrap_or(b'x'),
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'b' => 0x08,
+                        b'a' => 0x07,
+                        escaped => escaped,
+                    }),
+                    (b'\\', b'\'') if bytes.next_if_eq(&b'\'').is_some() => argument.push(b'\''),
+                    (byte, quote) if byte == quote => break,
+                    (byte, _) => argument.push(byte),
+                }
+            }
+            // a closing quote ends the argument, so it must be followed by a separator
+            if bytes.peek().is_some_and(|byte| !is_separator(byte)) {
+                return Err(unbalanced());
+            }
+        }
+        arguments.push(argument);
+    }
+}
+
+/// the byte of the next two bytes if both are hex digits, consuming them, else None
+fn hex_byte(bytes: &mut iter::Peekable<impl Iterator<Item = u8> + Clone>) -> Option<u8> {
+    let mut ahead = bytes.clone();
+    let high = (ahead.next()? as char).to_digit(16)?;
+    let low = (ahead.next()? as char).to_digit(16)?;
+    *bytes = ahead;
+    Some((high * 16 + low) as u8)
+}
+
+fn is_separator(byte: &u8) -> bool {
+    matches!(byte, b' ' | b'\n' | b'\r' | b'\t' | b'\0')
+}
+
+fn read_bulk_string<R: BufRead>(reader: &mut R, max_bulk_len: usize) -> io::Result<Value> {
+    let len = match read_length(reader)? {
+        Some(len) if len > max_bulk_len => {
+            return Err(invalid_data("Protocol error: invalid bulk length".into()))
+        }
+        Some(len) => len,
+        None => return Ok(Value::BulkString(None)),
+    };
+    // read as it arrives rather than allocated up front, as the length may be anything
+    let mut buf = Vec::new();
+    if reader.by_ref().take(len as u64).read_to_end(&mut buf)? != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    let final_delimiter = &mut [u8::default(); 2];
+    reader.read_exact(final_delimiter)?;
+    match &final_delimiter[..] {
+        b"\r\n" => Ok(Value::BulkString(Some(buf))),
+        input => Err(invalid_data(format!(
+            "Expected ending delimiter 'CR LF' for Bulk String, found: {:?}",
+            input
+        ))),
+    }
+}
+
+fn read_array<R: BufRead>(reader: &mut R, depth: usize, max_bulk_len: usize) -> io::Result<Value> {
+    let len = match read_length(reader)? {
+        Some(len) => len,
+        None => return Ok(Value::Array(None)),
+    };
+    let values = (0..len)
+        .map(|_| read_next_value(reader, depth, max_bulk_len))
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(Value::Array(Some(values)))
+}
+
+fn read_length<R: BufRead>(reader: &mut R) -> io::Result<Option<usize>> {
+    match read_line_as_integer(reader)? {
+        -1 => Ok(None),
+        len if len >= 0 => Ok(Some(len as usize)),
+        len => Err(invalid_data(format!("Invalid length: {}", len))),
+    }
+}
+
+fn read_line_as_integer<R: BufRead>(reader: &mut R) -> io::Result<i64> {
+    let line = read_line_as_string(reader)?;
+    line.parse::<i64>()
+        .map_err(|_| invalid_data(format!("Expected an integer, found: {:?}", line)))
+}
+
+fn read_line_as_string<R: BufRead>(reader: &mut R) -> io::Result<String> {
+    String::from_utf8(read_line(reader)?)
+        .map_err(|_| invalid_data("Expected a UTF-8 encoded line".into()))
+}
+
+fn read_line<R: BufRead>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut line = Vec::new();
+    let _ = reader
+        .by_ref()
+        .take(MAX_LINE_LEN)
+        .read_until(b'\n', &mut line)?;
+    if line.ends_with(b"\r\n") {
+        line.truncate(line.len() - 2);
+        Ok(line)
+    } else if line.len() as u64 == MAX_LINE_LEN {
+        Err(invalid_data("Protocol error: too big line".into()))
+    } else {
+        Err(invalid_data(
+            "End of input reached with missing or incorrect CR\\LF pair".into(),
+        ))
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,98 @@
+use std::{fmt, hash};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{Error, ErrorKind, KvStore, Result};
+
+/// Settings of a KvStore which may be changed while it is open, with `reconfigure` (as a server
+/// does when it reloads its configuration): when its log is compacted, its quotas, and whether it
+/// is instrumented
+///
+/// It may be read from JSON (each setting left out taking its default), such as
+/// ```json
+/// {"compaction_stale_fraction": 0.5, "max_keys": 100000}
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeOptions {
+    /// fraction of stale records (written over or removed) per key at which a write compacts the
+    /// log, 0.25 by default (it must be positive)
+    pub compaction_stale_fraction: f64,
+    /// least number of keys the store holds for a write to compact the log, 100 by default
+    pub compaction_min_keys: u64,
+    /// maximum number of keys the store may hold, as in StoreOptions (a store already holding
+    /// more keeps them, failing or evicting as a new key is set)
+    pub max_keys: Option<u64>,
+    /// maximum length in bytes of the store's log, as in StoreOptions (which must be set if the
+    /// store preallocates its logs)
+    pub max_log_bytes: Option<u64>,
+    /// hard ceiling of the number of stale records the log may hold per key, as in StoreOptions
+    pub max_stale_ratio: Option<f64>,
+    /// send the store's operational events and record its latencies and sizes, as in
+    /// StoreOptions, true by default
+    pub instrumented: bool,
+}
+
+impl Default for RuntimeOptions {
+    fn default() -> Self {
+        Self {
+            compaction_stale_fraction: 0.25,
+            compaction_min_keys: 100,
+            max_keys: None,
+            max_log_bytes: None,
+            max_stale_ratio: None,
+            instrumented: true,
+        }
+    }
+}
+
+impl RuntimeOptions {
+    /// true unless a fraction or ratio is not positive
+    pub(crate) fn is_valid(&self) -> bool {
+        let positive = |fraction: f64| fraction.is_finite() && fraction > 0.0;
+        positive(self.compaction_stale_fraction) && self.max_stale_ratio.is_none_or(positive)
+    }
+}
+
+impl<K, V> KvStore<K, V>
+where
+    K: Serialize + DeserializeOwned + Eq + PartialEq + hash::Hash + Clone + fmt::Debug,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    /// the settings of the store which may be changed while it is open
+    pub fn runtime_options(&self) -> RuntimeOptions {
+        RuntimeOptions {
+            compaction_stale_fraction: self.stale_fraction_for_compaction,
+            compaction_min_keys: self.min_records_before_compaction,
+            max_keys: self.max_keys,
+            max_log_bytes: self.max_log_bytes,
+            max_stale_ratio: self.max_stale_ratio,
+            instrumented: self.instrumented,
+        }
+    }
+    /// change the settings of the store, which apply from its next write (InvalidConfiguration,
+    /// changing none of them, if they are not valid)
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::{KvStore, RuntimeOptions};
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// store.reconfigure(RuntimeOptions {
+    ///     max_keys: Some(1000),
+    ///     ..store.runtime_options()
+    /// }).unwrap();
+    /// ```
+    pub fn reconfigure(&mut self, options: RuntimeOptions) -> Result<()> {
+        if !options.is_valid() || (self.preallocate && options.max_log_bytes.is_none()) {
+            return Err(Error::new(ErrorKind::InvalidConfiguration));
+        }
+        self.stale_fraction_for_compaction = options.compaction_stale_fraction;
+        self.min_records_before_compaction = options.compaction_min_keys;
+        self.max_keys = options.max_keys;
+        self.max_log_bytes = options.max_log_bytes;
+        self.max_stale_ratio = options.max_stale_ratio;
+        self.instrumented = options.instrumented;
+        Ok(())
+    }
+}
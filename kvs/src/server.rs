@@ -0,0 +1,394 @@
+//! The TCP server of kvs
+//!
+//! Each connection may speak either the Redis protocol (RESP) or the kvs-proto protocol used by
+//! KvsClient, which is detected from the first byte the client sends.
+//!
+//! Supported RESP commands are GET, SET, MGET, MSET, DEL, EXISTS, APPEND, STRLEN, KEYS, SCAN,
+//! SELECT, AUTH and PING along with the administrative commands COMPACT, FLUSHALL (or FLUSHDB),
+//! DBSIZE, INFO (the number of keys and the sizes of the keys and values written), CONFIG (RELOAD
+//! and GET) and COMMAND (COUNT and LIST). Commands may be sent inline (as a
+//! line of arguments, as typed into telnet), and requests which are pipelined by the client are
+//! read together before responding, with consecutive SET/MSET/DEL commands being applied to the
+//! engine as a single batch. MULTI starts a transaction of SET/MSET/DEL commands, which EXEC
+//! applies as a single batch (all of them or none) and DISCARD drops. This is enough for
+//! `redis-benchmark -t ping,set,get,mset` (with `-P` to pipeline) to run against the server;
+//! `redis_compatibility_report` (`kvs-server --redis-compat-report`) lists the commands and
+//! benchmark tests supported.
+//!
+//! kvs-proto clients may also `subscribe` to the events of keys, the connection then streaming
+//! them until it is closed, and `backup` every key and value of the server, which takes a snapshot
+//! of the engine and sends it in chunks, holding it until the last chunk is sent so that a client
+//! whose connection fails part way through resumes where it stopped.
+//!
+//! kvs-proto clients may tag their requests with an ID (such as the correlation ID of the work
+//! they are part of) `KvsClient::set_request_id`, which the server reports them by: each
+//! kvs-proto request is served within a `request` tracing span of its ID and command (with the
+//! `tracing` feature), a request with an ID which fails is logged to stderr with it, and the
+//! message of the error sent in response ends with it.
+
+mod acl;
+pub use acl::{Acl, AclUser};
+mod auth;
+mod cluster;
+mod filter;
+mod info;
+mod kvs_proto;
+mod limits;
+pub use limits::RateLimit;
+mod migration;
+mod redis;
+pub use redis::redis_compatibility_report;
+mod reload;
+pub use reload::{ReloadHandle, ServerConfig};
+mod request_log;
+pub use request_log::LogLevel;
+mod scan;
+mod shutdown;
+mod snapshots;
+pub use shutdown::ShutdownHandle;
+
+use std::{
+    io::{self, BufRead, Write},
+    net,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+#[cfg(feature = "tls")]
+use super::ServerTlsConfig;
+use super::{protocol, resp, KvsEngine, Request, Result, Topology};
+use auth::Authentication;
+use cluster::{Cluster, Placement};
+use filter::Filter;
+use limits::{RateLimiter, Throttle};
+use request_log::RequestLog;
+use scan::Cursors;
+use snapshots::Snapshots;
+
+/// pause before accepting connections again when out of file descriptors
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Key-Value Storage server on TCP, speaking both RESP and kvs-proto
+///
+/// A server hosts the engine it is created with as database 0, and may be configured with further
+/// databases, authentication, limits, a request filter and cluster membership through its `with_*`
+/// methods. It runs until it is shut down through its `shutdown_handle`.
+pub struct KvsServer<E: KvsEngine> {
+    context: ServerContext<E>,
+    password: Option<String>,
+    acl: Option<Arc<Acl>>,
+    max_connections: Option<usize>,
+    rate_limiter: Arc<RateLimiter>,
+    #[cfg(feature = "tls")]
+    tls: Option<ServerTlsConfig>,
+}
+
+/// state of a server which each of its connections serves requests with (a clone of it, as the
+/// engines need not be Sync)
+#[derive(Clone)]
+struct ServerContext<E: KvsEngine> {
+    databases: Vec<E>,
+    max_bulk_len: usize,
+    filter: Filter,
+    cluster: Cluster,
+    snapshots: Snapshots,
+    cursors: Cursors,
+    log: RequestLog,
+    reload: ReloadHandle,
+    shutdown: ShutdownHandle,
+}
+
+impl<E: KvsEngine> KvsServer<E> {
+    /// create a new server which serves requests from the given engine
+    pub fn new(engine: E) -> Self {
+        let (rate_limiter, log) = (Arc::new(RateLimiter::default()), RequestLog::default());
+        let reload = ReloadHandle::new(rate_limiter.clone(), log.clone());
+        reload.add_engine(engine.clone());
+        Self {
+            context: ServerContext {
+                databases: vec![engine],
+                max_bulk_len: resp::DEFAULT_MAX_BULK_LEN,
+                filter: Filter::default(),
+                cluster: Cluster::default(),
+                snapshots: Snapshots::default(),
+                cursors: Cursors::default(),
+                log,
+                reload,
+                shutdown: ShutdownHandle::new(),
+            },
+            password: None,
+            acl: None,
+            max_connections: None,
+            rate_limiter,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+    /// serve the engine as the next database (numbered from 1, the engine the server was created
+    /// with being database 0), which connections switch to with SELECT
+    ///
+    /// A connection uses database 0 until it switches to another (with `KvsClient::select` for
+    /// kvs-proto clients), its later requests being made to that database, so that one process
+    /// serves several applications. The HTTP gateway and gRPC service of an engine serve it alone.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kvs::{KvStore, KvsServer, SharedKvStore};
+    ///
+    /// let store = KvStore::open(std::path::Path::new("testdb")).unwrap();
+    /// let sessions = SharedKvStore::new(store.keyspace("sessions").unwrap());
+    /// let server = KvsServer::new(SharedKvStore::new(store)).with_database(sessions);
+    /// server.run("127.0.0.1:4000").unwrap();
+    /// ```
+    pub fn with_database(mut self, engine: E) -> Self {
+        self.context.reload.add_engine(engine.clone());
+        self.context.databases.push(engine);
+        self
+    }
+    /// require clients to authenticate with the given password (with AUTH for RESP) before any
+    /// other request
+    pub fn with_password(mut self, password: String) -> Self {
+        self.password = Some(password);
+        self
+    }
+    /// require clients to authenticate as a user of the access control list before any other
+    /// request, restricting them to the commands and keys the user is allowed
+    ///
+    /// Each user authenticates with a token of its own (`AUTH <token>` or `AUTH <user> <token>` for
+    /// RESP, the token being the password of `KvsClient::authenticate`), and may be restricted to
+    /// some commands, to the keys of some prefixes and to reading them. A request the user is not
+    /// allowed is refused with a `NOPERM` error. A connection authenticating with the password (if
+    /// the server has one as well) is not restricted.
+    pub fn with_acl(mut self, acl: Acl) -> Self {
+        self.acl = Some(Arc::new(acl));
+        self
+    }
+    /// log each kvs-proto request which takes at least the threshold to serve to stderr (with the
+    /// ID its client tagged it with)
+    pub fn with_slow_log(self, threshold: std::time::Duration) -> Self {
+        self.context.log.set_slow_threshold(Some(threshold));
+        self
+    }
+    /// log to stderr as the level says (Warn by default)
+    pub fn with_log_level(self, level: LogLevel) -> Self {
+        self.context.log.set_level(level);
+        self
+    }
+    /// refuse connections while the given number of connections are already open, sending them a
+    /// RESP error before closing them
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = Some(max_connections);
+        self
+    }
+    /// refuse RESP bulk strings (such as the value of a SET) longer than the given number of bytes
+    /// as a protocol error, rather than the 512 MiB of Redis's `proto-max-bulk-len`
+    pub fn with_max_bulk_len(mut self, max_bulk_len: usize) -> Self {
+        self.context.max_bulk_len = max_bulk_len;
+        self
+    }
+    /// limit the rate of requests from each client IP address
+    pub fn with_rate_limit(self, limit: RateLimit) -> Self {
+        self.rate_limiter.set_limit(Some(limit));
+        self
+    }
+    /// run the filter on each request before executing it, a request it rejects failing with the
+    /// message it gives (as a RESP error, or a ServerError for kvs-proto clients)
+    ///
+    /// RESP commands are given to the filter as the kvs-proto Requests they amount to, and a
+    /// request for many keys (such as SetMany, MSET or DEL) as the requests of a single key each it
+    /// amounts to. KEYS and SCAN, which have no kvs-proto requests, are not filtered.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use kvs::{KvsServer, Request, SharedKvStore};
+    ///
+    /// let engine = SharedKvStore::open(std::path::Path::new("testdb")).unwrap();
+    /// let server = KvsServer::new(engine).with_filter(|request: &Request| match request {
+    ///     Request::Set { value, .. } if value.len() > 1024 => Err("ERR value too long".into()),
+    ///     _ => Ok(()),
+    /// });
+    /// server.run("127.0.0.1:4000").unwrap();
+    /// ```
+    pub fn with_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&Request) -> std::result::Result<(), String> + Send + Sync + 'static,
+    {
+        self.context.filter = Filter::new(filter);
+        self
+    }
+    /// serve as the node of the given id of a cluster of the given topology, redirecting requests
+    /// for keys of the shards held by other nodes to them
+    ///
+    /// A request for a key of a shard the node does not hold is answered with the address of a
+    /// node which does (MOVED for kvs-proto clients, a `-MOVED <addr>` error for RESP), as is a
+    /// request the engine refuses as another node leads it (for kvs-proto clients). Clients may ask
+    /// for the topology to route their requests themselves, as ClusterClient does.
+    ///
+    /// To rebalance the cluster (for instance, after adding a node), a range of keys may be
+    /// migrated from a node to another while both go on serving requests: the node copies every
+    /// key of the range to the target and then each write to the range as it is made, until the
+    /// migration is cut over to a topology giving the range to the target. The node then serves by
+    /// the new topology (once the requests it is executing have finished), copies the last writes,
+    /// sends the topology to the target and removes its own copy of the range. Writes made to the
+    /// engine other than through the server (as by an HttpGateway) are copied too, but are not held
+    /// off during the cutover, so one made then may be lost. These administrative requests are made
+    /// with `KvsClient::migrate_range`, `migration_status`, `finish_migration` and `set_topology`.
+    pub fn with_cluster(mut self, id: u64, topology: Topology) -> Self {
+        self.context.cluster = Cluster::new(id, topology);
+        self
+    }
+    /// require every connection to use TLS with the given configuration
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, tls: ServerTlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+    /// read the settings of the ServerConfig in the JSON file at the path when the server starts
+    /// (overriding those it was configured with) and each time it is reloaded
+    ///
+    /// The log level, slow log threshold and rate limit, and the compaction thresholds and quotas
+    /// of the databases, are read from the file, so they may be changed without restarting the
+    /// server: it is reloaded through its `reload_handle` (as `kvs-server` does on SIGHUP) or by a
+    /// client (`KvsClient::reload_config`, or `CONFIG RELOAD` for RESP).
+    pub fn with_config_file(self, path: std::path::PathBuf) -> Self {
+        self.context.reload.set_path(path);
+        self
+    }
+    /// handle which reloads the server's config file
+    pub fn reload_handle(&self) -> ReloadHandle {
+        self.context.reload.clone()
+    }
+    /// handle which shuts the server down gracefully
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.context.shutdown.clone()
+    }
+    /// bind to the given address and serve connections until shut down
+    pub fn run<A: net::ToSocketAddrs>(self, addr: A) -> Result<()> {
+        self.serve(net::TcpListener::bind(addr)?)
+    }
+    /// serve connections from an already bound listener, each upon its own thread
+    ///
+    /// When shut down, this returns once every connection has closed and the engine of each
+    /// database has been shut down. The server's config file (if any) is read first, failing with
+    /// InvalidConfiguration if it is not valid. The operational events of each database (such as
+    /// its compactions and stalled writes) are logged to stderr as the log level says, as are
+    /// errors accepting connections, which the server carries on after (pausing briefly when out
+    /// of file descriptors).
+    pub fn serve(self, listener: net::TcpListener) -> Result<()> {
+        let context = self.context;
+        context.reload.reload_if_configured()?;
+        for (db, engine) in context.databases.iter().enumerate() {
+            context.log.log_events(db, engine.subscribe_events()?);
+        }
+        context.shutdown.listening_on(listener.local_addr()?)?;
+        while !context.shutdown.is_shutdown_requested() {
+            let (mut stream, client) = match listener.accept() {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    context.accept_failed(&err);
+                    continue;
+                }
+            };
+            if context.shutdown.is_shutdown_requested() {
+                break;
+            }
+            if let Some(max_connections) = self.max_connections {
+                if context.shutdown.open_connections()? >= max_connections {
+                    let _ = stream.write_all(b"-ERR max number of clients reached\r\n");
+                    continue;
+                }
+            }
+            let connection = match context.shutdown.register(&stream) {
+                Ok(connection) => connection,
+                Err(err) => {
+                    context.accept_failed(&err);
+                    continue;
+                }
+            };
+            let context = context.clone();
+            let password = self.password.clone();
+            let acl = self.acl.clone();
+            let throttle = Throttle::new(self.rate_limiter.clone(), client.ip());
+            #[cfg(feature = "tls")]
+            let tls = self.tls.clone();
+            thread::spawn(move || {
+                let _connection = connection;
+                let auth = Authentication::new(password.as_deref(), acl.as_deref());
+                #[cfg(feature = "tls")]
+                let result = match tls {
+                    Some(tls) => tls
+                        .accept(stream)
+                        .and_then(|stream| handle_connection(&context, auth, throttle, stream)),
+                    None => handle_connection(&context, auth, throttle, stream),
+                };
+                #[cfg(not(feature = "tls"))]
+                let result = handle_connection(&context, auth, throttle, stream);
+                match result {
+                    Err(err) if context.log.logs(LogLevel::Error) => {
+                        eprintln!("Connection terminated with error: {}", err)
+                    }
+                    _ => (),
+                }
+            });
+        }
+        drop(listener);
+        context.shutdown.drain_connections()?;
+        context.databases.iter().try_for_each(KvsEngine::shutdown)
+    }
+    /// serve a single connection (of either protocol) over the stream on the current thread, as
+    /// if accepted from the client, until its input ends
+    #[cfg(feature = "fuzzing")]
+    pub(crate) fn serve_stream<S: io::Read + io::Write>(
+        &self,
+        stream: S,
+        client: std::net::IpAddr,
+    ) -> Result<()> {
+        let auth = Authentication::new(self.password.as_deref(), self.acl.as_deref());
+        let throttle = Throttle::new(self.rate_limiter.clone(), client);
+        handle_connection(&self.context, auth, throttle, stream)
+    }
+}
+
+impl<E: KvsEngine> ServerContext<E> {
+    /// logs the error accepting (or registering) a connection, which the server outlives, pausing
+    /// when out of file descriptors so that open connections may close before it accepts again
+    fn accept_failed(&self, err: &(dyn std::error::Error + 'static)) {
+        if self.log.logs(LogLevel::Error) {
+            eprintln!("Failed to accept a connection: {}", err);
+        }
+        let io_error = err
+            .downcast_ref::<io::Error>()
+            .or_else(|| err.source()?.downcast_ref());
+        if io_error.is_some_and(out_of_file_descriptors) {
+            thread::sleep(ACCEPT_BACKOFF);
+        }
+    }
+}
+
+fn handle_connection<E: KvsEngine, S: io::Read + io::Write>(
+    context: &ServerContext<E>,
+    auth: Authentication,
+    throttle: Throttle,
+    stream: S,
+) -> Result<()> {
+    let mut reader = io::BufReader::new(stream);
+    match reader.fill_buf()?.first() {
+        Some(&first_byte) if protocol::is_kvs_proto_message_start(first_byte) => {
+            kvs_proto::handle_connection(context, auth, throttle, reader)
+        }
+        Some(_) => redis::handle_connection(context, auth, throttle, reader),
+        None => Ok(()),
+    }
+}
+
+/// true if the error is for the process (or the system) having run out of file descriptors
+fn out_of_file_descriptors(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    return matches!(err.raw_os_error(), Some(libc::EMFILE) | Some(libc::ENFILE));
+    #[cfg(not(unix))]
+    {
+        let _ = err;
+        false
+    }
+}
@@ -0,0 +1,263 @@
+use serde::Deserialize;
+
+use super::super::Request;
+
+/// Access control list of a KvsServer: the users who may connect, each authenticating with a
+/// token of its own, and the commands and keys each may use
+///
+/// Commands are named as in RESP (FLUSHDB being allowed as FLUSHALL), kvs-proto requests being
+/// named by the command they amount to: GET (GetWithMaxLag too), SET (SetOnce too), SETNX, DEL,
+/// DBSIZE, INFO, COMPACT, FLUSHALL, SELECT, KEYS, SCAN, SUBSCRIBE, LOCK (acquiring, refreshing and
+/// releasing locks), SNAPSHOT, CONFIG (reloading the server's config file) and CLUSTER (the
+/// topology and migration requests, Import included).
+///
+/// It may be read from JSON, such as
+/// ```json
+/// {"users": [
+///     {"name": "admin", "token": "admin-token"},
+///     {"name": "reports", "token": "reports-token", "read_only": true,
+///         "key_prefixes": ["report:"], "commands": ["GET", "DBSIZE"]}
+/// ]}
+/// ```
+///
+/// # Example
+/// ```no_run
+/// use kvs::{Acl, AclUser, KvsServer, SharedKvStore};
+///
+/// let engine = SharedKvStore::open(std::path::Path::new("testdb")).unwrap();
+/// let acl = Acl::new()
+///     .with_user(AclUser::new("admin".into(), "admin-token".into()))
+///     .with_user(
+///         AclUser::new("reports".into(), "reports-token".into())
+///             .read_only()
+///             .with_key_prefix("report:".into()),
+///     );
+/// KvsServer::new(engine).with_acl(acl).run("127.0.0.1:4000").unwrap();
+/// ```
+#[derive(Clone, Default, Deserialize)]
+pub struct Acl {
+    users: Vec<AclUser>,
+}
+
+impl Acl {
+    /// create a list without any users
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// allow the user to connect, authenticating with its token
+    pub fn with_user(mut self, user: AclUser) -> Self {
+        self.users.push(user);
+        self
+    }
+
+    /// the user the token (and name, if given) are of, comparing the attempted token with the
+    /// token of every user so the time taken does not reveal which matched
+    pub(super) fn user(&self, name: Option<&[u8]>, token: &[u8]) -> Option<&AclUser> {
+        self.users.iter().fold(None, |found, user| {
+            let matched = super::auth::constant_time_eq(user.token.as_bytes(), token)
+                && name.is_none_or(|name| name == user.name.as_bytes());
+            found.or(Some(user).filter(|_| matched))
+        })
+    }
+}
+
+/// User of an Acl, who may use every command and key (for reading and writing) unless restricted
+#[derive(Clone, Deserialize)]
+pub struct AclUser {
+    name: String,
+    token: String,
+    #[serde(default)]
+    read_only: bool,
+    #[serde(default)]
+    key_prefixes: Vec<String>,
+    #[serde(default)]
+    commands: Option<Vec<String>>,
+}
+
+impl AclUser {
+    /// create a user of the name, authenticating with the token, allowed every command and key
+    pub fn new(name: String, token: String) -> Self {
+        Self {
+            name,
+            token,
+            read_only: false,
+            key_prefixes: Vec::new(),
+            commands: None,
+        }
+    }
+    /// allow the user to read keys but not write them (nor compact or clear the store, or change
+    /// the cluster)
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+    /// allow the user the keys starting with the prefix, restricting it to the keys of its
+    /// prefixes once it is given one (so it may no longer use the commands for every key, such
+    /// as KEYS, FLUSHALL and SNAPSHOT)
+    pub fn with_key_prefix(mut self, prefix: String) -> Self {
+        self.key_prefixes.push(prefix);
+        self
+    }
+    /// allow the user the command (named as in RESP), restricting it to the commands given once
+    /// it is given one (AUTH always being allowed)
+    pub fn with_command(mut self, command: String) -> Self {
+        self.commands.get_or_insert_with(Vec::new).push(command);
+        self
+    }
+
+    /// checks the user may make the request as part of the command, returning why not if not
+    pub(super) fn check(
+        &self,
+        command: &str,
+        request: &Request,
+    ) -> std::result::Result<(), String> {
+        self.check_command(command)?;
+        if self.read_only && writes(request) {
+            return Err(format!(
+                "NOPERM this user has no permissions to write with the '{}' command",
+                command.to_lowercase()
+            ));
+        }
+        let permitted = match keys_of(request) {
+            Keys::None => true,
+            Keys::Every => self.key_prefixes.is_empty(),
+            Keys::Key(key) | Keys::Prefix(key) => self.is_permitted_key(key),
+        };
+        match permitted {
+            true => Ok(()),
+            false => Err(
+                "NOPERM this user has no permissions to access one of the keys used as arguments"
+                    .into(),
+            ),
+        }
+    }
+    /// checks the user may list every key with the command, returning why not if not
+    pub(super) fn check_listing(&self, command: &str) -> std::result::Result<(), String> {
+        self.check_command(command)?;
+        match self.key_prefixes.is_empty() {
+            true => Ok(()),
+            false => Err(format!(
+                "NOPERM this user has no permissions to list every key with the '{}' command",
+                command.to_lowercase()
+            )),
+        }
+    }
+
+    fn check_command(&self, command: &str) -> std::result::Result<(), String> {
+        let allowed = self.commands.as_ref().is_none_or(|commands| {
+            commands
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(command))
+        });
+        match allowed {
+            true => Ok(()),
+            false => Err(format!(
+                "NOPERM this user has no permissions to run the '{}' command",
+                command.to_lowercase()
+            )),
+        }
+    }
+    fn is_permitted_key(&self, key: &str) -> bool {
+        self.key_prefixes.is_empty()
+            || self
+                .key_prefixes
+                .iter()
+                .any(|prefix| key.starts_with(prefix.as_str()))
+    }
+}
+
+/// the command the kvs-proto request amounts to, as it is named in an Acl
+pub(super) fn command_of(request: &Request) -> &'static str {
+    match request {
+        Request::Get { .. } | Request::GetWithMaxLag { .. } => "GET",
+        Request::Set { .. } | Request::SetOnce { .. } => "SET",
+        Request::SetIfAbsent { .. } => "SETNX",
+        Request::Remove { .. } => "DEL",
+        Request::Compact => "COMPACT",
+        Request::Clear => "FLUSHALL",
+        Request::KeyCount => "DBSIZE",
+        Request::Info => "INFO",
+        Request::Auth { .. } => "AUTH",
+        Request::AcquireLock { .. } | Request::RefreshLock { .. } | Request::ReleaseLock { .. } => {
+            "LOCK"
+        }
+        Request::Subscribe { .. } => "SUBSCRIBE",
+        Request::Topology
+        | Request::SetTopology { .. }
+        | Request::MigrateRange { .. }
+        | Request::MigrationStatus
+        | Request::FinishMigration { .. }
+        | Request::Import { .. } => "CLUSTER",
+        Request::Snapshot { .. } => "SNAPSHOT",
+        Request::Select { .. } => "SELECT",
+        Request::Scan { .. } => "SCAN",
+        Request::ReloadConfig => "CONFIG",
+        Request::Append { .. } => "APPEND",
+        Request::ValueLen { .. } => "STRLEN",
+        Request::GetMany { .. } => "MGET",
+        Request::SetMany { .. } => "MSET",
+        Request::Traced { request, .. } => command_of(request),
+    }
+}
+
+/// the keys a request reads or writes
+enum Keys<'r> {
+    /// none (the request is not for keys)
+    None,
+    /// every key
+    Every,
+    /// the key (or the name of the lock)
+    Key(&'r str),
+    /// the keys starting with the prefix
+    Prefix(&'r str),
+}
+
+fn keys_of(request: &Request) -> Keys<'_> {
+    match request {
+        Request::Import { key, .. } => Keys::Key(key),
+        Request::Subscribe { prefix } | Request::Scan { prefix, .. } => Keys::Prefix(prefix),
+        Request::Traced { request, .. } => keys_of(request),
+        Request::Clear
+        | Request::Snapshot { .. }
+        | Request::MigrateRange { .. }
+        | Request::FinishMigration { .. }
+        | Request::ReloadConfig => Keys::Every,
+        request => request.key().map_or(Keys::None, Keys::Key),
+    }
+}
+
+/// true if the request changes the store (or the cluster)
+fn writes(request: &Request) -> bool {
+    match request {
+        Request::Get { .. }
+        | Request::GetWithMaxLag { .. }
+        | Request::KeyCount
+        | Request::Info
+        | Request::Auth { .. }
+        | Request::Subscribe { .. }
+        | Request::Topology
+        | Request::MigrationStatus
+        | Request::Snapshot { .. }
+        | Request::Select { .. }
+        | Request::Scan { .. }
+        | Request::ValueLen { .. }
+        | Request::GetMany { .. } => false,
+        Request::Set { .. }
+        | Request::Remove { .. }
+        | Request::Compact
+        | Request::Clear
+        | Request::SetIfAbsent { .. }
+        | Request::AcquireLock { .. }
+        | Request::RefreshLock { .. }
+        | Request::ReleaseLock { .. }
+        | Request::SetOnce { .. }
+        | Request::SetTopology { .. }
+        | Request::MigrateRange { .. }
+        | Request::FinishMigration { .. }
+        | Request::Import { .. }
+        | Request::ReloadConfig
+        | Request::Append { .. }
+        | Request::SetMany { .. } => true,
+        Request::Traced { request, .. } => writes(request),
+    }
+}
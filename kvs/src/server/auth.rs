@@ -0,0 +1,97 @@
+use super::super::Request;
+use super::acl::{self, Acl, AclUser};
+
+/// authentication state of a single connection to a server which may require a password (or the
+/// token of a user of its access control list)
+pub(super) struct Authentication<'a> {
+    password: Option<&'a str>,
+    acl: Option<&'a Acl>,
+    /// user of the ACL the connection authenticated as, None if it is not restricted by one
+    user: Option<&'a AclUser>,
+    authenticated: bool,
+}
+
+impl<'a> Authentication<'a> {
+    pub(super) fn new(password: Option<&'a str>, acl: Option<&'a Acl>) -> Self {
+        Self {
+            password,
+            acl,
+            user: None,
+            authenticated: password.is_none() && acl.is_none(),
+        }
+    }
+    /// true if no password is required or the connection has already authenticated
+    pub(super) fn is_authenticated(&self) -> bool {
+        self.authenticated
+    }
+    /// the password connections must authenticate with, if any
+    pub(super) fn password(&self) -> Option<&'a str> {
+        self.password
+    }
+    /// true if a password (or the token of a user) is required of connections
+    pub(super) fn is_required(&self) -> bool {
+        self.password.is_some() || self.acl.is_some()
+    }
+    /// true if the connection authenticated as a user of the ACL, whose requests are checked
+    pub(super) fn is_restricted(&self) -> bool {
+        self.user.is_some()
+    }
+    /// checks the attempted password, authenticating the connection if it matches either the
+    /// password (for the default user, or when no user is named) or the token of the named user
+    /// (of any user, when none is named) of the ACL; a failed attempt leaves an already
+    /// authenticated connection authenticated as it was
+    pub(super) fn authenticate(&mut self, username: Option<&[u8]>, attempt: &[u8]) -> bool {
+        let default_user = username.is_none_or(|username| username == b"default");
+        let user = self.acl.and_then(|acl| acl.user(username, attempt));
+        let matched = default_user
+            && self
+                .password
+                .is_some_and(|password| constant_time_eq(password.as_bytes(), attempt));
+        if matched || user.is_some() {
+            self.user = if matched { None } else { user };
+            self.authenticated = true;
+        }
+        matched || user.is_some()
+    }
+    /// checks the ACL user (if any) the connection authenticated as may make the request as part
+    /// of the command (named as in RESP), returning why not if not
+    pub(super) fn check(
+        &self,
+        command: &str,
+        request: &Request,
+    ) -> std::result::Result<(), String> {
+        match self.user {
+            Some(user) => user.check(command, request),
+            None => Ok(()),
+        }
+    }
+    /// checks the ACL user (if any) may make the kvs-proto request (for each of its keys, if it is
+    /// for many), returning why not if not
+    pub(super) fn check_request(&self, request: &Request) -> std::result::Result<(), String> {
+        let command = acl::command_of(request);
+        match request.per_key() {
+            Some(requests) => requests
+                .iter()
+                .try_for_each(|request| self.check(command, request)),
+            None => self.check(command, request),
+        }
+    }
+    /// checks the ACL user (if any) may list every key with the command, returning why not if not
+    pub(super) fn check_listing(&self, command: &str) -> std::result::Result<(), String> {
+        match self.user {
+            Some(user) => user.check_listing(command),
+            None => Ok(()),
+        }
+    }
+}
+
+/// compares without returning early so the time taken does not reveal how much of the password
+/// was guessed correctly
+pub(super) fn constant_time_eq(expected: &[u8], attempt: &[u8]) -> bool {
+    expected.len() == attempt.len()
+        && expected
+            .iter()
+            .zip(attempt)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
@@ -0,0 +1,177 @@
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard};
+
+use super::super::{Error, ErrorKind, KvsEngine, MigrationStatus, Request, Result, Topology};
+use super::migration::Migration;
+
+/// place of a server in its cluster (if any), shared by its connections
+#[derive(Clone, Default)]
+pub(super) struct Cluster {
+    membership: Option<Arc<Membership>>,
+}
+
+struct Membership {
+    id: u64,
+    topology: RwLock<Topology>,
+    /// the last migration started from this node
+    migration: Mutex<Option<Migration>>,
+}
+
+/// the topology of a server's cluster, held unchanged while requests are checked against it and
+/// executed, so that a migration is cut over only once the requests for its range have finished
+pub(super) struct Placement<'a> {
+    membership: Option<(&'a Membership, RwLockReadGuard<'a, Topology>)>,
+}
+
+impl Cluster {
+    pub(super) fn new(id: u64, topology: Topology) -> Self {
+        Self {
+            membership: Some(Arc::new(Membership {
+                id,
+                topology: RwLock::new(topology),
+                migration: Mutex::new(None),
+            })),
+        }
+    }
+    /// holds the topology unchanged until the placement is dropped
+    pub(super) fn placement(&self) -> Placement<'_> {
+        Placement {
+            membership: self.membership.as_deref().map(|membership| {
+                let topology = membership
+                    .topology
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner);
+                (membership, topology)
+            }),
+        }
+    }
+    /// the topology of the cluster, the leader of each shard held by this node being the leader
+    /// of the engine
+    pub(super) fn topology<E: KvsEngine>(&self, engine: &E) -> Result<Topology> {
+        let membership = self.membership()?;
+        let mut topology = membership
+            .topology
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let leader = engine.leader()?;
+        for shard in &mut topology.shards {
+            if shard.nodes.contains(&membership.id) {
+                shard.leader = leader;
+            }
+        }
+        Ok(topology)
+    }
+    /// replaces the topology (once the requests being executed have finished), unless it is older
+    /// than the current topology
+    pub(super) fn set_topology(&self, topology: Topology) -> Result<()> {
+        let mut current = self
+            .membership()?
+            .topology
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        if topology.epoch < current.epoch {
+            return Err(Error::with_message(
+                ErrorKind::InvalidConfiguration,
+                format!("the topology is older than epoch {}", current.epoch),
+            ));
+        }
+        *current = topology;
+        Ok(())
+    }
+    /// starts migrating the keys of the range to the node at the target address, unless a
+    /// migration is already running
+    pub(super) fn start_migration<E: KvsEngine>(
+        &self,
+        engine: &E,
+        start: String,
+        end: Option<String>,
+        target: String,
+        password: Option<&str>,
+    ) -> Result<()> {
+        let mut migration = self.migration()?;
+        if let Some(running) = migration.as_ref() {
+            if running.is_running()? {
+                return Err(Error::with_message(
+                    ErrorKind::MigrationFailed,
+                    "a migration is already running".into(),
+                ));
+            }
+        }
+        let password = password.map(str::to_owned);
+        *migration = Some(Migration::start(engine, start, end, target, password)?);
+        Ok(())
+    }
+    /// progress of the last migration, None if none has been started
+    pub(super) fn migration_status(&self) -> Result<Option<MigrationStatus>> {
+        self.migration()?
+            .as_ref()
+            .map(Migration::status)
+            .transpose()
+    }
+    /// cuts the running migration over to the topology: this node serves by the topology from
+    /// now on, and the migration finishes copying the keys of the range to the target
+    pub(super) fn finish_migration(&self, topology: Topology) -> Result<()> {
+        let mut migration = self.migration()?;
+        let migration = migration.as_mut().ok_or_else(|| {
+            Error::with_message(ErrorKind::MigrationFailed, "no migration is running".into())
+        })?;
+        if !migration.is_running()? {
+            return Err(Error::with_message(
+                ErrorKind::MigrationFailed,
+                "the migration is not running".into(),
+            ));
+        }
+        self.set_topology(topology.clone())?;
+        migration.finish(topology)
+    }
+
+    fn membership(&self) -> Result<&Membership> {
+        self.membership.as_deref().ok_or_else(|| {
+            Error::with_message(
+                ErrorKind::InvalidConfiguration,
+                "the server is not a node of a cluster".into(),
+            )
+        })
+    }
+    fn migration(&self) -> Result<MutexGuard<'_, Option<Migration>>> {
+        self.membership()?
+            .migration
+            .lock()
+            .map_err(|_| Error::new(ErrorKind::UnknownError))
+    }
+}
+
+impl Placement<'_> {
+    /// true if the server is a node of a cluster
+    pub(super) fn is_set(&self) -> bool {
+        self.membership.is_some()
+    }
+    /// checks that the shard holding the key (or each of the keys) of the request is on this node,
+    /// returning the address of a node it is on if not
+    pub(super) fn check(&self, request: &Request) -> std::result::Result<(), String> {
+        if let Some(requests) = request.per_key() {
+            return requests.iter().try_for_each(|request| self.check(request));
+        }
+        let ((membership, topology), key) = match (&self.membership, request.key()) {
+            (Some(membership), Some(key)) => (membership, key),
+            _ => return Ok(()),
+        };
+        match topology.shard_of(key) {
+            Some(shard) if !shard.nodes.contains(&membership.id) => match topology.route(key) {
+                Some(addr) => Err(addr.to_owned()),
+                None => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+    /// address of the node to send a request to which failed with the error, if the engine
+    /// failed it as another node is its leader
+    pub(super) fn redirect<E: KvsEngine>(&self, engine: &E, err: &Error) -> Option<String> {
+        let (_, topology) = self.membership.as_ref()?;
+        if *err.kind() != ErrorKind::NotLeader {
+            return None;
+        }
+        let leader = engine.leader().ok()??;
+        topology.node_addr(leader).map(str::to_owned)
+    }
+}
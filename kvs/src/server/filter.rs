@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use super::super::Request;
+
+/// filter a KvsServer runs on each request before executing it, given the request (RESP commands
+/// being given as the kvs-proto requests they amount to), returning a message describing why the
+/// request is rejected, if it is
+type FilterFn = dyn Fn(&Request) -> std::result::Result<(), String> + Send + Sync;
+
+/// request filter (if any) of a server, shared by its connections
+#[derive(Clone, Default)]
+pub(super) struct Filter {
+    filter: Option<Arc<FilterFn>>,
+}
+
+impl Filter {
+    pub(super) fn new<F>(filter: F) -> Self
+    where
+        F: Fn(&Request) -> std::result::Result<(), String> + Send + Sync + 'static,
+    {
+        Self {
+            filter: Some(Arc::new(filter)),
+        }
+    }
+    /// true if the server has a filter
+    pub(super) fn is_set(&self) -> bool {
+        self.filter.is_some()
+    }
+    /// checks the request, returning the message of the filter if it rejects it
+    pub(super) fn check(&self, request: &Request) -> std::result::Result<(), String> {
+        match &self.filter {
+            Some(filter) => filter(request),
+            None => Ok(()),
+        }
+    }
+}
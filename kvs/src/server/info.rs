@@ -0,0 +1,35 @@
+use super::super::{KvsEngine, Result};
+
+/// the report of INFO on the engine, in the format of Redis's: a `# <Section>` line heading the
+/// `<field>:<value>` lines of each section, each ended by CRLF
+///
+/// The sections are Keyspace (the number of keys) and, with the `stats` feature, Sizes (the
+/// percentiles of the sizes of the keys and values written). Only the named section
+/// (case-insensitively) is reported if a section is given, every section for `all`, `everything`
+/// or `default`, and none for a section the server does not have.
+pub(super) fn info<E: KvsEngine>(engine: &E, section: Option<&str>) -> Result<String> {
+    let reported = |name: &str| {
+        section.is_none_or(|section| {
+            ["all", "everything", "default", name]
+                .iter()
+                .any(|reported| section.eq_ignore_ascii_case(reported))
+        })
+    };
+    let mut report = String::new();
+    if reported("keyspace") {
+        report += &format!("# Keyspace\r\nkeys:{}\r\n", engine.key_count()?);
+    }
+    #[cfg(feature = "stats")]
+    if reported("sizes") {
+        let stats = engine.stats()?;
+        report += "# Sizes\r\n";
+        for (name, sizes) in [("key", stats.key_sizes), ("value", stats.value_sizes)] {
+            report += &format!(
+                "{0}_size_count:{1}\r\n{0}_size_p50:{2}\r\n{0}_size_p90:{3}\r\n\
+                    {0}_size_p99:{4}\r\n{0}_size_max:{5}\r\n",
+                name, sizes.count, sizes.p50, sizes.p90, sizes.p99, sizes.max
+            );
+        }
+    }
+    Ok(report)
+}
@@ -0,0 +1,301 @@
+use std::{io, sync::mpsc, time::Duration};
+
+use super::super::{
+    protocol, resp, Error, ErrorKind, KeyEvent, KvsEngine, Request, Response, Result, WriteBatch,
+};
+use super::{
+    acl, info, Authentication, Cluster, Cursors, Filter, ServerContext, ShutdownHandle, Throttle,
+};
+
+/// how often a connection streaming events checks whether the server is shutting down
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// serves a connection speaking kvs-proto until the client disconnects
+pub(super) fn handle_connection<E: KvsEngine, S: io::Read + io::Write>(
+    context: &ServerContext<E>,
+    mut auth: Authentication,
+    throttle: Throttle,
+    mut reader: io::BufReader<S>,
+) -> Result<()> {
+    let ServerContext {
+        databases,
+        filter,
+        cluster,
+        snapshots,
+        cursors,
+        log,
+        reload,
+        shutdown,
+        ..
+    } = context;
+    let mut engine = &databases[0];
+    while let Some(request) = protocol::read_message::<_, Request>(&mut reader)? {
+        throttle.wait(1)?;
+        let (id, request) = request.untraced();
+        let (id, command) = (id.as_deref(), acl::command_of(&request));
+        if let Request::Subscribe { prefix } = &request {
+            if auth.is_authenticated() {
+                let mut events = None;
+                let response = log.serve(id, command, || match check(&auth, filter, &request) {
+                    Err(msg) => Response::ServerError { msg },
+                    Ok(()) => {
+                        engine
+                            .subscribe(prefix.clone())
+                            .map_or_else(error_response, |subscribed| {
+                                events = Some(subscribed);
+                                Response::Ok
+                            })
+                    }
+                });
+                protocol::write_message(reader.get_mut(), &response)?;
+                match events {
+                    Some(events) => return stream_events(events, shutdown, reader.get_mut()),
+                    None => continue,
+                }
+            }
+        }
+        let response = log.serve(id, command, || match request {
+            Request::Snapshot {
+                snapshot_id,
+                offset,
+                max_bytes,
+            } if auth.is_authenticated() => match check(&auth, filter, &request) {
+                Err(msg) => Response::ServerError { msg },
+                Ok(()) => snapshots
+                    .chunk(engine, snapshot_id, offset, max_bytes)
+                    .map_or_else(error_response, Response::SnapshotChunk),
+            },
+            Request::Select { db } if auth.is_authenticated() => {
+                match check(&auth, filter, &request) {
+                    Err(msg) => Response::ServerError { msg },
+                    Ok(()) => match databases.get(db as usize) {
+                        Some(selected) => {
+                            engine = selected;
+                            Response::Ok
+                        }
+                        None => Response::ServerError {
+                            msg: format!("ERR database {} does not exist", db),
+                        },
+                    },
+                }
+            }
+            Request::ReloadConfig if auth.is_authenticated() => {
+                match check(&auth, filter, &request) {
+                    Err(msg) => Response::ServerError { msg },
+                    Ok(()) => reload
+                        .reload()
+                        .map_or_else(error_response, |_| Response::Ok),
+                }
+            }
+            request => execute_request(engine, &mut auth, filter, cluster, cursors, request),
+        });
+        protocol::write_message(reader.get_mut(), &response)?;
+    }
+    Ok(())
+}
+
+fn execute_request<E: KvsEngine>(
+    engine: &E,
+    auth: &mut Authentication,
+    filter: &Filter,
+    cluster: &Cluster,
+    cursors: &Cursors,
+    request: Request,
+) -> Response {
+    let result = match request {
+        Request::Auth { password } if auth.authenticate(None, password.as_bytes()) => {
+            Ok(Response::Ok)
+        }
+        Request::Auth { .. } => Ok(Response::Unauthenticated),
+        _ if !auth.is_authenticated() => Ok(Response::Unauthenticated),
+        request => match check(auth, filter, &request) {
+            Err(msg) => Ok(Response::ServerError { msg }),
+            Ok(()) => execute_in_cluster(engine, cluster, cursors, auth.password(), request),
+        },
+    };
+    result.unwrap_or_else(error_response)
+}
+
+/// checks the ACL user (if any) of the connection may make the request and the filter (if any)
+/// accepts it (each key of a request for many keys as a request of its own), returning why not if
+/// not
+fn check(
+    auth: &Authentication,
+    filter: &Filter,
+    request: &Request,
+) -> std::result::Result<(), String> {
+    auth.check_request(request)?;
+    match request.per_key() {
+        Some(requests) => requests
+            .iter()
+            .try_for_each(|request| filter.check(request)),
+        None => filter.check(request),
+    }
+}
+
+/// executes a request of an authenticated connection which the filter (if any) accepted, or
+/// redirects it to the node of the cluster serving its key
+fn execute_in_cluster<E: KvsEngine>(
+    engine: &E,
+    cluster: &Cluster,
+    cursors: &Cursors,
+    password: Option<&str>,
+    request: Request,
+) -> Result<Response> {
+    match request {
+        Request::Topology => cluster.topology(engine).map(Response::Topology),
+        Request::SetTopology { topology } => cluster.set_topology(topology).map(|_| Response::Ok),
+        Request::MigrateRange { start, end, target } => cluster
+            .start_migration(engine, start, end, target, password)
+            .map(|_| Response::Ok),
+        Request::MigrationStatus => cluster.migration_status().map(Response::Migration),
+        Request::FinishMigration { topology } => {
+            cluster.finish_migration(topology).map(|_| Response::Ok)
+        }
+        Request::Import { key, value } => import(engine, key, value).map(|_| Response::Ok),
+        request => {
+            let placement = cluster.placement();
+            if let Err(addr) = placement.check(&request) {
+                return Ok(Response::Moved { addr });
+            }
+            execute_authenticated_request(engine, cursors, request).or_else(|err| {
+                match placement.redirect(engine, &err) {
+                    Some(addr) => Ok(Response::Moved { addr }),
+                    None => Err(err),
+                }
+            })
+        }
+    }
+}
+
+/// executes a request of an authenticated connection for the keys this node serves
+fn execute_authenticated_request<E: KvsEngine>(
+    engine: &E,
+    cursors: &Cursors,
+    request: Request,
+) -> Result<Response> {
+    match request {
+        Request::Get { key } => engine.get(key).map(Response::Value),
+        Request::GetWithMaxLag { key, max_lag_ms } => engine
+            .get_with_max_lag(key, Duration::from_millis(max_lag_ms))
+            .map(Response::Value),
+        Request::Set { key, value } => engine.set(key, value).map(|_| Response::Ok),
+        Request::SetIfAbsent { key, value } => {
+            engine.set_if_absent(key, value).map(Response::Applied)
+        }
+        Request::SetOnce { key, value, token } => {
+            engine.set_once(key, value, token).map(Response::Applied)
+        }
+        Request::Remove { key } => engine.remove(key).map(|_| Response::Ok),
+        Request::Append { key, suffix } => engine
+            .append(key, suffix)
+            .map(|len| Response::Count(len as u64)),
+        Request::ValueLen { key } => engine.value_len(key).map(|len| Response::Count(len as u64)),
+        Request::GetMany { keys } => engine.get_many(keys).map(Response::Values),
+        Request::SetMany { pairs } => {
+            let mut batch = WriteBatch::new();
+            for (key, value) in pairs {
+                batch.set(key, value);
+            }
+            engine.write_batch(batch).map(|_| Response::Ok)
+        }
+        Request::Compact => engine.compact().map(|_| Response::Ok),
+        Request::Clear => engine.clear().map(|_| Response::Ok),
+        Request::KeyCount => engine
+            .key_count()
+            .map(|count| Response::Count(count as u64)),
+        Request::Info => info::info(engine, None).map(Response::Info),
+        Request::AcquireLock { name, ttl_ms } => engine
+            .acquire_lock(name, Duration::from_millis(ttl_ms))
+            .map(|lock| Response::Token(lock.map(|lock| lock.token))),
+        Request::RefreshLock {
+            name,
+            token,
+            ttl_ms,
+        } => engine
+            .refresh_lock(name, token, Duration::from_millis(ttl_ms))
+            .map(|lock| Response::Token(lock.map(|lock| lock.token))),
+        Request::ReleaseLock { name, token } => {
+            engine.release_lock(name, token).map(Response::Applied)
+        }
+        Request::Scan {
+            cursor,
+            prefix,
+            count,
+        } => scan_page(engine, cursors, cursor, &prefix, count),
+        Request::Topology
+        | Request::SetTopology { .. }
+        | Request::MigrateRange { .. }
+        | Request::MigrationStatus
+        | Request::FinishMigration { .. }
+        | Request::Import { .. } => unreachable!("executed by execute_in_cluster"),
+        Request::Snapshot { .. } => unreachable!("Snapshot is handled by handle_connection"),
+        Request::Auth { .. } => unreachable!("Auth is executed by execute_request"),
+        Request::Subscribe { .. } => unreachable!("Subscribe is handled by handle_connection"),
+        Request::Select { .. } => unreachable!("Select is handled by handle_connection"),
+        Request::ReloadConfig => unreachable!("ReloadConfig is handled by handle_connection"),
+        Request::Traced { .. } => unreachable!("Traced is unwrapped by handle_connection"),
+    }
+}
+
+/// the page of the scan of the keys starting with the prefix following the cursor, with the
+/// value of each key (leaving out the keys removed since the page's keys were listed)
+fn scan_page<E: KvsEngine>(
+    engine: &E,
+    cursors: &Cursors,
+    cursor: u64,
+    prefix: &str,
+    count: u64,
+) -> Result<Response> {
+    let (cursor, keys) = match cursors.page(engine, cursor, count, |key| key.starts_with(prefix))? {
+        Some(page) => page,
+        None => {
+            return Ok(Response::ServerError {
+                msg: "ERR invalid cursor".into(),
+            })
+        }
+    };
+    let mut pairs = Vec::with_capacity(keys.len());
+    for key in keys {
+        if let Some(value) = engine.get(key.clone())? {
+            pairs.push((key, value));
+        }
+    }
+    Ok(Response::ScanPage { cursor, pairs })
+}
+
+/// sets (or removes) a key copied from another node by a migration
+fn import<E: KvsEngine>(engine: &E, key: String, value: Option<String>) -> Result<()> {
+    match value {
+        Some(value) => engine.set(key, value),
+        None => match engine.remove(key) {
+            Err(err) if *err.kind() == ErrorKind::KeyNotPresent => Ok(()),
+            result => result,
+        },
+    }
+}
+
+fn error_response(err: Error) -> Response {
+    match err.kind() {
+        ErrorKind::KeyNotPresent => Response::KeyNotFound,
+        _ => Response::ServerError {
+            msg: resp::error_reply(&err),
+        },
+    }
+}
+
+/// sends each event to the client until the connection fails, the server shuts down or the
+/// subscription is dropped for falling behind
+fn stream_events<W: io::Write>(
+    events: mpsc::Receiver<KeyEvent<String>>,
+    shutdown: &ShutdownHandle,
+    mut writer: W,
+) -> Result<()> {
+    loop {
+        match events.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+            Ok(event) => protocol::write_message(&mut writer, &Response::Event(event))?,
+            Err(mpsc::RecvTimeoutError::Timeout) if !shutdown.is_shutdown_requested() => (),
+            Err(_) => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    collections::HashMap,
+    net,
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    thread, time,
+};
+
+use super::super::{Error, ErrorKind, Result};
+
+/// number of clients tracked before buckets which have refilled are forgotten
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+/// Token bucket limit on the rate of requests from each client IP address
+///
+/// Each request takes a token from the bucket of the client's address, which holds up to `burst`
+/// tokens and is refilled at `requests_per_second`. A connection whose client has run out of
+/// tokens waits for its bucket to refill before its requests are executed, so one client cannot
+/// starve the others however many connections it opens.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    requests_per_second: u32,
+    burst: u32,
+}
+
+impl RateLimit {
+    /// create a limit of `requests_per_second` (on average) allowing bursts of up to `burst`
+    /// requests, InvalidConfiguration if either is zero
+    pub fn new(requests_per_second: u32, burst: u32) -> Result<Self> {
+        if requests_per_second == 0 || burst == 0 {
+            return Err(Error::new(ErrorKind::InvalidConfiguration));
+        }
+        Ok(Self {
+            requests_per_second,
+            burst,
+        })
+    }
+}
+
+/// token buckets of all clients of a server, under the server's limit (if any), which may be
+/// changed while it runs
+#[derive(Default)]
+pub(super) struct RateLimiter {
+    limit: RwLock<Option<RateLimit>>,
+    buckets: Mutex<HashMap<net::IpAddr, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: time::Instant,
+}
+
+impl RateLimiter {
+    /// replaces the limit (None for no limit), each client's bucket holding no more than the
+    /// new burst
+    pub(super) fn set_limit(&self, limit: Option<RateLimit>) {
+        *self.limit.write().unwrap_or_else(PoisonError::into_inner) = limit;
+    }
+
+    /// takes tokens for the requests, returning how long to wait until the bucket has refilled
+    /// enough to cover them
+    fn reserve(&self, client: net::IpAddr, requests: usize) -> Result<time::Duration> {
+        let limit = match *self.limit.read().unwrap_or_else(PoisonError::into_inner) {
+            Some(limit) => limit,
+            None => return Ok(time::Duration::from_secs(0)),
+        };
+        let rate = f64::from(limit.requests_per_second);
+        let burst = f64::from(limit.burst);
+        let now = time::Instant::now();
+        let mut buckets = self
+            .buckets
+            .lock()
+            .map_err(|_| Error::new(ErrorKind::UnknownError))?;
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * rate < burst
+            });
+        }
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let refilled = now.duration_since(bucket.updated).as_secs_f64() * rate;
+        // tokens may go negative, reserving them for requests which wait for the refill
+        bucket.tokens = (bucket.tokens + refilled).min(burst) - requests as f64;
+        bucket.updated = now;
+        Ok(match bucket.tokens < 0.0 {
+            true => time::Duration::from_secs_f64(-bucket.tokens / rate),
+            false => time::Duration::from_secs(0),
+        })
+    }
+}
+
+/// rate limit (if any) applied to a single connection
+pub(super) struct Throttle {
+    limiter: Arc<RateLimiter>,
+    client: net::IpAddr,
+}
+
+impl Throttle {
+    pub(super) fn new(limiter: Arc<RateLimiter>, client: net::IpAddr) -> Self {
+        Self { limiter, client }
+    }
+    /// waits until the client may make the given number of requests
+    pub(super) fn wait(&self, requests: usize) -> Result<()> {
+        let delay = self.limiter.reserve(self.client, requests)?;
+        if delay > time::Duration::from_secs(0) {
+            thread::sleep(delay);
+        }
+        Ok(())
+    }
+}
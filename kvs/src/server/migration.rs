@@ -0,0 +1,202 @@
+use std::{
+    sync::{mpsc, Arc, Mutex, MutexGuard},
+    thread,
+    time::Duration,
+};
+
+use super::super::{
+    topology::range_contains, Error, ErrorKind, KeyEvent, KvsClient, KvsEngine, MigrationStatus,
+    Result, Topology, WriteBatch,
+};
+
+/// how long a migration streaming writes waits for an event before checking whether it is to be
+/// cut over
+const CUTOVER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// number of times a migration retries copying a key when its connection to the target fails
+const TARGET_RETRIES: u32 = 3;
+
+/// migration of a range of keys from this node to another, running upon its own thread
+///
+/// The thread subscribes to the events of the store before copying each key of the range, then
+/// copies the key of each event in the range, so that every write made while the keys are copied
+/// (and after) reaches the target. The current value of a key is copied rather than the value
+/// written, so the target ends up with the value of the last write to each key whatever order
+/// the copies are made in.
+pub(super) struct Migration {
+    shared: Arc<Mutex<Progress>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+/// state of a migration shared with its thread
+struct Progress {
+    status: MigrationStatus,
+    /// the topology to cut over to, once the migration is to finish
+    cutover: Option<Topology>,
+}
+
+impl Migration {
+    /// starts migrating the keys of the range from the engine to the node at the target address,
+    /// authenticating to it with the password
+    pub(super) fn start<E: KvsEngine>(
+        engine: &E,
+        start: String,
+        end: Option<String>,
+        target: String,
+        password: Option<String>,
+    ) -> Result<Self> {
+        let events = engine.subscribe(String::new())?;
+        let shared = Arc::new(Mutex::new(Progress {
+            status: MigrationStatus {
+                start,
+                end,
+                target,
+                copied: 0,
+                streaming: false,
+                finished: false,
+                error: None,
+            },
+            cutover: None,
+        }));
+        let (engine, thread_shared) = (engine.clone(), shared.clone());
+        let thread = thread::spawn(move || {
+            let result = run(&engine, &events, &thread_shared, password);
+            if let Ok(mut progress) = lock(&thread_shared) {
+                match result {
+                    Ok(()) => progress.status.finished = true,
+                    Err(err) => progress.status.error = Some(describe(&err)),
+                }
+            }
+        });
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+    pub(super) fn status(&self) -> Result<MigrationStatus> {
+        Ok(lock(&self.shared)?.status.clone())
+    }
+    /// true unless the migration has finished or failed
+    pub(super) fn is_running(&self) -> Result<bool> {
+        let progress = lock(&self.shared)?;
+        Ok(!progress.status.finished && progress.status.error.is_none())
+    }
+    /// cuts the migration over to the topology, waiting for the last writes to be copied, the
+    /// topology to be sent to the target and the keys of the range to be removed from the engine
+    ///
+    /// This node must already have stopped serving the range (having finished every request for
+    /// it), so that no further writes are made to it.
+    pub(super) fn finish(&mut self, topology: Topology) -> Result<()> {
+        {
+            let mut progress = lock(&self.shared)?;
+            if !progress.status.streaming || progress.status.error.is_some() {
+                return Err(Error::with_message(
+                    ErrorKind::MigrationFailed,
+                    "the migration is not streaming writes".into(),
+                ));
+            }
+            progress.cutover = Some(topology);
+        }
+        if let Some(thread) = self.thread.take() {
+            thread
+                .join()
+                .map_err(|_| Error::new(ErrorKind::UnknownError))?;
+        }
+        match self.status()?.error {
+            Some(msg) => Err(Error::with_message(ErrorKind::MigrationFailed, msg)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// copies the keys of the range, then the key of each write to the range, until cut over
+fn run<E: KvsEngine>(
+    engine: &E,
+    events: &mpsc::Receiver<KeyEvent<String>>,
+    shared: &Mutex<Progress>,
+    password: Option<String>,
+) -> Result<()> {
+    let status = lock(shared)?.status.clone();
+    let mut target = KvsClient::connect(status.target.as_str())?.with_retries(TARGET_RETRIES);
+    if let Some(password) = password {
+        target.authenticate(password)?;
+    }
+    let in_range = |key: &String| range_contains(&status.start, status.end.as_deref(), key);
+    for key in engine.keys()?.into_iter().filter(in_range) {
+        copy(engine, &mut target, shared, key)?;
+    }
+    lock(shared)?.status.streaming = true;
+    let topology = loop {
+        if let Some(topology) = lock(shared)?.cutover.take() {
+            break topology;
+        }
+        match events.recv_timeout(CUTOVER_POLL_INTERVAL) {
+            Ok(event) => copy_changed(engine, &mut target, shared, event, in_range)?,
+            Err(mpsc::RecvTimeoutError::Timeout) => (),
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                return Err(Error::with_message(
+                    ErrorKind::MigrationFailed,
+                    "the store stopped sending events".into(),
+                ))
+            }
+        }
+    };
+    // this node no longer writes to the range, so the events already sent are the last
+    for event in events.try_iter() {
+        copy_changed(engine, &mut target, shared, event, in_range)?;
+    }
+    target.set_topology(topology)?;
+    let mut batch = WriteBatch::new();
+    for key in engine.keys()?.into_iter().filter(in_range) {
+        batch.remove(key);
+    }
+    engine.write_batch(batch).map(|_| ())
+}
+
+/// copies the key of the event if it is in the range
+fn copy_changed<E: KvsEngine>(
+    engine: &E,
+    target: &mut KvsClient,
+    shared: &Mutex<Progress>,
+    event: KeyEvent<String>,
+    in_range: impl Fn(&String) -> bool,
+) -> Result<()> {
+    match event {
+        KeyEvent::Cleared => Err(Error::with_message(
+            ErrorKind::MigrationFailed,
+            "the store was cleared during the migration".into(),
+        )),
+        event => match event.key().filter(|&key| in_range(key)) {
+            Some(key) => copy(engine, target, shared, key.clone()),
+            None => Ok(()),
+        },
+    }
+}
+
+/// copies the current value of the key (or its absence) to the target
+fn copy<E: KvsEngine>(
+    engine: &E,
+    target: &mut KvsClient,
+    shared: &Mutex<Progress>,
+    key: String,
+) -> Result<()> {
+    // read as the store of this node holds it (having applied the writes it sent events for)
+    let value = engine.get_with_max_lag(key.clone(), Duration::MAX)?;
+    target.import(key, value)?;
+    lock(shared)?.status.copied += 1;
+    Ok(())
+}
+
+fn lock(shared: &Mutex<Progress>) -> Result<MutexGuard<'_, Progress>> {
+    shared
+        .lock()
+        .map_err(|_| Error::new(ErrorKind::UnknownError))
+}
+
+/// the error along with the error which caused it, if any
+fn describe(err: &Error) -> String {
+    match std::error::Error::source(err) {
+        Some(source) => format!("{}: {}", err, source),
+        None => err.to_string(),
+    }
+}
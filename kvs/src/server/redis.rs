@@ -0,0 +1,879 @@
+use std::{convert::TryFrom, io, mem};
+
+use super::super::{resp, Error, ErrorKind, KvsEngine, Request, Result, WriteBatch};
+use super::{
+    info, Authentication, Cluster, Cursors, Filter, Placement, ReloadHandle, ServerContext,
+    Throttle,
+};
+
+/// number of keys in a page of SCAN when no COUNT is given
+const DEFAULT_SCAN_COUNT: u64 = 10;
+
+/// upper bound on the number of pipelined requests executed before responses are written
+const MAX_PIPELINED_REQUESTS: usize = 1024;
+
+/// upper bound on the length of the glob-style patterns of KEYS, SCAN and CONFIG GET
+const MAX_PATTERN_LEN: usize = 1024;
+
+/// names of the supported commands
+const COMMANDS: &[&str] = &[
+    "GET", "SET", "SETNX", "DEL", "EXISTS", "KEYS", "SCAN", "COMPACT", "FLUSHALL", "FLUSHDB",
+    "DBSIZE", "SELECT", "CONFIG", "AUTH", "PING", "COMMAND", "MULTI", "EXEC", "DISCARD", "APPEND",
+    "STRLEN", "MGET", "MSET", "INFO",
+];
+
+/// parameters of the Redis configuration reported by CONFIG GET (which `redis-benchmark` asks
+/// for), with the values describing the server: it snapshots nothing and has no append-only file
+/// apart from its log
+const CONFIG_PARAMETERS: &[(&str, &str)] = &[("save", ""), ("appendonly", "no")];
+
+/// tests of `redis-benchmark` (as named to its `-t` option) with the commands each runs
+const BENCHMARK_TESTS: &[(&str, &[&str])] = &[
+    ("ping", &["PING"]),
+    ("set", &["SET"]),
+    ("get", &["GET"]),
+    ("incr", &["INCR"]),
+    ("lpush", &["LPUSH"]),
+    ("rpush", &["RPUSH"]),
+    ("lpop", &["LPOP"]),
+    ("rpop", &["RPOP"]),
+    ("sadd", &["SADD"]),
+    ("hset", &["HSET"]),
+    ("spop", &["SPOP"]),
+    ("zadd", &["ZADD"]),
+    ("zpopmin", &["ZPOPMIN"]),
+    ("lrange", &["LPUSH", "LRANGE"]),
+    ("mset", &["MSET"]),
+    ("xadd", &["XADD"]),
+];
+
+const NOAUTH: &str = "NOAUTH Authentication required.";
+
+type CommandResult = std::result::Result<resp::Value, String>;
+
+/// serves a connection speaking RESP until the client disconnects
+pub(super) fn handle_connection<E: KvsEngine, S: io::Read + io::Write>(
+    context: &ServerContext<E>,
+    mut auth: Authentication,
+    throttle: Throttle,
+    mut reader: io::BufReader<S>,
+) -> Result<()> {
+    let ServerContext {
+        databases,
+        filter,
+        cluster,
+        cursors,
+        reload,
+        max_bulk_len,
+        ..
+    } = context;
+    let mut output = Vec::new();
+    let mut session = Session::default();
+    loop {
+        // the requests read before a malformed one are served before the connection is closed
+        let mut requests = Vec::new();
+        let mut protocol_error = None;
+        loop {
+            match resp::read_command(&mut reader, *max_bulk_len) {
+                Ok(Some(request)) => requests.push(request),
+                Ok(None) => break,
+                Err(err) => {
+                    protocol_error = Some(err);
+                    break;
+                }
+            }
+            if reader.buffer().is_empty() || requests.len() >= MAX_PIPELINED_REQUESTS {
+                break;
+            }
+        }
+        if requests.is_empty() && protocol_error.is_none() {
+            return Ok(());
+        }
+        throttle.wait(requests.len())?;
+        let responses = execute_requests(
+            databases,
+            &mut session,
+            &mut auth,
+            filter,
+            cluster,
+            cursors,
+            reload,
+            requests,
+        );
+        for response in responses {
+            resp::write_value(&mut output, &response)?;
+        }
+        // as with Redis, input which is not RESP is replied to with the error of it
+        if let Some(err) = protocol_error.as_ref() {
+            if err.kind() == io::ErrorKind::InvalidData {
+                resp::write_value(&mut output, &resp::Value::Error(format!("ERR {}", err)))?;
+            }
+        }
+        let stream = reader.get_mut();
+        stream.write_all(&output)?;
+        stream.flush()?;
+        output.clear();
+        if let Some(err) = protocol_error {
+            return Err(err.into());
+        }
+    }
+}
+
+/// state of a connection which its commands change
+#[derive(Default)]
+struct Session {
+    /// index of the database commands are executed on (which SELECT switches)
+    selected: usize,
+    /// the writes queued since MULTI, until EXEC applies them as a single batch (or DISCARD drops
+    /// them)
+    transaction: Option<Transaction>,
+}
+
+/// writes queued by a transaction, which EXEC refuses to apply if any command queued failed
+#[derive(Default)]
+struct Transaction {
+    writes: PendingWrites,
+    aborted: bool,
+}
+
+/// executes (pipelined) requests in order, grouping consecutive writes into a single batch, to
+/// the selected database of the session
+#[allow(clippy::too_many_arguments)]
+fn execute_requests<E: KvsEngine>(
+    databases: &[E],
+    session: &mut Session,
+    auth: &mut Authentication,
+    filter: &Filter,
+    cluster: &Cluster,
+    cursors: &Cursors,
+    reload: &ReloadHandle,
+    requests: Vec<resp::Value>,
+) -> Vec<resp::Value> {
+    let mut responses = Vec::with_capacity(requests.len());
+    let mut pending_writes = PendingWrites::default();
+    let placement = cluster.placement();
+    for request in requests {
+        let command = parse_command(request).and_then(|command| match command {
+            Command::Auth(..) => Ok(command),
+            _ if !auth.is_authenticated() => Err(NOAUTH.into()),
+            _ => check_command(auth, filter, &placement, command),
+        });
+        let engine = &databases[session.selected];
+        if let Ok(Command::Multi) | Ok(Command::Exec) | Ok(Command::Discard) = command {
+            pending_writes.execute(engine, &mut responses);
+            responses.push(execute_transaction_command(session, engine, command));
+            continue;
+        }
+        if let Some(transaction) = session.transaction.as_mut() {
+            pending_writes.execute(engine, &mut responses);
+            responses.push(transaction.queue(command));
+            continue;
+        }
+        match command {
+            Ok(Command::Auth(username, password)) => {
+                pending_writes.execute(engine, &mut responses);
+                responses.push(authenticate(auth, username, password));
+            }
+            Ok(Command::Select(index)) => {
+                pending_writes.execute(engine, &mut responses);
+                match usize::try_from(index)
+                    .ok()
+                    .filter(|&db| db < databases.len())
+                {
+                    Some(db) => {
+                        session.selected = db;
+                        responses.push(resp::Value::SimpleString("OK".into()));
+                    }
+                    None => {
+                        responses.push(resp::Value::Error("ERR DB index is out of range".into()))
+                    }
+                }
+            }
+            Ok(Command::ConfigReload) => {
+                pending_writes.execute(engine, &mut responses);
+                responses.push(match reload.reload() {
+                    Ok(()) => resp::Value::SimpleString("OK".into()),
+                    Err(err) => resp::Value::Error(engine_error(err)),
+                });
+            }
+            Ok(Command::Set(key, value)) => pending_writes.set(key, value),
+            Ok(Command::MSet(pairs)) => pending_writes.mset(pairs),
+            Ok(Command::Del(keys)) => pending_writes.del(keys),
+            Ok(command) => {
+                pending_writes.execute(engine, &mut responses);
+                responses.push(
+                    execute_command(engine, cursors, command).unwrap_or_else(resp::Value::Error),
+                );
+            }
+            Err(message) => pending_writes.error(message),
+        }
+    }
+    pending_writes.execute(&databases[session.selected], &mut responses);
+    responses
+}
+
+/// executes MULTI, EXEC or DISCARD, which start, apply and drop the transaction of the session
+fn execute_transaction_command<E: KvsEngine>(
+    session: &mut Session,
+    engine: &E,
+    command: std::result::Result<Command, String>,
+) -> resp::Value {
+    match (command, session.transaction.take()) {
+        (Ok(Command::Multi), None) => {
+            session.transaction = Some(Transaction::default());
+            resp::Value::SimpleString("OK".into())
+        }
+        (Ok(Command::Multi), transaction @ Some(_)) => {
+            session.transaction = transaction;
+            resp::Value::Error("ERR MULTI calls can not be nested".into())
+        }
+        (Ok(Command::Exec), Some(transaction)) => transaction.exec(engine),
+        (Ok(Command::Discard), Some(_)) => resp::Value::SimpleString("OK".into()),
+        (Ok(command), None) => resp::Value::Error(format!("ERR {} without MULTI", command.name())),
+        _ => unreachable!("only MULTI, EXEC and DISCARD are transaction commands"),
+    }
+}
+
+impl Transaction {
+    /// queues the command, which only a write the batch can hold may be, replying QUEUED, or
+    /// aborts the transaction with the error of the command
+    fn queue(&mut self, command: std::result::Result<Command, String>) -> resp::Value {
+        match command {
+            Ok(Command::Set(key, value)) => self.writes.set(key, value),
+            Ok(Command::MSet(pairs)) => self.writes.mset(pairs),
+            Ok(Command::Del(keys)) => self.writes.del(keys),
+            Ok(command) => {
+                self.aborted = true;
+                return resp::Value::Error(format!(
+                    "ERR {} is not allowed in a transaction, which may only SET, MSET and DEL",
+                    command.name()
+                ));
+            }
+            Err(message) => {
+                self.aborted = true;
+                return resp::Value::Error(message);
+            }
+        }
+        resp::Value::SimpleString("QUEUED".into())
+    }
+
+    /// applies the queued writes as a single batch (all of them or, failing, none), replying the
+    /// array of the replies to each
+    fn exec<E: KvsEngine>(mut self, engine: &E) -> resp::Value {
+        if self.aborted {
+            return resp::Value::Error(
+                "EXECABORT Transaction discarded because of previous errors.".into(),
+            );
+        }
+        let mut replies = Vec::new();
+        self.writes.execute(engine, &mut replies);
+        resp::Value::Array(Some(replies))
+    }
+}
+
+enum Command {
+    Get(String),
+    Set(String, String),
+    SetNx(String, String),
+    Del(Vec<String>),
+    Append(String, String),
+    Strlen(String),
+    MGet(Vec<String>),
+    MSet(Vec<(String, String)>),
+    Exists(Vec<String>),
+    Keys(Vec<u8>),
+    Scan {
+        cursor: u64,
+        pattern: Option<Vec<u8>>,
+        count: u64,
+    },
+    Compact,
+    FlushAll,
+    DbSize,
+    Info(Option<String>),
+    Select(i64),
+    ConfigReload,
+    ConfigGet(Vec<u8>),
+    Auth(Option<Vec<u8>>, Vec<u8>),
+    Ping(Option<Vec<u8>>),
+    CountCommands,
+    ListCommands,
+    Multi,
+    Exec,
+    Discard,
+}
+
+impl Command {
+    /// name of the command (FLUSHDB being named FLUSHALL)
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Get(_) => "GET",
+            Command::Set(..) => "SET",
+            Command::SetNx(..) => "SETNX",
+            Command::Del(_) => "DEL",
+            Command::Append(..) => "APPEND",
+            Command::Strlen(_) => "STRLEN",
+            Command::MGet(_) => "MGET",
+            Command::MSet(_) => "MSET",
+            Command::Exists(_) => "EXISTS",
+            Command::Keys(_) => "KEYS",
+            Command::Scan { .. } => "SCAN",
+            Command::Compact => "COMPACT",
+            Command::FlushAll => "FLUSHALL",
+            Command::DbSize => "DBSIZE",
+            Command::Info(_) => "INFO",
+            Command::Select(_) => "SELECT",
+            Command::ConfigReload | Command::ConfigGet(_) => "CONFIG",
+            Command::Auth(..) => "AUTH",
+            Command::Ping(_) => "PING",
+            Command::CountCommands | Command::ListCommands => "COMMAND",
+            Command::Multi => "MULTI",
+            Command::Exec => "EXEC",
+            Command::Discard => "DISCARD",
+        }
+    }
+}
+
+fn parse_command(request: resp::Value) -> std::result::Result<Command, String> {
+    // the arguments are moved into the command rather than copied
+    let mut arguments = command_arguments(request)?;
+    let (command, arguments) = match arguments.split_first_mut() {
+        Some((command, arguments)) => (String::from_utf8_lossy(command).to_uppercase(), arguments),
+        None => return Err("ERR empty command".into()),
+    };
+    match (command.as_str(), arguments) {
+        ("GET", [key]) => Ok(Command::Get(utf8(key)?)),
+        ("SET", [key, value]) => Ok(Command::Set(utf8(key)?, utf8(value)?)),
+        ("SETNX", [key, value]) => Ok(Command::SetNx(utf8(key)?, utf8(value)?)),
+        ("APPEND", [key, suffix]) => Ok(Command::Append(utf8(key)?, utf8(suffix)?)),
+        ("STRLEN", [key]) => Ok(Command::Strlen(utf8(key)?)),
+        ("MGET", keys) if !keys.is_empty() => Ok(Command::MGet(utf8_all(keys)?)),
+        ("MSET", pairs) if !pairs.is_empty() && pairs.len() % 2 == 0 => {
+            let mut pairs = utf8_all(pairs)?.into_iter();
+            Ok(Command::MSet(
+                std::iter::from_fn(|| Some((pairs.next()?, pairs.next()?))).collect(),
+            ))
+        }
+        ("DEL", keys) if !keys.is_empty() => Ok(Command::Del(utf8_all(keys)?)),
+        ("EXISTS", keys) if !keys.is_empty() => Ok(Command::Exists(utf8_all(keys)?)),
+        ("KEYS", [pattern]) => Ok(Command::Keys(glob_pattern(pattern)?)),
+        ("SCAN", [cursor, options @ ..]) => parse_scan(cursor, options),
+        ("COMPACT", []) => Ok(Command::Compact),
+        ("FLUSHALL", []) | ("FLUSHDB", []) => Ok(Command::FlushAll),
+        ("FLUSHALL", [mode]) | ("FLUSHDB", [mode])
+            if mode.eq_ignore_ascii_case(b"SYNC") || mode.eq_ignore_ascii_case(b"ASYNC") =>
+        {
+            Ok(Command::FlushAll)
+        }
+        ("FLUSHALL", [_]) | ("FLUSHDB", [_]) => Err("ERR syntax error".into()),
+        ("DBSIZE", []) => Ok(Command::DbSize),
+        ("INFO", []) => Ok(Command::Info(None)),
+        ("INFO", [section]) => Ok(Command::Info(Some(utf8(section)?))),
+        ("SELECT", [index]) => match String::from_utf8_lossy(index).parse() {
+            Ok(index) => Ok(Command::Select(index)),
+            Err(_) => Err("ERR value is not an integer or out of range".into()),
+        },
+        ("CONFIG", [subcommand]) if subcommand.eq_ignore_ascii_case(b"RELOAD") => {
+            Ok(Command::ConfigReload)
+        }
+        ("CONFIG", [subcommand, pattern]) if subcommand.eq_ignore_ascii_case(b"GET") => {
+            Ok(Command::ConfigGet(glob_pattern(pattern)?))
+        }
+        ("CONFIG", [subcommand, ..]) => Err(format!(
+            "ERR unknown subcommand '{}'",
+            String::from_utf8_lossy(subcommand)
+        )),
+        ("PING", []) => Ok(Command::Ping(None)),
+        ("PING", [message]) => Ok(Command::Ping(Some(mem::take(message)))),
+        ("COMMAND", [subcommand]) if subcommand.eq_ignore_ascii_case(b"COUNT") => {
+            Ok(Command::CountCommands)
+        }
+        ("COMMAND", [subcommand]) if subcommand.eq_ignore_ascii_case(b"LIST") => {
+            Ok(Command::ListCommands)
+        }
+        ("COMMAND", [subcommand, ..]) => Err(format!(
+            "ERR unknown subcommand '{}'",
+            String::from_utf8_lossy(subcommand)
+        )),
+        ("MULTI", []) => Ok(Command::Multi),
+        ("EXEC", []) => Ok(Command::Exec),
+        ("DISCARD", []) => Ok(Command::Discard),
+        ("AUTH", [password]) => Ok(Command::Auth(None, mem::take(password))),
+        ("AUTH", [username, password]) => Ok(Command::Auth(
+            Some(mem::take(username)),
+            mem::take(password),
+        )),
+        (command, _) if COMMANDS.contains(&command) => Err(format!(
+            "ERR wrong number of arguments for '{}' command",
+            command.to_lowercase()
+        )),
+        _ => Err(format!("ERR unknown command '{}'", command)),
+    }
+}
+
+/// parses the cursor and the options (`MATCH pattern` and `COUNT count`) of SCAN
+fn parse_scan(cursor: &[u8], options: &mut [Vec<u8>]) -> std::result::Result<Command, String> {
+    let cursor = String::from_utf8_lossy(cursor)
+        .parse()
+        .map_err(|_| "ERR invalid cursor".to_string())?;
+    let (mut pattern, mut count) = (None, DEFAULT_SCAN_COUNT);
+    for option in options.chunks_mut(2) {
+        match option {
+            [name, value] if name.eq_ignore_ascii_case(b"MATCH") => {
+                pattern = Some(glob_pattern(value)?)
+            }
+            [name, value] if name.eq_ignore_ascii_case(b"COUNT") => {
+                count = String::from_utf8_lossy(value)
+                    .parse()
+                    .map_err(|_| "ERR value is not an integer or out of range".to_string())?;
+                if count == 0 {
+                    return Err("ERR syntax error".into());
+                }
+            }
+            _ => return Err("ERR syntax error".into()),
+        }
+    }
+    Ok(Command::Scan {
+        cursor,
+        pattern,
+        count,
+    })
+}
+
+/// checks the command with the ACL user (if any) of the connection and the filter (if any) as
+/// the kvs-proto requests it amounts to, returning it unless the user may not make or the filter
+/// rejects any of them or any is for a key held by another node of the cluster
+fn check_command(
+    auth: &Authentication,
+    filter: &Filter,
+    placement: &Placement,
+    command: Command,
+) -> std::result::Result<Command, String> {
+    if !filter.is_set() && !placement.is_set() && !auth.is_restricted() {
+        return Ok(command);
+    }
+    if let Command::Keys(_) | Command::Scan { .. } = command {
+        auth.check_listing(command.name())?;
+    }
+    let requests = match &command {
+        Command::Get(key) => vec![Request::Get { key: key.clone() }],
+        Command::Set(key, value) => vec![Request::Set {
+            key: key.clone(),
+            value: value.clone(),
+        }],
+        Command::SetNx(key, value) => vec![Request::SetIfAbsent {
+            key: key.clone(),
+            value: value.clone(),
+        }],
+        Command::Del(keys) => keys
+            .iter()
+            .map(|key| Request::Remove { key: key.clone() })
+            .collect(),
+        Command::Append(key, suffix) => vec![Request::Append {
+            key: key.clone(),
+            suffix: suffix.clone(),
+        }],
+        Command::Strlen(key) => vec![Request::ValueLen { key: key.clone() }],
+        Command::Exists(keys) | Command::MGet(keys) => keys
+            .iter()
+            .map(|key| Request::Get { key: key.clone() })
+            .collect(),
+        Command::MSet(pairs) => pairs
+            .iter()
+            .map(|(key, value)| Request::Set {
+                key: key.clone(),
+                value: value.clone(),
+            })
+            .collect(),
+        Command::Compact => vec![Request::Compact],
+        Command::FlushAll => vec![Request::Clear],
+        Command::DbSize => vec![Request::KeyCount],
+        Command::Info(_) => vec![Request::Info],
+        Command::Select(index) => match u64::try_from(*index) {
+            Ok(db) => vec![Request::Select { db }],
+            Err(_) => Vec::new(),
+        },
+        Command::ConfigReload => vec![Request::ReloadConfig],
+        Command::Keys(_)
+        | Command::Scan { .. }
+        | Command::ConfigGet(_)
+        | Command::Auth(..)
+        | Command::Ping(_)
+        | Command::CountCommands
+        | Command::ListCommands
+        | Command::Multi
+        | Command::Exec
+        | Command::Discard => Vec::new(),
+    };
+    requests
+        .iter()
+        .try_for_each(|request| {
+            auth.check(command.name(), request)?;
+            placement
+                .check(request)
+                .map_err(|addr| format!("MOVED {}", addr))?;
+            filter.check(request)
+        })
+        .map(|_| command)
+}
+
+fn execute_command<E: KvsEngine>(engine: &E, cursors: &Cursors, command: Command) -> CommandResult {
+    match command {
+        Command::Get(key) => {
+            let mut value = Vec::new();
+            let found = engine
+                .get_bytes(key.as_bytes(), &mut value)
+                .map_err(engine_error)?;
+            Ok(resp::Value::BulkString(Some(value).filter(|_| found)))
+        }
+        Command::Set(key, value) => {
+            engine.set(key, value).map_err(engine_error)?;
+            Ok(resp::Value::SimpleString("OK".into()))
+        }
+        Command::SetNx(key, value) => Ok(resp::Value::Integer(
+            engine.set_if_absent(key, value).map_err(engine_error)? as i64,
+        )),
+        Command::Del(keys) => {
+            let mut removed = 0;
+            for key in keys {
+                match engine.remove(key) {
+                    Ok(()) => removed += 1,
+                    Err(err) if *err.kind() == ErrorKind::KeyNotPresent => (),
+                    Err(err) => return Err(engine_error(err)),
+                }
+            }
+            Ok(resp::Value::Integer(removed))
+        }
+        Command::Append(key, suffix) => Ok(resp::Value::Integer(
+            engine.append(key, suffix).map_err(engine_error)? as i64,
+        )),
+        Command::Strlen(key) => Ok(resp::Value::Integer(
+            engine.value_len(key).map_err(engine_error)? as i64,
+        )),
+        Command::MGet(keys) => Ok(resp::Value::Array(Some(
+            engine
+                .get_many(keys)
+                .map_err(engine_error)?
+                .into_iter()
+                .map(|value| resp::Value::BulkString(value.map(String::into_bytes)))
+                .collect(),
+        ))),
+        Command::MSet(pairs) => {
+            let mut batch = WriteBatch::new();
+            for (key, value) in pairs {
+                batch.set(key, value);
+            }
+            engine.write_batch(batch).map_err(engine_error)?;
+            Ok(resp::Value::SimpleString("OK".into()))
+        }
+        Command::Exists(keys) => {
+            let (mut present, mut value) = (0, Vec::new());
+            for key in keys {
+                if engine
+                    .get_bytes(key.as_bytes(), &mut value)
+                    .map_err(engine_error)?
+                {
+                    present += 1;
+                }
+            }
+            Ok(resp::Value::Integer(present))
+        }
+        Command::Keys(pattern) => Ok(resp::Value::Array(Some(
+            engine
+                .keys()
+                .map_err(engine_error)?
+                .into_iter()
+                .filter(|key| glob_matches(&pattern, key.as_bytes()))
+                .map(|key| resp::Value::BulkString(Some(key.into_bytes())))
+                .collect(),
+        ))),
+        Command::Scan {
+            cursor,
+            pattern,
+            count,
+        } => {
+            let (cursor, keys) = cursors
+                .page(engine, cursor, count, |key| {
+                    pattern
+                        .as_ref()
+                        .is_none_or(|pattern| glob_matches(pattern, key.as_bytes()))
+                })
+                .map_err(engine_error)?
+                .ok_or_else(|| "ERR invalid cursor".to_string())?;
+            Ok(resp::Value::Array(Some(vec![
+                resp::Value::BulkString(Some(cursor.to_string().into_bytes())),
+                resp::Value::Array(Some(
+                    keys.into_iter()
+                        .map(|key| resp::Value::BulkString(Some(key.into_bytes())))
+                        .collect(),
+                )),
+            ])))
+        }
+        Command::Compact => {
+            engine.compact().map_err(engine_error)?;
+            Ok(resp::Value::SimpleString("OK".into()))
+        }
+        Command::FlushAll => {
+            engine.clear().map_err(engine_error)?;
+            Ok(resp::Value::SimpleString("OK".into()))
+        }
+        Command::DbSize => Ok(resp::Value::Integer(
+            engine.key_count().map_err(engine_error)? as i64,
+        )),
+        Command::Info(section) => Ok(resp::Value::BulkString(Some(
+            info::info(engine, section.as_deref())
+                .map_err(engine_error)?
+                .into_bytes(),
+        ))),
+        Command::ConfigGet(pattern) => Ok(resp::Value::Array(Some(
+            CONFIG_PARAMETERS
+                .iter()
+                .filter(|(name, _)| glob_matches(&pattern.to_ascii_lowercase(), name.as_bytes()))
+                .flat_map(|(name, value)| [name, value])
+                .map(|string| resp::Value::BulkString(Some(string.as_bytes().to_vec())))
+                .collect(),
+        ))),
+        Command::Ping(None) => Ok(resp::Value::SimpleString("PONG".into())),
+        Command::Ping(Some(message)) => Ok(resp::Value::BulkString(Some(message))),
+        Command::CountCommands => Ok(resp::Value::Integer(COMMANDS.len() as i64)),
+        Command::ListCommands => Ok(resp::Value::Array(Some(
+            COMMANDS
+                .iter()
+                .map(|name| resp::Value::BulkString(Some(name.to_lowercase().into_bytes())))
+                .collect(),
+        ))),
+        Command::Select(..) | Command::ConfigReload | Command::Auth(..) => {
+            unreachable!("SELECT, CONFIG and AUTH are executed by execute_requests")
+        }
+        Command::Multi | Command::Exec | Command::Discard => {
+            unreachable!("MULTI, EXEC and DISCARD are executed by execute_transaction_command")
+        }
+    }
+}
+
+/// report of the RESP commands a KvsServer supports, and of which tests of `redis-benchmark` it can
+/// run, as printed by `kvs-server --redis-compat-report`
+pub fn redis_compatibility_report() -> String {
+    let mut report = format!(
+        "RESP commands: {}\nredis-benchmark tests:\n",
+        COMMANDS.join(", ")
+    );
+    let mut supported = Vec::new();
+    for (test, commands) in BENCHMARK_TESTS {
+        match commands.iter().find(|command| !COMMANDS.contains(command)) {
+            Some(missing) => report += &format!("  {}: unsupported (needs {})\n", test, missing),
+            None => {
+                report += &format!("  {}: supported\n", test);
+                supported.push(*test);
+            }
+        }
+    }
+    report
+        + &format!(
+            "Run the supported tests with: redis-benchmark -p <port> -t {}\n",
+            supported.join(",")
+        )
+}
+
+/// authenticates the connection, only the `default` user being supported if a username is given
+fn authenticate(
+    auth: &mut Authentication,
+    username: Option<Vec<u8>>,
+    password: Vec<u8>,
+) -> resp::Value {
+    if !auth.is_required() {
+        return resp::Value::Error(
+            "ERR AUTH <password> called without any password configured for the default user. \
+                Are you sure your configuration is correct?"
+                .into(),
+        );
+    }
+    match auth.authenticate(username.as_deref(), &password) {
+        true => resp::Value::SimpleString("OK".into()),
+        false => resp::Value::Error(
+            "WRONGPASS invalid username-password pair or user is disabled.".into(),
+        ),
+    }
+}
+
+/// consecutive write commands collected into a batch along with how to reply to each of them
+#[derive(Default)]
+struct PendingWrites {
+    batch: WriteBatch<String, String>,
+    replies: Vec<PendingReply>,
+}
+
+enum PendingReply {
+    Ok { operations: usize },
+    Deleted { operations: usize },
+    Error(String),
+}
+
+impl PendingWrites {
+    fn set(&mut self, key: String, value: String) {
+        self.batch.set(key, value);
+        self.replies.push(PendingReply::Ok { operations: 1 });
+    }
+    fn mset(&mut self, pairs: Vec<(String, String)>) {
+        self.replies.push(PendingReply::Ok {
+            operations: pairs.len(),
+        });
+        for (key, value) in pairs {
+            self.batch.set(key, value);
+        }
+    }
+    fn del(&mut self, keys: Vec<String>) {
+        self.replies.push(PendingReply::Deleted {
+            operations: keys.len(),
+        });
+        for key in keys {
+            self.batch.remove(key);
+        }
+    }
+    fn error(&mut self, message: String) {
+        self.replies.push(PendingReply::Error(message));
+    }
+    fn execute<E: KvsEngine>(&mut self, engine: &E, responses: &mut Vec<resp::Value>) {
+        let PendingWrites { batch, replies } = std::mem::take(self);
+        let applied = match batch.is_empty() {
+            true => Ok(Vec::new()),
+            false => engine.write_batch(batch).map_err(engine_error),
+        };
+        let mut applied = match applied {
+            Ok(applied) => applied.into_iter(),
+            Err(message) => {
+                responses.extend(replies.into_iter().map(|reply| match reply {
+                    PendingReply::Error(message) => resp::Value::Error(message),
+                    _ => resp::Value::Error(message.clone()),
+                }));
+                return;
+            }
+        };
+        responses.extend(replies.into_iter().map(|reply| {
+            match reply {
+                PendingReply::Ok { operations } => {
+                    applied.by_ref().take(operations).for_each(drop);
+                    resp::Value::SimpleString("OK".into())
+                }
+                PendingReply::Deleted { operations } => resp::Value::Integer(
+                    applied
+                        .by_ref()
+                        .take(operations)
+                        .filter(|&applied| applied)
+                        .count() as i64,
+                ),
+                PendingReply::Error(message) => resp::Value::Error(message),
+            }
+        }));
+    }
+}
+
+fn command_arguments(request: resp::Value) -> std::result::Result<Vec<Vec<u8>>, String> {
+    match request {
+        resp::Value::Array(Some(values)) => values
+            .into_iter()
+            .map(|value| match value {
+                resp::Value::BulkString(Some(bytes)) => Ok(bytes),
+                resp::Value::SimpleString(string) => Ok(string.into_bytes()),
+                resp::Value::Integer(integer) => Ok(integer.to_string().into_bytes()),
+                _ => Err("ERR Protocol error: expected bulk strings as command arguments".into()),
+            })
+            .collect(),
+        _ => Err("ERR Protocol error: expected an array of bulk strings as command".into()),
+    }
+}
+
+fn glob_pattern(pattern: &mut Vec<u8>) -> std::result::Result<Vec<u8>, String> {
+    if pattern.len() > MAX_PATTERN_LEN {
+        return Err(format!("ERR pattern longer than {} bytes", MAX_PATTERN_LEN));
+    }
+    Ok(mem::take(pattern))
+}
+
+fn utf8_all(all_bytes: &mut [Vec<u8>]) -> std::result::Result<Vec<String>, String> {
+    all_bytes.iter_mut().map(utf8).collect()
+}
+
+fn utf8(bytes: &mut Vec<u8>) -> std::result::Result<String, String> {
+    String::from_utf8(mem::take(bytes))
+        .map_err(|_| "ERR keys and values must be valid UTF-8".into())
+}
+
+fn engine_error(err: Error) -> String {
+    resp::error_reply(&err)
+}
+
+/// Redis style glob-matching supporting `*`, `?`, `[...]` character classes (with `^` negation and
+/// `a-z` ranges) and `\` escapes, trying the text again from only the last `*` on a mismatch (as
+/// Redis does) so it takes at most the length of the pattern times that of the text
+fn glob_matches(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // the pattern following the last `*` and where in the text it was last tried from
+    let mut last_star = None;
+    while t < text.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            last_star = Some((p, t));
+            continue;
+        }
+        match token_matches(&pattern[p..], text[t]) {
+            Some(token_len) => {
+                p += token_len;
+                t += 1;
+            }
+            None => match last_star {
+                Some((after_star, tried_from)) => {
+                    p = after_star;
+                    t = tried_from + 1;
+                    last_star = Some((after_star, t));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&p| p == b'*')
+}
+
+/// matches the token (other than `*`) at the start of the pattern with a single character,
+/// returning the length of the token if it matched
+fn token_matches(pattern: &[u8], c: u8) -> Option<usize> {
+    let (matched, token_len) = match pattern {
+        [] => return None,
+        [b'?', ..] => (true, 1),
+        [b'[', class @ ..] => match class_matches(class, c) {
+            Some((matched, rest_of_pattern)) => (matched, pattern.len() - rest_of_pattern.len()),
+            None => (c == b'[', 1),
+        },
+        [b'\\', escaped, ..] => (*escaped == c, 2),
+        [p, ..] => (*p == c, 1),
+    };
+    matched.then_some(token_len)
+}
+
+/// matches a character class (the pattern following a `[`) returning whether it matched and the
+/// rest of the pattern following the closing `]`, or None if the class is unterminated
+fn class_matches(class: &[u8], c: u8) -> Option<(bool, &[u8])> {
+    let (negated, mut class) = match class.split_first() {
+        Some((b'^', rest)) => (true, rest),
+        _ => (false, class),
+    };
+    let mut matched = false;
+    loop {
+        match class {
+            [] => return None,
+            [b']', rest @ ..] => return Some((matched != negated, rest)),
+            [b'\\', escaped, rest @ ..] => {
+                matched |= *escaped == c;
+                class = rest;
+            }
+            [from, b'-', to, rest @ ..] if *to != b']' => {
+                matched |= (*from.min(to)..=*from.max(to)).contains(&c);
+                class = rest;
+            }
+            [single, rest @ ..] => {
+                matched |= *single == c;
+                class = rest;
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    fs, path,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+use super::super::{Error, ErrorKind, KvsEngine, Result, RuntimeOptions};
+use super::{LogLevel, RateLimit, RateLimiter, RequestLog};
+
+/// Settings of a KvsServer which may be changed while it runs, read from the JSON config file
+/// given `with_config_file` when the server starts and each time it is reloaded
+///
+/// Each setting left out of the file takes its default, so that removing a setting from the file
+/// and reloading it undoes it. For example
+/// ```json
+/// {"log_level": "info", "slow_log_ms": 100, "rate_limit": 1000, "rate_limit_burst": 2000,
+///     "store": {"compaction_stale_fraction": 0.5, "max_keys": 100000}}
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// how much the server logs to stderr
+    pub log_level: LogLevel,
+    /// least milliseconds a kvs-proto request takes to be logged as slow, None for none to be
+    pub slow_log_ms: Option<u64>,
+    /// requests per second (on average) allowed from each client IP address, None for no limit
+    pub rate_limit: Option<u32>,
+    /// requests allowed in a burst from each client IP address, `rate_limit` if not given
+    pub rate_limit_burst: Option<u32>,
+    /// compaction thresholds and quotas of the engine of each database, None to leave them as
+    /// they are
+    pub store: Option<RuntimeOptions>,
+}
+
+impl ServerConfig {
+    /// read the configuration from its JSON file, InvalidConfiguration if it is not valid
+    pub fn read(path: &path::Path) -> Result<Self> {
+        let json = fs::read_to_string(path).map_err(|err| Error::from(err).at_path(path))?;
+        let config: Self = serde_json::from_str(&json).map_err(|err| {
+            Error::with_message(ErrorKind::InvalidConfiguration, err.to_string()).at_path(path)
+        })?;
+        config.rate_limit()?;
+        if config.store.is_some_and(|store| !store.is_valid()) {
+            return Err(Error::new(ErrorKind::InvalidConfiguration).at_path(path));
+        }
+        Ok(config)
+    }
+
+    fn rate_limit(&self) -> Result<Option<RateLimit>> {
+        self.rate_limit
+            .map(|rate| RateLimit::new(rate, self.rate_limit_burst.unwrap_or(rate)))
+            .transpose()
+    }
+}
+
+/// Handle which reloads the config file of a KvsServer (given `with_config_file`), e.g. from a
+/// SIGHUP handler, applying the settings it holds to the server (and its databases) while it runs
+///
+/// Clients may reload it too, with `KvsClient::reload_config` (or `CONFIG RELOAD` for RESP).
+///
+/// # Example
+/// ```no_run
+/// use kvs::{KvsServer, SharedKvStore};
+///
+/// let engine = SharedKvStore::open(std::path::Path::new("testdb")).unwrap();
+/// let server = KvsServer::new(engine).with_config_file("kvs-server.json".into());
+/// let reload = server.reload_handle();
+/// std::thread::spawn(move || loop {
+///     std::thread::sleep(std::time::Duration::from_secs(60));
+///     if let Err(err) = reload.reload() {
+///         eprintln!("Failed to reload the configuration: {}", err);
+///     }
+/// });
+/// server.run("127.0.0.1:4000").unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ReloadHandle {
+    shared: Arc<ReloadShared>,
+}
+
+struct ReloadShared {
+    path: Mutex<Option<path::PathBuf>>,
+    rate_limiter: Arc<RateLimiter>,
+    log: RequestLog,
+    /// reconfigures the engine of each database
+    engines: Mutex<Vec<Reconfigure>>,
+}
+
+type Reconfigure = Box<dyn Fn(RuntimeOptions) -> Result<()> + Send>;
+
+impl ReloadHandle {
+    pub(super) fn new(rate_limiter: Arc<RateLimiter>, log: RequestLog) -> Self {
+        Self {
+            shared: Arc::new(ReloadShared {
+                path: Mutex::new(None),
+                rate_limiter,
+                log,
+                engines: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+    /// read the server's config file again and apply its settings, InvalidConfiguration
+    /// (leaving the settings as they were) if the server has no config file or it is not valid
+    pub fn reload(&self) -> Result<()> {
+        let path = self
+            .shared
+            .path
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidConfiguration))?;
+        self.apply(&ServerConfig::read(&path)?)
+    }
+
+    pub(super) fn set_path(&self, path: path::PathBuf) {
+        *self
+            .shared
+            .path
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(path);
+    }
+    /// reloads the config file if the server has one
+    pub(super) fn reload_if_configured(&self) -> Result<()> {
+        let configured = self
+            .shared
+            .path
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some();
+        match configured {
+            true => self.reload(),
+            false => Ok(()),
+        }
+    }
+    /// adds the engine of the next database to those the store settings are applied to
+    pub(super) fn add_engine<E: KvsEngine>(&self, engine: E) {
+        self.shared
+            .engines
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(move |options| engine.reconfigure(options)));
+    }
+
+    fn apply(&self, config: &ServerConfig) -> Result<()> {
+        if let Some(options) = config.store {
+            let engines = self
+                .shared
+                .engines
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            engines
+                .iter()
+                .try_for_each(|reconfigure| reconfigure(options))?;
+        }
+        self.shared.rate_limiter.set_limit(config.rate_limit()?);
+        let log = &self.shared.log;
+        log.set_level(config.log_level);
+        log.set_slow_threshold(config.slow_log_ms.map(Duration::from_millis));
+        Ok(())
+    }
+}
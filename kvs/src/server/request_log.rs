@@ -0,0 +1,131 @@
+use std::{
+    sync::{mpsc, Arc, PoisonError, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+use super::super::{Response, StoreEvent};
+
+/// How much a KvsServer logs to stderr, each level logging what the levels before it do too
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    /// nothing
+    Off,
+    /// the kvs-proto requests which fail (of clients which tagged them with an ID) and the
+    /// connections which end with an error
+    Error,
+    /// slow kvs-proto requests (if a slow log threshold is set), and the stalled writes and
+    /// recoveries of its databases
+    #[default]
+    Warn,
+    /// every kvs-proto request, with the time it took, and the compactions and log rotations of
+    /// its databases
+    Info,
+}
+
+/// how a server reports the kvs-proto requests it serves, shared by its connections (and changed
+/// when the server reloads its configuration)
+#[derive(Clone, Default)]
+pub(super) struct RequestLog {
+    settings: Arc<RwLock<LogSettings>>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct LogSettings {
+    level: LogLevel,
+    /// least time a request takes to be logged as slow, None if none are
+    slow_threshold: Option<Duration>,
+}
+
+impl RequestLog {
+    pub(super) fn set_level(&self, level: LogLevel) {
+        self.write(|settings| settings.level = level);
+    }
+    pub(super) fn set_slow_threshold(&self, slow_threshold: Option<Duration>) {
+        self.write(|settings| settings.slow_threshold = slow_threshold);
+    }
+    /// true if what is logged at the level is logged
+    pub(super) fn logs(&self, level: LogLevel) -> bool {
+        level != LogLevel::Off && level <= self.settings().level
+    }
+    /// serves the request of the command (named as in an Acl) within a tracing span (with the
+    /// `tracing` feature) of the ID the client tagged it with (if any), logging it to stderr as
+    /// the log level says, and (when it has an ID) adding the ID to the message of an error
+    pub(super) fn serve<F>(&self, id: Option<&str>, command: &str, serve: F) -> Response
+    where
+        F: FnOnce() -> Response,
+    {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!("request", id, command).entered();
+        let start = Instant::now();
+        let mut response = serve();
+        let elapsed = start.elapsed();
+        let settings = self.settings();
+        if let Response::ServerError { msg } = &mut response {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(error = msg.as_str(), "request failed");
+            if let Some(id) = id {
+                if settings.level >= LogLevel::Error {
+                    eprintln!("Request {} ({}) failed with error: {}", id, command, msg);
+                }
+                *msg = format!("{} (request {})", msg, id);
+            }
+        }
+        let id = id.unwrap_or("without an ID");
+        if settings.level >= LogLevel::Info {
+            eprintln!(
+                "Request {} ({}) took {}ms",
+                id,
+                command,
+                elapsed.as_millis()
+            );
+        } else if settings.level >= LogLevel::Warn
+            && settings
+                .slow_threshold
+                .is_some_and(|threshold| elapsed >= threshold)
+        {
+            eprintln!(
+                "Slow request {} ({}) took {}ms",
+                id,
+                command,
+                elapsed.as_millis()
+            );
+        }
+        response
+    }
+    /// logs the events of the engine of the database to stderr as the log level says, upon a
+    /// thread of its own (which ends when the engine is dropped)
+    pub(super) fn log_events(&self, db: usize, events: mpsc::Receiver<StoreEvent>) {
+        let log = self.clone();
+        thread::spawn(move || {
+            for event in events {
+                let level = match event {
+                    StoreEvent::WriteStalled { .. } | StoreEvent::RecoveryPerformed { .. } => {
+                        LogLevel::Warn
+                    }
+                    _ => LogLevel::Info,
+                };
+                #[cfg(feature = "tracing")]
+                tracing::info!(db, event = %event, "store event");
+                if log.logs(level) {
+                    eprintln!("Database {}: {}", db, event);
+                }
+            }
+        });
+    }
+
+    fn settings(&self) -> LogSettings {
+        *self.settings.read().unwrap_or_else(PoisonError::into_inner)
+    }
+    fn write(&self, change: impl FnOnce(&mut LogSettings)) {
+        change(
+            &mut self
+                .settings
+                .write()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+    }
+}
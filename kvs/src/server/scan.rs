@@ -0,0 +1,90 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use super::super::{version::now_millis, Error, ErrorKind, KvsEngine, Result};
+
+/// most keys a server sends in a page of a scan, however many are asked for, so that no response
+/// holds too much of the keyspace
+pub(super) const MAX_SCAN_COUNT: u64 = 10_000;
+
+/// number of cursors a server holds for scans in progress (the oldest being dropped to hold a new
+/// one)
+const MAX_HELD_CURSORS: usize = 10_000;
+
+/// cursors of the scans in progress, each holding the last key of the page it was returned with,
+/// shared by the server's connections
+///
+/// A scan continues from the keys after the cursor's key, rather than from a position among the
+/// keys, so its cursor stays valid whatever is written (or compacted) between its pages: each key
+/// present throughout the scan is returned exactly once, and a key set or removed during the scan
+/// is returned at most once. A cursor may be used again (as when a request is retried) until it is
+/// dropped, or the server restarts.
+#[derive(Clone, Default)]
+pub(super) struct Cursors {
+    held: Arc<Mutex<Held>>,
+}
+
+#[derive(Default)]
+struct Held {
+    last_keys: BTreeMap<u64, String>,
+    /// the cursor last returned
+    last_cursor: u64,
+}
+
+impl Cursors {
+    /// the page of up to `count` (at least one, at most MAX_SCAN_COUNT) of the engine's keys
+    /// accepted by `accepts`, in their order, following the last key of the page the cursor was
+    /// returned with (0 to start a scan), and the cursor of the next page (0 if none are left),
+    /// None if the cursor is not held
+    pub(super) fn page<E, F>(
+        &self,
+        engine: &E,
+        cursor: u64,
+        count: u64,
+        accepts: F,
+    ) -> Result<Option<(u64, Vec<String>)>>
+    where
+        E: KvsEngine,
+        F: Fn(&str) -> bool,
+    {
+        let after = match cursor {
+            0 => None,
+            cursor => match self.lock()?.last_keys.get(&cursor) {
+                Some(key) => Some(key.clone()),
+                None => return Ok(None),
+            },
+        };
+        let mut keys = engine.keys()?;
+        keys.retain(|key| after.as_ref().is_none_or(|after| key > after) && accepts(key));
+        keys.sort_unstable();
+        let count = count.clamp(1, MAX_SCAN_COUNT) as usize;
+        if keys.len() <= count {
+            return Ok(Some((0, keys)));
+        }
+        keys.truncate(count);
+        let next = self.hold(keys[count - 1].clone())?;
+        Ok(Some((next, keys)))
+    }
+
+    /// holds the key under a new cursor
+    fn hold(&self, last_key: String) -> Result<u64> {
+        let mut held = self.lock()?;
+        // cursors increase, so that none is reused even if the clock goes back (nor, mostly,
+        // once the server restarts)
+        let cursor = now_millis().max(held.last_cursor + 1);
+        held.last_cursor = cursor;
+        held.last_keys.insert(cursor, last_key);
+        while held.last_keys.len() > MAX_HELD_CURSORS {
+            held.last_keys.pop_first();
+        }
+        Ok(cursor)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Held>> {
+        self.held
+            .lock()
+            .map_err(|_| Error::new(ErrorKind::UnknownError))
+    }
+}
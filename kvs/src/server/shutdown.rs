@@ -0,0 +1,136 @@
+use std::{
+    collections::HashMap,
+    net,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+};
+
+use super::super::{Error, ErrorKind, Result};
+
+/// Handle which gracefully shuts down a KvsServer, e.g. from a signal handler
+///
+/// Once shut down, the server stops accepting connections, lets each open connection finish the
+/// requests it has already received and then shuts down the engine before `serve` returns.
+///
+/// # Example
+/// ```no_run
+/// use kvs::{KvsServer, SharedKvStore};
+///
+/// let engine = SharedKvStore::open(std::path::Path::new("testdb")).unwrap();
+/// let server = KvsServer::new(engine);
+/// let shutdown = server.shutdown_handle();
+/// std::thread::spawn(move || {
+///     std::thread::sleep(std::time::Duration::from_secs(60));
+///     shutdown.shutdown();
+/// });
+/// server.run("127.0.0.1:4000").unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    shared: Arc<ShutdownShared>,
+}
+
+struct ShutdownShared {
+    requested: AtomicBool,
+    local_addr: Mutex<Option<net::SocketAddr>>,
+    connections: Mutex<Connections>,
+    drained: Condvar,
+}
+
+#[derive(Default)]
+struct Connections {
+    next_id: u64,
+    streams: HashMap<u64, net::TcpStream>,
+}
+
+/// registration of an open connection, which is removed when dropped
+pub(super) struct Connection {
+    handle: ShutdownHandle,
+    id: u64,
+}
+
+impl ShutdownHandle {
+    pub(super) fn new() -> Self {
+        Self {
+            shared: Arc::new(ShutdownShared {
+                requested: AtomicBool::new(false),
+                local_addr: Mutex::new(None),
+                connections: Mutex::new(Connections::default()),
+                drained: Condvar::new(),
+            }),
+        }
+    }
+    /// shut down the server (which may not have started serving yet)
+    pub fn shutdown(&self) {
+        self.shared.requested.store(true, Ordering::SeqCst);
+        let local_addr = *self
+            .shared
+            .local_addr
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        // wake the server if it is waiting to accept a connection
+        if let Some(local_addr) = local_addr {
+            let _ = net::TcpStream::connect(local_addr);
+        }
+    }
+    /// true once the server has been asked to shut down
+    pub fn is_shutdown_requested(&self) -> bool {
+        self.shared.requested.load(Ordering::SeqCst)
+    }
+
+    pub(super) fn listening_on(&self, local_addr: net::SocketAddr) -> Result<()> {
+        *self
+            .shared
+            .local_addr
+            .lock()
+            .map_err(|_| Error::new(ErrorKind::UnknownError))? = Some(local_addr);
+        Ok(())
+    }
+    pub(super) fn register(&self, stream: &net::TcpStream) -> Result<Connection> {
+        let stream = stream.try_clone()?;
+        let mut connections = self.lock()?;
+        let id = connections.next_id;
+        connections.next_id += 1;
+        connections.streams.insert(id, stream);
+        Ok(Connection {
+            handle: self.clone(),
+            id,
+        })
+    }
+    pub(super) fn open_connections(&self) -> Result<usize> {
+        Ok(self.lock()?.streams.len())
+    }
+    /// stops reading further requests from every open connection and waits for them to close
+    pub(super) fn drain_connections(&self) -> Result<()> {
+        let mut connections = self.lock()?;
+        for stream in connections.streams.values() {
+            let _ = stream.shutdown(net::Shutdown::Read);
+        }
+        while !connections.streams.is_empty() {
+            connections = self
+                .shared
+                .drained
+                .wait(connections)
+                .map_err(|_| Error::new(ErrorKind::UnknownError))?;
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Connections>> {
+        self.shared
+            .connections
+            .lock()
+            .map_err(|_| Error::new(ErrorKind::UnknownError))
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if let Ok(mut connections) = self.handle.lock() {
+            connections.streams.remove(&self.id);
+        }
+        self.handle.shared.drained.notify_all();
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use super::super::{
+    transfer::Snapshot, version::now_millis, Error, ErrorKind, KvsEngine, Result, SnapshotChunk,
+};
+
+/// number of snapshots a server holds for clients still receiving them (the oldest being dropped
+/// to hold a new one)
+const MAX_HELD_SNAPSHOTS: usize = 4;
+
+/// snapshots of the engine taken for clients (such as `kvs backup --remote`), held until their
+/// last chunk is sent so that a client whose connection fails resumes where it stopped, shared by
+/// the server's connections
+#[derive(Clone, Default)]
+pub(super) struct Snapshots {
+    held: Arc<Mutex<Held>>,
+}
+
+#[derive(Default)]
+struct Held {
+    snapshots: BTreeMap<u64, Arc<Snapshot>>,
+    /// id of the last snapshot taken
+    last_id: u64,
+}
+
+impl Snapshots {
+    /// the chunk at the offset of the snapshot of the id, or the first chunk of a new snapshot
+    /// of the engine if no id is given or the snapshot is no longer held
+    pub(super) fn chunk<E: KvsEngine>(
+        &self,
+        engine: &E,
+        snapshot_id: Option<u64>,
+        offset: u64,
+        max_bytes: u64,
+    ) -> Result<SnapshotChunk> {
+        let max_bytes = max_bytes as usize;
+        let held = snapshot_id.and_then(|id| self.lock().ok()?.snapshots.get(&id).cloned());
+        let (snapshot, offset) = match held {
+            Some(snapshot) => (snapshot, offset),
+            None => (self.take(engine)?, 0),
+        };
+        let chunk = snapshot.chunk(offset, max_bytes);
+        if chunk.is_last() {
+            self.lock()?.snapshots.remove(&snapshot.id());
+        }
+        Ok(chunk)
+    }
+
+    /// takes a new snapshot of the engine and holds it
+    fn take<E: KvsEngine>(&self, engine: &E) -> Result<Arc<Snapshot>> {
+        let pairs = engine.pairs()?;
+        let mut held = self.lock()?;
+        // ids increase, so that none is reused even if the clock goes back
+        let id = now_millis().max(held.last_id + 1);
+        held.last_id = id;
+        let snapshot = Arc::new(Snapshot::new(id, pairs));
+        held.snapshots.insert(id, snapshot.clone());
+        while held.snapshots.len() > MAX_HELD_SNAPSHOTS {
+            held.snapshots.pop_first();
+        }
+        Ok(snapshot)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Held>> {
+        self.held
+            .lock()
+            .map_err(|_| Error::new(ErrorKind::UnknownError))
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    hash::{BuildHasher, Hasher},
+    path,
+    sync::{mpsc, Arc},
+    time::Duration,
+};
+
+#[cfg(feature = "stats")]
+use super::Stats;
+use super::{
+    latest_log_for_dir, notify, Error, ErrorKind, IndexHasher, KeyEvent, KvStore, KvsEngine, Lock,
+    Result, RuntimeOptions, SharedKvStore, StoreEvent, StoreOptions, WriteBatch,
+};
+
+/// number of partitions of a ShardedKvStore opened with `open`
+const DEFAULT_SHARDS: usize = 8;
+
+/// KvStore shared between threads as several partitions, each a store of its own (with its own
+/// log, index and lock) holding the keys which hash to it, so that threads working on keys of
+/// different partitions do not wait for one another
+///
+/// The partitions share the directory of the store, the files of each named with the file prefix
+/// followed by `-shard<n>`. A store must always be opened with the number of partitions it was
+/// created with, InvalidConfiguration otherwise, as its keys would be looked for in the wrong
+/// partitions. The quotas of the options (such as `max_keys`) apply to each partition.
+///
+/// A batch is applied with every partition it writes to locked, so no reader sees it part
+/// applied, but it is atomic within each partition only: a failure (or crash) part way through
+/// may leave it applied to some partitions and not others.
+///
+/// # Example
+/// ```
+/// use kvs::{KvsEngine, ShardedKvStore};
+///
+/// let dir = tempfile::TempDir::new().unwrap();
+/// let engine = ShardedKvStore::open(dir.path()).unwrap();
+/// let _ = engine.set("key1".into(), "value1".into());
+/// assert_eq!(engine.get("key1".into()).unwrap(), Some("value1".into()));
+/// ```
+#[derive(Clone)]
+pub struct ShardedKvStore {
+    shards: Arc<[SharedKvStore]>,
+}
+
+impl ShardedKvStore {
+    /// open a store of 8 partitions at a path for sharing between threads
+    pub fn open(path: &path::Path) -> Result<Self> {
+        Self::open_with_options(path, DEFAULT_SHARDS, &StoreOptions::default())
+    }
+    /// open a store of the given number of partitions at a path, each with the options,
+    /// InvalidConfiguration if there are no partitions or the store was created with another
+    /// number of them
+    pub fn open_with_options(
+        path: &path::Path,
+        shards: usize,
+        options: &StoreOptions,
+    ) -> Result<Self> {
+        if shards == 0 {
+            return Err(Error::new(ErrorKind::InvalidConfiguration));
+        }
+        let shard_options = |shard| StoreOptions {
+            file_prefix: format!("{}-shard{}", options.file_prefix, shard),
+            ..options.clone()
+        };
+        if path.is_dir() {
+            let mut existing = 0;
+            for shard in 0..=shards {
+                if latest_log_for_dir(path, &shard_options(shard).file_prefix)?.is_some() {
+                    existing += 1;
+                }
+            }
+            // a new store has none of the partitions, one created with as many has every one
+            if existing != 0 && existing != shards {
+                return Err(Error::new(ErrorKind::InvalidConfiguration).at_path(path));
+            }
+        }
+        let shards = (0..shards)
+            .map(|shard| KvStore::open_with_options(path, &shard_options(shard)))
+            .map(|store| store.map(SharedKvStore::new))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            shards: shards.into(),
+        })
+    }
+    /// number of partitions of the store
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// the partition holding the key (or lock of the name)
+    fn shard(&self, key: &str) -> &SharedKvStore {
+        &self.shards[self.shard_index(key)]
+    }
+    /// index of the partition holding the key, hashing it with FxHash (which, unlike the hasher
+    /// of an index, must not change while the store exists)
+    fn shard_index(&self, key: &str) -> usize {
+        let mut hasher = IndexHasher::fx().build_hasher();
+        hasher.write(key.as_bytes());
+        // the high bits of FxHash are better mixed than the low
+        (hasher.finish() >> 32) as usize % self.shards.len()
+    }
+}
+
+impl KvsEngine for ShardedKvStore {
+    fn set(&self, key: String, value: String) -> Result<()> {
+        self.shard(&key).set(key, value)
+    }
+    fn set_if_absent(&self, key: String, value: String) -> Result<bool> {
+        self.shard(&key).set_if_absent(key, value)
+    }
+    fn set_once(&self, key: String, value: String, token: u64) -> Result<bool> {
+        // a retry of the write is of the same key, so reaches the partition which saw the token
+        self.shard(&key).set_once(key, value, token)
+    }
+    fn get(&self, key: String) -> Result<Option<String>> {
+        self.shard(&key).get(key)
+    }
+    fn get_with_max_lag(&self, key: String, _max_lag: Duration) -> Result<Option<String>> {
+        self.get(key)
+    }
+    fn get_bytes(&self, key: &[u8], buf: &mut Vec<u8>) -> Result<bool> {
+        match std::str::from_utf8(key) {
+            Ok(key_str) => self.shard(key_str).get_bytes(key, buf),
+            Err(_) => {
+                buf.clear();
+                Ok(false)
+            }
+        }
+    }
+    fn append(&self, key: String, suffix: String) -> Result<usize> {
+        self.shard(&key).append(key, suffix)
+    }
+    fn value_len(&self, key: String) -> Result<usize> {
+        self.shard(&key).value_len(key)
+    }
+    fn remove(&self, key: String) -> Result<()> {
+        self.shard(&key).remove(key)
+    }
+    fn keys(&self) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for shard in self.shards.iter() {
+            keys.extend(shard.keys()?);
+        }
+        Ok(keys)
+    }
+    fn pairs(&self) -> Result<Vec<(String, String)>> {
+        let mut pairs = Vec::new();
+        for shard in self.shards.iter() {
+            pairs.extend(shard.pairs()?);
+        }
+        Ok(pairs)
+    }
+    fn write_batch(&self, batch: WriteBatch<String, String>) -> Result<Vec<bool>> {
+        let operations = batch.into_operations();
+        let mut batches = (0..self.shards.len())
+            .map(|_| (Vec::new(), WriteBatch::new()))
+            .collect::<Vec<_>>();
+        let count = operations.len();
+        for (position, (key, value)) in operations.into_iter().enumerate() {
+            let (positions, batch) = &mut batches[self.shard_index(&key)];
+            positions.push(position);
+            match value {
+                Some(value) => batch.set(key, value),
+                None => batch.remove(key),
+            };
+        }
+        // the partitions are locked in order, so that batches do not deadlock
+        let mut locked = Vec::new();
+        for (shard, (positions, batch)) in batches.into_iter().enumerate() {
+            if !batch.is_empty() {
+                locked.push((self.shards[shard].lock()?, positions, batch));
+            }
+        }
+        let mut applied = vec![false; count];
+        for (store, positions, batch) in &mut locked {
+            let results = store.write_batch(std::mem::take(batch))?;
+            for (&position, result) in positions.iter().zip(results) {
+                applied[position] = result;
+            }
+        }
+        Ok(applied)
+    }
+    fn key_count(&self) -> Result<usize> {
+        let mut count = 0;
+        for shard in self.shards.iter() {
+            count += shard.key_count()?;
+        }
+        Ok(count)
+    }
+    fn acquire_lock(&self, name: String, ttl: Duration) -> Result<Option<Lock>> {
+        self.shard(&name).acquire_lock(name, ttl)
+    }
+    fn refresh_lock(&self, name: String, token: u64, ttl: Duration) -> Result<Option<Lock>> {
+        self.shard(&name).refresh_lock(name, token, ttl)
+    }
+    fn release_lock(&self, name: String, token: u64) -> Result<bool> {
+        self.shard(&name).release_lock(name, token)
+    }
+    fn subscribe(&self, prefix: String) -> Result<mpsc::Receiver<KeyEvent<String>>> {
+        let (sender, receiver) = notify::channel();
+        for (shard, store) in self.shards.iter().enumerate() {
+            let prefix = prefix.clone();
+            // every partition is cleared together, the first alone sending Cleared
+            store.lock()?.subscribe_with(
+                move |key: &String| key.starts_with(&prefix),
+                sender.clone(),
+                shard == 0,
+            );
+        }
+        Ok(receiver)
+    }
+    fn subscribe_events(&self) -> Result<mpsc::Receiver<StoreEvent>> {
+        let (sender, receiver) = mpsc::channel();
+        for store in self.shards.iter() {
+            store.lock()?.subscribe_events_with(sender.clone());
+        }
+        Ok(receiver)
+    }
+    fn compact(&self) -> Result<()> {
+        self.shards.iter().try_for_each(KvsEngine::compact)
+    }
+    fn clear(&self) -> Result<()> {
+        // every partition is locked first, so no reader sees some cleared and others not
+        let mut locked = self
+            .shards
+            .iter()
+            .map(SharedKvStore::lock)
+            .collect::<Result<Vec<_>>>()?;
+        locked.iter_mut().try_for_each(|store| store.clear())
+    }
+    fn reconfigure(&self, options: RuntimeOptions) -> Result<()> {
+        self.shards
+            .iter()
+            .try_for_each(|shard| shard.reconfigure(options))
+    }
+    #[cfg(feature = "stats")]
+    fn stats(&self) -> Result<Stats> {
+        let stats = self
+            .shards
+            .iter()
+            .map(KvsEngine::stats)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Stats::combine(stats))
+    }
+    fn leader(&self) -> Result<Option<u64>> {
+        Ok(None)
+    }
+    fn shutdown(&self) -> Result<()> {
+        self.shards.iter().try_for_each(KvsEngine::shutdown)
+    }
+}
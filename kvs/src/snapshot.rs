@@ -0,0 +1,137 @@
+use std::{collections::HashMap, fs, hash, io, path};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{codec, sync_dir_of, Index, IndexHasher, Result, Trashed};
+
+/// Index of a KvStore persisted on clean shutdown so the next open need not replay the log
+#[derive(Serialize, Deserialize)]
+struct IndexSnapshot<K> {
+    /// file name of the log the index is of
+    log: String,
+    /// length of the log when the index was persisted
+    log_len: u64,
+    stale_count: u64,
+    entries: Vec<IndexEntry<K>>,
+    /// sequence number of the next write; defaulted so that snapshots written before writes were
+    /// versioned can still be read
+    #[serde(default)]
+    next_version: u64,
+    /// keys in the trash; defaulted so that snapshots written before removed keys were kept in a
+    /// trash can still be read
+    #[serde(default = "Vec::new")]
+    trash: Vec<TrashEntry<K>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexEntry<K> {
+    key: K,
+    db_key: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TrashEntry<K> {
+    key: K,
+    trashed: Trashed,
+}
+
+/// index, stale record count, sequence number of the next write and trash loaded from a snapshot
+pub(crate) type LoadedIndex<K> = (Index<K>, u64, u64, HashMap<K, Trashed>);
+
+/// writes (and syncs) the snapshot of the index of the log at db_path to the hint file, followed
+/// by its CRC-32
+pub(crate) fn write_index_snapshot<K>(
+    hint_path: &path::Path,
+    db_path: &path::Path,
+    log_len: u64,
+    stale_count: u64,
+    next_version: u64,
+    index: &Index<K>,
+    trash: &HashMap<K, Trashed>,
+) -> Result<()>
+where
+    K: Serialize,
+{
+    let snapshot = IndexSnapshot {
+        log: log_file_name(db_path),
+        log_len,
+        stale_count,
+        entries: index
+            .iter()
+            .map(|(key, &db_key)| IndexEntry { key, db_key })
+            .collect(),
+        next_version,
+        trash: trash
+            .iter()
+            .map(|(key, &trashed)| TrashEntry { key, trashed })
+            .collect(),
+    };
+    let mut bytes = Vec::new();
+    codec::encode(&snapshot, &mut bytes)?;
+    bytes.extend_from_slice(&crc32fast::hash(&bytes).to_be_bytes());
+    // written under a temporary name so that a crash part way through leaves no snapshot
+    let temp_path = hint_path.with_extension("hint.tmp");
+    fs::write(&temp_path, &bytes)?;
+    fs::File::open(&temp_path)?.sync_all()?;
+    fs::rename(&temp_path, hint_path)?;
+    sync_dir_of(hint_path);
+    Ok(())
+}
+
+/// removes the hint file (if it exists), returning the index snapshot it holds if its checksum is
+/// valid and it is of the log at db_path as it is now (its index hashing keys with the hasher)
+pub(crate) fn take_index_snapshot<K>(
+    hint_path: &path::Path,
+    db_path: &path::Path,
+    hasher: &IndexHasher,
+) -> Result<Option<LoadedIndex<K>>>
+where
+    K: DeserializeOwned + Eq + hash::Hash,
+{
+    let bytes = match fs::read(hint_path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    fs::remove_file(hint_path)?;
+    if bytes.len() < 4 {
+        return Ok(None);
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - 4);
+    if crc32fast::hash(body).to_be_bytes() != checksum {
+        return Ok(None);
+    }
+    // a snapshot written in ASN.1 DER by earlier versions is not read, the log being replayed
+    let snapshot: IndexSnapshot<K> = match codec::decode(body) {
+        Ok(snapshot) => snapshot,
+        Err(_) => return Ok(None),
+    };
+    if snapshot.log != log_file_name(db_path) || snapshot.log_len != fs::metadata(db_path)?.len() {
+        return Ok(None);
+    }
+    let mut index = Index::with_capacity_and_hasher(snapshot.entries.len(), hasher.clone());
+    index.extend(
+        snapshot
+            .entries
+            .into_iter()
+            .map(|entry| (entry.key, entry.db_key)),
+    );
+    let trash = snapshot
+        .trash
+        .into_iter()
+        .map(|entry| (entry.key, entry.trashed))
+        .collect();
+    Ok(Some((
+        index,
+        snapshot.stale_count,
+        snapshot.next_version,
+        trash,
+    )))
+}
+
+fn log_file_name(db_path: &path::Path) -> String {
+    db_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
@@ -0,0 +1,265 @@
+#[cfg(feature = "stats")]
+use std::time::Duration;
+
+#[cfg(feature = "stats")]
+use hdrhistogram::Histogram;
+
+/// operations of a KvStore whose latencies are recorded (with the `stats` feature)
+#[derive(Clone, Copy)]
+pub(crate) enum Operation {
+    Set,
+    Get,
+    Remove,
+    Compaction,
+}
+
+/// Latencies of the operations of a KvStore since it was opened, the sizes of the keys and values
+/// it has written, and the writes of the log not yet flushed or synced, from `KvStore::stats`
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Stats {
+    /// latencies of `set` and `merge` (including any compaction they triggered)
+    pub set: LatencyStats,
+    /// latencies of `get`
+    pub get: LatencyStats,
+    /// latencies of `remove` (including any compaction it triggered)
+    pub remove: LatencyStats,
+    /// latencies of compactions, whether triggered by writes or by `compact_now`
+    pub compaction: LatencyStats,
+    /// bytes written to the log since it was last synced (by `sync`, `shutdown`, `clear` or a
+    /// compaction), which a crash may lose
+    pub unsynced_bytes: u64,
+    /// bytes of records written but still in the write buffer, not yet handed to the file
+    pub pending_batch_bytes: u64,
+    /// records written since the write buffer was last flushed
+    pub unflushed_records: u64,
+    /// sizes of the keys written (including those removed) since the store was opened
+    pub key_sizes: SizeStats,
+    /// sizes of the values (and merge operands) written since the store was opened
+    pub value_sizes: SizeStats,
+}
+
+/// Summary of the recorded latencies of one operation (all zero if none were recorded)
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LatencyStats {
+    /// number of operations recorded
+    pub count: u64,
+    /// shortest latency
+    pub min: Duration,
+    /// mean latency
+    pub mean: Duration,
+    /// median latency
+    pub p50: Duration,
+    /// 99th percentile latency
+    pub p99: Duration,
+    /// 99.9th percentile latency
+    pub p999: Duration,
+    /// longest latency
+    pub max: Duration,
+}
+
+/// Summary of the sizes, in bytes as written to the log, of the keys or values written by a
+/// KvStore, to 3 significant figures (all zero if none were written)
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SizeStats {
+    /// number of keys or values written
+    pub count: u64,
+    /// median size
+    pub p50: u64,
+    /// 90th percentile size
+    pub p90: u64,
+    /// 99th percentile size
+    pub p99: u64,
+    /// largest size
+    pub max: u64,
+}
+
+/// per-operation histograms of latencies, in nanoseconds
+#[cfg(feature = "stats")]
+pub(crate) struct Latencies {
+    set: Histogram<u64>,
+    get: Histogram<u64>,
+    remove: Histogram<u64>,
+    compaction: Histogram<u64>,
+}
+
+/// histograms of the sizes of the keys and values written, in bytes
+#[cfg(feature = "stats")]
+pub(crate) struct Sizes {
+    keys: Histogram<u64>,
+    values: Histogram<u64>,
+}
+
+#[cfg(feature = "stats")]
+impl Sizes {
+    pub(crate) fn new() -> Self {
+        Self {
+            keys: Histogram::new(3).unwrap(),
+            values: Histogram::new(3).unwrap(),
+        }
+    }
+    /// records the sizes of the key and value (None for a removal) of a record written
+    pub(crate) fn record(&mut self, key: usize, value: Option<usize>) {
+        self.keys.saturating_record(key as u64);
+        if let Some(value) = value {
+            self.values.saturating_record(value as u64);
+        }
+    }
+}
+
+#[cfg(feature = "stats")]
+impl Stats {
+    /// the stats of several stores (such as the partitions of a ShardedKvStore) taken together,
+    /// their percentiles being the highest of any store (as the histograms are not at hand)
+    pub(crate) fn combine(stats: impl IntoIterator<Item = Stats>) -> Stats {
+        stats
+            .into_iter()
+            .fold(Stats::default(), |all, stats| Stats {
+                set: all.set.combine(stats.set),
+                get: all.get.combine(stats.get),
+                remove: all.remove.combine(stats.remove),
+                compaction: all.compaction.combine(stats.compaction),
+                unsynced_bytes: all.unsynced_bytes + stats.unsynced_bytes,
+                pending_batch_bytes: all.pending_batch_bytes + stats.pending_batch_bytes,
+                unflushed_records: all.unflushed_records + stats.unflushed_records,
+                key_sizes: all.key_sizes.combine(stats.key_sizes),
+                value_sizes: all.value_sizes.combine(stats.value_sizes),
+            })
+    }
+}
+
+#[cfg(feature = "stats")]
+impl LatencyStats {
+    fn combine(self, other: Self) -> Self {
+        match (self.count, other.count) {
+            (0, _) => return other,
+            (_, 0) => return self,
+            _ => (),
+        }
+        let count = self.count + other.count;
+        let total = self.mean.as_nanos() * u128::from(self.count)
+            + other.mean.as_nanos() * u128::from(other.count);
+        Self {
+            count,
+            min: self.min.min(other.min),
+            mean: Duration::from_nanos((total / u128::from(count)) as u64),
+            p50: self.p50.max(other.p50),
+            p99: self.p99.max(other.p99),
+            p999: self.p999.max(other.p999),
+            max: self.max.max(other.max),
+        }
+    }
+}
+
+#[cfg(feature = "stats")]
+impl SizeStats {
+    fn combine(self, other: Self) -> Self {
+        Self {
+            count: self.count + other.count,
+            p50: self.p50.max(other.p50),
+            p90: self.p90.max(other.p90),
+            p99: self.p99.max(other.p99),
+            max: self.max.max(other.max),
+        }
+    }
+}
+
+/// writes of the log not yet flushed from the write buffer, or not yet synced
+#[cfg(feature = "stats")]
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Backlog {
+    unsynced_bytes: u64,
+    unflushed_bytes: u64,
+    unflushed_records: u64,
+}
+
+#[cfg(feature = "stats")]
+impl Backlog {
+    pub(crate) fn written(&mut self, bytes: usize) {
+        self.unsynced_bytes += bytes as u64;
+        self.unflushed_bytes += bytes as u64;
+        self.unflushed_records += 1;
+    }
+    pub(crate) fn flushed(&mut self) {
+        self.unflushed_bytes = 0;
+        self.unflushed_records = 0;
+    }
+    pub(crate) fn synced(&mut self) {
+        *self = Self::default();
+    }
+    /// the writes since the last flush are dropped, as when a write fails
+    pub(crate) fn discarded(&mut self) {
+        self.unsynced_bytes -= self.unflushed_bytes;
+        self.flushed();
+    }
+}
+
+#[cfg(feature = "stats")]
+impl Latencies {
+    pub(crate) fn new() -> Self {
+        // auto-resizing histograms keeping 3 significant figures cannot fail to be created
+        let histogram = || Histogram::new(3).unwrap();
+        Self {
+            set: histogram(),
+            get: histogram(),
+            remove: histogram(),
+            compaction: histogram(),
+        }
+    }
+    pub(crate) fn record(&mut self, operation: Operation, latency: Duration) {
+        let histogram = match operation {
+            Operation::Set => &mut self.set,
+            Operation::Get => &mut self.get,
+            Operation::Remove => &mut self.remove,
+            Operation::Compaction => &mut self.compaction,
+        };
+        histogram.saturating_record(latency.as_nanos().min(u64::MAX as u128) as u64);
+    }
+    /// the summary of the latencies, with the sizes written, the backlog of writes and the bytes
+    /// still buffered
+    pub(crate) fn stats(&self, sizes: &Sizes, backlog: &Backlog, buffered_bytes: usize) -> Stats {
+        Stats {
+            set: summarize(&self.set),
+            get: summarize(&self.get),
+            remove: summarize(&self.remove),
+            compaction: summarize(&self.compaction),
+            unsynced_bytes: backlog.unsynced_bytes,
+            pending_batch_bytes: buffered_bytes as u64,
+            unflushed_records: backlog.unflushed_records,
+            key_sizes: summarize_sizes(&sizes.keys),
+            value_sizes: summarize_sizes(&sizes.values),
+        }
+    }
+}
+
+#[cfg(feature = "stats")]
+fn summarize(histogram: &Histogram<u64>) -> LatencyStats {
+    if histogram.is_empty() {
+        return LatencyStats::default();
+    }
+    LatencyStats {
+        count: histogram.len(),
+        min: Duration::from_nanos(histogram.min()),
+        mean: Duration::from_nanos(histogram.mean() as u64),
+        p50: Duration::from_nanos(histogram.value_at_quantile(0.5)),
+        p99: Duration::from_nanos(histogram.value_at_quantile(0.99)),
+        p999: Duration::from_nanos(histogram.value_at_quantile(0.999)),
+        max: Duration::from_nanos(histogram.max()),
+    }
+}
+
+#[cfg(feature = "stats")]
+fn summarize_sizes(histogram: &Histogram<u64>) -> SizeStats {
+    if histogram.is_empty() {
+        return SizeStats::default();
+    }
+    SizeStats {
+        count: histogram.len(),
+        p50: histogram.value_at_quantile(0.5),
+        p90: histogram.value_at_quantile(0.9),
+        p99: histogram.value_at_quantile(0.99),
+        max: histogram.max(),
+    }
+}
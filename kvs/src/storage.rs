@@ -0,0 +1,239 @@
+use std::{
+    fs,
+    io::{self, Seek},
+    path::Path,
+    sync::Arc,
+};
+
+#[cfg(unix)]
+mod direct;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
+
+/// How a KvStore reads and writes its files, set in its StoreOptions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IoBackend {
+    /// blocking reads and writes (the default)
+    #[default]
+    Standard,
+    /// (experimental) reads and writes submitted to an io_uring of each file, writes being left
+    /// in flight until the store flushes them and reads being made ahead of the store; opening
+    /// the store fails if the kernel does not support io_uring
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    IoUring,
+}
+
+/// How the writes of a KvStore reach the disk, set in its StoreOptions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteMode {
+    /// writes go to the page cache, reaching the disk when the store is synced or the kernel
+    /// writes them back (the default)
+    #[default]
+    Buffered,
+    /// each write is on the disk before it returns (O_DSYNC, on Unix only), so that the latency
+    /// of durability is paid by every write rather than by the syncs
+    Dsync,
+    /// as Dsync, with writes also bypassing the page cache (O_DIRECT, on Linux only), being
+    /// buffered to be written in whole blocks, of which the last partial one is written through
+    /// the page cache when the store flushes
+    Direct,
+}
+
+impl IoBackend {
+    /// opens the files of a store using this backend with the write mode, None if the platform
+    /// (or the backend) does not support the mode
+    pub(crate) fn storage(self, write_mode: WriteMode) -> Option<Arc<dyn OpenStorage>> {
+        match (self, write_mode) {
+            (IoBackend::Standard, WriteMode::Buffered) => Some(Arc::new(FileStorage)),
+            #[cfg(unix)]
+            (IoBackend::Standard, WriteMode::Dsync) => {
+                Some(Arc::new(direct::SyncedStorage { direct: false }))
+            }
+            #[cfg(target_os = "linux")]
+            (IoBackend::Standard, WriteMode::Direct) => {
+                Some(Arc::new(direct::SyncedStorage { direct: true }))
+            }
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            (IoBackend::IoUring, WriteMode::Buffered) => Some(Arc::new(uring::UringStorage)),
+            // reached unless the platform supports every mode (and io_uring is not enabled)
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+}
+
+/// File of a KvStore (its log, or the log being compacted into), the store reaching the disk only
+/// through this so that tests can inject I/O faults
+pub(crate) trait Storage: io::Read + io::Write + io::Seek + Send {
+    /// write the file's data through to the disk (fsync)
+    fn sync(&self) -> io::Result<()>;
+    /// truncate (or extend) the file to the given length
+    fn set_len(&self, len: u64) -> io::Result<()>;
+    /// allocate the blocks of the file up to the given length, leaving its length as it is
+    fn preallocate(&self, len: u64) -> io::Result<()>;
+}
+
+impl Storage for fs::File {
+    fn sync(&self) -> io::Result<()> {
+        self.sync_all()
+    }
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        fs::File::set_len(self, len)
+    }
+    fn preallocate(&self, len: u64) -> io::Result<()> {
+        preallocate(self, len)
+    }
+}
+
+/// allocates the blocks of the file up to the given length (on Linux, with fallocate) without
+/// changing its length, which is where the log ends; a no-op on other platforms and filesystems
+/// which cannot preallocate
+#[cfg(target_os = "linux")]
+pub(crate) fn preallocate(file: &fs::File, len: u64) -> io::Result<()> {
+    use std::{convert::TryFrom, os::unix::io::AsRawFd};
+
+    let len = libc::off_t::try_from(len).unwrap_or(libc::off_t::MAX);
+    match unsafe { libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE, 0, len) } {
+        0 => Ok(()),
+        _ => match io::Error::last_os_error() {
+            err if err.raw_os_error() == Some(libc::EOPNOTSUPP) => Ok(()),
+            err => Err(err),
+        },
+    }
+}
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn preallocate(_file: &fs::File, _len: u64) -> io::Result<()> {
+    Ok(())
+}
+
+/// Opens the files of a KvStore
+pub(crate) trait OpenStorage: Send + Sync {
+    /// open the file at path for reading from its start
+    fn open_reader(&self, path: &Path) -> io::Result<Box<dyn Storage>>;
+    /// open (creating it if it does not exist) the file at path for appending, or for writing from
+    /// its start if it is to be truncated
+    fn open_writer(&self, path: &Path, truncate: bool) -> io::Result<Box<dyn Storage>>;
+}
+
+/// Files on the local filesystem, used by every KvStore outside of tests
+pub(crate) struct FileStorage;
+
+impl OpenStorage for FileStorage {
+    fn open_reader(&self, path: &Path) -> io::Result<Box<dyn Storage>> {
+        Ok(Box::new(fs::OpenOptions::new().read(true).open(path)?))
+    }
+    fn open_writer(&self, path: &Path, truncate: bool) -> io::Result<Box<dyn Storage>> {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(truncate)
+            .open(path)?;
+        if !truncate {
+            file.seek(io::SeekFrom::End(0))?;
+        }
+        Ok(Box::new(file))
+    }
+}
+
+#[cfg(test)]
+pub(crate) use faults::FaultyStorage;
+
+#[cfg(test)]
+mod faults {
+    use std::{
+        io,
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    use super::{FileStorage, OpenStorage, Storage};
+
+    /// Faults injected into the files opened through a FaultyStorage, which may be changed at any
+    /// time (affecting files already open)
+    #[derive(Default)]
+    pub(crate) struct Faults {
+        /// bytes which may still be written (across all files) before writes fail with ENOSPC
+        pub(crate) space_left: Option<u64>,
+        /// writes take at most one byte at a time, as a short write would
+        pub(crate) partial_writes: bool,
+        /// fsync fails
+        pub(crate) fail_sync: bool,
+    }
+
+    /// Files on the local filesystem, into which the faults shared with the test are injected
+    #[derive(Clone, Default)]
+    pub(crate) struct FaultyStorage {
+        pub(crate) faults: Arc<Mutex<Faults>>,
+    }
+
+    impl OpenStorage for FaultyStorage {
+        fn open_reader(&self, path: &Path) -> io::Result<Box<dyn Storage>> {
+            FileStorage.open_reader(path)
+        }
+        fn open_writer(&self, path: &Path, truncate: bool) -> io::Result<Box<dyn Storage>> {
+            Ok(Box::new(FaultyFile {
+                file: FileStorage.open_writer(path, truncate)?,
+                faults: self.faults.clone(),
+            }))
+        }
+    }
+
+    struct FaultyFile {
+        file: Box<dyn Storage>,
+        faults: Arc<Mutex<Faults>>,
+    }
+
+    impl io::Write for FaultyFile {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut faults = self.faults.lock().unwrap();
+            let mut len = buf.len();
+            if faults.partial_writes {
+                len = len.min(1);
+            }
+            if let Some(space_left) = faults.space_left {
+                if space_left == 0 && len > 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::StorageFull,
+                        "no space left on device",
+                    ));
+                }
+                len = len.min(space_left as usize);
+            }
+            let written = self.file.write(&buf[..len])?;
+            if let Some(space_left) = &mut faults.space_left {
+                *space_left -= written as u64;
+            }
+            Ok(written)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.file.flush()
+        }
+    }
+
+    impl io::Read for FaultyFile {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.file.read(buf)
+        }
+    }
+
+    impl io::Seek for FaultyFile {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            self.file.seek(pos)
+        }
+    }
+
+    impl Storage for FaultyFile {
+        fn sync(&self) -> io::Result<()> {
+            match self.faults.lock().unwrap().fail_sync {
+                true => Err(io::Error::other("fsync failed")),
+                false => self.file.sync(),
+            }
+        }
+        fn set_len(&self, len: u64) -> io::Result<()> {
+            self.file.set_len(len)
+        }
+        fn preallocate(&self, len: u64) -> io::Result<()> {
+            self.file.preallocate(len)
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use std::{
+    fs, io,
+    os::unix::fs::{FileExt, OpenOptionsExt},
+    path::Path,
+};
+
+#[cfg(target_os = "linux")]
+use std::{alloc, cell::RefCell, ptr::NonNull, slice};
+
+use super::{preallocate, FileStorage, OpenStorage, Storage};
+
+/// alignment (and multiple) of the offsets, lengths and buffers of direct writes
+#[cfg(target_os = "linux")]
+const BLOCK: usize = 4096;
+
+/// number of blocks a file written directly buffers before writing them
+#[cfg(target_os = "linux")]
+const BUFFER_BLOCKS: usize = 64;
+
+/// Files on the local filesystem whose writes are on the disk when they return (O_DSYNC), and
+/// with `direct` also bypass the page cache (O_DIRECT, on Linux only), the files being read as
+/// usual
+pub(crate) struct SyncedStorage {
+    pub(crate) direct: bool,
+}
+
+impl OpenStorage for SyncedStorage {
+    fn open_reader(&self, path: &Path) -> io::Result<Box<dyn Storage>> {
+        FileStorage.open_reader(path)
+    }
+    fn open_writer(&self, path: &Path, truncate: bool) -> io::Result<Box<dyn Storage>> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(truncate)
+            .custom_flags(libc::O_DSYNC)
+            .open(path)?;
+        let position = file.metadata()?.len();
+        #[cfg(target_os = "linux")]
+        if self.direct {
+            let direct = fs::OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_DIRECT | libc::O_DSYNC)
+                .open(path)?;
+            return Ok(Box::new(DirectFile::new(direct, file, position)?));
+        }
+        Ok(Box::new(SyncedFile { file, position }))
+    }
+}
+
+/// File opened with O_DSYNC, written at the position it keeps
+struct SyncedFile {
+    file: fs::File,
+    position: u64,
+}
+
+impl io::Write for SyncedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write_at(buf, self.position)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Read for SyncedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.file.read_at(buf, self.position)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl io::Seek for SyncedFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.position = seek_position(&self.file, self.position, pos)?;
+        Ok(self.position)
+    }
+}
+
+impl Storage for SyncedFile {
+    fn sync(&self) -> io::Result<()> {
+        // each write is already on the disk, but not necessarily the metadata of the file
+        self.file.sync_all()
+    }
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        self.file.set_len(len)
+    }
+    fn preallocate(&self, len: u64) -> io::Result<()> {
+        preallocate(&self.file, len)
+    }
+}
+
+fn seek_position(file: &fs::File, position: u64, pos: io::SeekFrom) -> io::Result<u64> {
+    let position = match pos {
+        io::SeekFrom::Start(offset) => Some(offset),
+        io::SeekFrom::Current(delta) => position.checked_add_signed(delta),
+        io::SeekFrom::End(delta) => file.metadata()?.len().checked_add_signed(delta),
+    };
+    position
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position"))
+}
+
+/// File written with O_DIRECT through a buffer of whole blocks
+///
+/// Direct writes must be of whole blocks at block offsets from block-aligned memory, so the bytes
+/// written are buffered from the start of the block holding the position: whole blocks are
+/// written directly, while on flush the last partial block is written through the page cache
+/// (with O_DSYNC), to be written again directly once it is filled.
+#[cfg(target_os = "linux")]
+struct DirectFile {
+    // set_len is given only &self, but must reload the block holding the position
+    state: RefCell<DirectState>,
+}
+
+#[cfg(target_os = "linux")]
+struct DirectState {
+    direct: fs::File,
+    file: fs::File,
+    buf: AlignedBuf,
+    /// offset of the block at the start of the buffer
+    block_start: u64,
+    /// bytes of the buffer holding the file from block_start, the position following them
+    len: usize,
+    /// the buffer holds bytes not yet written to the file
+    dirty: bool,
+}
+
+#[cfg(target_os = "linux")]
+impl DirectFile {
+    fn new(direct: fs::File, file: fs::File, position: u64) -> io::Result<Self> {
+        let mut state = DirectState {
+            direct,
+            file,
+            buf: AlignedBuf::new(BLOCK * BUFFER_BLOCKS),
+            block_start: 0,
+            len: 0,
+            dirty: false,
+        };
+        state.reposition(position)?;
+        Ok(Self {
+            state: RefCell::new(state),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl DirectState {
+    fn position(&self) -> u64 {
+        self.block_start + self.len as u64
+    }
+    /// buffers the block holding the position, as the file holds it
+    fn reposition(&mut self, position: u64) -> io::Result<()> {
+        self.block_start = position - position % BLOCK as u64;
+        self.len = (position - self.block_start) as usize;
+        self.dirty = false;
+        let head = &mut self.buf.as_mut_slice()[..self.len];
+        let mut read = 0;
+        while read < head.len() {
+            match self
+                .file
+                .read_at(&mut head[read..], self.block_start + read as u64)?
+            {
+                0 => break,
+                more => read += more,
+            }
+        }
+        // bytes of the block beyond the end of the file read as zeros, as the file would extend
+        head[read..].fill(0);
+        Ok(())
+    }
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let capacity = self.buf.as_mut_slice().len();
+        let written = data.len().min(capacity - self.len);
+        self.buf.as_mut_slice()[self.len..self.len + written].copy_from_slice(&data[..written]);
+        self.len += written;
+        self.dirty = true;
+        if self.len == capacity {
+            self.flush()?;
+        }
+        Ok(written)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let whole_blocks = self.len - self.len % BLOCK;
+        let buf = self.buf.as_mut_slice();
+        self.direct
+            .write_all_at(&buf[..whole_blocks], self.block_start)?;
+        self.file.write_all_at(
+            &buf[whole_blocks..self.len],
+            self.block_start + whole_blocks as u64,
+        )?;
+        buf.copy_within(whole_blocks..self.len, 0);
+        self.block_start += whole_blocks as u64;
+        self.len -= whole_blocks;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl io::Write for DirectFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.state.get_mut().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.get_mut().flush()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl io::Read for DirectFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let state = self.state.get_mut();
+        state.flush()?;
+        let position = state.position();
+        let read = state.file.read_at(buf, position)?;
+        state.reposition(position + read as u64)?;
+        Ok(read)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl io::Seek for DirectFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let state = self.state.get_mut();
+        state.flush()?;
+        let position = seek_position(&state.file, state.position(), pos)?;
+        state.reposition(position)?;
+        Ok(position)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Storage for DirectFile {
+    fn sync(&self) -> io::Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.flush()?;
+        state.file.sync_all()
+    }
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.flush()?;
+        state.file.set_len(len)?;
+        let position = state.position();
+        state.reposition(position)
+    }
+    fn preallocate(&self, len: u64) -> io::Result<()> {
+        preallocate(&self.state.borrow().file, len)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for DirectFile {
+    fn drop(&mut self) {
+        // what was written is in the file once dropped, as for any other file
+        let _ = self.state.get_mut().flush();
+    }
+}
+
+/// zeroed memory aligned to a block, as direct writes must be made from
+#[cfg(target_os = "linux")]
+struct AlignedBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// the buffer is only reached through &mut self
+#[cfg(target_os = "linux")]
+unsafe impl Send for AlignedBuf {}
+
+#[cfg(target_os = "linux")]
+impl AlignedBuf {
+    fn new(len: usize) -> Self {
+        let layout = Self::layout(len);
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        match NonNull::new(ptr) {
+            Some(ptr) => Self { ptr, len },
+            None => alloc::handle_alloc_error(layout),
+        }
+    }
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+    fn layout(len: usize) -> alloc::Layout {
+        alloc::Layout::from_size_align(len, BLOCK).expect("a valid layout")
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), Self::layout(self.len)) }
+    }
+}
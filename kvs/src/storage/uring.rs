@@ -0,0 +1,526 @@
+use std::{
+    cell::RefCell,
+    fs, io, mem,
+    os::unix::{
+        fs::FileExt,
+        io::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+    },
+    path::Path,
+    ptr,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use super::{preallocate, OpenStorage, Storage};
+
+/// number of submissions a ring holds, which is also the most writes of a file in flight at once
+const QUEUE_DEPTH: u32 = 32;
+
+/// length of the reads submitted ahead of the reader of a file
+const READ_AHEAD_BYTES: usize = 64 * 1024;
+
+/// user data of the completion of an fsync (that of a write being its slot)
+const FSYNC: u64 = u64::MAX;
+
+/// user data of the completion of a read
+const READ: u64 = u64::MAX - 1;
+
+// from the Linux io_uring ABI (include/uapi/linux/io_uring.h)
+const IORING_OFF_SQ_RING: libc::off_t = 0;
+const IORING_OFF_CQ_RING: libc::off_t = 0x800_0000;
+const IORING_OFF_SQES: libc::off_t = 0x1000_0000;
+const IORING_ENTER_GETEVENTS: libc::c_uint = 1;
+const IORING_OP_FSYNC: u8 = 3;
+const IORING_OP_READ: u8 = 22;
+const IORING_OP_WRITE: u8 = 23;
+const IOSQE_IO_DRAIN: u8 = 1 << 1;
+
+#[repr(C)]
+#[derive(Default)]
+struct SqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqringOffsets,
+    cq_off: CqringOffsets,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    op_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    pad: u64,
+}
+
+#[repr(C)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+/// Files on the local filesystem read and written through an io_uring of their own
+///
+/// Writes are copied and submitted without waiting for them, so that the writes of a flush are
+/// in flight together, a flush waiting for them (as the store reads what it has flushed), and a
+/// sync submits the fsync behind the writes still in flight. Reads are submitted ahead of the
+/// reader, so that a scan of the log reads the next block while the records of the last are
+/// deserialized.
+pub(crate) struct UringStorage;
+
+impl OpenStorage for UringStorage {
+    fn open_reader(&self, path: &Path) -> io::Result<Box<dyn Storage>> {
+        let file = fs::OpenOptions::new().read(true).open(path)?;
+        Ok(Box::new(UringFile::new(file, 0)?))
+    }
+    fn open_writer(&self, path: &Path, truncate: bool) -> io::Result<Box<dyn Storage>> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(truncate)
+            .open(path)?;
+        let position = match truncate {
+            true => 0,
+            false => file.metadata()?.len(),
+        };
+        Ok(Box::new(UringFile::new(file, position)?))
+    }
+}
+
+/// a write in flight, whose buffer the kernel reads until it completes
+struct InFlight {
+    buf: Vec<u8>,
+    offset: u64,
+}
+
+/// a read submitted ahead of the reader into a buffer of its own, `filled` once it completes
+struct ReadAhead {
+    offset: u64,
+    buf: Vec<u8>,
+    filled: Option<usize>,
+}
+
+/// File read and written through its ring, whose state a sync (given only &self) also changes
+struct UringFile {
+    state: RefCell<State>,
+}
+
+struct State {
+    file: fs::File,
+    ring: Ring,
+    position: u64,
+    writes: Vec<Option<InFlight>>,
+    read_ahead: Option<ReadAhead>,
+    /// first failure of a submission in flight, reported by the next call
+    error: Option<io::Error>,
+}
+
+impl UringFile {
+    fn new(file: fs::File, position: u64) -> io::Result<Self> {
+        Ok(Self {
+            state: RefCell::new(State {
+                file,
+                // room for every write in flight along with a read ahead and an fsync
+                ring: Ring::new(QUEUE_DEPTH * 2)?,
+                position,
+                writes: (0..QUEUE_DEPTH).map(|_| None).collect(),
+                read_ahead: None,
+                error: None,
+            }),
+        })
+    }
+}
+
+impl State {
+    fn take_error(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+    /// waits for every submission in flight to complete, reporting the first failure
+    fn wait_all(&mut self) -> io::Result<()> {
+        while self.ring.in_flight > 0 {
+            self.ring.submit(1)?;
+            self.reap();
+        }
+        self.take_error()
+    }
+    /// handles the completions available, finishing short writes with blocking writes
+    fn reap(&mut self) {
+        while let Some(cqe) = self.ring.pop() {
+            let failure = match cqe.user_data {
+                READ => {
+                    if let Some(ahead) = &mut self.read_ahead {
+                        ahead.filled = Some(cqe.res.max(0) as usize);
+                    }
+                    (cqe.res < 0).then(|| io::Error::from_raw_os_error(-cqe.res))
+                }
+                FSYNC => (cqe.res < 0).then(|| io::Error::from_raw_os_error(-cqe.res)),
+                slot => match (self.writes[slot as usize].take(), cqe.res) {
+                    (_, res) if res < 0 => Some(io::Error::from_raw_os_error(-res)),
+                    (Some(write), res) if (res as usize) < write.buf.len() => self
+                        .file
+                        .write_all_at(&write.buf[res as usize..], write.offset + res as u64)
+                        .err(),
+                    _ => None,
+                },
+            };
+            if let Some(err) = failure {
+                self.error.get_or_insert(err);
+            }
+        }
+    }
+    fn submit_read_ahead(&mut self, offset: u64) -> io::Result<()> {
+        let mut buf = vec![0; READ_AHEAD_BYTES];
+        self.ring.push(Sqe {
+            opcode: IORING_OP_READ,
+            fd: self.file.as_raw_fd(),
+            off: offset,
+            addr: buf.as_mut_ptr() as u64,
+            len: buf.len() as u32,
+            user_data: READ,
+            ..Sqe::default()
+        });
+        self.read_ahead = Some(ReadAhead {
+            offset,
+            buf,
+            filled: None,
+        });
+        self.ring.submit(0)
+    }
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.take_error()?;
+        let slot = match self.writes.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None => {
+                self.wait_all()?;
+                0
+            }
+        };
+        let write = self.writes[slot].insert(InFlight {
+            buf: buf.to_vec(),
+            offset: self.position,
+        });
+        let sqe = Sqe {
+            opcode: IORING_OP_WRITE,
+            fd: self.file.as_raw_fd(),
+            off: write.offset,
+            addr: write.buf.as_ptr() as u64,
+            len: write.buf.len() as u32,
+            user_data: slot as u64,
+            ..Sqe::default()
+        };
+        self.ring.push(sqe);
+        self.ring.submit(0)?;
+        self.position += buf.len() as u64;
+        self.reap();
+        Ok(buf.len())
+    }
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.take_error()?;
+        loop {
+            let position = self.position;
+            let (offset, filled) = match &self.read_ahead {
+                Some(ahead) if ahead.offset <= position => (ahead.offset, ahead.filled),
+                _ => {
+                    // the kernel is done with the buffer of a read ahead only once it completes
+                    self.wait_all()?;
+                    self.submit_read_ahead(position)?;
+                    continue;
+                }
+            };
+            let filled = match filled {
+                Some(filled) => filled,
+                None => {
+                    self.wait_all()?;
+                    continue;
+                }
+            };
+            let start = (position - offset) as usize;
+            if start >= filled {
+                // the read ahead reached the end of the file, which may have grown since
+                let at_end = filled < READ_AHEAD_BYTES;
+                self.read_ahead = None;
+                if at_end && start == filled {
+                    let read = self.file.read_at(buf, position)?;
+                    self.position += read as u64;
+                    return Ok(read);
+                }
+                continue;
+            }
+            let read = buf.len().min(filled - start);
+            let ahead = &self.read_ahead.as_ref().unwrap().buf;
+            buf[..read].copy_from_slice(&ahead[start..start + read]);
+            self.position += read as u64;
+            if start + read == filled && filled == READ_AHEAD_BYTES {
+                self.submit_read_ahead(offset + filled as u64)?;
+            }
+            return Ok(read);
+        }
+    }
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        // a seek may follow a truncation of the file, so what was read ahead is not kept
+        self.wait_all()?;
+        self.read_ahead = None;
+        let position = match pos {
+            io::SeekFrom::Start(offset) => Some(offset),
+            io::SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+            io::SeekFrom::End(delta) => self.file.metadata()?.len().checked_add_signed(delta),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")
+        })?;
+        Ok(self.position)
+    }
+    fn sync(&mut self) -> io::Result<()> {
+        // drained, the fsync is only started once the writes submitted before it have completed
+        let sqe = Sqe {
+            opcode: IORING_OP_FSYNC,
+            flags: IOSQE_IO_DRAIN,
+            fd: self.file.as_raw_fd(),
+            user_data: FSYNC,
+            ..Sqe::default()
+        };
+        self.ring.push(sqe);
+        self.wait_all()
+    }
+}
+
+impl io::Write for UringFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.state.get_mut().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.get_mut().wait_all()
+    }
+}
+
+impl io::Read for UringFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.state.get_mut().read(buf)
+    }
+}
+
+impl io::Seek for UringFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.state.get_mut().seek(pos)
+    }
+}
+
+impl Storage for UringFile {
+    fn sync(&self) -> io::Result<()> {
+        self.state.borrow_mut().sync()
+    }
+    fn set_len(&self, len: u64) -> io::Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.wait_all()?;
+        state.file.set_len(len)
+    }
+    fn preallocate(&self, len: u64) -> io::Result<()> {
+        preallocate(&self.state.borrow().file, len)
+    }
+}
+
+impl Drop for UringFile {
+    fn drop(&mut self) {
+        // the kernel may still be reading from (or into) the buffers of the submissions in flight
+        let _ = self.state.get_mut().wait_all();
+    }
+}
+
+/// An io_uring: the submission and completion queues shared with the kernel
+struct Ring {
+    fd: OwnedFd,
+    // the rings are only reached through the pointers into them, but stay mapped until dropped
+    _sq: Mapping,
+    _cq: Mapping,
+    sqes: Mapping,
+    sq_tail: *const AtomicU32,
+    sq_mask: u32,
+    sq_array: *mut u32,
+    cq_head: *const AtomicU32,
+    cq_tail: *const AtomicU32,
+    cq_mask: u32,
+    cqes: *const Cqe,
+    /// submissions pushed but not yet submitted
+    unsubmitted: u32,
+    /// submissions whose completion has not been popped
+    in_flight: u32,
+}
+
+// the ring is only used through &mut self and its mappings belong to it alone
+unsafe impl Send for Ring {}
+
+impl Ring {
+    fn new(entries: u32) -> io::Result<Self> {
+        let mut params = Params::default();
+        let fd = unsafe { libc::syscall(libc::SYS_io_uring_setup, entries, &mut params) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len =
+            params.cq_off.cqes as usize + params.cq_entries as usize * mem::size_of::<Cqe>();
+        let sqes_len = params.sq_entries as usize * mem::size_of::<Sqe>();
+        let sq = Mapping::new(&fd, sq_len, IORING_OFF_SQ_RING)?;
+        let cq = Mapping::new(&fd, cq_len, IORING_OFF_CQ_RING)?;
+        let sqes = Mapping::new(&fd, sqes_len, IORING_OFF_SQES)?;
+        unsafe {
+            Ok(Self {
+                sq_tail: sq.at(params.sq_off.tail),
+                sq_mask: *sq.at::<u32>(params.sq_off.ring_mask),
+                sq_array: sq.at(params.sq_off.array),
+                cq_head: cq.at(params.cq_off.head),
+                cq_tail: cq.at(params.cq_off.tail),
+                cq_mask: *cq.at::<u32>(params.cq_off.ring_mask),
+                cqes: cq.at(params.cq_off.cqes),
+                fd,
+                _sq: sq,
+                _cq: cq,
+                sqes,
+                unsubmitted: 0,
+                in_flight: 0,
+            })
+        }
+    }
+    /// queues the submission, of which there are never more in flight than the ring holds
+    fn push(&mut self, sqe: Sqe) {
+        unsafe {
+            let tail = (*self.sq_tail).load(Ordering::Relaxed);
+            let index = tail & self.sq_mask;
+            ptr::write(self.sqes.at::<Sqe>(0).add(index as usize), sqe);
+            *self.sq_array.add(index as usize) = index;
+            (*self.sq_tail).store(tail.wrapping_add(1), Ordering::Release);
+        }
+        self.unsubmitted += 1;
+        self.in_flight += 1;
+    }
+    /// submits what was pushed, waiting for at least `wait_for` completions
+    fn submit(&mut self, wait_for: u32) -> io::Result<()> {
+        let flags = match wait_for {
+            0 => 0,
+            _ => IORING_ENTER_GETEVENTS,
+        };
+        loop {
+            let submitted = unsafe {
+                libc::syscall(
+                    libc::SYS_io_uring_enter,
+                    self.fd.as_raw_fd(),
+                    self.unsubmitted,
+                    wait_for,
+                    flags,
+                    ptr::null::<libc::sigset_t>(),
+                    0,
+                )
+            };
+            match submitted {
+                submitted if submitted >= 0 => {
+                    self.unsubmitted -= submitted as u32;
+                    return Ok(());
+                }
+                _ => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+    }
+    /// the next completion, if one is available
+    fn pop(&mut self) -> Option<Cqe> {
+        unsafe {
+            let head = (*self.cq_head).load(Ordering::Relaxed);
+            if head == (*self.cq_tail).load(Ordering::Acquire) {
+                return None;
+            }
+            let cqe = ptr::read(self.cqes.add((head & self.cq_mask) as usize));
+            (*self.cq_head).store(head.wrapping_add(1), Ordering::Release);
+            self.in_flight -= 1;
+            Some(cqe)
+        }
+    }
+}
+
+/// memory of a ring mapped from the kernel, unmapped on drop
+struct Mapping {
+    addr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(fd: &OwnedFd, len: usize, offset: libc::off_t) -> io::Result<Self> {
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd.as_raw_fd(),
+                offset,
+            )
+        };
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { addr, len })
+    }
+    /// pointer to the field at the offset of the mapping
+    unsafe fn at<T>(&self, offset: u32) -> *mut T {
+        (self.addr as *mut u8).add(offset as usize) as *mut T
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.addr, self.len);
+        }
+    }
+}
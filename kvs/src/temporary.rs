@@ -0,0 +1,45 @@
+use std::{
+    env, fs, io, path, process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use super::{Error, Result};
+
+/// number of temporary directories created by the process so far
+static CREATED: AtomicU64 = AtomicU64::new(0);
+
+/// Directory of a store made with `KvStore::temporary`, removed with whatever it holds when it is
+/// dropped
+pub(crate) struct TemporaryDir {
+    path: path::PathBuf,
+}
+
+impl TemporaryDir {
+    /// creates a directory of a name no other has under the system's temporary directory
+    pub(crate) fn new() -> Result<Self> {
+        loop {
+            let name = format!(
+                "kvs-{}-{}",
+                process::id(),
+                CREATED.fetch_add(1, Ordering::Relaxed)
+            );
+            let path = env::temp_dir().join(name);
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(Self { path }),
+                // left behind by an earlier process of the same id
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(Error::from(err).at_path(&path)),
+            }
+        }
+    }
+
+    pub(crate) fn path(&self) -> &path::Path {
+        &self.path
+    }
+}
+
+impl Drop for TemporaryDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
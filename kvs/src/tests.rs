@@ -2,7 +2,7 @@
 fn doc_test_package() {
     use crate::KvStore;
 
-    let mut store = KvStore::<String, String>::open(std::path::Path::new("testdb")).unwrap();
+    let mut store = KvStore::<String, String>::temporary().unwrap();
 
     let _ = store.set(String::from("key1"), String::from("value1"));
     let value1 = store.get(String::from("key1")).unwrap();
@@ -15,3 +15,197 @@ fn doc_test_package() {
     let value1 = store.get(String::from("key1")).unwrap();
     assert_eq!(value1, None);
 }
+
+fn open_faulty(
+    temp_dir: &tempfile::TempDir,
+) -> (
+    crate::KvStore<String, String>,
+    crate::storage::FaultyStorage,
+) {
+    let storage = crate::storage::FaultyStorage::default();
+    let store = crate::KvStore::open_with_storage(
+        temp_dir.path(),
+        &crate::StoreOptions::default(),
+        std::sync::Arc::new(storage.clone()),
+    )
+    .unwrap();
+    (store, storage)
+}
+
+#[test]
+fn partial_writes_are_completed() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let (mut store, storage) = open_faulty(&temp_dir);
+    storage.faults.lock().unwrap().partial_writes = true;
+    for i in 0..10 {
+        store
+            .set(format!("key{}", i), format!("value{}", i))
+            .unwrap();
+    }
+    store.remove("key0".to_owned()).unwrap();
+    store.compact_now().unwrap();
+    drop(store);
+
+    let mut store = crate::KvStore::<String, String>::open(temp_dir.path()).unwrap();
+    assert_eq!(store.len(), 9);
+    assert_eq!(store.get("key9".to_owned()).unwrap(), Some("value9".into()));
+}
+
+#[test]
+fn failed_write_when_out_of_space_is_not_applied() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let (mut store, storage) = open_faulty(&temp_dir);
+    store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    storage.faults.lock().unwrap().space_left = Some(4);
+    let err = store
+        .set("key2".to_owned(), "value2".to_owned())
+        .unwrap_err();
+    assert_eq!(*err.kind(), crate::ErrorKind::IoError);
+    assert_eq!(store.get("key1".to_owned()).unwrap(), Some("value1".into()));
+    assert_eq!(store.get("key2".to_owned()).unwrap(), None);
+    storage.faults.lock().unwrap().space_left = None;
+    store.set("key3".to_owned(), "value3".to_owned()).unwrap();
+    drop(store);
+
+    let mut store = crate::KvStore::<String, String>::open(temp_dir.path()).unwrap();
+    assert_eq!(store.get("key1".to_owned()).unwrap(), Some("value1".into()));
+    assert_eq!(store.get("key2".to_owned()).unwrap(), None);
+    assert_eq!(store.get("key3".to_owned()).unwrap(), Some("value3".into()));
+}
+
+#[test]
+fn compaction_out_of_space_leaves_log_in_place() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let (mut store, storage) = open_faulty(&temp_dir);
+    store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    store.set("key1".to_owned(), "value2".to_owned()).unwrap();
+    storage.faults.lock().unwrap().space_left = Some(0);
+    let err = store.compact_now().unwrap_err();
+    assert_eq!(*err.kind(), crate::ErrorKind::CompactionFailed);
+    assert_eq!(store.get("key1".to_owned()).unwrap(), Some("value2".into()));
+    let logs = std::fs::read_dir(temp_dir.path())
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().file_name() != "kvsdb.manifest")
+        .count();
+    assert_eq!(logs, 1);
+}
+
+#[test]
+fn failed_fsync_is_reported_and_shutdown_is_not_clean() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let (mut store, storage) = open_faulty(&temp_dir);
+    store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    storage.faults.lock().unwrap().fail_sync = true;
+    assert_eq!(*store.sync().unwrap_err().kind(), crate::ErrorKind::IoError);
+    assert!(store.shutdown().is_err());
+    drop(store);
+
+    let mut store = crate::KvStore::<String, String>::open(temp_dir.path()).unwrap();
+    assert!(!store.was_shut_down_cleanly());
+    assert_eq!(store.get("key1".to_owned()).unwrap(), Some("value1".into()));
+}
+
+#[test]
+fn writes_are_held_back_while_compaction_fails_over_the_stale_ceiling() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let storage = crate::storage::FaultyStorage::default();
+    let options = crate::StoreOptions {
+        max_stale_ratio: Some(2.0),
+        backpressure: crate::Backpressure::Sleep(std::time::Duration::from_millis(1)),
+        ..crate::StoreOptions::default()
+    };
+    let mut store = crate::KvStore::<String, String>::open_with_storage(
+        temp_dir.path(),
+        &options,
+        std::sync::Arc::new(storage.clone()),
+    )
+    .unwrap();
+    for i in 0..4 {
+        store.set("key1".to_owned(), format!("value{}", i)).unwrap();
+    }
+    storage.faults.lock().unwrap().space_left = Some(0);
+    let err = store
+        .set("key1".to_owned(), "value4".to_owned())
+        .unwrap_err();
+    assert_eq!(*err.kind(), crate::ErrorKind::Busy);
+    assert_eq!(store.get("key1".to_owned()).unwrap(), Some("value3".into()));
+
+    // the store holds too few keys for compaction to be triggered, but the ceiling compacts it
+    storage.faults.lock().unwrap().space_left = None;
+    store.set("key1".to_owned(), "value4".to_owned()).unwrap();
+    assert_eq!(store.stale_count, 1);
+    assert_eq!(store.get("key1".to_owned()).unwrap(), Some("value4".into()));
+}
+
+#[test]
+fn records_are_encoded_as_the_tuple_of_their_fields() {
+    use crate::{codec, Record, RecordFormat};
+
+    let rec = Record {
+        db_key: 42,
+        key: "key1".to_owned(),
+        value: Some("value1".to_owned()),
+        merge: false,
+        previous: Some(7),
+        version: 3,
+        timestamp: 1_000,
+    };
+    for format in [RecordFormat::Compact, RecordFormat::WithOffsets] {
+        let mut buf = Vec::new();
+        let (key, value) = codec::encode_record(&rec, format, &mut buf).unwrap();
+        let mut tuple = Vec::new();
+        let fields = (
+            &rec.key,
+            &rec.value,
+            rec.merge,
+            rec.previous,
+            rec.version,
+            rec.timestamp,
+        );
+        match format {
+            RecordFormat::Compact => codec::encode(&fields, &mut tuple),
+            RecordFormat::WithOffsets => codec::encode(
+                &(
+                    rec.db_key, fields.0, fields.1, fields.2, fields.3, fields.4, fields.5,
+                ),
+                &mut tuple,
+            ),
+        }
+        .unwrap();
+        // a record of the compact format is preceded by the CRC-32 of the tuple
+        if format == RecordFormat::Compact {
+            let checksum = format!("*{:08x}\n", crc32fast::hash(&tuple));
+            tuple.splice(0..0, checksum.into_bytes());
+        }
+        assert_eq!(buf, tuple);
+        assert_eq!(codec::decode::<String>(&buf[key]).unwrap(), "key1");
+        assert_eq!(codec::decode::<String>(&buf[value]).unwrap(), "value1");
+    }
+}
+
+#[test]
+fn subscribers_falling_behind_are_dropped_rather_than_held_events_without_limit() {
+    use crate::{notify::SUBSCRIBER_BACKLOG, KvsEngine, ShardedKvStore};
+    use std::sync::mpsc::TryRecvError;
+
+    let mut store = crate::KvStore::<String, String>::temporary().unwrap();
+    let events = store.subscribe(|_: &String| true);
+    for key in 0..SUBSCRIBER_BACKLOG + 100 {
+        store.set(key.to_string(), "value".to_owned()).unwrap();
+    }
+    assert_eq!(events.try_iter().count(), SUBSCRIBER_BACKLOG);
+    assert_eq!(events.try_recv(), Err(TryRecvError::Disconnected));
+
+    // the partitions of a sharded store share the backlog, and each drops the subscriber
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let store = ShardedKvStore::open(temp_dir.path()).unwrap();
+    let events = store.subscribe(String::new()).unwrap();
+    for key in 0..SUBSCRIBER_BACKLOG + 100 {
+        store.set(key.to_string(), "value".to_owned()).unwrap();
+    }
+    assert_eq!(events.try_iter().count(), SUBSCRIBER_BACKLOG);
+    for key in 0..100 {
+        store.set(key.to_string(), "value".to_owned()).unwrap();
+    }
+    assert_eq!(events.try_recv(), Err(TryRecvError::Disconnected));
+}
@@ -0,0 +1,138 @@
+use std::{convert::TryFrom, net, ops, path, sync::Arc};
+
+use rustls::{
+    crypto::ring,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName},
+};
+
+use super::{Error, ErrorKind, Result};
+
+/// TLS configuration for a KvsServer: the certificate chain and private key it presents
+///
+/// # Example
+/// ```no_run
+/// use kvs::{KvsServer, ServerTlsConfig, SharedKvStore};
+///
+/// let tls = ServerTlsConfig::from_pem_files(
+///     std::path::Path::new("server.crt"),
+///     std::path::Path::new("server.key"),
+/// )
+/// .unwrap();
+/// let engine = SharedKvStore::open(std::path::Path::new("testdb")).unwrap();
+/// KvsServer::new(engine).with_tls(tls).run("127.0.0.1:4000").unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ServerTlsConfig {
+    config: Arc<rustls::ServerConfig>,
+}
+
+impl ServerTlsConfig {
+    /// load the PEM encoded certificate chain (leaf first) and private key from the given files
+    pub fn from_pem_files(cert_path: &path::Path, key_path: &path::Path) -> Result<Self> {
+        let certs = CertificateDer::pem_file_iter(cert_path)
+            .and_then(|certs| certs.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|_| Error::new(ErrorKind::InvalidConfiguration))?;
+        let key = PrivateKeyDer::from_pem_file(key_path)
+            .map_err(|_| Error::new(ErrorKind::InvalidConfiguration))?;
+        let config =
+            rustls::ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(tls_error)?
+                .with_no_client_auth()
+                .with_single_cert(certs, key)
+                .map_err(|_| Error::new(ErrorKind::InvalidConfiguration))?;
+        Ok(Self::new(Arc::new(config)))
+    }
+    /// use an already built rustls configuration
+    pub fn new(config: Arc<rustls::ServerConfig>) -> Self {
+        Self { config }
+    }
+
+    pub(crate) fn accept(
+        &self,
+        stream: net::TcpStream,
+    ) -> Result<rustls::StreamOwned<rustls::ServerConnection, net::TcpStream>> {
+        let connection = rustls::ServerConnection::new(self.config.clone()).map_err(tls_error)?;
+        handshake(connection, stream)
+    }
+}
+
+/// TLS configuration for a KvsClient: the certificates trusted to sign the server's certificate
+/// and the name the server's certificate must be valid for
+///
+/// # Example
+/// ```no_run
+/// use kvs::{ClientTlsConfig, KvsClient};
+///
+/// let tls = ClientTlsConfig::from_pem_file(std::path::Path::new("ca.crt"), "localhost").unwrap();
+/// let mut client = KvsClient::connect_tls("127.0.0.1:4000", &tls).unwrap();
+/// client.set("key1".into(), "value1".into()).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ClientTlsConfig {
+    config: Arc<rustls::ClientConfig>,
+    server_name: ServerName<'static>,
+}
+
+impl ClientTlsConfig {
+    /// trust the PEM encoded CA certificates in the given file when verifying the server named
+    /// `server_name` (a DNS name or IP address)
+    pub fn from_pem_file(ca_cert_path: &path::Path, server_name: &str) -> Result<Self> {
+        let mut roots = rustls::RootCertStore::empty();
+        let certs = CertificateDer::pem_file_iter(ca_cert_path)
+            .map_err(|_| Error::new(ErrorKind::InvalidConfiguration))?;
+        for cert in certs {
+            roots
+                .add(cert.map_err(|_| Error::new(ErrorKind::InvalidConfiguration))?)
+                .map_err(|_| Error::new(ErrorKind::InvalidConfiguration))?;
+        }
+        let config =
+            rustls::ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(tls_error)?
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+        Self::new(Arc::new(config), server_name)
+    }
+    /// use an already built rustls configuration to verify the server named `server_name`
+    pub fn new(config: Arc<rustls::ClientConfig>, server_name: &str) -> Result<Self> {
+        let server_name = ServerName::try_from(server_name.to_owned())
+            .map_err(|_| Error::new(ErrorKind::InvalidConfiguration))?;
+        Ok(Self {
+            config,
+            server_name,
+        })
+    }
+
+    pub(crate) fn connect(
+        &self,
+        stream: net::TcpStream,
+    ) -> Result<rustls::StreamOwned<rustls::ClientConnection, net::TcpStream>> {
+        let connection =
+            rustls::ClientConnection::new(self.config.clone(), self.server_name.clone())
+                .map_err(tls_error)?;
+        handshake(connection, stream)
+    }
+}
+
+/// completes the handshake up front so that a failure to negotiate is reported as a TlsError
+/// rather than as an I/O error on the first request
+fn handshake<C, D>(
+    mut connection: C,
+    mut stream: net::TcpStream,
+) -> Result<rustls::StreamOwned<C, net::TcpStream>>
+where
+    C: ops::DerefMut<Target = rustls::ConnectionCommon<D>>,
+    D: rustls::SideData,
+{
+    while connection.is_handshaking() {
+        connection
+            .complete_io(&mut stream)
+            .map_err(|_| Error::new(ErrorKind::TlsError))?;
+    }
+    Ok(rustls::StreamOwned::new(connection, stream))
+}
+
+fn tls_error(_: rustls::Error) -> Error {
+    Error::new(ErrorKind::TlsError)
+}
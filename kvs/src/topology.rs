@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+
+/// Layout of a cluster of servers: its nodes, and the shards (ranges of keys) each serves
+///
+/// A KvsServer configured `with_cluster` answers a request for a key of a shard it does not hold
+/// with the address of a node which does, and a ClusterClient routes each request to the node
+/// holding its key. The topology can be read from JSON (as `kvs-server --cluster-topology` does):
+///
+/// ```
+/// let topology: kvs::Topology = serde_json::from_str(
+///     r#"{
+///         "epoch": 1,
+///         "nodes": [{"id": 1, "addr": "10.0.0.1:4000"}, {"id": 2, "addr": "10.0.0.2:4000"}],
+///         "shards": [
+///             {"start": "", "end": "m", "nodes": [1]},
+///             {"start": "m", "nodes": [2]}
+///         ]
+///     }"#,
+/// )
+/// .unwrap();
+/// assert_eq!(topology.route("kiwi"), Some("10.0.0.1:4000"));
+/// assert_eq!(topology.route("mango"), Some("10.0.0.2:4000"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Topology {
+    /// version of the topology, increased on every change so that the newer of two is known
+    pub epoch: u64,
+    /// every node of the cluster
+    pub nodes: Vec<ClusterNode>,
+    /// the shards, which together should cover every key once
+    pub shards: Vec<Shard>,
+}
+
+/// node of a cluster
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClusterNode {
+    /// id of the node (the id of its Raft node, if its engine is a RaftEngine)
+    pub id: u64,
+    /// address on which the node serves clients
+    pub addr: String,
+}
+
+/// range of keys held by some of the nodes of a cluster
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shard {
+    /// first key of the range
+    pub start: String,
+    /// key following the last of the range, None if the range runs to the end of the keys
+    pub end: Option<String>,
+    /// ids of the nodes holding the keys of the range (the replicas of a Raft cluster)
+    pub nodes: Vec<u64>,
+    /// id of the node of the shard accepting writes, if known (filled in by each node for the
+    /// shards it holds when it is asked for the topology)
+    pub leader: Option<u64>,
+}
+
+impl Topology {
+    /// topology of a cluster whose nodes each hold every key
+    pub fn single_shard(nodes: Vec<ClusterNode>) -> Self {
+        let shard = Shard {
+            start: String::new(),
+            end: None,
+            nodes: nodes.iter().map(|node| node.id).collect(),
+            leader: None,
+        };
+        Self {
+            epoch: 0,
+            nodes,
+            shards: vec![shard],
+        }
+    }
+    /// the shard holding the key, if any
+    pub fn shard_of(&self, key: &str) -> Option<&Shard> {
+        self.shards.iter().find(|shard| shard.contains(key))
+    }
+    /// address of the node of the given id, if it is a node of the cluster
+    pub fn node_addr(&self, id: u64) -> Option<&str> {
+        self.nodes
+            .iter()
+            .find(|node| node.id == id)
+            .map(|node| node.addr.as_str())
+    }
+    /// address of the node a request for the key is sent to: the leader of its shard if known,
+    /// else the first node of the shard
+    pub fn route(&self, key: &str) -> Option<&str> {
+        let shard = self.shard_of(key)?;
+        let id = shard.leader.or_else(|| shard.nodes.first().copied())?;
+        self.node_addr(id)
+    }
+}
+
+/// progress of the migration of a range of keys from one node of a cluster to another, from
+/// `KvsClient::migration_status`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MigrationStatus {
+    /// first key of the range
+    pub start: String,
+    /// key following the last of the range, None if the range runs to the end of the keys
+    pub end: Option<String>,
+    /// address of the node the keys are migrating to
+    pub target: String,
+    /// number of keys copied to the target so far (counting each copy of a key changed since it
+    /// was last copied)
+    pub copied: u64,
+    /// true once every key present when the migration started has been copied, each write to
+    /// the range being copied as it is made from then on
+    pub streaming: bool,
+    /// true once the migration has been cut over, the target holding the range
+    pub finished: bool,
+    /// description of the failure, if the migration failed
+    pub error: Option<String>,
+}
+
+impl Shard {
+    /// true if the key is in the range of the shard
+    pub fn contains(&self, key: &str) -> bool {
+        range_contains(&self.start, self.end.as_deref(), key)
+    }
+}
+
+/// true if the key is in the range from `start` up to (but excluding) `end`
+pub(crate) fn range_contains(start: &str, end: Option<&str>, key: &str) -> bool {
+    start <= key && end.is_none_or(|end| key < end)
+}
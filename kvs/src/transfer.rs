@@ -0,0 +1,168 @@
+use serde::{Deserialize, Serialize};
+
+/// most bytes of keys and values sent in one chunk of a snapshot, unless configured otherwise
+pub(crate) const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// Chunk of a consistent snapshot of every key and value of a store, streamed in order from the
+/// node holding the snapshot to a new replica (by a Raft leader) or a backup (by
+/// `KvsClient::backup`)
+///
+/// Each chunk carries a checksum of its pairs, so that a chunk corrupted on the way is requested
+/// again, the digest of the whole snapshot, checked once every chunk is received, and the offset of its first pair among the pairs of the snapshot, so that a transfer
+/// cut short is resumed from the first pair not received, for as long as the sender holds the
+/// snapshot of that id.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotChunk {
+    /// id of the snapshot the chunk is part of
+    pub snapshot_id: u64,
+    /// position of the first pair of the chunk among the pairs of the snapshot
+    pub offset: u64,
+    /// number of pairs in the whole snapshot
+    pub total: u64,
+    /// the keys and values of the chunk, in the order of the snapshot
+    pub pairs: Vec<(String, String)>,
+    /// CRC-32 of the length-prefixed keys and values of the pairs
+    pub checksum: u32,
+    /// CRC-32 of the length-prefixed keys and values of every pair of the snapshot, in order
+    pub digest: u32,
+}
+
+impl SnapshotChunk {
+    /// true if the checksum matches the pairs
+    pub fn is_intact(&self) -> bool {
+        checksum(&self.pairs) == self.checksum
+    }
+    /// true if the chunk holds the last pair of the snapshot
+    pub fn is_last(&self) -> bool {
+        self.offset + self.pairs.len() as u64 >= self.total
+    }
+}
+
+/// every pair of a store as of one moment, kept by the node sending it so that each chunk comes
+/// from the same snapshot however long the transfer takes
+pub(crate) struct Snapshot {
+    id: u64,
+    pairs: Vec<(String, String)>,
+    digest: u32,
+}
+
+impl Snapshot {
+    pub(crate) fn new(id: u64, pairs: Vec<(String, String)>) -> Self {
+        let digest = checksum(&pairs);
+        Self { id, pairs, digest }
+    }
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+    /// the chunk starting at the pair at the offset, holding the pairs which fit in `max_bytes`
+    /// bytes of keys and values (but at least one, unless the offset is past the last pair)
+    pub(crate) fn chunk(&self, offset: u64, max_bytes: usize) -> SnapshotChunk {
+        let start = (offset as usize).min(self.pairs.len());
+        let mut end = start;
+        let mut size = 0;
+        while let Some((key, value)) = self.pairs.get(end) {
+            size += key.len() + value.len();
+            if size > max_bytes && end > start {
+                break;
+            }
+            end += 1;
+        }
+        let pairs = self.pairs[start..end].to_vec();
+        SnapshotChunk {
+            snapshot_id: self.id,
+            offset: start as u64,
+            total: self.pairs.len() as u64,
+            checksum: checksum(&pairs),
+            digest: self.digest,
+            pairs,
+        }
+    }
+}
+
+/// the pairs of a snapshot received so far, in order
+pub(crate) struct SnapshotReceiver {
+    snapshot_id: u64,
+    /// number of pairs in the snapshot, known once its first chunk is received
+    total: Option<u64>,
+    /// digest of the snapshot, known once its first chunk is received
+    digest: Option<u32>,
+    pairs: Vec<(String, String)>,
+    /// digest of the pairs received so far
+    received: Digest,
+}
+
+impl SnapshotReceiver {
+    pub(crate) fn new(snapshot_id: u64) -> Self {
+        Self {
+            snapshot_id,
+            total: None,
+            digest: None,
+            pairs: Vec::new(),
+            received: Digest::new(),
+        }
+    }
+    pub(crate) fn snapshot_id(&self) -> u64 {
+        self.snapshot_id
+    }
+    /// offset of the next pair to receive
+    pub(crate) fn next_offset(&self) -> u64 {
+        self.pairs.len() as u64
+    }
+    /// adds the pairs of the chunk if it is intact, of this snapshot and starts at the next
+    /// offset, returning whether it did
+    pub(crate) fn receive(&mut self, chunk: SnapshotChunk) -> bool {
+        if chunk.snapshot_id != self.snapshot_id
+            || chunk.offset != self.next_offset()
+            || !chunk.is_intact()
+        {
+            return false;
+        }
+        self.total = Some(chunk.total);
+        self.digest = Some(chunk.digest);
+        self.received.update(&chunk.pairs);
+        self.pairs.extend(chunk.pairs);
+        true
+    }
+    /// true once every pair of the snapshot has been received
+    pub(crate) fn is_complete(&self) -> bool {
+        self.total.is_some_and(|total| self.next_offset() >= total)
+    }
+    /// true if the pairs received match the digest of the snapshot (so once it is complete, if
+    /// the snapshot was received intact)
+    pub(crate) fn is_intact(&self) -> bool {
+        self.digest == Some(self.received.value())
+    }
+    pub(crate) fn into_pairs(self) -> Vec<(String, String)> {
+        self.pairs
+    }
+}
+
+/// CRC-32 of the length-prefixed keys and values of a run of pairs, updated as each chunk of them
+/// is received, so that the digest of a snapshot is checked without holding its pairs twice
+#[derive(Clone, Default)]
+pub(crate) struct Digest {
+    hasher: crc32fast::Hasher,
+}
+
+impl Digest {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+    pub(crate) fn update(&mut self, pairs: &[(String, String)]) {
+        for (key, value) in pairs {
+            self.hasher.update(&(key.len() as u64).to_be_bytes());
+            self.hasher.update(key.as_bytes());
+            self.hasher.update(&(value.len() as u64).to_be_bytes());
+            self.hasher.update(value.as_bytes());
+        }
+    }
+    pub(crate) fn value(&self) -> u32 {
+        self.hasher.clone().finalize()
+    }
+}
+
+fn checksum(pairs: &[(String, String)]) -> u32 {
+    let mut digest = Digest::new();
+    digest.update(pairs);
+    digest.value()
+}
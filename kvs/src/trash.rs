@@ -0,0 +1,164 @@
+use std::{collections::HashMap, fmt, hash, io::Seek};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{
+    clock::millis_since_epoch, read_record_from, resolve_value, resolve_versioned_value,
+    write_record_to_writer, writer_position, Error, ErrorKind, KvStore, LogWriter, Record, Result,
+};
+
+/// Removed key held in the trash of a KvStore: where its removal, and the value it held, were
+/// written, and when it was removed
+///
+/// The tombstone of a key moved to the trash points back at its value (as a merge operand points
+/// at the record before it), so the trash is rebuilt from the log as the index is.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct Trashed {
+    /// offset of the tombstone of the key
+    tombstone: u64,
+    /// offset of the record of the value the key held (the latest, if merge operands)
+    value: u64,
+    /// time of the removal in milliseconds since the Unix epoch
+    removed: u64,
+}
+
+impl Trashed {
+    /// the trashed key the record is the tombstone of, None unless it points back at a value
+    pub(crate) fn of<K, V>(rec: &Record<K, V>) -> Option<Self> {
+        match (&rec.value, rec.previous) {
+            (None, Some(value)) => Some(Self {
+                tombstone: rec.db_key,
+                value,
+                removed: rec.timestamp,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl<K, V> KvStore<K, V>
+where
+    K: Serialize + DeserializeOwned + Eq + PartialEq + hash::Hash + Clone + fmt::Debug,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    /// the keys removed within the trash retention of the store (and not set since), whose values
+    /// may still be read with `get_trashed` and brought back with `restore_key` (in no particular
+    /// order)
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::{KvStore, StoreOptions};
+    /// use std::time::Duration;
+    ///
+    /// let dir = tempfile::TempDir::new().unwrap();
+    /// let options = StoreOptions {
+    ///     trash_retention: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+    ///     ..StoreOptions::default()
+    /// };
+    /// let mut store = KvStore::<String,String>::open_with_options(dir.path(), &options).unwrap();
+    /// store.set("key1".into(),"value1".into()).unwrap();
+    /// store.remove("key1".into()).unwrap();
+    /// assert_eq!(store.trashed_keys().collect::<Vec<_>>(), vec!["key1"]);
+    /// assert_eq!(store.get_trashed("key1".into()).unwrap(), Some("value1".into()));
+    /// store.restore_key("key1".into()).unwrap();
+    /// assert_eq!(store.get("key1".into()).unwrap(), Some("value1".into()));
+    /// ```
+    pub fn trashed_keys(&self) -> impl Iterator<Item = &K> {
+        let now = millis_since_epoch(self.clock.now());
+        self.trash
+            .iter()
+            .filter(move |(key, trashed)| self.is_retained(key, trashed, now))
+            .map(|(key, _)| key)
+    }
+    /// the value the key held when it was removed, None unless it is in the trash
+    pub fn get_trashed(&mut self, key: K) -> Result<Option<V>> {
+        match self.retained_value_of(&key) {
+            Some(db_key) => {
+                resolve_value(&mut self.reader, self.merge_operator.as_ref(), &key, db_key)
+            }
+            None => Ok(None),
+        }
+    }
+    /// set the key back to the value it held when it was removed, taking it out of the trash,
+    /// KeyNotPresent unless it is in the trash
+    pub fn restore_key(&mut self, key: K) -> Result<()> {
+        let value = match self.get_trashed(key.clone())? {
+            Some(value) => value,
+            None => return Err(Error::new(ErrorKind::KeyNotPresent).for_key(&key)),
+        };
+        self.set(key.clone(), value)?;
+        self.trash.remove(&key);
+        Ok(())
+    }
+
+    /// the offset of the value of the key if it is in the trash
+    fn retained_value_of(&self, key: &K) -> Option<u64> {
+        let now = millis_since_epoch(self.clock.now());
+        self.trash
+            .get(key)
+            .filter(|trashed| self.is_retained(key, trashed, now))
+            .map(|trashed| trashed.value)
+    }
+    /// whether the removed key is still in the trash at the given time (in milliseconds since the
+    /// Unix epoch): it has been neither set since nor held for longer than the retention
+    fn is_retained(&self, key: &K, trashed: &Trashed, now: u64) -> bool {
+        !self.index.contains_key(key)
+            && self.trash_retention.is_some_and(|retention| {
+                trashed.removed.saturating_add(retention.as_millis() as u64) > now
+            })
+    }
+    /// copies the value and tombstone of each key in the trash to the compacted log, returning
+    /// the trash of the compacted log; those which have outlived the retention are dropped for
+    /// good
+    ///
+    /// Called once the log has been scanned, so the store's reader is free to use.
+    pub(crate) fn copy_trash_to(&mut self, writer: &mut LogWriter) -> Result<HashMap<K, Trashed>> {
+        let now = millis_since_epoch(self.clock.now());
+        let retained = self
+            .trash
+            .iter()
+            .filter(|(key, trashed)| self.is_retained(key, trashed, now))
+            .map(|(key, trashed)| (key.clone(), *trashed))
+            .collect::<Vec<_>>();
+        let mut compacted_trash = HashMap::new();
+        for (key, trashed) in &retained {
+            // merge operands are resolved into the value, as they are for the keys held
+            let (value, version) = resolve_versioned_value(
+                &mut self.reader,
+                self.merge_operator.as_ref(),
+                key,
+                trashed.value,
+            )?;
+            let value_db_key = writer_position(writer)?;
+            let rec = Record {
+                db_key: value_db_key,
+                key,
+                value: value.as_ref(),
+                merge: false,
+                previous: None,
+                version: version.sequence(),
+                timestamp: millis_since_epoch(version.timestamp()),
+            };
+            write_record_to_writer(rec, self.record_format, &mut self.scratch, writer)?;
+            self.reader
+                .seek(std::io::SeekFrom::Start(trashed.tombstone))?;
+            let mut offset = trashed.tombstone;
+            let mut tombstone = match read_record_from::<_, K, V>(&mut self.reader, &mut offset)? {
+                Some(rec) if rec.db_key == trashed.tombstone && rec.key == *key => rec,
+                _ => {
+                    return Err(Error::new(ErrorKind::IndexInconsistent {
+                        offset: trashed.tombstone,
+                    })
+                    .at_offset(trashed.tombstone)
+                    .for_key(key))
+                }
+            };
+            tombstone.db_key = writer_position(writer)?;
+            tombstone.previous = Some(value_db_key);
+            let trashed = Trashed::of(&tombstone).unwrap();
+            write_record_to_writer(tombstone, self.record_format, &mut self.scratch, writer)?;
+            compacted_trash.insert(key.clone(), trashed);
+        }
+        Ok(compacted_trash)
+    }
+}
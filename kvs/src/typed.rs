@@ -0,0 +1,105 @@
+use std::marker::PhantomData;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{Error, ErrorKind, KvStore, Result};
+
+/// Values of one type, held as JSON under the keys of a KvStore starting with a prefix, from
+/// `KvStore::typed`
+///
+/// Keys are given without the prefix, so that several kinds of value can share a store, each
+/// under its own prefix:
+///
+/// ```
+/// use kvs::KvStore;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// struct UserProfile {
+///     name: String,
+///     age: u32,
+/// }
+///
+/// let mut store = KvStore::<String,String>::temporary().unwrap();
+/// let alice = UserProfile { name: "Alice".into(), age: 30 };
+/// store.typed::<UserProfile>("user:").set("1", &alice).unwrap();
+/// assert_eq!(store.get("user:1".into()).unwrap(), Some(r#"{"name":"Alice","age":30}"#.into()));
+/// assert_eq!(store.typed::<UserProfile>("user:").get("1").unwrap(), Some(alice));
+/// ```
+pub struct TypedStore<'a, T> {
+    store: &'a mut KvStore<String, String>,
+    prefix: String,
+    value_type: PhantomData<fn() -> T>,
+}
+
+impl KvStore<String, String> {
+    /// the values of type T held under the keys starting with the prefix
+    pub fn typed<T>(&mut self, prefix: &str) -> TypedStore<'_, T>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        TypedStore {
+            store: self,
+            prefix: prefix.to_owned(),
+            value_type: PhantomData,
+        }
+    }
+}
+
+impl<T> TypedStore<'_, T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// the prefix of the keys of the values
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+    /// get the value of the key (without the prefix), None if no such key, DeserializationError
+    /// if the value held is not a T
+    pub fn get(&mut self, key: &str) -> Result<Option<T>> {
+        let full_key = self.full_key(key);
+        match self.store.get(full_key.clone())? {
+            Some(json) => decode(&json)
+                .map(Some)
+                .map_err(|err| err.for_key(&full_key)),
+            None => Ok(None),
+        }
+    }
+    /// set the key (without the prefix) to the value
+    pub fn set(&mut self, key: &str, value: &T) -> Result<()> {
+        let json = serde_json::to_string(value)
+            .map_err(|err| Error::with_source(ErrorKind::SerializationError, err))?;
+        self.store.set(self.full_key(key), json)
+    }
+    /// remove the key (without the prefix), KeyNotPresent if no such key
+    pub fn remove(&mut self, key: &str) -> Result<()> {
+        self.store.remove(self.full_key(key))
+    }
+    /// every key (without the prefix) starting with the prefix and its value, in key order
+    pub fn scan(&mut self) -> Result<Vec<(String, T)>> {
+        let mut keys = self
+            .store
+            .keys()
+            .filter(|key| key.starts_with(&self.prefix))
+            .cloned()
+            .collect::<Vec<_>>();
+        keys.sort();
+        let mut found = Vec::with_capacity(keys.len());
+        for full_key in keys {
+            if let Some(json) = self.store.get(full_key.clone())? {
+                let value = decode(&json).map_err(|err| err.for_key(&full_key))?;
+                found.push((full_key[self.prefix.len()..].to_owned(), value));
+            }
+        }
+        Ok(found)
+    }
+
+    fn full_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+}
+
+fn decode<T: DeserializeOwned>(json: &str) -> Result<T> {
+    serde_json::from_str(json)
+        .map_err(|err| Error::with_source(ErrorKind::DeserializationError, err))
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::HashSet,
+    fmt, fs, hash, io,
+    path::{self, Path},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    latest_log_for_dir,
+    manifest::{self, Digest},
+    read_record_from, sync_dir_of, Error, ErrorKind, KvStore, Record, Result, DEFAULT_FILE_PREFIX,
+};
+
+/// Result of verifying the log of a KvStore
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifyReport {
+    /// log which was verified (None if the directory holds no log)
+    pub log_path: Option<path::PathBuf>,
+    /// number of valid records read from the log
+    pub records: u64,
+    /// number of those records holding the current value of a key
+    pub live_records: u64,
+    /// offset of the first record which could not be read, whose checksum does not match it, or
+    /// whose position does not match the offset recorded in it (None if every record is valid)
+    pub corruption_offset: Option<u64>,
+    /// true if the log was truncated at the corruption offset
+    pub repaired: bool,
+    /// number of closed logs (those of segments shut down cleanly) checked against the digest
+    /// recorded when they were closed
+    pub digests_checked: u64,
+    /// closed logs whose contents no longer match their digest, having changed since they were
+    /// closed (through bit rot, say)
+    pub digest_mismatches: Vec<path::PathBuf>,
+}
+
+impl VerifyReport {
+    /// number of valid records which are overwritten values, removed keys or removals
+    pub fn stale_records(&self) -> u64 {
+        self.records - self.live_records
+    }
+    /// fraction of the valid records which are stale (0 for an empty log)
+    pub fn stale_fraction(&self) -> f64 {
+        match self.records {
+            0 => 0.0,
+            records => self.stale_records() as f64 / records as f64,
+        }
+    }
+}
+
+impl<K, V> KvStore<K, V>
+where
+    K: Serialize + DeserializeOwned + Eq + PartialEq + hash::Hash + Clone + fmt::Debug,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    /// check the structure of every record in the log of the store at `path` (which must not be
+    /// open, and must use the default file prefix), reporting how many are stale and the offset of
+    /// the first corrupt one, and with `repair` truncating the log there so the valid records
+    /// before it can be opened safely
+    ///
+    /// Records of the compact format are checked against their checksum, and those with offsets
+    /// against the offset recorded in them.
+    ///
+    /// Every closed log listed by the store's manifest, which records the digest of each log when
+    /// the store is shut down, is also checked against its digest.
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let dir = tempfile::TempDir::new().unwrap();
+    /// drop(KvStore::<String,String>::open(dir.path()).unwrap());
+    /// let report = KvStore::<String,String>::verify(dir.path(), false).unwrap();
+    /// assert!(report.log_path.is_some());
+    /// ```
+    pub fn verify(path: &Path, repair: bool) -> Result<VerifyReport> {
+        let log_path = match latest_log_for_dir(path, DEFAULT_FILE_PREFIX)? {
+            Some(log_path) => log_path,
+            None => {
+                return Ok(VerifyReport {
+                    log_path: None,
+                    records: 0,
+                    live_records: 0,
+                    corruption_offset: None,
+                    repaired: false,
+                    digests_checked: 0,
+                    digest_mismatches: Vec::new(),
+                })
+            }
+        };
+        let len = fs::metadata(&log_path)?.len();
+        let mut reader = io::BufReader::new(fs::File::open(&log_path)?);
+        let mut live_keys = HashSet::new();
+        let mut records = 0;
+        let (mut corruption_offset, mut next_offset) = (None, 0);
+        loop {
+            let offset = next_offset;
+            if offset >= len {
+                break;
+            }
+            match read_record_from::<_, K, V>(&mut reader, &mut next_offset) {
+                Ok(Some(Record {
+                    db_key, key, value, ..
+                })) if db_key == offset => {
+                    records += 1;
+                    match value {
+                        Some(_) => live_keys.insert(key),
+                        None => live_keys.remove(&key),
+                    };
+                }
+                // records of a format this build cannot read are not corrupt, so are not repaired
+                Err(err) if *err.kind() == ErrorKind::UnsupportedFormat => {
+                    return Err(err.at_path(&log_path))
+                }
+                _ => {
+                    corruption_offset = Some(offset);
+                    break;
+                }
+            }
+        }
+        let mut manifest = manifest::read_manifest(path, DEFAULT_FILE_PREFIX)?;
+        let mut digests_checked = 0;
+        let mut digest_mismatches = Vec::new();
+        let closed = manifest.iter().flat_map(|manifest| &manifest.segments);
+        for segment in closed.filter(|segment| segment.digest.is_some()) {
+            let segment_path = path.join(&segment.log);
+            let digest = Digest::of_file(&segment_path)
+                .map_err(|err| Error::from(err).at_path(&segment_path))?;
+            digests_checked += 1;
+            if segment.digest != Some(digest) {
+                digest_mismatches.push(segment_path);
+            }
+        }
+        let repaired = match (corruption_offset, repair) {
+            (Some(offset), true) => {
+                let file = fs::OpenOptions::new().write(true).open(&log_path)?;
+                file.set_len(offset)?;
+                file.sync_all()?;
+                sync_dir_of(&log_path);
+                // the truncated log is no longer the one closed with the digest
+                if let Some(manifest) = &mut manifest {
+                    if let Some(active) = manifest.segments.last_mut() {
+                        active.digest = None;
+                    }
+                    manifest::write_manifest(path, DEFAULT_FILE_PREFIX, manifest)?;
+                }
+                true
+            }
+            _ => false,
+        };
+        Ok(VerifyReport {
+            log_path: Some(log_path),
+            records,
+            live_records: live_keys.len() as u64,
+            corruption_offset,
+            repaired,
+            digests_checked,
+            digest_mismatches,
+        })
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    fmt, hash,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{clock::millis_since_epoch, resolve_versioned_value, KvStore, Record, Result};
+
+/// Version of the value of a key: the sequence number of the write which made it, across all the
+/// keys of the store (so a key's versions only ever increase, even if it is removed and set again),
+/// and when that write was made
+///
+/// Values written before writes were versioned have sequence number 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Version {
+    sequence: u64,
+    timestamp: u64,
+}
+
+impl Version {
+    pub(crate) fn of<K, V>(rec: &Record<K, V>) -> Self {
+        Self {
+            sequence: rec.version,
+            timestamp: rec.timestamp,
+        }
+    }
+    /// sequence number of the write
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+    /// time at which the write was made (to the millisecond)
+    pub fn timestamp(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.timestamp)
+    }
+}
+
+/// current time in milliseconds since the Unix epoch, recorded in each record written
+pub(crate) fn now_millis() -> u64 {
+    millis_since_epoch(SystemTime::now())
+}
+
+impl<K, V> KvStore<K, V>
+where
+    K: Serialize + DeserializeOwned + Eq + PartialEq + hash::Hash + Clone + fmt::Debug,
+    V: Serialize + DeserializeOwned + Clone,
+{
+    /// get the value stored under the given key with its version, or None if no such key
+    ///
+    /// # Example
+    /// ```
+    /// use kvs::KvStore;
+    ///
+    /// let mut store = KvStore::<String,String>::temporary().unwrap();
+    /// store.set("key1".into(),"value1".into()).unwrap();
+    /// let (value, version) = store.get_versioned("key1".into()).unwrap().unwrap();
+    /// store.set("key1".into(),"value2".into()).unwrap();
+    /// let (_, next) = store.get_versioned("key1".into()).unwrap().unwrap();
+    /// assert_eq!(value, "value1");
+    /// assert!(next > version);
+    /// ```
+    pub fn get_versioned(&mut self, key: K) -> Result<Option<(V, Version)>> {
+        let db_key = match self.index.get(&key) {
+            Some(&db_key) => db_key,
+            None => return Ok(None),
+        };
+        let (value, version) =
+            resolve_versioned_value(&mut self.reader, self.merge_operator.as_ref(), &key, db_key)?;
+        Ok(value.map(|value| (value, version)))
+    }
+    /// set a key to a value only if its current value is of the expected version (from
+    /// `get_versioned`), returning whether it was set
+    ///
+    /// The check and the write are made together, so of several writers which read the same
+    /// version only the first to write succeeds.
+    pub fn set_if_version(&mut self, key: K, value: V, expected: Version) -> Result<bool> {
+        match self.get_versioned(key.clone())? {
+            Some((_, version)) if version.sequence == expected.sequence => {
+                self.set(key, value)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
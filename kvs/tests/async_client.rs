@@ -0,0 +1,70 @@
+#![cfg(feature = "async")]
+
+mod common;
+
+use common::start_server_at;
+use kvs::{AsyncKvsClient, ErrorKind, KvsClient};
+use tempfile::TempDir;
+use tokio_stream::StreamExt;
+
+#[test]
+fn async_client_set_get_remove() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_server_at(&temp_dir);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let client = AsyncKvsClient::connect(addr).await.unwrap();
+        client
+            .set("key1".to_owned(), "value1".to_owned())
+            .await
+            .unwrap();
+        assert_eq!(
+            client.get("key1".to_owned()).await.unwrap(),
+            Some("value1".to_owned())
+        );
+        // clones share the connection
+        let clone = client.clone();
+        clone.remove("key1".to_owned()).await.unwrap();
+        assert_eq!(client.get("key1".to_owned()).await.unwrap(), None);
+        let err = client.remove("key1".to_owned()).await.unwrap_err();
+        assert_eq!(*err.kind(), ErrorKind::KeyNotPresent);
+    });
+}
+
+#[test]
+fn async_client_scans_keys_of_a_prefix_as_a_stream() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_server_at(&temp_dir);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let client = KvsClient::connect(addr).unwrap().into_async();
+        for i in 0..100 {
+            client
+                .set(format!("user:{}", i), format!("name{}", i))
+                .await
+                .unwrap();
+            client
+                .set(format!("order:{}", i), i.to_string())
+                .await
+                .unwrap();
+        }
+
+        let mut users = client
+            .scan("user:".to_owned())
+            .collect::<kvs::Result<Vec<_>>>()
+            .await
+            .unwrap();
+        users.sort();
+        let mut expected = (0..100)
+            .map(|i| (format!("user:{}", i), format!("name{}", i)))
+            .collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(users, expected);
+
+        // a stream dropped part way through frees the client for other requests
+        let mut pairs = Box::pin(client.scan(String::new()));
+        assert!(pairs.next().await.unwrap().is_ok());
+        drop(pairs);
+        assert_eq!(client.key_count().await.unwrap(), 200);
+    });
+}
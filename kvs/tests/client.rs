@@ -0,0 +1,328 @@
+mod common;
+
+use common::start_server_at;
+use kvs::{
+    ErrorKind, KeyEvent, KvsClient, KvsClientPool, KvsEngine, KvsServer, PoolOptions, SharedKvStore,
+};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+// Proxies connections to the server, the first of which it closes once the server has answered
+// its first request, without passing the answer on.
+fn start_flaky_proxy_at(server: SocketAddr) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for (i, client) in listener.incoming().enumerate() {
+            let mut client = client.unwrap();
+            let mut upstream = TcpStream::connect(server).unwrap();
+            if i == 0 {
+                let mut buf = [0; 1024];
+                let len = client.read(&mut buf).unwrap();
+                upstream.write_all(&buf[..len]).unwrap();
+                let _ = upstream.read(&mut buf).unwrap();
+                continue;
+            }
+            let (mut client_reader, mut upstream_writer) =
+                (client.try_clone().unwrap(), upstream.try_clone().unwrap());
+            thread::spawn(move || io::copy(&mut client_reader, &mut upstream_writer));
+            thread::spawn(move || io::copy(&mut upstream, &mut client));
+        }
+    });
+    addr
+}
+
+#[test]
+fn client_retries_set_without_applying_it_twice() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let engine = SharedKvStore::open(temp_dir.path()).unwrap();
+    let events = engine.subscribe(String::new()).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = start_flaky_proxy_at(listener.local_addr().unwrap());
+    let server_engine = engine.clone();
+    thread::spawn(move || KvsServer::new(server_engine).serve(listener));
+
+    let mut client = KvsClient::connect(addr).unwrap().with_retries(3);
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    assert_eq!(
+        client.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+    assert_eq!(events.try_recv(), Ok(KeyEvent::Set("key1".to_owned())));
+    assert!(events.try_recv().is_err());
+
+    let token = KvsClient::new_idempotency_token();
+    assert!(client
+        .set_once("key2".to_owned(), "value1".to_owned(), token)
+        .unwrap());
+    client.set("key2".to_owned(), "value2".to_owned()).unwrap();
+    assert!(!client
+        .set_once("key2".to_owned(), "value1".to_owned(), token)
+        .unwrap());
+    assert_eq!(
+        client.get("key2".to_owned()).unwrap(),
+        Some("value2".to_owned())
+    );
+}
+
+#[test]
+fn client_without_retries_fails_on_broken_connection() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_flaky_proxy_at(start_server_at(&temp_dir));
+
+    let mut client = KvsClient::connect(addr).unwrap();
+    let err = client
+        .get("key1".to_owned())
+        .expect_err("the connection should have failed");
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::IoError | ErrorKind::ProtocolError
+    ));
+}
+
+#[test]
+fn client_backup_resumes_snapshot_on_another_connection() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let engine = SharedKvStore::open(temp_dir.path()).unwrap();
+    for i in 0..10 {
+        engine
+            .set(format!("key{}", i), format!("value{}", i))
+            .unwrap();
+    }
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server = listener.local_addr().unwrap();
+    let addr = start_flaky_proxy_at(server);
+    let server_engine = engine.clone();
+    thread::spawn(move || KvsServer::new(server_engine).serve(listener));
+
+    // later writes are not in the snapshot, whose chunks another connection goes on receiving
+    let first = KvsClient::connect(server)
+        .unwrap()
+        .snapshot_chunk(None, 0, 10)
+        .unwrap();
+    assert!(first.is_intact());
+    assert_eq!((first.offset, first.total), (0, 10));
+    assert_eq!(first.pairs.len(), 1);
+    engine
+        .set("key10".to_owned(), "value10".to_owned())
+        .unwrap();
+    let mut client = KvsClient::connect(server).unwrap();
+    let mut offset = 1;
+    while offset < first.total {
+        let chunk = client
+            .snapshot_chunk(Some(first.snapshot_id), offset, 10)
+            .unwrap();
+        assert!(chunk.is_intact());
+        assert_eq!(
+            (chunk.snapshot_id, chunk.offset),
+            (first.snapshot_id, offset)
+        );
+        offset += chunk.pairs.len() as u64;
+        assert_eq!(chunk.is_last(), offset == first.total);
+    }
+
+    let backup_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut client = KvsClient::connect(addr).unwrap().with_retries(3);
+    assert_eq!(client.backup(backup_dir.path()).unwrap(), 11);
+    let backup = SharedKvStore::open(backup_dir.path()).unwrap();
+    assert_eq!(backup.key_count().unwrap(), 11);
+    assert_eq!(
+        backup.get("key10".to_owned()).unwrap(),
+        Some("value10".to_owned())
+    );
+    assert_eq!(
+        client.backup(backup_dir.path()).unwrap_err().kind(),
+        &ErrorKind::AlreadyOpen
+    );
+    drop(backup);
+    assert_eq!(
+        client.backup(backup_dir.path()).unwrap_err().kind(),
+        &ErrorKind::InvalidConfiguration
+    );
+}
+
+#[test]
+fn pool_shared_between_threads() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let pool = KvsClientPool::new(
+        start_server_at(&temp_dir),
+        PoolOptions {
+            pool_size: 2,
+            ..PoolOptions::default()
+        },
+    )
+    .unwrap();
+
+    let handles = (0..8)
+        .map(|thread_id| {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                for i in 0..20 {
+                    let key = format!("key{}-{}", thread_id, i);
+                    pool.set(key.clone(), format!("value{}", i)).unwrap();
+                    assert_eq!(pool.get(key).unwrap(), Some(format!("value{}", i)));
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(
+        pool.get("key7-19".to_owned()).unwrap(),
+        Some("value19".to_owned())
+    );
+}
+
+#[test]
+fn pool_reconnects_with_backoff() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+
+    let pool = KvsClientPool::new(
+        addr,
+        PoolOptions {
+            initial_backoff: Duration::from_millis(20),
+            max_backoff: Duration::from_millis(100),
+            max_reconnect_attempts: 50,
+            ..PoolOptions::default()
+        },
+    )
+    .unwrap();
+
+    let engine = SharedKvStore::open(temp_dir.path()).unwrap();
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        KvsServer::new(engine).run(addr)
+    });
+
+    pool.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    assert_eq!(
+        pool.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+}
+
+#[test]
+fn pool_gives_up_connecting() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+
+    let pool = KvsClientPool::new(
+        addr,
+        PoolOptions {
+            initial_backoff: Duration::from_millis(1),
+            max_reconnect_attempts: 2,
+            ..PoolOptions::default()
+        },
+    )
+    .unwrap();
+    let err = pool.get("key1".to_owned()).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::IoError);
+}
+
+#[test]
+fn pool_request_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    // accept connections but never respond
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            thread::spawn(move || {
+                let _ = stream.read_to_end(&mut Vec::new());
+            });
+        }
+    });
+
+    let pool = KvsClientPool::new(
+        addr,
+        PoolOptions {
+            request_timeout: Some(Duration::from_millis(100)),
+            ..PoolOptions::default()
+        },
+    )
+    .unwrap();
+    let started = Instant::now();
+    let err = pool.get("key1".to_owned()).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::Timeout);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn client_times_out_on_a_silent_server_and_reconnects() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (accepted, connections) = std::sync::mpsc::channel();
+    // accept connections but never respond
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let _ = accepted.send(());
+            thread::spawn(move || {
+                let _ = stream.read_to_end(&mut Vec::new());
+            });
+        }
+    });
+
+    let client = &mut KvsClient::connect(addr)
+        .unwrap()
+        .with_timeout(Duration::from_millis(100));
+    let started = Instant::now();
+    let err = client.get("key1".to_owned()).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::Timeout);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    connections.recv().unwrap();
+
+    // the connection which timed out is replaced before the next request
+    let err = client.remove("key1".to_owned()).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::Timeout);
+    connections
+        .recv_timeout(Duration::from_secs(5))
+        .expect("the client did not reconnect");
+
+    // a request made once the deadline has passed fails without waiting on the server
+    client.set_deadline(Some(Instant::now()));
+    let started = Instant::now();
+    let err = client.get("key1".to_owned()).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::Timeout);
+    assert!(started.elapsed() < Duration::from_millis(100));
+}
+
+#[test]
+fn client_deadline_bounds_retries() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_server_at(&temp_dir);
+    let client = &mut KvsClient::connect(addr).unwrap().with_retries(3);
+    client.set_deadline(Some(Instant::now() + Duration::from_secs(60)));
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    assert_eq!(
+        client.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+    client.set_deadline(Some(Instant::now()));
+    let err = client.key_count().unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::Timeout);
+    client.set_deadline(None);
+    assert_eq!(client.key_count().unwrap(), 1);
+}
+
+#[test]
+fn pool_invalid_options() {
+    let err = KvsClientPool::new(
+        "127.0.0.1:4000",
+        PoolOptions {
+            pool_size: 0,
+            ..PoolOptions::default()
+        },
+    )
+    .err()
+    .unwrap();
+    assert_eq!(*err.kind(), ErrorKind::InvalidConfiguration);
+}
@@ -0,0 +1,208 @@
+use kvs::{
+    ClusterClient, ClusterNode, ClusterOptions, ErrorKind, KvsClient, KvsServer, Shard,
+    SharedKvStore, Topology,
+};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+// Starts a server for each directory as a node of a cluster whose keys before "m" are held by the
+// first node and the rest by the second, returning the topology.
+fn start_sharded_cluster(temp_dirs: &[TempDir; 2]) -> Topology {
+    let listeners = (0..2)
+        .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+        .collect::<Vec<_>>();
+    let topology = Topology {
+        epoch: 1,
+        nodes: listeners
+            .iter()
+            .zip(1..)
+            .map(|(listener, id)| ClusterNode {
+                id,
+                addr: listener.local_addr().unwrap().to_string(),
+            })
+            .collect(),
+        shards: vec![
+            Shard {
+                start: String::new(),
+                end: Some("m".to_owned()),
+                nodes: vec![1],
+                leader: None,
+            },
+            Shard {
+                start: "m".to_owned(),
+                end: None,
+                nodes: vec![2],
+                leader: None,
+            },
+        ],
+    };
+    for ((listener, temp_dir), id) in listeners.into_iter().zip(temp_dirs).zip(1..) {
+        let engine = SharedKvStore::open(temp_dir.path()).unwrap();
+        let server = KvsServer::new(engine).with_cluster(id, topology.clone());
+        thread::spawn(move || server.serve(listener));
+    }
+    topology
+}
+
+#[test]
+fn cluster_client_routes_keys_to_their_shards() {
+    let temp_dirs = [TempDir::new().unwrap(), TempDir::new().unwrap()];
+    let topology = start_sharded_cluster(&temp_dirs);
+    let (first, second) = (&topology.nodes[0].addr, &topology.nodes[1].addr);
+
+    let mut client = ClusterClient::connect(&[second.as_str()], ClusterOptions::default()).unwrap();
+    assert_eq!(client.topology(), &topology);
+    client.set("apple".to_owned(), "1".to_owned()).unwrap();
+    client.set("zebra".to_owned(), "2".to_owned()).unwrap();
+    assert!(!client
+        .set_if_absent("zebra".to_owned(), "3".to_owned())
+        .unwrap());
+    assert_eq!(
+        client.get("apple".to_owned()).unwrap(),
+        Some("1".to_owned())
+    );
+    assert_eq!(
+        client.get("zebra".to_owned()).unwrap(),
+        Some("2".to_owned())
+    );
+    client.remove("apple".to_owned()).unwrap();
+    assert_eq!(
+        client.remove("apple".to_owned()).unwrap_err().kind(),
+        &ErrorKind::KeyNotPresent
+    );
+
+    // each node holds only the keys of its shard, redirecting requests for others
+    let mut node = KvsClient::connect(first.as_str()).unwrap();
+    assert_eq!(node.key_count().unwrap(), 0);
+    let err = node.get("zebra".to_owned()).unwrap_err();
+    assert_eq!(err.kind(), &ErrorKind::Moved);
+    assert_eq!(
+        std::error::Error::source(&err).map(ToString::to_string),
+        Some(second.clone())
+    );
+    let mut node = KvsClient::connect(second.as_str()).unwrap();
+    assert_eq!(node.key_count().unwrap(), 1);
+
+    let mut stream = TcpStream::connect(first.as_str()).unwrap();
+    stream
+        .write_all(b"*3\r\n$3\r\nSET\r\n$5\r\nzebra\r\n$1\r\n4\r\n")
+        .unwrap();
+    let mut response = String::new();
+    BufReader::new(stream).read_line(&mut response).unwrap();
+    assert_eq!(response, format!("-MOVED {}\r\n", second));
+}
+
+#[test]
+fn topology_of_a_server_outside_a_cluster_fails() {
+    let temp_dir = TempDir::new().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let engine = SharedKvStore::open(temp_dir.path()).unwrap();
+    thread::spawn(move || KvsServer::new(engine).serve(listener));
+
+    let mut client = KvsClient::connect(addr).unwrap();
+    assert_eq!(
+        client.topology().unwrap_err().kind(),
+        &ErrorKind::ServerError
+    );
+    assert!(ClusterClient::connect(&[addr], ClusterOptions::default()).is_err());
+}
+
+#[test]
+fn cluster_migrates_a_range_while_serving_writes() {
+    let temp_dirs = [TempDir::new().unwrap(), TempDir::new().unwrap()];
+    let topology = start_sharded_cluster(&temp_dirs);
+    let (first, second) = (
+        topology.nodes[0].addr.clone(),
+        topology.nodes[1].addr.clone(),
+    );
+    let mut client = ClusterClient::connect(&[first.as_str()], ClusterOptions::default()).unwrap();
+    for key in &["apple", "grape", "kiwi", "lemon"] {
+        client.set(key.to_string(), "old".to_owned()).unwrap();
+    }
+
+    // writes keep being made to the range throughout the migration
+    let stop = Arc::new(AtomicBool::new(false));
+    let writer_stop = stop.clone();
+    let writer_seed = first.clone();
+    let writer = thread::spawn(move || {
+        let mut client =
+            ClusterClient::connect(&[writer_seed.as_str()], ClusterOptions::default()).unwrap();
+        let mut written: u32 = 0;
+        while !writer_stop.load(Ordering::Relaxed) || written < 50 {
+            let key = format!("key{}", written % 20);
+            match client.set(key, written.to_string()) {
+                Ok(()) => written += 1,
+                Err(err) if *err.kind() == ErrorKind::Moved => (),
+                Err(err) => panic!("{}", err),
+            }
+        }
+        written
+    });
+
+    let mut node = KvsClient::connect(first.as_str()).unwrap();
+    node.migrate_range("g".to_owned(), Some("m".to_owned()), second.clone())
+        .unwrap();
+    while !node.migration_status().unwrap().unwrap().streaming {
+        thread::sleep(Duration::from_millis(10));
+    }
+    client.set("kiwi".to_owned(), "new".to_owned()).unwrap();
+    client.remove("grape".to_owned()).unwrap();
+
+    let mut new_topology = topology.clone();
+    new_topology.epoch = 2;
+    new_topology.shards[0].end = Some("g".to_owned());
+    new_topology.shards[1].start = "g".to_owned();
+    node.finish_migration(new_topology.clone()).unwrap();
+    stop.store(true, Ordering::Relaxed);
+    let written = writer.join().unwrap();
+
+    let status = node.migration_status().unwrap().unwrap();
+    assert!(status.finished);
+    assert_eq!(status.error, None);
+    assert_eq!(node.topology().unwrap(), new_topology);
+    assert_eq!(node.key_count().unwrap(), 1);
+    let mut target = KvsClient::connect(second.as_str()).unwrap();
+    assert_eq!(target.topology().unwrap(), new_topology);
+    assert_eq!(target.key_count().unwrap(), 2 + 20);
+
+    assert_eq!(
+        client.get("kiwi".to_owned()).unwrap(),
+        Some("new".to_owned())
+    );
+    assert_eq!(client.get("grape".to_owned()).unwrap(), None);
+    assert_eq!(
+        client.get("apple".to_owned()).unwrap(),
+        Some("old".to_owned())
+    );
+    assert_eq!(client.topology(), &new_topology);
+    for i in written - 20..written {
+        assert_eq!(
+            target.get(format!("key{}", i % 20)).unwrap(),
+            Some(i.to_string())
+        );
+    }
+}
+
+#[test]
+fn cluster_refuses_older_topology() {
+    let temp_dirs = [TempDir::new().unwrap(), TempDir::new().unwrap()];
+    let topology = start_sharded_cluster(&temp_dirs);
+    let mut node = KvsClient::connect(topology.nodes[0].addr.as_str()).unwrap();
+    let mut older = topology.clone();
+    older.epoch = 0;
+    assert_eq!(
+        node.set_topology(older).unwrap_err().kind(),
+        &ErrorKind::ServerError
+    );
+    assert_eq!(node.migration_status().unwrap(), None);
+    assert_eq!(
+        node.finish_migration(topology).unwrap_err().kind(),
+        &ErrorKind::ServerError
+    );
+}
@@ -0,0 +1,13 @@
+use kvs::{KvsServer, SharedKvStore};
+use std::net::{SocketAddr, TcpListener};
+use std::thread;
+use tempfile::TempDir;
+
+// Starts a server for the store in the directory upon its own thread, returning its address.
+pub fn start_server_at(temp_dir: &TempDir) -> SocketAddr {
+    let engine = SharedKvStore::open(temp_dir.path()).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || KvsServer::new(engine).serve(listener));
+    addr
+}
@@ -0,0 +1,142 @@
+//! Crash consistency: random operations are applied to a store, then a crash is simulated by
+//! copying its log through a writer failing part way (as if the machine had stopped while the
+//! log was being written), and the store reopened from the copy must hold exactly the state
+//! after some prefix of the operations - that of every operation whose record was written whole.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use kvs::{ErrorKind, KvStore, Result};
+use proptest::prelude::*;
+use tempfile::TempDir;
+
+const KEYS: usize = 4;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Set(usize, u64),
+    Remove(usize),
+    Merge(usize, u64),
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (0..KEYS, any::<u64>()).prop_map(|(key, value)| Op::Set(key, value)),
+        (0..KEYS).prop_map(Op::Remove),
+        (0..KEYS, 0..1000u64).prop_map(|(key, increment)| Op::Merge(key, increment)),
+    ]
+}
+
+fn key(key: usize) -> String {
+    format!("key{}", key)
+}
+
+fn open_counters(path: &Path) -> Result<KvStore<String, u64>> {
+    let mut store = KvStore::<String, u64>::open(path)?;
+    store.set_merge_operator(|_key, count, increment| count.unwrap_or(0).wrapping_add(increment));
+    Ok(store)
+}
+
+fn log_in(dir: &Path) -> PathBuf {
+    fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().is_some_and(|extension| extension == "log"))
+        .expect("no log written")
+}
+
+/// Writer accepting only the first `remaining` bytes written through it, the write reaching
+/// that limit being partial and every later write failing, as if the process had crashed
+struct CrashingWriter<W> {
+    inner: W,
+    remaining: u64,
+}
+
+impl<W: Write> Write for CrashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.remaining == 0 && !buf.is_empty() {
+            return Err(io::Error::other("simulated crash"));
+        }
+        let len = buf.len().min(self.remaining as usize);
+        let written = self.inner.write(&buf[..len])?;
+        self.remaining -= written as u64;
+        Ok(written)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// copies the log in `from` into `to` as it would be on disk had a crash interrupted writing
+/// it after `cut` bytes
+fn crash_copy(from: &Path, to: &Path, cut: u64) {
+    let log = log_in(from);
+    let mut writer = CrashingWriter {
+        inner: fs::File::create(to.join(log.file_name().unwrap())).unwrap(),
+        remaining: cut,
+    };
+    let copied = io::copy(&mut fs::File::open(&log).unwrap(), &mut writer);
+    assert!(copied.is_ok() || writer.remaining == 0);
+    writer.flush().unwrap();
+}
+
+/// applies the operations to a store in `dir`, returning the length of the log and the
+/// expected contents of the store after each prefix of them
+fn apply(dir: &Path, ops: &[Op]) -> Result<Vec<(u64, HashMap<String, u64>)>> {
+    let mut store = open_counters(dir)?;
+    let mut model = HashMap::new();
+    let mut states = vec![(fs::metadata(log_in(dir))?.len(), model.clone())];
+    for op in ops {
+        match *op {
+            Op::Set(k, value) => {
+                store.set(key(k), value)?;
+                model.insert(key(k), value);
+            }
+            Op::Remove(k) => match store.remove(key(k)) {
+                Ok(()) => {
+                    model.remove(&key(k));
+                }
+                Err(err) => {
+                    assert_eq!(*err.kind(), ErrorKind::KeyNotPresent);
+                    assert!(!model.contains_key(&key(k)));
+                }
+            },
+            Op::Merge(k, increment) => {
+                store.merge(key(k), increment)?;
+                let count = model.entry(key(k)).or_insert(0);
+                *count = count.wrapping_add(increment);
+            }
+        }
+        states.push((fs::metadata(log_in(dir))?.len(), model.clone()));
+    }
+    Ok(states)
+}
+
+proptest! {
+    #[test]
+    fn store_reopens_to_a_prefix_after_a_crash(
+        ops in prop::collection::vec(op(), 1..40),
+        cut_fraction in 0.0..=1.0f64,
+    ) {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let (written_dir, crashed_dir) = (temp_dir.path().join("written"), temp_dir.path().join("crashed"));
+        fs::create_dir(&written_dir).unwrap();
+        fs::create_dir(&crashed_dir).unwrap();
+        let states = apply(&written_dir, &ops).unwrap();
+
+        let log_len = states.last().unwrap().0;
+        let cut = (log_len as f64 * cut_fraction) as u64;
+        crash_copy(&written_dir, &crashed_dir, cut);
+        let (_, expected) = states.iter().rev().find(|(len, _)| *len <= cut).unwrap();
+
+        let mut store = open_counters(&crashed_dir).unwrap();
+        prop_assert_eq!(store.len(), expected.len());
+        for k in 0..KEYS {
+            prop_assert_eq!(store.get(key(k)).unwrap(), expected.get(&key(k)).copied());
+        }
+    }
+}
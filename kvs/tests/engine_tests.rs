@@ -0,0 +1,73 @@
+use kvs::{
+    engine_tests, ErrorKind, KvsEngine, RaftEngine, RaftOptions, RaftRole, Result, ShardedKvStore,
+    SharedKvStore, StoreOptions, WriteBatch,
+};
+use std::net::TcpListener;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+// Starts a Raft cluster of one node at the path, waiting for it to lead.
+fn start_single_node(path: &Path) -> Result<RaftEngine> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let options = RaftOptions {
+        election_timeout: Duration::from_millis(50),
+        heartbeat_interval: Duration::from_millis(10),
+        ..RaftOptions::default()
+    };
+    let engine = RaftEngine::start(path, 1, vec![], listener, options)?;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while engine.status()?.role != RaftRole::Leader {
+        assert!(Instant::now() < deadline, "the node did not become leader");
+        thread::sleep(Duration::from_millis(10));
+    }
+    Ok(engine)
+}
+
+#[test]
+fn shared_kv_store_conforms() {
+    engine_tests::run_all(SharedKvStore::open);
+}
+
+#[test]
+fn sharded_kv_store_conforms() {
+    engine_tests::run_all(ShardedKvStore::open);
+}
+
+#[test]
+fn sharded_kv_store_must_be_reopened_with_its_shard_count() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = StoreOptions::default();
+    let engine = ShardedKvStore::open_with_options(temp_dir.path(), 4, &options)?;
+    let mut batch = WriteBatch::new();
+    for key_id in 0..100 {
+        batch.set(format!("key{}", key_id), key_id.to_string());
+    }
+    batch.remove("key0".to_owned()).remove("missing".to_owned());
+    let applied = engine.write_batch(batch)?;
+    assert_eq!(applied.len(), 102);
+    assert!(applied[..101].iter().all(|&applied| applied));
+    assert!(!applied[101]);
+    assert_eq!(engine.key_count()?, 99);
+    engine.shutdown()?;
+    drop(engine);
+
+    for shards in [0, 3, 5] {
+        match ShardedKvStore::open_with_options(temp_dir.path(), shards, &options) {
+            Err(err) => assert_eq!(err.kind(), &ErrorKind::InvalidConfiguration),
+            Ok(_) => panic!("opened a store of 4 partitions with {}", shards),
+        }
+    }
+    let engine = ShardedKvStore::open_with_options(temp_dir.path(), 4, &options)?;
+    assert_eq!(engine.shard_count(), 4);
+    assert_eq!(engine.get("key0".to_owned())?, None);
+    assert_eq!(engine.get("key99".to_owned())?, Some("99".to_owned()));
+    assert_eq!(engine.keys()?.len(), 99);
+    Ok(())
+}
+
+#[test]
+fn raft_engine_conforms() {
+    engine_tests::run_all(start_single_node);
+}
@@ -0,0 +1,115 @@
+//! Fuzzing of the server's reading of network input through the entry points of the fuzz targets
+//! (kvs::fuzzing), with arbitrary bytes and with well-formed messages corrupted at random: a run
+//! of a target panicking (or aborting, as when allocating what a malformed length says) fails.
+//! The inputs which once did are checked one by one.
+
+use std::io;
+
+use kvs::{fuzzing, Request};
+use proptest::prelude::*;
+
+/// well-formed messages of either protocol, which the corruptions start from
+fn seeds() -> Vec<Vec<u8>> {
+    let mut seeds = [
+        "*3\r\n$3\r\nSET\r\n$4\r\nkey1\r\n$6\r\nvalue1\r\n",
+        "*2\r\n$3\r\nGET\r\n$4\r\nkey1\r\n",
+        "*1\r\n$4\r\nPING\r\n",
+        "*3\r\n$6\r\nCONFIG\r\n$3\r\nGET\r\n$4\r\nsave\r\n",
+        "*2\r\n$6\r\nSELECT\r\n$1\r\n1\r\n",
+        "*4\r\n$4\r\nSCAN\r\n$1\r\n0\r\n$5\r\nCOUNT\r\n$1\r\n5\r\n",
+        "SET key2 value2\r\nGET key2\r\n",
+        "SET \"key 3\" \"va\\x6cue\\n\"\r\nGET 'key 3'\r\n",
+    ]
+    .iter()
+    .map(|seed| seed.as_bytes().to_vec())
+    .collect::<Vec<_>>();
+    let requests = [
+        Request::Set {
+            key: "key1".into(),
+            value: "value1".into(),
+        },
+        Request::Get { key: "key1".into() },
+        Request::Remove { key: "key1".into() },
+        Request::KeyCount,
+        Request::Subscribe {
+            prefix: "key".into(),
+        },
+    ];
+    for request in requests.iter() {
+        let mut seed = Vec::new();
+        kvs_proto_serde::to_writer(&mut io::BufWriter::new(&mut seed), request).unwrap();
+        seeds.push(seed);
+    }
+    // a Traced nested within Traced, deeper than any depth the deserializer allows
+    seeds.push(
+        ("#2\nRequest\n$Traced\n$id\n$id1\n$request\n".repeat(1_000) + "@Request\n$KeyCount\n")
+            .into_bytes(),
+    );
+    seeds
+}
+
+/// a seed with bytes overwritten, inserted and cut off at random
+fn corrupted() -> impl Strategy<Value = Vec<u8>> {
+    let seeds = seeds();
+    (
+        0..seeds.len(),
+        prop::collection::vec(
+            (any::<prop::sample::Index>(), any::<u8>(), any::<bool>()),
+            0..4,
+        ),
+        any::<prop::sample::Index>(),
+    )
+        .prop_map(move |(seed, changes, cut)| {
+            let mut input = seeds[seed].clone();
+            for (at, byte, insert) in changes {
+                let at = at.index(input.len());
+                if insert {
+                    input.insert(at, byte);
+                } else {
+                    input[at] = byte;
+                }
+            }
+            input.truncate(cut.index(input.len() + 1));
+            input
+        })
+}
+
+fn input() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![prop::collection::vec(any::<u8>(), 0..256), corrupted()]
+}
+
+proptest! {
+    #[test]
+    fn resp_commands_do_not_panic(input in input()) {
+        fuzzing::resp_commands(&input);
+    }
+
+    #[test]
+    fn kvs_proto_messages_do_not_panic(input in input()) {
+        fuzzing::kvs_proto_messages(&input);
+    }
+
+    #[test]
+    fn connections_do_not_panic(input in input()) {
+        fuzzing::connection(&input);
+    }
+}
+
+#[test]
+fn inputs_which_panicked_do_not() {
+    let inputs = [
+        // lengths far beyond the input, once allocated up front
+        "*1\r\n$9223372036854775807\r\n".to_owned(),
+        "#1\nRequest\n$Get\n$key\n&18446744073709551615\nkey1\n".to_owned(),
+        // arrays nested deeper than the stack
+        "*1\r\n".repeat(100_000),
+        // a field the request does not have, and a unit variant with elements
+        "#1\nRequest\n$Get\n$unknown\n`1\nD1\n".to_owned(),
+        "^1\nRequest\n$Compact\nD1\n".to_owned(),
+    ];
+    for input in inputs.iter() {
+        fuzzing::resp_commands(input.as_bytes());
+        fuzzing::kvs_proto_messages(input.as_bytes());
+        fuzzing::connection(input.as_bytes());
+    }
+}
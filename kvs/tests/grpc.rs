@@ -0,0 +1,113 @@
+#![cfg(feature = "grpc")]
+
+use kvs::grpc_proto::{key_event::Kind, kvs_client::KvsClient, *};
+use kvs::{GrpcServer, SharedKvStore};
+use std::net::{SocketAddr, TcpListener};
+use std::thread;
+use tempfile::TempDir;
+use tonic::{transport::Channel, Code};
+
+// Starts a gRPC server for the store in the directory upon its own thread, returning its address.
+fn start_grpc_server_at(temp_dir: &TempDir) -> SocketAddr {
+    let engine = SharedKvStore::open(temp_dir.path()).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || GrpcServer::new(engine).serve(listener));
+    addr
+}
+
+async fn connect(addr: SocketAddr) -> KvsClient<Channel> {
+    let channel = Channel::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+    KvsClient::new(channel)
+}
+
+fn set_request(key: &str, value: &str) -> SetRequest {
+    SetRequest {
+        key: key.to_owned(),
+        value: value.to_owned(),
+    }
+}
+
+#[test]
+fn grpc_set_get_remove_scan() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_grpc_server_at(&temp_dir);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let mut client = connect(addr).await;
+        for (key, value) in [("user:2", "bob"), ("user:1", "alice"), ("other", "value")] {
+            client.set(set_request(key, value)).await.unwrap();
+        }
+        let get = |key: &str| GetRequest {
+            key: key.to_owned(),
+        };
+        let found = client.get(get("user:1")).await.unwrap().into_inner();
+        assert_eq!(found.value, Some("alice".to_owned()));
+
+        let mut scan = client
+            .scan(ScanRequest {
+                prefix: "user:".to_owned(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let mut scanned = Vec::new();
+        while let Some(found) = scan.message().await.unwrap() {
+            scanned.push((found.key, found.value));
+        }
+        assert_eq!(
+            scanned,
+            vec![
+                ("user:1".to_owned(), "alice".to_owned()),
+                ("user:2".to_owned(), "bob".to_owned()),
+            ]
+        );
+
+        let remove = |key: &str| RemoveRequest {
+            key: key.to_owned(),
+        };
+        client.remove(remove("user:1")).await.unwrap();
+        let found = client.get(get("user:1")).await.unwrap().into_inner();
+        assert_eq!(found.value, None);
+        let err = client.remove(remove("user:1")).await.unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+    });
+}
+
+#[test]
+fn grpc_watch() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_grpc_server_at(&temp_dir);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        let mut client = connect(addr).await;
+        let mut events = client
+            .watch(WatchRequest {
+                prefix: "user:".to_owned(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+
+        client.set(set_request("user:1", "alice")).await.unwrap();
+        client.set(set_request("other", "value")).await.unwrap();
+        client
+            .remove(RemoveRequest {
+                key: "user:1".to_owned(),
+            })
+            .await
+            .unwrap();
+
+        let event = events.message().await.unwrap().unwrap();
+        assert_eq!((event.kind(), event.key.as_str()), (Kind::Set, "user:1"));
+        let event = events.message().await.unwrap().unwrap();
+        assert_eq!(
+            (event.kind(), event.key.as_str()),
+            (Kind::Removed, "user:1")
+        );
+    });
+}
@@ -0,0 +1,104 @@
+#![cfg(feature = "http")]
+
+use kvs::{HttpGateway, SharedKvStore};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use tempfile::TempDir;
+
+// Starts an HTTP gateway for the store in the directory upon its own thread, returning its address.
+fn start_gateway_at(temp_dir: &TempDir) -> SocketAddr {
+    let engine = SharedKvStore::open(temp_dir.path()).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || HttpGateway::new(engine).serve(listener));
+    addr
+}
+
+// Makes an HTTP/1.1 request on a new connection, returning the status code and body.
+fn request(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        body.len(),
+        body
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+    (status, body.to_owned())
+}
+
+#[test]
+fn http_put_get_delete() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_gateway_at(&temp_dir);
+
+    assert_eq!(
+        request(addr, "GET", "/keys/key1", ""),
+        (404, "key not found".into())
+    );
+    assert_eq!(request(addr, "PUT", "/keys/key1", "value1").0, 204);
+    assert_eq!(
+        request(addr, "GET", "/keys/key1", ""),
+        (200, "value1".into())
+    );
+    assert_eq!(request(addr, "PUT", "/keys/a%20key%2F2", "value2").0, 204);
+    assert_eq!(
+        request(addr, "GET", "/keys/a%20key%2F2?fresh=1", ""),
+        (200, "value2".into())
+    );
+    assert_eq!(request(addr, "DELETE", "/keys/key1", "").0, 204);
+    assert_eq!(request(addr, "DELETE", "/keys/key1", "").0, 404);
+    assert_eq!(request(addr, "GET", "/keys/key1", "").0, 404);
+}
+
+#[test]
+fn http_stats_and_invalid_requests() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_gateway_at(&temp_dir);
+
+    request(addr, "PUT", "/keys/key1", "value1");
+    let (status, body) = request(addr, "GET", "/stats", "");
+    assert_eq!(status, 200);
+    let stats: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(stats["keys"], 1);
+    #[cfg(feature = "stats")]
+    assert_eq!(stats["sizes"]["values"]["count"], 1);
+
+    assert_eq!(request(addr, "POST", "/stats", "").0, 405);
+    assert_eq!(request(addr, "POST", "/keys/key1", "").0, 405);
+    assert_eq!(request(addr, "GET", "/keys/", "").0, 400);
+    assert_eq!(request(addr, "GET", "/keys/%zz", "").0, 400);
+    assert_eq!(request(addr, "GET", "/other", "").0, 404);
+}
+
+#[test]
+fn http_watch_streams_key_events_over_websocket() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_gateway_at(&temp_dir);
+    let url = format!("ws://{}/watch/user%3A", addr);
+    let (mut socket, _) = tungstenite::client(url, TcpStream::connect(addr).unwrap()).unwrap();
+
+    request(addr, "PUT", "/keys/user:1", "alice");
+    request(addr, "PUT", "/keys/other", "value");
+    request(addr, "DELETE", "/keys/user:1", "");
+    let mut next_event = || -> serde_json::Value {
+        let message = socket.read().unwrap();
+        serde_json::from_str(message.to_text().unwrap()).unwrap()
+    };
+    assert_eq!(
+        next_event(),
+        serde_json::json!({ "event": "set", "key": "user:1" })
+    );
+    assert_eq!(
+        next_event(),
+        serde_json::json!({ "event": "removed", "key": "user:1" })
+    );
+    assert_eq!(request(addr, "GET", "/watch/user%3A", "").0, 400);
+}
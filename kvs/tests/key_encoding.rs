@@ -0,0 +1,119 @@
+use kvs::{decode_key, encode_key, CompositeKey, ErrorKind, KvStore, Result, U64Key};
+use tempfile::TempDir;
+
+#[test]
+fn encodings_sort_in_the_order_of_the_keys() {
+    let strings = [
+        "",
+        "a",
+        "a\0",
+        "ab",
+        "abcdefgh",
+        "abcdefgh\0",
+        "abcdefghi",
+        "b",
+    ];
+    let integers = [i64::MIN, -256, -1, 0, 1, 255, 256, i64::MAX];
+    let mut keys = Vec::new();
+    for string in &strings {
+        for &integer in &integers {
+            keys.push((string.to_string(), integer, integer as u32));
+        }
+    }
+    let mut sorted = keys.clone();
+    sorted.sort();
+    let mut by_encoding = keys;
+    by_encoding.sort_by_key(encode_key);
+    assert_eq!(by_encoding, sorted);
+}
+
+#[test]
+fn keys_are_decoded_from_their_encodings() {
+    let key = (
+        "user\0name".to_owned(),
+        vec![0u8, 0xff, 0, 0, 0, 0, 0, 0, 0xf7],
+        -42i32,
+        true,
+    );
+    let encoded = encode_key(&key);
+    assert_eq!(
+        decode_key::<(String, Vec<u8>, i32, bool)>(&encoded).unwrap(),
+        key
+    );
+
+    let truncated = decode_key::<(String, Vec<u8>, i32, bool)>(&encoded[..encoded.len() - 1]);
+    assert_eq!(
+        truncated.unwrap_err().kind(),
+        &ErrorKind::DeserializationError
+    );
+    let extended = [&encoded[..], &[0]].concat();
+    assert!(decode_key::<(String, Vec<u8>, i32, bool)>(&extended).is_err());
+    // a marker saying a group of a byte string holds more padding than it can
+    assert!(decode_key::<String>(&[0, 0, 0, 0, 0, 0, 0, 0, 0xf6]).is_err());
+}
+
+#[test]
+fn composite_keys_are_scanned_in_order_by_their_leading_fields() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<Vec<u8>, String>::open(temp_dir.path())?;
+    for (user, order) in [
+        ("bob", 2u64),
+        ("ann", 10),
+        ("ann", 9),
+        ("anna", 1),
+        ("bob", 1),
+    ] {
+        let key = encode_key(&(user.to_owned(), order));
+        store.set(key, format!("{}/{}", user, order))?;
+    }
+
+    let prefix = encode_key(&("ann".to_owned(),));
+    let mut keys = store
+        .keys()
+        .filter(|key| key.starts_with(&prefix))
+        .cloned()
+        .collect::<Vec<_>>();
+    keys.sort();
+    let orders = keys
+        .iter()
+        .map(|key| decode_key::<(String, u64)>(key))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(orders, vec![("ann".to_owned(), 9), ("ann".to_owned(), 10)]);
+    Ok(())
+}
+
+#[test]
+fn structured_keys_persist_and_sort_by_their_fields() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    {
+        let mut store = KvStore::<CompositeKey<(String, i64)>, String>::open(temp_dir.path())?;
+        store.set(CompositeKey(("b".to_owned(), -1)), "b-1".to_owned())?;
+        store.set(CompositeKey(("a".to_owned(), 5)), "a5".to_owned())?;
+        store.set(("a".to_owned(), -5).into(), "a-5".to_owned())?;
+    }
+    let mut store = KvStore::<CompositeKey<(String, i64)>, String>::open(temp_dir.path())?;
+    let mut keys = store.keys().cloned().collect::<Vec<_>>();
+    let mut by_encoding = keys.clone();
+    by_encoding.sort_by_key(CompositeKey::encoded);
+    keys.sort();
+    assert_eq!(keys, by_encoding);
+    let mut values = Vec::new();
+    for key in keys {
+        values.push(store.get(key)?.unwrap());
+    }
+    assert_eq!(values, vec!["a-5", "a5", "b-1"]);
+
+    let numbers_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut numbers = KvStore::<U64Key, u64>::open(numbers_dir.path())?;
+    for number in [300, 2, u64::MAX, 0] {
+        numbers.set(U64Key(number), number)?;
+    }
+    let mut keys = numbers.keys().copied().collect::<Vec<_>>();
+    keys.sort_by_key(U64Key::encoded);
+    assert_eq!(
+        keys,
+        vec![U64Key(0), U64Key(2), U64Key(300), U64Key(u64::MAX)]
+    );
+    assert_eq!(numbers.get(U64Key(300))?, Some(300));
+    Ok(())
+}
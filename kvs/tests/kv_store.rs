@@ -0,0 +1,2210 @@
+use kvs::{
+    DataLayout, ErrorKind, EvictionPolicy, IndexHasher, KeyEvent, KvStore, RecordFormat, Result,
+    RuntimeOptions, StoreEvent, StoreOptions, WriteBatch,
+};
+use std::sync::mpsc;
+use tempfile::TempDir;
+
+#[test]
+fn write_batch_applies_in_order() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key0".to_owned(), "value0".to_owned())?;
+
+    let mut batch = WriteBatch::new();
+    batch
+        .set("key1".to_owned(), "value1".to_owned())
+        .remove("key0".to_owned())
+        .remove("key0".to_owned())
+        .set("key1".to_owned(), "value2".to_owned())
+        .remove("missing".to_owned());
+    assert_eq!(
+        store.write_batch(batch)?,
+        vec![true, true, false, true, false]
+    );
+
+    assert_eq!(store.get("key0".to_owned())?, None);
+    assert_eq!(store.get("key1".to_owned())?, Some("value2".to_owned()));
+
+    drop(store);
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key0".to_owned())?, None);
+    assert_eq!(store.get("key1".to_owned())?, Some("value2".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn write_batch_large_with_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+
+    for iter in 0..10 {
+        let mut batch = WriteBatch::new();
+        for key_id in 0..200 {
+            batch.set(format!("key{}", key_id), format!("{}", iter));
+        }
+        store.write_batch(batch)?;
+    }
+
+    drop(store);
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    for key_id in 0..200 {
+        assert_eq!(store.get(format!("key{}", key_id))?, Some("9".to_owned()));
+    }
+    Ok(())
+}
+
+#[test]
+fn clean_shutdown_marker() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert!(!store.was_shut_down_cleanly());
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.shutdown()?;
+    drop(store);
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert!(store.was_shut_down_cleanly());
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    drop(store);
+
+    // the marker is consumed by opening, so a crash after that is not mistaken for a clean shutdown
+    let store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert!(!store.was_shut_down_cleanly());
+    Ok(())
+}
+
+#[test]
+fn clear_replaces_log() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    for i in 0..10 {
+        store.set(format!("key{}", i), format!("value{}", i))?;
+    }
+    assert_eq!(store.len(), 10);
+    store.clear()?;
+    assert!(store.is_empty());
+    assert_eq!(store.get("key1".to_owned())?, None);
+    // only the new log and the manifest naming it remain
+    let files = file_names(&temp_dir);
+    assert_eq!(files.len(), 2);
+    assert!(files.contains(&"kvsdb.manifest".to_owned()));
+    drop(store);
+
+    // the old log is not picked up again when reopening
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert!(store.is_empty());
+    store.set("key1".to_owned(), "value2".to_owned())?;
+    drop(store);
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.len(), 1);
+    assert_eq!(store.get("key1".to_owned())?, Some("value2".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn clear_after_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    for i in 0..1000 {
+        store.set(format!("key{}", i % 200), format!("value{}", i))?;
+    }
+    store.clear()?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    assert_eq!(store.len(), 1);
+    drop(store);
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.len(), 1);
+    assert_eq!(store.get("key2".to_owned())?, None);
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn compact_now_drops_stale_records() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    for i in 0..10 {
+        store.set("key1".to_owned(), format!("value{}", i))?;
+    }
+    let log_size = || -> u64 {
+        std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().metadata().unwrap().len())
+            .sum()
+    };
+    let before = log_size();
+    store.compact_now()?;
+    assert!(log_size() < before);
+    assert_eq!(store.get("key1".to_owned())?, Some("value9".to_owned()));
+    Ok(())
+}
+
+fn open_counters(temp_dir: &TempDir) -> Result<KvStore<String, u64>> {
+    let mut store = KvStore::<String, u64>::open(temp_dir.path())?;
+    store.set_merge_operator(|_key, count, increment| count.unwrap_or(0) + increment);
+    Ok(store)
+}
+
+#[test]
+fn merge_counters() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = open_counters(&temp_dir)?;
+    store.merge("new".to_owned(), 1)?;
+    store.merge("new".to_owned(), 2)?;
+    store.set("existing".to_owned(), 10)?;
+    store.merge("existing".to_owned(), 5)?;
+    assert_eq!(store.get("new".to_owned())?, Some(3));
+    assert_eq!(store.get("existing".to_owned())?, Some(15));
+
+    // a set or remove replaces the merged value
+    store.set("new".to_owned(), 100)?;
+    store.merge("new".to_owned(), 1)?;
+    store.remove("existing".to_owned())?;
+    store.merge("existing".to_owned(), 7)?;
+    assert_eq!(store.get("new".to_owned())?, Some(101));
+    assert_eq!(store.get("existing".to_owned())?, Some(7));
+    drop(store);
+
+    let mut store = open_counters(&temp_dir)?;
+    assert_eq!(store.get("new".to_owned())?, Some(101));
+    assert_eq!(store.get("existing".to_owned())?, Some(7));
+    Ok(())
+}
+
+#[test]
+fn merge_chains_collapse_on_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = open_counters(&temp_dir)?;
+    for i in 0..5000 {
+        store.merge(format!("counter{}", i % 100), 1)?;
+    }
+    store.compact_now()?;
+    for i in 0..100 {
+        assert_eq!(store.get(format!("counter{}", i))?, Some(50));
+    }
+    drop(store);
+
+    // once compacted the values no longer need the merge operator to be read
+    let mut store = KvStore::<String, u64>::open(temp_dir.path())?;
+    assert_eq!(store.get("counter7".to_owned())?, Some(50));
+    Ok(())
+}
+
+#[test]
+fn merge_without_operator() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, u64>::open(temp_dir.path())?;
+    let err = store.merge("counter".to_owned(), 1).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::MergeOperatorMissing);
+    drop(store);
+
+    let mut store = open_counters(&temp_dir)?;
+    store.merge("counter".to_owned(), 1)?;
+    drop(store);
+    let mut store = KvStore::<String, u64>::open(temp_dir.path())?;
+    let err = store.get("counter".to_owned()).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::MergeOperatorMissing);
+    Ok(())
+}
+
+#[cfg(feature = "legacy-asn1")]
+#[test]
+fn reads_logs_written_before_merge_records() -> Result<()> {
+    #[derive(serde::Serialize)]
+    struct OldRecord {
+        db_key: u64,
+        key: String,
+        value: Option<String>,
+    }
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut log = Vec::new();
+    for (key, value) in [
+        ("key1", Some("value1")),
+        ("key2", Some("value2")),
+        ("key1", None),
+    ] {
+        let rec = OldRecord {
+            db_key: log.len() as u64,
+            key: key.to_owned(),
+            value: value.map(str::to_owned),
+        };
+        log.extend(serde_asn1_der::to_vec(&rec).unwrap());
+    }
+    std::fs::write(
+        temp_dir
+            .path()
+            .join("kvsdb-00000000000000000000000000000000.log"),
+        log,
+    )?;
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key1".to_owned())?, None);
+    assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+    let mut buf = Vec::new();
+    assert!(store.get_raw("key2".to_owned(), &mut buf)?);
+    assert_eq!(buf, b"$value2\n");
+
+    // compaction rewrites the records in the current format
+    store.compact_now()?;
+    drop(store);
+    assert_eq!(std::fs::read(log_of(&temp_dir))?.first(), Some(&b'*'));
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+    Ok(())
+}
+
+#[cfg(not(feature = "legacy-asn1"))]
+#[test]
+fn logs_written_in_asn1_der_are_unsupported_without_the_legacy_feature() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    // a record of the earlier format is an ASN.1 DER sequence
+    let log = temp_dir
+        .path()
+        .join("kvsdb-00000000000000000000000000000000.log");
+    std::fs::write(&log, [0x30, 0x03, 0x02, 0x01, 0x00])?;
+
+    match KvStore::<String, String>::open(temp_dir.path()) {
+        Err(err) => assert_eq!(*err.kind(), ErrorKind::UnsupportedFormat),
+        Ok(_) => panic!("expected UnsupportedFormat"),
+    }
+    let err = KvStore::<String, String>::verify(temp_dir.path(), true).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::UnsupportedFormat);
+    assert_eq!(std::fs::metadata(&log)?.len(), 5);
+    Ok(())
+}
+
+fn keys_by_index(store: &mut KvStore<String, String>, index_key: &str) -> Result<Vec<String>> {
+    let mut keys = store
+        .get_by_index("domain", index_key)?
+        .into_iter()
+        .map(|(key, _)| key)
+        .collect::<Vec<_>>();
+    keys.sort();
+    Ok(keys)
+}
+
+fn index_by_domain(store: &mut KvStore<String, String>) -> Result<()> {
+    store.create_index("domain", |email: &String| {
+        email.split('@').nth(1).map(str::to_owned)
+    })
+}
+
+#[test]
+fn secondary_index_is_maintained_by_writes() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    index_by_domain(&mut store)?;
+    store.set("alice".to_owned(), "alice@example.com".to_owned())?;
+    store.set("bob".to_owned(), "bob@example.com".to_owned())?;
+    store.set("carol".to_owned(), "carol@example.org".to_owned())?;
+    store.set("dave".to_owned(), "no email".to_owned())?;
+    assert_eq!(keys_by_index(&mut store, "example.com")?, ["alice", "bob"]);
+
+    store.set("bob".to_owned(), "bob@example.org".to_owned())?;
+    store.remove("alice".to_owned())?;
+    assert_eq!(
+        keys_by_index(&mut store, "example.com")?,
+        Vec::<String>::new()
+    );
+    assert_eq!(keys_by_index(&mut store, "example.org")?, ["bob", "carol"]);
+
+    let mut batch = WriteBatch::new();
+    batch.set("erin".to_owned(), "erin@example.com".to_owned());
+    batch.remove("carol".to_owned());
+    store.write_batch(batch)?;
+    assert_eq!(keys_by_index(&mut store, "example.com")?, ["erin"]);
+    assert_eq!(
+        store.get_by_index("domain", "example.org")?,
+        [("bob".to_owned(), "bob@example.org".to_owned())]
+    );
+
+    store.clear()?;
+    assert_eq!(
+        keys_by_index(&mut store, "example.org")?,
+        Vec::<String>::new()
+    );
+    Ok(())
+}
+
+#[test]
+fn secondary_index_is_built_from_existing_values() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    {
+        let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+        store.set("alice".to_owned(), "alice@example.com".to_owned())?;
+        store.set("bob".to_owned(), "bob@example.org".to_owned())?;
+    }
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    index_by_domain(&mut store)?;
+    assert_eq!(keys_by_index(&mut store, "example.com")?, ["alice"]);
+    Ok(())
+}
+
+#[test]
+fn secondary_index_is_maintained_by_merges() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = open_counters(&temp_dir)?;
+    store.create_index("parity", |count: &u64| Some((count % 2).to_string()))?;
+    store.merge("counter".to_owned(), 1)?;
+    assert_eq!(
+        store.get_by_index("parity", "1")?,
+        [("counter".to_owned(), 1)]
+    );
+    store.merge("counter".to_owned(), 1)?;
+    assert_eq!(
+        store.get_by_index("parity", "0")?,
+        [("counter".to_owned(), 2)]
+    );
+    assert!(store.get_by_index("parity", "1")?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn get_by_unknown_index() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    match store.get_by_index("domain", "example.com") {
+        Err(err) => assert_eq!(*err.kind(), ErrorKind::IndexNotFound),
+        Ok(_) => panic!("expected IndexNotFound"),
+    }
+    Ok(())
+}
+
+#[test]
+fn keyspaces_are_separate_stores() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    {
+        let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        let mut users = store.keyspace::<String, String>("users")?;
+        users.set("key1".to_owned(), "user1".to_owned())?;
+        let mut counts = store.keyspace::<String, u64>("counts")?;
+        counts.set("key1".to_owned(), 1)?;
+        store.clear()?;
+        assert_eq!(users.get("key1".to_owned())?, Some("user1".to_owned()));
+    }
+    let store = KvStore::<String, String>::open(temp_dir.path())?;
+    let mut names = store.keyspace_names()?;
+    names.sort();
+    assert_eq!(names, ["counts", "users"]);
+    let mut users = store.keyspace::<String, String>("users")?;
+    assert_eq!(users.get("key1".to_owned())?, Some("user1".to_owned()));
+    let mut counts = store.keyspace::<String, u64>("counts")?;
+    assert_eq!(counts.get("key1".to_owned())?, Some(1));
+    Ok(())
+}
+
+#[test]
+fn invalid_keyspace_names() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::<String, String>::open(temp_dir.path())?;
+    for name in ["", "..", "a/b", "white space"] {
+        match store.keyspace::<String, String>(name) {
+            Err(err) => assert_eq!(*err.kind(), ErrorKind::InvalidKeyspaceName),
+            Ok(_) => panic!("expected InvalidKeyspaceName for {:?}", name),
+        }
+    }
+    Ok(())
+}
+
+fn log_of(temp_dir: &TempDir) -> std::path::PathBuf {
+    std::fs::read_dir(temp_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().is_some_and(|extension| extension == "log"))
+        .expect("no log written")
+}
+
+#[test]
+fn verify_reports_stale_records() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    {
+        let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("key1".to_owned(), "value2".to_owned())?;
+        store.set("key2".to_owned(), "value1".to_owned())?;
+        store.remove("key2".to_owned())?;
+    }
+    let report = KvStore::<String, String>::verify(temp_dir.path(), false)?;
+    assert_eq!(report.log_path, Some(log_of(&temp_dir)));
+    assert_eq!(report.records, 4);
+    assert_eq!(report.live_records, 1);
+    assert_eq!(report.stale_records(), 3);
+    assert_eq!(report.corruption_offset, None);
+    Ok(())
+}
+
+#[test]
+fn debug_iteration_reports_every_record_with_its_state() -> Result<()> {
+    use kvs::RecordState::{Live, Superseded, Tombstone};
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.set("key1".to_owned(), "value2".to_owned())?;
+    store.set("key2".to_owned(), "value1".to_owned())?;
+    store.remove("key2".to_owned())?;
+
+    let records = store
+        .debug_iter_all_records()?
+        .collect::<Result<Vec<_>>>()?;
+    let states: Vec<_> = records
+        .iter()
+        .map(|rec| (rec.key.as_str(), rec.value.as_deref(), rec.state))
+        .collect();
+    assert_eq!(
+        states,
+        vec![
+            ("key1", Some("value1"), Superseded),
+            ("key1", Some("value2"), Live),
+            ("key2", Some("value1"), Superseded),
+            ("key2", None, Tombstone),
+        ]
+    );
+    assert_eq!(records[0].offset, 0);
+    assert!(records
+        .windows(2)
+        .all(|pair| pair[0].offset + pair[0].len == pair[1].offset));
+    assert_eq!(store.get("key1".to_owned())?, Some("value2".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn verify_repairs_corrupt_tail() -> Result<()> {
+    use std::io::Write;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    {
+        let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("key2".to_owned(), "value2".to_owned())?;
+    }
+    let log = log_of(&temp_dir);
+    let len = std::fs::metadata(&log)?.len();
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(&log)?
+        .write_all(b"~7\nD12")?;
+
+    let report = KvStore::<String, String>::verify(temp_dir.path(), false)?;
+    assert_eq!(report.records, 2);
+    assert_eq!(report.corruption_offset, Some(len));
+    assert!(!report.repaired);
+    assert_eq!(std::fs::metadata(&log)?.len(), len + 6);
+
+    let report = KvStore::<String, String>::verify(temp_dir.path(), true)?;
+    assert!(report.repaired);
+    assert_eq!(std::fs::metadata(&log)?.len(), len);
+    assert_eq!(
+        KvStore::<String, String>::verify(temp_dir.path(), false)?.corruption_offset,
+        None
+    );
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key3".to_owned(), "value3".to_owned())?;
+    drop(store);
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+    assert_eq!(store.get("key3".to_owned())?, Some("value3".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn records_changed_since_they_were_written_do_not_match_their_checksum() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.set("key2".to_owned(), "value2".to_owned())?;
+    store.sync()?;
+
+    let log = log_of(&temp_dir);
+    let mut bytes = std::fs::read(&log)?;
+    let offset = bytes.iter().rposition(|&byte| byte == b'*').unwrap() as u64;
+    let at = bytes.windows(6).position(|w| w == b"value2").unwrap();
+    bytes[at] ^= 0x02;
+    std::fs::write(&log, bytes)?;
+    let corrupt = |err: kvs::Error| *err.kind() == ErrorKind::CorruptLog { offset };
+    assert!(store.get("key2".to_owned()).is_err_and(corrupt));
+    assert!(store
+        .get_raw("key2".to_owned(), &mut Vec::new())
+        .is_err_and(corrupt));
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    drop(store);
+
+    let report = KvStore::<String, String>::verify(temp_dir.path(), false)?;
+    assert_eq!(
+        (report.records, report.corruption_offset),
+        (1, Some(offset))
+    );
+    assert!(KvStore::<String, String>::open(temp_dir.path()).is_err_and(corrupt));
+    assert!(KvStore::<String, String>::verify(temp_dir.path(), true)?.repaired);
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(store.get("key2".to_owned())?, None);
+    Ok(())
+}
+
+#[test]
+fn verify_checks_closed_logs_against_their_digest() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    // records with offsets have no checksum of their own
+    let options = StoreOptions {
+        record_format: RecordFormat::WithOffsets,
+        ..StoreOptions::default()
+    };
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    let report = KvStore::<String, String>::verify(temp_dir.path(), false)?;
+    assert_eq!(report.digests_checked, 0);
+    store.shutdown()?;
+    drop(store);
+
+    let report = KvStore::<String, String>::verify(temp_dir.path(), false)?;
+    assert_eq!(report.digests_checked, 1);
+    assert!(report.digest_mismatches.is_empty());
+
+    // a flipped bit leaves every record readable, but not the log as it was closed
+    let log = log_of(&temp_dir);
+    let mut bytes = std::fs::read(&log)?;
+    let at = bytes.windows(6).position(|w| w == b"value1").unwrap();
+    bytes[at] ^= 0x02;
+    std::fs::write(&log, bytes)?;
+    let report = KvStore::<String, String>::verify(temp_dir.path(), false)?;
+    assert_eq!(report.corruption_offset, None);
+    assert_eq!(report.digest_mismatches, vec![log]);
+
+    // once opened again the log is written to, so no longer closed
+    drop(KvStore::<String, String>::open(temp_dir.path())?);
+    let report = KvStore::<String, String>::verify(temp_dir.path(), false)?;
+    assert_eq!(report.digests_checked, 0);
+    Ok(())
+}
+
+#[test]
+fn compact_into_new_directory() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let target_dir = temp_dir.path().join("compacted");
+    let mut store = open_counters(&temp_dir)?;
+    for increment in 1..=10 {
+        store.merge("counter".to_owned(), increment)?;
+        store.set(format!("key{}", increment), increment)?;
+    }
+    store.remove("key1".to_owned())?;
+    store.compact_into(&target_dir)?;
+    assert_eq!(store.get("key1".to_owned())?, None);
+
+    let report = KvStore::<String, u64>::verify(&target_dir, false)?;
+    assert_eq!(report.records, 10);
+    assert_eq!(report.stale_records(), 0);
+    let mut compacted = KvStore::<String, u64>::open(&target_dir)?;
+    assert!(compacted.was_shut_down_cleanly());
+    assert_eq!(compacted.get("counter".to_owned())?, Some(55));
+    assert_eq!(compacted.get("key1".to_owned())?, None);
+    assert_eq!(compacted.get("key10".to_owned())?, Some(10));
+
+    match store.compact_into(&target_dir) {
+        Err(err) => assert_eq!(*err.kind(), ErrorKind::InvalidConfiguration),
+        Ok(()) => panic!("expected InvalidConfiguration"),
+    }
+    Ok(())
+}
+
+#[test]
+fn errors_carry_context_and_source() -> Result<()> {
+    use std::error::Error;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    let err = store.remove("key1".to_owned()).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::KeyNotPresent);
+    assert_eq!(err.key(), Some("\"key1\""));
+    assert_eq!(
+        err.to_string(),
+        "Key not present in database (key \"key1\")"
+    );
+
+    let missing = temp_dir.path().join("missing");
+    let err = KvStore::<String, String>::verify(&missing, false).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::IoError);
+    assert_eq!(err.path(), Some(missing.as_path()));
+    let source = err
+        .source()
+        .and_then(|source| source.downcast_ref::<std::io::Error>())
+        .expect("expected the io::Error as the source");
+    assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+    Ok(())
+}
+
+#[test]
+fn get_of_unreadable_record_is_index_inconsistent() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.sync()?;
+    let first_record_len = std::fs::metadata(log_of(&temp_dir))?.len();
+    store.set("key2".to_owned(), "value2".to_owned())?;
+    store.sync()?;
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(log_of(&temp_dir))?
+        .set_len(first_record_len)?;
+
+    let err = store.get("key2".to_owned()).unwrap_err();
+    assert_eq!(
+        *err.kind(),
+        ErrorKind::IndexInconsistent {
+            offset: first_record_len
+        }
+    );
+    assert_eq!(err.key(), Some("\"key2\""));
+    assert_eq!(err.offset(), Some(first_record_len));
+
+    store.set_rebuild_index_on_inconsistency(true);
+    assert_eq!(store.get("key2".to_owned())?, None);
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(store.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn index_is_rebuilt_at_runtime_reporting_progress() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    for i in 0..10 {
+        store.set(format!("key{}", i % 4), format!("value{}", i))?;
+    }
+    store.sync()?;
+    let log_len = std::fs::metadata(log_of(&temp_dir))?.len();
+
+    let mut reported = Vec::new();
+    store.rebuild_index_with_progress(|progress| reported.push(progress))?;
+    assert_eq!(reported.len(), 10);
+    assert_eq!(reported[0].offset, 0);
+    assert!(reported
+        .windows(2)
+        .all(|pair| pair[0].offset < pair[1].offset));
+    assert!(reported
+        .iter()
+        .enumerate()
+        .all(|(i, progress)| progress.records == i as u64 + 1 && progress.log_len == log_len));
+    assert_eq!(store.len(), 4);
+    assert_eq!(store.get("key1".to_owned())?, Some("value9".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn index_snapshot_is_loaded_after_clean_shutdown() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    {
+        let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("key1".to_owned(), "value2".to_owned())?;
+        store.set("key2".to_owned(), "value3".to_owned())?;
+        store.shutdown()?;
+    }
+    // the snapshot is written to the hint file of the log, listed by the manifest until consumed
+    let log = log_of(&temp_dir);
+    let snapshot = log.with_extension("hint");
+    let manifest = temp_dir.path().join("kvsdb.manifest");
+    let segment = format!(
+        "segment {} {}",
+        &log.file_stem().unwrap().to_string_lossy()[6..],
+        log.file_name().unwrap().to_string_lossy()
+    );
+    // with the digest of the log, closed until the store is opened again
+    let bytes = std::fs::read(&log)?;
+    let digest = format!("digest={}:{:08x}", bytes.len(), crc32fast::hash(&bytes));
+    assert_eq!(
+        std::fs::read_to_string(&manifest)?,
+        format!(
+            "kvs manifest 2\n{} {} {}\n",
+            segment,
+            snapshot.file_name().unwrap().to_string_lossy(),
+            digest
+        )
+    );
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert!(!snapshot.exists());
+    assert_eq!(
+        std::fs::read_to_string(&manifest)?,
+        format!("kvs manifest 2\n{}\n", segment)
+    );
+    assert_eq!(store.len(), 2);
+    assert_eq!(store.get("key1".to_owned())?, Some("value2".to_owned()));
+    assert_eq!(store.get("key2".to_owned())?, Some("value3".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn stale_or_corrupt_index_snapshot_falls_back_to_replay() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    {
+        let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.shutdown()?;
+    }
+    let snapshot = log_of(&temp_dir).with_extension("hint");
+    // the store is written after the snapshot was taken
+    {
+        let saved = std::fs::read(&snapshot)?;
+        let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+        store.set("key2".to_owned(), "value2".to_owned())?;
+        store.shutdown()?;
+        drop(store);
+        std::fs::write(&snapshot, saved)?;
+    }
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+    store.shutdown()?;
+    drop(store);
+
+    // the snapshot is corrupted
+    let mut bytes = std::fs::read(&snapshot)?;
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    std::fs::write(&snapshot, bytes)?;
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.len(), 2);
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    Ok(())
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn stats_record_latency_of_each_operation() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    let stats = store.stats();
+    assert_eq!(stats.set, kvs::LatencyStats::default());
+    for i in 0..10 {
+        store.set(format!("key{}", i), "value".to_owned())?;
+    }
+    store.get("key0".to_owned())?;
+    store.remove("key0".to_owned())?;
+    assert!(store.remove("key0".to_owned()).is_err());
+    store.compact_now()?;
+    store.set_merge_operator(|_key, value, operand| value.unwrap_or_default() + &operand);
+    store.merge("key1".to_owned(), "value".to_owned())?;
+
+    let stats = store.stats();
+    assert_eq!(stats.set.count, 11);
+    assert_eq!(stats.get.count, 1);
+    assert_eq!(stats.remove.count, 2);
+    assert_eq!(stats.compaction.count, 1);
+    assert!(stats.set.min <= stats.set.p50 && stats.set.p50 <= stats.set.max);
+    assert!(stats.compaction.max > std::time::Duration::from_nanos(0));
+    Ok(())
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn stats_count_bytes_written_since_last_sync() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.stats().unsynced_bytes, 0);
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    let after_one = store.stats().unsynced_bytes;
+    assert!(after_one > 0);
+    store.set("key2".to_owned(), "value2".to_owned())?;
+    let stats = store.stats();
+    assert_eq!(
+        stats.unsynced_bytes,
+        std::fs::metadata(log_of(&temp_dir))?.len()
+    );
+    assert_eq!((stats.pending_batch_bytes, stats.unflushed_records), (0, 0));
+
+    store.sync()?;
+    assert_eq!(store.stats().unsynced_bytes, 0);
+    store.remove("key1".to_owned())?;
+    assert!(store.stats().unsynced_bytes > 0);
+    store.compact_now()?;
+    assert_eq!(store.stats().unsynced_bytes, 0);
+    Ok(())
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn stats_record_sizes_of_keys_and_values_written() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.stats().value_sizes, kvs::SizeStats::default());
+    for i in 0..100 {
+        store.set(format!("key{:03}", i), "x".repeat(10))?;
+    }
+    store.set("large".to_owned(), "x".repeat(1000))?;
+    store.remove("key000".to_owned())?;
+
+    // strings are encoded in the log with a tag and a length before them
+    let stats = store.stats();
+    assert_eq!(stats.key_sizes.count, 102);
+    assert_eq!((stats.key_sizes.p50, stats.key_sizes.max), (8, 8));
+    assert_eq!(stats.value_sizes.count, 101);
+    assert_eq!(stats.value_sizes.p50, 12);
+    assert_eq!(stats.value_sizes.p99, 12);
+    assert!(stats.value_sizes.max >= 1000);
+    Ok(())
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn stats_are_not_recorded_while_the_store_is_not_instrumented() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = StoreOptions {
+        instrumented: false,
+        ..StoreOptions::default()
+    };
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.get("key1".to_owned())?;
+    let stats = store.stats();
+    assert_eq!(stats.set, kvs::LatencyStats::default());
+    assert_eq!(stats.value_sizes, kvs::SizeStats::default());
+
+    store.reconfigure(RuntimeOptions {
+        instrumented: true,
+        ..store.runtime_options()
+    })?;
+    store.set("key1".to_owned(), "value2".to_owned())?;
+    let stats = store.stats();
+    assert_eq!((stats.set.count, stats.value_sizes.count), (1, 1));
+    Ok(())
+}
+
+#[test]
+fn records_of_either_format_are_read_from_the_same_log() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = StoreOptions {
+        record_format: RecordFormat::WithOffsets,
+        ..StoreOptions::default()
+    };
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.sync()?;
+    let with_offsets = std::fs::metadata(log_of(&temp_dir))?.len();
+    drop(store);
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key2".to_owned(), "value2".to_owned())?;
+    store.sync()?;
+    let compact = std::fs::metadata(log_of(&temp_dir))?.len() - with_offsets;
+    let log = std::fs::read(log_of(&temp_dir))?;
+    assert_eq!((log[0], log[with_offsets as usize]), (b'~', b'*'));
+    drop(store);
+
+    // replayed, as neither store was shut down cleanly
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+    let mut buf = Vec::new();
+    for key in &["key1", "key2"] {
+        assert!(store.get_raw(key.to_string(), &mut buf)?);
+    }
+    let report = KvStore::<String, String>::verify(temp_dir.path(), false)?;
+    assert_eq!((report.records, report.corruption_offset), (2, None));
+
+    // compaction rewrites the log in the format of the store
+    store.compact_now()?;
+    assert_eq!(std::fs::metadata(log_of(&temp_dir))?.len(), 2 * compact);
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn store_open_in_the_process_is_not_opened_again() -> Result<()> {
+    use kvs::{KvsEngine, SharedKvStore};
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert!(matches!(
+        KvStore::<String, String>::open(&temp_dir.path().join(".")).map_err(|err| *err.kind()),
+        Err(ErrorKind::AlreadyOpen)
+    ));
+    assert!(matches!(
+        KvStore::<String, String>::new(temp_dir.path()).map_err(|err| *err.kind()),
+        Err(ErrorKind::AlreadyOpen)
+    ));
+    let options = StoreOptions {
+        file_prefix: "other".to_owned(),
+        ..StoreOptions::default()
+    };
+    KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    drop(store);
+
+    // a SharedKvStore is shared instead, until every handle of it is dropped
+    let shared = SharedKvStore::open(temp_dir.path())?;
+    shared.set("key1".to_owned(), "value1".to_owned())?;
+    let again = SharedKvStore::open(temp_dir.path())?;
+    assert_eq!(again.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert!(matches!(
+        KvStore::<String, String>::open(temp_dir.path()).map_err(|err| *err.kind()),
+        Err(ErrorKind::AlreadyOpen)
+    ));
+    drop((shared, again));
+    KvStore::<String, String>::open(temp_dir.path())?;
+    Ok(())
+}
+
+#[test]
+fn temporary_stores_have_directories_of_their_own_removed_when_dropped() -> Result<()> {
+    let mut store = KvStore::<String, String>::temporary()?;
+    let other = KvStore::<String, u64>::temporary()?;
+    assert_ne!(store.dir(), other.dir());
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.compact_now()?;
+    let dir = store.dir().to_owned();
+    assert!(dir.starts_with(std::env::temp_dir()));
+
+    let mut store = store.migrate_values(|_, value| value.len() as u64)?;
+    assert_eq!(store.get("key1".to_owned())?, Some(6));
+    assert!(dir.is_dir());
+    drop(store);
+    assert!(!dir.exists());
+    assert!(other.dir().is_dir());
+    Ok(())
+}
+
+#[test]
+fn log_file_names_are_matched_ignoring_case() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    drop(store);
+    let log = log_of(&temp_dir);
+    let upper = log.file_name().unwrap().to_string_lossy().to_uppercase();
+    std::fs::rename(&log, temp_dir.path().join(upper))?;
+    for ignored in [
+        "kvsdb-notanid.log",
+        "kvsdb-00000000000000000000000000000000.log.bak",
+    ] {
+        std::fs::write(temp_dir.path().join(ignored), b"not a log")?;
+    }
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn stores_with_different_file_prefixes_share_a_directory() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = StoreOptions {
+        file_prefix: "other-store".to_owned(),
+        ..StoreOptions::default()
+    };
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    let mut other = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    other.set("key1".to_owned(), "other1".to_owned())?;
+    other.compact_now()?;
+    store.shutdown()?;
+    other.shutdown()?;
+    drop((store, other));
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    let mut other = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    assert!(store.was_shut_down_cleanly() && other.was_shut_down_cleanly());
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(other.get("key1".to_owned())?, Some("other1".to_owned()));
+
+    let options = StoreOptions {
+        file_prefix: "../escape".to_owned(),
+        ..StoreOptions::default()
+    };
+    match KvStore::<String, String>::open_with_options(temp_dir.path(), &options) {
+        Err(err) => assert_eq!(*err.kind(), ErrorKind::InvalidConfiguration),
+        Ok(_) => panic!("expected InvalidConfiguration"),
+    }
+    Ok(())
+}
+
+#[test]
+fn stores_open_in_missing_directories_but_not_over_files() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let nested = temp_dir.path().join("a").join("b");
+    let mut store = KvStore::<String, String>::open(&nested)?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    assert!(nested.is_dir());
+
+    let file = temp_dir.path().join("file");
+    std::fs::write(&file, "not a directory")?;
+    match KvStore::<String, String>::open(&file) {
+        Err(err) => {
+            assert_eq!(*err.kind(), ErrorKind::InvalidConfiguration);
+            assert_eq!(err.path(), Some(file.as_path()));
+        }
+        Ok(_) => panic!("expected InvalidConfiguration"),
+    }
+    match KvStore::<String, String>::open(&file.join("store")) {
+        Err(err) => assert_eq!(err.path(), Some(file.join("store").as_path())),
+        Ok(_) => panic!("expected an error creating the directory"),
+    }
+    Ok(())
+}
+
+fn file_names(temp_dir: &TempDir) -> Vec<String> {
+    let mut names = std::fs::read_dir(temp_dir.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+#[test]
+fn leftovers_of_interrupted_compaction_are_removed_on_open() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let compacted_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "old".to_owned())?;
+    drop(store);
+    let mut compacted = KvStore::<String, String>::open(compacted_dir.path())?;
+    compacted.set("key1".to_owned(), "old".to_owned())?;
+    compacted.compact_now()?;
+    compacted.set("key1".to_owned(), "new".to_owned())?;
+    drop(compacted);
+    let old_log = log_of(&temp_dir);
+    let new_log = log_of(&compacted_dir);
+    assert!(new_log.file_name() > old_log.file_name());
+    let copy_new_log = || {
+        std::fs::copy(&new_log, temp_dir.path().join(new_log.file_name().unwrap())).unwrap();
+        let interrupted = new_log.with_extension("compact");
+        std::fs::write(
+            temp_dir.path().join(interrupted.file_name().unwrap()),
+            b"partial",
+        )
+        .unwrap();
+    };
+
+    // as if compaction had renamed its log into place (its generation following that of the old
+    // log) but crashed before naming it in the manifest, and was compacting again
+    copy_new_log();
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key1".to_owned())?, Some("old".to_owned()));
+    drop(store);
+    let old_files = file_names(&temp_dir);
+    assert_eq!(old_files.len(), 2);
+    assert!(old_files.contains(&"kvsdb.manifest".to_owned()));
+
+    // as if it had crashed after naming the new log in the manifest, before removing the old log
+    copy_new_log();
+    std::fs::copy(
+        compacted_dir.path().join("kvsdb.manifest"),
+        temp_dir.path().join("kvsdb.manifest"),
+    )?;
+    // the old log being modified later must not matter
+    std::fs::File::options()
+        .append(true)
+        .open(&old_log)?
+        .set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(60))?;
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key1".to_owned())?, Some("new".to_owned()));
+    assert_eq!(file_names(&temp_dir), file_names(&compacted_dir));
+    Ok(())
+}
+
+#[test]
+fn manifest_naming_a_missing_log_is_corrupt() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    drop(store);
+    let log = log_of(&temp_dir);
+    let renamed = log.with_file_name("kvsdb-000000000000000000000000000000ff.log");
+    std::fs::rename(&log, &renamed)?;
+    match KvStore::<String, String>::open(temp_dir.path()) {
+        Err(err) => {
+            assert_eq!(*err.kind(), ErrorKind::CorruptManifest);
+            assert_eq!(err.path(), Some(log.as_path()));
+        }
+        Ok(_) => panic!("expected CorruptManifest"),
+    }
+
+    // without its manifest, the store opens its log of the highest generation
+    std::fs::remove_file(temp_dir.path().join("kvsdb.manifest"))?;
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn manifest_of_earlier_version_is_read() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.compact_now()?;
+    drop(store);
+    let log = log_of(&temp_dir);
+    let generation = log.file_stem().unwrap().to_string_lossy()[6..].to_owned();
+    std::fs::write(
+        temp_dir.path().join("kvsdb.manifest"),
+        format!("kvs manifest 1\ngeneration {}\n", generation),
+    )?;
+    // a log of an earlier generation is not listed, so is removed rather than opened
+    std::fs::copy(
+        &log,
+        log.with_file_name("kvsdb-00000000000000000000000000000000.log"),
+    )?;
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(file_names(&temp_dir).len(), 2);
+    Ok(())
+}
+
+#[test]
+fn upgrade_layout_converts_a_uuid_log_store() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let backup_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.set("key2".to_owned(), "value2".to_owned())?;
+    store.remove("key2".to_owned())?;
+    drop(store);
+    // leaves the single log, named with a UUID, of the first versions
+    let log = std::fs::read(log_of(&temp_dir))?;
+    for name in file_names(&temp_dir) {
+        std::fs::remove_file(temp_dir.path().join(name))?;
+    }
+    let uuid_log = temp_dir
+        .path()
+        .join("kvsdb-6f1c2e0a9b8d4c3e8a7f5d4c3b2a1908.log");
+    std::fs::write(&uuid_log, log)?;
+    assert_eq!(
+        DataLayout::detect(temp_dir.path())?,
+        Some(DataLayout::UuidLog)
+    );
+
+    KvStore::<String, String>::upgrade_layout(
+        temp_dir.path(),
+        DataLayout::UuidLog,
+        backup_dir.path(),
+    )?;
+    assert_eq!(
+        file_names(&backup_dir),
+        vec!["kvsdb-6f1c2e0a9b8d4c3e8a7f5d4c3b2a1908.log"]
+    );
+    assert_eq!(
+        DataLayout::detect(temp_dir.path())?,
+        Some(DataLayout::Manifest)
+    );
+    assert!(!uuid_log.exists());
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(store.get("key2".to_owned())?, None);
+    drop(store);
+
+    let err = KvStore::<String, String>::upgrade_layout(
+        temp_dir.path(),
+        DataLayout::UuidLog,
+        backup_dir.path(),
+    )
+    .unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::InvalidConfiguration);
+    Ok(())
+}
+
+#[test]
+fn compaction_drops_tombstones_unless_a_retained_segment_holds_the_key() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "old".to_owned())?;
+    drop(store);
+    // the log becomes an older segment, retained by the compaction of the active segment
+    let old_log = log_of(&temp_dir);
+    let active_log = old_log.with_file_name("kvsdb-00000000000000000000000000000001.log");
+    std::fs::write(&active_log, b"")?;
+    std::fs::write(
+        temp_dir.path().join("kvsdb.manifest"),
+        format!(
+            "kvs manifest 2\nsegment {:032x} {}\nsegment {:032x} {}\n",
+            0,
+            old_log.file_name().unwrap().to_string_lossy(),
+            1,
+            active_log.file_name().unwrap().to_string_lossy()
+        ),
+    )?;
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    for key in ["key1", "key2"] {
+        store.set(key.to_owned(), "new".to_owned())?;
+        store.remove(key.to_owned())?;
+    }
+    store.set("key3".to_owned(), "value3".to_owned())?;
+    store.compact_now()?;
+    drop(store);
+
+    // only the tombstone of key1, hiding its record in the older segment, is kept
+    let report = KvStore::<String, String>::verify(temp_dir.path(), false)?;
+    assert_eq!((report.records, report.live_records), (2, 1));
+    assert!(old_log.exists());
+    Ok(())
+}
+
+#[test]
+fn key_quota_rejects_new_keys_once_reached() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = StoreOptions {
+        max_keys: Some(2),
+        ..StoreOptions::default()
+    };
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.set("key2".to_owned(), "value2".to_owned())?;
+    let err = store
+        .set("key3".to_owned(), "value3".to_owned())
+        .unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::QuotaExceeded);
+    store.set("key2".to_owned(), "value2b".to_owned())?;
+
+    let mut batch = WriteBatch::new();
+    batch
+        .set("key3".to_owned(), "value3".to_owned())
+        .set("key4".to_owned(), "value4".to_owned())
+        .remove("key1".to_owned());
+    let err = store.write_batch(batch).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::QuotaExceeded);
+    assert_eq!(store.len(), 2);
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+
+    store.remove("key1".to_owned())?;
+    store.set("key3".to_owned(), "value3".to_owned())?;
+    drop(store);
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key1".to_owned())?, None);
+    assert_eq!(store.get("key2".to_owned())?, Some("value2b".to_owned()));
+    assert_eq!(store.get("key3".to_owned())?, Some("value3".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn reconfigure_changes_quotas_of_an_open_store() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.runtime_options(), RuntimeOptions::default());
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.set("key2".to_owned(), "value2".to_owned())?;
+
+    let options = RuntimeOptions {
+        max_keys: Some(2),
+        ..store.runtime_options()
+    };
+    store.reconfigure(options)?;
+    assert_eq!(store.runtime_options(), options);
+    let err = store
+        .set("key3".to_owned(), "value3".to_owned())
+        .unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::QuotaExceeded);
+
+    let invalid = RuntimeOptions {
+        compaction_stale_fraction: 0.0,
+        ..RuntimeOptions::default()
+    };
+    let err = store.reconfigure(invalid).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::InvalidConfiguration);
+    assert_eq!(store.runtime_options(), options);
+
+    store.reconfigure(RuntimeOptions::default())?;
+    store.set("key3".to_owned(), "value3".to_owned())?;
+    Ok(())
+}
+
+#[test]
+fn log_quota_rejects_writes_taking_the_log_past_it() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = StoreOptions {
+        max_log_bytes: Some(200),
+        ..StoreOptions::default()
+    };
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    let mut written = 0;
+    let err = loop {
+        match store.set("key".to_owned(), format!("value{}", written)) {
+            Ok(()) => written += 1,
+            Err(err) => break err,
+        }
+    };
+    assert_eq!(*err.kind(), ErrorKind::QuotaExceeded);
+    assert!(written > 1);
+    let log_len = std::fs::metadata(log_of(&temp_dir))?.len();
+    assert!(log_len <= 200);
+    assert_eq!(
+        store.get("key".to_owned())?,
+        Some(format!("value{}", written - 1))
+    );
+
+    store.compact_now()?;
+    store.set("key".to_owned(), "value".to_owned())?;
+    drop(store);
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key".to_owned())?, Some("value".to_owned()));
+    Ok(())
+}
+
+fn open_cache(temp_dir: &TempDir, eviction: EvictionPolicy) -> Result<KvStore<String, String>> {
+    let options = StoreOptions {
+        max_keys: Some(3),
+        eviction,
+        ..StoreOptions::default()
+    };
+    KvStore::open_with_options(temp_dir.path(), &options)
+}
+
+#[test]
+fn least_recently_used_keys_are_evicted() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = open_cache(&temp_dir, EvictionPolicy::LeastRecentlyUsed)?;
+    for i in 1..=3 {
+        store.set(format!("key{}", i), format!("value{}", i))?;
+    }
+    store.get("key1".to_owned())?;
+    store.set("key4".to_owned(), "value4".to_owned())?;
+    assert_eq!(store.len(), 3);
+    assert_eq!(store.get("key2".to_owned())?, None);
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+
+    let mut batch = WriteBatch::new();
+    batch
+        .set("key5".to_owned(), "value5".to_owned())
+        .set("key6".to_owned(), "value6".to_owned());
+    store.write_batch(batch)?;
+    assert_eq!(store.len(), 3);
+    let mut keys = store.keys().cloned().collect::<Vec<_>>();
+    keys.sort();
+    assert_eq!(keys, vec!["key1", "key5", "key6"]);
+    drop(store);
+
+    // evictions are written, and keys are ranked in the order they were written on open
+    let mut store = open_cache(&temp_dir, EvictionPolicy::LeastRecentlyUsed)?;
+    assert_eq!(store.len(), 3);
+    store.set("key7".to_owned(), "value7".to_owned())?;
+    assert_eq!(store.get("key1".to_owned())?, None);
+    assert_eq!(store.get("key5".to_owned())?, Some("value5".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn least_frequently_used_keys_are_evicted() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = open_cache(&temp_dir, EvictionPolicy::LeastFrequentlyUsed)?;
+    for i in 1..=3 {
+        store.set(format!("key{}", i), format!("value{}", i))?;
+    }
+    store.get("key1".to_owned())?;
+    store.get("key2".to_owned())?;
+    store.get("key2".to_owned())?;
+    store.set("key4".to_owned(), "value4".to_owned())?;
+    assert_eq!(store.get("key3".to_owned())?, None);
+    store.set("key5".to_owned(), "value5".to_owned())?;
+    assert_eq!(store.get("key4".to_owned())?, None);
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+    assert_eq!(store.get("key5".to_owned())?, Some("value5".to_owned()));
+    Ok(())
+}
+
+#[test]
+fn values_are_read_as_of_a_log_position() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    let empty = store.current_position()?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.set_merge_operator(|_key, list, item| list.unwrap_or_default() + &item);
+    store.merge("key2".to_owned(), "a".to_owned())?;
+    let first = store.current_position()?;
+    store.set("key1".to_owned(), "value2".to_owned())?;
+    store.merge("key2".to_owned(), "b".to_owned())?;
+    let second = store.current_position()?;
+    store.remove("key1".to_owned())?;
+
+    assert_eq!(store.get_as_of("key1".to_owned(), empty)?, None);
+    assert_eq!(
+        store.get_as_of("key1".to_owned(), first)?,
+        Some("value1".to_owned())
+    );
+    assert_eq!(
+        store.get_as_of("key2".to_owned(), first)?,
+        Some("a".to_owned())
+    );
+    assert_eq!(
+        store.get_as_of("key1".to_owned(), second)?,
+        Some("value2".to_owned())
+    );
+    assert_eq!(
+        store.get_as_of("key2".to_owned(), second)?,
+        Some("ab".to_owned())
+    );
+    let current = store.current_position()?;
+    assert_eq!(store.get_as_of("key1".to_owned(), current)?, None);
+    assert_eq!(store.get("key2".to_owned())?, Some("ab".to_owned()));
+
+    store.compact_now()?;
+    let err = store.get_as_of("key1".to_owned(), first).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::PositionUnavailable);
+    let current = store.current_position()?;
+    assert_eq!(
+        store.get_as_of("key2".to_owned(), current)?,
+        Some("ab".to_owned())
+    );
+    Ok(())
+}
+
+#[test]
+fn tail_yields_the_writes_since_a_position() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key0".to_owned(), "value0".to_owned())?;
+    let start = store.current_position()?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.set_merge_operator(|_key, list, item| list.unwrap_or_default() + &item);
+    store.merge("key1".to_owned(), "a".to_owned())?;
+    let mut batch = WriteBatch::new();
+    batch.remove("key0".to_owned());
+    store.write_batch(batch)?;
+
+    let changes = store.tail(start)?.collect::<Result<Vec<_>>>()?;
+    let writes = changes
+        .iter()
+        .map(|change| (change.key.as_str(), change.value.as_deref(), change.merge))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        writes,
+        vec![
+            ("key1", Some("value1"), false),
+            ("key1", Some("a"), true),
+            ("key0", None, false),
+        ]
+    );
+    assert_eq!(
+        store.get_as_of("key1".to_owned(), changes[1].position)?,
+        Some("value1a".to_owned())
+    );
+
+    let resume = changes.last().unwrap().position;
+    assert_eq!(store.tail(resume)?.count(), 0);
+    store.set("key2".to_owned(), "value2".to_owned())?;
+    let changes = store.tail(resume)?.collect::<Result<Vec<_>>>()?;
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].key, "key2");
+
+    store.compact_now()?;
+    match store.tail(resume) {
+        Err(err) => assert_eq!(*err.kind(), ErrorKind::PositionUnavailable),
+        Ok(_) => panic!("expected PositionUnavailable"),
+    }
+    Ok(())
+}
+
+#[test]
+fn versions_increase_across_writes_reopening_and_compaction() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get_versioned("key1".to_owned())?, None);
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    let (_, first) = store.get_versioned("key1".to_owned())?.unwrap();
+    store.remove("key1".to_owned())?;
+    store.set("key1".to_owned(), "value2".to_owned())?;
+    let (value, second) = store.get_versioned("key1".to_owned())?.unwrap();
+    assert_eq!(value, "value2");
+    assert!(second.sequence() > first.sequence());
+    assert!(second.timestamp() >= first.timestamp());
+
+    store.compact_now()?;
+    assert_eq!(store.get_versioned("key1".to_owned())?.unwrap().1, second);
+    store.shutdown()?;
+    drop(store);
+
+    // the next sequence number is restored from the snapshot, and from the log when replayed
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key2".to_owned(), "value2".to_owned())?;
+    let (_, third) = store.get_versioned("key2".to_owned())?.unwrap();
+    assert!(third.sequence() > second.sequence());
+    drop(store);
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key3".to_owned(), "value3".to_owned())?;
+    let (_, fourth) = store.get_versioned("key3".to_owned())?.unwrap();
+    assert!(fourth.sequence() > third.sequence());
+
+    let position = store.current_position()?;
+    store.set("key3".to_owned(), "value4".to_owned())?;
+    let change = store.tail(position)?.next().unwrap()?;
+    assert_eq!(
+        Some(change.version),
+        store
+            .get_versioned("key3".to_owned())?
+            .map(|(_, version)| version)
+    );
+    Ok(())
+}
+
+#[test]
+fn set_if_version_applies_only_at_the_expected_version() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    let (_, version) = store.get_versioned("key1".to_owned())?.unwrap();
+    assert!(store.set_if_version("key1".to_owned(), "value2".to_owned(), version)?);
+    assert!(!store.set_if_version("key1".to_owned(), "value3".to_owned(), version)?);
+    assert_eq!(store.get("key1".to_owned())?, Some("value2".to_owned()));
+
+    store.remove("key1".to_owned())?;
+    assert!(!store.set_if_version("key1".to_owned(), "value3".to_owned(), version)?);
+    assert_eq!(store.get("key1".to_owned())?, None);
+    Ok(())
+}
+
+#[test]
+fn locks_expire_and_are_taken_over_with_a_greater_token() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let clock = kvs::ManualClock::default();
+    let options = StoreOptions {
+        clock: std::sync::Arc::new(clock.clone()),
+        ..StoreOptions::default()
+    };
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    let ttl = std::time::Duration::from_millis(100);
+    let first = store.acquire_lock("job", ttl)?.unwrap();
+    assert_eq!(store.acquire_lock("job", ttl)?, None);
+    clock.advance(ttl / 2);
+    let refreshed = store.refresh_lock("job", first.token, ttl)?.unwrap();
+    assert_eq!(refreshed.token, first.token);
+    assert_eq!(refreshed.expires, first.expires + ttl / 2);
+
+    clock.advance(ttl);
+    assert_eq!(store.refresh_lock("job", first.token, ttl)?, None);
+    let second = store.acquire_lock("job", ttl)?.unwrap();
+    assert!(second.token > first.token);
+    assert!(!store.release_lock("job", first.token)?);
+    assert!(store.release_lock("job", second.token)?);
+    assert_eq!(store.get("job".to_owned())?, None);
+
+    // a key holding something other than a lock is never acquired
+    store.set("data".to_owned(), "value".to_owned())?;
+    assert_eq!(store.acquire_lock("data", ttl)?, None);
+    Ok(())
+}
+
+#[test]
+fn writes_are_timestamped_by_the_clock_of_the_store() -> Result<()> {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let clock = kvs::ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_000));
+    let options = StoreOptions {
+        clock: std::sync::Arc::new(clock.clone()),
+        ..StoreOptions::default()
+    };
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    clock.advance(Duration::from_secs(1));
+    let mut sessions = store.keyspace::<String, String>("sessions")?;
+    sessions.set("key1".to_owned(), "value1".to_owned())?;
+
+    let (_, version) = store.get_versioned("key1".to_owned())?.unwrap();
+    assert_eq!(version.timestamp(), UNIX_EPOCH + Duration::from_secs(1_000));
+    let (_, version) = sessions.get_versioned("key1".to_owned())?.unwrap();
+    assert_eq!(version.timestamp(), UNIX_EPOCH + Duration::from_secs(1_001));
+    Ok(())
+}
+
+#[test]
+fn removed_keys_are_kept_in_the_trash_until_their_retention_ends() -> Result<()> {
+    use std::time::Duration;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let clock = kvs::ManualClock::default();
+    let day = Duration::from_secs(24 * 60 * 60);
+    let options = StoreOptions {
+        clock: std::sync::Arc::new(clock.clone()),
+        trash_retention: Some(day * 7),
+        ..StoreOptions::default()
+    };
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    store.set("old".to_owned(), "value1".to_owned())?;
+    store.set("kept".to_owned(), "value2".to_owned())?;
+    store.set("reset".to_owned(), "value3".to_owned())?;
+    store.remove("old".to_owned())?;
+    clock.advance(day * 2);
+    let mut batch = WriteBatch::new();
+    batch
+        .set("kept".to_owned(), "value4".to_owned())
+        .remove("kept".to_owned());
+    store.write_batch(batch)?;
+    store.remove("reset".to_owned())?;
+    store.set("reset".to_owned(), "value5".to_owned())?;
+
+    let mut trashed = store.trashed_keys().cloned().collect::<Vec<_>>();
+    trashed.sort();
+    assert_eq!(trashed, vec!["kept", "old"]);
+    assert_eq!(store.get("kept".to_owned())?, None);
+    assert_eq!(
+        store.get_trashed("kept".to_owned())?,
+        Some("value4".to_owned())
+    );
+    assert_eq!(store.get_trashed("reset".to_owned())?, None);
+
+    // the trash is rebuilt from the log, and kept by compaction until the retention ends
+    drop(store);
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    clock.advance(day * 6);
+    store.compact_now()?;
+    assert_eq!(store.trashed_keys().collect::<Vec<_>>(), vec!["kept"]);
+    assert_eq!(store.get_trashed("old".to_owned())?, None);
+    assert_eq!(
+        store.restore_key("old".to_owned()).unwrap_err().kind(),
+        &ErrorKind::KeyNotPresent
+    );
+    store.shutdown()?;
+    drop(store);
+
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    assert!(store.was_shut_down_cleanly());
+    assert_eq!(
+        store.get_trashed("kept".to_owned())?,
+        Some("value4".to_owned())
+    );
+    store.restore_key("kept".to_owned())?;
+    assert_eq!(store.get("kept".to_owned())?, Some("value4".to_owned()));
+    assert_eq!(store.trashed_keys().count(), 0);
+
+    // without a retention, removals are permanent
+    drop(store);
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.remove("kept".to_owned())?;
+    assert_eq!(store.get_trashed("kept".to_owned())?, None);
+    Ok(())
+}
+
+#[test]
+fn typed_stores_share_a_store_under_their_prefixes() -> Result<()> {
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct UserProfile {
+        name: String,
+        age: u32,
+    }
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Order {
+        user: String,
+        total: u64,
+    }
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    let alice = UserProfile {
+        name: "Alice".to_owned(),
+        age: 30,
+    };
+    let bob = UserProfile {
+        name: "Bob".to_owned(),
+        age: 25,
+    };
+    let mut users = store.typed::<UserProfile>("user:");
+    users.set("2", &bob)?;
+    users.set("1", &alice)?;
+    store.typed::<Order>("order:").set(
+        "1",
+        &Order {
+            user: "1".to_owned(),
+            total: 100,
+        },
+    )?;
+
+    let mut users = store.typed::<UserProfile>("user:");
+    assert_eq!(users.get("1")?, Some(alice));
+    assert_eq!(users.get("3")?, None);
+    let scanned = users.scan()?;
+    assert_eq!(
+        scanned
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect::<Vec<_>>(),
+        vec!["1", "2"]
+    );
+    assert_eq!(scanned[1].1, bob);
+    users.remove("2")?;
+    assert_eq!(
+        users.remove("2").unwrap_err().kind(),
+        &ErrorKind::KeyNotPresent
+    );
+    assert_eq!(store.len(), 2);
+    assert_eq!(store.typed::<Order>("order:").scan()?.len(), 1);
+
+    // a value which is not of the type fails to decode
+    store.set("user:9".to_owned(), "not json".to_owned())?;
+    let err = store.typed::<UserProfile>("user:").get("9").unwrap_err();
+    assert_eq!(err.kind(), &ErrorKind::DeserializationError);
+    assert_eq!(err.key(), Some("\"user:9\""));
+    Ok(())
+}
+
+#[test]
+fn values_the_format_cannot_encode_fail_to_be_set() -> Result<()> {
+    #[derive(Clone, serde::Serialize, serde::Deserialize)]
+    struct Tagged {
+        name: String,
+        #[serde(flatten)]
+        tags: std::collections::HashMap<String, String>,
+    }
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, Tagged>::open(temp_dir.path())?;
+    let tagged = Tagged {
+        name: "name".to_owned(),
+        tags: [("tag".to_owned(), "value".to_owned())].into(),
+    };
+    let err = store.set("key".to_owned(), tagged).err().unwrap();
+    assert_eq!(err.kind(), &ErrorKind::SerializationError);
+    assert!(store.get("key".to_owned())?.is_none());
+    Ok(())
+}
+
+#[test]
+fn subscribers_receive_the_events_of_the_keys_they_filter() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = StoreOptions {
+        max_keys: Some(2),
+        eviction: EvictionPolicy::LeastRecentlyUsed,
+        ..StoreOptions::default()
+    };
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    let events = store.subscribe(|key: &String| key.starts_with("user:"));
+    let dropped = store.subscribe(|_: &String| true);
+    drop(dropped);
+
+    store.set("user:1".to_owned(), "alice".to_owned())?;
+    store.set("other".to_owned(), "value".to_owned())?;
+    let mut batch = WriteBatch::new();
+    batch
+        .set("user:2".to_owned(), "bob".to_owned())
+        .remove("user:1".to_owned());
+    store.write_batch(batch)?;
+    store.get("other".to_owned())?;
+    store.set("user:3".to_owned(), "carol".to_owned())?;
+    store.remove("other".to_owned())?;
+    store.clear()?;
+
+    assert_eq!(
+        events.try_iter().collect::<Vec<_>>(),
+        vec![
+            KeyEvent::Set("user:1".to_owned()),
+            KeyEvent::Set("user:2".to_owned()),
+            KeyEvent::Removed("user:1".to_owned()),
+            KeyEvent::Evicted("user:2".to_owned()),
+            KeyEvent::Set("user:3".to_owned()),
+            KeyEvent::Cleared,
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn index_hashers_are_pluggable() -> Result<()> {
+    let hashers = vec![
+        IndexHasher::fx(),
+        IndexHasher::custom(std::hash::BuildHasherDefault::<
+            std::collections::hash_map::DefaultHasher,
+        >::default()),
+    ];
+    for index_hasher in hashers {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let options = StoreOptions {
+            index_hasher,
+            ..StoreOptions::default()
+        };
+        let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+        for key_id in 0..100 {
+            store.set(format!("key{}", key_id), "1".to_owned())?;
+            store.set(format!("key{}", key_id), "2".to_owned())?;
+        }
+        store.compact_now()?;
+        store.shutdown()?;
+        drop(store);
+
+        // the index is loaded from its snapshot, then rebuilt from the log
+        let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+        assert!(store.was_shut_down_cleanly());
+        for key_id in 0..100 {
+            assert_eq!(store.get(format!("key{}", key_id))?, Some("2".to_owned()));
+        }
+        store.rebuild_index()?;
+        assert_eq!(store.get("key99".to_owned())?, Some("2".to_owned()));
+        store.clear()?;
+        store.set("key1".to_owned(), "3".to_owned())?;
+        assert_eq!(store.get("key1".to_owned())?, Some("3".to_owned()));
+        assert_eq!(store.get("key2".to_owned())?, None);
+    }
+    Ok(())
+}
+
+#[test]
+fn event_subscribers_receive_compactions_stalls_rotations_and_recoveries() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = StoreOptions {
+        max_stale_ratio: Some(2.0),
+        ..StoreOptions::default()
+    };
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    let events = store.subscribe_events();
+    for value in 0..5 {
+        store.set("key1".to_owned(), value.to_string())?;
+    }
+    let events = events.try_iter().collect::<Vec<_>>();
+    assert_eq!(events.len(), 4);
+    assert_eq!(events[0], StoreEvent::WriteStalled { stale_ratio: 3.0 });
+    assert_eq!(
+        events[1],
+        StoreEvent::CompactionStarted { stale_records: 3 }
+    );
+    match &events[2] {
+        StoreEvent::SegmentRotated { log } => assert!(log.exists()),
+        event => panic!("expected the log to be rotated, not {:?}", event),
+    }
+    assert!(matches!(
+        events[3],
+        StoreEvent::CompactionFinished {
+            succeeded: true,
+            ..
+        }
+    ));
+    drop(store);
+
+    // not having been shut down cleanly, the store recovers by replaying its log when opened
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    let events = store.subscribe_events();
+    assert!(matches!(
+        events.try_recv(),
+        Ok(StoreEvent::RecoveryPerformed { records: 2, .. })
+    ));
+    store.shutdown()?;
+    drop(store);
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert!(store.subscribe_events().try_recv().is_err());
+    Ok(())
+}
+
+#[test]
+fn events_are_not_sent_while_the_store_is_not_instrumented() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    drop(store);
+
+    // nor is the recovery performed when it was opened
+    let options = StoreOptions {
+        instrumented: false,
+        ..StoreOptions::default()
+    };
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    assert!(!store.runtime_options().instrumented);
+    let events = store.subscribe_events();
+    store.compact_now()?;
+    assert!(events.try_recv().is_err());
+
+    store.reconfigure(RuntimeOptions {
+        instrumented: true,
+        ..store.runtime_options()
+    })?;
+    store.compact_now()?;
+    assert_eq!(
+        events.try_recv(),
+        Ok(StoreEvent::CompactionStarted { stale_records: 0 })
+    );
+    Ok(())
+}
+
+#[test]
+fn hooks_transform_and_reject_sets_and_observe_removals() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = StoreOptions {
+        max_keys: Some(2),
+        eviction: EvictionPolicy::LeastRecentlyUsed,
+        ..StoreOptions::default()
+    };
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    let (removed_sender, removed) = mpsc::channel();
+    let (evicted_sender, evicted) = mpsc::channel();
+    store.set_before_set_hook(|_key, value: String| match value.is_empty() {
+        true => Err("values may not be empty".into()),
+        false => Ok(value.to_uppercase()),
+    });
+    store.set_after_remove_hook(move |key: &String| removed_sender.send(key.clone()).unwrap());
+    store.set_on_evict_hook(move |key: &String| evicted_sender.send(key.clone()).unwrap());
+
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    assert_eq!(store.get("key1".to_owned())?, Some("VALUE1".to_owned()));
+    let err = store.set("key1".to_owned(), "".to_owned()).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::Rejected);
+    assert_eq!(store.get("key1".to_owned())?, Some("VALUE1".to_owned()));
+
+    let mut batch = WriteBatch::new();
+    batch
+        .set("key2".to_owned(), "value2".to_owned())
+        .set("key3".to_owned(), "".to_owned());
+    let err = store.write_batch(batch).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::Rejected);
+    assert_eq!(store.get("key2".to_owned())?, None);
+
+    let mut batch = WriteBatch::new();
+    batch
+        .set("key2".to_owned(), "value2".to_owned())
+        .remove("key1".to_owned());
+    store.write_batch(batch)?;
+    assert_eq!(store.get("key2".to_owned())?, Some("VALUE2".to_owned()));
+    store.set("key3".to_owned(), "value3".to_owned())?;
+    store.set("key4".to_owned(), "value4".to_owned())?;
+    store.remove("key4".to_owned())?;
+
+    assert_eq!(removed.try_iter().collect::<Vec<_>>(), vec!["key1", "key4"]);
+    assert_eq!(evicted.try_iter().collect::<Vec<_>>(), vec!["key2"]);
+    Ok(())
+}
+
+#[test]
+fn hooks_transform_and_reject_merge_operands() -> Result<()> {
+    let mut store = KvStore::<String, String>::temporary()?;
+    store.set_merge_operator(|_key, value, operand| value.unwrap_or_default() + &operand);
+    store.set_before_set_hook(|_key, value: String| match value.contains(' ') {
+        true => Err("values may not hold spaces".into()),
+        false => Ok(value.to_uppercase()),
+    });
+
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.merge("key1".to_owned(), "suffix".to_owned())?;
+    assert_eq!(
+        store.get("key1".to_owned())?,
+        Some("VALUE1SUFFIX".to_owned())
+    );
+    let err = store
+        .merge("key1".to_owned(), " suffix".to_owned())
+        .unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::Rejected);
+    assert_eq!(
+        store.get("key1".to_owned())?,
+        Some("VALUE1SUFFIX".to_owned())
+    );
+    Ok(())
+}
+
+#[test]
+fn migrated_values_are_converted_and_persist() -> Result<()> {
+    #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct ProfileV1 {
+        name: String,
+    }
+    #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct ProfileV2 {
+        name: String,
+        age: Option<u32>,
+    }
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, ProfileV1>::open(temp_dir.path())?;
+    for name in &["alice", "bob", "carol"] {
+        store.set(
+            name.to_string(),
+            ProfileV1 {
+                name: name.to_uppercase(),
+            },
+        )?;
+    }
+    store.set(
+        "bob".to_owned(),
+        ProfileV1 {
+            name: "Robert".to_owned(),
+        },
+    )?;
+    store.remove("carol".to_owned())?;
+
+    let mut store = store.migrate_values(|_, value: ProfileV1| ProfileV2 {
+        name: value.name,
+        age: None,
+    })?;
+    let robert = ProfileV2 {
+        name: "Robert".to_owned(),
+        age: None,
+    };
+    assert_eq!(store.get("bob".to_owned())?, Some(robert.clone()));
+    assert_eq!(store.get("carol".to_owned())?, None);
+    store.set(
+        "dave".to_owned(),
+        ProfileV2 {
+            name: "DAVE".to_owned(),
+            age: Some(40),
+        },
+    )?;
+    drop(store);
+
+    let mut store = KvStore::<String, ProfileV2>::open(temp_dir.path())?;
+    assert_eq!(store.len(), 3);
+    assert_eq!(
+        store.get("alice".to_owned())?,
+        Some(ProfileV2 {
+            name: "ALICE".to_owned(),
+            age: None,
+        })
+    );
+    assert_eq!(store.get("bob".to_owned())?, Some(robert));
+    assert_eq!(
+        store
+            .get("dave".to_owned())?
+            .and_then(|profile| profile.age),
+        Some(40)
+    );
+    Ok(())
+}
+
+fn decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> T {
+    kvs_proto_serde::from_reader(&mut std::io::BufReader::new(bytes)).unwrap()
+}
+
+#[test]
+fn raw_values_are_the_serialized_values() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    let long_value = "v".repeat(1000);
+    store.set("short".to_owned(), "value".to_owned())?;
+    store.set("long".to_owned(), long_value.clone())?;
+    store.set("removed".to_owned(), "value".to_owned())?;
+    store.remove("removed".to_owned())?;
+
+    let mut buf = vec![0; 16];
+    assert!(store.get_raw("short".to_owned(), &mut buf)?);
+    assert_eq!(buf, b"$value\n");
+    assert!(store.get_raw("long".to_owned(), &mut buf)?);
+    assert_eq!(decode::<String>(&buf), long_value);
+    assert!(!store.get_raw("removed".to_owned(), &mut buf)?);
+    assert!(buf.is_empty());
+    assert!(!store.get_raw("missing".to_owned(), &mut buf)?);
+
+    let mut counters = KvStore::<String, u64>::open(temp_dir.path().join("counters").as_path())?;
+    counters.set_merge_operator(|_, value, operand| value.unwrap_or_default() + operand);
+    counters.set("hits".to_owned(), 1)?;
+    counters.merge("hits".to_owned(), 2)?;
+    assert!(counters.get_raw("hits".to_owned(), &mut buf)?);
+    assert_eq!(decode::<u64>(&buf), 3);
+    Ok(())
+}
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+#[test]
+fn io_uring_backend_reads_and_writes_the_same_log() -> Result<()> {
+    use kvs::IoBackend;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = StoreOptions {
+        io_backend: IoBackend::IoUring,
+        ..StoreOptions::default()
+    };
+    let value = |i: usize| format!("value{}-{}", i, "v".repeat(i % 200));
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    for i in 0..2000 {
+        store.set(format!("key{}", i), value(i))?;
+    }
+    for i in (0..2000).step_by(3) {
+        store.remove(format!("key{}", i))?;
+    }
+    assert_eq!(store.get("key1".to_owned())?, Some(value(1)));
+    store.compact_now()?;
+    store.sync()?;
+    drop(store);
+
+    // the log written through io_uring is read back (scanned with reads ahead) by either backend
+    for options in &[options, StoreOptions::default()] {
+        let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), options)?;
+        assert_eq!(store.len(), 1333);
+        for i in (0..2000).filter(|i| i % 3 != 0) {
+            assert_eq!(store.get(format!("key{}", i))?, Some(value(i)));
+        }
+        assert_eq!(store.get("key0".to_owned())?, None);
+    }
+    Ok(())
+}
+
+#[test]
+fn synced_write_modes_write_the_same_log() -> Result<()> {
+    use kvs::WriteMode;
+
+    let modes: &[WriteMode] = match cfg!(target_os = "linux") {
+        true => &[WriteMode::Dsync, WriteMode::Direct],
+        false if cfg!(unix) => &[WriteMode::Dsync],
+        false => &[],
+    };
+    let value = |i: usize| format!("value{}-{}", i, "v".repeat(i % 300));
+    for &write_mode in modes {
+        let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+        let options = StoreOptions {
+            write_mode,
+            ..StoreOptions::default()
+        };
+        let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+        for i in 0..2000 {
+            store.set(format!("key{}", i), value(i))?;
+        }
+        assert_eq!(store.get("key1999".to_owned())?, Some(value(1999)));
+        drop(store);
+
+        // reopened, the store appends to the block written last
+        let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+        store.remove("key0".to_owned())?;
+        store.set("key1".to_owned(), "updated".to_owned())?;
+        store.shutdown()?;
+        drop(store);
+
+        let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+        assert_eq!(store.len(), 1999, "{:?}", write_mode);
+        assert_eq!(store.get("key0".to_owned())?, None);
+        assert_eq!(store.get("key1".to_owned())?, Some("updated".to_owned()));
+        for i in 2..2000 {
+            assert_eq!(store.get(format!("key{}", i))?, Some(value(i)));
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn logs_are_preallocated_to_their_maximum_length() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let options = StoreOptions {
+        preallocate: true,
+        ..StoreOptions::default()
+    };
+    match KvStore::<String, String>::open_with_options(temp_dir.path(), &options) {
+        Err(err) => assert_eq!(*err.kind(), ErrorKind::InvalidConfiguration),
+        Ok(_) => panic!("expected InvalidConfiguration without max_log_bytes"),
+    }
+
+    let max_log_bytes = 1 << 20;
+    let options = StoreOptions {
+        max_log_bytes: Some(max_log_bytes),
+        ..options
+    };
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.set("key1".to_owned(), "value2".to_owned())?;
+    store.compact_now()?;
+    store.set("key2".to_owned(), "value3".to_owned())?;
+    drop(store);
+
+    let log_path = file_names(&temp_dir)
+        .into_iter()
+        .find(|name| name.ends_with(".log"))
+        .map(|name| temp_dir.path().join(name))
+        .expect("a log");
+    let metadata = std::fs::metadata(&log_path)?;
+    // the log keeps its length, its records being read up to its end
+    assert!(metadata.len() < 1024);
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::MetadataExt;
+        assert!(metadata.blocks() * 512 >= max_log_bytes);
+    }
+    let mut store = KvStore::<String, String>::open_with_options(temp_dir.path(), &options)?;
+    assert_eq!(store.get("key1".to_owned())?, Some("value2".to_owned()));
+    assert_eq!(store.get("key2".to_owned())?, Some("value3".to_owned()));
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use kvs::{ErrorKind, Request, Response};
+use serde::{de::DeserializeOwned, Serialize};
+use std::io;
+
+fn round_trip<T: Serialize + DeserializeOwned>(message: &T) -> T {
+    let mut writer = io::BufWriter::new(Vec::new());
+    kvs_proto_serde::to_writer(&mut writer, message).unwrap();
+    let encoded = writer.into_inner().unwrap();
+    kvs_proto_serde::from_reader(&mut io::BufReader::new(encoded.as_slice())).unwrap()
+}
+
+#[test]
+fn requests_round_trip() {
+    let requests = vec![
+        Request::Get {
+            key: "key1".to_owned(),
+        },
+        Request::Set {
+            key: "key1".to_owned(),
+            value: "value\nwith\nnewlines".to_owned(),
+        },
+        Request::Remove {
+            key: "key1".to_owned(),
+        },
+        Request::Compact,
+        Request::Clear,
+        Request::KeyCount,
+        Request::Auth {
+            password: "secret".to_owned(),
+        },
+    ];
+    for request in requests {
+        assert_eq!(round_trip(&request), request);
+    }
+}
+
+#[test]
+fn responses_round_trip() {
+    let responses = vec![
+        Response::Ok,
+        Response::Value(Some("value1".to_owned())),
+        Response::Value(None),
+        Response::Count(42),
+        Response::KeyNotFound,
+        Response::Unauthenticated,
+        Response::ServerError {
+            msg: "An I/O error occurred".to_owned(),
+        },
+    ];
+    for response in responses {
+        assert_eq!(round_trip(&response), response);
+    }
+}
+
+#[test]
+fn requests_traced_again_are_refused() {
+    let traced = Request::Traced {
+        id: "id1".to_owned(),
+        request: Box::new(Request::KeyCount),
+    };
+    assert_eq!(round_trip(&traced), traced);
+
+    // deep enough to overflow the stack if each level were read by recursing
+    for depth in [2, 100_000] {
+        let encoded =
+            "#2\nRequest\n$Traced\n$id\n$id1\n$request\n".repeat(depth) + "@Request\n$KeyCount\n";
+        let err =
+            kvs_proto_serde::from_reader::<_, Request>(&mut io::BufReader::new(encoded.as_bytes()))
+                .unwrap_err();
+        assert_eq!(*kvs::Error::from(err).kind(), ErrorKind::ProtocolError);
+    }
+}
@@ -0,0 +1,325 @@
+use kvs::{
+    ClusterClient, ClusterNode, ClusterOptions, ErrorKind, KvsClient, KvsEngine, KvsServer,
+    RaftEngine, RaftOptions, RaftRole, SharedKvStore, Topology,
+};
+use std::net::{SocketAddr, TcpListener};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+fn options() -> RaftOptions {
+    RaftOptions {
+        election_timeout: Duration::from_millis(150),
+        heartbeat_interval: Duration::from_millis(20),
+        ..RaftOptions::default()
+    }
+}
+
+// Binds a listener for each node of a cluster, returning them with each node's id and address.
+fn bind_nodes(count: u64) -> Vec<(u64, SocketAddr, TcpListener)> {
+    (1..=count)
+        .map(|id| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            (id, listener.local_addr().unwrap(), listener)
+        })
+        .collect()
+}
+
+fn start_node(
+    temp_dir: &TempDir,
+    nodes: &[(u64, SocketAddr)],
+    id: u64,
+    listener: TcpListener,
+    options: RaftOptions,
+) -> RaftEngine {
+    let peers = nodes
+        .iter()
+        .filter(|&&(peer, _)| peer != id)
+        .copied()
+        .collect();
+    RaftEngine::start(temp_dir.path(), id, peers, listener, options).unwrap()
+}
+
+// Waits for one of the engines to become the leader, returning its position.
+fn wait_for_leader(engines: &[&RaftEngine]) -> usize {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        let leaders = engines
+            .iter()
+            .enumerate()
+            .filter(|(_, engine)| engine.status().unwrap().role == RaftRole::Leader)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        if let [leader] = leaders[..] {
+            return leader;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    panic!("no leader was elected");
+}
+
+#[test]
+fn raft_single_node_cluster_serves_requests() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let engine = RaftEngine::start(temp_dir.path(), 1, Vec::new(), listener, options()).unwrap();
+    wait_for_leader(&[&engine]);
+
+    engine.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    assert!(!engine
+        .set_if_absent("key1".to_owned(), "value2".to_owned())
+        .unwrap());
+    assert_eq!(
+        engine.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+    let lock = engine
+        .acquire_lock("job".to_owned(), Duration::from_secs(10))
+        .unwrap()
+        .unwrap();
+    assert!(engine.release_lock("job".to_owned(), lock.token).unwrap());
+    assert_eq!(
+        engine.remove("key2".to_owned()).unwrap_err().kind(),
+        &ErrorKind::KeyNotPresent
+    );
+    engine.shutdown().unwrap();
+
+    // the writes are applied again as the log is replayed on restart
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let engine = RaftEngine::start(temp_dir.path(), 1, Vec::new(), listener, options()).unwrap();
+    wait_for_leader(&[&engine]);
+    assert_eq!(
+        engine.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+    assert_eq!(engine.key_count().unwrap(), 1);
+}
+
+#[test]
+fn raft_cluster_replicates_writes_and_fails_over() {
+    let temp_dirs = (0..3).map(|_| TempDir::new().unwrap()).collect::<Vec<_>>();
+    let nodes = bind_nodes(3);
+    let addrs = nodes
+        .iter()
+        .map(|&(id, addr, _)| (id, addr))
+        .collect::<Vec<_>>();
+    let engines = nodes
+        .into_iter()
+        .zip(&temp_dirs)
+        .map(|((id, _, listener), temp_dir)| start_node(temp_dir, &addrs, id, listener, options()))
+        .collect::<Vec<_>>();
+
+    let leader = wait_for_leader(&engines.iter().collect::<Vec<_>>());
+    engines[leader]
+        .set("key1".to_owned(), "value1".to_owned())
+        .unwrap();
+    let follower = (leader + 1) % 3;
+    let err = engines[follower]
+        .set("key2".to_owned(), "value2".to_owned())
+        .expect_err("a follower should not accept writes");
+    assert_eq!(err.kind(), &ErrorKind::NotLeader);
+    assert_eq!(
+        engines[follower].get("key1".to_owned()).unwrap_err().kind(),
+        &ErrorKind::NotLeader
+    );
+
+    engines[leader].shutdown().unwrap();
+    let remaining = engines
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| i != leader)
+        .map(|(_, engine)| engine)
+        .collect::<Vec<_>>();
+    let new_leader = remaining[wait_for_leader(&remaining)];
+    assert_eq!(
+        new_leader.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+    new_leader
+        .set("key2".to_owned(), "value2".to_owned())
+        .unwrap();
+    assert_eq!(new_leader.key_count().unwrap(), 2);
+}
+
+#[test]
+fn raft_followers_serve_reads_within_max_lag() {
+    let temp_dirs = (0..3).map(|_| TempDir::new().unwrap()).collect::<Vec<_>>();
+    let nodes = bind_nodes(3);
+    let addrs = nodes
+        .iter()
+        .map(|&(id, addr, _)| (id, addr))
+        .collect::<Vec<_>>();
+    let engines = nodes
+        .into_iter()
+        .zip(&temp_dirs)
+        .map(|((id, _, listener), temp_dir)| start_node(temp_dir, &addrs, id, listener, options()))
+        .collect::<Vec<_>>();
+    let leader = wait_for_leader(&engines.iter().collect::<Vec<_>>());
+    engines[leader]
+        .set("key1".to_owned(), "value1".to_owned())
+        .unwrap();
+
+    // a read which cannot lag is forwarded to the leader
+    let follower = &engines[(leader + 1) % 3];
+    assert_eq!(
+        follower
+            .get_with_max_lag("key1".to_owned(), Duration::ZERO)
+            .unwrap(),
+        Some("value1".to_owned())
+    );
+    let commit_index = engines[leader].status().unwrap().commit_index;
+    while follower.status().unwrap().applied_index < commit_index {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let server_addr = listener.local_addr().unwrap();
+    let server_engine = follower.clone();
+    thread::spawn(move || KvsServer::new(server_engine).serve(listener));
+    let mut client = KvsClient::connect(server_addr).unwrap();
+    assert_eq!(
+        client
+            .get_with_max_lag("key1".to_owned(), Duration::from_secs(60))
+            .unwrap(),
+        Some("value1".to_owned())
+    );
+
+    // without a leader, the follower serves reads within the lag since it last caught up
+    for (i, engine) in engines.iter().enumerate() {
+        if i != (leader + 1) % 3 {
+            engine.shutdown().unwrap();
+        }
+    }
+    assert_eq!(
+        follower
+            .get_with_max_lag("key1".to_owned(), Duration::from_secs(60))
+            .unwrap(),
+        Some("value1".to_owned())
+    );
+    assert!(follower
+        .get_with_max_lag("key1".to_owned(), Duration::ZERO)
+        .is_err());
+    assert!(client
+        .get_with_max_lag("key1".to_owned(), Duration::ZERO)
+        .is_err());
+}
+
+#[test]
+fn raft_lagging_node_catches_up_from_snapshot() {
+    let temp_dirs = (0..3).map(|_| TempDir::new().unwrap()).collect::<Vec<_>>();
+    let mut nodes = bind_nodes(3);
+    let addrs = nodes
+        .iter()
+        .map(|&(id, addr, _)| (id, addr))
+        .collect::<Vec<_>>();
+    // the snapshot is sent in many chunks
+    let options = RaftOptions {
+        snapshot_threshold: 5,
+        snapshot_chunk_size: 32,
+        ..options()
+    };
+    let (late_id, _, late_listener) = nodes.pop().unwrap();
+    let engines = nodes
+        .into_iter()
+        .zip(&temp_dirs)
+        .map(|((id, _, listener), temp_dir)| {
+            start_node(temp_dir, &addrs, id, listener, options.clone())
+        })
+        .collect::<Vec<_>>();
+
+    let leader = &engines[wait_for_leader(&engines.iter().collect::<Vec<_>>())];
+    for i in 0..20 {
+        leader
+            .set(format!("key{}", i), format!("value{}", i))
+            .unwrap();
+    }
+    leader.remove("key0".to_owned()).unwrap();
+
+    let late = start_node(&temp_dirs[2], &addrs, late_id, late_listener, options);
+    let commit_index = leader.status().unwrap().commit_index;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while late.status().unwrap().applied_index < commit_index {
+        assert!(Instant::now() < deadline, "the late node did not catch up");
+        thread::sleep(Duration::from_millis(20));
+    }
+    late.shutdown().unwrap();
+    for engine in &engines {
+        engine.shutdown().unwrap();
+    }
+
+    // shared with the node, whose threads may not have finished yet
+    let store = SharedKvStore::open(temp_dirs[2].path()).unwrap();
+    assert_eq!(store.key_count().unwrap(), 19);
+    assert_eq!(store.get("key0".to_owned()).unwrap(), None);
+    assert_eq!(
+        store.get("key19".to_owned()).unwrap(),
+        Some("value19".to_owned())
+    );
+}
+
+#[test]
+fn raft_invalid_configuration() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let result = RaftEngine::start(temp_dir.path(), 1, vec![(1, addr)], listener, options());
+    assert_eq!(
+        result.err().map(|err| *err.kind()),
+        Some(ErrorKind::InvalidConfiguration)
+    );
+}
+
+#[test]
+fn raft_cluster_client_follows_the_leader() {
+    let temp_dirs = (0..3).map(|_| TempDir::new().unwrap()).collect::<Vec<_>>();
+    let nodes = bind_nodes(3);
+    let addrs = nodes
+        .iter()
+        .map(|&(id, addr, _)| (id, addr))
+        .collect::<Vec<_>>();
+    let engines = nodes
+        .into_iter()
+        .zip(&temp_dirs)
+        .map(|((id, _, listener), temp_dir)| start_node(temp_dir, &addrs, id, listener, options()))
+        .collect::<Vec<_>>();
+    let listeners = (0..3)
+        .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+        .collect::<Vec<_>>();
+    let topology = Topology::single_shard(
+        listeners
+            .iter()
+            .zip(1..)
+            .map(|(listener, id)| ClusterNode {
+                id,
+                addr: listener.local_addr().unwrap().to_string(),
+            })
+            .collect(),
+    );
+    for ((listener, engine), id) in listeners.into_iter().zip(&engines).zip(1..) {
+        let server = KvsServer::new(engine.clone()).with_cluster(id, topology.clone());
+        thread::spawn(move || server.serve(listener));
+    }
+    let leader = wait_for_leader(&engines.iter().collect::<Vec<_>>());
+    let follower_addr = &topology.nodes[(leader + 1) % 3].addr;
+
+    // a follower redirects writes to the leader, which the cluster client follows
+    let mut node = KvsClient::connect(follower_addr.as_str()).unwrap();
+    let err = node
+        .set("key1".to_owned(), "value1".to_owned())
+        .unwrap_err();
+    assert_eq!(err.kind(), &ErrorKind::Moved);
+    assert_eq!(
+        std::error::Error::source(&err).map(ToString::to_string),
+        Some(topology.nodes[leader].addr.clone())
+    );
+    let mut client =
+        ClusterClient::connect(&[follower_addr.as_str()], ClusterOptions::default()).unwrap();
+    assert_eq!(
+        client.topology().shards[0].leader,
+        Some(topology.nodes[leader].id)
+    );
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    assert_eq!(
+        client.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+}
@@ -0,0 +1,1435 @@
+mod common;
+
+use common::start_server_at;
+use kvs::{
+    Acl, AclUser, ErrorKind, KeyEvent, KvStore, KvsClient, KvsServer, RateLimit, Request,
+    SharedKvStore,
+};
+use std::io::{self, BufRead, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+fn start_server(temp_dir: &TempDir) -> TcpStream {
+    TcpStream::connect(start_server_at(temp_dir)).unwrap()
+}
+
+// Starts a server configured by `configure` for the store in the directory, returning its address.
+fn start_configured_server_at<F>(temp_dir: &TempDir, configure: F) -> SocketAddr
+where
+    F: FnOnce(KvsServer<SharedKvStore>) -> KvsServer<SharedKvStore>,
+{
+    let server = configure(KvsServer::new(
+        SharedKvStore::open(temp_dir.path()).unwrap(),
+    ));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || server.serve(listener));
+    addr
+}
+
+fn start_server_with_password_at(temp_dir: &TempDir, password: &str) -> SocketAddr {
+    start_configured_server_at(temp_dir, |server| server.with_password(password.to_owned()))
+}
+
+fn encode_command(args: &[&str]) -> String {
+    args.iter()
+        .fold(format!("*{}\r\n", args.len()), |acc, arg| {
+            format!("{}${}\r\n{}\r\n", acc, arg.len(), arg)
+        })
+}
+
+fn read_response(reader: &mut io::BufReader<TcpStream>) -> String {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let len = line[1..line.len() - 2].parse::<i64>().unwrap_or(-1);
+    match line.as_bytes()[0] {
+        b'$' if len >= 0 => {
+            let mut data = vec![0; len as usize + 2];
+            reader.read_exact(&mut data).unwrap();
+            line + &String::from_utf8(data).unwrap()
+        }
+        b'*' => (0..len).fold(line, |acc, _| acc + &read_response(reader)),
+        _ => line,
+    }
+}
+
+fn command(stream: &mut TcpStream, args: &[&str]) -> String {
+    stream.write_all(encode_command(args).as_bytes()).unwrap();
+    read_response(&mut io::BufReader::new(stream.try_clone().unwrap()))
+}
+
+#[test]
+fn resp_set_and_get() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream = &mut start_server(&temp_dir);
+
+    assert_eq!(command(stream, &["SET", "key1", "value1"]), "+OK\r\n");
+    assert_eq!(command(stream, &["GET", "key1"]), "$6\r\nvalue1\r\n");
+    assert_eq!(command(stream, &["get", "key2"]), "$-1\r\n");
+    assert_eq!(command(stream, &["set", "key1", "value2"]), "+OK\r\n");
+    assert_eq!(command(stream, &["GET", "key1"]), "$6\r\nvalue2\r\n");
+}
+
+#[test]
+fn resp_setnx() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream = &mut start_server(&temp_dir);
+
+    assert_eq!(command(stream, &["SETNX", "key1", "value1"]), ":1\r\n");
+    assert_eq!(command(stream, &["setnx", "key1", "value2"]), ":0\r\n");
+    assert_eq!(command(stream, &["GET", "key1"]), "$6\r\nvalue1\r\n");
+    assert_eq!(
+        command(stream, &["SETNX", "key1"]),
+        "-ERR wrong number of arguments for 'setnx' command\r\n"
+    );
+}
+
+#[test]
+fn resp_append_and_strlen() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream = &mut start_server(&temp_dir);
+
+    assert_eq!(command(stream, &["STRLEN", "key1"]), ":0\r\n");
+    assert_eq!(command(stream, &["APPEND", "key1", "Hello"]), ":5\r\n");
+    assert_eq!(command(stream, &["append", "key1", " World"]), ":11\r\n");
+    assert_eq!(command(stream, &["GET", "key1"]), "$11\r\nHello World\r\n");
+    assert_eq!(command(stream, &["STRLEN", "key1"]), ":11\r\n");
+    // a value set after appends is appended to as any other
+    command(stream, &["SET", "key1", "value\u{e9}"]);
+    assert_eq!(command(stream, &["APPEND", "key1", "1"]), ":8\r\n");
+    assert_eq!(command(stream, &["GET", "key1"]), "$8\r\nvalue\u{e9}1\r\n");
+    assert_eq!(
+        command(stream, &["STRLEN"]),
+        "-ERR wrong number of arguments for 'strlen' command\r\n"
+    );
+}
+
+#[test]
+fn resp_mget_and_mset() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream = &mut start_server(&temp_dir);
+
+    assert_eq!(
+        command(stream, &["MSET", "key1", "value1", "key2", "value2"]),
+        "+OK\r\n"
+    );
+    assert_eq!(
+        command(stream, &["MGET", "key1", "key3", "key2"]),
+        "*3\r\n$6\r\nvalue1\r\n$-1\r\n$6\r\nvalue2\r\n"
+    );
+    // a key given twice is set to its last value
+    assert_eq!(
+        command(stream, &["mset", "key3", "value3", "key3", "value4"]),
+        "+OK\r\n"
+    );
+    assert_eq!(command(stream, &["GET", "key3"]), "$6\r\nvalue4\r\n");
+    assert_eq!(command(stream, &["MULTI"]), "+OK\r\n");
+    assert_eq!(
+        command(stream, &["MSET", "key1", "value5", "key4", "value4"]),
+        "+QUEUED\r\n"
+    );
+    assert_eq!(command(stream, &["DEL", "key2"]), "+QUEUED\r\n");
+    assert_eq!(command(stream, &["EXEC"]), "*2\r\n+OK\r\n:1\r\n");
+    assert_eq!(
+        command(stream, &["MGET", "key1", "key2", "key4"]),
+        "*3\r\n$6\r\nvalue5\r\n$-1\r\n$6\r\nvalue4\r\n"
+    );
+    assert_eq!(
+        command(stream, &["MSET", "key1", "value1", "key2"]),
+        "-ERR wrong number of arguments for 'mset' command\r\n"
+    );
+    assert_eq!(
+        command(stream, &["MGET"]),
+        "-ERR wrong number of arguments for 'mget' command\r\n"
+    );
+}
+
+#[test]
+fn resp_del_and_exists() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream = &mut start_server(&temp_dir);
+
+    command(stream, &["SET", "key1", "value1"]);
+    command(stream, &["SET", "key2", "value2"]);
+    assert_eq!(
+        command(stream, &["EXISTS", "key1", "key2", "key3"]),
+        ":2\r\n"
+    );
+    assert_eq!(command(stream, &["DEL", "key1", "key3"]), ":1\r\n");
+    assert_eq!(command(stream, &["EXISTS", "key1"]), ":0\r\n");
+    assert_eq!(command(stream, &["GET", "key1"]), "$-1\r\n");
+}
+
+#[test]
+fn resp_keys() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream = &mut start_server(&temp_dir);
+
+    command(stream, &["SET", "user:1", "a"]);
+    command(stream, &["SET", "user:2", "b"]);
+    command(stream, &["SET", "order:1", "c"]);
+
+    assert_eq!(
+        command(stream, &["KEYS", "order:*"]),
+        "*1\r\n$7\r\norder:1\r\n"
+    );
+    let all_users = command(stream, &["KEYS", "user:?"]);
+    assert!(all_users.starts_with("*2\r\n"));
+    assert!(all_users.contains("$6\r\nuser:1\r\n"));
+    assert!(all_users.contains("$6\r\nuser:2\r\n"));
+    assert_eq!(
+        command(stream, &["KEYS", "user:[^1]"]),
+        "*1\r\n$6\r\nuser:2\r\n"
+    );
+    assert_eq!(command(stream, &["KEYS", "nothing*"]), "*0\r\n");
+}
+
+#[test]
+fn resp_keys_with_many_stars_or_long_patterns() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream = &mut start_server(&temp_dir);
+
+    let key = "a".repeat(100);
+    command(stream, &["SET", &key, "a"]);
+
+    // would take exponential time if every `*` were retried at every position of the key
+    assert_eq!(
+        command(stream, &["KEYS", &"a*".repeat(50)]),
+        format!("*1\r\n$100\r\n{}\r\n", key)
+    );
+    assert_eq!(
+        command(stream, &["KEYS", &("a*".repeat(50) + "b")]),
+        "*0\r\n"
+    );
+    assert_eq!(command(stream, &["KEYS", "*\\a[a-"]), "*0\r\n");
+
+    assert!(command(stream, &["KEYS", &"*".repeat(2000)]).starts_with("-ERR pattern longer"));
+    assert!(command(stream, &["SCAN", "0", "MATCH", &"*".repeat(2000)]).starts_with("-ERR"));
+}
+
+// Splits the reply to SCAN into the cursor and the array of keys.
+fn scan_reply(reply: &str) -> (String, String) {
+    let mut parts = reply.splitn(4, "\r\n");
+    assert_eq!(parts.next(), Some("*2"));
+    let _ = parts.next();
+    let cursor = parts.next().unwrap().to_owned();
+    (cursor, parts.next().unwrap().to_owned())
+}
+
+#[test]
+fn resp_scan_pages_through_the_keys() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream = &mut start_server(&temp_dir);
+
+    for i in 0..5 {
+        command(stream, &["SET", &format!("user:{}", i), "a"]);
+    }
+    command(stream, &["SET", "order:1", "b"]);
+
+    let (cursor, keys) = scan_reply(&command(
+        stream,
+        &["SCAN", "0", "MATCH", "user:*", "COUNT", "2"],
+    ));
+    assert_eq!(keys, "*2\r\n$6\r\nuser:0\r\n$6\r\nuser:1\r\n");
+    let (next, keys) = scan_reply(&command(
+        stream,
+        &["SCAN", &cursor, "MATCH", "user:*", "COUNT", "2"],
+    ));
+    assert_eq!(keys, "*2\r\n$6\r\nuser:2\r\n$6\r\nuser:3\r\n");
+    // the page of a cursor may be requested again
+    assert_eq!(
+        scan_reply(&command(
+            stream,
+            &["SCAN", &cursor, "MATCH", "user:*", "COUNT", "2"],
+        ))
+        .1,
+        keys
+    );
+    assert_eq!(
+        command(stream, &["SCAN", &next, "MATCH", "user:*", "COUNT", "2"]),
+        "*2\r\n$1\r\n0\r\n*1\r\n$6\r\nuser:4\r\n"
+    );
+    assert_eq!(
+        command(stream, &["SCAN", "0"]),
+        "*2\r\n$1\r\n0\r\n*6\r\n$7\r\norder:1\r\n$6\r\nuser:0\r\n$6\r\nuser:1\r\n\
+            $6\r\nuser:2\r\n$6\r\nuser:3\r\n$6\r\nuser:4\r\n"
+    );
+    assert_eq!(command(stream, &["SCAN", "x"]), "-ERR invalid cursor\r\n");
+    assert_eq!(command(stream, &["SCAN", "17"]), "-ERR invalid cursor\r\n");
+    assert_eq!(
+        command(stream, &["SCAN", "0", "COUNT", "0"]),
+        "-ERR syntax error\r\n"
+    );
+}
+
+#[test]
+fn resp_errors() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream = &mut start_server(&temp_dir);
+
+    assert_eq!(
+        command(stream, &["GET"]),
+        "-ERR wrong number of arguments for 'get' command\r\n"
+    );
+    assert_eq!(
+        command(stream, &["FLY", "away"]),
+        "-ERR unknown command 'FLY'\r\n"
+    );
+    // the connection remains usable after an error
+    assert_eq!(command(stream, &["SET", "key1", "value1"]), "+OK\r\n");
+}
+
+#[test]
+fn resp_auth() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream =
+        &mut TcpStream::connect(start_server_with_password_at(&temp_dir, "secret")).unwrap();
+
+    assert_eq!(
+        command(stream, &["SET", "key1", "value1"]),
+        "-NOAUTH Authentication required.\r\n"
+    );
+    assert_eq!(
+        command(stream, &["FLY", "away"]),
+        "-ERR unknown command 'FLY'\r\n"
+    );
+    assert_eq!(
+        command(stream, &["AUTH", "wrong"]),
+        "-WRONGPASS invalid username-password pair or user is disabled.\r\n"
+    );
+    assert_eq!(
+        command(stream, &["AUTH", "admin", "secret"]),
+        "-WRONGPASS invalid username-password pair or user is disabled.\r\n"
+    );
+    assert_eq!(
+        command(stream, &["GET", "key1"]),
+        "-NOAUTH Authentication required.\r\n"
+    );
+    assert_eq!(command(stream, &["AUTH", "secret"]), "+OK\r\n");
+    assert_eq!(command(stream, &["SET", "key1", "value1"]), "+OK\r\n");
+    assert_eq!(
+        command(stream, &["AUTH", "wrong"]).chars().next(),
+        Some('-')
+    );
+    assert_eq!(command(stream, &["GET", "key1"]), "$6\r\nvalue1\r\n");
+
+    let stream =
+        &mut TcpStream::connect(start_server_with_password_at(&temp_dir, "secret")).unwrap();
+    assert_eq!(command(stream, &["AUTH", "default", "secret"]), "+OK\r\n");
+    assert_eq!(command(stream, &["GET", "key1"]), "$6\r\nvalue1\r\n");
+}
+
+#[test]
+fn resp_acl_restricts_users_to_their_commands_and_keys() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let acl = Acl::new()
+        .with_user(AclUser::new("admin".into(), "admin-token".into()))
+        .with_user(
+            AclUser::new("reports".into(), "reports-token".into())
+                .read_only()
+                .with_key_prefix("report:".into()),
+        )
+        .with_user(
+            AclUser::new("counter".into(), "counter-token".into())
+                .with_command("DBSIZE".into())
+                .with_command("get".into()),
+        );
+    let addr = start_configured_server_at(&temp_dir, |server| server.with_acl(acl));
+
+    let admin = &mut TcpStream::connect(addr).unwrap();
+    assert_eq!(
+        command(admin, &["GET", "report:1"]),
+        "-NOAUTH Authentication required.\r\n"
+    );
+    assert_eq!(
+        command(admin, &["AUTH", "reports", "admin-token"]),
+        "-WRONGPASS invalid username-password pair or user is disabled.\r\n"
+    );
+    assert_eq!(command(admin, &["AUTH", "admin", "admin-token"]), "+OK\r\n");
+    assert_eq!(command(admin, &["SET", "report:1", "q1"]), "+OK\r\n");
+    assert_eq!(command(admin, &["SET", "user:1", "ann"]), "+OK\r\n");
+
+    let reports = &mut TcpStream::connect(addr).unwrap();
+    assert_eq!(command(reports, &["AUTH", "reports-token"]), "+OK\r\n");
+    assert_eq!(command(reports, &["GET", "report:1"]), "$2\r\nq1\r\n");
+    assert_eq!(
+        command(reports, &["GET", "user:1"]),
+        "-NOPERM this user has no permissions to access one of the keys used as arguments\r\n"
+    );
+    assert_eq!(
+        command(reports, &["SET", "report:2", "q2"]),
+        "-NOPERM this user has no permissions to write with the 'set' command\r\n"
+    );
+    assert!(command(reports, &["KEYS", "*"]).starts_with("-NOPERM"));
+    assert!(command(reports, &["FLUSHALL"]).starts_with("-NOPERM"));
+
+    let counter = &mut TcpStream::connect(addr).unwrap();
+    assert_eq!(command(counter, &["AUTH", "counter-token"]), "+OK\r\n");
+    assert_eq!(command(counter, &["DBSIZE"]), ":2\r\n");
+    assert_eq!(command(counter, &["GET", "user:1"]), "$3\r\nann\r\n");
+    assert_eq!(
+        command(counter, &["DEL", "user:1"]),
+        "-NOPERM this user has no permissions to run the 'del' command\r\n"
+    );
+    assert_eq!(command(admin, &["GET", "user:1"]), "$3\r\nann\r\n");
+}
+
+#[test]
+fn resp_auth_without_password_configured() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream = &mut start_server(&temp_dir);
+
+    assert!(command(stream, &["AUTH", "secret"])
+        .starts_with("-ERR AUTH <password> called without any password configured"));
+    assert_eq!(command(stream, &["SET", "key1", "value1"]), "+OK\r\n");
+}
+
+#[test]
+fn resp_admin_commands() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream = &mut start_server(&temp_dir);
+
+    assert_eq!(command(stream, &["DBSIZE"]), ":0\r\n");
+    command(stream, &["SET", "key1", "value1"]);
+    command(stream, &["SET", "key1", "value2"]);
+    command(stream, &["SET", "key2", "value2"]);
+    assert_eq!(command(stream, &["DBSIZE"]), ":2\r\n");
+    assert_eq!(command(stream, &["COMPACT"]), "+OK\r\n");
+    assert_eq!(command(stream, &["GET", "key1"]), "$6\r\nvalue2\r\n");
+    assert_eq!(command(stream, &["FLUSHALL"]), "+OK\r\n");
+    assert_eq!(command(stream, &["DBSIZE"]), ":0\r\n");
+    assert_eq!(command(stream, &["GET", "key1"]), "$-1\r\n");
+    command(stream, &["SET", "key1", "value1"]);
+    assert_eq!(command(stream, &["flushdb", "async"]), "+OK\r\n");
+    assert_eq!(command(stream, &["DBSIZE"]), ":0\r\n");
+    assert_eq!(
+        command(stream, &["FLUSHALL", "now"]),
+        "-ERR syntax error\r\n"
+    );
+    assert_eq!(
+        command(stream, &["DBSIZE", "key1"]),
+        "-ERR wrong number of arguments for 'dbsize' command\r\n"
+    );
+}
+
+#[test]
+fn info_reports_the_keys_and_the_sizes_written() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_server_at(&temp_dir);
+    let stream = &mut TcpStream::connect(addr).unwrap();
+
+    command(stream, &["SET", "key1", "value1"]);
+    command(stream, &["SET", "key2", &"v".repeat(1000)]);
+    let info = command(stream, &["INFO"]);
+    assert!(info.starts_with('$'));
+    assert!(info.contains("# Keyspace\r\nkeys:2\r\n"));
+    assert_eq!(command(stream, &["INFO", "nothing"]), "$0\r\n\r\n");
+    // the report of kvs-proto's Info is that of RESP's bulk string
+    let report = KvsClient::connect(addr).unwrap().info().unwrap();
+    assert_eq!(info, format!("${}\r\n{}\r\n", report.len(), report));
+
+    #[cfg(feature = "stats")]
+    {
+        let size = |info: &str, field: &str| -> u64 {
+            let line = info.lines().find(|line| line.starts_with(field)).unwrap();
+            line[field.len() + 1..].parse().unwrap()
+        };
+        let sizes = command(stream, &["info", "SIZES"]);
+        assert!(!sizes.contains("# Keyspace"));
+        assert!(sizes.contains("# Sizes\r\n"));
+        assert_eq!(size(&sizes, "key_size_count"), 2);
+        assert_eq!(size(&sizes, "value_size_count"), 2);
+        assert!(size(&sizes, "value_size_p50") < 100);
+        assert!((1000..1010).contains(&size(&sizes, "value_size_max")));
+        assert_eq!(size(&sizes, "key_size_p99"), size(&sizes, "key_size_max"));
+    }
+}
+
+#[test]
+fn resp_select_switches_between_databases() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let other_dir = TempDir::new().expect("unable to create temporary working directory");
+    let other = SharedKvStore::open(other_dir.path()).unwrap();
+    let addr = start_configured_server_at(&temp_dir, |server| server.with_database(other));
+    let stream = &mut TcpStream::connect(addr).unwrap();
+
+    command(stream, &["SET", "key1", "value0"]);
+    assert_eq!(command(stream, &["SELECT", "1"]), "+OK\r\n");
+    assert_eq!(command(stream, &["GET", "key1"]), "$-1\r\n");
+    command(stream, &["SET", "key1", "value1"]);
+    assert_eq!(command(stream, &["DBSIZE"]), ":1\r\n");
+    assert_eq!(
+        command(stream, &["SELECT", "2"]),
+        "-ERR DB index is out of range\r\n"
+    );
+    assert_eq!(
+        command(stream, &["SELECT", "one"]),
+        "-ERR value is not an integer or out of range\r\n"
+    );
+    // a failed SELECT leaves the connection on the database it was using
+    assert_eq!(command(stream, &["GET", "key1"]), "$6\r\nvalue1\r\n");
+    assert_eq!(command(stream, &["SELECT", "0"]), "+OK\r\n");
+    assert_eq!(command(stream, &["GET", "key1"]), "$6\r\nvalue0\r\n");
+
+    // each connection starts on database 0
+    let stream = &mut TcpStream::connect(addr).unwrap();
+    assert_eq!(command(stream, &["GET", "key1"]), "$6\r\nvalue0\r\n");
+}
+
+#[test]
+fn max_connections() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_configured_server_at(&temp_dir, |server| server.with_max_connections(1));
+
+    let stream = &mut TcpStream::connect(addr).unwrap();
+    assert_eq!(command(stream, &["SET", "key1", "value1"]), "+OK\r\n");
+    let refused = &mut TcpStream::connect(addr).unwrap();
+    let mut response = String::new();
+    refused.read_to_string(&mut response).unwrap();
+    assert_eq!(response, "-ERR max number of clients reached\r\n");
+
+    stream.shutdown(std::net::Shutdown::Both).unwrap();
+    let started = Instant::now();
+    loop {
+        let stream = &mut TcpStream::connect(addr).unwrap();
+        match command(stream, &["GET", "key1"]).as_str() {
+            "$6\r\nvalue1\r\n" => break,
+            _ if started.elapsed() < Duration::from_secs(10) => {
+                thread::sleep(Duration::from_millis(10))
+            }
+            response => panic!("connection still refused: {}", response),
+        }
+    }
+}
+
+#[test]
+fn rate_limit_throttles_each_client() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let limit = RateLimit::new(20, 5).unwrap();
+    let addr = start_configured_server_at(&temp_dir, |server| server.with_rate_limit(limit));
+    let stream = &mut TcpStream::connect(addr).unwrap();
+    let other = &mut TcpStream::connect(addr).unwrap();
+
+    // the burst is served immediately, then requests from every connection of the client's
+    // address are limited to the rate
+    let started = Instant::now();
+    for _ in 0..5 {
+        assert_eq!(command(stream, &["SET", "key1", "value1"]), "+OK\r\n");
+    }
+    assert!(started.elapsed() < Duration::from_millis(200));
+    for _ in 0..5 {
+        assert_eq!(command(other, &["GET", "key1"]), "$6\r\nvalue1\r\n");
+    }
+    assert!(started.elapsed() >= Duration::from_millis(200));
+
+    assert!(RateLimit::new(0, 5).is_err());
+    assert!(RateLimit::new(5, 0).is_err());
+}
+
+#[test]
+fn resp_persists_between_servers() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    {
+        let stream = &mut start_server(&temp_dir);
+        assert_eq!(command(stream, &["SET", "key1", "value1"]), "+OK\r\n");
+    }
+    let stream = &mut start_server(&temp_dir);
+    assert_eq!(command(stream, &["GET", "key1"]), "$6\r\nvalue1\r\n");
+}
+
+#[test]
+fn resp_pipelined_commands() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream = &mut start_server(&temp_dir);
+
+    let pipeline = [
+        encode_command(&["SET", "key1", "value1"]),
+        encode_command(&["SET", "key2", "value2"]),
+        encode_command(&["DEL", "key1", "key3"]),
+        encode_command(&["GET", "key2"]),
+        encode_command(&["BOGUS"]),
+        encode_command(&["SET", "key1", "value3"]),
+        encode_command(&["EXISTS", "key1", "key2"]),
+    ]
+    .concat();
+    stream.write_all(pipeline.as_bytes()).unwrap();
+
+    let reader = &mut io::BufReader::new(stream.try_clone().unwrap());
+    assert_eq!(read_response(reader), "+OK\r\n");
+    assert_eq!(read_response(reader), "+OK\r\n");
+    assert_eq!(read_response(reader), ":1\r\n");
+    assert_eq!(read_response(reader), "$6\r\nvalue2\r\n");
+    assert_eq!(read_response(reader), "-ERR unknown command 'BOGUS'\r\n");
+    assert_eq!(read_response(reader), "+OK\r\n");
+    assert_eq!(read_response(reader), ":2\r\n");
+}
+
+// What redis-benchmark sends: CONFIG GET on connecting, then PING inline and as an array, SET and
+// GET (pipelined with -P).
+#[test]
+fn resp_serves_redis_benchmark() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream = &mut start_server(&temp_dir);
+
+    assert_eq!(
+        command(stream, &["CONFIG", "GET", "save"]),
+        "*2\r\n$4\r\nsave\r\n$0\r\n\r\n"
+    );
+    assert_eq!(
+        command(stream, &["CONFIG", "GET", "appendonly"]),
+        "*2\r\n$10\r\nappendonly\r\n$2\r\nno\r\n"
+    );
+    assert_eq!(command(stream, &["CONFIG", "GET", "maxmemory"]), "*0\r\n");
+    assert_eq!(command(stream, &["PING"]), "+PONG\r\n");
+    assert_eq!(command(stream, &["PING", "hello"]), "$5\r\nhello\r\n");
+    let count = command(stream, &["COMMAND", "COUNT"]);
+    let list = command(stream, &["COMMAND", "LIST"]);
+    assert!(list.starts_with(&format!("*{}", &count[1..])));
+    assert!(list.contains("$4\r\nping\r\n"));
+
+    let inline = "PING\r\n\r\nSET key:1 xxx\r\n  GET   key:1 \nget key:2\r\n";
+    stream.write_all(inline.as_bytes()).unwrap();
+    stream
+        .write_all(encode_command(&["GET", "key:1"]).as_bytes())
+        .unwrap();
+    let reader = &mut io::BufReader::new(stream.try_clone().unwrap());
+    assert_eq!(read_response(reader), "+PONG\r\n");
+    assert_eq!(read_response(reader), "+OK\r\n");
+    assert_eq!(read_response(reader), "$3\r\nxxx\r\n");
+    assert_eq!(read_response(reader), "$-1\r\n");
+    assert_eq!(read_response(reader), "$3\r\nxxx\r\n");
+}
+
+// Inline arguments quoted as redis-cli quotes them, and unbalanced quotes replied to with an error
+// before the connection is closed.
+#[test]
+fn resp_inline_quoted_arguments() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream = &mut start_server(&temp_dir);
+
+    let inline = concat!(
+        "SET \"key 1\" \"va\\\"l\\x41\\n\"\r\n",
+        "GET 'key 1'\r\n",
+        "SET 'it\\'s' \"\"\r\n",
+        "get it\"'s\"\r\n",
+        "GET \"key 1\"x\r\n",
+    );
+    stream.write_all(inline.as_bytes()).unwrap();
+    let reader = &mut io::BufReader::new(stream.try_clone().unwrap());
+    assert_eq!(read_response(reader), "+OK\r\n");
+    assert_eq!(read_response(reader), "$6\r\nva\"lA\n\r\n");
+    assert_eq!(read_response(reader), "+OK\r\n");
+    assert_eq!(read_response(reader), "$0\r\n\r\n");
+    assert_eq!(
+        read_response(reader),
+        "-ERR Protocol error: unbalanced quotes in request\r\n"
+    );
+    assert_eq!(reader.read(&mut [0; 16]).unwrap(), 0);
+}
+
+// Bulk strings longer than the server's maximum and lines without an end refused before they are
+// read.
+#[test]
+fn resp_bulk_strings_and_lines_beyond_the_limits() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_configured_server_at(&temp_dir, |server| server.with_max_bulk_len(8));
+
+    let stream = &mut TcpStream::connect(addr).unwrap();
+    assert_eq!(command(stream, &["SET", "key1", "12345678"]), "+OK\r\n");
+    stream
+        .write_all(b"*3\r\n$3\r\nSET\r\n$4\r\nkey1\r\n$1000000000\r\n")
+        .unwrap();
+    let reader = &mut io::BufReader::new(stream.try_clone().unwrap());
+    assert_eq!(
+        read_response(reader),
+        "-ERR Protocol error: invalid bulk length\r\n"
+    );
+    assert_eq!(reader.read(&mut [0; 16]).unwrap(), 0);
+
+    let stream = &mut TcpStream::connect(addr).unwrap();
+    stream.write_all(b"*1\r\n$").unwrap();
+    // no more than is read, so the connection is not reset by closing it with input unread
+    stream.write_all(&[b'1'; 64 * 1024]).unwrap();
+    let reader = &mut io::BufReader::new(stream.try_clone().unwrap());
+    assert_eq!(
+        read_response(reader),
+        "-ERR Protocol error: too big line\r\n"
+    );
+}
+
+#[test]
+fn resp_multi_exec_transactions() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_server_at(&temp_dir);
+    let stream = &mut TcpStream::connect(addr).unwrap();
+    let other = &mut TcpStream::connect(addr).unwrap();
+
+    assert_eq!(command(stream, &["SET", "key2", "value2"]), "+OK\r\n");
+    assert_eq!(command(stream, &["MULTI"]), "+OK\r\n");
+    assert_eq!(
+        command(stream, &["MULTI"]),
+        "-ERR MULTI calls can not be nested\r\n"
+    );
+    assert_eq!(command(stream, &["SET", "key1", "value1"]), "+QUEUED\r\n");
+    assert_eq!(command(stream, &["DEL", "key2", "key3"]), "+QUEUED\r\n");
+    // nothing is applied before EXEC
+    assert_eq!(command(other, &["GET", "key1"]), "$-1\r\n");
+    assert_eq!(command(other, &["GET", "key2"]), "$6\r\nvalue2\r\n");
+    assert_eq!(command(stream, &["EXEC"]), "*2\r\n+OK\r\n:1\r\n");
+    assert_eq!(command(other, &["GET", "key1"]), "$6\r\nvalue1\r\n");
+    assert_eq!(command(other, &["GET", "key2"]), "$-1\r\n");
+    assert_eq!(command(stream, &["MULTI"]), "+OK\r\n");
+    assert_eq!(command(stream, &["EXEC"]), "*0\r\n");
+
+    assert_eq!(command(stream, &["MULTI"]), "+OK\r\n");
+    assert_eq!(command(stream, &["SET", "key4", "value4"]), "+QUEUED\r\n");
+    assert_eq!(command(stream, &["DISCARD"]), "+OK\r\n");
+    assert_eq!(command(stream, &["GET", "key4"]), "$-1\r\n");
+    assert_eq!(command(stream, &["EXEC"]), "-ERR EXEC without MULTI\r\n");
+    assert_eq!(
+        command(stream, &["DISCARD"]),
+        "-ERR DISCARD without MULTI\r\n"
+    );
+
+    // a command which cannot be queued aborts the transaction
+    for refused in [&["GET", "key1"][..], &["FLY"][..]].iter() {
+        assert_eq!(command(stream, &["MULTI"]), "+OK\r\n");
+        assert_eq!(command(stream, &["SET", "key5", "value5"]), "+QUEUED\r\n");
+        assert!(command(stream, refused).starts_with("-ERR "));
+        assert_eq!(
+            command(stream, &["EXEC"]),
+            "-EXECABORT Transaction discarded because of previous errors.\r\n"
+        );
+        assert_eq!(command(stream, &["GET", "key5"]), "$-1\r\n");
+    }
+
+    // a transaction pipelined with other commands
+    let pipeline = [
+        encode_command(&["SET", "key6", "value6"]),
+        encode_command(&["MULTI"]),
+        encode_command(&["SET", "key7", "value7"]),
+        encode_command(&["EXEC"]),
+        encode_command(&["GET", "key7"]),
+    ]
+    .concat();
+    stream.write_all(pipeline.as_bytes()).unwrap();
+    let reader = &mut io::BufReader::new(stream.try_clone().unwrap());
+    assert_eq!(read_response(reader), "+OK\r\n");
+    assert_eq!(read_response(reader), "+OK\r\n");
+    assert_eq!(read_response(reader), "+QUEUED\r\n");
+    assert_eq!(read_response(reader), "*1\r\n+OK\r\n");
+    assert_eq!(read_response(reader), "$6\r\nvalue7\r\n");
+}
+
+#[test]
+fn resp_many_pipelined_commands() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let stream = &mut start_server(&temp_dir);
+
+    let pipeline = (0..5000)
+        .map(|i| encode_command(&["SET", &format!("key{}", i), &format!("value{}", i)]))
+        .collect::<String>();
+    stream.write_all(pipeline.as_bytes()).unwrap();
+
+    let reader = &mut io::BufReader::new(stream.try_clone().unwrap());
+    for _ in 0..5000 {
+        assert_eq!(read_response(reader), "+OK\r\n");
+    }
+    assert_eq!(command(stream, &["GET", "key4999"]), "$9\r\nvalue4999\r\n");
+}
+
+#[test]
+fn shutdown_drains_connections_and_shuts_down_engine() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let server = KvsServer::new(SharedKvStore::open(temp_dir.path()).unwrap());
+    let shutdown = server.shutdown_handle();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let serving = thread::spawn(move || server.serve(listener));
+
+    let stream = &mut TcpStream::connect(addr).unwrap();
+    assert_eq!(command(stream, &["SET", "key1", "value1"]), "+OK\r\n");
+    let idle = TcpStream::connect(addr).unwrap();
+
+    shutdown.shutdown();
+    serving.join().unwrap().unwrap();
+    assert!(shutdown.is_shutdown_requested());
+    // open connections are closed by the server and no more are accepted
+    assert_eq!(stream.read(&mut [0; 16]).unwrap(), 0);
+    assert_eq!((&idle).read(&mut [0; 16]).unwrap(), 0);
+    assert!(TcpStream::connect(addr).is_err());
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path()).unwrap();
+    assert!(store.was_shut_down_cleanly());
+    assert_eq!(
+        store.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+}
+
+#[test]
+fn kvs_proto_client_set_get_remove() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let client = &mut KvsClient::connect(start_server_at(&temp_dir)).unwrap();
+
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    assert_eq!(
+        client.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+    assert_eq!(client.get("key2".to_owned()).unwrap(), None);
+    client.remove("key1".to_owned()).unwrap();
+    assert_eq!(client.get("key1".to_owned()).unwrap(), None);
+    let err = client.remove("key1".to_owned()).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::KeyNotPresent);
+    // the connection remains usable after an error
+    client
+        .set("key1".to_owned(), "value\nwith\nnewlines".to_owned())
+        .unwrap();
+    assert_eq!(
+        client.get("key1".to_owned()).unwrap(),
+        Some("value\nwith\nnewlines".to_owned())
+    );
+}
+
+#[test]
+fn kvs_proto_client_admin() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let client = &mut KvsClient::connect(start_server_at(&temp_dir)).unwrap();
+
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    client.set("key1".to_owned(), "value2".to_owned()).unwrap();
+    client.set("key2".to_owned(), "value2".to_owned()).unwrap();
+    assert_eq!(client.key_count().unwrap(), 2);
+    client.compact().unwrap();
+    assert_eq!(
+        client.get("key1".to_owned()).unwrap(),
+        Some("value2".to_owned())
+    );
+    client.clear().unwrap();
+    assert_eq!(client.key_count().unwrap(), 0);
+    assert_eq!(client.get("key2".to_owned()).unwrap(), None);
+}
+
+#[test]
+fn kvs_proto_request_traced_again_ends_only_its_connection() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_server_at(&temp_dir);
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let nested =
+        "#2\nRequest\n$Traced\n$id\n$id1\n$request\n".repeat(5_000) + "@Request\n$KeyCount\n";
+    stream.write_all(nested.as_bytes()).unwrap();
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response);
+    assert!(response.is_empty());
+
+    let client = &mut KvsClient::connect(addr).unwrap();
+    assert_eq!(client.key_count().unwrap(), 0);
+}
+
+#[test]
+fn kvs_proto_client_select() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let other_dir = TempDir::new().expect("unable to create temporary working directory");
+    let other = SharedKvStore::open(other_dir.path()).unwrap();
+    let addr = start_configured_server_at(&temp_dir, |server| server.with_database(other));
+    let client = &mut KvsClient::connect(addr).unwrap();
+
+    client.set("key1".to_owned(), "value0".to_owned()).unwrap();
+    client.select(1).unwrap();
+    assert_eq!(client.get("key1".to_owned()).unwrap(), None);
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    let err = client.select(2).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::ServerError);
+    assert_eq!(
+        client.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+    client.select(0).unwrap();
+    assert_eq!(
+        client.get("key1".to_owned()).unwrap(),
+        Some("value0".to_owned())
+    );
+}
+
+#[test]
+fn kvs_proto_client_scan() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let client = &mut KvsClient::connect(start_server_at(&temp_dir)).unwrap();
+
+    for i in 0..2500 {
+        client
+            .set(format!("key{:04}", i), format!("value{}", i))
+            .unwrap();
+    }
+    client.set("other".to_owned(), "value".to_owned()).unwrap();
+
+    let (cursor, pairs) = client.scan_page(0, "key".to_owned(), 2).unwrap();
+    assert_ne!(cursor, 0);
+    assert_eq!(
+        pairs,
+        vec![
+            ("key0000".to_owned(), "value0".to_owned()),
+            ("key0001".to_owned(), "value1".to_owned())
+        ]
+    );
+    let (cursor, pairs) = client.scan_page(cursor, "key".to_owned(), 2497).unwrap();
+    assert_ne!(cursor, 0);
+    assert_eq!(pairs[2496].0, "key2498");
+    let (cursor, pairs) = client.scan_page(cursor, "key".to_owned(), 10).unwrap();
+    assert_eq!(cursor, 0);
+    assert_eq!(pairs, vec![("key2499".to_owned(), "value2499".to_owned())]);
+    let err = client.scan_page(17, "key".to_owned(), 10).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::ServerError);
+
+    // the scan is requested in pages, together returning every key in order
+    let scanned = client
+        .scan("key".to_owned())
+        .collect::<kvs::Result<Vec<_>>>()
+        .unwrap();
+    assert_eq!(scanned.len(), 2500);
+    assert!(scanned.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    assert_eq!(
+        scanned[1234],
+        ("key1234".to_owned(), "value1234".to_owned())
+    );
+    assert_eq!(client.scan(String::new()).count(), 2501);
+    assert_eq!(client.scan("none".to_owned()).count(), 0);
+}
+
+#[test]
+fn kvs_proto_scan_cursor_survives_writes_and_compaction() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_server_at(&temp_dir);
+    let client = &mut KvsClient::connect(addr).unwrap();
+    let writer = &mut KvsClient::connect(addr).unwrap();
+
+    for i in 0..500 {
+        client
+            .set(format!("key{:04}", i * 2), "kept".to_owned())
+            .unwrap();
+    }
+    let (mut cursor, mut scanned) = (0, Vec::new());
+    for page in 0.. {
+        let (next, pairs) = client.scan_page(cursor, "key".to_owned(), 30).unwrap();
+        scanned.extend(pairs.into_iter().map(|(key, _)| key));
+        if next == 0 {
+            break;
+        }
+        cursor = next;
+        // between pages, keys are set (and overwritten) before and after the cursor, keys
+        // scanned already are removed, and the log is rotated and compacted
+        for i in 0..100 {
+            writer
+                .set(
+                    format!("key{:04}", ((page * 37 + i * 13) % 1000) | 1),
+                    "new".repeat(20),
+                )
+                .unwrap();
+        }
+        writer.remove(scanned[scanned.len() - 1].clone()).unwrap();
+        if page % 3 == 0 {
+            writer.compact().unwrap();
+        }
+    }
+    // every key present throughout is returned, and no key twice, in order
+    assert!(scanned.windows(2).all(|pair| pair[0] < pair[1]));
+    for i in 0..500 {
+        let key = format!("key{:04}", i * 2);
+        assert!(scanned.contains(&key), "{} was not scanned", key);
+    }
+}
+
+#[test]
+fn kvs_proto_client_set_if_absent() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let client = &mut KvsClient::connect(start_server_at(&temp_dir)).unwrap();
+
+    assert!(client
+        .set_if_absent("key1".to_owned(), "value1".to_owned())
+        .unwrap());
+    assert!(!client
+        .set_if_absent("key1".to_owned(), "value2".to_owned())
+        .unwrap());
+    assert_eq!(
+        client.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+}
+
+#[test]
+fn kvs_proto_client_append() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let client = &mut KvsClient::connect(start_server_at(&temp_dir)).unwrap();
+
+    assert_eq!(client.value_len("key1".to_owned()).unwrap(), 0);
+    assert_eq!(
+        client
+            .append("key1".to_owned(), "value".to_owned())
+            .unwrap(),
+        5
+    );
+    assert_eq!(client.append("key1".to_owned(), "1".to_owned()).unwrap(), 6);
+    assert_eq!(client.value_len("key1".to_owned()).unwrap(), 6);
+    assert_eq!(
+        client.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+}
+
+#[test]
+fn kvs_proto_client_get_many_and_set_many() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let client = &mut KvsClient::connect(start_server_at(&temp_dir)).unwrap();
+
+    client
+        .set_many(vec![
+            ("key1".to_owned(), "value1".to_owned()),
+            ("key2".to_owned(), "value2".to_owned()),
+        ])
+        .unwrap();
+    assert_eq!(
+        client
+            .get_many(vec![
+                "key2".to_owned(),
+                "key3".to_owned(),
+                "key1".to_owned()
+            ])
+            .unwrap(),
+        vec![Some("value2".to_owned()), None, Some("value1".to_owned())]
+    );
+    assert_eq!(client.get_many(Vec::new()).unwrap(), Vec::new());
+    assert_eq!(client.key_count().unwrap(), 2);
+}
+
+#[test]
+fn kvs_proto_client_locks() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_server_at(&temp_dir);
+    let client = &mut KvsClient::connect(addr).unwrap();
+    let other = &mut KvsClient::connect(addr).unwrap();
+
+    let ttl = Duration::from_secs(10);
+    let lock = client.acquire_lock("job".to_owned(), ttl).unwrap().unwrap();
+    assert_eq!(other.acquire_lock("job".to_owned(), ttl).unwrap(), None);
+    let refreshed = client.refresh_lock(&lock, ttl).unwrap().unwrap();
+    assert_eq!(refreshed.token, lock.token);
+    assert!(client.release_lock(&refreshed).unwrap());
+    assert!(!client.release_lock(&refreshed).unwrap());
+    assert_eq!(client.refresh_lock(&lock, ttl).unwrap(), None);
+    let next = client.acquire_lock("job".to_owned(), ttl).unwrap().unwrap();
+    assert!(next.token > lock.token);
+}
+
+#[test]
+fn kvs_proto_client_subscribe() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_server_at(&temp_dir);
+    let mut events = KvsClient::connect(addr)
+        .unwrap()
+        .subscribe("user:".to_owned())
+        .unwrap();
+    let client = &mut KvsClient::connect(addr).unwrap();
+
+    client.set("user:1".to_owned(), "alice".to_owned()).unwrap();
+    client.set("other".to_owned(), "value".to_owned()).unwrap();
+    client.remove("user:1".to_owned()).unwrap();
+    client.clear().unwrap();
+    assert_eq!(
+        events.next().unwrap().unwrap(),
+        KeyEvent::Set("user:1".to_owned())
+    );
+    assert_eq!(
+        events.next().unwrap().unwrap(),
+        KeyEvent::Removed("user:1".to_owned())
+    );
+    assert_eq!(events.next().unwrap().unwrap(), KeyEvent::Cleared);
+}
+
+#[test]
+fn filter_rejects_requests_of_both_protocols() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_configured_server_at(&temp_dir, |server| {
+        server.with_filter(|request: &Request| match request {
+            Request::Set { value, .. } if value.is_empty() => Err("ERR empty value".into()),
+            Request::Remove { key } if key.starts_with("locked:") => {
+                Err("ERR key is locked".into())
+            }
+            _ => Ok(()),
+        })
+    });
+    let stream = &mut TcpStream::connect(addr).unwrap();
+
+    assert_eq!(command(stream, &["SET", "locked:1", "value1"]), "+OK\r\n");
+    assert_eq!(
+        command(stream, &["SET", "key1", ""]),
+        "-ERR empty value\r\n"
+    );
+    assert_eq!(
+        command(stream, &["DEL", "key1", "locked:1"]),
+        "-ERR key is locked\r\n"
+    );
+    assert_eq!(command(stream, &["DBSIZE"]), ":1\r\n");
+
+    let client = &mut KvsClient::connect(addr).unwrap();
+    let err = client.set("key1".to_owned(), "".to_owned()).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::ServerError);
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    let err = client.remove("locked:1".to_owned()).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::ServerError);
+    assert_eq!(client.key_count().unwrap(), 2);
+}
+
+#[test]
+fn kvs_proto_errors_carry_the_request_id() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_configured_server_at(&temp_dir, |server| {
+        server
+            .with_filter(|request: &Request| match request {
+                Request::Set { value, .. } if value.is_empty() => Err("ERR empty value".into()),
+                _ => Ok(()),
+            })
+            .with_slow_log(Duration::from_secs(60))
+    });
+    let client = &mut KvsClient::connect(addr).unwrap();
+
+    client.set_request_id(Some("req-42".to_owned()));
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    assert_eq!(
+        client.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+    let err = client.set("key1".to_owned(), "".to_owned()).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::ServerError);
+    let message = std::error::Error::source(&err).unwrap().to_string();
+    assert_eq!(message, "ERR empty value (request req-42)");
+
+    client.set_request_id(None);
+    let err = client.set("key1".to_owned(), "".to_owned()).unwrap_err();
+    let message = std::error::Error::source(&err).unwrap().to_string();
+    assert_eq!(message, "ERR empty value");
+}
+
+#[test]
+fn kvs_proto_client_authentication() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let client =
+        &mut KvsClient::connect(start_server_with_password_at(&temp_dir, "secret")).unwrap();
+
+    let err = client.get("key1".to_owned()).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::AuthenticationFailed);
+    let err = client.authenticate("wrong".to_owned()).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::AuthenticationFailed);
+    client.authenticate("secret".to_owned()).unwrap();
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    assert_eq!(
+        client.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+}
+
+#[test]
+fn kvs_proto_client_acl() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let acl: Acl = serde_json::from_str(
+        r#"{"users": [
+            {"name": "sessions", "token": "sessions-token", "key_prefixes": ["session:"]},
+            {"name": "reader", "token": "reader-token", "read_only": true}
+        ]}"#,
+    )
+    .unwrap();
+    let addr = start_configured_server_at(&temp_dir, |server| {
+        server.with_password("secret".into()).with_acl(acl)
+    });
+
+    let client = &mut KvsClient::connect(addr).unwrap();
+    let err = client.get("session:1".to_owned()).unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::AuthenticationFailed);
+    client.authenticate("sessions-token".to_owned()).unwrap();
+    client
+        .set("session:1".to_owned(), "ann".to_owned())
+        .unwrap();
+    let err = client
+        .set("user:1".to_owned(), "ann".to_owned())
+        .unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::ServerError);
+    // every key of a request for many keys is checked, none being set if any is refused
+    let pairs = vec![
+        ("session:2".to_owned(), "bob".to_owned()),
+        ("user:2".to_owned(), "bob".to_owned()),
+    ];
+    assert!(client.set_many(pairs).is_err());
+    assert_eq!(client.get("session:2".to_owned()).unwrap(), None);
+    assert!(client.clear().is_err());
+
+    let client = &mut KvsClient::connect(addr).unwrap();
+    client.authenticate("reader-token".to_owned()).unwrap();
+    assert_eq!(
+        client.get("session:1".to_owned()).unwrap(),
+        Some("ann".to_owned())
+    );
+    assert!(client.remove("session:1".to_owned()).is_err());
+
+    // the password is not restricted by the ACL
+    let client = &mut KvsClient::connect(addr).unwrap();
+    client.authenticate("secret".to_owned()).unwrap();
+    client.set("user:1".to_owned(), "ann".to_owned()).unwrap();
+}
+
+#[test]
+fn config_file_is_reloaded_by_clients() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let config_dir = TempDir::new().expect("unable to create temporary working directory");
+    let config_path = config_dir.path().join("kvs-server.json");
+    std::fs::write(&config_path, r#"{"store": {"max_keys": 1}}"#).unwrap();
+    let config = config_path.clone();
+    let addr = start_configured_server_at(&temp_dir, |server| server.with_config_file(config));
+    let client = &mut KvsClient::connect(addr).unwrap();
+
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    let err = client
+        .set("key2".to_owned(), "value2".to_owned())
+        .unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::QuotaExceeded);
+
+    std::fs::write(&config_path, r#"{"store": {"max_keys": 2}}"#).unwrap();
+    client.reload_config().unwrap();
+    client.set("key2".to_owned(), "value2".to_owned()).unwrap();
+
+    // a config file which is not valid leaves the settings as they were
+    std::fs::write(&config_path, r#"{"rate_limit": 0}"#).unwrap();
+    let err = client.reload_config().unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::ServerError);
+    let err = client
+        .set("key3".to_owned(), "value3".to_owned())
+        .unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::QuotaExceeded);
+    // the error replied to RESP clients is of the code of its kind
+    let stream = &mut TcpStream::connect(addr).unwrap();
+    assert!(command(stream, &["SET", "key3", "value3"]).starts_with("-OOM "));
+
+    std::fs::write(&config_path, r#"{"store": {}}"#).unwrap();
+    assert_eq!(command(stream, &["CONFIG", "RELOAD"]), "+OK\r\n");
+    assert_eq!(command(stream, &["SET", "key3", "value3"]), "+OK\r\n");
+    assert_eq!(
+        command(stream, &["CONFIG", "SET", "maxmemory", "1"]),
+        "-ERR unknown subcommand 'SET'\r\n"
+    );
+}
+
+#[test]
+fn config_reload_fails_without_a_config_file() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_server_at(&temp_dir);
+    let err = KvsClient::connect(addr)
+        .unwrap()
+        .reload_config()
+        .unwrap_err();
+    assert_eq!(*err.kind(), ErrorKind::ServerError);
+    let stream = &mut TcpStream::connect(addr).unwrap();
+    assert!(command(stream, &["CONFIG", "RELOAD"]).starts_with("-ERR"));
+}
+
+#[test]
+fn both_protocols_on_same_port() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_server_at(&temp_dir);
+    let client = &mut KvsClient::connect(addr).unwrap();
+    let stream = &mut TcpStream::connect(addr).unwrap();
+
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    assert_eq!(command(stream, &["GET", "key1"]), "$6\r\nvalue1\r\n");
+    assert_eq!(command(stream, &["SET", "key2", "value2"]), "+OK\r\n");
+    assert_eq!(
+        client.get("key2".to_owned()).unwrap(),
+        Some("value2".to_owned())
+    );
+}
+
+#[test]
+fn cli_client_set_get_rm() {
+    use assert_cmd::prelude::*;
+    use predicates::ord::eq;
+    use predicates::str::{contains, is_empty, PredicateStrExt};
+    use std::process::Command;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_server_at(&temp_dir).to_string();
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["set", "key1", "value1", "--addr", &addr])
+        .assert()
+        .success()
+        .stdout(is_empty());
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["get", "key1", "--addr", &addr])
+        .assert()
+        .success()
+        .stdout(eq("value1").trim());
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["rm", "key1", "--addr", &addr])
+        .assert()
+        .success()
+        .stdout(is_empty());
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["get", "key1", "--addr", &addr])
+        .assert()
+        .success()
+        .stdout(eq("Key not found").trim());
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["rm", "key1", "--addr", &addr])
+        .assert()
+        .failure()
+        .stderr(contains("Key not found"));
+}
+
+#[test]
+fn cli_client_json_output() {
+    use assert_cmd::prelude::*;
+    use predicates::ord::eq;
+    use predicates::str::PredicateStrExt;
+    use std::process::Command;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_server_at(&temp_dir).to_string();
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["set", "key1", "value \"1\"", "--addr", &addr])
+        .assert()
+        .success();
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["get", "key1", "--output", "json", "--addr", &addr])
+        .assert()
+        .success()
+        .stdout(eq(r#"{"key":"key1","value":"value \"1\""}"#).trim());
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["get", "key2", "--output", "json", "--addr", &addr])
+        .assert()
+        .success()
+        .stdout(eq(r#"{"key":"key2","value":null}"#).trim());
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["dbsize", "--output", "json", "--addr", &addr])
+        .assert()
+        .success()
+        .stdout(eq(r#"{"count":1}"#).trim());
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["stats", "--output", "json", "--addr", &addr])
+        .assert()
+        .success()
+        .stdout(predicates::str::starts_with(r#"{"keys":1"#));
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["stats", "--addr", &addr])
+        .assert()
+        .success()
+        .stdout(predicates::str::starts_with("keys: 1\n"));
+}
+
+#[test]
+fn cli_client_authentication() {
+    use assert_cmd::prelude::*;
+    use predicates::ord::eq;
+    use predicates::str::{contains, PredicateStrExt};
+    use std::process::Command;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_server_with_password_at(&temp_dir, "secret").to_string();
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .env_remove("KVS_PASSWORD")
+        .args(["set", "key1", "value1", "--addr", &addr])
+        .assert()
+        .failure()
+        .stderr(contains("Authentication failed"));
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args([
+            "set",
+            "key1",
+            "value1",
+            "--addr",
+            &addr,
+            "--password",
+            "wrong",
+        ])
+        .assert()
+        .failure()
+        .stderr(contains("Authentication failed"));
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args([
+            "set",
+            "key1",
+            "value1",
+            "--addr",
+            &addr,
+            "--password",
+            "secret",
+        ])
+        .assert()
+        .success();
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .env("KVS_PASSWORD", "secret")
+        .args(["get", "key1", "--addr", &addr])
+        .assert()
+        .success()
+        .stdout(eq("value1").trim());
+}
+
+#[test]
+fn cli_server_shuts_down_on_sigterm() {
+    use assert_cmd::prelude::*;
+    use std::process::{Command, Stdio};
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .to_string();
+    let mut server = Command::cargo_bin("kvs-server")
+        .unwrap()
+        .args(["--addr", &addr])
+        .env_remove("KVS_PASSWORD")
+        .current_dir(&temp_dir)
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let started = Instant::now();
+    let client = &mut loop {
+        match KvsClient::connect(&addr) {
+            Ok(client) => break client,
+            Err(_) if started.elapsed() < Duration::from_secs(10) => {
+                thread::sleep(Duration::from_millis(20))
+            }
+            Err(err) => panic!("unable to connect to kvs-server: {}", err),
+        }
+    };
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+
+    let killed = Command::new("kill")
+        .args(["-TERM", &server.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(killed.success());
+    assert!(server.wait().unwrap().success());
+
+    assert!(KvStore::<String, String>::open(temp_dir.path())
+        .unwrap()
+        .was_shut_down_cleanly());
+}
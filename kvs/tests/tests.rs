@@ -1,7 +1,7 @@
 use assert_cmd::prelude::*;
 use kvs::{KvStore, Result};
 use predicates::ord::eq;
-use predicates::str::{contains, is_empty, PredicateStrExt};
+use predicates::str::{contains, is_empty, starts_with, PredicateStrExt};
 use std::process::Command;
 use tempfile::TempDir;
 use walkdir::WalkDir;
@@ -17,7 +17,7 @@ fn cli_no_args() {
 fn cli_version() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["-V"])
+        .args(["-V"])
         .assert()
         .stdout(contains(env!("CARGO_PKG_VERSION")));
 }
@@ -28,7 +28,7 @@ fn cli_get_non_existent_key() {
     let temp_dir = TempDir::new().unwrap();
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["get", "key1"])
+        .args(["get", "key1"])
         .current_dir(&temp_dir)
         .assert()
         .success()
@@ -41,7 +41,7 @@ fn cli_rm_non_existent_key() {
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["rm", "key1"])
+        .args(["rm", "key1"])
         .current_dir(&temp_dir)
         .assert()
         .failure()
@@ -54,7 +54,7 @@ fn cli_set() {
     let temp_dir = TempDir::new().expect("unable to create temporary working directory");
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["set", "key1", "value1"])
+        .args(["set", "key1", "value1"])
         .current_dir(&temp_dir)
         .assert()
         .success()
@@ -72,7 +72,7 @@ fn cli_get_stored() -> Result<()> {
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["get", "key1"])
+        .args(["get", "key1"])
         .current_dir(&temp_dir)
         .assert()
         .success()
@@ -80,7 +80,7 @@ fn cli_get_stored() -> Result<()> {
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["get", "key2"])
+        .args(["get", "key2"])
         .current_dir(&temp_dir)
         .assert()
         .success()
@@ -100,7 +100,7 @@ fn cli_rm_stored() -> Result<()> {
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["rm", "key1"])
+        .args(["rm", "key1"])
         .current_dir(&temp_dir)
         .assert()
         .success()
@@ -108,7 +108,7 @@ fn cli_rm_stored() -> Result<()> {
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["get", "key1"])
+        .args(["get", "key1"])
         .current_dir(&temp_dir)
         .assert()
         .success()
@@ -117,17 +117,409 @@ fn cli_rm_stored() -> Result<()> {
     Ok(())
 }
 
+// `kvs --db-dir <PATH> --keyspace <NAME>` should use the keyspace of the given database.
+#[test]
+fn cli_db_dir_and_keyspace() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let db_dir = temp_dir.path().join("db");
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["set", "key1", "value1", "--keyspace", "users", "--db-dir"])
+        .arg(&db_dir)
+        .assert()
+        .success()
+        .stdout(is_empty());
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["get", "key1", "--keyspace", "users", "--db-dir"])
+        .arg(&db_dir)
+        .assert()
+        .success()
+        .stdout(eq("value1").trim());
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["get", "key1", "--db-dir"])
+        .arg(&db_dir)
+        .assert()
+        .success()
+        .stdout(eq("Key not found").trim());
+
+    let store = KvStore::<String, String>::open(&db_dir)?;
+    let mut users = store.keyspace::<String, String>("users")?;
+    assert_eq!(users.get("key1".to_owned())?, Some("value1".to_owned()));
+    Ok(())
+}
+
+// `kvs scan [PREFIX]` should print the matching keys in order.
+#[test]
+fn cli_scan() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    let mut store = KvStore::open(temp_dir.path())?;
+    store.set("user:2".to_owned(), "bob".to_owned())?;
+    store.set("user:1".to_owned(), "alice".to_owned())?;
+    store.set("user:3".to_owned(), "carol".to_owned())?;
+    store.set("group:1".to_owned(), "admins".to_owned())?;
+    drop(store);
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["scan"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq("group:1\nuser:1\nuser:2\nuser:3\n"));
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["scan", "user:", "--limit", "2", "--values"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq("user:1\talice\nuser:2\tbob\n"));
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["scan", "--limit", "many"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure();
+
+    Ok(())
+}
+
+// `kvs get`, `kvs scan` and `kvs stats` with `--output json` should print JSON.
+#[test]
+fn cli_json_output() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    let mut store = KvStore::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.set("key2".to_owned(), "value \"2\"".to_owned())?;
+    drop(store);
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["get", "key2", "--output", "json"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq(r#"{"key":"key2","value":"value \"2\""}"#).trim());
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["get", "key3", "--output", "json"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq(r#"{"key":"key3","value":null}"#).trim());
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["scan", "--output", "json"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq(r#"["key1","key2"]"#).trim());
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["scan", "key1", "--values", "--output", "json"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(eq(r#"[{"key":"key1","value":"value1"}]"#).trim());
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["stats", "--output", "json"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(starts_with(r#"{"keys":2"#));
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["stats"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(starts_with("keys: 2\n"));
+
+    Ok(())
+}
+
+// `kvs load` should set the records read from stdin, reporting progress on stderr.
+#[test]
+fn cli_load() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    assert_cmd::Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["load", "--batch-size", "2"])
+        .current_dir(&temp_dir)
+        .write_stdin("key1\tvalue1\nkey2\tvalue 2\n\nkey3\tvalue\t3\n")
+        .assert()
+        .success()
+        .stdout(is_empty())
+        .stderr(eq("2 loaded\n3 loaded\n"));
+
+    assert_cmd::Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["load", "--format", "ndjson"])
+        .current_dir(&temp_dir)
+        .write_stdin("{\"key\":\"key4\",\"value\":\"value4\"}\nkey5\tvalue5\n")
+        .assert()
+        .failure()
+        .stderr(contains("line 2"));
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key2".to_owned())?, Some("value 2".to_owned()));
+    assert_eq!(store.get("key3".to_owned())?, Some("value\t3".to_owned()));
+    assert_eq!(store.get("key4".to_owned())?, Some("value4".to_owned()));
+    assert_eq!(store.get("key5".to_owned())?, None);
+    Ok(())
+}
+
+// `kvs bench` should set its keys, run the load and report its latencies.
+#[test]
+fn cli_bench() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["bench", "--ops", "500", "--threads", "2", "--keys", "50"])
+        .args(["--workload", "read50", "--value-size", "8"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("500 operations (read50) on 2 threads"))
+        .stdout(contains("get: "))
+        .stdout(contains("set: "));
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["bench", "--workload", "write90"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("--workload"))
+        .stderr(contains("read<PERCENT>"));
+
+    for (flag, value) in [
+        ("--threads", "0"),
+        ("--keys", "0"),
+        ("--workload", "read101"),
+    ] {
+        Command::cargo_bin("kvs")
+            .unwrap()
+            .args(["bench", flag, value])
+            .current_dir(&temp_dir)
+            .assert()
+            .failure()
+            .stderr(contains(flag))
+            .stderr(contains(format!("not '{}'", value)));
+    }
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.len(), 50);
+    assert_eq!(
+        store.get("bench:0000000049".to_owned())?,
+        Some("vvvvvvvv".to_owned())
+    );
+    Ok(())
+}
+
+// `kvs compact` should drop stale records, in place or into `--target-dir`.
+#[test]
+fn cli_compact() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let target_dir = temp_dir.path().join("compacted");
+
+    let mut store = KvStore::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.set("key1".to_owned(), "value2".to_owned())?;
+    drop(store);
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["compact", "--target-dir"])
+        .arg(&target_dir)
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(is_empty());
+    assert_eq!(
+        KvStore::<String, String>::verify(temp_dir.path(), false)?.records,
+        2
+    );
+    assert_eq!(
+        KvStore::<String, String>::verify(&target_dir, false)?.records,
+        1
+    );
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["compact"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(is_empty());
+    assert_eq!(
+        KvStore::<String, String>::verify(temp_dir.path(), false)?.records,
+        1
+    );
+
+    Ok(())
+}
+
+// `kvs backup` should copy the store, or that of a server given with `--remote`, to a new store.
+#[test]
+fn cli_backup() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let server_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    let mut store = KvStore::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    drop(store);
+    let mut store = KvStore::open(server_dir.path())?;
+    store.set("key2".to_owned(), "value2".to_owned())?;
+    drop(store);
+    let engine = kvs::SharedKvStore::open(server_dir.path())?;
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || kvs::KvsServer::new(engine).serve(listener));
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["backup", "local"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(is_empty());
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["backup", "remote", "--remote"])
+        .arg(addr.to_string())
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(is_empty());
+
+    let mut local = KvStore::<String, String>::open(&temp_dir.path().join("local"))?;
+    assert_eq!(local.get("key1".to_owned())?, Some("value1".to_owned()));
+    let mut remote = KvStore::<String, String>::open(&temp_dir.path().join("remote"))?;
+    assert_eq!(remote.len(), 1);
+    assert_eq!(remote.get("key2".to_owned())?, Some("value2".to_owned()));
+    drop(remote);
+
+    // a backup is not written over an existing store
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["backup", "remote", "--remote"])
+        .arg(addr.to_string())
+        .current_dir(&temp_dir)
+        .assert()
+        .failure();
+    Ok(())
+}
+
+// `kvs verify` should report the records of the log and fail if it is corrupt.
+#[test]
+fn cli_verify() -> Result<()> {
+    use std::io::Write;
+
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    let mut store = KvStore::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    store.set("key1".to_owned(), "value2".to_owned())?;
+    drop(store);
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["verify"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("records: 2\n"))
+        .stdout(contains("stale records: 1 (50.0%)"))
+        .stdout(contains("corruption: none"));
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["verify", "--verbose"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("superseded key1 = value1\n"))
+        .stdout(contains("live key1 = value2\n"));
+
+    let log = KvStore::<String, String>::verify(temp_dir.path(), false)?
+        .log_path
+        .unwrap();
+    std::fs::OpenOptions::new()
+        .append(true)
+        .open(log)?
+        .write_all(b"garbage")?;
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["verify"])
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stdout(contains("corruption: at offset"));
+
+    Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["verify", "--repair"])
+        .current_dir(&temp_dir)
+        .assert()
+        .success()
+        .stdout(contains("(truncated)"));
+
+    Ok(())
+}
+
+// `kvs repl` should execute each command read from stdin against the store opened once.
+#[test]
+fn cli_repl() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+
+    assert_cmd::Command::cargo_bin("kvs")
+        .unwrap()
+        .args(["repl"])
+        .current_dir(&temp_dir)
+        .write_stdin(
+            "set key1 \"value 1\"\nset key2 value2\nrm key2\nget key1\nget key2\nrm key2\n\
+                bogus\nscan --values\nexit\nget key1\n",
+        )
+        .assert()
+        .success()
+        .stdout(eq("value 1\nKey not found\nKey not found\nkey1\tvalue 1\n"))
+        .stderr(contains("bogus"));
+
+    let mut store = KvStore::<String, String>::open(temp_dir.path())?;
+    assert_eq!(store.get("key1".to_owned())?, Some("value 1".to_owned()));
+    assert_eq!(store.get("key2".to_owned())?, None);
+    Ok(())
+}
+
 #[test]
 fn cli_invalid_get() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["get"])
+        .args(["get"])
         .assert()
         .failure();
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["get", "extra", "field"])
+        .args(["get", "extra", "field"])
         .assert()
         .failure();
 }
@@ -136,19 +528,19 @@ fn cli_invalid_get() {
 fn cli_invalid_set() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["set"])
+        .args(["set"])
         .assert()
         .failure();
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["set", "missing_field"])
+        .args(["set", "missing_field"])
         .assert()
         .failure();
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["set", "extra", "extra", "field"])
+        .args(["set", "extra", "extra", "field"])
         .assert()
         .failure();
 }
@@ -157,13 +549,13 @@ fn cli_invalid_set() {
 fn cli_invalid_rm() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["rm"])
+        .args(["rm"])
         .assert()
         .failure();
 
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["rm", "extra", "field"])
+        .args(["rm", "extra", "field"])
         .assert()
         .failure();
 }
@@ -172,7 +564,7 @@ fn cli_invalid_rm() {
 fn cli_invalid_subcommand() {
     Command::cargo_bin("kvs")
         .unwrap()
-        .args(&["unknown", "subcommand"])
+        .args(["unknown", "subcommand"])
         .assert()
         .failure();
 }
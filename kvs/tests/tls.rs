@@ -0,0 +1,97 @@
+#![cfg(feature = "tls")]
+
+use kvs::{
+    ClientTlsConfig, ErrorKind, KvsClient, KvsClientPool, KvsServer, PoolOptions, ServerTlsConfig,
+    SharedKvStore,
+};
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("tls")
+        .join(name)
+}
+
+// Starts a TLS server for the store in the directory upon its own thread, returning its address.
+fn start_tls_server_at(temp_dir: &TempDir) -> SocketAddr {
+    let tls =
+        ServerTlsConfig::from_pem_files(&fixture("server.crt"), &fixture("server.key")).unwrap();
+    let engine = SharedKvStore::open(temp_dir.path()).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || KvsServer::new(engine).with_tls(tls).serve(listener));
+    addr
+}
+
+#[test]
+fn tls_client_set_get_remove() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_tls_server_at(&temp_dir);
+    let tls = ClientTlsConfig::from_pem_file(&fixture("ca.crt"), "localhost").unwrap();
+    let mut client = KvsClient::connect_tls(addr, &tls).unwrap();
+
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    assert_eq!(
+        client.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+    client.remove("key1".to_owned()).unwrap();
+    assert_eq!(client.get("key1".to_owned()).unwrap(), None);
+}
+
+#[test]
+fn tls_client_rejects_wrong_server_name() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_tls_server_at(&temp_dir);
+    let tls = ClientTlsConfig::from_pem_file(&fixture("ca.crt"), "kvs.example.com").unwrap();
+
+    let err = KvsClient::connect_tls(addr, &tls).err().unwrap();
+    assert_eq!(err.kind(), &ErrorKind::TlsError);
+}
+
+#[test]
+fn tls_server_rejects_plain_client() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_tls_server_at(&temp_dir);
+    let mut client =
+        KvsClient::connect_timeout(addr, Duration::from_secs(5)).expect("unable to connect");
+
+    assert!(client.set("key1".to_owned(), "value1".to_owned()).is_err());
+}
+
+#[test]
+fn tls_pool() {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr = start_tls_server_at(&temp_dir);
+    let pool = KvsClientPool::new(
+        addr,
+        PoolOptions {
+            tls: Some(ClientTlsConfig::from_pem_file(&fixture("ca.crt"), "127.0.0.1").unwrap()),
+            ..PoolOptions::default()
+        },
+    )
+    .unwrap();
+
+    pool.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    assert_eq!(
+        pool.get("key1".to_owned()).unwrap(),
+        Some("value1".to_owned())
+    );
+}
+
+#[test]
+fn tls_invalid_configuration() {
+    let err = ServerTlsConfig::from_pem_files(&fixture("ca.crt"), &fixture("missing.key"))
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), &ErrorKind::InvalidConfiguration);
+    let err = ClientTlsConfig::from_pem_file(&fixture("ca.crt"), "not a name!")
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), &ErrorKind::InvalidConfiguration);
+}
@@ -1,69 +1,135 @@
 #[cfg(test)]
 mod tests;
 
-use super::error;
+use super::{error, value};
 
-use std::io::{self, BufRead, Read};
+use std::{io, iter};
 
 use serde::{
     de::{self, IntoDeserializer},
     Deserialize,
 };
 
+use codec_util::{LineEnding, Reader};
+
 use error::{Error, ErrorKind, Result};
 
+/// deepest nesting of the arrays of a Value (or other self-describing type), beyond which the
+/// input is refused rather than recursed into
+const MAX_DEPTH: usize = 64;
+
+/// Bounds on what the input of a deserialization may hold, beyond which it fails with
+/// LimitExceeded rather than reading on (as the lengths and counts the input gives are not to be
+/// trusted)
+///
+/// The defaults are those of Redis: bulk strings of up to 512MiB and arrays of up to 1048576
+/// elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// longest bulk string or line (of a simple string or number, say), in bytes
+    pub max_len: u64,
+    /// most elements of an array
+    pub max_elements: u32,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_len: 512 * 1024 * 1024,
+            max_elements: 1024 * 1024,
+        }
+    }
+}
+
+/// How strings of the input which are not valid UTF-8 are deserialized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Utf8 {
+    /// refused with a FromUtf8Error
+    #[default]
+    Strict,
+    /// with each invalid sequence replaced by U+FFFD (the replacement character)
+    Lossy,
+    /// as the bytes they are, for types taking bytes as well as strings (as serde_bytes::ByteBuf
+    /// does) to hold them unchanged; byte arrays may then be read from simple strings too
+    Raw,
+}
+
+/// Options of a deserialization
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Options {
+    /// bounds on what the input may hold
+    pub limits: Limits,
+    /// how strings which are not valid UTF-8 are deserialized
+    pub utf8: Utf8,
+}
+
 struct Deserializer<'reader, R: io::Read> {
-    reader: &'reader mut io::BufReader<R>,
+    reader: Reader<'reader, R>,
+    options: Options,
+    depth: usize,
 }
 
+/// deserializes the value at the position of the reader, with the default Options
 pub fn from_reader<'reader, R: io::Read, T>(reader: &'reader mut io::BufReader<R>) -> Result<T>
 where
     T: Deserialize<'reader>,
 {
-    let mut deserializer = Deserializer { reader };
+    from_reader_with_options(reader, Options::default())
+}
+
+/// deserializes the value at the position of the reader, LimitExceeded if its input goes beyond
+/// the limits
+pub fn from_reader_with_limits<'reader, R: io::Read, T>(
+    reader: &'reader mut io::BufReader<R>,
+    limits: Limits,
+) -> Result<T>
+where
+    T: Deserialize<'reader>,
+{
+    let options = Options {
+        limits,
+        ..Options::default()
+    };
+    from_reader_with_options(reader, options)
+}
+
+/// deserializes the value at the position of the reader with the options
+pub fn from_reader_with_options<'reader, R: io::Read, T>(
+    reader: &'reader mut io::BufReader<R>,
+    options: Options,
+) -> Result<T>
+where
+    T: Deserialize<'reader>,
+{
+    let mut deserializer = Deserializer {
+        reader: Reader::new(reader, LineEnding::CrLf).with_max_len(options.limits.max_len),
+        options,
+        depth: 0,
+    };
     T::deserialize(&mut deserializer)
 }
 
 impl<'a, R: io::Read> Deserializer<'a, R> {
-    fn peek(&mut self) -> Result<Option<u8>> {
-        let buf = self.peekn(1)?;
-        match buf {
-            [b] => Ok(Some(*b)),
-            _ => Ok(None),
-        }
-    }
-    fn peekn(&mut self, num: u8) -> Result<&[u8]> {
-        let buf = self.reader.fill_buf()?;
-        Ok(&buf[..(num as usize).min(buf.len())])
-    }
-
-    fn consume(&mut self, num: u8) {
-        self.reader.consume(num as usize);
+    fn read_line(&mut self) -> Result<String> {
+        Ok(String::from_utf8(self.reader.read_line()?)?)
     }
 
-    fn read_line(&mut self) -> Result<String> {
-        let mut line = String::new();
-        let _ = self.reader.read_line(&mut line)?;
-        if line.ends_with("\r\n") {
-            line.pop();
-            line.pop();
-            Ok(line)
-        } else {
-            Err(Error {
-                kind: ErrorKind::DataError,
-                message: format!(
-                    "End of input reached with missing or incorrect CR\\LF pair. Input is: {}",
-                    line
-                ),
-            })
+    fn read_length(&mut self) -> Result<u32> {
+        let element_count = self.read_line()?.parse::<u32>()?;
+        if element_count > self.options.limits.max_elements {
+            return Err(Error::limit_exceeded(format!(
+                "Element count {} beyond the limit of {}",
+                element_count, self.options.limits.max_elements
+            )));
         }
+        Ok(element_count)
     }
 
     fn parse_u64(&mut self) -> Result<u64> {
-        match self.peek()? {
+        match self.reader.peek()? {
             #[allow(clippy::char_lit_as_u8)]
             Some(b':') => {
-                self.consume(1);
+                self.reader.consume(1);
                 Ok(self.read_line()?.parse::<u64>()?)
             }
             input => Err(Error {
@@ -74,10 +140,10 @@ impl<'a, R: io::Read> Deserializer<'a, R> {
     }
 
     fn parse_i64(&mut self) -> Result<i64> {
-        match self.peek()? {
+        match self.reader.peek()? {
             #[allow(clippy::char_lit_as_u8)]
             Some(b':') => {
-                self.consume(1);
+                self.reader.consume(1);
                 Ok(self.read_line()?.parse::<i64>()?)
             }
             input => Err(Error {
@@ -88,10 +154,10 @@ impl<'a, R: io::Read> Deserializer<'a, R> {
     }
 
     fn parse_f64(&mut self) -> Result<f64> {
-        match self.peek()? {
+        match self.reader.peek()? {
             #[allow(clippy::char_lit_as_u8)]
             Some(b'+') => {
-                self.consume(1);
+                self.reader.consume(1);
                 Ok(self.read_line()?.parse::<f64>()?)
             }
             input => Err(Error {
@@ -102,10 +168,10 @@ impl<'a, R: io::Read> Deserializer<'a, R> {
     }
 
     fn parse_f32(&mut self) -> Result<f32> {
-        match self.peek()? {
+        match self.reader.peek()? {
             #[allow(clippy::char_lit_as_u8)]
             Some(b'+') => {
-                self.consume(1);
+                self.reader.consume(1);
                 Ok(self.read_line()?.parse::<f32>()?)
             }
             input => Err(Error {
@@ -127,14 +193,15 @@ impl<'a, R: io::Read> Deserializer<'a, R> {
         })
     }
 
+    /// the bytes of a simple or bulk string, which may not be valid UTF-8
     #[allow(clippy::char_lit_as_u8)]
-    fn parse_string(&mut self) -> Result<String> {
-        match self.peek()? {
+    fn parse_string_bytes(&mut self) -> Result<Vec<u8>> {
+        match self.reader.peek()? {
             Some(b'+') => {
-                self.consume(1);
-                Ok(self.read_line()?)
+                self.reader.consume(1);
+                Ok(self.reader.read_line()?)
             }
-            Some(b'$') => Ok(String::from_utf8(self.parse_bytes()?)?),
+            Some(b'$') => self.parse_bytes(),
             input => Err(Error {
                 kind: ErrorKind::DataError,
                 message: format!(
@@ -147,27 +214,11 @@ impl<'a, R: io::Read> Deserializer<'a, R> {
 
     #[allow(clippy::char_lit_as_u8)]
     fn parse_bytes(&mut self) -> Result<Vec<u8>> {
-        match self.peek()? {
+        match self.reader.peek()? {
             Some(b'$') => {
-                self.consume(1);
-                let len = self.read_line()?.parse::<usize>()?;
-                let mut buf = Vec::<u8>::with_capacity(len);
-                buf.resize(len, Default::default());
-                self.reader.read_exact(buf.as_mut())?;
-                let final_delimiter = self.peekn(2)?;
-                match final_delimiter {
-                    [0xD, 0xA] => {
-                        self.consume(2);
-                        Ok(buf)
-                    }
-                    input => Err(Error {
-                        kind: ErrorKind::DataError,
-                        message: format!(
-                            "Expected ending delimiter 'CR LF' for input of Bytes, found: {:?}",
-                            input
-                        ),
-                    }),
-                }
+                self.reader.consume(1);
+                let len = self.read_line()?.parse::<u64>()?;
+                Ok(self.reader.read_given(len)?)
             }
             input => Err(Error {
                 kind: ErrorKind::DataError,
@@ -197,14 +248,68 @@ macro_rules! parse_number_and_apply_visitor {
     }};
 }
 
-impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R> {
+impl<'de, R: io::Read> de::Deserializer<'de> for &mut Deserializer<'de, R> {
     type Error = Error;
 
-    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        match self.reader.peek()? {
+            Some(b'+') => self.deserialize_string(visitor),
+            Some(b'-') => {
+                // an error is visited as a map of the message, under a key no other map may have
+                self.reader.consume(1);
+                let message = self.read_line()?;
+                visitor.visit_map(de::value::MapDeserializer::new(iter::once((
+                    value::ERROR_TOKEN,
+                    message,
+                ))))
+            }
+            Some(b':') => self.deserialize_i64(visitor),
+            Some(b'$') => match self.reader.peekn(5)? {
+                b"$-1\r\n" => {
+                    self.reader.consume(5);
+                    visitor.visit_none()
+                }
+                _ => self.deserialize_byte_buf(visitor),
+            },
+            Some(b'*') => {
+                if self.reader.peekn(5)? == b"*-1\r\n" {
+                    self.reader.consume(5);
+                    return visitor.visit_unit();
+                }
+                // the elements are of any type too, so their nesting is bounded by the input alone
+                if self.depth >= MAX_DEPTH {
+                    return Err(Error {
+                        kind: ErrorKind::DataError,
+                        message: format!("Arrays nested deeper than {}", MAX_DEPTH),
+                    });
+                }
+                self.depth += 1;
+                self.reader.consume(1);
+                let value = self.read_length().and_then(|element_count| {
+                    visitor.visit_seq(DeserializerSeqElements {
+                        de: &mut *self,
+                        element_count,
+                    })
+                });
+                self.depth -= 1;
+                value
+            }
+            Some(input) => Err(Error {
+                kind: ErrorKind::DataError,
+                message: format!(
+                    "Expected + - : $ or * for input of a RESP value, found: {:?}",
+                    input
+                ),
+            }),
+            None => Err(Error {
+                kind: ErrorKind::DataError,
+                message: "Expected + - : $ or * for input of a RESP value. Empty input/EOF found instead."
+                    .into(),
+            }),
+        }
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
@@ -301,43 +406,53 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
         visitor.visit_char(self.parse_char()?)
     }
 
-    fn deserialize_str<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!("Deserialization of unowned strings is not supported with this deserializer")
+        // the input is read rather than borrowed, so strings are always owned
+        self.deserialize_string(visitor)
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_string(self.parse_string()?)
+        let bytes = self.parse_string_bytes()?;
+        match (String::from_utf8(bytes), self.options.utf8) {
+            (Ok(string), _) => visitor.visit_string(string),
+            (Err(err), Utf8::Strict) => Err(err.into()),
+            (Err(err), Utf8::Lossy) => {
+                visitor.visit_string(String::from_utf8_lossy(err.as_bytes()).into_owned())
+            }
+            (Err(err), Utf8::Raw) => visitor.visit_byte_buf(err.into_bytes()),
+        }
     }
 
-    fn deserialize_bytes<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        unimplemented!(
-            "Deserialization of unowned byte arrays is not supported with this deserializer"
-        )
+        self.deserialize_byte_buf(visitor)
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        visitor.visit_byte_buf(self.parse_bytes()?)
+        match self.options.utf8 {
+            Utf8::Raw => visitor.visit_byte_buf(self.parse_string_bytes()?),
+            _ => visitor.visit_byte_buf(self.parse_bytes()?),
+        }
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        match self.peekn(5)? {
+        match self.reader.peekn(5)? {
             b"$-1\r\n" => {
-                self.consume(5);
+                self.reader.consume(5);
                 visitor.visit_none()
             }
             _ => visitor.visit_some(self),
@@ -348,9 +463,9 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
     where
         V: de::Visitor<'de>,
     {
-        match self.peekn(4)? {
+        match self.reader.peekn(4)? {
             b"*0\r\n" => {
-                self.consume(4);
+                self.reader.consume(4);
                 visitor.visit_unit()
             }
             input => Err(Error {
@@ -381,10 +496,10 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
     where
         V: de::Visitor<'de>,
     {
-        match self.peek()? {
+        match self.reader.peek()? {
             Some(b'*') => {
-                self.consume(1);
-                let element_count = self.read_line()?.parse::<u32>()?;
+                self.reader.consume(1);
+                let element_count = self.read_length()?;
                 visitor.visit_seq(DeserializerSeqElements {
                     de: self,
                     element_count,
@@ -410,10 +525,10 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
     where
         V: de::Visitor<'de>,
     {
-        match self.peek()? {
+        match self.reader.peek()? {
             Some(b'*') => {
-                self.consume(1);
-                let element_count = self.read_line()?.parse::<u32>()?;
+                self.reader.consume(1);
+                let element_count = self.read_length()?;
                 if len != element_count as usize {
                     return Err(Error {
                         kind: ErrorKind::DataError,
@@ -460,10 +575,10 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
     where
         V: de::Visitor<'de>,
     {
-        match self.peek()? {
+        match self.reader.peek()? {
             Some(b'*') => {
-                self.consume(1);
-                let element_count = self.read_line()?.parse::<u32>()?;
+                self.reader.consume(1);
+                let element_count = self.read_length()?;
                 visitor.visit_map(DeserializerSeqElements {
                     de: self,
                     element_count,
@@ -506,11 +621,24 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
     where
         V: de::Visitor<'de>,
     {
-        match self.peek()? {
-            Some(b':') => visitor.visit_enum(variants[self.parse_u64()? as usize].into_deserializer()),
-            Some(b'*') => match self.peekn(4)? {
+        match self.reader.peek()? {
+            Some(b':') => {
+                let index = self.parse_u64()?;
+                match variants.get(index as usize) {
+                    Some(variant) => visitor.visit_enum(variant.into_deserializer()),
+                    None => Err(Error {
+                        kind: ErrorKind::DataError,
+                        message: format!(
+                            "Expected a variant index below {}, found {}",
+                            variants.len(),
+                            index
+                        ),
+                    }),
+                }
+            }
+            Some(b'*') => match self.reader.peekn(4)? {
                 b"*2\r\n" => {
-                    self.consume(4);
+                    self.reader.consume(4);
                     Ok(visitor.visit_enum(DeserializeEnum{de:self})?)
                 },
                 input => Err(Error {
@@ -541,7 +669,7 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
     where
         V: de::Visitor<'de>,
     {
-        match self.peek()? {
+        match self.reader.peek()? {
             Some(b'+') => self.deserialize_string(visitor),
             Some(b':') => self.deserialize_u32(visitor),
             Some(input) => Err(Error {
@@ -559,11 +687,11 @@ impl<'de, 'a, R: io::Read> de::Deserializer<'de> for &'a mut Deserializer<'de, R
         }
     }
 
-    fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        self.deserialize_any(visitor)
     }
 }
 
@@ -598,9 +726,9 @@ impl<'de, 'a, R: io::Read> de::MapAccess<'de> for DeserializerSeqElements<'a, 'd
             return Ok(None);
         }
         self.element_count -= 1;
-        match self.de.peek()? {
+        match self.de.reader.peek()? {
             Some(b'*') => {
-                self.de.consume(1);
+                self.de.reader.consume(1);
                 match self.de.read_line()?.parse::<u32>()? {
                     2 => seed.deserialize(&mut *self.de).map(Some),
                     input => Err(Error {
@@ -655,7 +783,12 @@ impl<'de, 'a, R: io::Read> de::VariantAccess<'de> for DeserializeEnum<'a, 'de, R
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
-        unimplemented!("should never be called - unit variants handled immediately")
+        // unit variants are of their index alone, never of an array of it and data
+        Err(Error {
+            kind: ErrorKind::DataError,
+            message: "Expected data after the index of a variant of *2, found a unit variant"
+                .into(),
+        })
     }
 
     fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
@@ -506,3 +506,156 @@ mod test_enum {
         Ok(())
     }
 }
+
+mod test_limits {
+    use super::super::*;
+
+    const LIMITS: Limits = Limits {
+        max_len: 8,
+        max_elements: 2,
+    };
+
+    fn limit_exceeded<T: serde::de::DeserializeOwned>(input: &str) -> bool {
+        let reader = &mut io::BufReader::new(input.as_bytes());
+        matches!(
+            from_reader_with_limits::<_, T>(reader, LIMITS),
+            Err(Error {
+                kind: ErrorKind::LimitExceeded,
+                ..
+            })
+        )
+    }
+
+    #[test]
+    fn test_within_limits() -> Result<()> {
+        let reader = &mut io::BufReader::new("$8\r\n12345678\r\n*2\r\n:1\r\n:2\r\n".as_bytes());
+
+        assert_eq!(
+            "12345678",
+            from_reader_with_limits::<_, String>(reader, LIMITS)?
+        );
+        assert_eq!(
+            vec![1, 2],
+            from_reader_with_limits::<_, Vec<u32>>(reader, LIMITS)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_beyond_limits() {
+        assert!(limit_exceeded::<String>("$9\r\n123456789\r\n"));
+        assert!(limit_exceeded::<String>(&format!("${}\r\n", u64::MAX)));
+        assert!(limit_exceeded::<String>("+123456789"));
+        assert!(limit_exceeded::<Vec<u32>>("*3\r\n:1\r\n:2\r\n:3\r\n"));
+        assert!(limit_exceeded::<Vec<u32>>(&format!("*{}\r\n", u32::MAX)));
+    }
+}
+
+mod test_utf8 {
+    use super::super::*;
+    use serde_bytes::ByteBuf;
+
+    const INVALID: &[u8] = b"+ab\xffc\r\n$4\r\nab\xffc\r\n";
+
+    fn options(utf8: Utf8) -> Options {
+        Options {
+            utf8,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn test_strict() {
+        let reader = &mut io::BufReader::new(INVALID);
+
+        assert!(matches!(
+            from_reader_with_options::<_, String>(reader, options(Utf8::Strict)),
+            Err(Error {
+                kind: ErrorKind::FromUtf8Error(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_lossy() -> Result<()> {
+        let reader = &mut io::BufReader::new(INVALID);
+
+        for _ in 0..2 {
+            let string = from_reader_with_options::<_, String>(reader, options(Utf8::Lossy))?;
+            assert_eq!("ab\u{FFFD}c", string);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_raw() -> Result<()> {
+        let reader = &mut io::BufReader::new(INVALID);
+
+        for _ in 0..2 {
+            let bytes = from_reader_with_options::<_, ByteBuf>(reader, options(Utf8::Raw))?;
+            assert_eq!(b"ab\xffc", bytes.as_slice());
+        }
+        let reader = &mut io::BufReader::new(INVALID);
+        assert!(from_reader_with_options::<_, String>(reader, options(Utf8::Raw)).is_err());
+        let reader = &mut io::BufReader::new(INVALID);
+        assert!(from_reader_with_options::<_, ByteBuf>(reader, options(Utf8::Strict)).is_err());
+
+        Ok(())
+    }
+}
+
+mod test_malformed_input {
+    use super::super::*;
+
+    #[derive(PartialEq, Deserialize, Debug)]
+    struct Defaulted {
+        #[serde(default)]
+        a: u32,
+    }
+
+    #[derive(PartialEq, Deserialize, Debug)]
+    enum Variants {
+        Unit,
+        Pair(u32, u32),
+    }
+
+    fn data_error<T: serde::de::DeserializeOwned>(input: &str) -> bool {
+        let reader = &mut io::BufReader::new(input.as_bytes());
+        matches!(
+            from_reader::<_, T>(reader),
+            Err(Error {
+                kind: ErrorKind::DataError,
+                ..
+            })
+        )
+    }
+
+    #[test]
+    fn test_unknown_field_is_skipped() -> Result<()> {
+        let input = "*1\r\n*2\r\n+b\r\n*2\r\n:1\r\n*2\r\n$2\r\nab\r\n-ERR\r\n";
+        let reader = &mut io::BufReader::new(input.as_bytes());
+
+        assert_eq!(Defaulted { a: 0 }, from_reader(reader)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_variant_index_out_of_range_is_refused() {
+        assert!(data_error::<Variants>(":2\r\n"));
+    }
+
+    #[test]
+    fn test_unit_variant_with_data_is_refused() {
+        assert!(data_error::<Variants>("*2\r\n:0\r\n:1\r\n"));
+    }
+
+    #[test]
+    fn test_deeply_nested_unknown_field_is_refused() {
+        let input = format!("*1\r\n*2\r\n+b\r\n{}", "*1\r\n".repeat(100_000));
+        assert!(data_error::<Defaulted>(&input));
+    }
+}
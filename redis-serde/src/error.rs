@@ -0,0 +1,151 @@
+#[cfg(test)]
+mod tests;
+
+use std::{io, num, string};
+
+use serde::{de, ser};
+
+/// Cause of an Error
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum ErrorKind {
+    /// reading or writing failed
+    IoError(io::Error),
+    /// an integer (or length) of the input is not one
+    ParseIntError(num::ParseIntError),
+    /// a float of the input is not one
+    ParseFloatError(num::ParseFloatError),
+    /// a string of the input is not UTF-8 (and Utf8::Strict refuses it)
+    FromUtf8Error(string::FromUtf8Error),
+    /// the input is not RESP of the type deserialized, or the value is not one RESP can hold
+    DataError,
+    /// the input went beyond the Limits of the deserialization
+    LimitExceeded,
+}
+
+/// Error of a serialization or deserialization, of its cause and a message describing it
+#[derive(Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn limit_exceeded(message: String) -> Self {
+        Self {
+            kind: ErrorKind::LimitExceeded,
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::IoError(_) => "I/O error",
+            Self::ParseIntError(_) => "invalid integer",
+            Self::ParseFloatError(_) => "invalid float",
+            Self::FromUtf8Error(_) => "invalid UTF-8",
+            Self::DataError => "invalid data",
+            Self::LimitExceeded => "limit exceeded",
+        })
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::IoError(err) => Some(err),
+            ErrorKind::ParseIntError(err) => Some(err),
+            ErrorKind::ParseFloatError(err) => Some(err),
+            ErrorKind::FromUtf8Error(err) => Some(err),
+            ErrorKind::DataError | ErrorKind::LimitExceeded => None,
+        }
+    }
+}
+
+impl ser::Error for Error {
+    fn custom<T>(msg: T) -> Self
+    where
+        T: std::fmt::Display,
+    {
+        Self {
+            kind: ErrorKind::DataError,
+            message: msg.to_string(),
+        }
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T>(msg: T) -> Self
+    where
+        T: std::fmt::Display,
+    {
+        Self {
+            kind: ErrorKind::DataError,
+            message: msg.to_string(),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(io_error: io::Error) -> Self {
+        let message = io_error.to_string();
+        Self {
+            kind: ErrorKind::IoError(io_error),
+            message,
+        }
+    }
+}
+
+impl From<codec_util::Error> for Error {
+    fn from(codec_error: codec_util::Error) -> Self {
+        let kind = match codec_error.kind {
+            codec_util::ErrorKind::IoError(io_error) => ErrorKind::IoError(io_error),
+            codec_util::ErrorKind::LimitExceeded => ErrorKind::LimitExceeded,
+            codec_util::ErrorKind::MissingLineEnding => ErrorKind::DataError,
+        };
+        Self {
+            kind,
+            message: codec_error.message,
+        }
+    }
+}
+
+impl From<num::ParseIntError> for Error {
+    fn from(parse_error: num::ParseIntError) -> Self {
+        let message = parse_error.to_string();
+        Self {
+            kind: ErrorKind::ParseIntError(parse_error),
+            message,
+        }
+    }
+}
+
+impl From<num::ParseFloatError> for Error {
+    fn from(parse_error: num::ParseFloatError) -> Self {
+        let message = parse_error.to_string();
+        Self {
+            kind: ErrorKind::ParseFloatError(parse_error),
+            message,
+        }
+    }
+}
+
+impl From<string::FromUtf8Error> for Error {
+    fn from(parse_error: string::FromUtf8Error) -> Self {
+        let message = parse_error.to_string();
+        Self {
+            kind: ErrorKind::FromUtf8Error(parse_error),
+            message,
+        }
+    }
+}
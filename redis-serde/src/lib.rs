@@ -0,0 +1,28 @@
+//! Serde serialization and deserialization of RESP (REdis Serialization Protocol), the protocol of
+//! Redis and of `kvs-server` to Redis clients
+//!
+//! Values are written as RESP writes them: integers (and booleans, chars and the indexes of unit
+//! variants) as `:`, floats and strings of a single line as simple strings `+`, other strings and
+//! byte arrays as bulk strings `$`, None as the null bulk string `$-1`, and sequences, tuples,
+//! structs and maps as arrays `*` (each field or entry an array of its key and value). A variant
+//! holding data is the array of its index and the data.
+//!
+//! Replies whose shape is not known up front, such as those of a Redis server, are read as
+//! [`Value`]s.
+
+#[cfg(test)]
+mod tests;
+
+mod error;
+
+mod de;
+mod ser;
+mod value;
+
+pub use de::{
+    from_reader, from_reader_with_limits, from_reader_with_options, Limits, Options, Utf8,
+};
+pub use ser::to_writer;
+pub use value::Value;
+
+pub use error::{Error, ErrorKind, Result};
@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod tests;
 
-use super::error;
+use super::{error, value};
 
 use std::io::{self, Write};
 
@@ -13,6 +13,7 @@ struct Serializer<'writer, W: io::Write> {
     writer: &'writer mut io::BufWriter<W>,
 }
 
+/// serializes the value to the writer, flushing it
 pub fn to_writer<W, T>(writer: &mut io::BufWriter<W>, value: T) -> Result<()>
 where
     W: io::Write,
@@ -88,7 +89,7 @@ impl<'a, 'writer, W: io::Write> ser::Serializer for &'a mut Serializer<'writer,
     }
 
     fn serialize_str(self, v: &str) -> Result<Self::Ok> {
-        let to_write = if v.contains(|c| c == '\r' || c == '\n') {
+        let to_write = if v.contains(['\r', '\n']) {
             format!("${}\r\n{}\r\n", v.len(), v)
         } else {
             format!("+{}\r\n", v)
@@ -110,9 +111,9 @@ impl<'a, 'writer, W: io::Write> ser::Serializer for &'a mut Serializer<'writer,
         Ok(())
     }
 
-    fn serialize_some<T: ?Sized>(self, value: &T) -> Result<Self::Ok>
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(self)
     }
@@ -122,7 +123,11 @@ impl<'a, 'writer, W: io::Write> ser::Serializer for &'a mut Serializer<'writer,
         Ok(())
     }
 
-    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> {
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Self::Ok> {
+        if name == value::NULL_ARRAY_TOKEN {
+            self.writer.write_all("*-1\r\n".as_bytes())?;
+            return Ok(());
+        }
         self.serialize_unit()
     }
 
@@ -135,14 +140,29 @@ impl<'a, 'writer, W: io::Write> ser::Serializer for &'a mut Serializer<'writer,
         self.serialize_u32(variant_index)
     }
 
-    fn serialize_newtype_struct<T: ?Sized>(self, _name: &'static str, value: &T) -> Result<Self::Ok>
+    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<Self::Ok>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
+        if name == value::ERROR_TOKEN {
+            // the message of an error is written as a simple string is, but for its indicator
+            let mut message = Vec::new();
+            to_writer(&mut io::BufWriter::new(&mut message), value)?;
+            return match message.split_first() {
+                Some((b'+', line)) => {
+                    self.writer.write_all("-".as_bytes())?;
+                    self.writer.write_all(line)?;
+                    Ok(())
+                }
+                _ => Err(ser::Error::custom(
+                    "Expected the message of an error to be a string of a single line",
+                )),
+            };
+        }
         value.serialize(self)
     }
 
-    fn serialize_newtype_variant<T: ?Sized>(
+    fn serialize_newtype_variant<T>(
         self,
         _name: &'static str,
         variant_index: u32,
@@ -150,7 +170,7 @@ impl<'a, 'writer, W: io::Write> ser::Serializer for &'a mut Serializer<'writer,
         value: &T,
     ) -> Result<Self::Ok>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.writer.write_all("*2\r\n".as_bytes())?;
         self.serialize_u32(variant_index)?;
@@ -162,9 +182,11 @@ impl<'a, 'writer, W: io::Write> ser::Serializer for &'a mut Serializer<'writer,
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
         match len {
             Some(len) => self.writer.write_all(format!("*{}\r\n", len).as_bytes())?,
-            None => unimplemented!(
-                "Sequences without a known length before iterating are not supported by this serialization format"
-            ),
+            None => {
+                return Err(ser::Error::custom(
+                    "Sequences without a known length before iterating are not supported by this serialization format",
+                ))
+            }
         };
         Ok(self)
     }
@@ -199,9 +221,11 @@ impl<'a, 'writer, W: io::Write> ser::Serializer for &'a mut Serializer<'writer,
     fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
         match len {
             Some(len) => self.writer.write_all(format!("*{}\r\n", len).as_bytes())?,
-            None => unimplemented!(
-                "Maps without a known length before iterating are not supported by this serialization format"
-            ),
+            None => {
+                return Err(ser::Error::custom(
+                    "Maps without a known length before iterating are not supported by this serialization format",
+                ))
+            }
         };
         Ok(self)
     }
@@ -230,9 +254,9 @@ impl<'a, 'writer, W: io::Write> ser::SerializeSeq for &'a mut Serializer<'writer
 
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut **self)
     }
@@ -247,9 +271,9 @@ impl<'a, 'writer, W: io::Write> ser::SerializeTuple for &'a mut Serializer<'writ
 
     type Error = Error;
 
-    fn serialize_element<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut **self)
     }
@@ -264,9 +288,9 @@ impl<'a, 'writer, W: io::Write> ser::SerializeTupleStruct for &'a mut Serializer
 
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut **self)
     }
@@ -281,9 +305,9 @@ impl<'a, 'writer, W: io::Write> ser::SerializeTupleVariant for &'a mut Serialize
 
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut **self)
     }
@@ -299,17 +323,17 @@ impl<'a, 'writer, W: io::Write> ser::SerializeMap for &'a mut Serializer<'writer
 
     type Error = Error;
 
-    fn serialize_key<T: ?Sized>(&mut self, key: &T) -> Result<()>
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.writer.write_all("*2\r\n".as_bytes())?;
         key.serialize(&mut **self)
     }
 
-    fn serialize_value<T: ?Sized>(&mut self, value: &T) -> Result<()>
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         value.serialize(&mut **self)?;
         Ok(())
@@ -325,9 +349,9 @@ impl<'a, 'writer, W: io::Write> ser::SerializeStruct for &'a mut Serializer<'wri
 
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.writer.write_all("*2\r\n".as_bytes())?;
         key.serialize(&mut **self)?;
@@ -345,9 +369,9 @@ impl<'a, 'writer, W: io::Write> ser::SerializeStructVariant for &'a mut Serializ
 
     type Error = Error;
 
-    fn serialize_field<T: ?Sized>(&mut self, key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
-        T: Serialize,
+        T: ?Sized + Serialize,
     {
         self.writer.write_all("*2\r\n".as_bytes())?;
         key.serialize(&mut **self)?;
@@ -21,11 +21,11 @@ fn test_float<T: Display + Serialize>(value: T) -> Result<()> {
 #[test]
 fn test_bool() -> Result<()> {
     let mut buf = Vec::<u8>::new();
-    to_writer(&mut io::BufWriter::new(&mut buf), &true)?;
+    to_writer(&mut io::BufWriter::new(&mut buf), true)?;
     assert_eq!(":1\r\n".as_bytes(), buf.as_slice());
 
     let mut buf = Vec::<u8>::new();
-    to_writer(&mut io::BufWriter::new(&mut buf), &false)?;
+    to_writer(&mut io::BufWriter::new(&mut buf), false)?;
     assert_eq!(":0\r\n".as_bytes(), buf.as_slice());
 
     Ok(())
@@ -141,7 +141,7 @@ fn test_str() -> Result<()> {
     ];
     let mut buf = Vec::<u8>::new();
     for str in strs_to_test {
-        let expected = if str.contains(|c| c == '\r' || c == '\n') {
+        let expected = if str.contains(['\r', '\n']) {
             format!("${}\r\n{}\r\n", str.len(), str)
         } else {
             format!("+{}\r\n", str)
@@ -0,0 +1,146 @@
+#[cfg(test)]
+mod tests;
+
+use std::{convert::TryFrom, fmt};
+
+use serde::{de, ser, Deserialize, Serialize};
+
+/// name of the newtype struct of the message of an error, which the Serializer writes as `-`
+/// rather than as a simple string (and the key of the map an error is deserialized as)
+pub(crate) const ERROR_TOKEN: &str = "$redis_serde::Error";
+/// name of the unit struct which the Serializer writes as the null array `*-1`
+pub(crate) const NULL_ARRAY_TOKEN: &str = "$redis_serde::NullArray";
+
+/// A single RESP value, of any of its types
+///
+/// Serialized as RESP writes it, and deserialized from any input (a reply of a Redis server, say),
+/// an error reply being a Value::Error rather than an Error of the deserialization.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    /// a line of text, `+`, which is written as a bulk string if it is not of a single line
+    SimpleString(String),
+    /// an error reply, `-`, of a single line
+    Error(String),
+    /// `:`
+    Integer(i64),
+    /// `$`, None being the null bulk string `$-1`
+    BulkString(Option<Vec<u8>>),
+    /// `*`, None being the null array `*-1`
+    Array(Option<Vec<Value>>),
+}
+
+impl Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        match self {
+            Value::SimpleString(string) => serializer.serialize_str(string),
+            Value::Error(message) => serializer.serialize_newtype_struct(ERROR_TOKEN, message),
+            Value::Integer(integer) => serializer.serialize_i64(*integer),
+            Value::BulkString(None) => serializer.serialize_none(),
+            Value::BulkString(Some(bytes)) => serializer.serialize_bytes(bytes),
+            Value::Array(None) => serializer.serialize_unit_struct(NULL_ARRAY_TOKEN),
+            Value::Array(Some(values)) => serializer.collect_seq(values),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> de::Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a RESP value")
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Value::Integer(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        i64::try_from(v)
+            .map(Value::Integer)
+            .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Value::SimpleString(v.into()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Value::SimpleString(v))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Value::BulkString(Some(v.into())))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Value::BulkString(Some(v)))
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Value::BulkString(None))
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        Ok(Value::Array(None))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        // not allocated up front by the count of the input, which is not to be trusted
+        let mut values = Vec::new();
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(Value::Array(Some(values)))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::MapAccess<'de>,
+    {
+        match map.next_entry::<String, String>()? {
+            Some((key, message)) if key == ERROR_TOKEN => Ok(Value::Error(message)),
+            _ => Err(de::Error::invalid_type(de::Unexpected::Map, &self)),
+        }
+    }
+}
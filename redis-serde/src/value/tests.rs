@@ -0,0 +1,88 @@
+use std::io;
+
+use super::*;
+
+use super::super::{from_reader, to_writer, ErrorKind, Result};
+
+fn written(value: &Value) -> Result<String> {
+    let mut buf = Vec::<u8>::new();
+    to_writer(&mut io::BufWriter::new(&mut buf), value)?;
+    Ok(String::from_utf8(buf).unwrap())
+}
+
+#[test]
+fn test_serialize() -> Result<()> {
+    assert_eq!("+OK\r\n", written(&Value::SimpleString("OK".into()))?);
+    assert_eq!(
+        "-ERR unknown command\r\n",
+        written(&Value::Error("ERR unknown command".into()))?
+    );
+    assert_eq!(":-42\r\n", written(&Value::Integer(-42))?);
+    assert_eq!(
+        "$5\r\nva\r\nl\r\n",
+        written(&Value::BulkString(Some(b"va\r\nl".to_vec())))?
+    );
+    assert_eq!("$-1\r\n", written(&Value::BulkString(None))?);
+    assert_eq!("*-1\r\n", written(&Value::Array(None))?);
+    assert_eq!(
+        "*2\r\n:1\r\n*1\r\n$1\r\na\r\n",
+        written(&Value::Array(Some(vec![
+            Value::Integer(1),
+            Value::Array(Some(vec![Value::BulkString(Some(b"a".to_vec()))])),
+        ])))?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_serialize_multiline_error() {
+    let error = written(&Value::Error("ERR\r\nsecond line".into())).unwrap_err();
+    assert!(matches!(error.kind, ErrorKind::DataError));
+}
+
+#[test]
+fn test_deserialize() -> Result<()> {
+    let input = "+OK\r\n\
+                 -WRONGTYPE not a list\r\n\
+                 :7\r\n\
+                 $3\r\nkey\r\n\
+                 $-1\r\n\
+                 *-1\r\n\
+                 *0\r\n\
+                 *3\r\n:1\r\n+two\r\n*1\r\n$-1\r\n";
+    let reader = &mut io::BufReader::new(input.as_bytes());
+
+    assert_eq!(Value::SimpleString("OK".into()), from_reader(reader)?);
+    assert_eq!(
+        Value::Error("WRONGTYPE not a list".into()),
+        from_reader(reader)?
+    );
+    assert_eq!(Value::Integer(7), from_reader(reader)?);
+    assert_eq!(
+        Value::BulkString(Some(b"key".to_vec())),
+        from_reader(reader)?
+    );
+    assert_eq!(Value::BulkString(None), from_reader(reader)?);
+    assert_eq!(Value::Array(None), from_reader(reader)?);
+    assert_eq!(Value::Array(Some(vec![])), from_reader(reader)?);
+    assert_eq!(
+        Value::Array(Some(vec![
+            Value::Integer(1),
+            Value::SimpleString("two".into()),
+            Value::Array(Some(vec![Value::BulkString(None)])),
+        ])),
+        from_reader(reader)?
+    );
+    Ok(())
+}
+
+#[test]
+fn test_deserialize_malformed() {
+    for input in ["?1\r\n", "", "*2\r\n:1\r\n", &"*1\r\n".repeat(100)].iter() {
+        let reader = &mut io::BufReader::new(input.as_bytes());
+        assert!(matches!(
+            from_reader::<_, Value>(reader).unwrap_err().kind,
+            ErrorKind::DataError | ErrorKind::IoError(_)
+        ));
+    }
+}